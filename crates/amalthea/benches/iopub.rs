@@ -0,0 +1,120 @@
+/*
+ * iopub.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+//! Throughput of IOPub messages, from typed messages sent by the kernel to
+//! typed messages read by a subscriber, over an in-process ZeroMQ transport.
+//!
+//! Run with `cargo bench -p amalthea --bench iopub`. Takes the number of
+//! messages as optional argument.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use amalthea::session::Session;
+use amalthea::socket::socket::Socket;
+use amalthea::wire::jupyter_message::JupyterMessage;
+use amalthea::wire::jupyter_message::Message;
+use amalthea::wire::status::ExecutionState;
+use amalthea::wire::status::KernelStatus;
+use amalthea::wire::stream::Stream;
+use amalthea::wire::stream::StreamOutput;
+use amalthea::wire::subscription_message::SubscriptionMessage;
+
+/// Messages are sent in batches smaller than the high water mark of the
+/// subscriber so that none are dropped
+const BATCH_SIZE: usize = 500;
+
+fn main() {
+    let n: usize = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(100_000);
+
+    let session = Session::create("bench-key").unwrap();
+    let ctx = zmq::Context::new();
+    let endpoint = String::from("inproc://iopub-bench");
+
+    let publisher = Socket::new(
+        session.clone(),
+        ctx.clone(),
+        String::from("IOPub"),
+        zmq::XPUB,
+        None,
+        endpoint.clone(),
+    )
+    .unwrap();
+
+    let subscriber = Socket::new(
+        session.clone(),
+        ctx.clone(),
+        String::from("IOPub"),
+        zmq::SUB,
+        None,
+        endpoint,
+    )
+    .unwrap();
+    subscriber.subscribe(b"").unwrap();
+    SubscriptionMessage::read_from_socket(&publisher).unwrap();
+
+    let status = || KernelStatus {
+        execution_state: ExecutionState::Busy,
+    };
+    let stream = || StreamOutput {
+        name: Stream::Stdout,
+        text: String::from("[1] 0.1915194 0.6221088 0.4377277 0.7853586 0.7799758\n"),
+    };
+
+    report(
+        "status",
+        n,
+        run(n, &publisher, &subscriber, &session, status),
+    );
+    report(
+        "stream",
+        n,
+        run(n, &publisher, &subscriber, &session, stream),
+    );
+}
+
+fn run<T>(
+    n: usize,
+    publisher: &Socket,
+    subscriber: &Socket,
+    session: &Session,
+    content: impl Fn() -> T,
+) -> Duration
+where
+    T: amalthea::wire::jupyter_message::ProtocolMessage,
+{
+    let start = Instant::now();
+
+    let mut remaining = n;
+    while remaining > 0 {
+        let batch = remaining.min(BATCH_SIZE);
+
+        for _ in 0..batch {
+            JupyterMessage::create(content(), None, session)
+                .send(publisher)
+                .unwrap();
+        }
+        for _ in 0..batch {
+            match Message::read_from_socket(subscriber).unwrap() {
+                Message::Status(_) | Message::Stream(_) => {},
+                msg => panic!("Unexpected message: {msg:?}"),
+            }
+        }
+
+        remaining -= batch;
+    }
+
+    start.elapsed()
+}
+
+fn report(kind: &str, n: usize, elapsed: Duration) {
+    let per_second = n as f64 / elapsed.as_secs_f64();
+    println!("{kind:>8}: {n} messages in {elapsed:.2?} ({per_second:.0} messages/s)");
+}
@@ -0,0 +1,25 @@
+//
+// jupyter_header.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Fuzzes the parsing of Jupyter message headers sent by the frontend
+
+#![no_main]
+
+use amalthea::wire::header::JupyterHeader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(header) = serde_json::from_slice::<JupyterHeader>(data) else {
+        return;
+    };
+
+    // Headers are echoed back as the parent header of our replies
+    let value = serde_json::to_value(&header).unwrap();
+    let parsed: JupyterHeader = serde_json::from_value(value).unwrap();
+    assert_eq!(parsed.msg_id, header.msg_id);
+    assert_eq!(parsed.msg_type, header.msg_type);
+});
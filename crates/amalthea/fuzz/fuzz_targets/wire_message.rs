@@ -0,0 +1,74 @@
+//
+// wire_message.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Fuzzes the parsing of ZeroMQ frames received from the frontend into wire
+// messages, and of wire messages into typed Jupyter messages.
+
+#![no_main]
+
+use amalthea::wire::jupyter_message::Message;
+use amalthea::wire::wire_message::WireMessage;
+use hmac::Hmac;
+use hmac::Mac;
+use libfuzzer_sys::arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use sha2::Sha256;
+
+const MSG_DELIM: &[u8] = b"<IDS|MSG>";
+
+#[derive(Debug, Arbitrary)]
+enum Signature {
+    /// No HMAC key, signatures are not checked
+    Unchecked(Vec<u8>),
+
+    /// Correct signature, so that parsing gets past HMAC validation
+    Valid,
+
+    /// Arbitrary signature checked against the key
+    Invalid(Vec<u8>),
+}
+
+#[derive(Debug, Arbitrary)]
+struct Frames {
+    identities: Vec<Vec<u8>>,
+    signature: Signature,
+
+    /// The header, parent header, metadata, content, and any extra buffers
+    parts: Vec<Vec<u8>>,
+
+    /// Whether to include the `<IDS|MSG>` delimiter
+    delimiter: bool,
+}
+
+fuzz_target!(|frames: Frames| {
+    let key: Hmac<Sha256> = Hmac::new_from_slice(b"fuzz").unwrap();
+
+    let (signature, hmac_key) = match frames.signature {
+        Signature::Unchecked(signature) => (signature, None),
+        Signature::Invalid(signature) => (signature, Some(key)),
+        Signature::Valid => {
+            let mut signer = key.clone();
+            for part in &frames.parts {
+                signer.update(part);
+            }
+            let signature = hex::encode(signer.finalize().into_bytes());
+            (signature.into_bytes(), Some(key))
+        },
+    };
+
+    let mut bufs = frames.identities;
+    if frames.delimiter {
+        bufs.push(MSG_DELIM.to_vec());
+    }
+    bufs.push(signature);
+    bufs.extend(frames.parts);
+
+    let Ok(msg) = WireMessage::from_buffers(bufs, &hmac_key) else {
+        return;
+    };
+    let _ = Message::try_from(&msg);
+});
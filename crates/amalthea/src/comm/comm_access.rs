@@ -0,0 +1,47 @@
+/*
+ * comm_access.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use std::sync::OnceLock;
+
+use serde_json::Value;
+
+use crate::wire::header::JupyterHeader;
+
+/// An RPC sent by a frontend to a comm
+pub struct CommRpc<'a> {
+    /// Name of the comm, e.g. `positron.variables`
+    pub comm_name: &'a str,
+
+    /// Header of the `comm_msg`, which identifies the client in `session`
+    pub header: &'a JupyterHeader,
+
+    /// The request, usually with a `method` field
+    pub data: &'a Value,
+}
+
+/// Decides whether an RPC is delivered to its comm. Returns the message of the
+/// error sent back to the frontend otherwise.
+pub type CommAccessPolicy = Box<dyn Fn(&CommRpc) -> Result<(), String> + Send + Sync>;
+
+static POLICY: OnceLock<CommAccessPolicy> = OnceLock::new();
+
+/// Install the policy of the kernel, e.g. to reject RPCs that change the
+/// session in read-only sessions. Can only be set once. All RPCs are allowed
+/// until then.
+pub fn set_policy(policy: CommAccessPolicy) {
+    if POLICY.set(policy).is_err() {
+        log::error!("The comm access policy can only be set once");
+    }
+}
+
+/// Whether `rpc` may be delivered to its comm
+pub fn check(rpc: &CommRpc) -> Result<(), String> {
+    match POLICY.get() {
+        Some(policy) => policy(rpc),
+        None => Ok(()),
+    }
+}
@@ -29,12 +29,18 @@ pub enum Comm {
     /// A data viewer.
     DataViewer,
 
+    /// The backend of the delimited file import wizard.
+    DataImport,
+
     /// The Positron help pane.
     Help,
 
     /// The Positron frontend.
     Ui,
 
+    /// A structured log of kernel events.
+    EventLog,
+
     /// Some other comm with a custom name.
     Other(String),
 }
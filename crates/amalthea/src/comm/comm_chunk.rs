@@ -0,0 +1,334 @@
+/*
+ * comm_chunk.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Comm payloads whose serialised JSON is larger than this many bytes are
+/// split into chunks before being sent over the wire.
+pub const COMM_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Incomplete payloads are dropped when no chunk has arrived for them for this
+/// long, e.g. because the frontend gave up in the middle of a message.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Bound on the total size of the chunks of incomplete payloads
+const MAX_PENDING_SIZE: usize = 64 * COMM_CHUNK_SIZE;
+
+/// A piece of a comm payload that was too large to be sent in one message.
+///
+/// On the wire, a chunk replaces the `data` field of a `comm_msg` with
+/// `{ "comm_chunk": { "id": ..., "index": ..., "count": ..., "content": ... } }`.
+/// Concatenating the `content` of all chunks sharing an `id`, in `index`
+/// order, gives back the serialised JSON of the original payload.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CommChunk {
+    /// Identifies the payload this chunk belongs to.
+    pub id: String,
+
+    /// The 0-based position of this chunk.
+    pub index: usize,
+
+    /// The total number of chunks making up the payload.
+    pub count: usize,
+
+    /// A slice of the serialised JSON payload.
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CommChunkEnvelope {
+    comm_chunk: CommChunk,
+}
+
+/**
+ * Split a comm payload into chunks of at most `chunk_size` bytes.
+ *
+ * Payloads that fit in a single chunk are returned as is so that small
+ * messages, by far the most common, are unaffected.
+ */
+pub fn comm_chunks(data: Value, chunk_size: usize) -> Vec<Value> {
+    let serialised = match serde_json::to_string(&data) {
+        Ok(serialised) => serialised,
+        Err(err) => {
+            log::error!("Can't serialise comm payload for chunking: {err:?}");
+            return vec![data];
+        },
+    };
+
+    if serialised.len() <= chunk_size {
+        return vec![data];
+    }
+
+    let contents = split_at_char_boundaries(&serialised, chunk_size);
+    let id = uuid::Uuid::new_v4().to_string();
+    let count = contents.len();
+
+    contents
+        .into_iter()
+        .enumerate()
+        .map(|(index, content)| {
+            let envelope = CommChunkEnvelope {
+                comm_chunk: CommChunk {
+                    id: id.clone(),
+                    index,
+                    count,
+                    content: content.to_string(),
+                },
+            };
+            serde_json::to_value(envelope).unwrap()
+        })
+        .collect()
+}
+
+fn split_at_char_boundaries(x: &str, size: usize) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut rest = x;
+
+    while !rest.is_empty() {
+        let mut end = usize::min(size, rest.len());
+
+        // Never split in the middle of a UTF-8 sequence
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        // Make progress even with a chunk size smaller than a character
+        if end == 0 {
+            end = rest
+                .chars()
+                .next()
+                .map(|c| c.len_utf8())
+                .unwrap_or(rest.len());
+        }
+
+        let (chunk, tail) = rest.split_at(end);
+        out.push(chunk);
+        rest = tail;
+    }
+
+    out
+}
+
+/**
+ * Reassembles chunked payloads received from the frontend.
+ *
+ * Payloads that are not chunks are passed through untouched. Incomplete
+ * payloads are dropped once they are stale or take up too much memory.
+ */
+pub struct CommChunkAssembler {
+    /// Chunks received so far, indexed by payload ID.
+    pending: HashMap<String, PendingPayload>,
+
+    /// Total size of the chunks in `pending`, in bytes.
+    pending_size: usize,
+
+    timeout: Duration,
+    max_pending_size: usize,
+}
+
+struct PendingPayload {
+    chunks: Vec<Option<String>>,
+
+    /// When the last chunk of this payload arrived.
+    updated: Instant,
+}
+
+impl Default for CommChunkAssembler {
+    fn default() -> Self {
+        Self {
+            pending: HashMap::new(),
+            pending_size: 0,
+            timeout: PENDING_TIMEOUT,
+            max_pending_size: MAX_PENDING_SIZE,
+        }
+    }
+}
+
+impl CommChunkAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * Feed a payload to the assembler.
+     *
+     * Returns the payload if it is not a chunk, or the reassembled payload if
+     * this was the last missing chunk. Returns `Ok(None)` while chunks are
+     * still missing.
+     */
+    pub fn add(&mut self, data: Value) -> anyhow::Result<Option<Value>> {
+        if !Self::is_chunk(&data) {
+            return Ok(Some(data));
+        }
+
+        let envelope: CommChunkEnvelope = serde_json::from_value(data)?;
+        let chunk = envelope.comm_chunk;
+
+        self.evict_stale(Instant::now());
+
+        if chunk.count == 0 || chunk.index >= chunk.count {
+            self.remove(&chunk.id);
+            return Err(anyhow::anyhow!(
+                "Invalid comm chunk {} of {} for payload '{}'",
+                chunk.index,
+                chunk.count,
+                chunk.id
+            ));
+        }
+
+        if self.pending_size + chunk.content.len() > self.max_pending_size {
+            self.remove(&chunk.id);
+            return Err(anyhow::anyhow!(
+                "Too many incomplete chunked payloads, dropping payload '{}'",
+                chunk.id
+            ));
+        }
+
+        let payload = self
+            .pending
+            .entry(chunk.id.clone())
+            .or_insert_with(|| PendingPayload {
+                chunks: vec![None; chunk.count],
+                updated: Instant::now(),
+            });
+
+        if payload.chunks.len() != chunk.count {
+            self.remove(&chunk.id);
+            return Err(anyhow::anyhow!(
+                "Inconsistent number of chunks for payload '{}'",
+                chunk.id
+            ));
+        }
+
+        let size = chunk.content.len();
+        let previous = payload.chunks[chunk.index].replace(chunk.content);
+        payload.updated = Instant::now();
+
+        self.pending_size += size;
+        self.pending_size -= previous.map_or(0, |previous| previous.len());
+
+        if payload.chunks.iter().any(|chunk| chunk.is_none()) {
+            return Ok(None);
+        }
+
+        let chunks = self.remove(&chunk.id).unwrap();
+        let serialised: String = chunks.into_iter().flatten().collect();
+
+        Ok(Some(serde_json::from_str(&serialised)?))
+    }
+
+    /// Drop the payloads that haven't received a chunk for too long
+    fn evict_stale(&mut self, now: Instant) {
+        let stale: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, payload)| now.duration_since(payload.updated) > self.timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in stale {
+            log::warn!("Dropping incomplete chunked payload '{id}', no chunk received in time");
+            self.remove(&id);
+        }
+    }
+
+    /// Forget a pending payload, returning its chunks
+    fn remove(&mut self, id: &str) -> Option<Vec<Option<String>>> {
+        let payload = self.pending.remove(id)?;
+        let size: usize = payload
+            .chunks
+            .iter()
+            .flatten()
+            .map(|chunk| chunk.len())
+            .sum();
+        self.pending_size -= size;
+        Some(payload.chunks)
+    }
+
+    fn is_chunk(data: &Value) -> bool {
+        match data.as_object() {
+            Some(object) => object.len() == 1 && object.contains_key("comm_chunk"),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::comm::comm_chunk::comm_chunks;
+    use crate::comm::comm_chunk::CommChunkAssembler;
+
+    #[test]
+    fn test_small_payloads_are_not_chunked() {
+        let data = json!({ "method": "foo", "params": {} });
+        let chunks = comm_chunks(data.clone(), 1024);
+        assert_eq!(chunks, vec![data.clone()]);
+
+        let mut assembler = CommChunkAssembler::new();
+        assert_eq!(assembler.add(data.clone()).unwrap(), Some(data));
+    }
+
+    #[test]
+    fn test_chunks_roundtrip() {
+        let data = json!({ "method": "foo", "params": { "text": "ünïcödé ".repeat(10) } });
+        let chunks = comm_chunks(data.clone(), 16);
+        assert!(chunks.len() > 1);
+
+        // Chunks may arrive in any order
+        let mut assembler = CommChunkAssembler::new();
+        let (last, rest) = chunks.split_first().unwrap();
+        for chunk in rest.iter().rev() {
+            assert_eq!(assembler.add(chunk.clone()).unwrap(), None);
+        }
+        assert_eq!(assembler.add(last.clone()).unwrap(), Some(data));
+        assert!(assembler.pending.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_chunks() {
+        let mut assembler = CommChunkAssembler::new();
+        let chunk = json!({ "comm_chunk": { "id": "a", "index": 2, "count": 2, "content": "" } });
+        assert!(assembler.add(chunk).is_err());
+        assert!(assembler.pending.is_empty());
+    }
+
+    #[test]
+    fn test_incomplete_payloads_are_bounded() {
+        let chunk = |id: &str, content: &str| json!({ "comm_chunk": { "id": id, "index": 0, "count": 2, "content": content } });
+
+        // By size
+        let mut assembler = CommChunkAssembler {
+            max_pending_size: 8,
+            ..CommChunkAssembler::new()
+        };
+        assert_eq!(assembler.add(chunk("a", "12345")).unwrap(), None);
+        assert!(assembler.add(chunk("b", "12345")).is_err());
+        assert_eq!(assembler.pending.len(), 1);
+        assert_eq!(assembler.pending_size, 5);
+
+        // By age
+        let mut assembler = CommChunkAssembler {
+            timeout: Duration::ZERO,
+            ..CommChunkAssembler::new()
+        };
+        assert_eq!(assembler.add(chunk("a", "12345")).unwrap(), None);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(assembler.add(chunk("b", "12345")).unwrap(), None);
+        assert!(!assembler.pending.contains_key("a"));
+        assert_eq!(assembler.pending_size, 5);
+    }
+}
@@ -12,14 +12,27 @@ use crossbeam::channel::Select;
 use crossbeam::channel::Sender;
 use log::info;
 use log::warn;
+use serde_json::Value;
 use stdext::result::ResultOrLog;
 use stdext::spawn;
 
+use crate::comm::base_comm::json_rpc_error;
+use crate::comm::base_comm::JsonRpcErrorCode;
+use crate::comm::comm_access;
+use crate::comm::comm_access::CommRpc;
 use crate::comm::comm_channel::CommMsg;
+use crate::comm::comm_chunk::comm_chunks;
+use crate::comm::comm_chunk::CommChunkAssembler;
+use crate::comm::comm_chunk::COMM_CHUNK_SIZE;
 use crate::comm::event::CommInfo;
 use crate::comm::event::CommManagerEvent;
 use crate::comm::event::CommManagerInfoReply;
 use crate::comm::event::CommManagerRequest;
+use crate::comm::event_log_comm::KernelEventKind;
+use crate::comm::rpc_barrier;
+use crate::event_log;
+use crate::features;
+use crate::features::COMM_CHUNKING;
 use crate::socket::comm::CommInitiator;
 use crate::socket::comm::CommSocket;
 use crate::socket::iopub::IOPubMessage;
@@ -33,6 +46,7 @@ pub struct CommManager {
     iopub_tx: Sender<IOPubMessage>,
     comm_event_rx: Receiver<CommManagerEvent>,
     pending_rpcs: HashMap<String, JupyterHeader>,
+    incoming_chunks: CommChunkAssembler,
 }
 
 impl CommManager {
@@ -63,6 +77,7 @@ impl CommManager {
             comm_event_rx,
             open_comms: Vec::<CommSocket>::new(),
             pending_rpcs: HashMap::<String, JupyterHeader>::new(),
+            incoming_chunks: CommChunkAssembler::new(),
         }
     }
 
@@ -112,6 +127,12 @@ impl CommManager {
                             .unwrap();
                     }
 
+                    event_log::record(
+                        KernelEventKind::CommOpened,
+                        None,
+                        Some(comm_socket.comm_name.clone()),
+                    );
+
                     // Add to our own list of open comms
                     self.open_comms.push(comm_socket);
 
@@ -136,11 +157,44 @@ impl CommManager {
 
                     // If we found it, send the message to the comm. TODO: Fewer unwraps
                     if let Some(index) = index {
+                        // Large payloads are sent by the frontend in chunks;
+                        // wait until we have all of them before delivering
+                        let Some(msg) = self.assemble_chunks(&comm_id, msg) else {
+                            return;
+                        };
+
                         let comm = self.open_comms.get(index).unwrap();
+
+                        // The kernel may not allow the client to make this RPC,
+                        // e.g. in a read-only session
+                        if let CommMsg::Rpc(id, data) = &msg {
+                            if let Some(header) = self.pending_rpcs.get(id) {
+                                let rpc = CommRpc {
+                                    comm_name: &comm.comm_name,
+                                    header,
+                                    data,
+                                };
+                                if let Err(message) = comm_access::check(&rpc) {
+                                    log::info!(
+                                        "Rejecting RPC to comm '{}': {message}",
+                                        comm.comm_name
+                                    );
+                                    let id = id.clone();
+                                    self.reply_error(&comm_id, id, message);
+                                    return;
+                                }
+                            }
+                        }
+
                         log::trace!("Comm manager: Sending message to comm '{}'", comm.comm_name);
 
                         comm.incoming_tx.send(msg).unwrap();
                     } else {
+                        // Nobody will ever reply to this RPC
+                        if let CommMsg::Rpc(id, _) = &msg {
+                            self.pending_rpcs.remove(id);
+                            rpc_barrier::finished(id);
+                        }
                         log::warn!(
                             "Received message for unknown comm channel {}: {:?}",
                             comm_id,
@@ -158,13 +212,24 @@ impl CommManager {
                         .position(|comm_socket| comm_socket.comm_id == comm_id);
 
                     // If we found it, remove it.
+                    rpc_barrier::comm_closed(&comm_id);
+
                     if let Some(index) = index {
-                        // Notify the comm that it's been closed
+                        // Notify the comm that it's been closed. Cancel its
+                        // work first since it might be busy and won't see the
+                        // message until it's done.
                         let comm = self.open_comms.get(index).unwrap();
+                        comm.closed.cancel();
                         comm.incoming_tx
                             .send(CommMsg::Close)
                             .or_log_error("Failed to send comm_close to comm.");
 
+                        event_log::record(
+                            KernelEventKind::CommClosed,
+                            None,
+                            Some(comm.comm_name.clone()),
+                        );
+
                         // Remove it from our list of open comms
                         self.open_comms.remove(index);
 
@@ -195,6 +260,13 @@ impl CommManager {
 
                         tx.send(CommManagerInfoReply { comms }).unwrap();
                     },
+
+                    // Closing all comms at once
+                    CommManagerRequest::CloseAll(tx) => {
+                        let count = self.close_all();
+                        tx.send(count)
+                            .or_log_error("Failed to reply to close request");
+                    },
                 },
             }
         } else {
@@ -208,51 +280,177 @@ impl CommManager {
                 },
             };
 
-            // Amend the message with the comm's ID, convert it to an
-            // IOPub message, and send it to the frontend
-            let msg = match comm_msg {
+            // Amend the message with the comm's ID, convert it to IOPub
+            // messages, and send them to the frontend. Large payloads are
+            // split in several messages.
+            let msgs: Vec<IOPubMessage> = match comm_msg {
                 // The comm is emitting data to the frontend without being
                 // asked; this is treated like an event.
-                CommMsg::Data(data) => IOPubMessage::CommMsgEvent(CommWireMsg {
-                    comm_id: comm_socket.comm_id.clone(),
-                    data,
-                }),
+                CommMsg::Data(data) => outgoing_chunks(data)
+                    .into_iter()
+                    .map(|data| {
+                        IOPubMessage::CommMsgEvent(CommWireMsg {
+                            comm_id: comm_socket.comm_id.clone(),
+                            data,
+                        })
+                    })
+                    .collect(),
 
                 // The comm is replying to a message from the frontend; the
                 // first parameter names the ID of the message to which this is
                 // a reply.
                 CommMsg::Rpc(string, data) => {
-                    // Create the payload to send to the frontend
-                    let payload = CommWireMsg {
-                        comm_id: comm_socket.comm_id.clone(),
-                        data,
-                    };
-
                     // Try to find the message ID in the map of pending RPCs.
-                    match self.pending_rpcs.remove(&string) {
-                        Some(header) => {
-                            // Found it; consume the pending RPC and convert the
-                            // message to a reply.
-                            IOPubMessage::CommMsgReply(header, payload)
-                        },
-                        None => {
-                            // Didn't find it; log a warning and treat it like
-                            // an event so that the frontend still gets the
-                            // data.
-                            log::warn!(
-                                "Received RPC response '{payload:?}' for unknown message ID {string}");
-                            IOPubMessage::CommMsgEvent(payload)
-                        },
+                    // If found, consume the pending RPC and convert the
+                    // message to a reply.
+                    let header = self.pending_rpcs.remove(&string);
+                    rpc_barrier::finished(&string);
+
+                    if header.is_none() {
+                        // Didn't find it; log a warning and treat it like
+                        // an event so that the frontend still gets the
+                        // data.
+                        log::warn!(
+                            "Received RPC response '{data:?}' for unknown message ID {string}"
+                        );
                     }
+
+                    outgoing_chunks(data)
+                        .into_iter()
+                        .map(|data| {
+                            // Create the payload to send to the frontend
+                            let payload = CommWireMsg {
+                                comm_id: comm_socket.comm_id.clone(),
+                                data,
+                            };
+                            match header {
+                                Some(ref header) => {
+                                    IOPubMessage::CommMsgReply(header.clone(), payload)
+                                },
+                                None => IOPubMessage::CommMsgEvent(payload),
+                            }
+                        })
+                        .collect()
                 },
 
-                CommMsg::Close => IOPubMessage::CommClose(CommClose {
-                    comm_id: comm_socket.comm_id.clone(),
-                }),
+                CommMsg::Close => {
+                    comm_socket.closed.cancel();
+                    rpc_barrier::comm_closed(&comm_socket.comm_id);
+                    event_log::record(
+                        KernelEventKind::CommClosed,
+                        None,
+                        Some(comm_socket.comm_name.clone()),
+                    );
+                    vec![IOPubMessage::CommClose(CommClose {
+                        comm_id: comm_socket.comm_id.clone(),
+                    })]
+                },
             };
 
-            // Deliver the message to the frontend
-            self.iopub_tx.send(msg).unwrap();
+            // Deliver the messages to the frontend
+            for msg in msgs {
+                self.iopub_tx.send(msg).unwrap();
+            }
+        }
+    }
+
+    /**
+     * Close all open comms. Both sides are notified: the back end of each
+     * comm gets `CommMsg::Close` and the frontend gets a `comm_close`
+     * message. Returns the number of comms that were closed.
+     */
+    fn close_all(&mut self) -> usize {
+        let count = self.open_comms.len();
+
+        for comm in self.open_comms.drain(..) {
+            comm.closed.cancel();
+            rpc_barrier::comm_closed(&comm.comm_id);
+            comm.incoming_tx
+                .send(CommMsg::Close)
+                .or_log_error("Failed to send comm_close to comm.");
+
+            event_log::record(
+                KernelEventKind::CommClosed,
+                None,
+                Some(comm.comm_name.clone()),
+            );
+
+            self.iopub_tx
+                .send(IOPubMessage::CommClose(CommClose {
+                    comm_id: comm.comm_id.clone(),
+                }))
+                .or_log_error("Failed to send comm_close to frontend.");
+        }
+
+        info!("Closed all {count} open comms");
+        count
+    }
+
+    /**
+     * Reassemble chunked messages received from the frontend.
+     *
+     * Returns `None` while chunks are still missing. Only the message
+     * completing a chunked RPC gets a reply, so the pending RPCs of the other
+     * chunks are dropped.
+     */
+    fn assemble_chunks(&mut self, comm_id: &str, msg: CommMsg) -> Option<CommMsg> {
+        let (id, data) = match msg {
+            CommMsg::Rpc(id, data) => (Some(id), data),
+            CommMsg::Data(data) => (None, data),
+            CommMsg::Close => return Some(msg),
+        };
+
+        match self.incoming_chunks.add(data) {
+            Ok(Some(data)) => match id {
+                Some(id) => Some(CommMsg::Rpc(id, data)),
+                None => Some(CommMsg::Data(data)),
+            },
+            Ok(None) => {
+                if let Some(id) = id {
+                    self.pending_rpcs.remove(&id);
+                    rpc_barrier::finished(&id);
+                }
+                None
+            },
+            Err(err) => {
+                log::error!("Can't reassemble chunked message for comm {comm_id}: {err:?}");
+
+                // Let the frontend know rather than leaving the RPC hanging
+                if let Some(id) = id {
+                    let message = format!("Can't reassemble chunked message: {err}");
+                    self.reply_error(comm_id, id, message);
+                }
+
+                None
+            },
         }
     }
+
+    /**
+     * Reply to a pending RPC with an error without delivering it to its comm.
+     */
+    fn reply_error(&mut self, comm_id: &str, id: String, message: String) {
+        rpc_barrier::finished(&id);
+
+        let Some(header) = self.pending_rpcs.remove(&id) else {
+            return;
+        };
+
+        let data = json_rpc_error(JsonRpcErrorCode::InvalidRequest, message);
+        let payload = CommWireMsg {
+            comm_id: String::from(comm_id),
+            data,
+        };
+        self.iopub_tx
+            .send(IOPubMessage::CommMsgReply(header, payload))
+            .unwrap();
+    }
+}
+
+/// Split an outgoing payload in chunks, unless chunking is turned off
+fn outgoing_chunks(data: Value) -> Vec<Value> {
+    if !features::is_enabled(&COMM_CHUNKING) {
+        return vec![data];
+    }
+    comm_chunks(data, COMM_CHUNK_SIZE)
 }
@@ -31,6 +31,16 @@ pub struct ExportedData {
 	pub format: ExportFormat
 }
 
+/// The result of exporting a table to a file
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExportedObject {
+	/// The path of the file the table was written to
+	pub file: String,
+
+	/// The format the table was written in
+	pub format: ExportObjectFormat
+}
+
 /// The result of applying filters to a table
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct FilterResult {
@@ -899,6 +909,22 @@ pub enum ExportFormat {
 	Html
 }
 
+/// Possible values for Format in ExportObject
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+pub enum ExportObjectFormat {
+	#[serde(rename = "rds")]
+	#[strum(to_string = "rds")]
+	Rds,
+
+	#[serde(rename = "parquet")]
+	#[strum(to_string = "parquet")]
+	Parquet,
+
+	#[serde(rename = "csv")]
+	#[strum(to_string = "csv")]
+	Csv
+}
+
 /// Possible values for SupportStatus
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
 pub enum SupportStatus {
@@ -1037,6 +1063,17 @@ pub struct ExportDataSelectionParams {
 	pub format: ExportFormat,
 }
 
+/// Parameters for the ExportObject method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExportObjectParams {
+	/// The path of the file to write
+	pub file: String,
+
+	/// The format to write the table in. Defaults to Parquet when a Parquet
+	/// writer is installed, and to CSV otherwise.
+	pub format: Option<ExportObjectFormat>,
+}
+
 /// Parameters for the SetColumnFilters method.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct SetColumnFiltersParams {
@@ -1119,6 +1156,14 @@ pub enum DataExplorerBackendRequest {
 	#[serde(rename = "export_data_selection")]
 	ExportDataSelection(ExportDataSelectionParams),
 
+	/// Export the table to a file
+	///
+	/// Saves the table, with the current row filters and sort applied, to a
+	/// file so that it can be used outside of the session. Progress is
+	/// reported with `progress` events of the UI comm.
+	#[serde(rename = "export_object")]
+	ExportObject(ExportObjectParams),
+
 	/// Set column filters to select subset of table columns
 	///
 	/// Set or clear column filters on table, replacing any previous filters
@@ -1173,6 +1218,9 @@ pub enum DataExplorerBackendReply {
 	/// Exported result
 	ExportDataSelectionReply(ExportedData),
 
+	/// The file the table was written to, and its format.
+	ExportObjectReply(ExportedObject),
+
 	/// Reply for the set_column_filters method (no result)
 	SetColumnFiltersReply(),
 
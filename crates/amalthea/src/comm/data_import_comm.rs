@@ -0,0 +1,198 @@
+/*
+ * data_import_comm.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Options for parsing a delimited file
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ImportOptions {
+    /// The field delimiter
+    pub delimiter: String,
+
+    /// Whether the first row contains column names
+    pub header: bool,
+
+    /// The quote character, or an empty string if fields are not quoted
+    pub quote: String,
+
+    /// The encoding of the file, e.g. `UTF-8` or `latin1`
+    pub encoding: String,
+
+    /// The type of each column. Types are guessed from the data when
+    /// omitted.
+    pub column_types: Option<Vec<ColumnType>>,
+}
+
+/// A column of an import preview
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ImportColumn {
+    /// The name of the column
+    pub name: String,
+
+    /// The type of the column
+    pub column_type: ColumnType,
+}
+
+/// The parsed beginning of a delimited file
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ImportPreview {
+    /// The options used to parse the file
+    pub options: ImportOptions,
+
+    /// The columns of the file
+    pub columns: Vec<ImportColumn>,
+
+    /// The first rows of the file, as unparsed field values. Rows with
+    /// fewer fields than columns are padded with empty values.
+    pub rows: Vec<Vec<String>>,
+
+    /// Whether the file has more rows than the preview
+    pub truncated: bool,
+}
+
+/// Possible values for ColumnType
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+pub enum ColumnType {
+    #[serde(rename = "logical")]
+    #[strum(to_string = "logical")]
+    Logical,
+
+    #[serde(rename = "integer")]
+    #[strum(to_string = "integer")]
+    Integer,
+
+    #[serde(rename = "double")]
+    #[strum(to_string = "double")]
+    Double,
+
+    #[serde(rename = "character")]
+    #[strum(to_string = "character")]
+    Character,
+
+    #[serde(rename = "date")]
+    #[strum(to_string = "date")]
+    Date,
+
+    #[serde(rename = "datetime")]
+    #[strum(to_string = "datetime")]
+    Datetime,
+}
+
+/// Possible values for Reader in GenerateCode
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+pub enum GenerateCodeReader {
+    #[serde(rename = "readr")]
+    #[strum(to_string = "readr")]
+    Readr,
+
+    #[serde(rename = "base")]
+    #[strum(to_string = "base")]
+    Base,
+}
+
+/// Parameters for the Sniff method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SniffParams {
+    /// The path of the file
+    pub path: String,
+}
+
+/// Parameters for the Preview method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PreviewParams {
+    /// The path of the file
+    pub path: String,
+
+    /// The options to parse the file with. The best sniffed options are
+    /// used when omitted.
+    pub options: Option<ImportOptions>,
+
+    /// The maximum number of rows to preview
+    pub max_rows: Option<i64>,
+}
+
+/// Parameters for the GenerateCode method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GenerateCodeParams {
+    /// The path of the file
+    pub path: String,
+
+    /// The options to parse the file with
+    pub options: ImportOptions,
+
+    /// The function used to read the file
+    pub reader: GenerateCodeReader,
+
+    /// The name of the variable to assign the data to. Derived from the
+    /// file name when omitted.
+    pub name: Option<String>,
+}
+
+/**
+ * Backend RPC request types for the data_import comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "params")]
+pub enum DataImportBackendRequest {
+    /// Guess parsing options
+    ///
+    /// Guesses plausible options to parse a delimited file from its first
+    /// lines, best candidates first.
+    #[serde(rename = "sniff")]
+    Sniff(SniffParams),
+
+    /// Preview a file
+    ///
+    /// Parses the first rows of a delimited file.
+    #[serde(rename = "preview")]
+    Preview(PreviewParams),
+
+    /// Generate import code
+    ///
+    /// Generates the R code that reads a delimited file with the given
+    /// options.
+    #[serde(rename = "generate_code")]
+    GenerateCode(GenerateCodeParams),
+}
+
+/**
+ * Backend RPC Reply types for the data_import comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "result")]
+pub enum DataImportBackendReply {
+    /// Candidate options, best first
+    SniffReply(Vec<ImportOptions>),
+
+    /// The parsed beginning of a delimited file
+    PreviewReply(ImportPreview),
+
+    /// The R code
+    GenerateCodeReply(String),
+}
+
+/**
+ * Frontend RPC request types for the data_import comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "params")]
+pub enum DataImportFrontendRequest {}
+
+/**
+ * Frontend RPC Reply types for the data_import comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "result")]
+pub enum DataImportFrontendReply {}
+
+/**
+ * Frontend events for the data_import comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "params")]
+pub enum DataImportFrontendEvent {}
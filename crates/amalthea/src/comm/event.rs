@@ -39,6 +39,10 @@ pub enum CommManagerEvent {
 pub enum CommManagerRequest {
     /// Open comm information
     Info(Sender<CommManagerInfoReply>),
+
+    /// Close all open comms, e.g. because the frontend is going away. Replies
+    /// with the number of comms that were closed.
+    CloseAll(Sender<usize>),
 }
 
 pub struct CommManagerInfoReply {
@@ -0,0 +1,130 @@
+/*
+ * event_log_comm.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A kernel event
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct KernelEvent {
+    /// The kind of event
+    pub kind: KernelEventKind,
+
+    /// When the event occurred, in milliseconds since the Unix epoch
+    pub timestamp: i64,
+
+    /// How long the event lasted, in milliseconds, for events that span a
+    /// period of time
+    pub duration: Option<i64>,
+
+    /// Additional details about the event, such as the name of a comm or
+    /// the code being executed
+    pub detail: Option<String>,
+}
+
+/// Possible values for Kind in KernelEvent
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+pub enum KernelEventKind {
+    #[serde(rename = "execution_started")]
+    #[strum(to_string = "execution_started")]
+    ExecutionStarted,
+
+    #[serde(rename = "execution_finished")]
+    #[strum(to_string = "execution_finished")]
+    ExecutionFinished,
+
+    #[serde(rename = "interrupt")]
+    #[strum(to_string = "interrupt")]
+    Interrupt,
+
+    #[serde(rename = "comm_opened")]
+    #[strum(to_string = "comm_opened")]
+    CommOpened,
+
+    #[serde(rename = "comm_closed")]
+    #[strum(to_string = "comm_closed")]
+    CommClosed,
+
+    #[serde(rename = "plot_rendered")]
+    #[strum(to_string = "plot_rendered")]
+    PlotRendered,
+
+    /// Time spent in garbage collection during an execution, which may
+    /// span several collections
+    #[serde(rename = "gc_time")]
+    #[strum(to_string = "gc_time")]
+    GcTime,
+
+    #[serde(rename = "input_denied")]
+    #[strum(to_string = "input_denied")]
+    InputDenied,
+}
+
+/// Parameters for the GetEvents method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GetEventsParams {
+    /// Only return events that occurred at or after this timestamp, in
+    /// milliseconds since the Unix epoch. All recorded events are returned
+    /// when omitted.
+    pub since: Option<i64>,
+}
+
+/// Parameters for the Event method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct EventParams {
+    /// The event that occurred
+    pub event: KernelEvent,
+}
+
+/**
+ * Backend RPC request types for the event_log comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "params")]
+pub enum EventLogBackendRequest {
+    /// Get recorded kernel events
+    ///
+    /// Returns the events recorded by the kernel, oldest first. Only the
+    /// most recent events are retained.
+    #[serde(rename = "get_events")]
+    GetEvents(GetEventsParams),
+}
+
+/**
+ * Backend RPC Reply types for the event_log comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "result")]
+pub enum EventLogBackendReply {
+    /// The recorded events
+    GetEventsReply(Vec<KernelEvent>),
+}
+
+/**
+ * Frontend RPC request types for the event_log comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "params")]
+pub enum EventLogFrontendRequest {}
+
+/**
+ * Frontend RPC Reply types for the event_log comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "result")]
+pub enum EventLogFrontendReply {}
+
+/**
+ * Frontend events for the event_log comm
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "params")]
+pub enum EventLogFrontendEvent {
+    /// A kernel event occurred
+    #[serde(rename = "event")]
+    Event(EventParams),
+}
@@ -10,15 +10,23 @@
 // https://github.com/rust-lang/rustfmt/issues/5080
 
 pub mod base_comm;
+pub mod comm_access;
 pub mod comm_channel;
+pub mod comm_chunk;
 pub mod comm_manager;
 #[rustfmt::skip]
 pub mod data_explorer_comm;
+#[rustfmt::skip]
+pub mod data_import_comm;
 pub mod event;
 #[rustfmt::skip]
+pub mod event_log_comm;
+#[rustfmt::skip]
 pub mod help_comm;
 #[rustfmt::skip]
 pub mod plot_comm;
+pub mod protocol;
+pub mod rpc_barrier;
 pub mod server_comm;
 #[rustfmt::skip]
 pub mod ui_comm;
@@ -34,7 +34,10 @@ pub struct PlotResult {
 	pub data: String,
 
 	/// The MIME type of the plot data
-	pub mime_type: String
+	pub mime_type: String,
+
+	/// An accessible plain-text description of the plot, if available
+	pub description: Option<String>
 }
 
 /// The size of a plot
@@ -97,6 +100,18 @@ pub struct RenderParams {
 	pub format: RenderFormat,
 }
 
+/// Parameters for the Click method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ClickParams {
+	/// The horizontal position of the click, in pixels of the rendered plot
+	/// image from its left edge
+	pub x: f64,
+
+	/// The vertical position of the click, in pixels of the rendered plot
+	/// image from its top edge
+	pub y: f64,
+}
+
 /**
  * Backend RPC request types for the plot comm
  */
@@ -117,6 +132,20 @@ pub enum PlotBackendRequest {
 	#[serde(rename = "render")]
 	Render(RenderParams),
 
+	/// Locate a point on a plot
+	///
+	/// Sends the location of a click on the plot while the backend is
+	/// waiting for graphical input, e.g. for `locator()` in R.
+	#[serde(rename = "click")]
+	Click(ClickParams),
+
+	/// Cancel graphical input
+	///
+	/// Stops waiting for clicks on the plot, e.g. when the user presses
+	/// Escape while locating points.
+	#[serde(rename = "cancel_locate")]
+	CancelLocate,
+
 }
 
 /**
@@ -131,6 +160,12 @@ pub enum PlotBackendReply {
 	/// A rendered plot
 	RenderReply(PlotResult),
 
+	/// Reply for the click method (no result)
+	ClickReply(),
+
+	/// Reply for the cancel_locate method (no result)
+	CancelLocateReply(),
+
 }
 
 /**
@@ -161,5 +196,13 @@ pub enum PlotFrontendEvent {
 	#[serde(rename = "show")]
 	Show,
 
+	/// The backend is waiting for a click on the plot
+	#[serde(rename = "locate")]
+	Locate,
+
+	/// The backend stopped waiting for a click on the plot
+	#[serde(rename = "locate_end")]
+	LocateEnd,
+
 }
 
@@ -0,0 +1,163 @@
+/*
+ * protocol.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use serde_json::json;
+use serde_json::Value;
+
+use crate::comm::comm_channel::CommMsg;
+use crate::error::Error;
+use crate::socket::comm::CommSocket;
+
+/// Field of the `comm_open` data in which frontends declare the protocol
+/// versions they support, e.g. `{ "protocol_versions": [1, 2] }` when opening
+/// a comm. Versions of the comms opened by the kernel are declared ahead of
+/// time in the same field of any comm the frontend opens, keyed by target
+/// name, e.g. `{ "protocol_versions": { "positron.dataExplorer": [1, 2] } }`.
+pub const PROTOCOL_VERSIONS_FIELD: &str = "protocol_versions";
+
+/// Field of the `comm_open` data of the comms opened by the kernel, and of
+/// the `protocol_version` event sent on the comms opened by the frontend,
+/// carrying the negotiated version
+pub const PROTOCOL_VERSION_FIELD: &str = "protocol_version";
+
+/// Protocol versions declared by the frontend, by comm target name. Kept
+/// across comms so that the comms opened by the kernel can be negotiated
+/// against them.
+static DECLARED_VERSIONS: LazyLock<Mutex<HashMap<String, Vec<u32>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Record the protocol versions declared in the `comm_open` data of a comm
+/// opened by the frontend
+pub fn declare(target_name: &str, data: &Value) {
+    let mut declared = DECLARED_VERSIONS.lock().unwrap();
+
+    match data.get(PROTOCOL_VERSIONS_FIELD) {
+        Some(Value::Array(_)) => {
+            let versions = parse_versions(target_name, &data[PROTOCOL_VERSIONS_FIELD]);
+            declared.insert(String::from(target_name), versions);
+        },
+        Some(Value::Object(targets)) => {
+            for (target, versions) in targets {
+                declared.insert(target.clone(), parse_versions(target, versions));
+            }
+        },
+        Some(value) => {
+            log::warn!("Ignoring invalid protocol versions for '{target_name}': {value}");
+        },
+        None => {},
+    }
+}
+
+fn parse_versions(target_name: &str, versions: &Value) -> Vec<u32> {
+    let Some(versions) = versions.as_array() else {
+        log::warn!("Ignoring invalid protocol versions for '{target_name}': {versions}");
+        return vec![];
+    };
+
+    versions
+        .iter()
+        .filter_map(|version| version.as_u64())
+        .filter_map(|version| u32::try_from(version).ok())
+        .collect()
+}
+
+/// Protocol versions of `target_name` declared by the frontend, if any
+pub fn declared_versions(target_name: &str) -> Option<Vec<u32>> {
+    let declared = DECLARED_VERSIONS.lock().unwrap();
+    declared.get(target_name).cloned()
+}
+
+/// Pick the protocol version of a comm: the highest version supported by both
+/// the kernel and the frontend. Frontends that don't declare versions predate
+/// versioning and speak the latest protocol of the kernel.
+///
+/// - `target_name`: The target name of the comm, e.g. `positron.variables`.
+/// - `supported`: The versions supported by the kernel.
+pub fn negotiate(target_name: &str, supported: &[u32]) -> crate::Result<u32> {
+    let Some(latest) = supported.iter().max() else {
+        return Err(Error::UnsupportedCommVersion(
+            String::from(target_name),
+            vec![],
+            vec![],
+        ));
+    };
+
+    let Some(declared) = declared_versions(target_name) else {
+        return Ok(*latest);
+    };
+
+    supported
+        .iter()
+        .filter(|version| declared.contains(version))
+        .max()
+        .copied()
+        .ok_or_else(|| {
+            Error::UnsupportedCommVersion(String::from(target_name), declared, supported.to_vec())
+        })
+}
+
+/// Tell the frontend which protocol version was picked for a comm it opened.
+/// Only frontends that declared versions expect the `protocol_version` event.
+pub fn announce(comm: &CommSocket, version: u32) {
+    if declared_versions(&comm.comm_name).is_none() {
+        return;
+    }
+
+    let event = json!({
+        "method": PROTOCOL_VERSION_FIELD,
+        "params": {
+            "version": version,
+        },
+    });
+
+    if let Err(err) = comm.outgoing_tx.send(CommMsg::Data(event)) {
+        log::error!(
+            "Can't announce protocol version of '{}' comm: {err}",
+            comm.comm_name
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::comm::protocol::declare;
+    use crate::comm::protocol::declared_versions;
+    use crate::comm::protocol::negotiate;
+
+    #[test]
+    fn test_negotiate_undeclared() {
+        // Frontends that don't declare versions get the latest protocol
+        declare("test.undeclared", &json!({}));
+        assert_eq!(negotiate("test.undeclared", &[1, 2]).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_negotiate_declared() {
+        declare("test.declared", &json!({ "protocol_versions": [1, 3] }));
+        assert_eq!(declared_versions("test.declared"), Some(vec![1, 3]));
+        assert_eq!(negotiate("test.declared", &[1, 2]).unwrap(), 1);
+        assert_eq!(negotiate("test.declared", &[1, 2, 3]).unwrap(), 3);
+        assert!(negotiate("test.declared", &[2]).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_declared_ahead() {
+        // Versions of comms opened by the kernel are declared by another comm
+        declare(
+            "test.ui",
+            &json!({ "protocol_versions": { "test.kernel": [2] } }),
+        );
+        assert_eq!(declared_versions("test.ui"), None);
+        assert_eq!(negotiate("test.kernel", &[1, 2]).unwrap(), 2);
+    }
+}
@@ -0,0 +1,186 @@
+/*
+ * rpc_barrier.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use std::collections::HashMap;
+use std::sync::Condvar;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Timeout used when an execution doesn't specify one
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Upper bound on the timeout requested by frontends, so that a forgotten RPC
+/// can't hold up the Shell socket indefinitely
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Tracks the comm RPCs sent by the frontend that haven't been replied to yet,
+/// so that executions can wait for them to complete before running.
+///
+/// RPCs are registered by the Shell thread as they are received and released
+/// by the comm manager once the comm replies, or when the comm is closed.
+/// Since the Shell thread handles messages in order, an execution can only
+/// ever wait for RPCs received before it, which can't in turn be waiting on
+/// the execution.
+#[derive(Default)]
+pub struct RpcBarrier {
+    /// Maps the message IDs of pending RPCs to the ID of their comm
+    pending: Mutex<HashMap<String, String>>,
+    changed: Condvar,
+}
+
+static RPC_BARRIER: LazyLock<RpcBarrier> = LazyLock::new(RpcBarrier::default);
+
+impl RpcBarrier {
+    pub fn started(&self, msg_id: &str, comm_id: &str) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.insert(String::from(msg_id), String::from(comm_id));
+    }
+
+    pub fn finished(&self, msg_id: &str) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.remove(msg_id).is_some() {
+            self.changed.notify_all();
+        }
+    }
+
+    /// Release all the RPCs of a comm, which will never be replied to
+    pub fn comm_closed(&self, comm_id: &str) {
+        let mut pending = self.pending.lock().unwrap();
+        let len = pending.len();
+        pending.retain(|_, comm| comm != comm_id);
+        if pending.len() != len {
+            self.changed.notify_all();
+        }
+    }
+
+    /// Block until the given RPCs, and all pending RPCs of the given comms,
+    /// have completed. RPCs that are unknown or already completed don't hold
+    /// up the wait.
+    ///
+    /// Returns the message IDs of the RPCs still pending when the timeout
+    /// elapsed, if any.
+    pub fn wait(
+        &self,
+        rpcs: &[String],
+        comms: &[String],
+        timeout: Duration,
+    ) -> Result<(), Vec<String>> {
+        let deadline = Instant::now() + timeout.min(MAX_TIMEOUT);
+        let mut pending = self.pending.lock().unwrap();
+
+        loop {
+            let blocking: Vec<String> = pending
+                .iter()
+                .filter(|(msg_id, comm_id)| rpcs.contains(msg_id) || comms.contains(comm_id))
+                .map(|(msg_id, _)| msg_id.clone())
+                .collect();
+
+            if blocking.is_empty() {
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(blocking);
+            }
+
+            pending = self
+                .changed
+                .wait_timeout(pending, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+}
+
+/// Record an RPC sent by the frontend to a comm
+pub fn started(msg_id: &str, comm_id: &str) {
+    RPC_BARRIER.started(msg_id, comm_id);
+}
+
+/// Record that an RPC was replied to, or will never be
+pub fn finished(msg_id: &str) {
+    RPC_BARRIER.finished(msg_id);
+}
+
+/// Record that a comm was closed, releasing its pending RPCs
+pub fn comm_closed(comm_id: &str) {
+    RPC_BARRIER.comm_closed(comm_id);
+}
+
+/// Wait for pending RPCs to complete, see `RpcBarrier::wait()`
+pub fn wait(rpcs: &[String], comms: &[String], timeout: Duration) -> Result<(), Vec<String>> {
+    RPC_BARRIER.wait(rpcs, comms, timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::comm::rpc_barrier::RpcBarrier;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| String::from(*id)).collect()
+    }
+
+    #[test]
+    fn test_rpc_barrier_unknown_rpcs() {
+        let barrier = RpcBarrier::default();
+        barrier.started("a", "comm");
+        barrier.finished("a");
+
+        // Completed and unknown RPCs don't block
+        let timeout = Duration::from_millis(10);
+        assert!(barrier.wait(&ids(&["a", "b"]), &[], timeout).is_ok());
+        assert!(barrier.wait(&[], &ids(&["comm"]), timeout).is_ok());
+    }
+
+    #[test]
+    fn test_rpc_barrier_timeout() {
+        let barrier = RpcBarrier::default();
+        barrier.started("a", "comm1");
+        barrier.started("b", "comm2");
+
+        let timeout = Duration::from_millis(10);
+        assert_eq!(barrier.wait(&ids(&["a"]), &[], timeout), Err(ids(&["a"])));
+        assert_eq!(
+            barrier.wait(&[], &ids(&["comm2"]), timeout),
+            Err(ids(&["b"]))
+        );
+
+        // Closing the comm releases its RPCs
+        barrier.comm_closed("comm1");
+        assert!(barrier.wait(&ids(&["a"]), &[], timeout).is_ok());
+    }
+
+    #[test]
+    fn test_rpc_barrier_waits_for_completion() {
+        let barrier = Arc::new(RpcBarrier::default());
+        barrier.started("a", "comm");
+        barrier.started("b", "comm");
+
+        let handle = thread::spawn({
+            let barrier = barrier.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                barrier.finished("a");
+                thread::sleep(Duration::from_millis(20));
+                barrier.finished("b");
+            }
+        });
+
+        let result = barrier.wait(&[], &ids(&["comm"]), Duration::from_secs(10));
+        assert!(result.is_ok());
+        assert!(barrier.pending.lock().unwrap().is_empty());
+
+        handle.join().unwrap();
+    }
+}
@@ -97,6 +97,17 @@ pub struct Range {
 	pub end: Position
 }
 
+/// A binding of an attached package masking other bindings of the same name
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MaskedBinding {
+	/// The name of the binding
+	pub name: String,
+
+	/// The environments of the search path whose bindings are masked, e.g.
+	/// `package:stats`
+	pub masked: Vec<String>,
+}
+
 /// Parameters for the CallMethod method.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct CallMethodParams {
@@ -160,6 +171,16 @@ pub struct ShowQuestionParams {
 	pub cancel_button_title: String,
 }
 
+/// Parameters for the ShowQuickPick method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ShowQuickPickParams {
+	/// The title of the quick pick
+	pub title: String,
+
+	/// The items the user can pick from
+	pub items: Vec<String>,
+}
+
 /// Parameters for the ShowDialog method.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ShowDialogParams {
@@ -251,6 +272,56 @@ pub struct ModifyEditorSelectionsParams {
 	pub values: Vec<String>,
 }
 
+/// Parameters for the Progress method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProgressParams {
+	/// Identifies the task this progress report belongs to
+	pub id: String,
+
+	/// The title of the task
+	pub title: String,
+
+	/// A message describing the current step of the task
+	pub message: String,
+
+	/// Whether the task is complete
+	pub done: bool,
+}
+
+/// Parameters for the ExecutionRunning method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExecutionRunningParams {
+	/// The ID of the `execute_request` of the execution
+	pub msg_id: String,
+
+	/// The first line of the code being executed
+	pub code: String,
+
+	/// Time spent running, in milliseconds
+	pub elapsed_ms: i64,
+
+	/// Time since the execution last emitted output, in milliseconds, or
+	/// null if it hasn't emitted any
+	pub since_output_ms: Option<i64>,
+
+	/// A message describing the state of the execution, e.g. `Still running
+	/// (elapsed 2m 13s, last output 45s ago)`
+	pub message: String,
+
+	/// Whether the execution is complete
+	pub done: bool,
+}
+
+/// Parameters for the BindingsMasked method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BindingsMaskedParams {
+	/// The newly attached environment, e.g. `package:dplyr`
+	pub environment: String,
+
+	/// The bindings of the environment that mask other bindings
+	pub bindings: Vec<MaskedBinding>,
+}
+
 /// Parameters for the ShowUrl method.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ShowUrlParams {
@@ -323,6 +394,12 @@ pub enum UiFrontendRequest {
 	#[serde(rename = "show_question")]
 	ShowQuestion(ShowQuestionParams),
 
+	/// Show a quick pick
+	///
+	/// Use this for letting the user pick one item from a list
+	#[serde(rename = "show_quick_pick")]
+	ShowQuickPick(ShowQuickPickParams),
+
 	/// Show a dialog
 	///
 	/// Use this for a modal dialog that the user can only accept
@@ -388,6 +465,10 @@ pub enum UiFrontendReply {
 	/// Whether the user accepted or rejected the dialog.
 	ShowQuestionReply(bool),
 
+	/// The 0-based index of the picked item, or null if the user dismissed
+	/// the quick pick.
+	ShowQuickPickReply(Option<i64>),
+
 	/// Reply for the show_dialog method (no result)
 	ShowDialogReply(),
 
@@ -466,6 +547,28 @@ pub enum UiFrontendEvent {
 	#[serde(rename = "show_html_file")]
 	ShowHtmlFile(ShowHtmlFileParams),
 
+	/// Reports the progress of a long running task, such as a package
+	/// installation, so the frontend can show that the runtime is not hung.
+	#[serde(rename = "progress")]
+	Progress(ProgressParams),
+
+	/// Reports an execution that has been running for a while, periodically
+	/// and once more when it completes, so the frontend can show more than a
+	/// spinner and notify the user when it is done.
+	#[serde(rename = "execution_running")]
+	ExecutionRunning(ExecutionRunningParams),
+
+	/// Signals that an attached package masks bindings of other
+	/// environments on the search path, e.g. `dplyr::filter()` masking
+	/// `stats::filter()`.
+	#[serde(rename = "bindings_masked")]
+	BindingsMasked(BindingsMaskedParams),
+
+	/// Asks the frontend to restart the runtime session, e.g. from a keyboard
+	/// shortcut handled by the LSP.
+	#[serde(rename = "restart_session")]
+	RestartSession,
+
 	/// This event is used to signal that the stored messages the front-end
 	/// replays when constructing multi-output plots should be reset. This
 	/// happens for things like a holoviews extension being changed.
@@ -484,6 +587,7 @@ pub fn ui_frontend_reply_from_value(
 	match request {
 		UiFrontendRequest::NewDocument(_) => Ok(UiFrontendReply::NewDocumentReply()),
 		UiFrontendRequest::ShowQuestion(_) => Ok(UiFrontendReply::ShowQuestionReply(serde_json::from_value(reply)?)),
+		UiFrontendRequest::ShowQuickPick(_) => Ok(UiFrontendReply::ShowQuickPickReply(serde_json::from_value(reply)?)),
 		UiFrontendRequest::ShowDialog(_) => Ok(UiFrontendReply::ShowDialogReply()),
 		UiFrontendRequest::DebugSleep(_) => Ok(UiFrontendReply::DebugSleepReply()),
 		UiFrontendRequest::ExecuteCommand(_) => Ok(UiFrontendReply::ExecuteCommandReply()),
@@ -23,7 +23,22 @@ pub struct VariableList {
 	pub length: i64,
 
 	/// The version of the view (incremented with each update)
-	pub version: Option<i64>
+	pub version: Option<i64>,
+
+	/// The groups of the variables if the view is grouped by kind. The
+	/// variables of each group are listed consecutively, in the order of the
+	/// groups.
+	pub groups: Option<Vec<VariableGroup>>
+}
+
+/// A group of variables of the same kind.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct VariableGroup {
+	/// The kind of the variables in the group
+	pub kind: VariableGroupKind,
+
+	/// The number of variables in the group
+	pub length: i64
 }
 
 /// An inspected variable.
@@ -44,6 +59,16 @@ pub struct FormattedVariable {
 	pub content: String
 }
 
+/// The result of exporting a variable to a file
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExportedObject {
+	/// The path of the file the variable was written to
+	pub file: String,
+
+	/// The format the variable was written in
+	pub format: ExportObjectFormat
+}
+
 /// A single variable in the runtime.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Variable {
@@ -102,6 +127,66 @@ pub enum ClipboardFormatFormat {
 	TextPlain
 }
 
+/// Possible values for Kind in VariableGroup
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+pub enum VariableGroupKind {
+	#[serde(rename = "data")]
+	#[strum(to_string = "data")]
+	Data,
+
+	#[serde(rename = "functions")]
+	#[strum(to_string = "functions")]
+	Functions,
+
+	#[serde(rename = "values")]
+	#[strum(to_string = "values")]
+	Values
+}
+
+/// Possible values for GroupBy in SetView
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+pub enum SetViewGroupBy {
+	#[serde(rename = "none")]
+	#[strum(to_string = "none")]
+	None,
+
+	#[serde(rename = "kind")]
+	#[strum(to_string = "kind")]
+	Kind
+}
+
+/// Possible values for SortBy in SetView
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+pub enum SetViewSortBy {
+	#[serde(rename = "name")]
+	#[strum(to_string = "name")]
+	Name,
+
+	#[serde(rename = "size")]
+	#[strum(to_string = "size")]
+	Size,
+
+	#[serde(rename = "recent")]
+	#[strum(to_string = "recent")]
+	Recent
+}
+
+/// Possible values for Format in ExportObject
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+pub enum ExportObjectFormat {
+	#[serde(rename = "rds")]
+	#[strum(to_string = "rds")]
+	Rds,
+
+	#[serde(rename = "parquet")]
+	#[strum(to_string = "parquet")]
+	Parquet,
+
+	#[serde(rename = "csv")]
+	#[strum(to_string = "csv")]
+	Csv
+}
+
 /// Possible values for Kind in Variable
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum_macros::Display)]
 pub enum VariableKind {
@@ -196,12 +281,51 @@ pub struct ViewParams {
 	pub path: Vec<String>,
 }
 
+/// Parameters for the SetView method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SetViewParams {
+	/// How to group the variables: not at all, or by kind (data, functions,
+	/// and values)
+	pub group_by: SetViewGroupBy,
+
+	/// A regular expression. Only the variables whose name matches it are
+	/// listed.
+	pub filter: Option<String>,
+
+	/// How to sort the variables within each group: by name, by decreasing
+	/// size, or by most recent modification
+	pub sort_by: SetViewSortBy,
+
+	/// Whether to list hidden objects, i.e. variables whose name starts with
+	/// a dot
+	pub show_hidden: bool,
+}
+
+/// Parameters for the ExportObject method.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExportObjectParams {
+	/// The path to the variable to export, as an array of access keys.
+	pub path: Vec<String>,
+
+	/// The path of the file to write
+	pub file: String,
+
+	/// The format to write the variable in. Defaults to the preferred format
+	/// for the class of the variable: Parquet for data frames when a Parquet
+	/// writer is installed, CSV for other tabular data, and RDS otherwise.
+	pub format: Option<ExportObjectFormat>,
+}
+
 /// Parameters for the Update method.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct UpdateParams {
 	/// An array of variables that have been newly assigned.
 	pub assigned: Vec<Variable>,
 
+	/// An array of variables that were modified in place, such as
+	/// environments, with their new summaries.
+	pub updated: Vec<Variable>,
+
 	/// An array of variables that were not evaluated for value updates.
 	pub unevaluated: Vec<Variable>,
 
@@ -225,6 +349,9 @@ pub struct RefreshParams {
 	/// The version of the view (incremented with each update), or 0 if the
 	/// backend doesn't track versions.
 	pub version: i64,
+
+	/// The groups of the variables if the view is grouped by kind
+	pub groups: Option<Vec<VariableGroup>>,
 }
 
 /**
@@ -271,6 +398,20 @@ pub enum VariablesBackendRequest {
 	#[serde(rename = "view")]
 	View(ViewParams),
 
+	/// Configure the view of the variables
+	///
+	/// Sets how the variables are grouped, filtered, and sorted. Applies to
+	/// all subsequent listings, refreshes, and updates.
+	#[serde(rename = "set_view")]
+	SetView(SetViewParams),
+
+	/// Export a variable to a file
+	///
+	/// Saves a variable to a file so that it can be used outside of the
+	/// session. Progress is reported with `progress` events of the UI comm.
+	#[serde(rename = "export_object")]
+	ExportObject(ExportObjectParams),
+
 }
 
 /**
@@ -297,6 +438,12 @@ pub enum VariablesBackendReply {
 	/// The ID of the viewer that was opened.
 	ViewReply(String),
 
+	/// A view containing a list of variables in the session.
+	SetViewReply(VariableList),
+
+	/// The file the variable was written to, and its format.
+	ExportObjectReply(ExportedObject),
+
 }
 
 /**
@@ -0,0 +1,130 @@
+/*
+ * cursor.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+//! Conversions for the `cursor_pos` field of completion and inspection
+//! requests.
+//!
+//! Since version 5.2 of the protocol, `cursor_pos` counts Unicode code points
+//! (JavaScript frontends convert from their UTF-16 offsets before sending).
+//! Rust strings are indexed by bytes, tree-sitter counts byte columns, and the
+//! LSP counts UTF-16 code units, so these positions must be converted before
+//! slicing code or handing it to analysis tools. Positions past the end of the
+//! code are clamped to the end.
+
+/// Converts a `cursor_pos` to a byte offset into `code`
+pub fn cursor_pos_to_offset(code: &str, cursor_pos: u32) -> usize {
+    code.char_indices()
+        .nth(cursor_pos as usize)
+        .map_or(code.len(), |(offset, _)| offset)
+}
+
+/// Converts a byte offset into `code` to a `cursor_pos`. Offsets in the middle
+/// of a character are rounded up to the next character boundary.
+pub fn offset_to_cursor_pos(code: &str, offset: usize) -> u32 {
+    let mut boundary = offset.min(code.len());
+    while !code.is_char_boundary(boundary) {
+        boundary += 1;
+    }
+    code[..boundary].chars().count() as u32
+}
+
+/// Converts a `cursor_pos` to a 0-based line and a byte column within that
+/// line, as used by tree-sitter points
+pub fn cursor_pos_to_point(code: &str, cursor_pos: u32) -> (usize, usize) {
+    let offset = cursor_pos_to_offset(code, cursor_pos);
+    let before = &code[..offset];
+
+    let row = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    (row, offset - line_start)
+}
+
+/// Converts a 0-based line and UTF-16 column, as used by LSP positions, to a
+/// `cursor_pos`. Columns past the end of the line are clamped to the end of
+/// the line.
+pub fn utf16_position_to_cursor_pos(code: &str, row: usize, column: usize) -> u32 {
+    let mut cursor_pos = 0;
+
+    let mut lines = code.split('\n');
+    for line in lines.by_ref().take(row) {
+        // Count the newline too
+        cursor_pos += line.chars().count() + 1;
+    }
+
+    let Some(line) = lines.next() else {
+        // Past the last line
+        return code.chars().count() as u32;
+    };
+
+    let mut n = 0;
+    for char in line.chars() {
+        if n >= column {
+            break;
+        }
+        n += char.len_utf16();
+        cursor_pos += 1;
+    }
+
+    cursor_pos as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cursor::cursor_pos_to_offset;
+    use crate::cursor::cursor_pos_to_point;
+    use crate::cursor::offset_to_cursor_pos;
+    use crate::cursor::utf16_position_to_cursor_pos;
+
+    #[test]
+    fn test_cursor_pos_ascii() {
+        let code = "foo(x)";
+        assert_eq!(cursor_pos_to_offset(code, 4), 4);
+        assert_eq!(offset_to_cursor_pos(code, 4), 4);
+        assert_eq!(cursor_pos_to_point(code, 4), (0, 4));
+        assert_eq!(utf16_position_to_cursor_pos(code, 0, 4), 4);
+    }
+
+    #[test]
+    fn test_cursor_pos_emoji() {
+        // `🙂` is 1 code point, 2 UTF-16 code units, and 4 bytes
+        let code = "x <- '🙂'; pri";
+        assert_eq!(cursor_pos_to_offset(code, 7), 10);
+        assert_eq!(cursor_pos_to_offset(code, 13), code.len());
+        assert_eq!(offset_to_cursor_pos(code, 10), 7);
+        assert_eq!(cursor_pos_to_point(code, 13), (0, 16));
+        assert_eq!(utf16_position_to_cursor_pos(code, 0, 14), 13);
+
+        // Mid-character offsets are rounded up
+        assert_eq!(offset_to_cursor_pos(code, 7), 7);
+    }
+
+    #[test]
+    fn test_cursor_pos_cjk() {
+        // CJK characters are 1 code point, 1 UTF-16 code unit, and 3 bytes
+        let code = "変数 <- 1\n変数";
+        assert_eq!(cursor_pos_to_offset(code, 2), 6);
+        assert_eq!(offset_to_cursor_pos(code, 6), 2);
+        assert_eq!(cursor_pos_to_point(code, 2), (0, 6));
+
+        // On the second line
+        assert_eq!(cursor_pos_to_offset(code, 10), code.len());
+        assert_eq!(cursor_pos_to_point(code, 10), (1, 6));
+        assert_eq!(utf16_position_to_cursor_pos(code, 1, 2), 10);
+        assert_eq!(utf16_position_to_cursor_pos(code, 1, 0), 8);
+    }
+
+    #[test]
+    fn test_cursor_pos_past_end() {
+        let code = "ś\nx";
+        assert_eq!(cursor_pos_to_offset(code, 10), code.len());
+        assert_eq!(offset_to_cursor_pos(code, 10), 3);
+        assert_eq!(cursor_pos_to_point(code, 10), (1, 1));
+        assert_eq!(utf16_position_to_cursor_pos(code, 0, 10), 1);
+        assert_eq!(utf16_position_to_cursor_pos(code, 5, 0), 3);
+    }
+}
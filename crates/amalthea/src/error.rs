@@ -42,6 +42,9 @@ pub enum Error {
     SysError(String, String),
     UnknownCommName(String),
     UnknownCommId(String),
+    /// Target name, versions declared by the frontend, versions supported by
+    /// the kernel
+    UnsupportedCommVersion(String, Vec<u32>, Vec<u32>),
     InvalidCommMessage(String, String, String),
     InvalidInputRequest(String),
     InvalidConsoleInput(String),
@@ -189,6 +192,12 @@ impl fmt::Display for Error {
             Error::UnknownCommId(id) => {
                 write!(f, "The comm id '{}' does not exist.", id)
             },
+            Error::UnsupportedCommVersion(target, declared, supported) => {
+                write!(
+                    f,
+                    "The frontend supports versions {declared:?} of the '{target}' comm protocol but the kernel supports versions {supported:?}."
+                )
+            },
             Error::InvalidCommMessage(id, msg, err) => {
                 write!(
                     f,
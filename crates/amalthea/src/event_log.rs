@@ -0,0 +1,155 @@
+/*
+ * event_log.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use std::collections::VecDeque;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use stdext::result::ResultOrLog;
+use stdext::spawn;
+
+use crate::comm::comm_channel::CommMsg;
+use crate::comm::event_log_comm::EventLogBackendReply;
+use crate::comm::event_log_comm::EventLogBackendRequest;
+use crate::comm::event_log_comm::EventLogFrontendEvent;
+use crate::comm::event_log_comm::EventParams;
+use crate::comm::event_log_comm::KernelEvent;
+use crate::comm::event_log_comm::KernelEventKind;
+use crate::socket::comm::CommSocket;
+
+/// Maximum number of events retained for frontends that connect after the
+/// fact. Older events are discarded first.
+const MAX_EVENTS: usize = 1000;
+
+/// Structured log of kernel events (executions, interrupts, comms, plots,
+/// time spent in GC, denied input requests) used by frontends to render a
+/// timeline of the session
+///
+/// Events are recorded from any thread with `record()`. They are kept in a
+/// bounded buffer and streamed to the `positron.eventLog` comms that are
+/// currently open.
+#[derive(Default)]
+struct EventLog {
+    events: VecDeque<KernelEvent>,
+    comms: Vec<CommSocket>,
+}
+
+static EVENT_LOG: LazyLock<Mutex<EventLog>> = LazyLock::new(|| Mutex::new(EventLog::default()));
+
+impl EventLog {
+    fn push(&mut self, event: KernelEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event.clone());
+
+        for comm in self.comms.iter() {
+            let event = EventLogFrontendEvent::Event(EventParams {
+                event: event.clone(),
+            });
+            let Ok(event) = serde_json::to_value(event) else {
+                continue;
+            };
+            comm.outgoing_tx
+                .send(CommMsg::Data(event))
+                .or_log_warning("Failed to send kernel event to frontend");
+        }
+    }
+
+    fn events_since(&self, since: Option<i64>) -> Vec<KernelEvent> {
+        self.events
+            .iter()
+            .filter(|event| since.map_or(true, |since| event.timestamp >= since))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Record a kernel event that occurred now.
+///
+/// - `duration`: How long the event lasted, for events recorded once they are
+///   complete, e.g. a finished execution.
+/// - `detail`: Free-form information about the event, e.g. the name of the
+///   comm that was opened.
+pub fn record(kind: KernelEventKind, duration: Option<Duration>, detail: Option<String>) {
+    let event = KernelEvent {
+        kind,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        duration: duration.map(|duration| duration.as_millis() as i64),
+        detail,
+    };
+    EVENT_LOG.lock().unwrap().push(event);
+}
+
+/// Start streaming events to a newly opened event log comm. Past events can be
+/// requested with `get_events`.
+pub fn start(comm: CommSocket) {
+    EVENT_LOG.lock().unwrap().comms.push(comm.clone());
+
+    spawn!("event-log", move || {
+        for msg in comm.incoming_rx.iter() {
+            if let CommMsg::Close = msg {
+                break;
+            }
+
+            comm.handle_request(msg, |req| match req {
+                EventLogBackendRequest::GetEvents(params) => {
+                    let events = EVENT_LOG.lock().unwrap().events_since(params.since);
+                    Ok(EventLogBackendReply::GetEventsReply(events))
+                },
+            });
+        }
+
+        EVENT_LOG
+            .lock()
+            .unwrap()
+            .comms
+            .retain(|socket| socket.comm_id != comm.comm_id);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::comm::event_log_comm::KernelEvent;
+    use crate::comm::event_log_comm::KernelEventKind;
+    use crate::event_log::EventLog;
+    use crate::event_log::MAX_EVENTS;
+
+    fn event(timestamp: i64) -> KernelEvent {
+        KernelEvent {
+            kind: KernelEventKind::Interrupt,
+            timestamp,
+            duration: None,
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn test_event_log_is_bounded() {
+        let mut log = EventLog::default();
+
+        for timestamp in 0..(MAX_EVENTS as i64 + 10) {
+            log.push(event(timestamp));
+        }
+
+        let events = log.events_since(None);
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events[0].timestamp, 10);
+    }
+
+    #[test]
+    fn test_event_log_since() {
+        let mut log = EventLog::default();
+        log.push(event(1));
+        log.push(event(2));
+        log.push(event(3));
+
+        let events = log.events_since(Some(2));
+        assert_eq!(events, vec![event(2), event(3)]);
+    }
+}
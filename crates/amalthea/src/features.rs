@@ -0,0 +1,239 @@
+/*
+ * features.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::sync::RwLock;
+
+use serde::Serialize;
+
+/// A behavior of the kernel that can be turned on or off while protocols
+/// evolve, so that frontends and users can opt in to new behaviors, or out of
+/// them, during the transition.
+///
+/// Features are declared as constants next to the code they gate and checked
+/// with `is_enabled()` at the time the behavior applies. Their default is
+/// fixed at compile time and can be overridden at runtime, see `Source`.
+#[derive(Debug)]
+pub struct Feature {
+    /// Identifies the feature in overrides, e.g. `comm_chunking`
+    pub name: &'static str,
+    pub description: &'static str,
+    pub stage: Stage,
+    pub default: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Off by default, opt in to try it out
+    Experimental,
+    /// On by default, can be turned off while frontends catch up
+    Stable,
+    /// Being phased out, turning it on is discouraged
+    Deprecated,
+}
+
+/// Where an override comes from. Command line flags take precedence over
+/// user settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Config,
+    CommandLine,
+}
+
+/// State of a feature, as reported to frontends
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FeatureStatus {
+    pub name: String,
+    pub description: String,
+    pub stage: Stage,
+    pub default: bool,
+    pub enabled: bool,
+    /// Where the override that applies comes from, if any
+    pub source: Option<Source>,
+}
+
+/// Split comm payloads larger than `COMM_CHUNK_SIZE` in chunks, see
+/// `comm_chunk.rs`. Frontends that can reassemble chunks turn it on.
+pub const COMM_CHUNKING: Feature = Feature {
+    name: "comm_chunking",
+    description: "Split large comm payloads in chunks",
+    stage: Stage::Experimental,
+    default: false,
+};
+
+/// Features known to the kernel, in registration order
+static FEATURES: LazyLock<RwLock<Vec<&'static Feature>>> =
+    LazyLock::new(|| RwLock::new(vec![&COMM_CHUNKING]));
+
+/// Overrides by source, then by feature name
+static OVERRIDES: LazyLock<RwLock<HashMap<Source, HashMap<String, bool>>>> =
+    LazyLock::new(Default::default);
+
+/// Register the features of a kernel. Amalthea's own features are always
+/// registered.
+pub fn register(features: &[&'static Feature]) {
+    let mut registered = FEATURES.write().unwrap();
+    for feature in features {
+        if !registered.iter().any(|x| x.name == feature.name) {
+            registered.push(feature);
+        }
+    }
+}
+
+/// Whether a feature is enabled, taking overrides into account
+pub fn is_enabled(feature: &Feature) -> bool {
+    match find_override(feature.name) {
+        Some((_, enabled)) => enabled,
+        None => feature.default,
+    }
+}
+
+/// Replace the overrides coming from `source`. Unknown features are reported
+/// but kept, they might be registered later.
+pub fn set_overrides(source: Source, overrides: HashMap<String, bool>) {
+    for (name, enabled) in overrides.iter() {
+        match find(name) {
+            Some(feature) if feature.stage == Stage::Deprecated && *enabled => {
+                log::warn!("Feature '{name}' is deprecated and will be removed.");
+            },
+            Some(_) => {},
+            None => log::warn!("Ignoring override of unknown feature '{name}'."),
+        }
+    }
+
+    log::info!("Feature overrides from {source:?}: {overrides:?}");
+    OVERRIDES.write().unwrap().insert(source, overrides);
+}
+
+/// Parse an override passed on the command line: `name` or `name=true` turns
+/// a feature on, `name=false` turns it off
+pub fn parse_override(arg: &str) -> anyhow::Result<(String, bool)> {
+    let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => (name, value),
+        None => (arg, "true"),
+    };
+
+    let enabled = match value.to_lowercase().as_str() {
+        "true" | "on" | "1" => true,
+        "false" | "off" | "0" => false,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Invalid value for feature '{name}': '{value}'"
+            ))
+        },
+    };
+
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Missing feature name in '{arg}'"));
+    }
+
+    Ok((String::from(name), enabled))
+}
+
+/// State of all registered features
+pub fn status() -> Vec<FeatureStatus> {
+    let features = FEATURES.read().unwrap();
+
+    features
+        .iter()
+        .map(|feature| {
+            let over = find_override(feature.name);
+            FeatureStatus {
+                name: String::from(feature.name),
+                description: String::from(feature.description),
+                stage: feature.stage,
+                default: feature.default,
+                enabled: over.map_or(feature.default, |(_, enabled)| enabled),
+                source: over.map(|(source, _)| source),
+            }
+        })
+        .collect()
+}
+
+fn find(name: &str) -> Option<&'static Feature> {
+    let features = FEATURES.read().unwrap();
+    features
+        .iter()
+        .find(|feature| feature.name == name)
+        .copied()
+}
+
+/// The override that applies to a feature, from the source of highest
+/// precedence
+fn find_override(name: &str) -> Option<(Source, bool)> {
+    let overrides = OVERRIDES.read().unwrap();
+
+    let mut sources: Vec<&Source> = overrides.keys().collect();
+    sources.sort();
+
+    sources.into_iter().rev().find_map(|source| {
+        overrides[source]
+            .get(name)
+            .map(|enabled| (*source, *enabled))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::features::is_enabled;
+    use crate::features::parse_override;
+    use crate::features::register;
+    use crate::features::set_overrides;
+    use crate::features::status;
+    use crate::features::Feature;
+    use crate::features::Source;
+    use crate::features::Stage;
+
+    const TEST_FEATURE: Feature = Feature {
+        name: "test_feature",
+        description: "A feature for testing",
+        stage: Stage::Experimental,
+        default: false,
+    };
+
+    #[test]
+    fn test_features_overrides() {
+        register(&[&TEST_FEATURE]);
+        assert!(!is_enabled(&TEST_FEATURE));
+
+        let overrides = HashMap::from([(String::from("test_feature"), true)]);
+        set_overrides(Source::Config, overrides);
+        assert!(is_enabled(&TEST_FEATURE));
+
+        // Command line flags take precedence over settings
+        let overrides = HashMap::from([(String::from("test_feature"), false)]);
+        set_overrides(Source::CommandLine, overrides);
+        assert!(!is_enabled(&TEST_FEATURE));
+
+        let status = status();
+        let status = status.iter().find(|x| x.name == "test_feature").unwrap();
+        assert_eq!(status.stage, Stage::Experimental);
+        assert!(!status.default);
+        assert!(!status.enabled);
+        assert_eq!(status.source, Some(Source::CommandLine));
+    }
+
+    #[test]
+    fn test_features_parse_override() {
+        assert_eq!(parse_override("foo").unwrap(), (String::from("foo"), true));
+        assert_eq!(
+            parse_override("foo=false").unwrap(),
+            (String::from("foo"), false)
+        );
+        assert_eq!(
+            parse_override("foo=ON").unwrap(),
+            (String::from("foo"), true)
+        );
+        assert!(parse_override("foo=maybe").is_err());
+        assert!(parse_override("=true").is_err());
+    }
+}
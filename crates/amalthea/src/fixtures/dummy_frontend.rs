@@ -17,6 +17,7 @@ use crate::wire::execute_input::ExecuteInput;
 use crate::wire::execute_request::ExecuteRequest;
 use crate::wire::handshake_reply::HandshakeReply;
 use crate::wire::input_reply::InputReply;
+use crate::wire::interrupt_request::InterruptRequest;
 use crate::wire::jupyter_message::JupyterMessage;
 use crate::wire::jupyter_message::Message;
 use crate::wire::jupyter_message::ProtocolMessage;
@@ -36,7 +37,7 @@ pub struct DummyConnection {
 }
 
 pub struct DummyFrontend {
-    pub _control_socket: Socket,
+    pub control_socket: Socket,
     pub shell_socket: Socket,
     pub iopub_socket: Socket,
     pub stdin_socket: Socket,
@@ -132,7 +133,7 @@ impl DummyFrontend {
         // the Jupyter specification, these must share a ZeroMQ identity.
         let shell_id = rand::thread_rng().gen::<[u8; 16]>();
 
-        let _control_socket = Socket::new(
+        let control_socket = Socket::new(
             connection.session.clone(),
             connection.ctx.clone(),
             String::from("Control"),
@@ -197,7 +198,7 @@ impl DummyFrontend {
         });
 
         Self {
-            _control_socket,
+            control_socket,
             shell_socket,
             iopub_socket,
             stdin_socket,
@@ -212,6 +213,16 @@ impl DummyFrontend {
         Self::send(&self.shell_socket, &self.session, msg)
     }
 
+    /// Sends a Jupyter message on the Shell socket as if it came from another
+    /// client, identified by `session_id`
+    pub fn send_shell_from_session<T: ProtocolMessage>(&self, msg: T, session_id: &str) -> String {
+        let mut message = JupyterMessage::create(msg, None, &self.session);
+        message.header.session = String::from(session_id);
+        let id = message.header.msg_id.clone();
+        message.send(&self.shell_socket).unwrap();
+        id
+    }
+
     pub fn send_execute_request(&self, code: &str, options: ExecuteRequestOptions) -> String {
         self.send_shell(ExecuteRequest {
             code: String::from(code),
@@ -220,9 +231,19 @@ impl DummyFrontend {
             user_expressions: serde_json::Value::Null,
             allow_stdin: options.allow_stdin,
             stop_on_error: false,
+            positron: None,
         })
     }
 
+    /// Sends a Jupyter message on the Control socket
+    pub fn send_control<T: ProtocolMessage>(&self, msg: T) -> String {
+        Self::send(&self.control_socket, &self.session, msg)
+    }
+
+    pub fn send_control_interrupt_request(&self) -> String {
+        self.send_control(InterruptRequest {})
+    }
+
     /// Sends a Jupyter message on the Stdin socket
     pub fn send_stdin<T: ProtocolMessage>(&self, msg: T) {
         Self::send(&self.stdin_socket, &self.session, msg);
@@ -249,6 +270,11 @@ impl DummyFrontend {
         panic!("Timeout while expecting message on socket {}", socket.name);
     }
 
+    /// Receives a Jupyter message from the Control socket
+    pub fn recv_control(&self) -> Message {
+        Self::recv(&self.control_socket)
+    }
+
     /// Receives a Jupyter message from the Shell socket
     pub fn recv_shell(&self) -> Message {
         Self::recv(&self.shell_socket)
@@ -275,6 +301,31 @@ impl DummyFrontend {
         })
     }
 
+    /// Receive from Control and assert `InterruptReply` message
+    pub fn recv_control_interrupt_reply(&self) {
+        let msg = self.recv_control();
+
+        assert_matches!(msg, Message::InterruptReply(data) => {
+            assert_eq!(data.content.status, Status::Ok);
+        });
+    }
+
+    /// Receive from IOPub until the Idle message of the request `msg_id`.
+    /// Other messages are skipped, e.g. the output of an interrupted
+    /// execution or the status of Control requests.
+    pub fn recv_iopub_until_idle(&self, msg_id: &str) {
+        loop {
+            let Message::Status(data) = self.recv_iopub() else {
+                continue;
+            };
+
+            let parent = data.parent_header.as_ref().map(|header| header.msg_id.as_str());
+            if data.content.execution_state == ExecutionState::Idle && parent == Some(msg_id) {
+                return;
+            }
+        }
+    }
+
     /// Receive from Shell and assert `ExecuteReplyException` message.
     /// Returns `execution_count`.
     pub fn recv_shell_execute_reply_exception(&self) -> u32 {
@@ -1 +1,2 @@
 pub mod dummy_frontend;
+pub mod replay;
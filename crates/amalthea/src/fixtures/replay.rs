@@ -0,0 +1,89 @@
+/*
+ * replay.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use crate::fixtures::dummy_frontend::DummyFrontend;
+use crate::recorder::Direction;
+use crate::recorder::RecordedMessage;
+use crate::socket::socket::Socket;
+use crate::wire::wire_message::WireMessage;
+
+/// How long to wait for each message of the kernel. Replays may run code that
+/// took a while in the recorded session.
+const REPLAY_TIMEOUT_MS: i64 = 10_000;
+
+/// A message that the kernel sent in the recorded session, along with the
+/// message it sent in its place during the replay
+pub struct ReplayedMessage<'a> {
+    pub recorded: &'a RecordedMessage,
+    pub replayed: WireMessage,
+}
+
+/// Replay a recorded session against the kernel connected to `frontend`.
+///
+/// The messages of the frontend are sent again in order, with their original
+/// IDs so that the replies of the kernel point to the same parents as in the
+/// recording. Before sending a message, the messages that the kernel sent
+/// before it in the recording are received from the same sockets. Since
+/// requests wait on the replies they followed, the kernel sees the same
+/// sequence of requests as in the recorded session.
+///
+/// Returns the messages of the kernel, paired with their recorded
+/// counterparts, so that tests can compare them or look for the reported
+/// failure. Fails if the kernel doesn't send as many messages as it did in
+/// the recording.
+pub fn replay<'a>(
+    frontend: &DummyFrontend,
+    recording: &'a [RecordedMessage],
+) -> anyhow::Result<Vec<ReplayedMessage<'a>>> {
+    let mut out = Vec::new();
+
+    for recorded in recording {
+        let Some(socket) = socket(frontend, &recorded.socket) else {
+            log::warn!(
+                "Skipping message recorded on unknown socket {}",
+                recorded.socket
+            );
+            continue;
+        };
+
+        match recorded.direction {
+            Direction::Incoming => {
+                recorded.to_wire_message().send(socket)?;
+            },
+            Direction::Outgoing => {
+                // Consumed by the dummy frontend when it connected
+                if recorded.header.msg_type == "iopub_welcome" {
+                    continue;
+                }
+
+                if !socket.poll_incoming(REPLAY_TIMEOUT_MS)? {
+                    return Err(anyhow::anyhow!(
+                        "Timeout while expecting '{}' message on socket {} (recorded at {})",
+                        recorded.header.msg_type,
+                        recorded.socket,
+                        recorded.timestamp
+                    ));
+                }
+
+                let replayed = WireMessage::read_from_socket(socket)?;
+                out.push(ReplayedMessage { recorded, replayed });
+            },
+        }
+    }
+
+    Ok(out)
+}
+
+fn socket<'a>(frontend: &'a DummyFrontend, name: &str) -> Option<&'a Socket> {
+    match name {
+        "Shell" => Some(&frontend.shell_socket),
+        "Control" => Some(&frontend.control_socket),
+        "Stdin" => Some(&frontend.stdin_socket),
+        "IOPub" => Some(&frontend.iopub_socket),
+        _ => None,
+    }
+}
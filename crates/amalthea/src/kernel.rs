@@ -31,6 +31,7 @@ use crate::socket::heartbeat::Heartbeat;
 use crate::socket::iopub::IOPub;
 use crate::socket::iopub::IOPubMessage;
 use crate::socket::shell::Shell;
+use crate::socket::shell::ShellCancellation;
 use crate::socket::socket::Socket;
 use crate::socket::stdin::StdInRequest;
 use crate::socket::stdin::Stdin;
@@ -104,7 +105,12 @@ pub fn connect(
     )?;
     let shell_port = port_finalize(&shell_socket, connection_file.shell_port)?;
 
+    // Shell requests are cancelled when the Control socket receives an
+    // interrupt
+    let shell_cancellation = ShellCancellation::default();
+
     let iopub_tx_clone = iopub_tx.clone();
+    let shell_cancellation_clone = shell_cancellation.clone();
     spawn!(format!("{name}-shell"), move || {
         shell_thread(
             shell_socket,
@@ -113,6 +119,7 @@ pub fn connect(
             shell_handler,
             lsp_handler,
             dap_handler,
+            shell_cancellation_clone,
         )
     });
 
@@ -249,6 +256,7 @@ pub fn connect(
             iopub_tx_clone,
             control_handler,
             stdin_interrupt_tx,
+            shell_cancellation,
         );
         log::error!("Control thread exited");
     });
@@ -317,8 +325,15 @@ fn control_thread(
     iopub_tx: Sender<IOPubMessage>,
     handler: Arc<Mutex<dyn ControlHandler>>,
     stdin_interrupt_tx: Sender<bool>,
+    shell_cancellation: ShellCancellation,
 ) {
-    let control = Control::new(socket, iopub_tx, handler, stdin_interrupt_tx);
+    let control = Control::new(
+        socket,
+        iopub_tx,
+        handler,
+        stdin_interrupt_tx,
+        shell_cancellation,
+    );
     control.listen();
 }
 
@@ -330,6 +345,7 @@ fn shell_thread(
     shell_handler: Box<dyn ShellHandler>,
     lsp_handler: Option<Arc<Mutex<dyn ServerHandler>>>,
     dap_handler: Option<Arc<Mutex<dyn ServerHandler>>>,
+    cancellation: ShellCancellation,
 ) -> Result<(), Error> {
     let mut shell = Shell::new(
         socket,
@@ -338,6 +354,7 @@ fn shell_thread(
         shell_handler,
         lsp_handler,
         dap_handler,
+        cancellation,
     );
     shell.listen();
     Ok(())
@@ -6,6 +6,7 @@
  */
 
 use async_trait::async_trait;
+use stdext::cancellation::CancellationToken;
 
 use crate::comm::comm_channel::Comm;
 use crate::socket::comm::CommSocket;
@@ -21,13 +22,35 @@ use crate::wire::kernel_info_reply::KernelInfoReply;
 use crate::wire::kernel_info_request::KernelInfoRequest;
 use crate::wire::originator::Originator;
 
+/// Context of a request received on the Shell socket, passed to the handler
+/// along with the request.
+#[derive(Debug, Clone)]
+pub struct ShellContext {
+    /// Identifies the peer that sent the request, along with the request
+    /// header that is the parent of messages sent on its behalf. Needed e.g.
+    /// to perform an input request during execution.
+    pub originator: Originator,
+
+    /// Cancelled when the frontend interrupts the kernel while the request is
+    /// being handled. Long running handlers, e.g. completions, should give up
+    /// once it's cancelled.
+    pub cancellation: CancellationToken,
+}
+
+/// Handler of the requests received on the Shell socket.
+///
+/// Completion, inspection, and code completeness requests only need shared
+/// access to the handler. They are handled on threads of their own, possibly
+/// concurrently, so that a slow request doesn't hold up the Shell socket. The
+/// other requests are handled one at a time on the Shell thread.
 #[async_trait]
-pub trait ShellHandler: Send {
+pub trait ShellHandler: Send + Sync {
     /// Handles a request for information about the kernel.
     ///
     /// Docs: https://jupyter-client.readthedocs.io/en/stable/messaging.html#kernel-info
     async fn handle_info_request(
         &mut self,
+        ctx: &ShellContext,
         req: &KernelInfoRequest,
     ) -> crate::Result<KernelInfoReply>;
 
@@ -37,30 +60,36 @@ pub trait ShellHandler: Send {
     /// Docs: https://jupyter-client.readthedocs.io/en/stable/messaging.html#code-completeness
     async fn handle_is_complete_request(
         &self,
+        ctx: &ShellContext,
         req: &IsCompleteRequest,
     ) -> crate::Result<IsCompleteReply>;
 
     /// Handles a request to execute code.
     ///
-    /// The `originator` is an opaque byte array identifying the peer that sent
-    /// the request; it is needed to perform an input request during execution.
-    ///
     /// Docs: https://jupyter-client.readthedocs.io/en/stable/messaging.html#execute
     async fn handle_execute_request(
         &mut self,
-        originator: Originator,
+        ctx: &ShellContext,
         req: &ExecuteRequest,
     ) -> crate::Result<ExecuteReply>;
 
     /// Handles a request to provide completions for the given code fragment.
     ///
     /// Docs: https://jupyter-client.readthedocs.io/en/stable/messaging.html#completion
-    async fn handle_complete_request(&self, req: &CompleteRequest) -> crate::Result<CompleteReply>;
+    async fn handle_complete_request(
+        &self,
+        ctx: &ShellContext,
+        req: &CompleteRequest,
+    ) -> crate::Result<CompleteReply>;
 
     /// Handles a request to inspect a fragment of code.
     ///
     /// Docs: https://jupyter-client.readthedocs.io/en/stable/messaging.html#introspection
-    async fn handle_inspect_request(&self, req: &InspectRequest) -> crate::Result<InspectReply>;
+    async fn handle_inspect_request(
+        &self,
+        ctx: &ShellContext,
+        req: &InspectRequest,
+    ) -> crate::Result<InspectReply>;
 
     /// Handles a request to open a comm.
     ///
@@ -68,7 +97,13 @@ pub trait ShellHandler: Send {
     ///
     /// Returns true if the handler handled the request (and opened the comm), false if it did not.
     ///
+    /// * `ctx` - The context of the `comm_open` message
     /// * `target` - The target name of the comm, such as `positron.variables`
     /// * `comm` - The comm channel to use to communicate with the frontend
-    async fn handle_comm_open(&self, target: Comm, comm: CommSocket) -> crate::Result<bool>;
+    async fn handle_comm_open(
+        &self,
+        ctx: &ShellContext,
+        target: Comm,
+        comm: CommSocket,
+    ) -> crate::Result<bool>;
 }
@@ -7,12 +7,16 @@
 
 pub mod comm;
 pub mod connection_file;
+pub mod cursor;
 pub mod error;
+pub mod event_log;
+pub mod features;
 pub mod fixtures;
 pub mod kernel;
 pub mod kernel_dirs;
 pub mod kernel_spec;
 pub mod language;
+pub mod recorder;
 pub mod registration_file;
 pub mod session;
 pub mod socket;
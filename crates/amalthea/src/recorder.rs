@@ -0,0 +1,251 @@
+/*
+ * recorder.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::value::RawValue;
+
+use crate::wire::header::JupyterHeader;
+use crate::wire::wire_message::WireMessage;
+use crate::wire::wire_message::WireMessageRef;
+
+/// Recording of the Jupyter messages exchanged with frontends, enabled with
+/// `start()`. Recordings are meant to be attached to bug reports and fed back
+/// to a kernel with `fixtures::replay` to reproduce protocol issues.
+///
+/// Messages are written as JSON lines, as soon as they are sent or received,
+/// so that the recording is complete even if the kernel crashes. HMAC
+/// signatures and ZeroMQ identities are not recorded, recordings can be shared
+/// without leaking the key of the session.
+static RECORDER: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Sockets whose traffic is recorded. Internal sockets and the registration
+/// handshake are left out.
+const RECORDED_SOCKETS: &[&str] = &["Shell", "Control", "Stdin", "IOPub"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Sent by the frontend to the kernel
+    Incoming,
+    /// Sent by the kernel to the frontend
+    Outgoing,
+}
+
+/// A Jupyter message, as recorded
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// Time at which the message went through the socket, RFC 3339
+    pub timestamp: String,
+    pub direction: Direction,
+    /// Name of the socket, e.g. `Shell`
+    pub socket: String,
+    pub header: JupyterHeader,
+    pub parent_header: Option<JupyterHeader>,
+    pub metadata: Box<RawValue>,
+    pub content: Box<RawValue>,
+}
+
+impl RecordedMessage {
+    fn new(
+        direction: Direction,
+        socket: &str,
+        header: &JupyterHeader,
+        parent_header: &Option<JupyterHeader>,
+        metadata: &RawValue,
+        content: &RawValue,
+    ) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            direction,
+            socket: String::from(socket),
+            header: header.clone(),
+            parent_header: parent_header.clone(),
+            metadata: metadata.to_owned(),
+            content: content.to_owned(),
+        }
+    }
+
+    /// The recorded message as a wire message that can be sent again. It is
+    /// signed with the key of the socket it is sent on.
+    pub fn to_wire_message(&self) -> WireMessage {
+        WireMessage {
+            zmq_identities: Vec::new(),
+            header: self.header.clone(),
+            parent_header: self.parent_header.clone(),
+            metadata: self.metadata.clone(),
+            content: self.content.clone(),
+        }
+    }
+}
+
+/// Start recording the traffic of the session to a new file in `dir`, which is
+/// created if needed. Returns the path of the recording.
+pub fn start(dir: &Path) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let path = dir.join(format!("session-{timestamp}-{}.jsonl", std::process::id()));
+    let file = File::create(&path)?;
+
+    if RECORDER.set(Mutex::new(file)).is_err() {
+        return Err(anyhow::anyhow!("The session is already being recorded"));
+    }
+
+    log::info!("Recording wire traffic to '{}'", path.display());
+    Ok(path)
+}
+
+/// Whether the traffic is being recorded. Checked before doing any work to
+/// record a message.
+pub fn is_recording() -> bool {
+    RECORDER.get().is_some()
+}
+
+/// Record a message received on `socket`. The message is parsed from the frames
+/// again as the receiver may not parse it fully.
+pub(crate) fn record_incoming(socket: &str, frames: &[zmq::Message]) {
+    if !is_recording() || !RECORDED_SOCKETS.contains(&socket) {
+        return;
+    }
+
+    // The signature was checked by the receiver, if there is one
+    let msg = match WireMessageRef::from_frames(frames, &None) {
+        Ok(msg) => msg,
+        Err(err) => {
+            log::warn!("Can't record message received on {socket}: {err}");
+            return;
+        },
+    };
+
+    write(RecordedMessage::new(
+        Direction::Incoming,
+        socket,
+        &msg.header,
+        &msg.parent_header,
+        msg.metadata,
+        msg.content,
+    ));
+}
+
+/// Record a message sent on `socket`
+pub(crate) fn record_outgoing(socket: &str, msg: &WireMessage) {
+    if !is_recording() || !RECORDED_SOCKETS.contains(&socket) {
+        return;
+    }
+
+    write(RecordedMessage::new(
+        Direction::Outgoing,
+        socket,
+        &msg.header,
+        &msg.parent_header,
+        &msg.metadata,
+        &msg.content,
+    ));
+}
+
+fn write(msg: RecordedMessage) {
+    let Some(recorder) = RECORDER.get() else {
+        return;
+    };
+
+    let result = (|| -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(&msg)?;
+        line.push(b'\n');
+
+        let mut file = recorder.lock().unwrap();
+        file.write_all(&line)?;
+        file.flush()?;
+        Ok(())
+    })();
+
+    if let Err(err) = result {
+        log::warn!("Can't record '{}' message: {err}", msg.header.msg_type);
+    }
+}
+
+/// Read a recording created with `start()`
+pub fn read(path: &Path) -> anyhow::Result<Vec<RecordedMessage>> {
+    let file = File::open(path)?;
+    let mut messages = Vec::new();
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let msg = serde_json::from_str(&line)
+            .map_err(|err| anyhow::anyhow!("Invalid message at line {}: {err}", i + 1))?;
+        messages.push(msg);
+    }
+
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use serde_json::value::RawValue;
+
+    use crate::recorder::read;
+    use crate::recorder::Direction;
+    use crate::recorder::RecordedMessage;
+    use crate::wire::header::JupyterHeader;
+
+    #[test]
+    fn test_recording_round_trip() {
+        let header = JupyterHeader {
+            msg_id: String::from("1"),
+            session: String::from("s"),
+            username: String::from("u"),
+            date: String::from(""),
+            msg_type: String::from("execute_request"),
+            version: String::from("5.3"),
+        };
+        let content = RawValue::from_string(String::from(r#"{"code":"1 + 1"}"#)).unwrap();
+        let metadata = RawValue::from_string(String::from("{}")).unwrap();
+
+        let msg = RecordedMessage::new(
+            Direction::Incoming,
+            "Shell",
+            &header,
+            &None,
+            &metadata,
+            &content,
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("amalthea-recording-{}.jsonl", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "{}", serde_json::to_string(&msg).unwrap()).unwrap();
+        drop(file);
+
+        let messages = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].direction, Direction::Incoming);
+        assert_eq!(messages[0].socket, "Shell");
+        assert_eq!(messages[0].header.msg_type, "execute_request");
+        assert_eq!(messages[0].content.get(), r#"{"code":"1 + 1"}"#);
+
+        let wire = messages[0].to_wire_message();
+        assert!(wire.zmq_identities.is_empty());
+        assert_eq!(wire.header.msg_id, "1");
+    }
+}
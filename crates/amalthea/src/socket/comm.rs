@@ -9,6 +9,7 @@ use crossbeam::channel::Receiver;
 use crossbeam::channel::Sender;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use stdext::cancellation::CancellationToken;
 
 use crate::comm::base_comm::json_rpc_error;
 use crate::comm::base_comm::JsonRpcErrorCode;
@@ -53,6 +54,12 @@ pub struct CommSocket {
 
     /// The other side of the channel receiving messages from the frontend
     pub incoming_rx: Receiver<CommMsg>,
+
+    /// Cancelled by the comm manager as soon as the comm is closed, so that
+    /// long running work done on behalf of the comm, e.g. formatting a large
+    /// table, can stop early. Unlike `CommMsg::Close`, this doesn't wait for
+    /// the back end to process its incoming messages.
+    pub closed: CancellationToken,
 }
 
 /**
@@ -96,6 +103,7 @@ impl CommSocket {
             outgoing_rx,
             incoming_tx,
             incoming_rx,
+            closed: CancellationToken::new(),
         }
     }
 
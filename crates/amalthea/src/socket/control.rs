@@ -17,10 +17,13 @@ use log::trace;
 use log::warn;
 use stdext::unwrap;
 
+use crate::comm::event_log_comm::KernelEventKind;
 use crate::error::Error;
+use crate::event_log;
 use crate::language::control_handler::ControlHandler;
 use crate::socket::iopub::IOPubContextChannel;
 use crate::socket::iopub::IOPubMessage;
+use crate::socket::shell::ShellCancellation;
 use crate::socket::socket::Socket;
 use crate::wire::interrupt_request::InterruptRequest;
 use crate::wire::jupyter_message::JupyterMessage;
@@ -35,6 +38,7 @@ pub struct Control {
     iopub_tx: Sender<IOPubMessage>,
     handler: Arc<Mutex<dyn ControlHandler>>,
     stdin_interrupt_tx: Sender<bool>,
+    shell_cancellation: ShellCancellation,
 }
 
 impl Control {
@@ -43,12 +47,14 @@ impl Control {
         iopub_tx: Sender<IOPubMessage>,
         handler: Arc<Mutex<dyn ControlHandler>>,
         stdin_interrupt_tx: Sender<bool>,
+        shell_cancellation: ShellCancellation,
     ) -> Self {
         Self {
             socket,
             iopub_tx,
             handler,
             stdin_interrupt_tx,
+            shell_cancellation,
         }
     }
 
@@ -154,6 +160,8 @@ impl Control {
             req
         );
 
+        event_log::record(KernelEventKind::Interrupt, None, None);
+
         // Notify StdIn socket first in case it's waiting for
         // input which is never going to come because of the
         // interrupt
@@ -161,6 +169,10 @@ impl Control {
             error!("Failed to send interrupt request: {:?}", err);
         }
 
+        // Let the request being handled on the Shell socket give up, e.g.
+        // long running completions
+        self.shell_cancellation.cancel();
+
         // Lock the control handler object on this thread
         let control_handler = self.handler.lock().unwrap();
 
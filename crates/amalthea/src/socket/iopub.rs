@@ -5,8 +5,12 @@
  *
  */
 
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
+use std::time::Instant;
 
+use crossbeam::channel::never;
 use crossbeam::channel::tick;
 use crossbeam::channel::Receiver;
 use crossbeam::channel::Sender;
@@ -34,6 +38,25 @@ use crate::wire::subscription_message::SubscriptionMessage;
 use crate::wire::update_display_data::UpdateDisplayData;
 use crate::wire::welcome::Welcome;
 
+/// Interval at which stream output is flushed to the frontend by default
+pub const DEFAULT_STREAM_FLUSH_INTERVAL: Duration = Duration::from_millis(80);
+
+/// Current flush interval, in milliseconds
+static STREAM_FLUSH_INTERVAL: AtomicU64 =
+    AtomicU64::new(DEFAULT_STREAM_FLUSH_INTERVAL.as_millis() as u64);
+
+/// Set the interval at which `stdout` and `stderr` output is flushed to the
+/// frontend. Output is batched in between to limit the number of messages
+/// when there is a lot of it. With a zero interval, output is sent as soon as
+/// it is produced. Takes effect at the next flush.
+pub fn set_stream_flush_interval(interval: Duration) {
+    STREAM_FLUSH_INTERVAL.store(interval.as_millis() as u64, Ordering::Relaxed);
+}
+
+pub fn stream_flush_interval() -> Duration {
+    Duration::from_millis(STREAM_FLUSH_INTERVAL.load(Ordering::Relaxed))
+}
+
 pub struct IOPub {
     /// A channel that receives IOPub messages from other threads
     rx: Receiver<IOPubMessage>,
@@ -126,11 +149,17 @@ impl IOPub {
         self.emit_state(ExecutionState::Starting);
 
         // Flush the active stream (either stdout or stderr) at regular
-        // intervals
-        let flush_interval = *StreamBuffer::interval();
-        let flush_interval = tick(flush_interval);
+        // intervals. The interval may change while we listen.
+        let mut interval = stream_flush_interval();
+        let mut flush_interval = flush_ticker(interval);
 
         loop {
+            if stream_flush_interval() != interval {
+                self.flush_stream();
+                interval = stream_flush_interval();
+                flush_interval = flush_ticker(interval);
+            }
+
             select! {
                 recv(self.rx) -> message => {
                     match message {
@@ -362,6 +391,11 @@ impl IOPub {
 
         self.buffer.push(message.text);
 
+        // Unbuffered output
+        if stream_flush_interval().is_zero() {
+            self.flush_stream();
+        }
+
         Ok(())
     }
 
@@ -427,9 +461,14 @@ impl StreamBuffer {
             text,
         }
     }
+}
 
-    fn interval() -> &'static Duration {
-        static STREAM_BUFFER_INTERVAL: Duration = Duration::from_millis(80);
-        &STREAM_BUFFER_INTERVAL
+/// Ticks every `interval`. A zero interval never ticks since streams are then
+/// flushed as soon as they are written to.
+fn flush_ticker(interval: Duration) -> Receiver<Instant> {
+    if interval.is_zero() {
+        never()
+    } else {
+        tick(interval)
     }
 }
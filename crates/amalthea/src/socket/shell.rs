@@ -5,25 +5,37 @@
  *
  */
 
-use std::cell::RefCell;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
 
+use crossbeam::channel::unbounded;
 use crossbeam::channel::Receiver;
 use crossbeam::channel::Sender;
 use futures::executor::block_on;
 use serde_json::json;
+use stdext::cancellation::CancellationToken;
 use stdext::result::ResultOrLog;
+use stdext::spawn;
 
 use crate::comm::comm_channel::Comm;
 use crate::comm::comm_channel::CommMsg;
 use crate::comm::event::CommManagerEvent;
 use crate::comm::event::CommManagerInfoReply;
 use crate::comm::event::CommManagerRequest;
+use crate::comm::event_log_comm::KernelEventKind;
+use crate::comm::protocol;
+use crate::comm::rpc_barrier;
 use crate::comm::server_comm::ServerComm;
 use crate::error::Error;
+use crate::event_log;
 use crate::language::server_handler::ServerHandler;
+use crate::language::shell_handler::ShellContext;
 use crate::language::shell_handler::ShellHandler;
 use crate::socket::comm::CommInitiator;
 use crate::socket::comm::CommSocket;
@@ -37,6 +49,7 @@ use crate::wire::comm_info_request::CommInfoRequest;
 use crate::wire::comm_msg::CommWireMsg;
 use crate::wire::comm_open::CommOpen;
 use crate::wire::exception::Exception;
+use crate::wire::execute_request::ExecuteRequest;
 use crate::wire::header::JupyterHeader;
 use crate::wire::jupyter_message::JupyterMessage;
 use crate::wire::jupyter_message::Message;
@@ -47,6 +60,18 @@ use crate::wire::originator::Originator;
 use crate::wire::status::ExecutionState;
 use crate::wire::status::KernelStatus;
 
+/// Maximum number of characters of code recorded in the kernel event log for
+/// each execution
+const EVENT_CODE_SUMMARY_LEN: usize = 80;
+
+/// How often the Shell thread checks for replies of requests handled on other
+/// threads while some are in flight, in milliseconds
+const REPLY_POLL_INTERVAL_MS: i64 = 10;
+
+/// Sends the reply of a request handled on another thread. Run on the Shell
+/// thread, the only one allowed to use the socket.
+type ShellReply = Box<dyn FnOnce(&Socket) -> crate::Result<()> + Send>;
+
 /// Wrapper for the Shell socket; receives requests for execution, etc. from the
 /// frontend and handles them or dispatches them to the execution thread.
 pub struct Shell {
@@ -56,8 +81,9 @@ pub struct Shell {
     /// Sends messages to the IOPub socket (owned by another thread)
     iopub_tx: Sender<IOPubMessage>,
 
-    /// Language-provided shell handler object
-    shell_handler: RefCell<Box<dyn ShellHandler>>,
+    /// Language-provided shell handler object, shared with the threads
+    /// handling completion and inspection requests
+    shell_handler: Arc<RwLock<Box<dyn ShellHandler>>>,
 
     /// Language-provided LSP handler object
     lsp_handler: Option<Arc<Mutex<dyn ServerHandler>>>,
@@ -67,6 +93,46 @@ pub struct Shell {
 
     /// Channel used to deliver comm events to the comm manager
     comm_manager_tx: Sender<CommManagerEvent>,
+
+    /// Cancellation of the requests being handled, shared with Control
+    cancellation: ShellCancellation,
+
+    /// Replies of the requests handled on other threads
+    reply_tx: Sender<ShellReply>,
+    reply_rx: Receiver<ShellReply>,
+
+    /// Number of requests handled on other threads that haven't replied yet
+    pending_replies: Cell<usize>,
+}
+
+/// Cancellation tokens of the requests being handled on the Shell socket,
+/// indexed by message ID. Shared with the Control socket, which cancels them
+/// when the frontend interrupts the kernel.
+#[derive(Clone, Debug, Default)]
+pub struct ShellCancellation {
+    tokens: Arc<Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl ShellCancellation {
+    /// Cancel the requests being handled, if any
+    pub fn cancel(&self) {
+        for token in self.tokens.lock().unwrap().values() {
+            token.cancel();
+        }
+    }
+
+    fn start(&self, id: &str) -> CancellationToken {
+        let token = CancellationToken::new();
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(String::from(id), token.clone());
+        token
+    }
+
+    fn finish(&self, id: &str) {
+        self.tokens.lock().unwrap().remove(id);
+    }
 }
 
 impl Shell {
@@ -78,6 +144,7 @@ impl Shell {
     /// * `comm_changed_rx` - A channel that receives messages from the comm manager thread
     /// * `shell_handler` - The language's shell channel handler
     /// * `lsp_handler` - The language's LSP handler, if it supports LSP
+    /// * `cancellation` - Cancels requests when the kernel is interrupted
     pub fn new(
         socket: Socket,
         iopub_tx: Sender<IOPubMessage>,
@@ -85,10 +152,12 @@ impl Shell {
         shell_handler: Box<dyn ShellHandler>,
         lsp_handler: Option<Arc<Mutex<dyn ServerHandler>>>,
         dap_handler: Option<Arc<Mutex<dyn ServerHandler>>>,
+        cancellation: ShellCancellation,
     ) -> Self {
-        // Need a RefCell to allow handler methods to be mutable.
-        // We only run one handler at a time so this is safe.
-        let shell_handler = RefCell::new(shell_handler);
+        // Need a lock to allow handler methods to be mutable. Requests
+        // handled on other threads only need shared access.
+        let shell_handler = Arc::new(RwLock::new(shell_handler));
+        let (reply_tx, reply_rx) = unbounded();
         Self {
             socket,
             iopub_tx,
@@ -96,6 +165,10 @@ impl Shell {
             lsp_handler,
             dap_handler,
             comm_manager_tx,
+            cancellation,
+            reply_tx,
+            reply_rx,
+            pending_replies: Cell::new(0),
         }
     }
 
@@ -103,7 +176,26 @@ impl Shell {
     pub fn listen(&mut self) {
         // Begin listening for shell messages
         loop {
+            // Deliver the replies of requests handled on other threads
+            self.send_pending_replies();
+
             log::trace!("Waiting for shell messages");
+
+            // Wake up regularly to deliver replies while requests are being
+            // handled on other threads, otherwise wait for the next message
+            let timeout = match self.pending_replies.get() {
+                0 => -1,
+                _ => REPLY_POLL_INTERVAL_MS,
+            };
+            match self.socket.poll_incoming(timeout) {
+                Ok(true) => {},
+                Ok(false) => continue,
+                Err(err) => {
+                    log::warn!("Could not poll shell socket: {err}");
+                    continue;
+                },
+            }
+
             // Attempt to read the next message from the ZeroMQ socket
             let message = match Message::read_from_socket(&self.socket) {
                 Ok(m) => m,
@@ -122,48 +214,98 @@ impl Shell {
         }
     }
 
+    /// Send the replies of the requests handled on other threads that have
+    /// completed
+    fn send_pending_replies(&self) {
+        while let Ok(reply) = self.reply_rx.try_recv() {
+            self.pending_replies.set(self.pending_replies.get() - 1);
+
+            if let Err(err) = reply(&self.socket) {
+                log::error!("Could not send shell reply: {err}");
+            }
+        }
+    }
+
     /// Process a message received from the front-end, optionally dispatching
     /// messages to the IOPub or execution threads
     fn process_message(&self, msg: Message) -> crate::Result<()> {
-        let shell_handler = &mut self.shell_handler.borrow_mut();
+        // The handler is only borrowed while handling messages addressed to it
+        let shell_handler = || self.shell_handler.write().unwrap();
+
         match msg {
-            Message::KernelInfoRequest(req) => self.handle_request(req.clone(), |msg| {
-                block_on(shell_handler.handle_info_request(msg))
+            Message::KernelInfoRequest(req) => self.handle_request(req.clone(), |ctx, msg| {
+                block_on(shell_handler().handle_info_request(ctx, msg))
                     .map(kernel_info_full_reply::KernelInfoReply::from)
             }),
-            Message::IsCompleteRequest(req) => self.handle_request(req, |msg| {
-                block_on(shell_handler.handle_is_complete_request(msg))
-            }),
-            Message::ExecuteRequest(req) => {
-                // FIXME: We should ideally not pass the originator to the language kernel
-                let originator = Originator::from(&req);
-                self.handle_request(req, |msg| {
-                    block_on(shell_handler.handle_execute_request(originator, msg))
+            Message::IsCompleteRequest(req) => {
+                self.handle_request_off_thread(req, |shell_handler, ctx, msg| {
+                    block_on(shell_handler.handle_is_complete_request(ctx, msg))
                 })
             },
-            Message::CompleteRequest(req) => self.handle_request(req, |msg| {
-                block_on(shell_handler.handle_complete_request(msg))
+            Message::ExecuteRequest(req) => self.handle_request(req, |ctx, msg| {
+                // Honor the execution's dependencies on in-flight RPCs
+                // before anything is sent to the language kernel
+                self.wait_for_rpcs(msg)?;
+
+                // Record the execution in the kernel event log, with the
+                // first line of code as a summary
+                let summary = msg.code.lines().next().unwrap_or_default();
+                let summary: String = summary.chars().take(EVENT_CODE_SUMMARY_LEN).collect();
+                event_log::record(
+                    KernelEventKind::ExecutionStarted,
+                    None,
+                    Some(summary.clone()),
+                );
+
+                let start = Instant::now();
+                let reply = block_on(shell_handler().handle_execute_request(ctx, msg));
+
+                let status = match &reply {
+                    Ok(reply) if reply.status == Status::Ok => "ok",
+                    _ => "error",
+                };
+                event_log::record(
+                    KernelEventKind::ExecutionFinished,
+                    Some(start.elapsed()),
+                    Some(format!("{summary} ({status})")),
+                );
+
+                reply
             }),
+            Message::CompleteRequest(req) => self
+                .handle_request_off_thread(req, |shell_handler, ctx, msg| {
+                    block_on(shell_handler.handle_complete_request(ctx, msg))
+                }),
             Message::CommInfoRequest(req) => {
-                self.handle_request(req, |msg| self.handle_comm_info_request(msg))
-            },
-            Message::CommOpen(req) => {
-                self.handle_notification(req, |msg| self.handle_comm_open(shell_handler, msg))
+                self.handle_request(req, |_ctx, msg| self.handle_comm_info_request(msg))
             },
+            Message::CommOpen(req) => self.handle_notification(req, |ctx, msg| {
+                self.handle_comm_open(&mut shell_handler(), ctx, msg)
+            }),
             Message::CommMsg(req) => {
                 let header = req.header.clone();
-                self.handle_notification(req, |msg| self.handle_comm_msg(header, msg))
+                self.handle_notification(req, |_ctx, msg| self.handle_comm_msg(header, msg))
             },
             Message::CommClose(req) => {
-                self.handle_notification(req, |msg| self.handle_comm_close(msg))
+                self.handle_notification(req, |_ctx, msg| self.handle_comm_close(msg))
             },
-            Message::InspectRequest(req) => self.handle_request(req, |msg| {
-                block_on(shell_handler.handle_inspect_request(msg))
-            }),
+            Message::InspectRequest(req) => self
+                .handle_request_off_thread(req, |shell_handler, ctx, msg| {
+                    block_on(shell_handler.handle_inspect_request(ctx, msg))
+                }),
             _ => Err(Error::UnsupportedMessage(msg, String::from("shell"))),
         }
     }
 
+    /// Create the context of a message, whose cancellation token is
+    /// registered until `finish()` is called
+    fn context<T>(&self, msg: &JupyterMessage<T>) -> ShellContext {
+        ShellContext {
+            originator: Originator::from(msg),
+            cancellation: self.cancellation.start(&msg.header.msg_id),
+        }
+    }
+
     /// Wrapper for all request handlers; emits busy, invokes the handler, then
     /// emits idle. Most frontends expect all shell messages to be wrapped in
     /// this pair of statuses.
@@ -175,7 +317,7 @@ impl Shell {
     where
         Req: ProtocolMessage,
         Rep: ProtocolMessage,
-        Handler: FnOnce(&Req) -> crate::Result<Rep>,
+        Handler: FnOnce(&ShellContext, &Req) -> crate::Result<Rep>,
     {
         // Enter the kernel-busy state in preparation for handling the message.
         self.iopub_tx
@@ -191,19 +333,11 @@ impl Shell {
         // is so we can mark the kernel as no longer busy when we're done, it'd
         // be better to take an async fn `handler` here just mark kernel as idle
         // when it finishes.
-        let result = handler(&req.content);
+        let ctx = self.context(&req);
+        let result = handler(&ctx, &req.content);
+        self.cancellation.finish(&req.header.msg_id);
 
-        let result = match result {
-            Ok(reply) => req.send_reply(reply, &self.socket),
-            Err(crate::Error::ShellErrorReply(error)) => req.send_error::<Rep>(error, &self.socket),
-            Err(crate::Error::ShellErrorExecuteReply(error, exec_count)) => {
-                req.send_execute_error(error, exec_count, &self.socket)
-            },
-            Err(err) => {
-                let error = Exception::internal_error(format!("{err:?}"));
-                req.send_error::<Rep>(error, &self.socket)
-            },
-        };
+        let result = send_reply(&req, result, &self.socket);
 
         // Return to idle -- we always do this, even if the message generated an
         // error, since many frontends won't submit additional messages until
@@ -215,6 +349,58 @@ impl Shell {
         result.and(Ok(()))
     }
 
+    /// Like `handle_request()`, but handles the request on a thread of its
+    /// own so that slow requests, e.g. completions of large objects, don't
+    /// hold up the requests behind them. The handler is passed the shared
+    /// shell handler along with the context of the request, and its reply is
+    /// sent back to the Shell thread.
+    fn handle_request_off_thread<Req, Rep, Handler>(
+        &self,
+        req: JupyterMessage<Req>,
+        handler: Handler,
+    ) -> crate::Result<()>
+    where
+        Req: ProtocolMessage + Send + 'static,
+        Rep: ProtocolMessage + Send + 'static,
+        Handler:
+            FnOnce(&dyn ShellHandler, &ShellContext, &Req) -> crate::Result<Rep> + Send + 'static,
+    {
+        // Enter the kernel-busy state in preparation for handling the message.
+        self.iopub_tx
+            .send(status(req.clone(), ExecutionState::Busy))
+            .unwrap();
+
+        log::info!("Received shell request: {req:?}");
+
+        let ctx = self.context(&req);
+        let shell_handler = self.shell_handler.clone();
+        let cancellation = self.cancellation.clone();
+        let iopub_tx = self.iopub_tx.clone();
+        let reply_tx = self.reply_tx.clone();
+        self.pending_replies.set(self.pending_replies.get() + 1);
+
+        spawn!(format!("shell-{}", req.header.msg_type), move || {
+            let result = handler(&**shell_handler.read().unwrap(), &ctx, &req.content);
+            cancellation.finish(&req.header.msg_id);
+
+            let reply: ShellReply = Box::new(move |socket| {
+                let result = send_reply(&req, result, socket);
+
+                // Return to idle once the reply is sent, as in `handle_request()`
+                iopub_tx
+                    .send(status(req.clone(), ExecutionState::Idle))
+                    .unwrap();
+
+                result
+            });
+            reply_tx
+                .send(reply)
+                .or_log_error("Failed to send shell reply to the Shell thread");
+        });
+
+        Ok(())
+    }
+
     fn handle_notification<Not, Handler>(
         &self,
         not: JupyterMessage<Not>,
@@ -222,7 +408,7 @@ impl Shell {
     ) -> crate::Result<()>
     where
         Not: ProtocolMessage,
-        Handler: FnOnce(&Not) -> crate::Result<()>,
+        Handler: FnOnce(&ShellContext, &Not) -> crate::Result<()>,
     {
         // Enter the kernel-busy state in preparation for handling the message
         self.iopub_tx
@@ -232,7 +418,9 @@ impl Shell {
         log::info!("Received shell notification: {not:?}");
 
         // Handle the message
-        let result = handler(&not.content);
+        let ctx = self.context(&not);
+        let result = handler(&ctx, &not.content);
+        self.cancellation.finish(&not.header.msg_id);
 
         // Return to idle
         self.iopub_tx
@@ -281,12 +469,13 @@ impl Shell {
     fn handle_comm_open(
         &self,
         shell_handler: &mut Box<dyn ShellHandler>,
+        ctx: &ShellContext,
         msg: &CommOpen,
     ) -> crate::Result<()> {
         log::info!("Received request to open comm: {msg:?}");
 
         // Process the comm open request
-        let result = self.open_comm(shell_handler, msg);
+        let result = self.open_comm(shell_handler, ctx, msg);
 
         // There is no error reply for a comm open request. Instead we must send
         // a `comm_close` message as soon as possible. The error is logged on our side.
@@ -301,10 +490,51 @@ impl Shell {
         Ok(())
     }
 
+    /// Wait for the comm RPCs that an execution depends on to complete. Fails
+    /// the execution rather than running it out of order if they don't
+    /// complete in time.
+    fn wait_for_rpcs(&self, req: &ExecuteRequest) -> crate::Result<()> {
+        let Some(positron) = &req.positron else {
+            return Ok(());
+        };
+        if positron.wait_for_rpcs.is_empty() && positron.wait_for_comms.is_empty() {
+            return Ok(());
+        }
+
+        let timeout = match positron.wait_timeout {
+            Some(timeout) => Duration::from_millis(timeout),
+            None => rpc_barrier::DEFAULT_TIMEOUT,
+        };
+
+        let start = Instant::now();
+        let result = rpc_barrier::wait(&positron.wait_for_rpcs, &positron.wait_for_comms, timeout);
+        log::trace!(
+            "Waited {:?} for comm RPCs before execution",
+            start.elapsed()
+        );
+
+        result.map_err(|pending| {
+            log::warn!("Timed out waiting for comm RPCs before execution: {pending:?}");
+            Error::ShellErrorReply(Exception {
+                ename: String::from("RpcTimeoutError"),
+                evalue: format!(
+                    "Timed out after {} ms waiting for comm RPCs to complete: {}",
+                    start.elapsed().as_millis(),
+                    pending.join(", ")
+                ),
+                traceback: vec![],
+            })
+        })
+    }
+
     /// Deliver a request from the frontend to a comm. Specifically, this is a
     /// request from the frontend to deliver a message to a backend, often as
     /// the request side of a request/response pair.
     fn handle_comm_msg(&self, header: JupyterHeader, msg: &CommWireMsg) -> crate::Result<()> {
+        // Register the RPC before handing it over to the comm manager, so that
+        // executions received after it can wait for its completion
+        rpc_barrier::started(&header.msg_id, &msg.comm_id);
+
         // Store this message as a pending RPC request so that when the comm
         // responds, we can match it up
         self.comm_manager_tx
@@ -328,8 +558,13 @@ impl Shell {
     fn open_comm(
         &self,
         shell_handler: &mut Box<dyn ShellHandler>,
+        ctx: &ShellContext,
         msg: &CommOpen,
     ) -> crate::Result<()> {
+        // Record the protocol versions supported by the frontend, if declared,
+        // before the comm negotiates its version
+        protocol::declare(&msg.target_name, &msg.data);
+
         // Check to see whether the target name begins with "positron." This
         // prefix designates comm IDs that are known to the Positron IDE.
         let comm = match msg.target_name.starts_with("positron.") {
@@ -395,6 +630,10 @@ impl Shell {
                 conn_init_rx = Some(init_rx);
                 true
             },
+            Comm::EventLog => {
+                event_log::start(comm_socket.clone());
+                true
+            },
             Comm::Lsp => {
                 let init_rx = Self::start_server_comm(
                     &msg,
@@ -406,12 +645,12 @@ impl Shell {
                 true
             },
 
-            // Only the LSP and DAP comms are handled by the Amalthea
-            // kernel framework itself; all other comms are passed through
+            // Only the LSP, DAP, and event log comms are handled by the
+            // Amalthea kernel framework itself; all other comms are passed through
             // to the shell handler.
             _ => {
                 // Call the shell handler to open the comm
-                block_on(shell_handler.handle_comm_open(comm, comm_socket.clone()))?
+                block_on(shell_handler.handle_comm_open(ctx, comm, comm_socket.clone()))?
             },
         };
 
@@ -491,6 +730,29 @@ impl Shell {
     }
 }
 
+/// Send the reply of a request, or the error it failed with
+fn send_reply<Req, Rep>(
+    req: &JupyterMessage<Req>,
+    result: crate::Result<Rep>,
+    socket: &Socket,
+) -> crate::Result<()>
+where
+    Req: ProtocolMessage,
+    Rep: ProtocolMessage,
+{
+    match result {
+        Ok(reply) => req.send_reply(reply, socket),
+        Err(crate::Error::ShellErrorReply(error)) => req.send_error::<Rep>(error, socket),
+        Err(crate::Error::ShellErrorExecuteReply(error, exec_count)) => {
+            req.send_execute_error(error, exec_count, socket)
+        },
+        Err(err) => {
+            let error = Exception::internal_error(format!("{err:?}"));
+            req.send_error::<Rep>(error, socket)
+        },
+    }
+}
+
 /// Create IOPub status message.
 fn status(parent: JupyterMessage<impl ProtocolMessage>, state: ExecutionState) -> IOPubMessage {
     let reply = KernelStatus {
@@ -498,3 +760,38 @@ fn status(parent: JupyterMessage<impl ProtocolMessage>, state: ExecutionState) -
     };
     IOPubMessage::Status(parent.header, IOPubContextChannel::Shell, reply)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::socket::shell::ShellCancellation;
+
+    #[test]
+    fn test_shell_cancellation() {
+        let cancellation = ShellCancellation::default();
+
+        // Nothing to cancel between requests
+        cancellation.cancel();
+
+        let token = cancellation.start("1");
+        assert!(!token.is_cancelled());
+        cancellation.clone().cancel();
+        assert!(token.is_cancelled());
+        cancellation.finish("1");
+
+        // Interrupts don't carry over to the next request
+        let token = cancellation.start("2");
+        assert!(!token.is_cancelled());
+        cancellation.finish("2");
+        cancellation.cancel();
+        assert!(!token.is_cancelled());
+
+        // Interrupts cancel all requests in flight
+        let token = cancellation.start("3");
+        let other = cancellation.start("4");
+        cancellation.cancel();
+        assert!(token.is_cancelled());
+        assert!(other.is_cancelled());
+        cancellation.finish("3");
+        cancellation.finish("4");
+    }
+}
@@ -5,7 +5,10 @@
  *
  */
 
+use std::cell::RefCell;
+
 use crate::error::Error;
+use crate::recorder;
 use crate::session::Session;
 
 /// Represents a socket that sends and receives messages that are optionally
@@ -21,8 +24,16 @@ pub struct Socket {
 
     /// A ZeroMQ socket over which signed messages are to be sent/received
     pub socket: zmq::Socket,
+
+    /// Frames of the last message received with `recv_frames()`, reused
+    /// across messages
+    frames: RefCell<Vec<zmq::Message>>,
 }
 
+/// Frames larger than this are released once they have been handled rather
+/// than kept around until the next message
+const MAX_RETAINED_FRAME_SIZE: usize = 64 * 1024;
+
 impl Socket {
     /// Create a new Socket instance from a kernel session and a ZeroMQ context.
     pub fn new(
@@ -98,6 +109,7 @@ impl Socket {
             socket,
             session,
             name,
+            frames: RefCell::new(Vec::new()),
         })
     }
 
@@ -127,6 +139,7 @@ impl Socket {
             socket,
             session,
             name,
+            frames: RefCell::new(Vec::new()),
         })
     }
 
@@ -173,6 +186,44 @@ impl Socket {
         }
     }
 
+    /// Receive a multi-part message from the socket and pass its frames to
+    /// `f`. Unlike `recv_multipart()`, the frames aren't copied out of the
+    /// ZeroMQ messages, and the messages are reused from one call to the next.
+    ///
+    /// `f` must not receive from the same socket.
+    ///
+    /// **Note**: This will block until a message is delivered on the socket.
+    pub fn recv_frames<R>(
+        &self,
+        f: impl FnOnce(&[zmq::Message]) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let mut frames = self.frames.borrow_mut();
+
+        let mut n = 0;
+        loop {
+            if n == frames.len() {
+                frames.push(zmq::Message::new());
+            }
+            self.recv(&mut frames[n])?;
+            n += 1;
+
+            if !frames[n - 1].get_more() {
+                break;
+            }
+        }
+
+        recorder::record_incoming(&self.name, &frames[..n]);
+        let out = f(&frames[..n]);
+
+        for frame in frames.iter_mut() {
+            if frame.len() > MAX_RETAINED_FRAME_SIZE {
+                *frame = zmq::Message::new();
+            }
+        }
+
+        out
+    }
+
     /// Send a message on the socket.
     pub fn send(&self, msg: zmq::Message) -> Result<(), Error> {
         match self.socket.send(msg, 0) {
@@ -182,8 +233,8 @@ impl Socket {
     }
 
     /// Send a multi-part message on the socket.
-    pub fn send_multipart(&self, data: &Vec<Vec<u8>>) -> Result<(), Error> {
-        match self.socket.send_multipart(data, 0) {
+    pub fn send_multipart(&self, data: &[&[u8]]) -> Result<(), Error> {
+        match self.socket.send_multipart(data.iter().copied(), 0) {
             Ok(data) => Ok(data),
             Err(err) => Err(Error::ZmqError(self.name.clone(), err)),
         }
@@ -16,7 +16,8 @@ use crate::wire::jupyter_message::MessageType;
 pub struct CompleteRequest {
     /// The code fragment to complete.
     pub code: String,
-    /// The position of the cursor in the incomplete code.
+    /// The position of the cursor in the incomplete code, in Unicode code
+    /// points. See `crate::cursor` for conversions.
     pub cursor_pos: u32,
 }
 
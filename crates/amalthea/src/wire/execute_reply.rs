@@ -23,6 +23,13 @@ pub struct ExecuteReply {
 
     /// Results for user expressions
     pub user_expressions: Value,
+
+    /// Actions for the frontend to take, e.g. showing text in a pager with
+    /// `{"source": "page", "data": {"text/plain": ...}, "start": 0}`.
+    /// Deprecated by Jupyter in favour of `display_data`, but pagers still
+    /// rely on it.
+    #[serde(default)]
+    pub payload: Vec<Value>,
 }
 
 impl MessageType for ExecuteReply {
@@ -12,6 +12,7 @@ use serde_json::Value;
 use crate::wire::jupyter_message::MessageType;
 
 /// Represents a request from the frontend to execute code
+#[serde_with::skip_serializing_none]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ExecuteRequest {
     /// The code to be executed
@@ -33,6 +34,35 @@ pub struct ExecuteRequest {
     /// Whether the kernel should discard the execution queue if evaluating the
     /// code results in an error
     pub stop_on_error: bool,
+
+    /// Posit extension
+    #[serde(default)]
+    pub positron: Option<ExecuteRequestPositron>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExecuteRequestPositron {
+    /// Message IDs of comm RPCs that must complete before the code is
+    /// executed, e.g. a data export that must finish before the code deletes
+    /// the exported object
+    #[serde(default)]
+    pub wait_for_rpcs: Vec<String>,
+
+    /// IDs of comms whose pending RPCs must all complete before the code is
+    /// executed
+    #[serde(default)]
+    pub wait_for_comms: Vec<String>,
+
+    /// How long to wait for the RPCs, in milliseconds. The execution fails
+    /// if they haven't completed by then.
+    pub wait_timeout: Option<u64>,
+
+    /// Whether the options, graphical parameters, working directory, and
+    /// RNG kind are restored once the code is executed, so that the
+    /// execution doesn't leak state to the following ones. Defaults to the
+    /// kernel setting.
+    pub isolate_state: Option<bool>,
 }
 
 impl MessageType for ExecuteRequest {
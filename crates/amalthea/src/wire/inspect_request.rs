@@ -16,7 +16,8 @@ pub struct InspectRequest {
     /// The code context in which introspection is requested
     pub code: String,
 
-    /// The cursor position within 'code', in Unicode characters
+    /// The cursor position within 'code', in Unicode characters. See
+    /// `crate::cursor` for conversions.
     pub cursor_pos: u32,
 
     /// The level of detail requested (0 or 1)
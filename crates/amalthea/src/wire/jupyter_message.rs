@@ -49,6 +49,7 @@ use crate::wire::originator::Originator;
 use crate::wire::shutdown_request::ShutdownRequest;
 use crate::wire::status::KernelStatus;
 use crate::wire::wire_message::WireMessage;
+use crate::wire::wire_message::WireMessageRef;
 
 /// Represents a Jupyter message
 #[derive(Debug, Clone)]
@@ -185,13 +186,21 @@ impl TryFrom<&Message> for WireMessage {
 impl TryFrom<&WireMessage> for Message {
     type Error = crate::error::Error;
 
+    fn try_from(msg: &WireMessage) -> Result<Self, Error> {
+        Message::try_from(&msg.view())
+    }
+}
+
+impl TryFrom<&WireMessageRef<'_>> for Message {
+    type Error = crate::error::Error;
+
     /// Converts from a wire message to a Jupyter message by examining the message
     /// type and attempting to coerce the content into the appropriate
     /// structure.
     ///
     /// Note that not all message types are supported here; this handles only
     /// messages that are received from the frontend.
-    fn try_from(msg: &WireMessage) -> Result<Self, Error> {
+    fn try_from(msg: &WireMessageRef) -> Result<Self, Error> {
         let kind = msg.header.msg_type.clone();
 
         if kind == KernelInfoRequest::message_type() {
@@ -295,9 +304,13 @@ impl TryFrom<&WireMessage> for Message {
 }
 
 impl Message {
+    /// Reads a message from the socket. The message is parsed from the
+    /// frames received by the socket, which are never copied.
     pub fn read_from_socket(socket: &Socket) -> Result<Self, Error> {
-        let msg = WireMessage::read_from_socket(socket)?;
-        Message::try_from(&msg)
+        socket.recv_frames(|frames| {
+            let msg = WireMessageRef::from_frames(frames, &socket.session.hmac)?;
+            Message::try_from(&msg)
+        })
     }
 
     pub fn send(&self, socket: &Socket) -> Result<(), Error> {
@@ -45,4 +45,17 @@ pub struct LanguageInfoPositron {
 
     /// Initial continuation prompt
     pub continuation_prompt: Option<String>,
+
+    /// Fingerprint of the package environment of the session, to match
+    /// notebooks to the environment they ran in
+    pub environment_fingerprint: Option<EnvironmentFingerprint>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EnvironmentFingerprint {
+    /// What was hashed, e.g. a lockfile
+    pub source: String,
+
+    /// The hash, prefixed by its algorithm, e.g. `sha256:...`
+    pub hash: String,
 }
@@ -5,17 +5,17 @@
  *
  */
 
-use generic_array::GenericArray;
 use hmac::Hmac;
 use log::trace;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
-use serde_json::json;
+use serde_json::value::RawValue;
 use serde_json::value::Value;
 use sha2::Sha256;
 
 use crate::error::Error;
+use crate::recorder;
 use crate::socket::socket::Socket;
 use crate::wire::header::JupyterHeader;
 use crate::wire::jupyter_message::JupyterMessage;
@@ -28,6 +28,10 @@ const MSG_DELIM: &[u8] = b"<IDS|MSG>";
 /// Represents an untyped Jupyter message delivered over the wire. A WireMessage
 /// can represent any kind of Jupyter message; typically its header will be
 /// examined and it will be converted into a typed JupyterMessage.
+///
+/// The metadata and content are kept as raw JSON. They are only parsed once
+/// the type of the message is known, straight into the corresponding Rust
+/// type, and typed messages are serialized straight into raw JSON.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WireMessage {
     /// The ZeroMQ identities. These store the peer identity for messages
@@ -44,60 +48,56 @@ pub struct WireMessage {
     pub parent_header: Option<JupyterHeader>,
 
     /// Additional metadata, if any
-    pub metadata: Value,
+    pub metadata: Box<RawValue>,
 
     /// The body (payload) of the message
-    pub content: Value,
+    pub content: Box<RawValue>,
 }
 
-impl WireMessage {
-    /// Read a WireMessage from a ZeroMQ socket.
-    pub fn read_from_socket(socket: &Socket) -> Result<WireMessage, Error> {
-        let bufs = socket.recv_multipart()?;
-        Self::from_buffers(bufs, &socket.session.hmac)
-    }
-
-    /// Return the Jupyter type of the message.
-    pub fn message_type(&self) -> String {
-        self.header.msg_type.clone()
-    }
-
-    /// Parse a Jupyter message from an array of buffers (from a ZeroMQ message)
-    pub fn from_buffers(
-        mut bufs: Vec<Vec<u8>>,
-        hmac_key: &Option<Hmac<Sha256>>,
-    ) -> Result<WireMessage, Error> {
-        let mut iter = bufs.iter();
+/// A view of a Jupyter message that borrows the frames of the ZeroMQ message
+/// it was parsed from. Only the headers are parsed, the metadata and content
+/// point into the frames. This is what the hot path of reading messages from
+/// sockets works with, see `Socket::recv_frames()`.
+#[derive(Debug)]
+pub struct WireMessageRef<'a> {
+    pub zmq_identities: Vec<&'a [u8]>,
+    pub header: JupyterHeader,
+    pub parent_header: Option<JupyterHeader>,
+    pub metadata: &'a RawValue,
+    pub content: &'a RawValue,
+}
 
+impl<'a> WireMessageRef<'a> {
+    /// Parse a Jupyter message from the frames of a ZeroMQ message, e.g.
+    /// `zmq::Message` or `Vec<u8>` frames.
+    pub fn from_frames<F>(frames: &'a [F], hmac_key: &Option<Hmac<Sha256>>) -> Result<Self, Error>
+    where
+        F: std::ops::Deref<Target = [u8]>,
+    {
         // Find the position of the <IDS|MSG> delimiter in the message, which
         // separates the socket identities (IDS) from the body of the message
         // (MSG).
-        let pos = match iter.position(|buf| &buf[..] == MSG_DELIM) {
+        let pos = match frames.iter().position(|frame| &frame[..] == MSG_DELIM) {
             Some(p) => p,
             None => return Err(Error::MissingDelimiter),
         };
 
-        // Form a collection of the remaining parts, and remove the delimiter.
-        let parts: Vec<_> = bufs.drain(pos + 1..).collect();
-        bufs.pop();
+        let zmq_identities: Vec<&'a [u8]> = frames[..pos].iter().map(|frame| &frame[..]).collect();
+        let parts: Vec<&'a [u8]> = frames[pos + 1..].iter().map(|frame| &frame[..]).collect();
 
         // We expect to have at least 5 parts left (the HMAC + 4 message frames)
-        if parts.len() < 4 {
-            return Err(Error::InsufficientParts(parts.len(), 4));
+        if parts.len() < 5 {
+            return Err(Error::InsufficientParts(parts.len(), 5));
         }
 
         // Consume and validate the HMAC signature.
         WireMessage::validate_hmac(&parts, hmac_key)?;
 
         // Parse the message header
-        let header_val = WireMessage::parse_buffer(String::from("header"), &parts[1])?;
-        let header: JupyterHeader = match serde_json::from_value(header_val.clone()) {
-            Ok(h) => h,
-            Err(err) => return Err(Error::InvalidPart(String::from("header"), header_val, err)),
-        };
+        let header = parse_part("header", parts[1])?;
 
         // Parse the parent header.
-        let parent: Option<JupyterHeader> = match parts[2].len() {
+        let parent_header = match parts[2].len() {
             0 | 1 | 2 | 4 => {
                 // If there is no meaningful content in the parent header
                 // buffer, we have no parent message, which is OK per the wire
@@ -106,36 +106,98 @@ impl WireMessage {
             },
             _ => {
                 // If we do have content, ensure it parses as a header.
-                let parent_val =
-                    WireMessage::parse_buffer(String::from("parent header"), &parts[2])?;
-                match serde_json::from_value(parent_val.clone()) {
-                    Ok(h) => Some(h),
-                    Err(err) => {
-                        return Err(Error::InvalidPart(
-                            String::from("parent header"),
-                            parent_val,
-                            err,
-                        ))
-                    },
-                }
+                Some(parse_part("parent header", parts[2])?)
             },
         };
 
         Ok(Self {
-            zmq_identities: bufs,
+            zmq_identities,
             header,
-            parent_header: parent,
-            metadata: WireMessage::parse_buffer(String::from("metadata"), &parts[3])?,
-            content: WireMessage::parse_buffer(String::from("content"), &parts[4])?,
+            parent_header,
+            metadata: parse_part("metadata", parts[3])?,
+            content: parse_part("content", parts[4])?,
         })
     }
 
+    /// Copy the message out of the frames it borrows
+    pub fn into_owned(self) -> WireMessage {
+        WireMessage {
+            zmq_identities: self.zmq_identities.iter().map(|id| id.to_vec()).collect(),
+            header: self.header,
+            parent_header: self.parent_header,
+            metadata: self.metadata.to_owned(),
+            content: self.content.to_owned(),
+        }
+    }
+}
+
+/// Parse a single part of a multipart ZeroMQ message. Raw JSON values borrow
+/// from the buffer.
+fn parse_part<'a, T: Deserialize<'a>>(desc: &str, buf: &'a [u8]) -> Result<T, Error> {
+    // Convert the raw byte sequence from the ZeroMQ message into UTF-8
+    let str = match std::str::from_utf8(buf) {
+        Ok(s) => s,
+        Err(err) => return Err(Error::Utf8Error(String::from(desc), buf.to_vec(), err)),
+    };
+
+    match serde_json::from_str(str) {
+        Ok(val) => Ok(val),
+        // Tell apart invalid JSON from valid JSON that doesn't match the
+        // schema. Only done on failure, so the happy path parses once.
+        Err(err) => match serde_json::from_str::<Value>(str) {
+            Ok(val) => Err(Error::InvalidPart(String::from(desc), val, err)),
+            Err(err) => Err(Error::JsonParseError(
+                String::from(desc),
+                String::from(str),
+                err,
+            )),
+        },
+    }
+}
+
+/// Parse raw JSON for error reports
+fn raw_to_value(raw: &RawValue) -> Value {
+    serde_json::from_str(raw.get()).unwrap_or(Value::Null)
+}
+
+impl WireMessage {
+    /// Read a WireMessage from a ZeroMQ socket.
+    pub fn read_from_socket(socket: &Socket) -> Result<WireMessage, Error> {
+        socket.recv_frames(|frames| {
+            Ok(WireMessageRef::from_frames(frames, &socket.session.hmac)?.into_owned())
+        })
+    }
+
+    /// Return the Jupyter type of the message.
+    pub fn message_type(&self) -> String {
+        self.header.msg_type.clone()
+    }
+
+    /// Parse a Jupyter message from an array of buffers (from a ZeroMQ message)
+    pub fn from_buffers(
+        bufs: Vec<Vec<u8>>,
+        hmac_key: &Option<Hmac<Sha256>>,
+    ) -> Result<WireMessage, Error> {
+        Ok(WireMessageRef::from_frames(&bufs, hmac_key)?.into_owned())
+    }
+
+    /// A view of this message, e.g. to convert it to a typed message
+    pub fn view(&self) -> WireMessageRef<'_> {
+        WireMessageRef {
+            zmq_identities: self.zmq_identities.iter().map(|id| id.as_slice()).collect(),
+            header: self.header.clone(),
+            parent_header: self.parent_header.clone(),
+            metadata: &self.metadata,
+            content: &self.content,
+        }
+    }
+
     /// Validates the message's HMAC signature
-    fn validate_hmac(bufs: &Vec<Vec<u8>>, hmac_key: &Option<Hmac<Sha256>>) -> Result<(), Error> {
+    fn validate_hmac(bufs: &[&[u8]], hmac_key: &Option<Hmac<Sha256>>) -> Result<(), Error> {
         use hmac::Mac;
 
         // The hmac signature is the first value
-        let data = &bufs[0];
+        let data = bufs[0];
 
         // If we don't have a key at all, no need to validate. It is acceptable
         // (per Jupyter spec) to have an empty connection key, which indicates
@@ -146,24 +208,20 @@ impl WireMessage {
         };
 
         // Decode the hexadecimal representation of the signature
-        let decoded = match hex::decode(&data) {
+        let decoded = match hex::decode(data) {
             Ok(decoded_bytes) => decoded_bytes,
             Err(error) => return Err(Error::InvalidHmac(data.to_vec(), error)),
         };
 
-        // Compute the real signature according to our own key
+        // Compute the real signature according to our own key, skipping the
+        // signature itself
         let mut hmac_validator = key.clone();
-        let mut key = true;
-        for buf in bufs {
-            // Skip the key itself when computing checksum
-            if key {
-                key = false;
-                continue;
-            }
-            hmac_validator.update(&buf);
+        for buf in &bufs[1..] {
+            hmac_validator.update(buf);
         }
-        // Verify the signature
-        if let Err(err) = hmac_validator.verify(GenericArray::from_slice(&decoded)) {
+        // Verify the signature. Unlike `verify()`, `verify_slice()` doesn't
+        // panic when the signature doesn't have the expected length.
+        if let Err(err) = hmac_validator.verify_slice(&decoded) {
             return Err(Error::BadSignature(decoded, err));
         }
 
@@ -171,114 +229,92 @@ impl WireMessage {
         Ok(())
     }
 
-    /// Parse raw buffer data from a single part of a multipart ZeroMQ message
-    /// into a JSON value.
-    fn parse_buffer(desc: String, buf: &[u8]) -> Result<serde_json::Value, Error> {
-        // Convert the raw byte sequence from the ZeroMQ message into UTF-8
-        let str = match std::str::from_utf8(&buf) {
-            Ok(s) => s,
-            Err(err) => return Err(Error::Utf8Error(desc, buf.to_vec(), err)),
-        };
-
-        // Parse the UTF-8 string as JSON
-        let val: serde_json::Value = match serde_json::from_str(str) {
-            Ok(v) => v,
-            Err(err) => return Err(Error::JsonParseError(desc, String::from(str), err)),
-        };
-
-        Ok(val)
-    }
-
     /// Send this message to the given ZeroMQ socket.
     pub fn send(&self, socket: &Socket) -> Result<(), Error> {
-        match &self.parent_header {
-            Some(parent) => {
-                trace!(
-                    "Sending '{}' message (reply to '{}') via {} socket",
-                    self.msg_type(),
-                    parent.msg_type,
-                    socket.name
-                );
-            },
-            None => {
-                trace!(
-                    "Sending '{}' message via {} socket",
-                    self.msg_type(),
-                    socket.name
-                );
-            },
+        // Describing the message requires parsing its content, only do it
+        // when the message is actually logged
+        if log::log_enabled!(log::Level::Trace) {
+            match &self.parent_header {
+                Some(parent) => {
+                    trace!(
+                        "Sending '{}' message (reply to '{}') via {} socket",
+                        self.msg_type(),
+                        parent.msg_type,
+                        socket.name
+                    );
+                },
+                None => {
+                    trace!(
+                        "Sending '{}' message via {} socket",
+                        self.msg_type(),
+                        socket.name
+                    );
+                },
+            }
         }
 
-        // Serialize JSON values into byte parts in preparation for transmission
-        let mut parts: Vec<Vec<u8>> = match self.to_raw_parts() {
-            Ok(v) => v,
-            Err(err) => return Err(Error::CannotSerialize(err)),
+        // Serialize the headers. The metadata and content are already
+        // serialized.
+        let header = serde_json::to_vec(&self.header).map_err(Error::CannotSerialize)?;
+
+        // The Jupyter protocol states that orphan messages should have an empty
+        // dict as parent. We have a special `serialize_with` tag in the struct
+        // declaration to deal with that but since we're serialising the field
+        // directly here, this tag is not inspected. So we convert `None` to an
+        // empty dict manually.
+        let parent_header = match &self.parent_header {
+            Some(parent) => serde_json::to_vec(parent).map_err(Error::CannotSerialize)?,
+            None => b"{}".to_vec(),
         };
 
+        let parts: [&[u8]; 4] = [
+            &header,
+            &parent_header,
+            self.metadata.get().as_bytes(),
+            self.content.get().as_bytes(),
+        ];
+
         // Compute HMAC signature
         let hmac = match &socket.session.hmac {
             Some(key) => {
                 use hmac::Mac;
                 let mut sig = key.clone();
-                for part in &parts {
-                    sig.update(&part);
+                for part in parts {
+                    sig.update(part);
                 }
                 hex::encode(sig.finalize().into_bytes().as_slice())
             },
             None => String::new(),
         };
 
-        // Create vector to store message to be delivered; start with the socket identities, if any
-        let mut msg: Vec<Vec<u8>> = self.zmq_identities.clone();
-
-        // Add <IDS|MSG> delimiter
-        msg.push(MSG_DELIM.to_vec());
-
-        // Add HMAC signature
-        msg.push(hmac.as_bytes().to_vec());
-
-        // Add all the message parts
-        msg.append(&mut parts);
+        // Frames to be delivered; start with the socket identities, if any,
+        // followed by the <IDS|MSG> delimiter, the HMAC signature, and the
+        // message parts. Frames are copied only once, into ZeroMQ messages.
+        let mut frames: Vec<&[u8]> = Vec::with_capacity(self.zmq_identities.len() + 6);
+        frames.extend(self.zmq_identities.iter().map(|id| id.as_slice()));
+        frames.push(MSG_DELIM);
+        frames.push(hmac.as_bytes());
+        frames.extend(parts);
 
         // Deliver the message!
-        socket.send_multipart(&msg)?;
+        socket.send_multipart(&frames)?;
+        recorder::record_outgoing(&socket.name, self);
 
         // Successful delivery
         Ok(())
     }
 
-    /// Returns a vector containing the raw parts of the message
-    fn to_raw_parts(&self) -> Result<Vec<Vec<u8>>, serde_json::Error> {
-        let mut parts: Vec<Vec<u8>> = Vec::new();
-        parts.push(serde_json::to_vec(&self.header)?);
-
-        // The Jupyter protocol states that orphan messages should have an empty
-        // dict as parent. We have a special `serialize_with` tag in the struct
-        // declaration to deal with that but since we're serialising the field
-        // directly here, this tag is not inspected. So we convert `None` to an
-        // empty dict manually.
-        if self.parent_header.is_some() {
-            parts.push(serde_json::to_vec(&self.parent_header)?);
-        } else {
-            parts.push(serde_json::to_vec(&serde_json::Map::new())?);
-        }
-
-        parts.push(serde_json::to_vec(&self.metadata)?);
-        parts.push(serde_json::to_vec(&self.content)?);
-        Ok(parts)
-    }
-
     fn msg_type(&self) -> String {
         match self.header.msg_type.as_str() {
             "comm_msg" => {
-                if let Value::Object(map) = &self.content {
+                if let Value::Object(map) = raw_to_value(&self.content) {
                     let comm_id = Self::comm_msg_id(map.get("comm_id"));
                     let comm_msg_type = Self::comm_msg_type(map.get("data"));
                     return format!("comm_msg/{comm_id}/{comm_msg_type}");
                 }
             },
             "status" => {
-                if let Value::Object(map) = &self.content {
+                if let Value::Object(map) = raw_to_value(&self.content) {
                     if let Some(Value::String(execution_state)) = map.get("execution_state") {
                         return format!("status/{execution_state}");
                     }
@@ -329,22 +365,23 @@ impl WireMessage {
 }
 
 // Conversion: WireMessage (untyped) -> JupyterMessage (typed); used on
-// messages we receive over the wire to parse into the correct type.
-impl<T: ProtocolMessage + DeserializeOwned> TryFrom<&WireMessage> for JupyterMessage<T> {
+// messages we receive over the wire to parse into the correct type. The
+// content is deserialized straight from the raw JSON of the wire.
+impl<T: ProtocolMessage + DeserializeOwned> TryFrom<&WireMessageRef<'_>> for JupyterMessage<T> {
     type Error = crate::error::Error;
-    fn try_from(msg: &WireMessage) -> Result<JupyterMessage<T>, Error> {
-        let content = match serde_json::from_value(msg.content.clone()) {
+    fn try_from(msg: &WireMessageRef) -> Result<JupyterMessage<T>, Error> {
+        let content = match serde_json::from_str(msg.content.get()) {
             Ok(val) => val,
             Err(err) => {
                 return Err(Error::InvalidMessage(
                     T::message_type(),
-                    msg.content.clone(),
+                    raw_to_value(msg.content),
                     err,
                 ))
             },
         };
         Ok(JupyterMessage {
-            zmq_identities: msg.zmq_identities.clone(),
+            zmq_identities: msg.zmq_identities.iter().map(|id| id.to_vec()).collect(),
             header: msg.header.clone(),
             parent_header: msg.parent_header.clone(),
             content,
@@ -352,18 +389,30 @@ impl<T: ProtocolMessage + DeserializeOwned> TryFrom<&WireMessage> for JupyterMes
     }
 }
 
+impl<T: ProtocolMessage + DeserializeOwned> TryFrom<&WireMessage> for JupyterMessage<T> {
+    type Error = crate::error::Error;
+    fn try_from(msg: &WireMessage) -> Result<JupyterMessage<T>, Error> {
+        JupyterMessage::try_from(&msg.view())
+    }
+}
+
 // Conversion: JupyterMessage (typed) -> WireMessage (untyped); used prior to
 // sending messages to get them ready for dispatch.
 impl<T: ProtocolMessage> TryFrom<&JupyterMessage<T>> for WireMessage {
     type Error = crate::error::Error;
 
     /// Convert a typed JupyterMessage into a WireMessage, preserving ZeroMQ
-    /// socket identities.
+    /// socket identities. The content is serialized straight to raw JSON,
+    /// without going through a `serde_json::Value`.
     fn try_from(msg: &JupyterMessage<T>) -> Result<Self, Error>
     where
         T: ProtocolMessage,
     {
-        let content = match serde_json::to_value(msg.content.clone()) {
+        let content = match serde_json::value::to_raw_value(&msg.content) {
+            Ok(val) => val,
+            Err(err) => return Err(Error::CannotSerialize(err)),
+        };
+        let metadata = match RawValue::from_string(String::from("{}")) {
             Ok(val) => val,
             Err(err) => return Err(Error::CannotSerialize(err)),
         };
@@ -371,7 +420,7 @@ impl<T: ProtocolMessage> TryFrom<&JupyterMessage<T>> for WireMessage {
             zmq_identities: msg.zmq_identities.clone(),
             header: msg.header.clone(),
             parent_header: msg.parent_header.clone(),
-            metadata: json!({}),
+            metadata,
             content,
         })
     }
@@ -388,3 +437,96 @@ where
         None => serde_json::Map::new().serialize(serializer),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hmac::Hmac;
+    use hmac::Mac;
+
+    use crate::error::Error;
+    use crate::wire::execute_request::ExecuteRequest;
+    use crate::wire::jupyter_message::JupyterMessage;
+    use crate::wire::wire_message::WireMessage;
+    use crate::wire::wire_message::WireMessageRef;
+    use crate::wire::wire_message::MSG_DELIM;
+
+    fn header() -> Vec<u8> {
+        br#"{"msg_id":"1","session":"s","username":"u","date":"","msg_type":"kernel_info_request","version":"5.3"}"#.to_vec()
+    }
+
+    #[test]
+    fn test_from_buffers_insufficient_parts() {
+        // Only 3 of the 4 message frames
+        let bufs = vec![
+            MSG_DELIM.to_vec(),
+            b"".to_vec(),
+            header(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+        ];
+        let result = WireMessage::from_buffers(bufs, &None);
+        assert!(matches!(result, Err(Error::InsufficientParts(4, 5))));
+    }
+
+    #[test]
+    fn test_from_buffers_short_signature() {
+        let key = Hmac::new_from_slice(b"key").unwrap();
+
+        // A valid hex string that is too short to be a SHA-256 signature
+        let bufs = vec![
+            MSG_DELIM.to_vec(),
+            b"abcd".to_vec(),
+            header(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+        ];
+        let result = WireMessage::from_buffers(bufs, &Some(key));
+        assert!(matches!(result, Err(Error::BadSignature(_, _))));
+    }
+
+    #[test]
+    fn test_from_buffers_without_key() {
+        let bufs = vec![
+            b"identity".to_vec(),
+            MSG_DELIM.to_vec(),
+            b"".to_vec(),
+            header(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+        ];
+        let msg = WireMessage::from_buffers(bufs, &None).unwrap();
+        assert_eq!(msg.zmq_identities, vec![b"identity".to_vec()]);
+        assert_eq!(msg.header.msg_type, "kernel_info_request");
+        assert!(msg.parent_header.is_none());
+    }
+
+    #[test]
+    fn test_from_frames_borrows_content() {
+        let bufs = vec![
+            MSG_DELIM.to_vec(),
+            b"".to_vec(),
+            header(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+            br#"{"code": 1}"#.to_vec(),
+        ];
+        let msg = WireMessageRef::from_frames(&bufs, &None).unwrap();
+
+        // The content points into its frame
+        assert_eq!(msg.content.get(), r#"{"code": 1}"#);
+        assert_eq!(msg.content.get().as_ptr(), bufs[5].as_ptr());
+
+        // It is only checked against the schema once converted to a typed
+        // message
+        let result = JupyterMessage::<ExecuteRequest>::try_from(&msg);
+        assert!(matches!(result, Err(Error::InvalidMessage(_, _, _))));
+
+        // Invalid JSON is reported right away
+        let mut bufs = bufs;
+        bufs[5] = b"{".to_vec();
+        let result = WireMessageRef::from_frames(&bufs, &None);
+        assert!(matches!(result, Err(Error::JsonParseError(_, _, _))));
+    }
+}
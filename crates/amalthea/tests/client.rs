@@ -9,8 +9,12 @@ mod control;
 mod dummy_frontend;
 mod shell;
 
+use std::time::Instant;
+
 use amalthea::comm::comm_channel::CommMsg;
 use amalthea::comm::event::CommManagerEvent;
+use amalthea::fixtures::replay::replay;
+use amalthea::recorder::RecordedMessage;
 use amalthea::socket::comm::CommInitiator;
 use amalthea::socket::comm::CommSocket;
 use amalthea::wire::comm_close::CommClose;
@@ -18,12 +22,14 @@ use amalthea::wire::comm_info_reply::CommInfoTargetName;
 use amalthea::wire::comm_info_request::CommInfoRequest;
 use amalthea::wire::comm_msg::CommWireMsg;
 use amalthea::wire::comm_open::CommOpen;
+use amalthea::wire::complete_request::CompleteRequest;
 use amalthea::wire::jupyter_message::Message;
 use amalthea::wire::kernel_info_request::KernelInfoRequest;
 use amalthea::wire::status::ExecutionState;
 use assert_matches::assert_matches;
 use dummy_frontend::DummyAmaltheaFrontend;
 use serde_json;
+use shell::SLOW_COMPLETION_DELAY;
 
 #[test]
 fn test_amalthea_kernel_info() {
@@ -63,6 +69,37 @@ fn test_amalthea_execute_request() {
     frontend.recv_iopub_idle();
 }
 
+#[test]
+fn test_amalthea_concurrent_complete_requests() {
+    let frontend = DummyAmaltheaFrontend::lock();
+
+    let start = Instant::now();
+    frontend.send_shell(CompleteRequest {
+        code: String::from("slow"),
+        cursor_pos: 4,
+    });
+    frontend.send_shell(CompleteRequest {
+        code: String::from("fast"),
+        cursor_pos: 4,
+    });
+
+    // The second request is answered while the slow one is still in flight
+    assert_matches!(frontend.recv_shell(), Message::CompleteReply(reply) => {
+        assert_eq!(reply.content.matches, vec![String::from("fast")]);
+    });
+    assert!(start.elapsed() < SLOW_COMPLETION_DELAY);
+
+    assert_matches!(frontend.recv_shell(), Message::CompleteReply(reply) => {
+        assert_eq!(reply.content.matches, vec![String::from("slow")]);
+    });
+
+    // Each request is wrapped in its own pair of statuses
+    frontend.recv_iopub_busy();
+    frontend.recv_iopub_busy();
+    frontend.recv_iopub_idle();
+    frontend.recv_iopub_idle();
+}
+
 #[test]
 fn test_amalthea_input_request() {
     let frontend = DummyAmaltheaFrontend::lock();
@@ -87,6 +124,69 @@ fn test_amalthea_input_request() {
     frontend.recv_iopub_idle();
 }
 
+#[test]
+fn test_amalthea_replay() {
+    let frontend = DummyAmaltheaFrontend::lock();
+
+    let header = |id: &str, msg_type: &str| {
+        format!(
+            r#"{{"msg_id":"{id}","session":"recorded","username":"u","date":"","msg_type":"{msg_type}","version":"5.3"}}"#
+        )
+    };
+    let message = |direction: &str, socket: &str, id: &str, msg_type: &str, content: &str| {
+        let parent = match direction {
+            "incoming" => String::from("null"),
+            _ => header("replay-request", "execute_request"),
+        };
+        format!(
+            r#"{{"timestamp":"","direction":"{direction}","socket":"{socket}","header":{},"parent_header":{parent},"metadata":{{}},"content":{content}}}"#,
+            header(id, msg_type)
+        )
+    };
+
+    let recording = [
+        message(
+            "incoming",
+            "Shell",
+            "replay-request",
+            "execute_request",
+            r#"{"code":"42","silent":false,"store_history":true,"user_expressions":{},"allow_stdin":false,"stop_on_error":false}"#,
+        ),
+        message(
+            "outgoing",
+            "IOPub",
+            "1",
+            "status",
+            r#"{"execution_state":"busy"}"#,
+        ),
+        message("outgoing", "IOPub", "2", "execute_input", "{}"),
+        message("outgoing", "IOPub", "3", "execute_result", "{}"),
+        message("outgoing", "Shell", "4", "execute_reply", "{}"),
+        message(
+            "outgoing",
+            "IOPub",
+            "5",
+            "status",
+            r#"{"execution_state":"idle"}"#,
+        ),
+    ];
+    let recording: Vec<RecordedMessage> = recording
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    let replayed = replay(&frontend, &recording).unwrap();
+    assert_eq!(replayed.len(), 5);
+
+    for msg in replayed.iter() {
+        assert_eq!(msg.replayed.header.msg_type, msg.recorded.header.msg_type);
+
+        // Replies point to the replayed request
+        let parent = msg.replayed.parent_header.as_ref().unwrap();
+        assert_eq!(parent.msg_id, "replay-request");
+    }
+}
+
 #[test]
 fn test_amalthea_heartbeat() {
     let frontend = DummyAmaltheaFrontend::lock();
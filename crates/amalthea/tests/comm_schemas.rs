@@ -0,0 +1,114 @@
+/*
+ * comm_schemas.rs
+ *
+ * Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+ *
+ */
+
+//! Checks that the generated comm files agree with the schemas of
+//! `comms/*.json`. Both are supposed to change together, see
+//! `comms/README.md`, and this catches generated files edited by hand without
+//! the matching schema change, or schemas that were never regenerated.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")
+}
+
+/// `show_quick_pick` -> `ShowQuickPick`
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Body of `pub struct <name> { ... }` in a generated file
+fn struct_body<'a>(source: &'a str, name: &str) -> Option<&'a str> {
+    let start = source.find(&format!("pub struct {name} {{"))?;
+    let source = &source[start..];
+    let end = source.find("\n}")?;
+    Some(&source[..end])
+}
+
+fn check_fields(source: &str, name: &str, fields: &[&str], file: &str) {
+    let Some(body) = struct_body(source, name) else {
+        panic!("`{name}` is not in `{file}`");
+    };
+
+    for field in fields {
+        assert!(
+            body.contains(&format!("pub {field}:")) || body.contains(&format!("pub r#{field}:")),
+            "`{name}` of `{file}` doesn't have the `{field}` field"
+        );
+    }
+}
+
+#[test]
+fn test_generated_comms_match_schemas() {
+    let root = repo_root();
+    let schemas = std::fs::read_dir(root.join("comms")).unwrap();
+    let mut n = 0;
+
+    for entry in schemas {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+
+        // `ui-frontend-openrpc.json` -> `ui_comm.rs`
+        let stem = path.file_stem().unwrap().to_str().unwrap();
+        let comm = stem.split('-').next().unwrap();
+        let file = format!("{comm}_comm.rs");
+
+        let source = root.join("crates/amalthea/src/comm").join(&file);
+        let source = std::fs::read_to_string(&source)
+            .unwrap_or_else(|err| panic!("Can't read `{file}` for `{stem}`: {err}"));
+        assert!(
+            source.starts_with("// @generated"),
+            "`{file}` isn't generated"
+        );
+
+        let schema: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+
+        for method in schema["methods"].as_array().unwrap() {
+            let name = method["name"].as_str().unwrap();
+            assert!(
+                source.contains(&format!("#[serde(rename = \"{name}\")]")),
+                "Method `{name}` of `{stem}` is not in `{file}`"
+            );
+
+            let params: Vec<&str> = method["params"]
+                .as_array()
+                .map(|params| params.iter().map(|p| p["name"].as_str().unwrap()).collect())
+                .unwrap_or_default();
+            if !params.is_empty() {
+                let params_struct = format!("{}Params", camel_case(name));
+                check_fields(&source, &params_struct, &params, &file);
+            }
+        }
+
+        if let Some(types) = schema["components"]["schemas"].as_object() {
+            for (name, definition) in types {
+                let properties: Vec<&str> = definition["properties"]
+                    .as_object()
+                    .map(|properties| properties.keys().map(String::as_str).collect())
+                    .unwrap_or_default();
+                check_fields(&source, &camel_case(name), &properties, &file);
+            }
+        }
+
+        n += 1;
+    }
+
+    assert!(n > 0, "No comm schemas found");
+}
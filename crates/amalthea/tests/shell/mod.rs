@@ -6,9 +6,11 @@
  */
 
 use std::thread;
+use std::time::Duration;
 
 use amalthea::comm::comm_channel::Comm;
 use amalthea::comm::comm_channel::CommMsg;
+use amalthea::language::shell_handler::ShellContext;
 use amalthea::language::shell_handler::ShellHandler;
 use amalthea::socket::comm::CommSocket;
 use amalthea::socket::iopub::IOPubMessage;
@@ -43,6 +45,9 @@ use crossbeam::channel::Sender;
 use log::warn;
 use serde_json::json;
 
+/// How long completions of the "slow" keyword take
+pub const SLOW_COMPLETION_DELAY: Duration = Duration::from_millis(500);
+
 pub struct Shell {
     iopub: Sender<IOPubMessage>,
     stdin_request_tx: Sender<StdInRequest>,
@@ -86,6 +91,7 @@ impl Shell {
 impl ShellHandler for Shell {
     async fn handle_info_request(
         &mut self,
+        _ctx: &ShellContext,
         _req: &KernelInfoRequest,
     ) -> amalthea::Result<KernelInfoReply> {
         let info = LanguageInfo {
@@ -109,11 +115,19 @@ impl ShellHandler for Shell {
 
     async fn handle_complete_request(
         &self,
-        _req: &CompleteRequest,
+        _ctx: &ShellContext,
+        req: &CompleteRequest,
     ) -> amalthea::Result<CompleteReply> {
-        // No matches in this toy implementation.
+        // Keyword: "slow"
+        //
+        // Take a while to complete, e.g. like completions of large objects
+        if req.code == "slow" {
+            thread::sleep(SLOW_COMPLETION_DELAY);
+        }
+
+        // The only match in this toy implementation is the code itself.
         Ok(CompleteReply {
-            matches: Vec::new(),
+            matches: vec![req.code.clone()],
             status: Status::Ok,
             cursor_start: 0,
             cursor_end: 0,
@@ -124,6 +138,7 @@ impl ShellHandler for Shell {
     /// Handle a request to test code for completion.
     async fn handle_is_complete_request(
         &self,
+        _ctx: &ShellContext,
         _req: &IsCompleteRequest,
     ) -> amalthea::Result<IsCompleteReply> {
         // In this echo example, the code is always complete!
@@ -136,7 +151,7 @@ impl ShellHandler for Shell {
     /// Handles an ExecuteRequest; "executes" the code by echoing it.
     async fn handle_execute_request(
         &mut self,
-        originator: Originator,
+        ctx: &ShellContext,
         req: &ExecuteRequest,
     ) -> amalthea::Result<ExecuteReply> {
         // Increment counter if we are storing this execution in history
@@ -191,7 +206,7 @@ impl ShellHandler for Shell {
         //
         // Create an artificial prompt for input
         if req.code == "prompt" {
-            self.prompt_for_input(originator);
+            self.prompt_for_input(ctx.originator.clone());
 
             // Block for the reply
             let reply = self.stdin_reply_rx.recv().unwrap();
@@ -221,11 +236,16 @@ impl ShellHandler for Shell {
             status: Status::Ok,
             execution_count: self.execution_count,
             user_expressions: serde_json::Value::Null,
+            payload: vec![],
         })
     }
 
     /// Handles an introspection request
-    async fn handle_inspect_request(&self, req: &InspectRequest) -> amalthea::Result<InspectReply> {
+    async fn handle_inspect_request(
+        &self,
+        _ctx: &ShellContext,
+        req: &InspectRequest,
+    ) -> amalthea::Result<InspectReply> {
         let data = match req.code.as_str() {
             "err" => {
                 json!({"text/plain": "This generates an error!"})
@@ -243,7 +263,12 @@ impl ShellHandler for Shell {
         })
     }
 
-    async fn handle_comm_open(&self, req: Comm, comm: CommSocket) -> amalthea::Result<bool> {
+    async fn handle_comm_open(
+        &self,
+        _ctx: &ShellContext,
+        req: Comm,
+        comm: CommSocket,
+    ) -> amalthea::Result<bool> {
         // Used to test error replies
         match req {
             Comm::Other(name) if name == "unknown" => {
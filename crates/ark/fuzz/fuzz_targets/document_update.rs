@@ -0,0 +1,71 @@
+//
+// document_update.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Fuzzes the incremental updating and reparsing of LSP documents with the
+// `didChange` notifications sent by the frontend
+
+#![no_main]
+
+use ark::lsp::documents::Document;
+use libfuzzer_sys::arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use tower_lsp::lsp_types::DidChangeTextDocumentParams;
+use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::Range;
+use tower_lsp::lsp_types::TextDocumentContentChangeEvent;
+use tower_lsp::lsp_types::Url;
+use tower_lsp::lsp_types::VersionedTextDocumentIdentifier;
+use tree_sitter::Parser;
+
+#[derive(Debug, Arbitrary)]
+struct Edit {
+    // Small integers so that most ranges fall inside the document
+    start: (u8, u8),
+    end: (u8, u8),
+    text: String,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    contents: String,
+    edits: Vec<Vec<Edit>>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&tree_sitter_r::LANGUAGE.into())
+        .unwrap();
+
+    let mut document = Document::new_with_parser(&input.contents, &mut parser, Some(0));
+    let uri = Url::parse("file:///fuzz.R").unwrap();
+
+    for (i, edits) in input.edits.into_iter().enumerate() {
+        let content_changes = edits
+            .into_iter()
+            .map(|edit| TextDocumentContentChangeEvent {
+                range: Some(Range {
+                    start: Position::new(edit.start.0 as u32, edit.start.1 as u32),
+                    end: Position::new(edit.end.0 as u32, edit.end.1 as u32),
+                }),
+                range_length: None,
+                text: edit.text,
+            })
+            .collect();
+
+        let params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: i as i32 + 1,
+            },
+            content_changes,
+        };
+
+        // Invalid edits are rejected with an error, but must never panic
+        let _ = document.on_did_change(&mut parser, &params);
+    }
+});
@@ -0,0 +1,90 @@
+//
+// cell_state.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Isolated executions don't leak global state to the following ones, which
+// makes notebooks reproducible regardless of the order in which cells are
+// run. The options, graphical parameters, working directory, and RNG kind are
+// captured when the execution starts and restored once it completes.
+//
+// Isolation is opt-in, either for all executions with `isolate_state` in the
+// `[execution]` section of the config file, or per execution with the
+// `isolate_state` field of the Positron extension of the `execute_request`,
+// which takes precedence.
+
+use amalthea::wire::execute_request::ExecuteRequest;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::object::RObject;
+
+use crate::modules::ARK_ENVS;
+use crate::user_config::user_config;
+
+/// Whether the global state should be restored after `req`
+pub(crate) fn is_isolated(req: &ExecuteRequest) -> bool {
+    req.positron
+        .as_ref()
+        .and_then(|positron| positron.isolate_state)
+        .unwrap_or_else(|| user_config().execution.isolate_state)
+}
+
+/// Capture the global state. Must be called on the R thread.
+pub(crate) fn snapshot() -> Option<RObject> {
+    match RFunction::new("", "cell_state_snapshot").call_in(ARK_ENVS.positron_ns) {
+        Ok(snapshot) => Some(snapshot),
+        Err(err) => {
+            log::error!("Can't capture the global state: {err:?}");
+            None
+        },
+    }
+}
+
+/// Restore the global state captured by `snapshot()`. Must be called on the R
+/// thread.
+pub(crate) fn restore(snapshot: RObject) {
+    let result = RFunction::new("", "cell_state_restore")
+        .add(snapshot)
+        .call_in(ARK_ENVS.positron_ns);
+
+    if let Err(err) = result {
+        log::error!("Can't restore the global state: {err:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use harp::exec::RFunction;
+    use harp::exec::RFunctionExt;
+    use harp::utils::r_is_null;
+
+    use crate::cell_state::restore;
+    use crate::cell_state::snapshot;
+    use crate::r_task;
+
+    #[test]
+    fn test_cell_state_restore() {
+        r_task(|| {
+            let wd = std::env::current_dir().unwrap();
+            let snapshot = snapshot().unwrap();
+
+            harp::parse_eval_base("options(ark.test.cell_state = 1, digits = 3)").unwrap();
+            harp::parse_eval_base("setwd(tempdir())").unwrap();
+            harp::parse_eval_base("RNGkind('Wichmann-Hill')").unwrap();
+
+            restore(snapshot);
+
+            assert!(r_is_null(harp::get_option("ark.test.cell_state").sexp));
+
+            let digits: i32 = harp::get_option("digits").try_into().unwrap();
+            assert_eq!(digits, 7);
+
+            assert_eq!(std::env::current_dir().unwrap(), wd);
+
+            let kind: Vec<String> = RFunction::from("RNGkind").call().unwrap().try_into().unwrap();
+            assert_eq!(kind[0], "Mersenne-Twister");
+        })
+    }
+}
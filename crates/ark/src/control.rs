@@ -5,6 +5,10 @@
  *
  */
 
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use amalthea::comm::event::CommManagerEvent;
 use amalthea::language::control_handler::ControlHandler;
 use amalthea::wire::exception::Exception;
 use amalthea::wire::interrupt_reply::InterruptReply;
@@ -14,16 +18,26 @@ use amalthea::wire::shutdown_request::ShutdownRequest;
 use async_trait::async_trait;
 use crossbeam::channel::Sender;
 
+use crate::dap::Dap;
 use crate::request::RRequest;
+use crate::teardown;
 
 pub struct Control {
     r_request_tx: Sender<RRequest>,
+    comm_manager_tx: Sender<CommManagerEvent>,
+    dap: Arc<Mutex<Dap>>,
 }
 
 impl Control {
-    pub fn new(sender: Sender<RRequest>) -> Self {
+    pub fn new(
+        sender: Sender<RRequest>,
+        comm_manager_tx: Sender<CommManagerEvent>,
+        dap: Arc<Mutex<Dap>>,
+    ) -> Self {
         Self {
             r_request_tx: sender,
+            comm_manager_tx,
+            dap,
         }
     }
 }
@@ -41,6 +55,10 @@ impl ControlHandler for Control {
         // until complete shutdown before replying and instead just signals
         // a shutdown via a global flag picked up by an event loop.
 
+        // Release everything owned by the frontend first. This also
+        // interrupts R if it's busy, so that it picks up the request below.
+        teardown::teardown(self.comm_manager_tx.clone(), self.dap.clone());
+
         let status = if let Err(err) = self.r_request_tx.send(RRequest::Shutdown(msg.restart)) {
             log::error!("Could not deliver shutdown request to execution thread: {err:?}");
             Status::Error
@@ -56,6 +74,11 @@ impl ControlHandler for Control {
 
     async fn handle_interrupt_request(&self) -> Result<InterruptReply, Exception> {
         log::info!("Received interrupt request");
+
+        // Users interrupt kernels that look stuck, record what was going on
+        crate::task_queue::log_snapshot();
+
+        crate::signals::set_interrupt_requested();
         crate::sys::control::handle_interrupt_request();
         Ok(InterruptReply { status: Status::Ok })
     }
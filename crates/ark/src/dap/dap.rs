@@ -6,6 +6,9 @@
 //
 
 use std::collections::HashMap;
+use std::net::Shutdown;
+use std::net::SocketAddr;
+use std::net::TcpStream;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -17,6 +20,7 @@ use serde_json::json;
 use stdext::log_error;
 use stdext::spawn;
 
+use crate::dap::dap_position::DapPositionEncoding;
 use crate::dap::dap_r_main::FrameInfo;
 use crate::dap::dap_r_main::FrameSource;
 use crate::dap::dap_server;
@@ -48,9 +52,23 @@ pub struct Dap {
     /// This always exists when `is_connected` is true.
     pub backend_events_tx: Option<Sender<DapBackendEvent>>,
 
+    /// Whether the DAP server was stopped with `stop()`. The server thread
+    /// exits instead of accepting new connections.
+    pub is_stopped: bool,
+
+    /// Address the DAP server listens on, once started
+    pub address: Option<SocketAddr>,
+
+    /// Connection to the DAP client, to disconnect it on `stop()`
+    pub stream: Option<TcpStream>,
+
     /// Current call stack
     pub stack: Option<Vec<FrameInfo>>,
 
+    /// Line and column conventions of the connected client, as requested
+    /// in the `Initialize` request
+    pub position_encoding: DapPositionEncoding,
+
     /// Map of `source` -> `source_reference` used for frames that don't have
     /// associated files (i.e. no `srcref` attribute). The `source` is the key to
     /// ensure that we don't insert the same function multiple times, which would result
@@ -96,7 +114,11 @@ impl Dap {
             is_debugging: false,
             is_connected: false,
             backend_events_tx: None,
+            is_stopped: false,
+            address: None,
+            stream: None,
             stack: None,
+            position_encoding: DapPositionEncoding::default(),
             fallback_sources: HashMap::new(),
             current_source_reference: 1,
             frame_id_to_variables_reference: HashMap::new(),
@@ -165,6 +187,22 @@ impl Dap {
         }
     }
 
+    /// Stop the DAP server: disconnect the client, if any, and stop listening
+    /// for new connections
+    pub fn stop(&mut self) {
+        self.is_stopped = true;
+
+        if let Some(stream) = self.stream.take() {
+            log_error!(stream.shutdown(Shutdown::Both));
+        }
+
+        // Wake up the server thread if it's waiting for a client, so that it
+        // notices it was stopped
+        if let Some(address) = self.address.take() {
+            let _ = TcpStream::connect(address);
+        }
+    }
+
     /// Load `fallback_sources` with this stack's text sources
     fn load_fallback_sources(&mut self, stack: &Vec<FrameInfo>) {
         for frame in stack.iter() {
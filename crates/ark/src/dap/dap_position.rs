@@ -0,0 +1,216 @@
+//
+// dap_position.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+/// Line and column conventions requested by the DAP client
+///
+/// R srcrefs give us 1-based lines and 1-based byte columns. DAP clients
+/// expect columns counted in UTF-16 code units, and may opt out of 1-based
+/// counting for both lines and columns via the `linesStartAt1` and
+/// `columnsStartAt1` fields of the `Initialize` request. Both default to
+/// `true` per the DAP specification.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DapPositionEncoding {
+    pub lines_start_at1: bool,
+    pub columns_start_at1: bool,
+}
+
+impl Default for DapPositionEncoding {
+    fn default() -> Self {
+        Self {
+            lines_start_at1: true,
+            columns_start_at1: true,
+        }
+    }
+}
+
+impl DapPositionEncoding {
+    pub fn new(lines_start_at1: Option<bool>, columns_start_at1: Option<bool>) -> Self {
+        Self {
+            lines_start_at1: lines_start_at1.unwrap_or(true),
+            columns_start_at1: columns_start_at1.unwrap_or(true),
+        }
+    }
+
+    /// Convert a 1-based R line to a DAP line
+    ///
+    /// A line of `0` means "unknown location" and is passed through as is.
+    pub fn to_dap_line(&self, line: i64) -> i64 {
+        if line <= 0 || self.lines_start_at1 {
+            return line;
+        }
+        line - 1
+    }
+
+    /// Convert a DAP line to a 1-based R line
+    pub fn from_dap_line(&self, line: i64) -> i64 {
+        if self.lines_start_at1 {
+            return line;
+        }
+        line + 1
+    }
+
+    /// Convert a 1-based R byte column to a DAP column
+    ///
+    /// `line` is the text of the line the column points into. If it's not
+    /// available, we can't reencode and fall back to the byte column.
+    ///
+    /// A column of `0` means "unknown location" and is passed through as is.
+    pub fn to_dap_column(&self, line: Option<&str>, column: i64) -> i64 {
+        if column <= 0 {
+            return column;
+        }
+
+        let offset = (column - 1) as usize;
+
+        let offset = match line {
+            Some(line) => convert_column_from_utf8_to_utf16(line, offset),
+            None => offset,
+        };
+
+        let offset = offset as i64;
+
+        if self.columns_start_at1 {
+            offset + 1
+        } else {
+            offset
+        }
+    }
+
+    /// Convert a DAP column to a 1-based R byte column
+    ///
+    /// This is the inverse of `to_dap_column()`, e.g. for mapping
+    /// breakpoint locations sent by the client back to srcrefs.
+    pub fn from_dap_column(&self, line: Option<&str>, column: i64) -> i64 {
+        let offset = if self.columns_start_at1 {
+            column - 1
+        } else {
+            column
+        };
+
+        if offset < 0 {
+            return 0;
+        }
+
+        let offset = offset as usize;
+
+        let offset = match line {
+            Some(line) => convert_column_from_utf16_to_utf8(line, offset),
+            None => offset,
+        };
+
+        offset as i64 + 1
+    }
+}
+
+/// Converts a 0-based byte offset into a line to a 0-based UTF-16 offset
+///
+/// Offsets past the end of the line are extended with one code unit per
+/// byte. This happens with exclusive end columns pointing just past the
+/// last character. Offsets in the middle of a character are rounded up to
+/// the next character boundary.
+pub fn convert_column_from_utf8_to_utf16(line: &str, offset: usize) -> usize {
+    if line.is_ascii() {
+        return offset;
+    }
+
+    if offset >= line.len() {
+        let n = line.encode_utf16().count();
+        return n + (offset - line.len());
+    }
+
+    let mut boundary = offset;
+    while !line.is_char_boundary(boundary) {
+        boundary += 1;
+    }
+
+    line[..boundary].encode_utf16().count()
+}
+
+/// Converts a 0-based UTF-16 offset into a line to a 0-based byte offset
+///
+/// Offsets past the end of the line are extended with one byte per code unit.
+pub fn convert_column_from_utf16_to_utf8(line: &str, offset: usize) -> usize {
+    if line.is_ascii() {
+        return offset;
+    }
+
+    let mut n = 0;
+
+    for (pos, char) in line.char_indices() {
+        if n >= offset {
+            return pos;
+        }
+        n += char.len_utf16();
+    }
+
+    line.len() + offset.saturating_sub(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dap::dap_position::convert_column_from_utf16_to_utf8;
+    use crate::dap::dap_position::convert_column_from_utf8_to_utf16;
+    use crate::dap::dap_position::DapPositionEncoding;
+
+    #[test]
+    fn test_convert_column_ascii() {
+        assert_eq!(convert_column_from_utf8_to_utf16("foo(x)", 4), 4);
+        assert_eq!(convert_column_from_utf16_to_utf8("foo(x)", 4), 4);
+    }
+
+    #[test]
+    fn test_convert_column_multibyte() {
+        // `ś` is 2 bytes in UTF-8 and 1 code unit in UTF-16
+        let line = "ś <- foo(x)";
+        assert_eq!(convert_column_from_utf8_to_utf16(line, 0), 0);
+        assert_eq!(convert_column_from_utf8_to_utf16(line, 2), 1);
+        assert_eq!(convert_column_from_utf8_to_utf16(line, 6), 5);
+        assert_eq!(convert_column_from_utf16_to_utf8(line, 1), 2);
+        assert_eq!(convert_column_from_utf16_to_utf8(line, 5), 6);
+
+        // Emojis are 4 bytes in UTF-8 and 2 code units in UTF-16
+        let line = "x <- '🙂'; y";
+        assert_eq!(convert_column_from_utf8_to_utf16(line, 10), 8);
+        assert_eq!(convert_column_from_utf16_to_utf8(line, 8), 10);
+
+        // Mid-character offsets are rounded up to the next boundary
+        assert_eq!(convert_column_from_utf8_to_utf16(line, 7), 8);
+    }
+
+    #[test]
+    fn test_convert_column_past_end() {
+        let line = "ś";
+        assert_eq!(convert_column_from_utf8_to_utf16(line, 2), 1);
+        assert_eq!(convert_column_from_utf8_to_utf16(line, 3), 2);
+        assert_eq!(convert_column_from_utf16_to_utf8(line, 1), 2);
+        assert_eq!(convert_column_from_utf16_to_utf8(line, 2), 3);
+    }
+
+    #[test]
+    fn test_dap_position_encoding() {
+        let line = Some("ś <- foo(x)");
+
+        // `foo` starts at byte column 7 (1-based)
+        let encoding = DapPositionEncoding::default();
+        assert_eq!(encoding.to_dap_column(line, 7), 6);
+        assert_eq!(encoding.from_dap_column(line, 6), 7);
+        assert_eq!(encoding.to_dap_line(3), 3);
+
+        let encoding = DapPositionEncoding::new(Some(false), Some(false));
+        assert_eq!(encoding.to_dap_column(line, 7), 5);
+        assert_eq!(encoding.from_dap_column(line, 5), 7);
+        assert_eq!(encoding.to_dap_line(3), 2);
+        assert_eq!(encoding.from_dap_line(2), 3);
+
+        // Unknown locations are passed through
+        assert_eq!(encoding.to_dap_column(line, 0), 0);
+        assert_eq!(encoding.to_dap_line(0), 0);
+
+        // Without line text we can only fall back to bytes
+        assert_eq!(encoding.to_dap_column(None, 7), 6);
+    }
+}
@@ -70,6 +70,8 @@ pub struct FrameInfo {
     pub frame_name: String,
     pub source: FrameSource,
     pub environment: Option<RThreadSafe<RObject>>,
+    /// 1-based lines and 1-based byte columns. The end column is exclusive.
+    /// Columns are converted to the client's encoding in `into_dap_frame()`.
     pub start_line: i64,
     pub start_column: i64,
     pub end_line: i64,
@@ -294,12 +296,11 @@ impl RMainDap {
             let end_line = VECTOR_ELT(info, i);
             let end_line: i32 = RObject::view(end_line).try_into()?;
 
-            // For `end_column`, the column range provided by R is inclusive `[,]`, but the
-            // one used on the DAP / Positron side is exclusive `[,)` so we have to add 1.
+            // Already exclusive, see `srcref_to_range()`. Unknown locations
+            // have a column of 0.
             i += 1;
             let end_column = VECTOR_ELT(info, i);
             let end_column: i32 = RObject::view(end_column).try_into()?;
-            let end_column = end_column + 1;
 
             let id = self.next_frame_id();
 
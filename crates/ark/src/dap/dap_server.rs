@@ -32,6 +32,7 @@ use stdext::spawn;
 
 use super::dap::Dap;
 use super::dap::DapBackendEvent;
+use crate::dap::dap_position::DapPositionEncoding;
 use crate::dap::dap_r_main::FrameInfo;
 use crate::dap::dap_r_main::FrameSource;
 use crate::dap::dap_variables::object_variables;
@@ -53,6 +54,7 @@ pub fn start_dap(
     log::trace!("DAP: Thread starting at address {}.", tcp_address);
 
     let listener = TcpListener::bind(tcp_address).unwrap();
+    state.lock().unwrap().address = listener.local_addr().ok();
 
     conn_init_tx
         .send(true)
@@ -63,10 +65,15 @@ pub fn start_dap(
 
         let stream = match listener.accept() {
             Ok((stream, addr)) => {
-                log::info!("DAP: Connected to client {addr:?}");
-
                 let mut state = state.lock().unwrap();
+                if state.is_stopped {
+                    log::info!("DAP: Server stopped");
+                    return;
+                }
+
+                log::info!("DAP: Connected to client {addr:?}");
                 state.is_connected = true;
+                state.stream = stream.try_clone().ok();
 
                 stream
             },
@@ -110,6 +117,7 @@ pub fn start_dap(
                     log::trace!("DAP: Disconnected from client");
                     let mut state = state.lock().unwrap();
                     state.is_connected = false;
+                    state.stream = None;
                     break;
                 }
             }
@@ -117,6 +125,11 @@ pub fn start_dap(
             // Terminate the events thread
             let _ = done_tx.send(true);
         });
+
+        if state.lock().unwrap().is_stopped {
+            log::info!("DAP: Server stopped");
+            return;
+        }
     }
 }
 
@@ -260,7 +273,13 @@ impl<R: Read, W: Write> DapServer<R, W> {
         true
     }
 
-    fn handle_initialize(&mut self, req: Request, _args: InitializeArguments) {
+    fn handle_initialize(&mut self, req: Request, args: InitializeArguments) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.position_encoding =
+                DapPositionEncoding::new(args.lines_start_at1, args.columns_start_at1);
+        }
+
         let rsp = req.success(ResponseBody::Initialize(types::Capabilities {
             supports_restart_request: Some(true),
             ..Default::default()
@@ -339,11 +358,16 @@ impl<R: Read, W: Write> DapServer<R, W> {
         let state = self.state.lock().unwrap();
         let stack = &state.stack;
         let fallback_sources = &state.fallback_sources;
+        let encoding = &state.position_encoding;
+
+        // Source lines are needed to reencode byte columns. Keyed by file path
+        // or source text so that files are read at most once per request.
+        let mut source_lines: HashMap<String, Option<Vec<String>>> = HashMap::new();
 
         let stack = match stack {
             Some(stack) => stack
                 .into_iter()
-                .map(|frame| into_dap_frame(frame, fallback_sources))
+                .map(|frame| into_dap_frame(frame, fallback_sources, encoding, &mut source_lines))
                 .collect(),
             _ => vec![],
         };
@@ -548,15 +572,30 @@ impl<R: Read, W: Write> DapServer<R, W> {
     }
 }
 
-fn into_dap_frame(frame: &FrameInfo, fallback_sources: &HashMap<String, i32>) -> StackFrame {
+fn into_dap_frame(
+    frame: &FrameInfo,
+    fallback_sources: &HashMap<String, i32>,
+    encoding: &DapPositionEncoding,
+    source_lines: &mut HashMap<String, Option<Vec<String>>>,
+) -> StackFrame {
     let id = frame.id;
     let source_name = frame.source_name.clone();
     let frame_name = frame.frame_name.clone();
     let source = frame.source.clone();
-    let start_line = frame.start_line;
-    let start_column = frame.start_column;
-    let end_line = frame.end_line;
-    let end_column = frame.end_column;
+
+    // Convert byte columns to the client's encoding, using the text of the
+    // lines the columns point into
+    let lines = frame_lines(&frame.source, source_lines);
+    let line_text = |line: i64| -> Option<&str> {
+        let lines = lines?;
+        let index = usize::try_from(line - 1).ok()?;
+        lines.get(index).map(String::as_str)
+    };
+
+    let start_line = encoding.to_dap_line(frame.start_line);
+    let start_column = encoding.to_dap_column(line_text(frame.start_line), frame.start_column);
+    let end_line = encoding.to_dap_line(frame.end_line);
+    let end_column = encoding.to_dap_column(line_text(frame.end_line), frame.end_column);
 
     // Retrieve either `path` or `source_reference` depending on the `source` type.
     // In the `Text` case, a `source_reference` should always exist because we loaded
@@ -597,3 +636,35 @@ fn into_dap_frame(frame: &FrameInfo, fallback_sources: &HashMap<String, i32>) ->
         presentation_hint: None,
     }
 }
+
+/// Retrieve the lines of a frame source
+///
+/// Text sources are split directly. File sources are read from disk. Results
+/// are cached in `source_lines`. Returns `None` if the file can't be read, in
+/// which case columns are sent as bytes.
+fn frame_lines<'a>(
+    source: &'a FrameSource,
+    source_lines: &'a mut HashMap<String, Option<Vec<String>>>,
+) -> Option<&'a Vec<String>> {
+    let (key, contents) = match source {
+        FrameSource::File(path) => (path, None),
+        FrameSource::Text(text) => (text, Some(text)),
+    };
+
+    source_lines
+        .entry(key.clone())
+        .or_insert_with(|| {
+            let contents = match contents {
+                Some(contents) => contents.clone(),
+                None => match std::fs::read_to_string(key) {
+                    Ok(contents) => contents,
+                    Err(err) => {
+                        log::warn!("DAP: Can't read source file '{key}': {err:?}");
+                        return None;
+                    },
+                },
+            };
+            Some(contents.lines().map(String::from).collect())
+        })
+        .as_ref()
+}
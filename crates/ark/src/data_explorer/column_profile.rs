@@ -25,6 +25,7 @@ use harp::exec::RFunctionExt;
 use harp::tbl_get_column;
 use harp::RObject;
 use harp::TableKind;
+use stdext::cancellation::CancellationToken;
 use stdext::unwrap;
 
 use crate::data_explorer::histogram;
@@ -53,9 +54,16 @@ pub async fn handle_columns_profiles_requests(
         params.kind,
         params.request.profiles,
         params.request.format_options,
+        &comm.closed,
     )
-    .await
-    .unwrap_or_else(|e| {
+    .await;
+
+    // Nobody is waiting for the profiles if the comm was closed in the meantime
+    if comm.closed.is_cancelled() {
+        return Ok(());
+    }
+
+    let profiles = profiles.unwrap_or_else(|e| {
         // In case something goes wrong while computing the profiles, we send
         // an empty response. Ideally, we would have a way to comunicate an that
         // an error happened but it's not implemented yet.
@@ -81,6 +89,7 @@ async fn process_columns_profiles_requests(
     kind: TableKind,
     profiles: Vec<ColumnProfileRequest>,
     format_options: FormatOptions,
+    closed: &CancellationToken,
 ) -> anyhow::Result<Vec<ColumnProfileResult>> {
     // This is an R thread, so we can actually get the data frame.
     // If it fails we quickly return an empty result set and end the task.
@@ -90,6 +99,12 @@ async fn process_columns_profiles_requests(
     let mut results: Vec<ColumnProfileResult> = Vec::with_capacity(profiles.len());
 
     for profile in profiles.into_iter() {
+        // Profiles are computed across several idle tasks, so the comm is
+        // checked directly rather than with `harp::cancellation`
+        if closed.is_cancelled() {
+            return Err(harp::Error::Cancelled.into());
+        }
+
         log::trace!("Processing column!");
         results.push(
             profile_column(
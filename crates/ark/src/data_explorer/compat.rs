@@ -0,0 +1,144 @@
+//
+// compat.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Adapters for older versions of the data explorer protocol. Requests of
+// frontends that negotiated an older version are upgraded to the current
+// protocol before being handled, so that the data explorer itself only ever
+// deals with the current types.
+//
+// Version 1 requested data values with a contiguous range of rows for a set
+// of columns. Version 2 selects rows per column, with ranges or indices.
+
+use amalthea::comm::comm_channel::CommMsg;
+use amalthea::comm::data_explorer_comm::ArraySelection;
+use amalthea::comm::data_explorer_comm::ColumnSelection;
+use amalthea::comm::data_explorer_comm::DataSelectionRange;
+use amalthea::comm::data_explorer_comm::FormatOptions;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Versions of the data explorer protocol supported by ark, the last one
+/// being the current protocol
+pub const DATA_EXPLORER_PROTOCOL_VERSIONS: &[u32] = &[1, 2];
+
+/// Parameters of `get_data_values` in version 1
+#[derive(Deserialize)]
+struct GetDataValuesParamsV1 {
+    row_start_index: i64,
+    num_rows: i64,
+    column_indices: Vec<i64>,
+    format_options: FormatOptions,
+}
+
+/// Upgrade a message received from a frontend speaking `version` of the
+/// protocol to the current version. Messages that didn't change are
+/// returned as is.
+pub fn upgrade_request(version: u32, msg: CommMsg) -> anyhow::Result<CommMsg> {
+    let CommMsg::Rpc(id, data) = msg else {
+        return Ok(msg);
+    };
+
+    let data = match version {
+        1 => upgrade_request_v1(data)?,
+        _ => data,
+    };
+
+    Ok(CommMsg::Rpc(id, data))
+}
+
+fn upgrade_request_v1(data: Value) -> anyhow::Result<Value> {
+    if data.get("method").and_then(|method| method.as_str()) != Some("get_data_values") {
+        return Ok(data);
+    }
+
+    let Some(params) = data.get("params") else {
+        return Ok(data);
+    };
+    let params: GetDataValuesParamsV1 = serde_json::from_value(params.clone())?;
+
+    let spec = ArraySelection::SelectRange(DataSelectionRange {
+        first_index: params.row_start_index,
+        last_index: params.row_start_index + params.num_rows - 1,
+    });
+
+    let columns: Vec<ColumnSelection> = params
+        .column_indices
+        .into_iter()
+        .map(|column_index| ColumnSelection {
+            column_index,
+            spec: spec.clone(),
+        })
+        .collect();
+
+    Ok(serde_json::json!({
+        "method": "get_data_values",
+        "params": {
+            "columns": columns,
+            "format_options": params.format_options,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use amalthea::comm::comm_channel::CommMsg;
+    use amalthea::comm::data_explorer_comm::ArraySelection;
+    use amalthea::comm::data_explorer_comm::DataExplorerBackendRequest;
+    use amalthea::comm::data_explorer_comm::DataSelectionRange;
+    use serde_json::json;
+
+    use crate::data_explorer::compat::upgrade_request;
+
+    fn request(version: u32, data: serde_json::Value) -> DataExplorerBackendRequest {
+        let msg = CommMsg::Rpc(String::from("id"), data);
+        let CommMsg::Rpc(_, data) = upgrade_request(version, msg).unwrap() else {
+            panic!("Expected an RPC");
+        };
+        serde_json::from_value(data).unwrap()
+    }
+
+    #[test]
+    fn test_upgrade_get_data_values_v1() {
+        let format_options = json!({
+            "large_num_digits": 2,
+            "small_num_digits": 4,
+            "max_integral_digits": 7,
+            "max_value_length": 1000,
+            "thousands_sep": null
+        });
+
+        let req = request(
+            1,
+            json!({
+                "method": "get_data_values",
+                "params": {
+                    "row_start_index": 10,
+                    "num_rows": 5,
+                    "column_indices": [0, 2],
+                    "format_options": format_options,
+                }
+            }),
+        );
+
+        let DataExplorerBackendRequest::GetDataValues(params) = req else {
+            panic!("Expected `get_data_values`");
+        };
+        assert_eq!(params.columns.len(), 2);
+        assert_eq!(params.columns[1].column_index, 2);
+        assert_eq!(
+            params.columns[1].spec,
+            ArraySelection::SelectRange(DataSelectionRange {
+                first_index: 10,
+                last_index: 14,
+            })
+        );
+
+        // Other requests are passed through
+        let req = request(1, json!({ "method": "get_state" }));
+        assert_eq!(req, DataExplorerBackendRequest::GetState);
+    }
+}
@@ -7,6 +7,7 @@
 
 use amalthea::comm::data_explorer_comm::ColumnValue;
 use amalthea::comm::data_explorer_comm::FormatOptions;
+use harp::cancellation::check_cancelled;
 use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
 use harp::object::r_dbl_is_finite;
@@ -92,15 +93,15 @@ fn format_values(x: SEXP, format_options: &FormatOptions) -> anyhow::Result<Vec<
         REALSXP => Ok(format_dbl(
             unsafe { NumericVector::new_unchecked(x) },
             format_options,
-        )),
+        )?),
         INTSXP => Ok(format_int(
             unsafe { IntegerVector::new_unchecked(x) },
             format_options,
-        )),
-        STRSXP => Ok(format_chr(unsafe { CharacterVector::new_unchecked(x) })),
-        LGLSXP => Ok(format_lgl(unsafe { LogicalVector::new_unchecked(x) })),
-        CPLXSXP => Ok(format_cpl(unsafe { ComplexVector::new_unchecked(x) })),
-        VECSXP => Ok(format_list(x)),
+        )?),
+        STRSXP => Ok(format_chr(unsafe { CharacterVector::new_unchecked(x) })?),
+        LGLSXP => Ok(format_lgl(unsafe { LogicalVector::new_unchecked(x) })?),
+        CPLXSXP => Ok(format_cpl(unsafe { ComplexVector::new_unchecked(x) })?),
+        VECSXP => Ok(format_list(x)?),
         _ => Err(anyhow::anyhow!("Unsupported column type")),
     }
 }
@@ -147,8 +148,13 @@ fn format_object(x: SEXP) -> Vec<FormattedValue> {
         return formatted.collect();
     });
 
-    unsafe { LogicalVector::new_unchecked(is_na.sexp) }
-        .iter()
+    let is_na = unsafe { LogicalVector::new_unchecked(is_na.sexp) }.to_vec_lossy();
+    let is_na = unwrap!(is_na, Err(_) => {
+        return formatted.collect();
+    });
+
+    is_na
+        .into_iter()
         .zip(formatted)
         .map(|(is_na, v)| {
             // We don't expect is.na to return NA's, but if it happens, we treat it as false
@@ -162,11 +168,14 @@ fn format_object(x: SEXP) -> Vec<FormattedValue> {
         .collect()
 }
 
-fn format_list(x: SEXP) -> Vec<FormattedValue> {
+fn format_list(x: SEXP) -> harp::Result<Vec<FormattedValue>> {
     let len = r_length(x);
     let mut output = Vec::<FormattedValue>::with_capacity(len as usize);
 
     for i in 0..len {
+        // Formatting elements evaluates R code, which can be slow
+        check_cancelled()?;
+
         let elt = harp::list_get(x, i);
         let formatted = if r_is_null(elt) {
             FormattedValue::NULL
@@ -176,7 +185,7 @@ fn format_list(x: SEXP) -> Vec<FormattedValue> {
         output.push(formatted);
     }
 
-    output
+    Ok(output)
 }
 
 fn format_list_elt(x: SEXP) -> String {
@@ -219,17 +228,19 @@ fn format_list_elt(x: SEXP) -> String {
     format!("<{} [{}]>", class_str, dim_str)
 }
 
-fn format_cpl(x: ComplexVector) -> Vec<FormattedValue> {
-    x.iter()
+fn format_cpl(x: ComplexVector) -> harp::Result<Vec<FormattedValue>> {
+    Ok(x.to_vec_lossy()?
+        .into_iter()
         .map(|x| match x {
             Some(v) => FormattedValue::Value(format!("{}+{}i", v.r, v.i)),
             None => FormattedValue::NA,
         })
-        .collect()
+        .collect())
 }
 
-fn format_lgl(x: LogicalVector) -> Vec<FormattedValue> {
-    x.iter()
+fn format_lgl(x: LogicalVector) -> harp::Result<Vec<FormattedValue>> {
+    Ok(x.to_vec_lossy()?
+        .into_iter()
         .map(|x| match x {
             Some(v) => match v {
                 true => FormattedValue::Value("TRUE".to_string()),
@@ -237,20 +248,24 @@ fn format_lgl(x: LogicalVector) -> Vec<FormattedValue> {
             },
             None => FormattedValue::NA,
         })
-        .collect()
+        .collect())
 }
 
-fn format_chr(x: CharacterVector) -> Vec<FormattedValue> {
-    x.iter()
+fn format_chr(x: CharacterVector) -> harp::Result<Vec<FormattedValue>> {
+    Ok(x.to_vec_lossy()?
+        .into_iter()
         .map(|x| match x {
             Some(v) => FormattedValue::Value(v),
             None => FormattedValue::NA,
         })
-        .collect()
+        .collect())
 }
 
-fn format_int(x: IntegerVector, options: &FormatOptions) -> Vec<FormattedValue> {
-    x.iter().map(|x| format_int_elt(x, options)).collect()
+fn format_int(x: IntegerVector, options: &FormatOptions) -> harp::Result<Vec<FormattedValue>> {
+    Ok(x.to_vec_lossy()?
+        .into_iter()
+        .map(|x| format_int_elt(x, options))
+        .collect())
 }
 
 fn format_int_elt(x: Option<i32>, options: &FormatOptions) -> FormattedValue {
@@ -263,8 +278,11 @@ fn format_int_elt(x: Option<i32>, options: &FormatOptions) -> FormattedValue {
     }
 }
 
-fn format_dbl(x: NumericVector, options: &FormatOptions) -> Vec<FormattedValue> {
-    x.iter().map(|x| format_dbl_elt(x, options)).collect()
+fn format_dbl(x: NumericVector, options: &FormatOptions) -> harp::Result<Vec<FormattedValue>> {
+    Ok(x.to_vec_lossy()?
+        .into_iter()
+        .map(|x| format_dbl_elt(x, options))
+        .collect())
 }
 
 fn format_dbl_elt(x: Option<f64>, options: &FormatOptions) -> FormattedValue {
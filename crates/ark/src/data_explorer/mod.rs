@@ -6,6 +6,7 @@
 //
 
 pub mod column_profile;
+pub mod compat;
 pub mod export_selection;
 pub mod format;
 pub mod histogram;
@@ -25,7 +25,10 @@ use amalthea::comm::data_explorer_comm::DataExplorerFrontendEvent;
 use amalthea::comm::data_explorer_comm::ExportDataSelectionFeatures;
 use amalthea::comm::data_explorer_comm::ExportDataSelectionParams;
 use amalthea::comm::data_explorer_comm::ExportFormat;
+use amalthea::comm::data_explorer_comm::ExportObjectFormat;
+use amalthea::comm::data_explorer_comm::ExportObjectParams;
 use amalthea::comm::data_explorer_comm::ExportedData;
+use amalthea::comm::data_explorer_comm::ExportedObject;
 use amalthea::comm::data_explorer_comm::FilterComparisonOp;
 use amalthea::comm::data_explorer_comm::FilterResult;
 use amalthea::comm::data_explorer_comm::FormatOptions;
@@ -51,6 +54,7 @@ use amalthea::comm::data_explorer_comm::TableSchema;
 use amalthea::comm::data_explorer_comm::TableSelection;
 use amalthea::comm::data_explorer_comm::TableShape;
 use amalthea::comm::event::CommManagerEvent;
+use amalthea::comm::protocol;
 use amalthea::socket::comm::CommInitiator;
 use amalthea::socket::comm::CommSocket;
 use anyhow::anyhow;
@@ -58,6 +62,7 @@ use anyhow::bail;
 use crossbeam::channel::unbounded;
 use crossbeam::channel::Sender;
 use crossbeam::select;
+use harp::cancellation::check_cancelled;
 use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
 use harp::object::RObject;
@@ -78,17 +83,22 @@ use uuid::Uuid;
 
 use crate::data_explorer::column_profile::handle_columns_profiles_requests;
 use crate::data_explorer::column_profile::ProcessColumnsProfilesParams;
+use crate::data_explorer::compat;
+use crate::data_explorer::compat::DATA_EXPLORER_PROTOCOL_VERSIONS;
 use crate::data_explorer::export_selection;
 use crate::data_explorer::format;
 use crate::data_explorer::format::format_string;
 use crate::data_explorer::table::Table;
 use crate::data_explorer::utils::display_type;
 use crate::data_explorer::utils::tbl_subset_with_view_indices;
+use crate::export_object::export_object;
 use crate::interface::RMain;
 use crate::lsp::events::EVENTS;
 use crate::modules::ARK_ENVS;
 use crate::r_task;
+use crate::r_task::r_task_cancellable;
 use crate::thread::RThreadSafe;
+use crate::user_config::user_config;
 use crate::variables::variable::WorkspaceVariableDisplayType;
 
 /// A name/value binding pair in an environment.
@@ -150,12 +160,17 @@ pub struct RDataExplorer {
     /// The communication socket for the data viewer.
     comm: CommSocket,
 
+    /// The version of the protocol negotiated with the frontend. Requests of
+    /// older versions are upgraded before being handled.
+    protocol_version: u32,
+
     /// A channel to send messages to the CommManager.
     comm_manager_tx: Sender<CommManagerEvent>,
 }
 #[derive(Deserialize, Serialize)]
 struct Metadata {
     title: String,
+    protocol_version: u32,
 }
 
 impl Drop for RDataExplorer {
@@ -180,6 +195,11 @@ impl RDataExplorer {
             String::from("positron.dataExplorer"),
         );
 
+        let protocol_version =
+            protocol::negotiate(&comm.comm_name, DATA_EXPLORER_PROTOCOL_VERSIONS).map_err(
+                |err| harp::Error::Anyhow(anyhow!("Can't open data viewer for '{title}': {err}")),
+            )?;
+
         // To be able to `Send` the `data` to the thread to be owned by the data
         // viewer, it needs to be made thread safe
         let table = Table::new(RThreadSafe::new(data));
@@ -203,6 +223,7 @@ impl RDataExplorer {
                         row_filters: vec![],
                         col_filters: vec![],
                         comm,
+                        protocol_version,
                         comm_manager_tx,
                     };
 
@@ -233,6 +254,7 @@ impl RDataExplorer {
         let execute: anyhow::Result<()> = local! {
             let metadata = Metadata {
                 title: self.title.clone(),
+                protocol_version: self.protocol_version,
             };
             let comm_open_json = serde_json::to_value(metadata)?;
             // Notify frontend that the data viewer comm is open
@@ -297,6 +319,14 @@ impl RDataExplorer {
                         break;
                     }
 
+                    let msg = match compat::upgrade_request(self.protocol_version, msg) {
+                        Ok(msg) => msg,
+                        Err(err) => {
+                            log::error!("Data Viewer: Can't upgrade message from protocol version {}: {err:?}", self.protocol_version);
+                            continue;
+                        },
+                    };
+
                     let comm = self.comm.clone();
                     comm.handle_request(msg, |req| self.handle_rpc(req));
                 },
@@ -406,7 +436,9 @@ impl RDataExplorer {
             // Columns didn't change, but the data has. If there are sort
             // keys, we need to sort the rows again to reflect the new data.
             if self.sort_keys.len() > 0 {
-                self.sorted_indices = Some(r_task(|| self.r_sort_rows())?);
+                self.sorted_indices = Some(r_task_cancellable(self.comm.closed.clone(), || {
+                    self.r_sort_rows()
+                })?);
             }
 
             // Recompute and apply filters and sorts.
@@ -468,7 +500,9 @@ impl RDataExplorer {
             DataExplorerBackendRequest::GetDataValues(GetDataValuesParams {
                 columns,
                 format_options,
-            }) => r_task(|| self.r_get_data_values(columns, format_options)),
+            }) => r_task_cancellable(self.comm.closed.clone(), || {
+                self.r_get_data_values(columns, format_options)
+            }),
 
             DataExplorerBackendRequest::SetSortColumns(SetSortColumnsParams {
                 sort_keys: keys,
@@ -480,7 +514,9 @@ impl RDataExplorer {
                 // indices; otherwise, sort the rows and save the result
                 self.sorted_indices = match keys.len() {
                     0 => None,
-                    _ => Some(r_task(|| self.r_sort_rows())?),
+                    _ => Some(r_task_cancellable(self.comm.closed.clone(), || {
+                        self.r_sort_rows()
+                    })?),
                 };
 
                 // Apply sorts to the filtered indices to create view indices
@@ -520,7 +556,9 @@ impl RDataExplorer {
                 Ok(DataExplorerBackendReply::GetColumnProfilesReply())
             },
 
-            DataExplorerBackendRequest::GetState => r_task(|| self.r_get_state()),
+            DataExplorerBackendRequest::GetState => {
+                r_task_cancellable(self.comm.closed.clone(), || self.r_get_state())
+            },
 
             DataExplorerBackendRequest::SearchSchema(_) => {
                 return Err(anyhow!("Data Explorer: Not yet supported"));
@@ -531,8 +569,9 @@ impl RDataExplorer {
             },
 
             DataExplorerBackendRequest::GetRowLabels(req) => {
-                let row_labels =
-                    r_task(|| self.r_get_row_labels(req.selection, &req.format_options))?;
+                let row_labels = r_task_cancellable(self.comm.closed.clone(), || {
+                    self.r_get_row_labels(req.selection, &req.format_options)
+                })?;
                 Ok(DataExplorerBackendReply::GetRowLabelsReply(
                     TableRowLabels {
                         row_labels: vec![row_labels],
@@ -549,6 +588,13 @@ impl RDataExplorer {
                     format,
                 },
             )),
+
+            DataExplorerBackendRequest::ExportObject(ExportObjectParams { file, format }) => {
+                let format = self.r_export_object(&file, format)?;
+                Ok(DataExplorerBackendReply::ExportObjectReply(
+                    ExportedObject { file, format },
+                ))
+            },
         }
     }
 }
@@ -718,7 +764,8 @@ impl RDataExplorer {
             return Ok((None, None));
         }
 
-        let (indices, errors) = r_task(|| self.r_filter_rows())?;
+        let (indices, errors) =
+            r_task_cancellable(self.comm.closed.clone(), || self.r_filter_rows())?;
         // this is called for the side-effect of updating the row_filters with validty status and
         // error messages
         let had_errors = Some(self.apply_filter_errors(errors)?);
@@ -977,10 +1024,12 @@ impl RDataExplorer {
     ) -> anyhow::Result<DataExplorerBackendReply> {
         let mut column_data: Vec<Vec<ColumnValue>> = Vec::with_capacity(columns.len());
         for selection in columns {
+            check_cancelled()?;
+
             let tbl = tbl_subset_with_view_indices(
                 self.table.get()?.sexp,
                 &self.view_indices,
-                Some(self.get_row_selection_indices(selection.spec)),
+                Some(self.get_page_indices(selection.spec)),
                 Some(vec![selection.column_index]),
             )?;
 
@@ -1005,7 +1054,7 @@ impl RDataExplorer {
         let tbl = tbl_subset_with_view_indices(
             self.table.get()?.sexp,
             &self.view_indices,
-            Some(self.get_row_selection_indices(selection)),
+            Some(self.get_page_indices(selection)),
             Some(vec![]), // Use empty vec, because we only need the row names.
         )?;
 
@@ -1029,6 +1078,16 @@ impl RDataExplorer {
 
     // Given an ArraySelection, this materializes the indices that will actually be used.
     // Also does some sanity checks to avoid OOB access.
+    /// Indices of the rows to send to the frontend, truncated to the page size
+    /// configured by the user
+    fn get_page_indices(&self, selection: ArraySelection) -> Vec<i64> {
+        let mut indices = self.get_row_selection_indices(selection);
+        if let Some(page_size) = user_config().data_viewer.page_size {
+            indices.truncate(page_size);
+        }
+        indices
+    }
+
     fn get_row_selection_indices(&self, selection: ArraySelection) -> Vec<i64> {
         let num_view_rows = match self.view_indices {
             Some(ref indices) => indices.len() as i32,
@@ -1055,7 +1114,7 @@ impl RDataExplorer {
         selection: TableSelection,
         format: ExportFormat,
     ) -> anyhow::Result<String> {
-        r_task(|| {
+        r_task_cancellable(self.comm.closed.clone(), || {
             export_selection::export_selection(
                 self.table.get()?.sexp,
                 &self.view_indices,
@@ -1064,6 +1123,26 @@ impl RDataExplorer {
             )
         })
     }
+
+    /// Save the table to a file, with the current row filters and sort
+    /// applied
+    fn r_export_object(
+        &self,
+        file: &str,
+        format: Option<ExportObjectFormat>,
+    ) -> anyhow::Result<ExportObjectFormat> {
+        r_task_cancellable(self.comm.closed.clone(), || {
+            let table = self.table.get()?;
+            let table = match &self.view_indices {
+                Some(view_indices) => {
+                    let rows = (0..view_indices.len() as i64).collect();
+                    tbl_subset_with_view_indices(table.sexp, &self.view_indices, Some(rows), None)?
+                },
+                None => table,
+            };
+            export_object(table.sexp, file, format, &self.title)
+        })
+    }
 }
 
 fn table_info_or_bail(x: SEXP) -> anyhow::Result<TableInfo> {
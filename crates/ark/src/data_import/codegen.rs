@@ -0,0 +1,296 @@
+//
+// codegen.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::path::Path;
+
+use amalthea::comm::data_import_comm::ColumnType;
+use amalthea::comm::data_import_comm::GenerateCodeParams;
+use amalthea::comm::data_import_comm::GenerateCodeReader;
+use amalthea::comm::data_import_comm::ImportOptions;
+
+use crate::data_import::remote;
+
+/// Generate the R code that reads a file with the given options and assigns
+/// the result to a variable, e.g. `data <- readr::read_csv("data.csv")`.
+/// Arguments equal to the defaults of the reader are omitted.
+///
+/// Remote files are downloaded to a temporary file first, which works the
+/// same with all readers and encodings:
+///
+/// ```r
+/// sales_path <- tempfile(fileext = ".csv")
+/// utils::download.file("https://example.com/sales.csv", sales_path, mode = "wb")
+/// sales <- readr::read_csv(sales_path)
+/// ```
+pub(crate) fn generate_code(params: &GenerateCodeParams) -> String {
+    let is_url = remote::is_url(&params.path);
+
+    let path = if is_url {
+        remote::url_path(&params.path)
+    } else {
+        params.path.clone()
+    };
+    let name = match &params.name {
+        Some(name) => name.clone(),
+        None => variable_name(&path),
+    };
+
+    let file = if is_url {
+        format!("{name}_path")
+    } else {
+        r_string(&params.path)
+    };
+    let call = match params.reader {
+        GenerateCodeReader::Readr => readr_call(&file, &params.options),
+        GenerateCodeReader::Base => base_call(&file, &params.options),
+    };
+
+    if !is_url {
+        return format!("{name} <- {call}");
+    }
+
+    let tempfile = match Path::new(&path).extension() {
+        Some(ext) => format!(
+            "tempfile(fileext = {})",
+            r_string(&format!(".{}", ext.to_string_lossy()))
+        ),
+        None => String::from("tempfile()"),
+    };
+    let download = format!(
+        "utils::download.file({}, {file}, mode = \"wb\")",
+        r_string(&params.path)
+    );
+
+    format!("{file} <- {tempfile}\n{download}\n{name} <- {call}")
+}
+
+/// `file` is the R code of the file argument, i.e. a string or a variable
+fn readr_call(file: &str, options: &ImportOptions) -> String {
+    let mut args = vec![file.to_string()];
+
+    let fun = match options.delimiter.as_str() {
+        "," => "readr::read_csv",
+        "\t" => "readr::read_tsv",
+        delimiter => {
+            args.push(format!("delim = {}", r_string(delimiter)));
+            "readr::read_delim"
+        },
+    };
+
+    if !options.header {
+        args.push(String::from("col_names = FALSE"));
+    }
+    if options.quote != "\"" {
+        args.push(format!("quote = {}", r_string(&options.quote)));
+    }
+    if let Some(column_types) = &options.column_types {
+        let spec: String = column_types.iter().map(readr_col_type).collect();
+        args.push(format!("col_types = {}", r_string(&spec)));
+    }
+
+    // readr skips byte order marks on its own
+    if !matches!(options.encoding.as_str(), "UTF-8" | "UTF-8-BOM") {
+        args.push(format!(
+            "locale = readr::locale(encoding = {})",
+            r_string(&options.encoding)
+        ));
+    }
+
+    format!("{fun}({})", args.join(", "))
+}
+
+fn base_call(file: &str, options: &ImportOptions) -> String {
+    let mut args = vec![file.to_string()];
+
+    // `read.table()` defaults to no header and two quote characters
+    let (fun, header_default, quote_default) = match options.delimiter.as_str() {
+        "," => ("utils::read.csv", true, "\""),
+        "\t" => ("utils::read.delim", true, "\""),
+        delimiter => {
+            args.push(format!("sep = {}", r_string(delimiter)));
+            ("utils::read.table", false, "\"'")
+        },
+    };
+
+    if options.header != header_default {
+        let header = if options.header { "TRUE" } else { "FALSE" };
+        args.push(format!("header = {header}"));
+    }
+    if options.quote != quote_default {
+        args.push(format!("quote = {}", r_string(&options.quote)));
+    }
+    if let Some(column_types) = &options.column_types {
+        let classes: Vec<String> = column_types
+            .iter()
+            .map(|column_type| r_string(base_col_class(column_type)))
+            .collect();
+        args.push(format!("colClasses = c({})", classes.join(", ")));
+    }
+    if options.encoding != "UTF-8" {
+        args.push(format!("fileEncoding = {}", r_string(&options.encoding)));
+    }
+
+    format!("{fun}({})", args.join(", "))
+}
+
+/// Compact column specification of `readr::cols()`
+fn readr_col_type(column_type: &ColumnType) -> char {
+    match column_type {
+        ColumnType::Logical => 'l',
+        ColumnType::Integer => 'i',
+        ColumnType::Double => 'd',
+        ColumnType::Character => 'c',
+        ColumnType::Date => 'D',
+        ColumnType::Datetime => 'T',
+    }
+}
+
+fn base_col_class(column_type: &ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Logical => "logical",
+        ColumnType::Integer => "integer",
+        ColumnType::Double => "numeric",
+        ColumnType::Character => "character",
+        ColumnType::Date => "Date",
+        ColumnType::Datetime => "POSIXct",
+    }
+}
+
+fn r_string(x: &str) -> String {
+    let mut out = String::with_capacity(x.len() + 2);
+    out.push('"');
+    for char in x.chars() {
+        match char {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(char),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Syntactic variable name derived from the file name, e.g. `sales_2024` for
+/// `sales-2024.csv`
+fn variable_name(path: &str) -> String {
+    let stem = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name: String = stem
+        .chars()
+        .map(|char| {
+            if char.is_alphanumeric() || char == '.' || char == '_' {
+                char
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    match name.chars().next() {
+        None => String::from("data"),
+        Some(char) if char.is_alphabetic() => name,
+        Some(_) => format!("data_{name}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amalthea::comm::data_import_comm::ColumnType;
+    use amalthea::comm::data_import_comm::GenerateCodeParams;
+    use amalthea::comm::data_import_comm::GenerateCodeReader;
+    use amalthea::comm::data_import_comm::ImportOptions;
+
+    use crate::data_import::codegen::generate_code;
+    use crate::data_import::codegen::variable_name;
+
+    fn params(options: ImportOptions, reader: GenerateCodeReader) -> GenerateCodeParams {
+        GenerateCodeParams {
+            path: String::from("data/sales 2024.csv"),
+            options,
+            reader,
+            name: None,
+        }
+    }
+
+    fn csv_options() -> ImportOptions {
+        ImportOptions {
+            delimiter: String::from(","),
+            header: true,
+            quote: String::from("\""),
+            encoding: String::from("UTF-8"),
+            column_types: None,
+        }
+    }
+
+    #[test]
+    fn test_generate_code_defaults() {
+        assert_eq!(
+            generate_code(&params(csv_options(), GenerateCodeReader::Readr)),
+            "sales_2024 <- readr::read_csv(\"data/sales 2024.csv\")"
+        );
+        assert_eq!(
+            generate_code(&params(csv_options(), GenerateCodeReader::Base)),
+            "sales_2024 <- utils::read.csv(\"data/sales 2024.csv\")"
+        );
+    }
+
+    #[test]
+    fn test_generate_code_options() {
+        let options = ImportOptions {
+            delimiter: String::from(";"),
+            header: false,
+            quote: String::from("\""),
+            encoding: String::from("latin1"),
+            column_types: Some(vec![ColumnType::Integer, ColumnType::Date]),
+        };
+
+        let mut params = params(options, GenerateCodeReader::Readr);
+        params.name = Some(String::from("df"));
+        assert_eq!(
+            generate_code(&params),
+            "df <- readr::read_delim(\"data/sales 2024.csv\", delim = \";\", col_names = FALSE, col_types = \"iD\", locale = readr::locale(encoding = \"latin1\"))"
+        );
+
+        params.reader = GenerateCodeReader::Base;
+        assert_eq!(
+            generate_code(&params),
+            "df <- utils::read.table(\"data/sales 2024.csv\", sep = \";\", quote = \"\\\"\", colClasses = c(\"integer\", \"Date\"), fileEncoding = \"latin1\")"
+        );
+    }
+
+    #[test]
+    fn test_generate_code_url() {
+        let mut params = params(csv_options(), GenerateCodeReader::Readr);
+        params.path = String::from("https://example.com/data/sales.csv?raw=true");
+
+        assert_eq!(
+            generate_code(&params),
+            "sales_path <- tempfile(fileext = \".csv\")\nutils::download.file(\"https://example.com/data/sales.csv?raw=true\", sales_path, mode = \"wb\")\nsales <- readr::read_csv(sales_path)"
+        );
+
+        params.path = String::from("https://example.com/export");
+        params.reader = GenerateCodeReader::Base;
+        params.name = Some(String::from("df"));
+        assert_eq!(
+            generate_code(&params),
+            "df_path <- tempfile()\nutils::download.file(\"https://example.com/export\", df_path, mode = \"wb\")\ndf <- utils::read.csv(df_path)"
+        );
+    }
+
+    #[test]
+    fn test_variable_name() {
+        assert_eq!(variable_name("data/iris.csv"), "iris");
+        assert_eq!(variable_name("/tmp/2024-results.tsv"), "data_2024_results");
+        assert_eq!(variable_name(""), "data");
+    }
+}
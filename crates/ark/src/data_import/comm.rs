@@ -0,0 +1,53 @@
+//
+// comm.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use amalthea::comm::comm_channel::CommMsg;
+use amalthea::comm::data_import_comm::DataImportBackendReply;
+use amalthea::comm::data_import_comm::DataImportBackendRequest;
+use amalthea::socket::comm::CommSocket;
+use stdext::spawn;
+
+use crate::data_import::codegen::generate_code;
+use crate::data_import::parse::preview;
+use crate::data_import::parse::read_sample;
+use crate::data_import::parse::sniff;
+
+/// Serve requests of the delimited file import wizard
+///
+/// Files are sniffed and parsed in Rust on a dedicated thread, so the wizard
+/// stays responsive while R is busy. R is only involved once the user runs the
+/// generated code. Files can be local paths or http(s) URLs, of which only the
+/// beginning is fetched, see `remote.rs`.
+pub fn start(comm: CommSocket) {
+    spawn!("ark-data-import", move || {
+        for msg in comm.incoming_rx.iter() {
+            if let CommMsg::Close = msg {
+                break;
+            }
+            comm.handle_request(msg, handle_rpc);
+        }
+    });
+}
+
+fn handle_rpc(req: DataImportBackendRequest) -> anyhow::Result<DataImportBackendReply> {
+    match req {
+        DataImportBackendRequest::Sniff(params) => {
+            let sample = read_sample(&params.path, None)?;
+            Ok(DataImportBackendReply::SniffReply(sniff(
+                &sample.text,
+                sample.encoding,
+            )))
+        },
+        DataImportBackendRequest::Preview(params) => {
+            let preview = preview(&params.path, params.options, params.max_rows)?;
+            Ok(DataImportBackendReply::PreviewReply(preview))
+        },
+        DataImportBackendRequest::GenerateCode(params) => Ok(
+            DataImportBackendReply::GenerateCodeReply(generate_code(&params)),
+        ),
+    }
+}
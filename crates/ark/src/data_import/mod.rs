@@ -0,0 +1,11 @@
+//
+// mod.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+pub mod codegen;
+pub mod comm;
+pub mod parse;
+pub mod remote;
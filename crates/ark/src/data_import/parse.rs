@@ -0,0 +1,599 @@
+//
+// parse.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use amalthea::comm::data_import_comm::ColumnType;
+use amalthea::comm::data_import_comm::ImportColumn;
+use amalthea::comm::data_import_comm::ImportOptions;
+use amalthea::comm::data_import_comm::ImportPreview;
+use anyhow::anyhow;
+use regex::Regex;
+
+use crate::data_import::remote;
+
+/// Number of bytes read from the beginning of a file. Sniffing and previews
+/// only look at this sample so that they stay fast on large and remote files.
+const SAMPLE_SIZE: usize = 256 * 1024;
+
+/// Number of records used to guess the delimiter and the header
+const SNIFF_RECORDS: usize = 100;
+
+const DEFAULT_PREVIEW_ROWS: usize = 100;
+const MAX_PREVIEW_ROWS: usize = 1000;
+
+/// Delimiters tried when sniffing, in order of preference for ties
+const DELIMITERS: &[char] = &[',', '\t', ';', '|'];
+
+static RE_INTEGER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[-+]?\d+$").unwrap());
+static RE_DOUBLE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[-+]?(\d+\.?\d*|\.\d+)([eE][-+]?\d+)?$|^[-+]?Inf$|^NaN$").unwrap()
+});
+static RE_DATE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap());
+static RE_DATETIME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(:\d{2}(\.\d+)?)?(Z|[-+]\d{2}:?\d{2})?$").unwrap()
+});
+
+/// Decoded beginning of a file
+pub(crate) struct Sample {
+    pub text: String,
+    pub encoding: &'static str,
+
+    /// Whether the file is longer than the sample
+    pub truncated: bool,
+}
+
+/// Read the beginning of a file, given by its path or its http(s) URL
+pub(crate) fn read_sample(path: &str, encoding: Option<&str>) -> anyhow::Result<Sample> {
+    let (bytes, truncated) = if remote::is_url(path) {
+        remote::fetch_head(path, SAMPLE_SIZE)?
+    } else {
+        read_head(Path::new(path), SAMPLE_SIZE)?
+    };
+
+    let (mut text, encoding) = match encoding {
+        Some(encoding) => (decode(&bytes, encoding)?, encoding_name(encoding)?),
+        None => detect_encoding(&bytes, truncated),
+    };
+
+    // Drop the last line since it may have been cut in the middle
+    if truncated {
+        if let Some(end) = text.rfind('\n') {
+            text.truncate(end + 1);
+        }
+    }
+
+    Ok(Sample {
+        text,
+        encoding,
+        truncated,
+    })
+}
+
+fn read_head(path: &Path, size: usize) -> anyhow::Result<(Vec<u8>, bool)> {
+    let file = File::open(path)?;
+    let truncated = file.metadata()?.len() > size as u64;
+
+    let mut bytes = Vec::new();
+    file.take(size as u64).read_to_end(&mut bytes)?;
+
+    Ok((bytes, truncated))
+}
+
+/// Guess the encoding from byte order marks and UTF-8 validity. Files that
+/// aren't valid UTF-8 are assumed to be latin1, which can decode any bytes.
+/// When the sample is `truncated`, it may end in the middle of a character.
+fn detect_encoding(bytes: &[u8], truncated: bool) -> (String, &'static str) {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return (decode_utf8(&bytes[3..]), "UTF-8-BOM");
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return (decode_utf16(&bytes[2..], u16::from_le_bytes), "UTF-16LE");
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return (decode_utf16(&bytes[2..], u16::from_be_bytes), "UTF-16BE");
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (String::from(text), "UTF-8"),
+        Err(err) if truncated && err.error_len().is_none() => (decode_utf8(bytes), "UTF-8"),
+        Err(_) => (decode_latin1(bytes), "latin1"),
+    }
+}
+
+fn encoding_name(encoding: &str) -> anyhow::Result<&'static str> {
+    match encoding.to_uppercase().as_str() {
+        "UTF-8" | "UTF8" => Ok("UTF-8"),
+        "UTF-8-BOM" => Ok("UTF-8-BOM"),
+        "LATIN1" | "ISO-8859-1" => Ok("latin1"),
+        "UTF-16LE" => Ok("UTF-16LE"),
+        "UTF-16BE" => Ok("UTF-16BE"),
+        _ => Err(anyhow!("Unsupported encoding '{encoding}'")),
+    }
+}
+
+fn decode(bytes: &[u8], encoding: &str) -> anyhow::Result<String> {
+    let text = match encoding_name(encoding)? {
+        "UTF-8" => decode_utf8(bytes),
+        "UTF-8-BOM" => decode_utf8(bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes)),
+        "latin1" => decode_latin1(bytes),
+        "UTF-16LE" => decode_utf16(
+            bytes.strip_prefix(&[0xFF, 0xFE]).unwrap_or(bytes),
+            u16::from_le_bytes,
+        ),
+        "UTF-16BE" => decode_utf16(
+            bytes.strip_prefix(&[0xFE, 0xFF]).unwrap_or(bytes),
+            u16::from_be_bytes,
+        ),
+        _ => unreachable!(),
+    };
+    Ok(text)
+}
+
+fn decode_utf8(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| *byte as char).collect()
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|char| char.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Split delimited text into records of fields
+///
+/// Quoted fields may contain delimiters, newlines, and doubled quotes. Blank
+/// lines are skipped. Stops after `max` records.
+pub(crate) fn parse_records(
+    text: &str,
+    delimiter: char,
+    quote: Option<char>,
+    max: usize,
+) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        if records.len() == max {
+            return records;
+        }
+
+        if in_quotes {
+            if Some(char) == quote {
+                if chars.peek() == Some(&char) {
+                    field.push(char);
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(char);
+            }
+            continue;
+        }
+
+        match char {
+            _ if Some(char) == quote && field.is_empty() => in_quotes = true,
+            _ if char == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {},
+            '\n' | '\r' => {
+                if record.is_empty() && field.is_empty() {
+                    continue;
+                }
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            },
+            _ => field.push(char),
+        }
+    }
+
+    if (!record.is_empty() || !field.is_empty()) && records.len() < max {
+        record.push(field);
+        records.push(record);
+    }
+
+    records
+}
+
+/// Guess plausible options to parse `text`, best candidates first
+///
+/// Delimiters are ranked by how consistently they split records into the
+/// same number of fields, then by that number of fields.
+pub(crate) fn sniff(text: &str, encoding: &str) -> Vec<ImportOptions> {
+    let mut candidates = Vec::new();
+
+    for delimiter in DELIMITERS {
+        let records = parse_records(text, *delimiter, Some('"'), SNIFF_RECORDS);
+        let Some(n_fields) = mode(records.iter().map(|record| record.len())) else {
+            continue;
+        };
+        if n_fields < 2 {
+            continue;
+        }
+
+        let n_consistent = records
+            .iter()
+            .filter(|record| record.len() == n_fields)
+            .count();
+        let consistency = n_consistent as f64 / records.len() as f64;
+
+        let options = ImportOptions {
+            delimiter: delimiter.to_string(),
+            header: guess_header(&records),
+            quote: String::from("\""),
+            encoding: String::from(encoding),
+            column_types: None,
+        };
+        candidates.push((consistency, n_fields, options));
+    }
+
+    // Stable sort so that ties keep the order of `DELIMITERS`
+    candidates.sort_by(|(c1, n1, _), (c2, n2, _)| c2.total_cmp(c1).then(n2.cmp(n1)));
+
+    let mut candidates: Vec<ImportOptions> = candidates
+        .into_iter()
+        .map(|(_, _, options)| options)
+        .collect();
+
+    // A single column of data
+    if candidates.is_empty() {
+        candidates.push(ImportOptions {
+            delimiter: String::from(","),
+            header: true,
+            quote: String::from("\""),
+            encoding: String::from(encoding),
+            column_types: None,
+        });
+    }
+
+    candidates
+}
+
+/// Most frequent value, the largest one in case of ties
+fn mode(values: impl Iterator<Item = usize>) -> Option<usize> {
+    let mut counts = std::collections::BTreeMap::<usize, usize>::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(v1, n1), (v2, n2)| n1.cmp(n2).then(v1.cmp(v2)))
+        .map(|(value, _)| value)
+}
+
+/// The first record is a header unless its values have the types of the
+/// values below. When all columns are character, we can't tell and assume a
+/// header like `read_csv()` does.
+fn guess_header(records: &[Vec<String>]) -> bool {
+    let [first, rest @ ..] = records else {
+        return true;
+    };
+    if rest.is_empty() {
+        return true;
+    }
+
+    let types = infer_types(rest, first.len());
+    let typed: Vec<(&String, &ColumnType)> = first
+        .iter()
+        .zip(types.iter())
+        .filter(|(_, column_type)| **column_type != ColumnType::Character)
+        .collect();
+
+    if typed.is_empty() {
+        return true;
+    }
+
+    typed
+        .into_iter()
+        .any(|(value, column_type)| !is_missing(value) && !matches_type(value, column_type))
+}
+
+/// Guess the type of each of the first `n_columns` columns
+pub(crate) fn infer_types(records: &[Vec<String>], n_columns: usize) -> Vec<ColumnType> {
+    (0..n_columns)
+        .map(|i| {
+            let values = records
+                .iter()
+                .filter_map(|record| record.get(i))
+                .map(|value| value.as_str());
+            infer_type(values)
+        })
+        .collect()
+}
+
+fn infer_type<'a>(values: impl Iterator<Item = &'a str>) -> ColumnType {
+    let mut out: Option<ColumnType> = None;
+
+    for value in values {
+        if is_missing(value) {
+            continue;
+        }
+        let column_type = value_type(value);
+        out = Some(match out {
+            None => column_type,
+            Some(out) => merge_types(out, column_type),
+        });
+        if out == Some(ColumnType::Character) {
+            break;
+        }
+    }
+
+    // Like `read_csv()`, columns of missing values are logical
+    out.unwrap_or(ColumnType::Logical)
+}
+
+fn is_missing(value: &str) -> bool {
+    value.is_empty() || value == "NA"
+}
+
+fn value_type(value: &str) -> ColumnType {
+    if matches!(
+        value,
+        "TRUE" | "FALSE" | "T" | "F" | "true" | "false" | "True" | "False"
+    ) {
+        ColumnType::Logical
+    } else if RE_INTEGER.is_match(value) && value.parse::<i32>().is_ok() {
+        ColumnType::Integer
+    } else if RE_DOUBLE.is_match(value) {
+        ColumnType::Double
+    } else if RE_DATE.is_match(value) {
+        ColumnType::Date
+    } else if RE_DATETIME.is_match(value) {
+        ColumnType::Datetime
+    } else {
+        ColumnType::Character
+    }
+}
+
+fn merge_types(x: ColumnType, y: ColumnType) -> ColumnType {
+    use ColumnType::*;
+
+    match (x, y) {
+        (x, y) if x == y => x,
+        (Integer, Double) | (Double, Integer) => Double,
+        (Date, Datetime) | (Datetime, Date) => Datetime,
+        _ => Character,
+    }
+}
+
+fn matches_type(value: &str, column_type: &ColumnType) -> bool {
+    let value_type = value_type(value);
+    value_type == *column_type || merge_types(value_type, column_type.clone()) == *column_type
+}
+
+/// Parse the beginning of a file, given by its path or its http(s) URL.
+/// Options are sniffed when not supplied.
+pub(crate) fn preview(
+    path: &str,
+    options: Option<ImportOptions>,
+    max_rows: Option<i64>,
+) -> anyhow::Result<ImportPreview> {
+    let max_rows = max_rows
+        .map(|n| (n.max(0) as usize).min(MAX_PREVIEW_ROWS))
+        .unwrap_or(DEFAULT_PREVIEW_ROWS);
+
+    let sample = read_sample(
+        path,
+        options.as_ref().map(|options| options.encoding.as_str()),
+    )?;
+
+    let options = match options {
+        Some(options) => options,
+        None => sniff(&sample.text, sample.encoding).swap_remove(0),
+    };
+
+    let delimiter = single_char(&options.delimiter)
+        .ok_or_else(|| anyhow!("The delimiter must be a single character"))?;
+    let quote = match options.quote.as_str() {
+        "" => None,
+        quote => Some(
+            single_char(quote).ok_or_else(|| anyhow!("The quote must be a single character"))?,
+        ),
+    };
+
+    // Parse one more row than needed to know whether there are more
+    let n_records = max_rows + options.header as usize + 1;
+    let mut records = parse_records(&sample.text, delimiter, quote, n_records);
+
+    let header = if options.header && !records.is_empty() {
+        Some(records.remove(0))
+    } else {
+        None
+    };
+
+    let truncated = records.len() > max_rows || sample.truncated;
+    records.truncate(max_rows);
+
+    let n_columns = records
+        .iter()
+        .chain(header.iter())
+        .map(|record| record.len())
+        .max()
+        .unwrap_or(0);
+
+    for record in records.iter_mut() {
+        record.resize(n_columns, String::new());
+    }
+
+    let column_types = match &options.column_types {
+        Some(column_types) if column_types.len() != n_columns => {
+            return Err(anyhow!(
+                "Expected {n_columns} column types, got {}",
+                column_types.len()
+            ));
+        },
+        Some(column_types) => column_types.clone(),
+        None => infer_types(&records, n_columns),
+    };
+
+    let columns = column_types
+        .into_iter()
+        .enumerate()
+        .map(|(i, column_type)| {
+            let name = header
+                .as_ref()
+                .and_then(|header| header.get(i))
+                .filter(|name| !name.is_empty())
+                .cloned()
+                .unwrap_or_else(|| format!("X{}", i + 1));
+            ImportColumn { name, column_type }
+        })
+        .collect();
+
+    Ok(ImportPreview {
+        options,
+        columns,
+        rows: records,
+        truncated,
+    })
+}
+
+fn single_char(x: &str) -> Option<char> {
+    let mut chars = x.chars();
+    match (chars.next(), chars.next()) {
+        (Some(char), None) => Some(char),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use amalthea::comm::data_import_comm::ColumnType;
+
+    use crate::data_import::parse::detect_encoding;
+    use crate::data_import::parse::guess_header;
+    use crate::data_import::parse::infer_types;
+    use crate::data_import::parse::parse_records;
+    use crate::data_import::parse::preview;
+    use crate::data_import::parse::sniff;
+
+    fn records(x: &[&[&str]]) -> Vec<Vec<String>> {
+        x.iter()
+            .map(|record| record.iter().map(|field| field.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let text = "a,b\n1,\"x, \"\"y\"\"\"\r\n\n2,\"multi\nline\"\n3";
+        assert_eq!(
+            parse_records(text, ',', Some('"'), 10),
+            records(&[&["a", "b"], &["1", "x, \"y\""], &["2", "multi\nline"], &[
+                "3"
+            ]])
+        );
+
+        // Stops after `max` records
+        assert_eq!(
+            parse_records(text, ',', Some('"'), 1),
+            records(&[&["a", "b"]])
+        );
+
+        // Without quoting
+        assert_eq!(
+            parse_records("\"a\";b", ';', None, 10),
+            records(&[&["\"a\"", "b"]])
+        );
+    }
+
+    #[test]
+    fn test_sniff_delimiter() {
+        let options = sniff("a;b;c\n1,5;2;3\n4;5;6\n", "UTF-8");
+        assert_eq!(options[0].delimiter, ";");
+        assert!(options[0].header);
+
+        let options = sniff("x\ty\n1\t2\n", "UTF-8");
+        assert_eq!(options[0].delimiter, "\t");
+
+        // Single column
+        let options = sniff("x\n1\n2\n", "UTF-8");
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].delimiter, ",");
+    }
+
+    #[test]
+    fn test_guess_header() {
+        assert!(guess_header(&records(&[&["x", "y"], &["1", "2"]])));
+        assert!(!guess_header(&records(&[&["1", "2"], &["3", "4"]])));
+
+        // All character: assume a header
+        assert!(guess_header(&records(&[&["a", "b"], &["c", "d"]])));
+    }
+
+    #[test]
+    fn test_infer_types() {
+        let types = infer_types(
+            &records(&[&["1", "1.5", "TRUE", "2024-01-01", "a", ""], &[
+                "NA",
+                "2",
+                "F",
+                "2024-01-02 10:00",
+                "1",
+                "",
+            ]]),
+            6,
+        );
+        assert_eq!(types, vec![
+            ColumnType::Integer,
+            ColumnType::Double,
+            ColumnType::Logical,
+            ColumnType::Datetime,
+            ColumnType::Character,
+            ColumnType::Logical,
+        ]);
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(detect_encoding(b"caf\xC3\xA9", false).1, "UTF-8");
+        assert_eq!(detect_encoding(b"\xEF\xBB\xBFx", false).0, "x");
+        assert_eq!(
+            detect_encoding(b"caf\xE9", false),
+            (String::from("café"), "latin1")
+        );
+
+        // Character cut at the end of the sample
+        assert_eq!(detect_encoding(b"caf\xC3", true).1, "UTF-8");
+        assert_eq!(detect_encoding(b"caf\xC3", false).1, "latin1");
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "name,value\na,1\nb,2\nc\n").unwrap();
+
+        let path = file.path().to_str().unwrap();
+
+        let preview = preview(path, None, Some(2)).unwrap();
+        assert_eq!(preview.options.delimiter, ",");
+        assert_eq!(preview.columns.len(), 2);
+        assert_eq!(preview.columns[0].name, "name");
+        assert_eq!(preview.columns[1].column_type, ColumnType::Integer);
+        assert_eq!(preview.rows, records(&[&["a", "1"], &["b", "2"]]));
+        assert!(preview.truncated);
+
+        // Short rows are padded
+        let preview = super::preview(path, None, None).unwrap();
+        assert_eq!(preview.rows[2], vec!["c", ""]);
+        assert!(!preview.truncated);
+    }
+}
@@ -0,0 +1,114 @@
+//
+// remote.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::io::Read;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use reqwest::header::RANGE;
+
+/// Time allowed to fetch the beginning of a remote file, including the
+/// connection. A slow server fails the preview rather than hanging the wizard.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Whether `path` is an http(s) URL rather than a local path
+pub(crate) fn is_url(path: &str) -> bool {
+    let path = path.trim_start().to_lowercase();
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Fetch the first `size` bytes of a remote file, without downloading the
+/// rest. Returns whether the file is longer than that.
+///
+/// A range is requested so that servers that support it only send the
+/// beginning, and the body is dropped after `size` bytes otherwise. The
+/// proxies of the `HTTP_PROXY`, `HTTPS_PROXY`, and `NO_PROXY` environment
+/// variables are honored, like R's `download.file()` does.
+pub(crate) fn fetch_head(url: &str, size: usize) -> anyhow::Result<(Vec<u8>, bool)> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?;
+
+    // One more byte than needed tells whether the file is longer
+    let response = client
+        .get(url)
+        .header(RANGE, format!("bytes=0-{size}"))
+        .send()?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("Can't fetch '{url}': {status}"));
+    }
+
+    let mut bytes = Vec::new();
+    response.take(size as u64 + 1).read_to_end(&mut bytes)?;
+
+    let truncated = bytes.len() > size;
+    bytes.truncate(size);
+
+    Ok((bytes, truncated))
+}
+
+/// Path of a URL without its query and fragment, from which the file name
+/// and extension are derived
+pub(crate) fn url_path(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(url) => url.path().to_string(),
+        Err(_) => String::from(url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use crate::data_import::remote::fetch_head;
+    use crate::data_import::remote::is_url;
+    use crate::data_import::remote::url_path;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/data.csv"));
+        assert!(is_url("HTTP://example.com/data.csv"));
+        assert!(!is_url("ftp://example.com/data.csv"));
+        assert!(!is_url("data/https.csv"));
+
+        assert_eq!(
+            url_path("https://example.com/data/sales.csv?raw=true#top"),
+            "/data/sales.csv"
+        );
+    }
+
+    #[test]
+    fn test_fetch_head() {
+        // A server that ignores the range and sends the whole file
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+
+            let body = "x,y\n".repeat(100);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        });
+
+        let url = format!("http://127.0.0.1:{port}/data.csv");
+        let (bytes, truncated) = fetch_head(&url, 10).unwrap();
+        assert_eq!(bytes, b"x,y\nx,y\nx,");
+        assert!(truncated);
+
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+//
+// export_object.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::fmt::Display;
+
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use libr::SEXP;
+use serde::de::DeserializeOwned;
+
+/// Save an object to a file so that it can be used outside of the session,
+/// reporting progress to the frontend. Shared by the `export_object` methods
+/// of the variables and data explorer comms.
+///
+/// - `format`: One of the `ExportObjectFormat` enums of the comms. Defaults
+///   to the preferred format for the class of `x`, see `.ps.export.formats()`.
+/// - `title`: Describes the object in progress reports, e.g. its name.
+///
+/// Returns the format the object was written in. Must be called on the R
+/// thread.
+pub fn export_object<F>(x: SEXP, file: &str, format: Option<F>, title: &str) -> anyhow::Result<F>
+where
+    F: Display + DeserializeOwned,
+{
+    let mut call = RFunction::from(".ps.export.object");
+    call.add(x).add(file).param("title", title);
+
+    if let Some(format) = format {
+        call.param("format", format.to_string());
+    }
+
+    let format: String = call.call()?.try_into()?;
+    Ok(serde_json::from_value(serde_json::Value::String(format))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use amalthea::comm::variables_comm::ExportObjectFormat;
+    use harp::cancellation::with_cancellation;
+    use stdext::cancellation::CancellationToken;
+
+    use crate::export_object::export_object;
+    use crate::r_task;
+
+    #[test]
+    fn test_export_object() {
+        r_task(|| {
+            let dir = tempfile::tempdir().unwrap();
+
+            let df = harp::parse_eval_base("data.frame(x = 1:3, y = c('a', 'b', NA))").unwrap();
+            let file = dir.path().join("df.csv");
+            let file = file.to_str().unwrap();
+
+            let format = export_object(df.sexp, file, Some(ExportObjectFormat::Csv), "df").unwrap();
+            assert_eq!(format, ExportObjectFormat::Csv);
+            assert_eq!(
+                std::fs::read_to_string(file).unwrap(),
+                "\"x\",\"y\"\n1,\"a\"\n2,\"b\"\n3,\n"
+            );
+
+            // Objects that aren't tabular are serialized to RDS
+            let fit = harp::parse_eval_base("list(a = 1, b = list(2))").unwrap();
+            let file = dir.path().join("fit.rds");
+            let file = file.to_str().unwrap();
+
+            let format = export_object(fit.sexp, file, None::<ExportObjectFormat>, "fit").unwrap();
+            assert_eq!(format, ExportObjectFormat::Rds);
+            assert!(std::path::Path::new(file).exists());
+
+            // Unsupported formats are an error and leave no file behind
+            let file = dir.path().join("fit.csv");
+            let file = file.to_str().unwrap();
+            assert!(export_object(fit.sexp, file, Some(ExportObjectFormat::Csv), "fit").is_err());
+            assert!(!std::path::Path::new(file).exists());
+        })
+    }
+
+    #[test]
+    fn test_export_object_cancelled() {
+        r_task(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let file = dir.path().join("df.csv");
+            let file = file.to_str().unwrap();
+
+            let df = harp::parse_eval_base("data.frame(x = 1:3)").unwrap();
+
+            // Closing the comm cancels the export and removes the partial file
+            let token = CancellationToken::new();
+            token.cancel();
+            let result = with_cancellation(&token, || {
+                export_object(df.sexp, file, Some(ExportObjectFormat::Csv), "df")
+            });
+            assert!(result.is_err());
+            assert!(!std::path::Path::new(file).exists());
+        })
+    }
+}
@@ -0,0 +1,42 @@
+//
+// features.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use amalthea::features;
+use amalthea::features::Feature;
+use amalthea::features::Stage;
+use harp::object::RObject;
+use libr::SEXP;
+
+/// Cut short event loop callbacks caught in an output loop, see `HookGuard`
+pub const HOOK_GUARD: Feature = Feature {
+    name: "hook_guard",
+    description: "Disable event loop callbacks caught in an output loop",
+    stage: Stage::Stable,
+    default: true,
+};
+
+/// Provide semantic tokens to the editor, see `semantic_tokens.rs`. Checked
+/// when the LSP is initialized.
+pub const SEMANTIC_TOKENS: Feature = Feature {
+    name: "semantic_tokens",
+    description: "Highlight functions, parameters, methods, and namespaces in the editor",
+    stage: Stage::Stable,
+    default: true,
+};
+
+/// Register the features of Ark along with Amalthea's. Must be called before
+/// overrides are set so that typos in feature names can be reported.
+pub fn register() {
+    features::register(&[&HOOK_GUARD, &SEMANTIC_TOKENS]);
+}
+
+/// State of all features, for `.ps.rpc.features()`
+#[harp::register]
+pub unsafe extern "C" fn ps_features() -> anyhow::Result<SEXP> {
+    let status = serde_json::to_value(features::status())?;
+    Ok(RObject::from_json(status)?.sexp)
+}
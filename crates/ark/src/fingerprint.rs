@@ -0,0 +1,105 @@
+//
+// fingerprint.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// A fingerprint identifies the package environment of the session, so that
+// saved notebooks can be matched to the environment they ran in. Projects
+// locked with renv are identified by the hash of their `renv.lock` file, which
+// doesn't change as packages get loaded. Other sessions are identified by the
+// hash of the versions of R and of the loaded packages.
+//
+// The fingerprint is included in the `kernel_info_reply` when
+// `environment_fingerprint` is set in the `[startup]` section of the config
+// file, and is available at any time with the `environment_fingerprint` RPC.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use amalthea::wire::language_info::EnvironmentFingerprint;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::object::RObject;
+use harp::utils::r_is_null;
+use libr::SEXP;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::modules::ARK_ENVS;
+
+/// Fingerprint of the current environment. Must be called on the R thread.
+pub(crate) fn environment_fingerprint() -> anyhow::Result<EnvironmentFingerprint> {
+    let lockfile =
+        RFunction::new("", "environment_fingerprint_lockfile").call_in(ARK_ENVS.positron_ns)?;
+
+    if !r_is_null(lockfile.sexp) {
+        let lockfile: String = lockfile.try_into()?;
+        return lockfile_fingerprint(Path::new(&lockfile));
+    }
+
+    let packages: Vec<String> = RFunction::new("", "environment_fingerprint_packages")
+        .call_in(ARK_ENVS.positron_ns)?
+        .try_into()?;
+
+    Ok(EnvironmentFingerprint {
+        source: String::from("packages"),
+        hash: sha256(packages.join("\n").as_bytes()),
+    })
+}
+
+fn lockfile_fingerprint(path: &Path) -> anyhow::Result<EnvironmentFingerprint> {
+    let contents = std::fs::read(path)?;
+
+    Ok(EnvironmentFingerprint {
+        source: String::from("renv.lock"),
+        hash: sha256(&contents),
+    })
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(bytes)))
+}
+
+#[harp::register]
+pub unsafe extern "C" fn ps_environment_fingerprint() -> anyhow::Result<SEXP> {
+    let fingerprint = environment_fingerprint()?;
+
+    let out = HashMap::from([
+        (String::from("source"), fingerprint.source),
+        (String::from("hash"), fingerprint.hash),
+    ]);
+    Ok(RObject::from(out).sexp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::fingerprint::environment_fingerprint;
+    use crate::fingerprint::lockfile_fingerprint;
+    use crate::r_task;
+
+    #[test]
+    fn test_environment_fingerprint() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "{{}}").unwrap();
+
+        let fingerprint = lockfile_fingerprint(file.path()).unwrap();
+        assert_eq!(fingerprint.source, "renv.lock");
+        assert_eq!(
+            fingerprint.hash,
+            "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+
+        r_task(|| {
+            let fingerprint = environment_fingerprint().unwrap();
+            assert_eq!(fingerprint.source, "packages");
+            assert!(fingerprint.hash.starts_with("sha256:"));
+
+            // Stable as long as no package is loaded
+            assert_eq!(environment_fingerprint().unwrap(), fingerprint);
+        })
+    }
+}
@@ -0,0 +1,76 @@
+//
+// fixtures/comm.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use amalthea::comm::comm_channel::CommMsg;
+use amalthea::socket::comm::CommInitiator;
+use amalthea::socket::comm::CommSocket;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::wait;
+
+/// Create a comm socket as if the comm had been opened by the frontend.
+///
+/// Pass the socket to the `start()` method of the comm under test, then drive
+/// it with `socket_rpc_request()` and `socket_recv_msg()`.
+pub fn frontend_comm_socket(comm_name: &str) -> CommSocket {
+    CommSocket::new(
+        CommInitiator::FrontEnd,
+        uuid::Uuid::new_v4().to_string(),
+        String::from(comm_name),
+    )
+}
+
+/// Send an RPC to a comm and wait for its reply
+pub fn socket_rpc_request<RequestType, ReplyType>(
+    socket: &CommSocket,
+    req: RequestType,
+) -> ReplyType
+where
+    RequestType: Serialize,
+    ReplyType: DeserializeOwned,
+{
+    // Randomly generate a unique ID for this request.
+    let id = uuid::Uuid::new_v4().to_string();
+
+    // Serialize the message for the wire
+    let json = serde_json::to_value(req).unwrap();
+    println!("--> {:?}", json);
+
+    // Convert the request to a CommMsg and send it.
+    let msg = CommMsg::Rpc(id, json);
+    socket.incoming_tx.send(msg).unwrap();
+    let msg = socket_recv_msg(socket);
+
+    // Extract the reply from the CommMsg.
+    match msg {
+        CommMsg::Rpc(_id, value) => {
+            println!("<-- {:?}", value);
+            serde_json::from_value(value).unwrap()
+        },
+        _ => panic!("Unexpected Comm Message"),
+    }
+}
+
+/// Receive the next message sent by a comm to the frontend, e.g. an event.
+/// Panics if the comm doesn't send anything within a second.
+pub fn socket_recv_msg(socket: &CommSocket) -> CommMsg {
+    wait::COMM_MESSAGE
+        .recv(&socket.outgoing_rx)
+        .expect("Comm should send a message")
+}
+
+/// Receive the next event sent by a comm to the frontend and deserialize it
+pub fn socket_recv_event<EventType>(socket: &CommSocket) -> EventType
+where
+    EventType: DeserializeOwned,
+{
+    match socket_recv_msg(socket) {
+        CommMsg::Data(value) => serde_json::from_value(value).unwrap(),
+        msg => panic!("Expected comm event, got {msg:?}"),
+    }
+}
@@ -29,6 +29,8 @@ struct DummyArkFrontendOptions {
     user_r_profile: bool,
     r_environ: bool,
     session_mode: SessionMode,
+    read_only: bool,
+    shared_session: bool,
 }
 
 /// Wrapper around `DummyArkFrontend` that uses `SessionMode::Notebook`
@@ -46,6 +48,17 @@ pub struct DummyArkFrontendRprofile {
     inner: DummyArkFrontend,
 }
 
+/// Wrapper around `DummyArkFrontend` that starts a read-only session
+pub struct DummyArkFrontendReadOnly {
+    inner: DummyArkFrontend,
+}
+
+/// Wrapper around `DummyArkFrontend` that starts a shared session driven by
+/// the frontend. Use `send_shell_from_session()` to act as an observer.
+pub struct DummyArkFrontendShared {
+    inner: DummyArkFrontend,
+}
+
 impl DummyArkFrontend {
     pub fn lock() -> Self {
         Self {
@@ -75,6 +88,10 @@ impl DummyArkFrontend {
         let connection = DummyConnection::new();
         let (connection_file, registration_file) = connection.get_connection_files();
 
+        let driver_session = options
+            .shared_session
+            .then(|| connection.session.session_id.clone());
+
         let mut r_args = vec![];
 
         // We aren't animals!
@@ -105,6 +122,8 @@ impl DummyArkFrontend {
                 None,
                 options.session_mode,
                 false,
+                options.read_only,
+                driver_session,
             );
         });
 
@@ -216,6 +235,78 @@ impl DerefMut for DummyArkFrontendRprofile {
     }
 }
 
+impl DummyArkFrontendReadOnly {
+    /// Lock a frontend connected to a read-only session.
+    ///
+    /// NOTE: Only one `DummyArkFrontend` variant should call `lock()` within
+    /// a given process.
+    pub fn lock() -> Self {
+        Self::init();
+
+        Self {
+            inner: DummyArkFrontend::lock(),
+        }
+    }
+
+    /// Initialize with code execution disabled
+    fn init() {
+        let mut options = DummyArkFrontendOptions::default();
+        options.read_only = true;
+        FRONTEND.get_or_init(|| Arc::new(Mutex::new(DummyArkFrontend::init(options))));
+    }
+}
+
+// Allow method calls to be forwarded to inner type
+impl Deref for DummyArkFrontendReadOnly {
+    type Target = DummyFrontend;
+
+    fn deref(&self) -> &Self::Target {
+        Deref::deref(&self.inner)
+    }
+}
+
+impl DerefMut for DummyArkFrontendReadOnly {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        DerefMut::deref_mut(&mut self.inner)
+    }
+}
+
+impl DummyArkFrontendShared {
+    /// Lock a frontend connected to a shared session.
+    ///
+    /// NOTE: Only one `DummyArkFrontend` variant should call `lock()` within
+    /// a given process.
+    pub fn lock() -> Self {
+        Self::init();
+
+        Self {
+            inner: DummyArkFrontend::lock(),
+        }
+    }
+
+    /// Initialize with other clients allowed to observe the session
+    fn init() {
+        let mut options = DummyArkFrontendOptions::default();
+        options.shared_session = true;
+        FRONTEND.get_or_init(|| Arc::new(Mutex::new(DummyArkFrontend::init(options))));
+    }
+}
+
+// Allow method calls to be forwarded to inner type
+impl Deref for DummyArkFrontendShared {
+    type Target = DummyFrontend;
+
+    fn deref(&self) -> &Self::Target {
+        Deref::deref(&self.inner)
+    }
+}
+
+impl DerefMut for DummyArkFrontendShared {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        DerefMut::deref_mut(&mut self.inner)
+    }
+}
+
 impl Default for DummyArkFrontendOptions {
     fn default() -> Self {
         Self {
@@ -224,6 +315,8 @@ impl Default for DummyArkFrontendOptions {
             user_r_profile: false,
             r_environ: false,
             session_mode: SessionMode::Console,
+            read_only: false,
+            shared_session: false,
         }
     }
 }
@@ -1,5 +1,21 @@
+//
+// fixtures/mod.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Test support for Ark and downstream crates. See `doc/testing.md`.
+//
+// - `r_task()` and `r_test_init()` run code against an R session initialized
+//   once per process.
+// - The comm fixtures drive a comm without a kernel.
+// - `DummyArkFrontend` starts the full kernel and talks to it over ZeroMQ.
+
+pub mod comm;
 pub mod dummy_frontend;
 pub mod utils;
 
+pub use comm::*;
 pub use dummy_frontend::*;
 pub use utils::*;
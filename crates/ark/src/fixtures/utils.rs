@@ -9,10 +9,6 @@ use std::sync::Mutex;
 use std::sync::MutexGuard;
 use std::sync::Once;
 
-use amalthea::comm::comm_channel::CommMsg;
-use amalthea::socket;
-use serde::de::DeserializeOwned;
-use serde::Serialize;
 use tree_sitter::Point;
 
 use crate::modules;
@@ -27,7 +23,10 @@ pub fn r_test_lock() -> MutexGuard<'static, ()> {
 
 static INIT: Once = Once::new();
 
-pub(crate) fn r_test_init() {
+/// Initialize R and the Positron modules for tests, once per process.
+///
+/// Prefer `r_task()`, which takes the R test lock and calls this for you.
+pub fn r_test_init() {
     harp::fixtures::r_test_init();
     INIT.call_once(|| {
         // Initialize the positron module so tests can use them.
@@ -58,40 +57,6 @@ pub fn point_from_cursor(x: &str) -> (String, Point) {
     panic!("`x` must include a `@` character!");
 }
 
-pub fn socket_rpc_request<'de, RequestType, ReplyType>(
-    socket: &socket::comm::CommSocket,
-    req: RequestType,
-) -> ReplyType
-where
-    RequestType: Serialize,
-    ReplyType: DeserializeOwned,
-{
-    // Randomly generate a unique ID for this request.
-    let id = uuid::Uuid::new_v4().to_string();
-
-    // Serialize the message for the wire
-    let json = serde_json::to_value(req).unwrap();
-    println!("--> {:?}", json);
-
-    // Convert the request to a CommMsg and send it.
-    let msg = CommMsg::Rpc(id, json);
-    socket.incoming_tx.send(msg).unwrap();
-    let msg = socket
-        .outgoing_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
-
-    // Extract the reply from the CommMsg.
-    match msg {
-        CommMsg::Rpc(_id, value) => {
-            println!("<-- {:?}", value);
-            let reply = serde_json::from_value(value).unwrap();
-            reply
-        },
-        _ => panic!("Unexpected Comm Message"),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use tree_sitter::Point;
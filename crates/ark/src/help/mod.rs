@@ -7,3 +7,4 @@
 
 pub mod message;
 pub mod r_help;
+pub mod sanitize;
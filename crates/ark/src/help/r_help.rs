@@ -18,6 +18,8 @@ use crossbeam::channel::Sender;
 use crossbeam::select;
 use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
+use libr::Rf_ScalarLogical;
+use libr::SEXP;
 use log::info;
 use log::trace;
 use log::warn;
@@ -25,7 +27,8 @@ use stdext::spawn;
 
 use crate::help::message::HelpEvent;
 use crate::help::message::ShowHelpUrlParams;
-use crate::r_task;
+use crate::interface::RMain;
+use crate::r_task::r_task_cancellable;
 
 /**
  * The R Help handler (together with the help proxy) provides the server side of
@@ -215,7 +218,8 @@ impl RHelp {
 
     #[tracing::instrument(level = "trace", skip(self))]
     fn show_help_topic(&self, topic: String) -> anyhow::Result<bool> {
-        let found = r_task(|| unsafe {
+        // Stop waiting for R if the help pane is closed in the meantime
+        let found = r_task_cancellable(self.comm.closed.clone(), || unsafe {
             RFunction::from(".ps.help.showHelpTopic")
                 .add(topic)
                 .call()?
@@ -232,3 +236,11 @@ impl RHelp {
             .and_then(|x| x.try_into())
     }
 }
+
+/// Is a help comm connected to display HTML help? If not, help is shown as
+/// plain text in the console.
+#[harp::register]
+pub unsafe extern "C" fn ps_help_is_connected() -> anyhow::Result<SEXP> {
+    let connected = RMain::is_initialized() && RMain::with(|main| main.is_help_connected());
+    Ok(Rf_ScalarLogical(connected as i32))
+}
@@ -0,0 +1,364 @@
+//
+// sanitize.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::sync::LazyLock;
+
+use ego_tree::NodeRef;
+use regex::Regex;
+use scraper::node::Element;
+use scraper::Html;
+use scraper::Node;
+use url::Url;
+
+/// Content Security Policy sent with proxied help pages. Blocks whatever the
+/// sanitizer might have missed from loading remote resources, while still
+/// allowing the resources served by the help server itself.
+pub const HELP_CONTENT_SECURITY_POLICY: &str =
+    "default-src 'self' 'unsafe-inline' data:; object-src 'none'; base-uri 'none'; form-action 'none'";
+
+/// Elements dropped along with their contents
+const BLOCKED_ELEMENTS: &[&str] = &[
+    "applet", "base", "embed", "form", "frame", "frameset", "iframe", "noscript", "object",
+    "portal", "template",
+];
+
+/// Elements without contents or end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "br", "col", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Attributes that load or navigate to a URL
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "poster", "background", "data", "xlink:href"];
+
+/// Attributes dropped unconditionally, in addition to `on*` event handlers
+const BLOCKED_ATTRIBUTES: &[&str] = &["srcdoc", "srcset", "action", "formaction", "ping"];
+
+static RE_URL_SCHEME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z][a-z0-9+.-]*:").unwrap());
+static RE_CSS_IMPORT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)@import[^;]*;?").unwrap());
+static RE_CSS_REMOTE_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)url\(\s*['"]?\s*(https?:|//|javascript:)[^)]*\)"#).unwrap());
+static RE_CSS_EXPRESSION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)expression\s*\(").unwrap());
+
+/// Where sanitized pages are served from
+pub struct HelpOrigin {
+    /// Port of R's help server
+    pub r_port: u16,
+
+    /// Port of our help proxy
+    pub proxy_port: u16,
+}
+
+/// Sanitize an HTML help page before it is displayed by the frontend
+///
+/// Help pages are rendered from package documentation and vignettes, which
+/// may contain arbitrary HTML. The page is parsed and serialized again from an
+/// allowlist, so anything not understood is dropped:
+///
+/// - Scripts are removed, including event handler attributes and
+///   `javascript:` URLs. Only the scripts that R ships for its help pages,
+///   served from `/doc/html/`, are kept.
+/// - Frames, plugins, forms, and `<base>` elements are removed.
+/// - Remote resources (images, stylesheets, CSS `url()` and `@import`) are
+///   blocked. Links to remote pages are kept since following them is up to the
+///   user.
+/// - Absolute URLs pointing to R's help server are rewritten to go through
+///   the help proxy.
+pub fn sanitize_html(html: &str, origin: &HelpOrigin) -> String {
+    let document = Html::parse_document(html);
+
+    let mut out = String::with_capacity(html.len());
+    write_node(document.tree.root(), &mut out, origin);
+    out
+}
+
+fn write_node(node: NodeRef<Node>, out: &mut String, origin: &HelpOrigin) {
+    match node.value() {
+        Node::Document | Node::Fragment => write_children(node, out, origin),
+        Node::Doctype(_) => out.push_str("<!DOCTYPE html>\n"),
+        Node::Text(text) => {
+            let in_style = node
+                .parent()
+                .and_then(|parent| parent.value().as_element())
+                .is_some_and(|parent| parent.name() == "style");
+
+            if in_style {
+                // Raw text, must not be escaped
+                out.push_str(&sanitize_css(text).replace("</", "<\\/"));
+            } else {
+                escape_into(text, out, false);
+            }
+        },
+        Node::Element(element) => write_element(node, element, out, origin),
+        // Comments can hide conditional markup, and processing instructions
+        // have no business in help pages
+        _ => {},
+    }
+}
+
+fn write_children(node: NodeRef<Node>, out: &mut String, origin: &HelpOrigin) {
+    for child in node.children() {
+        write_node(child, out, origin);
+    }
+}
+
+fn write_element(node: NodeRef<Node>, element: &Element, out: &mut String, origin: &HelpOrigin) {
+    let name = element.name();
+
+    if BLOCKED_ELEMENTS.contains(&name) {
+        return;
+    }
+    if name == "meta" && element.attr("http-equiv").is_some() {
+        return;
+    }
+
+    let attributes = sanitize_attributes(name, element, origin);
+
+    match name {
+        // Scripts are only kept when they are R's own, and never inline
+        "script" => {
+            let trusted = attributes
+                .iter()
+                .any(|(name, value)| name == "src" && is_r_script(value));
+            if trusted {
+                out.push_str("<script");
+                write_attributes(&attributes, out);
+                out.push_str("></script>");
+            }
+            return;
+        },
+        // Stylesheets whose remote URL was blocked are useless
+        "link" if !attributes.iter().any(|(name, _)| name == "href") => return,
+        _ => {},
+    }
+
+    out.push('<');
+    out.push_str(name);
+    write_attributes(&attributes, out);
+    out.push('>');
+
+    if VOID_ELEMENTS.contains(&name) {
+        return;
+    }
+
+    write_children(node, out, origin);
+
+    out.push_str("</");
+    out.push_str(name);
+    out.push('>');
+}
+
+fn sanitize_attributes(
+    element_name: &str,
+    element: &Element,
+    origin: &HelpOrigin,
+) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+
+    for (name, value) in element.attrs() {
+        let name = name.to_lowercase();
+
+        if name.starts_with("on") || BLOCKED_ATTRIBUTES.contains(&name.as_str()) {
+            continue;
+        }
+
+        let value = if name == "style" {
+            sanitize_css(value)
+        } else if URL_ATTRIBUTES.contains(&name.as_str()) {
+            let navigation = name == "href" && matches!(element_name, "a" | "area");
+            match sanitize_url(value, navigation, origin) {
+                Some(value) => value,
+                None => continue,
+            }
+        } else {
+            String::from(value)
+        };
+
+        out.push((name, value));
+    }
+
+    out
+}
+
+/// Returns `None` when the URL must not be used. Remote URLs are only allowed
+/// for navigation, as opposed to resources loaded with the page.
+fn sanitize_url(url: &str, navigation: bool, origin: &HelpOrigin) -> Option<String> {
+    // Browsers ignore whitespace and control characters in schemes, e.g.
+    // `java\tscript:`
+    let normalized: String = url
+        .chars()
+        .filter(|char| !char.is_whitespace() && !char.is_control())
+        .collect::<String>()
+        .to_lowercase();
+
+    if normalized.starts_with("javascript:") || normalized.starts_with("vbscript:") {
+        return None;
+    }
+    if normalized.starts_with("data:") {
+        return (!navigation && normalized.starts_with("data:image/")).then(|| String::from(url));
+    }
+
+    if !normalized.starts_with("//") && !RE_URL_SCHEME.is_match(&normalized) {
+        return Some(String::from(url));
+    }
+
+    // Protocol-relative URLs resolve against the help server
+    let parsed = if normalized.starts_with("//") {
+        Url::parse(&format!("http:{}", url.trim()))
+    } else {
+        Url::parse(url.trim())
+    };
+
+    if let Ok(mut parsed) = parsed {
+        let is_local = matches!(parsed.host_str(), Some("127.0.0.1" | "localhost"));
+        if is_local && parsed.port() == Some(origin.r_port) {
+            parsed.set_port(Some(origin.proxy_port)).ok()?;
+            return Some(parsed.to_string());
+        }
+        if is_local && parsed.port() == Some(origin.proxy_port) {
+            return Some(parsed.to_string());
+        }
+    }
+
+    navigation.then(|| String::from(url))
+}
+
+/// Scripts shipped with R for its help pages, e.g. code highlighting and math
+/// rendering, are served from `/doc/html/`
+fn is_r_script(src: &str) -> bool {
+    src.starts_with("/doc/html/") || src.starts_with("../../../doc/html/")
+}
+
+fn sanitize_css(css: &str) -> String {
+    let css = RE_CSS_IMPORT.replace_all(css, "");
+    let css = RE_CSS_REMOTE_URL.replace_all(&css, "none");
+    let css = RE_CSS_EXPRESSION.replace_all(&css, "(");
+    css.into_owned()
+}
+
+fn write_attributes(attributes: &[(String, String)], out: &mut String) {
+    for (name, value) in attributes {
+        out.push(' ');
+        out.push_str(name);
+        out.push_str("=\"");
+        escape_into(value, out, true);
+        out.push('"');
+    }
+}
+
+fn escape_into(text: &str, out: &mut String, attribute: bool) {
+    for char in text.chars() {
+        match char {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attribute => out.push_str("&quot;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            _ => out.push(char),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::help::sanitize::sanitize_html;
+    use crate::help::sanitize::HelpOrigin;
+
+    const ORIGIN: HelpOrigin = HelpOrigin {
+        r_port: 1000,
+        proxy_port: 2000,
+    };
+
+    fn body(html: &str) -> String {
+        let out = sanitize_html(html, &ORIGIN);
+        let start = out.find("<body>").unwrap() + "<body>".len();
+        let end = out.find("</body>").unwrap();
+        String::from(&out[start..end])
+    }
+
+    #[test]
+    fn test_sanitize_scripts() {
+        assert_eq!(body("<p>a<script>alert(1)</script>b</p>"), "<p>ab</p>");
+        assert_eq!(
+            body(r#"<p onclick="alert(1)" class="x">a</p>"#),
+            r#"<p class="x">a</p>"#
+        );
+        assert_eq!(
+            body(r#"<a href=" java&#9;script:alert(1)">a</a>"#),
+            "<a>a</a>"
+        );
+        assert_eq!(
+            body(r#"<iframe src="https://example.com"></iframe><object data="x.swf"></object>"#),
+            ""
+        );
+
+        // R's own scripts are kept
+        assert_eq!(
+            body(r#"<p>a</p><script src="/doc/html/prism.js">inline()</script>"#),
+            r#"<p>a</p><script src="/doc/html/prism.js"></script>"#
+        );
+        assert_eq!(
+            body(r#"<p>a</p><script src="https://cdn.example.com/x.js"></script>"#),
+            "<p>a</p>"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_remote_resources() {
+        assert_eq!(
+            body(r#"<img src="https://example.com/track.png" alt="x">"#),
+            r#"<img alt="x">"#
+        );
+        assert_eq!(
+            body(r#"<img src="//example.com/track.png"><img src="figures/plot.png">"#),
+            r#"<img><img src="figures/plot.png">"#
+        );
+        assert_eq!(
+            body(r#"<p style="background: url('https://example.com/x.png'); color: red">a</p>"#),
+            r#"<p style="background: none; color: red">a</p>"#
+        );
+
+        // Remote links are fine
+        assert_eq!(
+            body(r#"<a href="https://cran.r-project.org">CRAN</a>"#),
+            r#"<a href="https://cran.r-project.org">CRAN</a>"#
+        );
+
+        let out = sanitize_html(
+            r#"<head><link rel="stylesheet" href="https://example.com/x.css"><style>@import url(https://example.com/y.css); p { color: red }</style></head>"#,
+            &ORIGIN,
+        );
+        assert!(!out.contains("example.com"));
+        assert!(out.contains("p { color: red }"));
+    }
+
+    #[test]
+    fn test_sanitize_rewrites_to_proxy() {
+        assert_eq!(
+            body(r#"<a href="http://127.0.0.1:1000/library/base/html/mean.html">mean</a>"#),
+            r#"<a href="http://127.0.0.1:2000/library/base/html/mean.html">mean</a>"#
+        );
+        assert_eq!(
+            body(r#"<img src="http://localhost:1000/doc/html/logo.jpg">"#),
+            r#"<img src="http://localhost:2000/doc/html/logo.jpg">"#
+        );
+    }
+
+    #[test]
+    fn test_sanitize_escapes_text() {
+        assert_eq!(
+            body("<pre>x &lt;- 1 &amp;&amp; y</pre>"),
+            "<pre>x &lt;- 1 &amp;&amp; y</pre>"
+        );
+        assert_eq!(
+            body(r#"<a title="&quot;&gt;<script>">a</a>"#),
+            r#"<a title="&quot;&gt;&lt;script&gt;">a</a>"#
+        );
+    }
+}
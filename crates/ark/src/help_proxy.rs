@@ -6,7 +6,9 @@
 //
 
 use std::net::TcpListener;
+use std::sync::Mutex;
 
+use actix_web::dev::ServerHandle;
 use actix_web::get;
 use actix_web::http::header::ContentType;
 use actix_web::web;
@@ -28,6 +30,9 @@ use stdext::spawn;
 use stdext::unwrap;
 use url::Url;
 
+use crate::help::sanitize::sanitize_html;
+use crate::help::sanitize::HelpOrigin;
+use crate::help::sanitize::HELP_CONTENT_SECURITY_POLICY;
 use crate::r_task;
 
 // Embed `resources/help/` which is where replacement resources can be found.
@@ -35,6 +40,9 @@ use crate::r_task;
 #[folder = "resources/help/"]
 struct Asset;
 
+/// Handle of the running help proxy server, used to stop it
+static SERVER_HANDLE: Mutex<Option<ServerHandle>> = Mutex::new(None);
+
 #[derive(Deserialize)]
 struct PreviewRdParams {
     file: String,
@@ -54,6 +62,16 @@ pub fn start(target_port: u16) -> anyhow::Result<u16> {
     Ok(source_port)
 }
 
+// Stops the help proxy, if running. Pending requests are dropped.
+pub fn stop() -> anyhow::Result<()> {
+    let Some(handle) = SERVER_HANDLE.lock().unwrap().take() else {
+        return Ok(());
+    };
+
+    futures::executor::block_on(handle.stop(false));
+    Ok(())
+}
+
 // The help proxy main entry point.
 #[tokio::main]
 async fn task(source_port: u16, target_port: u16) -> anyhow::Result<()> {
@@ -67,9 +85,19 @@ async fn task(source_port: u16, target_port: u16) -> anyhow::Result<()> {
 // AppState struct.
 #[derive(Clone)]
 struct AppState {
+    source_port: u16,
     target_port: u16,
 }
 
+impl AppState {
+    fn origin(&self) -> HelpOrigin {
+        HelpOrigin {
+            r_port: self.target_port,
+            proxy_port: self.source_port,
+        }
+    }
+}
+
 // HelpProxy struct.
 struct HelpProxy {
     source_port: u16,
@@ -90,6 +118,7 @@ impl HelpProxy {
     async fn run(&self) -> anyhow::Result<()> {
         // Create the app state.
         let app_state = web::Data::new(AppState {
+            source_port: self.source_port,
             target_port: self.target_port,
         });
 
@@ -101,10 +130,13 @@ impl HelpProxy {
                 .service(preview_img)
                 .default_service(web::to(proxy_request))
         })
-        .bind(("127.0.0.1", self.source_port))?;
+        .bind(("127.0.0.1", self.source_port))?
+        .run();
+
+        *SERVER_HANDLE.lock().unwrap() = Some(server.handle());
 
         // Run the server.
-        Ok(server.run().await?)
+        Ok(server.await?)
     }
 
     fn get_os_assigned_port() -> std::io::Result<u16> {
@@ -175,18 +207,31 @@ async fn proxy_request(req: HttpRequest, app_state: web::Data<AppState>) -> Http
                 _ => None,
             };
 
-            // Return the replacement resource or the real resource.
-            match replacement_embedded_file {
-                Some(replacement_embedded_file) => {
-                    http_response_builder.body(replacement_embedded_file.data)
+            if let Some(replacement_embedded_file) = replacement_embedded_file {
+                return http_response_builder.body(replacement_embedded_file.data);
+            }
+
+            let body = match response.bytes().await {
+                Ok(body) => body,
+                Err(error) => {
+                    log::error!("Error proxying {}: {}", target_url_string, error);
+                    return HttpResponse::BadGateway().finish();
                 },
-                None => http_response_builder.body(match response.bytes().await {
-                    Ok(body) => body,
-                    Err(error) => {
-                        log::error!("Error proxying {}: {}", target_url_string, error);
-                        return HttpResponse::BadGateway().finish();
-                    },
-                }),
+            };
+
+            // HTML pages may come from untrusted package documentation and
+            // are sanitized before reaching the frontend.
+            let is_html = content_type
+                .and_then(|content_type| content_type.to_str().ok())
+                .is_some_and(|content_type| content_type.starts_with("text/html"));
+
+            if is_html {
+                let html = String::from_utf8_lossy(&body);
+                http_response_builder
+                    .insert_header(("Content-Security-Policy", HELP_CONTENT_SECURITY_POLICY))
+                    .body(sanitize_html(&html, &app_state.origin()))
+            } else {
+                http_response_builder.body(body)
             }
         },
         // Error.
@@ -198,7 +243,10 @@ async fn proxy_request(req: HttpRequest, app_state: web::Data<AppState>) -> Http
 }
 
 #[get("/preview")]
-async fn preview_rd(params: web::Query<PreviewRdParams>) -> HttpResponse {
+async fn preview_rd(
+    params: web::Query<PreviewRdParams>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
     let file = params.file.as_str();
 
     log::info!("Received request with path 'preview' and file '{file}'.");
@@ -222,7 +270,8 @@ async fn preview_rd(params: web::Query<PreviewRdParams>) -> HttpResponse {
 
     HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(content)
+        .insert_header(("Content-Security-Policy", HELP_CONTENT_SECURITY_POLICY))
+        .body(sanitize_html(&content, &app_state.origin()))
 }
 
 #[get("/dev-figure")]
@@ -0,0 +1,358 @@
+//
+// hook_guard.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::time::Duration;
+use std::time::Instant;
+
+/// Bytes of output that hooks may write within `HOOK_OUTPUT_WINDOW` before
+/// they are considered to be looping
+pub const MAX_HOOK_OUTPUT: usize = 1024 * 1024;
+
+/// Period over which the output of hooks is metered. Hooks of long-lived
+/// apps, e.g. a Shiny app logging requests, may write any amount of output
+/// over time as long as they don't flood the console.
+pub const HOOK_OUTPUT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Nesting depth of hooks, e.g. a callback that waits for input from a nested
+/// prompt that runs the callback again
+pub const MAX_HOOK_DEPTH: usize = 16;
+
+/// Protection against output loops caused by hooks, i.e. R code that ark runs
+/// while idle on behalf of packages or the user: input handlers (e.g.
+/// callbacks of `later` or `httpuv`), `R_ProcessEvents()`, and finalizers.
+///
+/// A hook that prints and schedules itself again keeps the input handlers
+/// active, so ark keeps running it without ever getting back to the prompt.
+/// The output of hooks is metered, and once they write more than
+/// `MAX_HOOK_OUTPUT` bytes within `HOOK_OUTPUT_WINDOW` or nest deeper than
+/// `MAX_HOOK_DEPTH`, the guard trips: the running hooks are cut short and
+/// their output is dropped.
+///
+/// If an input handler was running when the guard tripped, it's the one
+/// caught in the loop. It's removed from the event loop once the hooks have
+/// unwound and the other hooks keep running. Otherwise the loop comes from
+/// `R_ProcessEvents()` or a finalizer, which can't be told apart from the
+/// other hooks of the same kind, so all hooks are disabled until the next
+/// execution.
+#[derive(Debug, Default)]
+pub struct HookGuard {
+    /// Nesting depth of the hooks being run
+    depth: usize,
+
+    /// Bytes of output written by hooks since `window_start`
+    written: usize,
+
+    /// Start of the current metering period
+    window_start: Option<Instant>,
+
+    /// Why hooks were disabled, if they were
+    tripped: Option<HookLoop>,
+
+    /// Address of the input handler being run, if any
+    handler: Option<usize>,
+
+    /// Address of the input handler that was running when the guard tripped
+    culprit: Option<usize>,
+
+    /// Loop the user hasn't been told about yet
+    report: Option<HookReport>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HookLoop {
+    Output,
+    Depth,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HookReport {
+    pub reason: HookLoop,
+
+    /// Whether the input handler caught in the loop was removed, in which
+    /// case the other hooks keep running
+    pub handler_removed: bool,
+}
+
+impl HookGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enter a hook run. Returns `false` if hooks shouldn't run, in which case
+    /// `exit()` must not be called. Callers should call `exit()` from a drop
+    /// guard so that the depth is restored if the hook panics.
+    pub fn enter(&mut self) -> bool {
+        if self.tripped.is_some() {
+            return false;
+        }
+        if self.depth >= MAX_HOOK_DEPTH {
+            self.trip(HookLoop::Depth);
+            return false;
+        }
+
+        self.depth += 1;
+        true
+    }
+
+    pub fn exit(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+
+        // Forget handlers that R jumped over
+        if self.depth == 0 {
+            self.handler = None;
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.depth > 0
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped.is_some()
+    }
+
+    /// Record the address of the input handler about to run, or `None` once
+    /// it returns. Returns the handler that was running before, to be
+    /// restored when handlers are nested.
+    pub fn set_handler(&mut self, handler: Option<usize>) -> Option<usize> {
+        std::mem::replace(&mut self.handler, handler)
+    }
+
+    /// The input handler caught in the loop, once the hooks have unwound. The
+    /// caller removes it from the event loop, so hooks are re-enabled right
+    /// away instead of at the next execution.
+    pub fn take_culprit(&mut self) -> Option<usize> {
+        if self.is_running() {
+            return None;
+        }
+        let culprit = self.culprit.take()?;
+
+        self.tripped = None;
+        self.written = 0;
+        self.window_start = None;
+        if let Some(report) = &mut self.report {
+            report.handler_removed = true;
+        }
+
+        Some(culprit)
+    }
+
+    /// Take note of `n` bytes of output. Returns `false` if the output
+    /// should be dropped.
+    pub fn output(&mut self, n: usize) -> bool {
+        self.output_at(n, Instant::now())
+    }
+
+    fn output_at(&mut self, n: usize, now: Instant) -> bool {
+        if !self.is_running() {
+            return true;
+        }
+        if self.is_tripped() {
+            return false;
+        }
+
+        match self.window_start {
+            Some(start) if now.duration_since(start) < HOOK_OUTPUT_WINDOW => {},
+            _ => {
+                self.window_start = Some(now);
+                self.written = 0;
+            },
+        }
+
+        self.written += n;
+        if self.written > MAX_HOOK_OUTPUT {
+            self.trip(HookLoop::Output);
+            return false;
+        }
+
+        true
+    }
+
+    /// The loop the user hasn't been told about yet, once the hooks have
+    /// unwound and we know whether its input handler was removed
+    pub fn take_report(&mut self) -> Option<HookReport> {
+        if self.is_running() {
+            return None;
+        }
+        self.report.take()
+    }
+
+    /// Reset the guard at the start of an execution, which re-enables hooks.
+    /// Returns `true` if they were disabled.
+    ///
+    /// The depth is cleared too. An execution means the user is in control,
+    /// e.g. at a prompt nested in a hook, and its output isn't metered. Runs
+    /// that didn't exit, e.g. because R jumped over them, are forgotten.
+    pub fn reset(&mut self) -> bool {
+        self.depth = 0;
+        self.written = 0;
+        self.window_start = None;
+        self.handler = None;
+        self.culprit = None;
+        self.report = None;
+        self.tripped.take().is_some()
+    }
+
+    fn trip(&mut self, reason: HookLoop) {
+        log::error!(
+            "Output loop detected: event loop callbacks {}",
+            reason.cause()
+        );
+        self.tripped = Some(reason);
+        self.culprit = self.handler;
+        self.report = Some(HookReport {
+            reason,
+            handler_removed: false,
+        });
+    }
+}
+
+impl HookLoop {
+    fn cause(&self) -> String {
+        match self {
+            HookLoop::Output => format!(
+                "wrote more than {} MB of output in {} seconds",
+                MAX_HOOK_OUTPUT / (1024 * 1024),
+                HOOK_OUTPUT_WINDOW.as_secs()
+            ),
+            HookLoop::Depth => format!("were nested more than {MAX_HOOK_DEPTH} times"),
+        }
+    }
+}
+
+impl HookReport {
+    pub fn message(&self) -> String {
+        let cause = self.reason.cause();
+        if self.handler_removed {
+            format!(
+                "Output loop detected: an event loop callback (e.g. from `later` or `httpuv`) {cause}.\n\
+                 It was removed, other callbacks keep running."
+            )
+        } else {
+            format!(
+                "Output loop detected: event loop callbacks (e.g. from `later`, `httpuv`, or finalizers) {cause}.\n\
+                 Callbacks are disabled and their output is discarded until the next execution."
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::hook_guard::HookGuard;
+    use crate::hook_guard::HookLoop;
+    use crate::hook_guard::HookReport;
+    use crate::hook_guard::HOOK_OUTPUT_WINDOW;
+    use crate::hook_guard::MAX_HOOK_DEPTH;
+    use crate::hook_guard::MAX_HOOK_OUTPUT;
+
+    #[test]
+    fn test_hook_guard_output() {
+        let mut guard = HookGuard::new();
+
+        // Output of executions is never metered
+        assert!(guard.output(MAX_HOOK_OUTPUT * 2));
+
+        assert!(guard.enter());
+        assert!(guard.output(MAX_HOOK_OUTPUT));
+        assert!(!guard.output(1));
+        assert!(!guard.output(1));
+        guard.exit();
+
+        // The loop is reported once. No input handler was running, so hooks
+        // are disabled.
+        let report = HookReport {
+            reason: HookLoop::Output,
+            handler_removed: false,
+        };
+        assert_eq!(guard.take_culprit(), None);
+        assert_eq!(guard.take_report(), Some(report));
+        assert_eq!(guard.take_report(), None);
+
+        // Hooks stay disabled until the next execution
+        assert!(!guard.enter());
+        assert!(guard.reset());
+        assert!(guard.enter());
+        assert!(guard.output(1));
+        guard.exit();
+        assert!(!guard.reset());
+    }
+
+    #[test]
+    fn test_hook_guard_output_window() {
+        let mut guard = HookGuard::new();
+        let start = Instant::now();
+
+        assert!(guard.enter());
+        assert!(guard.output_at(MAX_HOOK_OUTPUT, start));
+
+        // The budget is renewed in the next period
+        assert!(guard.output_at(MAX_HOOK_OUTPUT, start + HOOK_OUTPUT_WINDOW));
+        assert!(!guard.output_at(1, start + HOOK_OUTPUT_WINDOW));
+        assert!(guard.is_tripped());
+    }
+
+    #[test]
+    fn test_hook_guard_depth() {
+        let mut guard = HookGuard::new();
+
+        for _ in 0..MAX_HOOK_DEPTH {
+            assert!(guard.enter());
+        }
+        assert!(!guard.enter());
+
+        // Not reported until the hooks have unwound
+        assert_eq!(guard.take_report(), None);
+
+        for _ in 0..MAX_HOOK_DEPTH {
+            guard.exit();
+        }
+        assert!(!guard.is_running());
+        assert_eq!(guard.take_report().unwrap().reason, HookLoop::Depth);
+        assert!(guard.reset());
+
+        // Executions clear runs that didn't exit
+        assert!(guard.enter());
+        assert!(guard.enter());
+        guard.reset();
+        assert!(!guard.is_running());
+        guard.exit();
+        assert!(!guard.is_running());
+    }
+
+    #[test]
+    fn test_hook_guard_culprit() {
+        let mut guard = HookGuard::new();
+
+        assert!(guard.enter());
+        assert_eq!(guard.set_handler(Some(1)), None);
+        assert!(guard.output(MAX_HOOK_OUTPUT));
+        assert!(!guard.output(1));
+
+        // The culprit is only removed once the hooks have unwound
+        assert_eq!(guard.take_culprit(), None);
+        assert_eq!(guard.set_handler(None), Some(1));
+        guard.exit();
+        assert_eq!(guard.take_culprit(), Some(1));
+        assert_eq!(guard.take_culprit(), None);
+
+        let report = HookReport {
+            reason: HookLoop::Output,
+            handler_removed: true,
+        };
+        assert_eq!(guard.take_report(), Some(report));
+
+        // The other hooks keep running
+        assert!(!guard.is_tripped());
+        assert!(guard.enter());
+        assert!(guard.output(1));
+        guard.exit();
+        assert!(!guard.reset());
+    }
+}
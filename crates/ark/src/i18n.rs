@@ -0,0 +1,348 @@
+//
+// i18n.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::sync::LazyLock;
+use std::sync::RwLock;
+
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::object::RObject;
+use libr::SEXP;
+
+/// A user-facing message emitted by ark itself, as opposed to R's own
+/// messages which R translates. `text` is the English message, also used as
+/// fallback. Placeholders are written `%s` so the same messages can be used
+/// with `sprintf()` on the R side.
+struct Message {
+    id: &'static str,
+    text: &'static str,
+    translations: &'static [(&'static str, &'static str)],
+}
+
+static CATALOG: &[Message] = &[
+    Message {
+        id: "read_only_session",
+        text: "Can't execute code: this session is read-only.",
+        translations: &[
+            ("de", "Code kann nicht ausgeführt werden: Diese Sitzung ist schreibgeschützt."),
+            ("es", "No se puede ejecutar el código: esta sesión es de solo lectura."),
+            ("fr", "Impossible d'exécuter le code : cette session est en lecture seule."),
+        ],
+    },
+    Message {
+        id: "observer_session",
+        text: "Can't execute code: another client is driving this session.",
+        translations: &[
+            ("de", "Code kann nicht ausgeführt werden: Ein anderer Client steuert diese Sitzung."),
+            ("es", "No se puede ejecutar el código: otro cliente controla esta sesión."),
+            ("fr", "Impossible d'exécuter le code : un autre client pilote cette session."),
+        ],
+    },
+    Message {
+        id: "read_only_change",
+        text: "Can't change the session: it is read-only.",
+        translations: &[
+            ("de", "Die Sitzung kann nicht geändert werden: Sie ist schreibgeschützt."),
+            ("es", "No se puede modificar la sesión: es de solo lectura."),
+            ("fr", "Impossible de modifier la session : elle est en lecture seule."),
+        ],
+    },
+    Message {
+        id: "observer_change",
+        text: "Can't change the session: another client is driving it.",
+        translations: &[
+            ("de", "Die Sitzung kann nicht geändert werden: Ein anderer Client steuert sie."),
+            ("es", "No se puede modificar la sesión: otro cliente la controla."),
+            ("fr", "Impossible de modifier la session : un autre client la pilote."),
+        ],
+    },
+    Message {
+        id: "incomplete_input",
+        text: "Code fragment is not complete: %s",
+        translations: &[
+            ("de", "Das Codefragment ist unvollständig: %s"),
+            ("es", "El fragmento de código está incompleto: %s"),
+            ("fr", "Le fragment de code est incomplet : %s"),
+        ],
+    },
+    Message {
+        id: "read_only_banner",
+        text: "This session is read-only. Code can't be executed.",
+        translations: &[
+            ("de", "Diese Sitzung ist schreibgeschützt. Code kann nicht ausgeführt werden."),
+            ("es", "Esta sesión es de solo lectura. No se puede ejecutar código."),
+            ("fr", "Cette session est en lecture seule. Le code ne peut pas être exécuté."),
+        ],
+    },
+    Message {
+        id: "yes",
+        text: "Yes",
+        translations: &[("de", "Ja"), ("es", "Sí"), ("fr", "Oui")],
+    },
+    Message {
+        id: "no",
+        text: "No",
+        translations: &[("de", "Nein"), ("es", "No"), ("fr", "Non")],
+    },
+    Message {
+        id: "clear_workspace_question",
+        text: "Remove all objects from the global environment?",
+        translations: &[
+            ("de", "Alle Objekte aus der globalen Umgebung entfernen?"),
+            ("es", "¿Eliminar todos los objetos del entorno global?"),
+            ("fr", "Supprimer tous les objets de l'environnement global ?"),
+        ],
+    },
+    Message {
+        id: "install_packages_title",
+        text: "Install packages",
+        translations: &[
+            ("de", "Pakete installieren"),
+            ("es", "Instalar paquetes"),
+            ("fr", "Installer des packages"),
+        ],
+    },
+    Message {
+        id: "code_lens_run_function",
+        text: "Run function",
+        translations: &[
+            ("de", "Funktion ausführen"),
+            ("es", "Ejecutar función"),
+            ("fr", "Exécuter la fonction"),
+        ],
+    },
+    Message {
+        id: "code_lens_debug_function",
+        text: "Debug on next call",
+        translations: &[
+            ("de", "Beim nächsten Aufruf debuggen"),
+            ("es", "Depurar en la próxima llamada"),
+            ("fr", "Déboguer au prochain appel"),
+        ],
+    },
+    Message {
+        id: "code_lens_run_test",
+        text: "Run test",
+        translations: &[
+            ("de", "Test ausführen"),
+            ("es", "Ejecutar prueba"),
+            ("fr", "Exécuter le test"),
+        ],
+    },
+    Message {
+        id: "reload_packages_stale",
+        text: "The installed version of %s changed since it was loaded.",
+        translations: &[
+            ("de", "Die installierte Version von %s hat sich seit dem Laden geändert."),
+            ("es", "La versión instalada de %s cambió desde que se cargó."),
+            ("fr", "La version installée de %s a changé depuis son chargement."),
+        ],
+    },
+    Message {
+        id: "reload_packages_dependents",
+        text: "Packages depending on it will be reloaded too: %s.",
+        translations: &[
+            ("de", "Davon abhängige Pakete werden ebenfalls neu geladen: %s."),
+            ("es", "Los paquetes que dependen de él también se recargarán: %s."),
+            ("fr", "Les packages qui en dépendent seront également rechargés : %s."),
+        ],
+    },
+    Message {
+        id: "reload_packages_hint",
+        text: "Run `%s` to reload it.",
+        translations: &[
+            ("de", "Führen Sie `%s` aus, um es neu zu laden."),
+            ("es", "Ejecute `%s` para recargarlo."),
+            ("fr", "Exécutez `%s` pour le recharger."),
+        ],
+    },
+];
+
+/// Preferred languages of the session, most preferred first
+static LANGUAGES: LazyLock<RwLock<Vec<String>>> = LazyLock::new(|| RwLock::new(Vec::new()));
+
+/// Update the preferred languages from `LANGUAGE` and the messages locale of
+/// R, which R's own messages follow. Must be called on the R thread. Called
+/// after startup and after each execution in case they were changed with
+/// `Sys.setenv()` or `Sys.setlocale()`.
+pub fn refresh() {
+    // `Sys.setlocale()` doesn't update the environment variables, so the
+    // locale is queried from R when it can be. It already accounts for
+    // `LC_ALL`.
+    let locale = messages_locale();
+    let languages = languages(|var| match var {
+        "LC_ALL" | "LC_MESSAGES" | "LANG" if locale.is_some() => locale.clone(),
+        _ => std::env::var(var).ok(),
+    });
+
+    let mut current = LANGUAGES.write().unwrap();
+    if *current != languages {
+        log::trace!("Using languages {languages:?} for kernel messages");
+        *current = languages;
+    }
+}
+
+/// The current messages locale of R, e.g. `fr_FR.UTF-8`. `None` on Windows,
+/// where R doesn't have this category.
+fn messages_locale() -> Option<String> {
+    if cfg!(windows) {
+        return None;
+    }
+
+    let locale = RFunction::new("base", "Sys.getlocale")
+        .param("category", "LC_MESSAGES")
+        .call()
+        .and_then(String::try_from);
+
+    match locale {
+        Ok(locale) if !locale.is_empty() => Some(locale),
+        Ok(_) => None,
+        Err(err) => {
+            log::warn!("Can't get the messages locale: {err:?}");
+            None
+        },
+    }
+}
+
+/// Translate a message to the preferred language of the session, falling back
+/// to English. Unknown ids are returned as is.
+pub fn tr(id: &str) -> &str {
+    let Some(message) = CATALOG.iter().find(|message| message.id == id) else {
+        log::warn!("Unknown message id '{id}'");
+        return id;
+    };
+
+    let languages = LANGUAGES.read().unwrap();
+    translate(message, &languages)
+}
+
+fn translate(message: &'static Message, languages: &[String]) -> &'static str {
+    languages
+        .iter()
+        .find_map(|language| {
+            message
+                .translations
+                .iter()
+                .find(|(lang, _)| lang == language)
+                .map(|(_, text)| *text)
+        })
+        .unwrap_or(message.text)
+}
+
+/// Candidate languages in the same order of precedence as gettext: the
+/// colon-separated `LANGUAGE` list, then the messages locale. Each locale like
+/// `pt_BR.UTF-8` contributes `pt_BR` and `pt`. As in gettext, `LANGUAGE` is
+/// ignored in the C locale.
+fn languages(env: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .find_map(|var| env(var).filter(|value| !value.is_empty()));
+
+    let is_c_locale = |locale: &str| locale == "C" || locale == "POSIX" || locale.starts_with("C.");
+    if locale.as_deref().is_some_and(is_c_locale) {
+        return Vec::new();
+    }
+
+    let mut out = Vec::new();
+    let preferences = env("LANGUAGE").unwrap_or_default();
+
+    for locale in preferences.split(':').chain(locale.as_deref()) {
+        // Drop the encoding and modifier, e.g. `.UTF-8` or `@euro`
+        let locale = locale.split(['.', '@']).next().unwrap_or_default();
+        if locale.is_empty() {
+            continue;
+        }
+
+        let language = locale.split('_').next().unwrap_or_default();
+        for candidate in [locale, language] {
+            if !out.iter().any(|lang| lang == candidate) {
+                out.push(String::from(candidate));
+            }
+        }
+    }
+
+    out
+}
+
+#[harp::register]
+pub unsafe extern "C" fn ps_translate(id: SEXP) -> anyhow::Result<SEXP> {
+    let id: String = RObject::view(id).try_into()?;
+    Ok(RObject::from(tr(&id)).sexp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::i18n::languages;
+    use crate::i18n::translate;
+    use crate::i18n::CATALOG;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        |var| {
+            vars.iter()
+                .find(|(name, _)| *name == var)
+                .map(|(_, value)| String::from(*value))
+        }
+    }
+
+    #[test]
+    fn test_languages() {
+        assert_eq!(languages(env(&[])), Vec::<String>::new());
+        assert_eq!(languages(env(&[("LANG", "fr_FR.UTF-8")])), vec![
+            "fr_FR", "fr"
+        ]);
+
+        // `LC_ALL` has precedence over `LANG`
+        assert_eq!(
+            languages(env(&[("LANG", "fr_FR.UTF-8"), ("LC_ALL", "de_DE.UTF-8")])),
+            vec!["de_DE", "de"]
+        );
+
+        // `LANGUAGE` has precedence over the locale
+        assert_eq!(
+            languages(env(&[("LANG", "en_US.UTF-8"), ("LANGUAGE", "es:fr")])),
+            vec!["es", "fr", "en_US", "en"]
+        );
+
+        // Without a locale, e.g. on Windows
+        assert_eq!(languages(env(&[("LANGUAGE", "de")])), vec!["de"]);
+
+        // Except in the C locale
+        assert_eq!(
+            languages(env(&[("LANG", "C"), ("LANGUAGE", "fr")])),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_translate() {
+        let message = CATALOG.iter().find(|m| m.id == "yes").unwrap();
+
+        assert_eq!(translate(message, &[]), "Yes");
+        assert_eq!(translate(message, &[String::from("fr")]), "Oui");
+        assert_eq!(translate(message, &[String::from("ja")]), "Yes");
+        assert_eq!(
+            translate(message, &[String::from("ja"), String::from("de")]),
+            "Ja"
+        );
+    }
+
+    #[test]
+    fn test_catalog_is_consistent() {
+        let ids: HashSet<&str> = CATALOG.iter().map(|message| message.id).collect();
+        assert_eq!(ids.len(), CATALOG.len());
+
+        // Translations must keep the placeholders of the English message
+        for message in CATALOG {
+            let n = message.text.matches("%s").count();
+            for (lang, text) in message.translations {
+                assert_eq!(text.matches("%s").count(), n, "{} ({lang})", message.id);
+            }
+        }
+    }
+}
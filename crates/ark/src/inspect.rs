@@ -0,0 +1,207 @@
+//
+// inspect.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Answers Jupyter `inspect_request` messages, e.g. `x?` and `x??` in
+// notebooks. The symbol or `$` chain under the cursor is evaluated in the
+// global environment like the live values of hovers: active bindings and
+// promises are left alone, and `$` methods aren't called. Describing the
+// object with `str()` may still dispatch to methods registered by packages,
+// so this is bounded by a time limit and only happens while R is idle.
+//
+// Functions are described by their help page, or by their signature when they
+// don't have one. Other objects are described by their class and the output
+// of `str()`. At detail level 1, the source of functions is added and `str()`
+// descends into all levels of lists. Anything else, e.g. keywords and argument
+// names, falls back to the hover of the LSP.
+
+use std::time::Duration;
+
+use amalthea::cursor;
+use amalthea::wire::inspect_request::InspectRequest;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::object::RObject;
+use harp::utils::r_is_function;
+use tree_sitter::Node;
+use tree_sitter::Point;
+
+use crate::lsp::document_context::DocumentContext;
+use crate::lsp::documents::Document;
+use crate::lsp::hover::r_hover;
+use crate::lsp::hover_evaluation::evaluate;
+use crate::lsp::hover_evaluation::hover_expression;
+use crate::lsp::hover_evaluation::is_glimpse_target;
+use crate::lsp::hover_evaluation::with_time_limit;
+use crate::lsp::signature_help::r_signature_label;
+use crate::lsp::traits::rope::RopeExt;
+use crate::modules::ARK_ENVS;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+
+/// Time budget for evaluating and describing an object. `str()` may dispatch
+/// to methods registered by packages, which could be slow.
+const INSPECT_TIME_LIMIT: Duration = Duration::from_secs(1);
+
+/// Description of the object under the cursor, as markdown. Must be called on
+/// the R thread.
+pub(crate) fn r_inspect(req: &InspectRequest) -> anyhow::Result<Option<String>> {
+    let code = req.code.as_str();
+
+    let document = Document::new(code, None);
+    let (row, column) = cursor::cursor_pos_to_point(code, req.cursor_pos);
+    let context = DocumentContext::new(&document, Point::new(row, column), None);
+
+    let Some(node) = inspect_expression(context.node) else {
+        return Ok(r_hover(&context)?.map(|content| content.value));
+    };
+
+    let contents = &document.contents;
+    let name = contents.node_slice(&node)?.to_string();
+    let detailed = req.detail_level > 0;
+
+    // Objects that don't exist or can't be evaluated safely aren't found
+    let object = with_time_limit(INSPECT_TIME_LIMIT, || evaluate(&node, contents))?;
+    let Some(object) = object else {
+        return Ok(None);
+    };
+
+    if r_is_function(object.sexp) {
+        let value = inspect_function(&context, &node, &name, object, detailed)?;
+        return Ok(Some(value));
+    }
+
+    let description = with_time_limit(INSPECT_TIME_LIMIT, || {
+        Ok(RFunction::new("", "inspect_object")
+            .add(object)
+            .param("detail_level", req.detail_level as i32)
+            .call_in(ARK_ENVS.positron_ns)?)
+    })?;
+
+    let class: Vec<String> = description.vector_elt(0)?.try_into()?;
+    let structure: Vec<String> = description.vector_elt(1)?.try_into()?;
+
+    let class: Vec<String> = class.iter().map(|class| format!("`{class}`")).collect();
+    Ok(Some(format!(
+        "`{name}`: object of class {}\n\n```\n{}\n```",
+        class.join(", "),
+        structure.join("\n")
+    )))
+}
+
+fn inspect_function(
+    context: &DocumentContext,
+    node: &Node,
+    name: &str,
+    function: RObject,
+    detailed: bool,
+) -> anyhow::Result<String> {
+    // The hover of a symbol is its help page, or its signature. For `$`
+    // chains it would document the field name instead.
+    let help = if node.is_identifier() {
+        r_hover(context).unwrap_or_else(|err| {
+            log::warn!("Can't get help for `{name}`: {err:?}");
+            None
+        })
+    } else {
+        None
+    };
+
+    let mut value = match help {
+        Some(help) => help.value,
+        None => {
+            let label = r_signature_label(name, function.sexp)?;
+            format!("`{name}`: function\n\n```r\n{label}\n```")
+        },
+    };
+
+    if detailed {
+        let source: Vec<String> = RFunction::new("", "inspect_source")
+            .add(function)
+            .call_in(ARK_ENVS.positron_ns)?
+            .try_into()?;
+        let source = source.join("\n");
+        value.push_str(&format!("\n\n**Source**\n\n```r\n{source}\n```"));
+    }
+
+    Ok(value)
+}
+
+/// The expression to inspect for the node under the cursor: a symbol, or a
+/// `$` chain up to the cursor. Argument names, namespaced symbols, and
+/// literals aren't objects of the session.
+fn inspect_expression(node: Node) -> Option<Node> {
+    let node = hover_expression(node)?;
+
+    match node.node_type() {
+        NodeType::Identifier => is_glimpse_target(&node).then_some(node),
+        NodeType::ExtractOperator(_) => Some(node),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amalthea::wire::inspect_request::InspectRequest;
+
+    use crate::inspect::r_inspect;
+    use crate::r_task;
+
+    fn inspect(code: &str, detail_level: u32) -> Option<String> {
+        let req = InspectRequest {
+            code: String::from(code),
+            cursor_pos: code.chars().count() as u32,
+            detail_level,
+        };
+        r_task(|| r_inspect(&req).unwrap())
+    }
+
+    #[test]
+    fn test_inspect_object() {
+        r_task(|| {
+            harp::parse_eval_global(
+                "ark_test_inspect <- list(a = 1:3, b = list(c = 'x'))
+                 ark_test_inspect_fn <- function(x) x + 1
+                 makeActiveBinding('ark_test_inspect_active', function() stop('triggered'), globalenv())",
+            )
+            .unwrap();
+        });
+
+        let value = inspect("ark_test_inspect", 0).unwrap();
+        assert!(value.starts_with("`ark_test_inspect`: object of class `list`"));
+        assert!(value.contains("$ a: int [1:3] 1 2 3"));
+        assert!(!value.contains("..$ c"));
+
+        // Nested lists are expanded at detail level 1
+        let value = inspect("ark_test_inspect", 1).unwrap();
+        assert!(value.contains("..$ c: chr \"x\""));
+
+        // `$` chains
+        let value = inspect("ark_test_inspect$b", 0).unwrap();
+        assert!(value.starts_with("`ark_test_inspect$b`: object of class `list`"));
+
+        // Functions without a help page show their signature, and their source
+        // at detail level 1
+        let value = inspect("ark_test_inspect_fn", 0).unwrap();
+        assert!(value.contains("ark_test_inspect_fn(x)"));
+        assert!(!value.contains("x + 1"));
+
+        let value = inspect("ark_test_inspect_fn", 1).unwrap();
+        assert!(value.contains("**Source**"));
+        assert!(value.contains("x + 1"));
+
+        // Active bindings aren't triggered
+        assert_eq!(inspect("ark_test_inspect_active", 0), None);
+        assert_eq!(inspect("ark_test_inspect_undefined", 0), None);
+
+        r_task(|| {
+            harp::parse_eval_global(
+                "rm(ark_test_inspect, ark_test_inspect_fn, ark_test_inspect_active)",
+            )
+            .unwrap();
+        });
+    }
+}
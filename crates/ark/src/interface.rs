@@ -22,11 +22,14 @@ use std::time::Duration;
 
 use amalthea::comm::base_comm::JsonRpcReply;
 use amalthea::comm::event::CommManagerEvent;
+use amalthea::comm::event_log_comm::KernelEventKind;
 use amalthea::comm::ui_comm::ui_frontend_reply_from_value;
 use amalthea::comm::ui_comm::BusyParams;
 use amalthea::comm::ui_comm::ShowMessageParams;
 use amalthea::comm::ui_comm::UiFrontendEvent;
 use amalthea::comm::ui_comm::UiFrontendRequest;
+use amalthea::event_log;
+use amalthea::features;
 use amalthea::socket::iopub::IOPubMessage;
 use amalthea::socket::iopub::Wait;
 use amalthea::socket::stdin::StdInRequest;
@@ -42,6 +45,7 @@ use amalthea::wire::input_request::ShellInputRequest;
 use amalthea::wire::input_request::StdInRpcReply;
 use amalthea::wire::input_request::UiCommFrontendRequest;
 use amalthea::wire::jupyter_message::Status;
+use amalthea::wire::language_info::EnvironmentFingerprint;
 use amalthea::wire::originator::Originator;
 use amalthea::wire::stream::Stream;
 use amalthea::wire::stream::StreamOutput;
@@ -88,37 +92,55 @@ use stdext::result::ResultOrLog;
 use stdext::*;
 use uuid::Uuid;
 
+use crate::cell_state;
 use crate::dap::dap::DapBackendEvent;
 use crate::dap::dap_r_main::RMainDap;
 use crate::dap::Dap;
 use crate::errors;
+use crate::features::HOOK_GUARD;
+use crate::fingerprint;
 use crate::help::message::HelpEvent;
 use crate::help::r_help::RHelp;
+use crate::hook_guard::HookGuard;
+use crate::i18n;
+use crate::i18n::tr;
 use crate::lsp::events::EVENTS;
 use crate::lsp::main_loop::Event;
 use crate::lsp::main_loop::KernelNotification;
 use crate::lsp::main_loop::TokioUnboundedSender;
 use crate::lsp::state_handlers::ConsoleInputs;
 use crate::modules;
+use crate::modules::ARK_ENVS;
 use crate::plots::graphics_device;
+use crate::printed_table::printed_table;
 use crate::r_task;
 use crate::r_task::BoxFuture;
 use crate::r_task::RTask;
 use crate::r_task::RTaskStartInfo;
 use crate::r_task::RTaskStatus;
+use crate::repr::repr_mimebundle;
 use crate::request::debug_request_command;
 use crate::request::KernelRequest;
 use crate::request::RRequest;
+use crate::session_access;
+use crate::session_access::AccessDenied;
+use crate::session_access::SessionAccess;
 use crate::signals::initialize_signal_handlers;
 use crate::signals::interrupts_pending;
 use crate::signals::set_interrupts_pending;
+use crate::signals::take_interrupt_requested;
 use crate::srcref::ns_populate_srcref;
 use crate::srcref::resource_loaded_namespaces;
 use crate::startup;
 use crate::strings::lines;
 use crate::sys::console::console_to_utf8;
+use crate::task_queue;
+use crate::transcript::Transcript;
 use crate::ui::UiCommMessage;
 use crate::ui::UiCommSender;
+use crate::user_config::user_config;
+use crate::wait;
+use crate::warmup;
 
 static RE_DEBUG_PROMPT: Lazy<Regex> = Lazy::new(|| Regex::new(r"Browse\[\d+\]").unwrap());
 
@@ -194,6 +216,9 @@ pub struct RMain {
     /// `execute_result` Jupyter messages instead of `stream` messages.
     autoprint_output: String,
 
+    /// Payloads of the reply to the current execution, see `pager.rs`
+    payloads: Vec<serde_json::Value>,
+
     /// Channel to send and receive tasks from `RTask`s
     tasks_interrupt_rx: Receiver<RTask>,
     tasks_idle_rx: Receiver<RTask>,
@@ -221,6 +246,17 @@ pub struct RMain {
     pub positron_ns: Option<RObject>,
 
     pending_lines: Vec<String>,
+
+    /// Record of console executions, for exporting the session
+    transcript: Transcript,
+
+    /// Protection against output loops of the hooks run while idle
+    pub(crate) hook_guard: HookGuard,
+
+    /// Problems with the R library found at startup, see
+    /// `startup::check_library()`. Shown to the user once the UI comm
+    /// connects.
+    library_problems: Option<String>,
 }
 
 /// Represents the currently active execution request from the frontend. It
@@ -230,6 +266,11 @@ struct ActiveReadConsoleRequest {
     request: ExecuteRequest,
     originator: Originator,
     reply_tx: Sender<amalthea::Result<ExecuteReply>>,
+    /// Cumulative GC time in seconds when the request started
+    gc_time: Option<f64>,
+    /// Global state to restore once the request completes, for isolated
+    /// executions
+    state_snapshot: Option<RObject>,
 }
 
 /// Represents kernel metadata (available after the kernel has fully started)
@@ -239,6 +280,7 @@ pub struct KernelInfo {
     pub banner: String,
     pub input_prompt: Option<String>,
     pub continuation_prompt: Option<String>,
+    pub environment_fingerprint: Option<EnvironmentFingerprint>,
 }
 
 /// This struct represents the data that we wish R would pass to
@@ -300,7 +342,16 @@ impl RMain {
         kernel_request_rx: Receiver<KernelRequest>,
         dap: Arc<Mutex<Dap>>,
         session_mode: SessionMode,
+        read_only: bool,
+        driver_session: Option<String>,
     ) {
+        // Before the comms connect, so that their RPCs are checked from the
+        // start
+        session_access::initialize(SessionAccess {
+            read_only,
+            driver_session,
+        });
+
         // Set the main thread ID.
         // Must happen before doing anything that checks `RMain::on_main_thread()`,
         // like running an `r_task()` (posit-dev/positron#4973).
@@ -427,6 +478,19 @@ impl RMain {
             // Set up the global error handler (after support function initialization)
             errors::initialize();
 
+            // Load package metadata once the kernel is idle (after r_task
+            // initialization)
+            if user_config().startup.warmup_packages {
+                warmup::spawn();
+            }
+
+            // Report broken libraries, e.g. after an upgrade of R, before
+            // packages fail to load with cryptic errors. Runs once the kernel
+            // is idle (after r_task and support function initialization).
+            if user_config().startup.check_library {
+                startup::spawn_check_library();
+            }
+
             // Now that R has started (emitting any startup messages), and now that we have set
             // up all hooks and handlers, officially finish the R initialization process to
             // unblock the kernel-info request and also allow the LSP to start.
@@ -463,11 +527,40 @@ impl RMain {
         let input_prompt: String = harp::get_option("prompt").try_into().unwrap();
         let continuation_prompt: String = harp::get_option("continue").try_into().unwrap();
 
+        // Kernel messages follow the language of R's own messages
+        i18n::refresh();
+
+        let mut banner = R_BANNER.clone();
+        if session_access::is_read_only() {
+            banner.push_str(tr("read_only_banner"));
+            banner.push('\n');
+        }
+
+        // Identifies the environment in saved notebooks, see `fingerprint.rs`
+        let environment_fingerprint = if user_config().startup.environment_fingerprint {
+            match fingerprint::environment_fingerprint() {
+                Ok(fingerprint) => {
+                    banner.push_str(&format!(
+                        "\nEnvironment fingerprint: {} ({})\n",
+                        fingerprint.hash, fingerprint.source
+                    ));
+                    Some(fingerprint)
+                },
+                Err(err) => {
+                    log::error!("Can't compute the environment fingerprint: {err:?}");
+                    None
+                },
+            }
+        } else {
+            None
+        };
+
         let kernel_info = KernelInfo {
             version: version.clone(),
-            banner: R_BANNER.clone(),
+            banner,
             input_prompt: Some(input_prompt),
             continuation_prompt: Some(continuation_prompt),
+            environment_fingerprint,
         };
 
         log::info!("Sending kernel info: {version}");
@@ -501,6 +594,7 @@ impl RMain {
             active_request: None,
             execution_count: 0,
             autoprint_output: String::new(),
+            payloads: Vec::new(),
             ui_comm_tx: None,
             error_occurred: false,
             error_message: String::new(),
@@ -515,6 +609,9 @@ impl RMain {
             session_mode,
             positron_ns: None,
             pending_lines: Vec::new(),
+            transcript: Transcript::new(),
+            hook_guard: HookGuard::new(),
+            library_problems: None,
         }
     }
 
@@ -591,14 +688,25 @@ impl RMain {
         thread.id() == unsafe { R_MAIN_THREAD_ID.unwrap() }
     }
 
+    /// Number of execute requests handled so far
+    pub(crate) fn execution_count(&self) -> u32 {
+        self.execution_count
+    }
+
     /// Provides read-only access to `iopub_tx`
     pub fn get_iopub_tx(&self) -> &Sender<IOPubMessage> {
         &self.iopub_tx
     }
 
+    /// Attach a payload to the reply to the current execution
+    pub(crate) fn push_payload(&mut self, payload: serde_json::Value) {
+        self.payloads.push(payload);
+    }
+
     fn init_execute_request(&mut self, req: &ExecuteRequest) -> (ConsoleInput, u32) {
-        // Reset the autoprint buffer
+        // Reset the autoprint buffer and the payloads
         self.autoprint_output = String::new();
+        self.payloads = Vec::new();
 
         // Increment counter if we are storing this execution in history
         if req.store_history {
@@ -702,6 +810,14 @@ impl RMain {
             self.refresh_lsp();
         }
 
+        // If the last request was interrupted, comms might have missed
+        // updates. Give them a chance to reconcile with the R state. We wait
+        // for a top-level prompt as the interrupt may have been delivered to
+        // a `readline()` prompt that is now being unwound.
+        if !info.browser && !info.incomplete && !info.input_request && take_interrupt_requested() {
+            EVENTS.console_interrupted.emit(());
+        }
+
         // Signal prompt
         EVENTS.console_prompt.emit(());
 
@@ -863,6 +979,10 @@ impl RMain {
         // the rest of the pending lines.
         if info.input_request {
             if let Some(req) = &self.active_request {
+                if !req.request.allow_stdin {
+                    return Some(self.handle_denied_input_request(&info.input_prompt));
+                }
+
                 // Send request to frontend. We'll wait for an `input_reply`
                 // from the frontend in the event loop in `read_console()`.
                 // The active request remains active.
@@ -888,11 +1008,26 @@ impl RMain {
             return Some(console_result);
         }
 
+        // Tell the user about packages that were reinstalled by the last
+        // execution, as part of its output. This only checks modification
+        // times and never waits on the frontend, so it doesn't hold up the
+        // reply.
+        if self.active_request.is_some() && !info.browser {
+            self.check_stale_namespaces();
+        }
+
         // Finally, check if we have an active request from a previous `read_console()`
         // iteration. If so, we `take()` and clear the `active_request` as we're about
         // to complete it and send a reply to unblock the active Shell
         // request.
-        if let Some(req) = std::mem::take(&mut self.active_request) {
+        if let Some(mut req) = std::mem::take(&mut self.active_request) {
+            // Restore the global state of isolated executions before the
+            // frontend is refreshed, so that it sees the restored working
+            // directory
+            if let Some(snapshot) = req.state_snapshot.take() {
+                cell_state::restore(snapshot);
+            }
+
             // FIXME: Race condition between the comm and shell socket threads.
             //
             // Perform a refresh of the frontend state
@@ -907,13 +1042,16 @@ impl RMain {
             // Check for pending graphics updates
             // (Important that this occurs while in the "busy" state of this ExecuteRequest
             // so that the `parent` message is set correctly in any Jupyter messages)
-            unsafe {
+            let new_plots = unsafe {
                 graphics_device::on_did_execute_request(
                     self.comm_manager_tx.clone(),
                     self.iopub_tx.clone(),
                     self.is_ui_comm_connected() && self.session_mode == SessionMode::Console,
                 )
             };
+            for id in new_plots.iter() {
+                self.transcript.push_plot(id);
+            }
 
             // Let frontend know the last request is complete. This turns us
             // back to Idle.
@@ -937,15 +1075,45 @@ impl RMain {
 
         let input = match req {
             RRequest::ExecuteCode(exec_req, originator, reply_tx) => {
+                task_queue::start_execution();
+
+                // Give hooks disabled by an output loop another chance
+                if self.hook_guard.reset() {
+                    log::info!("Re-enabling event loop callbacks");
+                }
+
+                if let Err(err) = session_access::check_client(&originator.header.session) {
+                    match err {
+                        AccessDenied::ReadOnly => {
+                            self.reject_execute_request(&exec_req, err, reply_tx)
+                        },
+                        AccessDenied::Observer => {
+                            self.reject_observer_execute_request(&exec_req, err, reply_tx)
+                        },
+                    }
+                    return None;
+                }
+
                 // Extract input from request
                 let (input, exec_count) = { self.init_execute_request(&exec_req) };
 
+                // Record user executions in the session transcript
+                if !exec_req.silent && exec_req.store_history {
+                    self.transcript.start_entry(&exec_req.code);
+                }
+
+                let state_snapshot = cell_state::is_isolated(&exec_req)
+                    .then(cell_state::snapshot)
+                    .flatten();
+
                 // Save `ExecuteCode` request so we can respond to it at next prompt
                 self.active_request = Some(ActiveReadConsoleRequest {
                     exec_count,
                     request: exec_req,
                     originator,
                     reply_tx,
+                    gc_time: r_gc_time(),
+                    state_snapshot,
                 });
 
                 input
@@ -1001,6 +1169,62 @@ impl RMain {
         }
     }
 
+    /// Reply to an execute request with an error without evaluating the code.
+    /// Used in read-only sessions.
+    fn reject_execute_request(
+        &mut self,
+        req: &ExecuteRequest,
+        err: AccessDenied,
+        reply_tx: Sender<amalthea::Result<ExecuteReply>>,
+    ) {
+        let (_, exec_count) = self.init_execute_request(req);
+
+        let mut exception = Exception {
+            ename: String::from(""),
+            evalue: String::from(err.execution_message()),
+            traceback: vec![],
+        };
+
+        // See `make_execute_reply_error()`
+        if let SessionMode::Notebook = self.session_mode {
+            exception.traceback.insert(0, exception.evalue.clone())
+        }
+
+        if !req.silent {
+            let result = IOPubMessage::ExecuteError(ExecuteError {
+                exception: exception.clone(),
+            });
+            self.iopub_tx.send(result).unwrap();
+        }
+
+        reply_tx
+            .send(new_execute_reply_error(exception, exec_count))
+            .unwrap();
+    }
+
+    /// Reply to an execute request of an observer of a shared session with an
+    /// error. Unlike `reject_execute_request()`, nothing is broadcast on IOPub
+    /// so the code of observers doesn't show up in the other clients, and the
+    /// execution count is left untouched.
+    fn reject_observer_execute_request(
+        &mut self,
+        req: &ExecuteRequest,
+        err: AccessDenied,
+        reply_tx: Sender<amalthea::Result<ExecuteReply>>,
+    ) {
+        log::info!("Rejecting execute request of an observer: {}", req.code);
+
+        let exception = Exception {
+            ename: String::from(""),
+            evalue: String::from(err.execution_message()),
+            traceback: vec![],
+        };
+
+        reply_tx
+            .send(new_execute_reply_error(exception, self.execution_count))
+            .unwrap();
+    }
+
     /// Handle an `input_request` received outside of an `execute_request` context
     ///
     /// We believe it is always invalid to receive an `input_request` that isn't
@@ -1035,6 +1259,42 @@ impl RMain {
         return ConsoleResult::Error(Error::InvalidInputRequest(message));
     }
 
+    /// Handle an `input_request` within an `execute_request` that doesn't
+    /// allow them, e.g. a notebook cell executed with `allow_stdin: false`
+    ///
+    /// The frontend wouldn't answer an input request so we don't send one.
+    /// Instead we warn about the call that requested input and how to avoid
+    /// it, both on stderr and in the kernel event log, and throw an R error
+    /// so the execution fails rather than hangs.
+    fn handle_denied_input_request(&self, prompt: &str) -> ConsoleResult {
+        let call = input_request_call();
+        log::info!("Denied `input_request` of {call:?} as the execution doesn't allow stdin.");
+
+        let origin = match &call {
+            Some(call) => format!("`{call}`"),
+            None => String::from("The code"),
+        };
+        let warning = format!(
+            "Warning: {origin} requested input from the user with prompt {prompt:?}, but this frontend doesn't accept input requests during this execution.\n\
+             Supply the answer in the code instead, for instance with an argument or an option, or run the code in a console.\n"
+        );
+        self.iopub_tx
+            .send(IOPubMessage::Stream(StreamOutput {
+                name: Stream::Stderr,
+                text: warning,
+            }))
+            .unwrap();
+
+        event_log::record(
+            KernelEventKind::InputDenied,
+            None,
+            Some(call.unwrap_or_else(|| String::from(prompt))),
+        );
+
+        let message = String::from("Can't request input from the user in this execution.");
+        ConsoleResult::Error(Error::InvalidInputRequest(message))
+    }
+
     fn in_renv_autoloader() -> bool {
         harp::get_option("renv.autoloader.running")
             .try_into()
@@ -1107,16 +1367,19 @@ impl RMain {
         match task {
             RTask::Sync(task) => {
                 // Immediately let caller know we have started so it can set up the
-                // timeout
+                // timeout. The caller may have given up waiting, see
+                // `r_task_timeout()`.
                 if let Some(ref status_tx) = task.status_tx {
-                    status_tx.send(RTaskStatus::Started).unwrap();
+                    let _ = status_tx.send(RTaskStatus::Started);
                 }
 
+                task_queue::start(task.start_info.queue_id);
                 let result = task.start_info.span.in_scope(|| r_sandbox(task.fun));
+                task_queue::finish(task.start_info.queue_id);
 
                 // Unblock caller via the notification channel
                 if let Some(ref status_tx) = task.status_tx {
-                    status_tx.send(RTaskStatus::Finished(result)).unwrap()
+                    let _ = status_tx.send(RTaskStatus::Finished(result));
                 }
 
                 Some(task.start_info)
@@ -1148,6 +1411,8 @@ impl RMain {
             None => self.pending_futures.remove(&waker.id).unwrap(),
         };
 
+        task_queue::start(start_info.queue_id);
+
         let awaker = waker.clone().into();
         let mut ctxt = &mut std::task::Context::from_waker(&awaker);
 
@@ -1158,10 +1423,12 @@ impl RMain {
         {
             Poll::Ready(()) => {
                 start_info.bump_elapsed(tick.elapsed());
+                task_queue::finish(start_info.queue_id);
                 Some(start_info)
             },
             Poll::Pending => {
                 start_info.bump_elapsed(tick.elapsed());
+                task_queue::park(start_info.queue_id);
                 self.pending_futures.insert(waker.id, (fut, start_info));
                 None
             },
@@ -1197,6 +1464,34 @@ impl RMain {
 
             ui_comm_tx.send_refresh(input_prompt, continuation_prompt);
         });
+
+        // Problems found before the UI comm connected
+        if let Some(message) = self.library_problems.take() {
+            self.show_library_problems(message);
+        }
+    }
+
+    /// Ask the frontend to restart the session. This is an event of the UI
+    /// comm rather than a frontend method, so it doesn't need an active
+    /// request.
+    pub(crate) fn request_restart(&self) -> anyhow::Result<()> {
+        let ui_comm_tx = self
+            .get_ui_comm_tx()
+            .ok_or_else(|| anyhow::anyhow!("Can't restart R: the UI comm is not connected"))?;
+
+        ui_comm_tx.send_event(UiFrontendEvent::RestartSession);
+        Ok(())
+    }
+
+    /// Show problems with the R library to the user, or keep them until the
+    /// UI comm connects
+    pub(crate) fn show_library_problems(&mut self, message: String) {
+        match self.get_ui_comm_tx() {
+            Some(ui_comm_tx) => {
+                ui_comm_tx.send_event(UiFrontendEvent::ShowMessage(ShowMessageParams { message }))
+            },
+            None => self.library_problems = Some(message),
+        }
     }
 
     pub fn get_ui_comm_tx(&self) -> Option<&UiCommSender> {
@@ -1235,10 +1530,16 @@ impl RMain {
         }
     }
 
-    fn is_ui_comm_connected(&self) -> bool {
+    pub(crate) fn is_ui_comm_connected(&self) -> bool {
         self.get_ui_comm_tx().is_some()
     }
 
+    /// Whether the code of an execute request is running, as opposed to R
+    /// waiting for input at the prompt
+    pub(crate) fn is_executing(&self) -> bool {
+        self.active_request.is_some()
+    }
+
     fn handle_pending_line(&mut self, buf: *mut c_uchar, buflen: c_int) -> Option<ConsoleResult> {
         if self.error_occurred {
             // If an error has occurred, we've already sent a complete expression that resulted in
@@ -1352,11 +1653,20 @@ impl RMain {
         ))
     }
 
+    fn check_stale_namespaces(&self) {
+        if let Err(err) = RFunction::from(".ps.packages.checkStaleNamespaces").call() {
+            log::error!("Failed to check for stale namespaces: {err:?}");
+        }
+    }
+
     // Reply to the previously active request. The current prompt type and
     // whether an error has occurred defines the reply kind.
     fn reply_execute_request(&mut self, req: ActiveReadConsoleRequest, prompt_info: &PromptInfo) {
         let prompt = &prompt_info.input_prompt;
 
+        // The code might have changed `LANGUAGE` or the locale
+        i18n::refresh();
+
         let (reply, result) = if prompt_info.incomplete {
             log::trace!("Got prompt {} signaling incomplete request", prompt);
             (new_incomplete_reply(&req.request, req.exec_count), None)
@@ -1366,13 +1676,28 @@ impl RMain {
             log::trace!("Got R prompt '{}', completing execution", prompt);
 
             self.make_execute_reply_error(req.exec_count)
-                .unwrap_or_else(|| self.make_execute_reply(req.exec_count))
+                .unwrap_or_else(|| self.make_execute_reply(req.exec_count, &req.request.code))
         };
 
         if let Some(result) = result {
             self.iopub_tx.send(result).unwrap();
         }
 
+        // Record executions that spent a long time in garbage collection in
+        // the kernel event log
+        if let (Some(start), Some(end)) = (req.gc_time, r_gc_time()) {
+            let gc_time = Duration::from_secs_f64((end - start).max(0.0));
+            if gc_time >= GC_TIME_THRESHOLD {
+                event_log::record(
+                    KernelEventKind::GcTime,
+                    Some(gc_time),
+                    Some(format!("During execution {}", req.exec_count)),
+                );
+            }
+        }
+
+        self.transcript.end_entry();
+
         log::trace!("Sending `execute_reply`: {reply:?}");
         req.reply_tx.send(reply).unwrap();
     }
@@ -1434,6 +1759,8 @@ impl RMain {
             exception.traceback.insert(0, exception.evalue.clone())
         }
 
+        self.transcript.push_error(&exception.evalue);
+
         let reply = new_execute_reply_error(exception.clone(), exec_count);
         let result = IOPubMessage::ExecuteError(ExecuteError { exception });
 
@@ -1443,13 +1770,10 @@ impl RMain {
     fn make_execute_reply(
         &mut self,
         exec_count: u32,
+        code: &str,
     ) -> (amalthea::Result<ExecuteReply>, Option<IOPubMessage>) {
-        // TODO: Implement rich printing of certain outputs.
-        // Will we need something similar to the RStudio model,
-        // where we implement custom print() methods? Or can
-        // we make the stub below behave sensibly even when
-        // streaming R output?
         let mut data = serde_json::Map::new();
+        let mut metadata = serde_json::Map::new();
 
         // The output generated by autoprint is emitted as an
         // `execute_result` message.
@@ -1461,7 +1785,8 @@ impl RMain {
             // mutable self ref across calling methods to avoid the clone?
             autoprint.pop();
         }
-        if autoprint.len() != 0 {
+        let printed = autoprint.len() != 0;
+        if printed {
             data.insert("text/plain".to_string(), json!(autoprint));
         }
 
@@ -1477,15 +1802,36 @@ impl RMain {
                     },
                 };
             }
+
+            // Rich representations of the value, see `.ps.repr_mimebundle()`.
+            // They take precedence over the defaults.
+            if printed {
+                match repr_mimebundle(value) {
+                    Ok(bundle) => data.extend(bundle),
+                    Err(err) => log::error!("Can't represent the value of the execution: {err:?}"),
+                }
+            }
+
+            // Describe printed tibbles and data.tables so the console can
+            // render column types and link to the data viewer
+            if printed {
+                match printed_table(value, exec_count, code) {
+                    Ok(Some(table)) => {
+                        metadata.insert("positron".to_string(), json!({ "table": table }));
+                    },
+                    Ok(None) => {},
+                    Err(err) => log::error!("Can't describe printed table: {err:?}"),
+                }
+            }
         }
 
-        let reply = new_execute_reply(exec_count);
+        let reply = new_execute_reply(exec_count, std::mem::take(&mut self.payloads));
 
         let result = (data.len() > 0).then(|| {
             IOPubMessage::ExecuteResult(ExecuteResult {
                 execution_count: exec_count,
                 data: serde_json::Value::Object(data),
-                metadata: json!({}),
+                metadata: serde_json::Value::Object(metadata),
             })
         });
 
@@ -1557,6 +1903,12 @@ impl RMain {
 
         let r_main = RMain::get_mut();
 
+        // Drop the output of hooks caught in an output loop
+        if features::is_enabled(&HOOK_GUARD) && !r_main.hook_guard.output(content.len()) {
+            r_main.report_hook_loop();
+            return;
+        }
+
         // To capture the current `debug: <call>` output, for use in the debugger's
         // match based fallback
         r_main.dap.handle_stdout(&content);
@@ -1575,6 +1927,9 @@ impl RMain {
             }
         }
 
+        r_main.transcript.push_output(&content);
+        task_queue::record_output();
+
         if stream == Stream::Stdout && is_auto_printing() {
             // If we are at top-level, we're handling visible output auto-printed by
             // the R REPL. We accumulate this output (it typically comes in multiple
@@ -1625,6 +1980,24 @@ impl RMain {
         r_main.iopub_tx.send(message).unwrap();
     }
 
+    /// Tell the user about an output loop caused by hooks, once
+    fn report_hook_loop(&mut self) {
+        let Some(report) = self.hook_guard.take_report() else {
+            return;
+        };
+        let message = report.message();
+
+        let stream = IOPubMessage::Stream(StreamOutput {
+            name: Stream::Stderr,
+            text: format!("{message}\n"),
+        });
+        self.iopub_tx.send(stream).unwrap();
+
+        self.with_ui_comm_tx(|ui_comm_tx| {
+            ui_comm_tx.send_event(UiFrontendEvent::ShowMessage(ShowMessageParams { message }))
+        });
+    }
+
     /// Invoked by R to change busy state
     fn busy(&mut self, which: i32) {
         // Ensure signal handlers are initialized.
@@ -1682,20 +2055,29 @@ impl RMain {
     }
 
     unsafe fn process_events() {
-        // Process regular R events. We're normally running with polled
-        // events disabled so that won't run here. We also run with
-        // interrupts disabled, so on Windows those won't get run here
-        // either (i.e. if `UserBreak` is set), but it will reset `UserBreak`
-        // so we need to ensure we handle interrupts right before calling
-        // this.
-        R_ProcessEvents();
-
-        crate::sys::interface::run_activity_handlers();
-
-        // Run pending finalizers. We need to do this eagerly as otherwise finalizers
-        // might end up being executed on the LSP thread.
-        // https://github.com/rstudio/positron/issues/431
-        R_RunPendingFinalizers();
+        // Hooks are skipped while disabled by an output loop, see `HookGuard`
+        let guarded = features::is_enabled(&HOOK_GUARD);
+        if !guarded || RMain::get_mut().hook_guard.enter() {
+            // Exit the hook run even if a hook panics
+            let _run = if guarded { Some(HookRun) } else { None };
+
+            // Process regular R events. We're normally running with polled
+            // events disabled so that won't run here. We also run with
+            // interrupts disabled, so on Windows those won't get run here
+            // either (i.e. if `UserBreak` is set), but it will reset `UserBreak`
+            // so we need to ensure we handle interrupts right before calling
+            // this.
+            R_ProcessEvents();
+
+            crate::sys::interface::run_activity_handlers();
+
+            // Run pending finalizers. We need to do this eagerly as otherwise finalizers
+            // might end up being executed on the LSP thread.
+            // https://github.com/rstudio/positron/issues/431
+            R_RunPendingFinalizers();
+        }
+        crate::sys::interface::remove_looping_handler();
+        RMain::get_mut().report_hook_loop();
 
         // Check for Positron render requests
         graphics_device::on_process_events();
@@ -1723,6 +2105,14 @@ impl RMain {
         Ok(())
     }
 
+    pub(crate) fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
+    pub(crate) fn is_help_connected(&self) -> bool {
+        self.help_event_tx.is_some()
+    }
+
     pub(crate) fn is_help_url(&self, url: &str) -> bool {
         let Some(port) = self.help_port else {
             log::error!("No help port is available to check if '{url}' is a help url. Is the help comm open?");
@@ -1733,7 +2123,7 @@ impl RMain {
         RHelp::is_help_url(url, port)
     }
 
-    fn send_lsp_notification(&self, event: KernelNotification) {
+    pub(crate) fn send_lsp_notification(&self, event: KernelNotification) {
         if let Some(ref tx) = self.lsp_events_tx {
             tx.send(Event::Kernel(event)).unwrap();
         }
@@ -1781,7 +2171,7 @@ impl RMain {
         });
 
         // Block for reply
-        let reply = reply_rx.recv().unwrap();
+        let reply = wait::UI_FRONTEND_REPLY.recv(&reply_rx)?;
 
         log::trace!("Got reply from frontend method: {reply:?}");
 
@@ -1797,7 +2187,7 @@ impl RMain {
                     }
 
                     // Now deserialize to an R object
-                    Ok(RObject::try_from(reply.result)?)
+                    Ok(RObject::from_json(reply.result)?)
                 },
                 JsonRpcReply::Error(reply) => {
                     let message = reply.error.message;
@@ -1816,11 +2206,20 @@ impl RMain {
     }
 }
 
+/// A run of hooks, see `HookGuard`. Exits the run when dropped.
+struct HookRun;
+
+impl Drop for HookRun {
+    fn drop(&mut self) {
+        RMain::get_mut().hook_guard.exit();
+    }
+}
+
 /// Report an incomplete request to the frontend
 fn new_incomplete_reply(req: &ExecuteRequest, exec_count: u32) -> amalthea::Result<ExecuteReply> {
     let error = Exception {
         ename: "IncompleteInput".to_string(),
-        evalue: format!("Code fragment is not complete: {}", req.code),
+        evalue: tr("incomplete_input").replace("%s", &req.code),
         traceback: vec![],
     };
     Err(amalthea::Error::ShellErrorExecuteReply(error, exec_count))
@@ -1829,11 +2228,31 @@ fn new_incomplete_reply(req: &ExecuteRequest, exec_count: u32) -> amalthea::Resu
 static RE_STACK_OVERFLOW: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"C stack usage [ 0-9]+ is too close to the limit\n").unwrap());
 
-fn new_execute_reply(exec_count: u32) -> amalthea::Result<ExecuteReply> {
+/// Time spent in garbage collection during an execution above which it is
+/// recorded in the kernel event log. This is the total time of all the
+/// collections of the execution, not the length of a single pause.
+const GC_TIME_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// Cumulative time spent in garbage collection by the session, in seconds.
+/// `gc.time()` enables GC timing as a side effect so this starts counting at
+/// the first execution.
+fn r_gc_time() -> Option<f64> {
+    let time = RFunction::new("base", "gc.time").call().ok()?;
+    let time = Vec::<f64>::try_from(&time).ok()?;
+
+    // The elapsed time is the third component
+    time.get(2).copied()
+}
+
+fn new_execute_reply(
+    exec_count: u32,
+    payload: Vec<serde_json::Value>,
+) -> amalthea::Result<ExecuteReply> {
     Ok(ExecuteReply {
         status: Status::Ok,
         execution_count: exec_count,
         user_expressions: json!({}),
+        payload,
     })
 }
 
@@ -1852,6 +2271,17 @@ fn to_html(frame: SEXP) -> Result<String> {
     }
 }
 
+/// The call that requested input from the user, deparsed, see
+/// `input_request_call()` on the R side
+fn input_request_call() -> Option<String> {
+    let calls = harp::session::r_sys_calls().ok()?;
+    let call = RFunction::new("", "input_request_call")
+        .add(calls)
+        .call_in(ARK_ENVS.positron_ns)
+        .ok()?;
+    call.try_into().ok()
+}
+
 // Inputs generated by `ReadConsole` for the LSP
 pub(crate) fn console_inputs() -> anyhow::Result<ConsoleInputs> {
     // TODO: Should send the debug environment if debugging:
@@ -1865,9 +2295,15 @@ pub(crate) fn console_inputs() -> anyhow::Result<ConsoleInputs> {
         .call()?
         .try_into()?;
 
+    // Get the set of documented and currently set options
+    let known_options: Vec<String> = RFunction::from(".ps.completions.optionNames")
+        .call()?
+        .try_into()?;
+
     Ok(ConsoleInputs {
         console_scopes: scopes,
         installed_packages,
+        known_options,
     })
 }
 
@@ -14,7 +14,7 @@ pub unsafe extern "C" fn ps_to_json(obj: SEXP) -> anyhow::Result<SEXP> {
     let obj = RObject::view(obj);
 
     // Convert the object to a JSON value; this is the core serialization step
-    let val = serde_json::Value::try_from(obj)?;
+    let val = obj.try_to_json()?;
 
     // Format the JSON value as a string for display
     let json = serde_json::to_string_pretty(&val)?;
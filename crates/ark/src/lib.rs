@@ -7,15 +7,23 @@
 
 pub mod analysis;
 pub mod browser;
+pub mod cell_state;
 pub mod connections;
 pub mod control;
 pub mod coordinates;
 pub mod dap;
 pub mod data_explorer;
+pub mod data_import;
 pub mod errors;
+pub mod export_object;
+pub mod features;
+pub mod fingerprint;
 pub mod fixtures;
 pub mod help;
 pub mod help_proxy;
+pub mod hook_guard;
+pub mod i18n;
+pub mod inspect;
 pub mod interface;
 pub mod json;
 pub mod logger;
@@ -24,10 +32,14 @@ pub mod lsp;
 pub mod methods;
 pub mod modules;
 pub mod modules_utils;
+pub mod pager;
 pub mod plots;
+pub mod printed_table;
 pub mod r_task;
+pub mod repr;
 pub mod request;
 pub mod reticulate;
+pub mod session_access;
 pub mod shell;
 pub mod signals;
 pub mod srcref;
@@ -35,13 +47,19 @@ pub mod start;
 pub mod startup;
 pub mod strings;
 pub mod sys;
+pub mod task_queue;
+pub mod teardown;
 pub mod thread;
+pub mod transcript;
 pub mod traps;
 pub mod treesitter;
 pub mod ui;
+pub mod user_config;
 pub mod variables;
 pub mod version;
 pub mod viewer;
+pub mod wait;
+pub mod warmup;
 
 pub(crate) use r_task::r_task;
 
@@ -18,18 +18,28 @@ use tracing_subscriber::Layer;
 
 use crate::logger_hprof;
 
-pub fn init(log_file: Option<&str>, profile_file: Option<&str>) {
+/// Initialize the logger
+///
+/// - `log_level`: Verbosity of Ark's log, from `--log-level` or the user
+///   settings. Takes precedence over `RUST_LOG`.
+pub fn init(log_file: Option<&str>, profile_file: Option<&str>, log_level: Option<&str>) {
     static ONCE: Once = Once::new();
 
     ONCE.call_once(|| {
-        // Parse `RUST_LOG`
-        let mut env_filter = EnvFilter::from_default_env();
+        // Parse `RUST_LOG`, unless a level was supplied
+        let mut env_filter = match log_level {
+            Some(level) => EnvFilter::new(format!("ark={level}")),
+            None => EnvFilter::from_default_env(),
+        };
 
         // Propagate 'ark' verbosity to internal crates
         let re = Regex::new(r"ark=([a-zA-Z]+)(,|$)").unwrap();
-        let rust_log = std::env::var("RUST_LOG")
-            .ok()
-            .unwrap_or_else(|| String::from("ark=info"));
+        let rust_log = match log_level {
+            Some(level) => format!("ark={level}"),
+            None => std::env::var("RUST_LOG")
+                .ok()
+                .unwrap_or_else(|| String::from("ark=info")),
+        };
         if let Some(level) = re
             .captures(&rust_log)
             .and_then(|c| c.get(1))
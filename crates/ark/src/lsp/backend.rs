@@ -7,18 +7,27 @@
 
 #![allow(deprecated)]
 
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::LazyLock;
 
 use crossbeam::channel::Sender;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
 use serde_json::Value;
 use stdext::result::ResultOrLog;
 use tokio::net::TcpListener;
+use tokio::net::TcpStream;
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc::unbounded_channel as tokio_unbounded_channel;
+use tokio::sync::Notify;
 use tower_lsp::jsonrpc;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::request::GotoImplementationParams;
 use tower_lsp::lsp_types::request::GotoImplementationResponse;
+use tower_lsp::lsp_types::request::GotoTypeDefinitionParams;
+use tower_lsp::lsp_types::request::GotoTypeDefinitionResponse;
 use tower_lsp::lsp_types::SelectionRange;
 use tower_lsp::lsp_types::*;
 use tower_lsp::Client;
@@ -27,6 +36,9 @@ use tower_lsp::LspService;
 use tower_lsp::Server;
 
 use crate::interface::RMain;
+use crate::lsp::brackets;
+use crate::lsp::brackets::BracketsParams;
+use crate::lsp::brackets::BracketsResponse;
 use crate::lsp::handlers::VirtualDocumentParams;
 use crate::lsp::handlers::VirtualDocumentResponse;
 use crate::lsp::handlers::ARK_VDOC_REQUEST;
@@ -36,12 +48,18 @@ use crate::lsp::help_topic::HelpTopicResponse;
 use crate::lsp::input_boundaries;
 use crate::lsp::input_boundaries::InputBoundariesParams;
 use crate::lsp::input_boundaries::InputBoundariesResponse;
+use crate::lsp::main_loop::ClientEvent;
 use crate::lsp::main_loop::Event;
-use crate::lsp::main_loop::GlobalState;
 use crate::lsp::main_loop::TokioUnboundedSender;
+use crate::lsp::session::ClientId;
+use crate::lsp::session::LspSession;
 use crate::lsp::statement_range;
 use crate::lsp::statement_range::StatementRangeParams;
 use crate::lsp::statement_range::StatementRangeResponse;
+use crate::lsp::virtual_documents;
+use crate::lsp::virtual_documents::VirtualDocumentDidChangeParams;
+use crate::lsp::virtual_documents::VirtualDocumentDidCloseParams;
+use crate::lsp::virtual_documents::VirtualDocumentDidOpenParams;
 use crate::r_task;
 
 // Based on https://stackoverflow.com/a/69324393/1725177
@@ -74,6 +92,10 @@ pub(crate) enum LspNotification {
     DidChangeTextDocument(DidChangeTextDocumentParams),
     DidSaveTextDocument(DidSaveTextDocumentParams),
     DidCloseTextDocument(DidCloseTextDocumentParams),
+    WorkDoneProgressCancel(WorkDoneProgressCancelParams),
+    DidOpenVirtualDocument(VirtualDocumentDidOpenParams),
+    DidChangeVirtualDocument(VirtualDocumentDidChangeParams),
+    DidCloseVirtualDocument(VirtualDocumentDidCloseParams),
 }
 
 #[derive(Debug)]
@@ -88,14 +110,24 @@ pub(crate) enum LspRequest {
     Hover(HoverParams),
     SignatureHelp(SignatureHelpParams),
     GotoDefinition(GotoDefinitionParams),
+    GotoTypeDefinition(GotoTypeDefinitionParams),
     GotoImplementation(GotoImplementationParams),
     SelectionRange(SelectionRangeParams),
+    FoldingRange(FoldingRangeParams),
+    SemanticTokensFull(SemanticTokensParams),
     References(ReferenceParams),
+    PrepareRename(TextDocumentPositionParams),
+    Rename(RenameParams),
+    CodeLens(CodeLensParams),
+    CodeAction(CodeActionParams),
     StatementRange(StatementRangeParams),
     HelpTopic(HelpTopicParams),
     OnTypeFormatting(DocumentOnTypeFormattingParams),
+    Formatting(DocumentFormattingParams),
+    RangeFormatting(DocumentRangeFormattingParams),
     VirtualDocument(VirtualDocumentParams),
     InputBoundaries(InputBoundariesParams),
+    Brackets(BracketsParams),
 }
 
 #[derive(Debug)]
@@ -110,24 +142,33 @@ pub(crate) enum LspResponse {
     Hover(Option<Hover>),
     SignatureHelp(Option<SignatureHelp>),
     GotoDefinition(Option<GotoDefinitionResponse>),
+    GotoTypeDefinition(Option<GotoTypeDefinitionResponse>),
     GotoImplementation(Option<GotoImplementationResponse>),
     SelectionRange(Option<Vec<SelectionRange>>),
+    FoldingRange(Option<Vec<FoldingRange>>),
+    SemanticTokensFull(Option<SemanticTokensResult>),
     References(Option<Vec<Location>>),
+    PrepareRename(Option<PrepareRenameResponse>),
+    Rename(Option<WorkspaceEdit>),
+    CodeLens(Option<Vec<CodeLens>>),
+    CodeAction(Option<CodeActionResponse>),
     StatementRange(Option<StatementRangeResponse>),
     HelpTopic(Option<HelpTopicResponse>),
     OnTypeFormatting(Option<Vec<TextEdit>>),
+    Formatting(Option<Vec<TextEdit>>),
+    RangeFormatting(Option<Vec<TextEdit>>),
     VirtualDocument(VirtualDocumentResponse),
     InputBoundaries(InputBoundariesResponse),
+    Brackets(BracketsResponse),
 }
 
 #[derive(Debug)]
 struct Backend {
-    /// Channel for communication with the main loop.
-    events_tx: TokioUnboundedSender<Event>,
+    /// Identifies the connection in the session
+    client_id: ClientId,
 
-    /// Handle to main loop. Drop it to cancel the loop, all associated tasks,
-    /// and drop all owned state.
-    _main_loop: tokio::task::JoinSet<()>,
+    /// Channel for communication with the main loop of the session.
+    events_tx: TokioUnboundedSender<Event>,
 }
 
 impl Backend {
@@ -137,7 +178,10 @@ impl Backend {
 
         // Relay request to main loop
         self.events_tx
-            .send(Event::Lsp(LspMessage::Request(request, response_tx)))
+            .send(Event::Lsp(
+                self.client_id,
+                LspMessage::Request(request, response_tx),
+            ))
             .unwrap();
 
         // Wait for response from main loop
@@ -147,7 +191,7 @@ impl Backend {
     fn notify(&self, notif: LspNotification) {
         // Relay notification to main loop
         self.events_tx
-            .send(Event::Lsp(LspMessage::Notification(notif)))
+            .send(Event::Lsp(self.client_id, LspMessage::Notification(notif)))
             .unwrap();
     }
 }
@@ -230,6 +274,10 @@ impl LanguageServer for Backend {
         self.notify(LspNotification::DidCloseTextDocument(params));
     }
 
+    async fn work_done_progress_cancel(&self, params: WorkDoneProgressCancelParams) {
+        self.notify(LspNotification::WorkDoneProgressCancel(params));
+    }
+
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
         cast_response!(
             self.request(LspRequest::Completion(params)).await,
@@ -268,6 +316,16 @@ impl LanguageServer for Backend {
         )
     }
 
+    async fn goto_type_definition(
+        &self,
+        params: GotoTypeDefinitionParams,
+    ) -> Result<Option<GotoTypeDefinitionResponse>> {
+        cast_response!(
+            self.request(LspRequest::GotoTypeDefinition(params)).await,
+            LspResponse::GotoTypeDefinition
+        )
+    }
+
     async fn goto_implementation(
         &self,
         params: GotoImplementationParams,
@@ -288,6 +346,37 @@ impl LanguageServer for Backend {
         )
     }
 
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        cast_response!(
+            self.request(LspRequest::FoldingRange(params)).await,
+            LspResponse::FoldingRange
+        )
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        cast_response!(
+            self.request(LspRequest::SemanticTokensFull(params)).await,
+            LspResponse::SemanticTokensFull
+        )
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        cast_response!(
+            self.request(LspRequest::CodeLens(params)).await,
+            LspResponse::CodeLens
+        )
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        cast_response!(
+            self.request(LspRequest::CodeAction(params)).await,
+            LspResponse::CodeAction
+        )
+    }
+
     async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
         cast_response!(
             self.request(LspRequest::References(params)).await,
@@ -295,6 +384,23 @@ impl LanguageServer for Backend {
         )
     }
 
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        cast_response!(
+            self.request(LspRequest::PrepareRename(params)).await,
+            LspResponse::PrepareRename
+        )
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        cast_response!(
+            self.request(LspRequest::Rename(params)).await,
+            LspResponse::Rename
+        )
+    }
+
     async fn on_type_formatting(
         &self,
         params: DocumentOnTypeFormattingParams,
@@ -304,6 +410,23 @@ impl LanguageServer for Backend {
             LspResponse::OnTypeFormatting
         )
     }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        cast_response!(
+            self.request(LspRequest::Formatting(params)).await,
+            LspResponse::Formatting
+        )
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        cast_response!(
+            self.request(LspRequest::RangeFormatting(params)).await,
+            LspResponse::RangeFormatting
+        )
+    }
 }
 
 // Custom methods for the backend.
@@ -362,77 +485,174 @@ impl Backend {
         )
     }
 
+    async fn brackets(
+        &self,
+        params: BracketsParams,
+    ) -> tower_lsp::jsonrpc::Result<BracketsResponse> {
+        cast_response!(
+            self.request(LspRequest::Brackets(params)).await,
+            LspResponse::Brackets
+        )
+    }
+
+    async fn did_open_virtual_document(&self, params: VirtualDocumentDidOpenParams) {
+        self.notify(LspNotification::DidOpenVirtualDocument(params));
+    }
+
+    async fn did_change_virtual_document(&self, params: VirtualDocumentDidChangeParams) {
+        self.notify(LspNotification::DidChangeVirtualDocument(params));
+    }
+
+    async fn did_close_virtual_document(&self, params: VirtualDocumentDidCloseParams) {
+        self.notify(LspNotification::DidCloseVirtualDocument(params));
+    }
+
     async fn notification(&self, params: Option<Value>) {
         log::info!("Received Positron notification: {:?}", params);
     }
 }
 
-pub fn start_lsp(runtime: Arc<Runtime>, address: String, conn_init_tx: Sender<bool>) {
+/// Notified by `stop_lsp()`
+static LSP_STOP: LazyLock<Notify> = LazyLock::new(Notify::new);
+static LSP_STOPPED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn start_lsp(
+    runtime: Arc<Runtime>,
+    session: Arc<LspSession>,
+    address: String,
+    conn_init_tx: Sender<bool>,
+) {
+    // Forward event channel along to `RMain`. `RMain` should be initialized
+    // by now, since the caller of this function waits to receive the init
+    // notification sent on `kernel_init_rx`. Even if it isn't, this should be
+    // okay because `r_task()` defensively blocks until its sender is
+    // initialized. On reconnects the channel is the same, but setting it
+    // refreshes the console scopes.
+    r_task({
+        let events_tx = session.events_tx();
+        move || {
+            let main = RMain::get_mut();
+            main.set_lsp_channel(events_tx);
+        }
+    });
+
     runtime.block_on(async {
-        log::trace!("Connecting to LSP at '{}'", &address);
-        let listener = TcpListener::bind(&address).await.unwrap();
-
-        // Notify frontend that we are ready to accept connections
-        conn_init_tx
-            .send(true)
-            .or_log_warning("Couldn't send LSP server init notification");
-
-        let (stream, _) = listener.accept().await.unwrap();
-        log::trace!("Connected to LSP at '{}'", address);
-        let (read, write) = tokio::io::split(stream);
-
-        let init = |client: Client| {
-            let state = GlobalState::new(client);
-            let events_tx = state.events_tx();
-
-            // Start main loop and hold onto the handle that keeps it alive
-            let main_loop = state.start();
-
-            // Forward event channel along to `RMain`.
-            // This also updates an outdated channel after a reconnect.
-            // `RMain` should be initialized by now, since the caller of this
-            // function waits to receive the init notification sent on
-            // `kernel_init_rx`. Even if it isn't, this should be okay because
-            // `r_task()` defensively blocks until its sender is initialized.
-            r_task({
-                let events_tx = events_tx.clone();
-                move || {
-                    let main = RMain::get_mut();
-                    main.set_lsp_channel(events_tx);
-                }
-            });
-
-            Backend {
-                events_tx,
-                _main_loop: main_loop,
-            }
-        };
-
-        let (service, socket) = LspService::build(init)
-            .custom_method(
-                statement_range::POSITRON_STATEMENT_RANGE_REQUEST,
-                Backend::statement_range,
-            )
-            .custom_method(help_topic::POSITRON_HELP_TOPIC_REQUEST, Backend::help_topic)
-            .custom_method(ARK_VDOC_REQUEST, Backend::virtual_document)
-            // In principle this should probably be a Jupyter request
-            .custom_method(
-                input_boundaries::POSITRON_INPUT_BOUNDARIES_REQUEST,
-                Backend::input_boundaries,
-            )
-            .custom_method("positron/notification", Backend::notification)
-            .finish();
-
-        let server = Server::new(read, write, socket);
-        server.serve(service).await;
-
-        log::trace!(
-            "LSP thread exiting gracefully after connection closed ({:?}).",
-            address
-        );
+        tokio::select! {
+            _ = serve_lsp(session, address.clone(), conn_init_tx) => {},
+            _ = stopped() => log::info!("LSP server stopped ({address:?})."),
+        }
     })
 }
 
+/// Stop the LSP servers: clients are disconnected and the servers stop
+/// listening. Servers started afterwards stop right away.
+pub fn stop_lsp() -> anyhow::Result<()> {
+    LSP_STOPPED.store(true, Ordering::Release);
+    LSP_STOP.notify_waiters();
+    Ok(())
+}
+
+async fn stopped() {
+    // Created before checking the flag so that a concurrent `stop_lsp()`
+    // isn't missed
+    let notified = LSP_STOP.notified();
+    if LSP_STOPPED.load(Ordering::Acquire) {
+        return;
+    }
+    notified.await
+}
+
+/// Listen on `address` until the server is stopped. Clients may disconnect
+/// and reconnect, and several clients may be connected at once. They all
+/// share the session.
+async fn serve_lsp(session: Arc<LspSession>, address: String, conn_init_tx: Sender<bool>) {
+    log::trace!("Connecting to LSP at '{}'", &address);
+    let listener = TcpListener::bind(&address).await.unwrap();
+
+    // Notify frontend that we are ready to accept connections
+    conn_init_tx
+        .send(true)
+        .or_log_warning("Couldn't send LSP server init notification");
+
+    // Connections are polled here rather than spawned so that they are
+    // dropped along with the server when it is stopped
+    let mut connections = FuturesUnordered::new();
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    log::trace!("Connected to LSP at '{address}' from '{peer}'");
+                    connections.push(serve_client(session.clone(), stream));
+                },
+                Err(err) => log::error!("Can't accept LSP connection at '{address}': {err:?}"),
+            },
+
+            Some(client_id) = connections.next(), if !connections.is_empty() => {
+                log::trace!("LSP client {client_id} disconnected from '{address}'");
+            },
+        }
+    }
+}
+
+async fn serve_client(session: Arc<LspSession>, stream: TcpStream) -> ClientId {
+    let (read, write) = tokio::io::split(stream);
+
+    let client_id = session.next_client_id();
+    let events_tx = session.events_tx();
+
+    let init = |client: Client| {
+        // The client is registered before any of its messages reach the
+        // main loop since they go through the same channel
+        events_tx
+            .send(Event::Client(ClientEvent::Connected(client_id, client)))
+            .unwrap();
+
+        Backend {
+            client_id,
+            events_tx: events_tx.clone(),
+        }
+    };
+
+    let (service, socket) = LspService::build(init)
+        .custom_method(
+            statement_range::POSITRON_STATEMENT_RANGE_REQUEST,
+            Backend::statement_range,
+        )
+        .custom_method(help_topic::POSITRON_HELP_TOPIC_REQUEST, Backend::help_topic)
+        .custom_method(ARK_VDOC_REQUEST, Backend::virtual_document)
+        // In principle this should probably be a Jupyter request
+        .custom_method(
+            input_boundaries::POSITRON_INPUT_BOUNDARIES_REQUEST,
+            Backend::input_boundaries,
+        )
+        .custom_method(brackets::POSITRON_BRACKETS_REQUEST, Backend::brackets)
+        .custom_method(
+            virtual_documents::ARK_VIRTUAL_DOCUMENT_DID_OPEN,
+            Backend::did_open_virtual_document,
+        )
+        .custom_method(
+            virtual_documents::ARK_VIRTUAL_DOCUMENT_DID_CHANGE,
+            Backend::did_change_virtual_document,
+        )
+        .custom_method(
+            virtual_documents::ARK_VIRTUAL_DOCUMENT_DID_CLOSE,
+            Backend::did_close_virtual_document,
+        )
+        .custom_method("positron/notification", Backend::notification)
+        .finish();
+
+    let server = Server::new(read, write, socket);
+    server.serve(service).await;
+
+    // The main loop keeps the world state for the next client
+    events_tx
+        .send(Event::Client(ClientEvent::Disconnected(client_id)))
+        .or_log_warning("Couldn't notify LSP main loop of disconnection");
+
+    client_id
+}
+
 fn new_jsonrpc_error(message: String) -> jsonrpc::Error {
     jsonrpc::Error {
         code: jsonrpc::ErrorCode::ServerError(-1),
@@ -0,0 +1,432 @@
+//
+// brackets.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use ropey::Rope;
+use serde::Deserialize;
+use serde::Serialize;
+use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::Range;
+use tree_sitter::Point;
+
+use crate::lsp::encoding::convert_point_to_position;
+use crate::lsp::encoding::convert_position_to_point;
+
+pub static POSITRON_BRACKETS_REQUEST: &str = "positron/brackets";
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BracketsParams {
+    /// Console input, possibly spanning several lines.
+    pub text: String,
+    /// The location of the cursor in `text`.
+    pub position: Position,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BracketsResponse {
+    /// Pairs of matching brackets, ordered by opening bracket.
+    pub pairs: Vec<BracketPair>,
+    /// Brackets without a match, e.g. the `(` of a call that isn't complete
+    /// yet.
+    pub unmatched: Vec<Range>,
+    /// The pair with a bracket right before the cursor, or else right after
+    /// it, to highlight.
+    pub cursor_pair: Option<BracketPair>,
+    /// Whether the cursor is inside a string or a backquoted name, where
+    /// brackets and quotes shouldn't be auto-paired.
+    pub in_string: bool,
+    /// Whether the cursor is inside a comment.
+    pub in_comment: bool,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize)]
+pub struct BracketPair {
+    pub open: Range,
+    pub close: Range,
+}
+
+/// Brackets of console input and whether the cursor is in a string or
+/// comment
+///
+/// Console input is often incomplete, e.g. an unterminated string while it's
+/// being typed, which the tree-sitter parser recovers from in ways that don't
+/// reflect what the user is typing. The input is tokenized instead, only
+/// looking for what matters here: strings (including raw strings), comments,
+/// and brackets outside of them.
+pub(crate) fn brackets(text: &str, position: Position) -> BracketsResponse {
+    let contents = Rope::from_str(text);
+    let cursor = convert_position_to_point(&contents, position);
+
+    let tokens = tokenize(text);
+
+    let mut stack: Vec<&Token> = Vec::new();
+    let mut pairs: Vec<(&Token, &Token)> = Vec::new();
+    let mut unmatched: Vec<&Token> = Vec::new();
+
+    let mut in_string = false;
+    let mut in_comment = false;
+
+    for token in tokens.iter() {
+        match token.kind {
+            TokenKind::String { terminated } => {
+                in_string |= token.start < cursor && (cursor < token.end || !terminated);
+            },
+            TokenKind::Comment => {
+                in_comment |= token.start < cursor && cursor <= token.end;
+            },
+            TokenKind::Open(_) => stack.push(token),
+            TokenKind::Close(bracket) => match stack.last() {
+                Some(open) if open.kind == TokenKind::Open(bracket) => {
+                    pairs.push((stack.pop().unwrap(), token));
+                },
+                _ => unmatched.push(token),
+            },
+        }
+    }
+    unmatched.extend(stack);
+
+    pairs.sort_by_key(|(open, _)| open.start);
+    unmatched.sort_by_key(|token| token.start);
+
+    let cursor_pair = pairs
+        .iter()
+        .find(|(open, close)| open.end == cursor || close.end == cursor)
+        .or_else(|| {
+            pairs
+                .iter()
+                .find(|(open, close)| open.start == cursor || close.start == cursor)
+        })
+        .map(|(open, close)| bracket_pair(&contents, open, close));
+
+    BracketsResponse {
+        pairs: pairs
+            .iter()
+            .map(|(open, close)| bracket_pair(&contents, open, close))
+            .collect(),
+        unmatched: unmatched
+            .iter()
+            .map(|token| token_range(&contents, token))
+            .collect(),
+        cursor_pair,
+        in_string,
+        in_comment,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Bracket {
+    Paren,
+    Square,
+    DoubleSquare,
+    Brace,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TokenKind {
+    Open(Bracket),
+    Close(Bracket),
+    String { terminated: bool },
+    Comment,
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    start: Point,
+    end: Point,
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut lexer = Lexer::new(text);
+    let mut tokens = Vec::new();
+
+    // Opening brackets, to tell `]]` closing `[[` from two `]`
+    let mut open: Vec<Bracket> = Vec::new();
+
+    while let Some(c) = lexer.peek(0) {
+        let start = lexer.point();
+
+        let kind = match c {
+            '#' => {
+                while lexer.peek(0).is_some_and(|c| c != '\n') {
+                    lexer.bump();
+                }
+                TokenKind::Comment
+            },
+            '"' | '\'' | '`' => {
+                lexer.bump();
+                TokenKind::String {
+                    terminated: lexer.skip_quoted(c),
+                }
+            },
+            'r' | 'R' if lexer.is_raw_string_start() => {
+                lexer.bump();
+                TokenKind::String {
+                    terminated: lexer.skip_raw_string(),
+                }
+            },
+            '(' | '{' | '[' => {
+                let bracket = match c {
+                    '(' => Bracket::Paren,
+                    '{' => Bracket::Brace,
+                    _ if lexer.peek(1) == Some('[') => {
+                        lexer.bump();
+                        Bracket::DoubleSquare
+                    },
+                    _ => Bracket::Square,
+                };
+                lexer.bump();
+                open.push(bracket);
+                TokenKind::Open(bracket)
+            },
+            ')' | '}' | ']' => {
+                let bracket = match c {
+                    ')' => Bracket::Paren,
+                    '}' => Bracket::Brace,
+                    _ if open.last() == Some(&Bracket::DoubleSquare) &&
+                        lexer.peek(1) == Some(']') =>
+                    {
+                        lexer.bump();
+                        Bracket::DoubleSquare
+                    },
+                    _ => Bracket::Square,
+                };
+                lexer.bump();
+                if open.last() == Some(&bracket) {
+                    open.pop();
+                }
+                TokenKind::Close(bracket)
+            },
+            _ => {
+                lexer.bump_identifier_or_char();
+                continue;
+            },
+        };
+
+        tokens.push(Token {
+            kind,
+            start,
+            end: lexer.point(),
+        });
+    }
+
+    tokens
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    index: usize,
+    row: usize,
+    /// Byte column, as in tree-sitter points
+    column: usize,
+}
+
+impl Lexer {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            index: 0,
+            row: 0,
+            column: 0,
+        }
+    }
+
+    fn point(&self) -> Point {
+        Point::new(self.row, self.column)
+    }
+
+    fn peek(&self, n: usize) -> Option<char> {
+        self.chars.get(self.index + n).copied()
+    }
+
+    fn bump(&mut self) {
+        let Some(c) = self.peek(0) else {
+            return;
+        };
+        self.index += 1;
+
+        if c == '\n' {
+            self.row += 1;
+            self.column = 0;
+        } else {
+            self.column += c.len_utf8();
+        }
+    }
+
+    /// Skips identifiers whole so that e.g. the `r` of `bar"` isn't taken for
+    /// the start of a raw string
+    fn bump_identifier_or_char(&mut self) {
+        let is_identifier = |c: char| c.is_alphanumeric() || c == '.' || c == '_';
+
+        if !self.peek(0).is_some_and(is_identifier) {
+            self.bump();
+            return;
+        }
+        while self.peek(0).is_some_and(is_identifier) {
+            self.bump();
+        }
+    }
+
+    /// Skips the rest of a string delimited by `quote`. Returns whether the
+    /// closing quote was found.
+    fn skip_quoted(&mut self, quote: char) -> bool {
+        while let Some(c) = self.peek(0) {
+            self.bump();
+            if c == '\\' {
+                self.bump();
+            } else if c == quote {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether a raw string starts here, e.g. `r"(...)"` or `R'--[...]--'`
+    fn is_raw_string_start(&self) -> bool {
+        if !matches!(self.peek(1), Some('"' | '\'')) {
+            return false;
+        }
+        let mut n = 2;
+        while self.peek(n) == Some('-') {
+            n += 1;
+        }
+        matches!(self.peek(n), Some('(' | '[' | '{'))
+    }
+
+    /// Skips the rest of a raw string, starting at its opening quote. Returns
+    /// whether the closing delimiter was found.
+    fn skip_raw_string(&mut self) -> bool {
+        let Some(quote) = self.peek(0) else {
+            return false;
+        };
+        self.bump();
+
+        let mut dashes = 0;
+        while self.peek(0) == Some('-') {
+            dashes += 1;
+            self.bump();
+        }
+
+        let close = match self.peek(0) {
+            Some('(') => ')',
+            Some('[') => ']',
+            _ => '}',
+        };
+        self.bump();
+
+        while let Some(c) = self.peek(0) {
+            self.bump();
+            if c != close {
+                continue;
+            }
+            let is_end =
+                (0..dashes).all(|i| self.peek(i) == Some('-')) && self.peek(dashes) == Some(quote);
+            if is_end {
+                for _ in 0..=dashes {
+                    self.bump();
+                }
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn token_range(contents: &Rope, token: &Token) -> Range {
+    Range::new(
+        convert_point_to_position(contents, token.start),
+        convert_point_to_position(contents, token.end),
+    )
+}
+
+fn bracket_pair(contents: &Rope, open: &Token, close: &Token) -> BracketPair {
+    BracketPair {
+        open: token_range(contents, open),
+        close: token_range(contents, close),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+    use tower_lsp::lsp_types::Range;
+
+    use crate::lsp::brackets::brackets;
+    use crate::lsp::brackets::BracketPair;
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn test_brackets_pairs() {
+        let response = brackets("f(x[[1]], y[2])", Position::new(0, 0));
+        assert_eq!(response.pairs, vec![
+            BracketPair {
+                open: range(0, 1, 2),
+                close: range(0, 14, 15),
+            },
+            BracketPair {
+                open: range(0, 3, 5),
+                close: range(0, 6, 8),
+            },
+            BracketPair {
+                open: range(0, 11, 12),
+                close: range(0, 13, 14),
+            },
+        ]);
+        assert!(response.unmatched.is_empty());
+
+        // `]]` closing a `[` within `[[`
+        let response = brackets("x[[y[1]]]", Position::new(0, 0));
+        assert_eq!(response.pairs.len(), 2);
+        assert_eq!(response.pairs[1].close, range(0, 6, 7));
+        assert!(response.unmatched.is_empty());
+    }
+
+    #[test]
+    fn test_brackets_incomplete_input() {
+        let response = brackets("f(x, {\n  y)", Position::new(1, 4));
+        assert_eq!(response.unmatched, vec![
+            range(0, 1, 2),
+            range(0, 5, 6),
+            range(1, 3, 4)
+        ]);
+
+        // Cursor right after a closing bracket
+        let response = brackets("(1) + 2", Position::new(0, 3));
+        assert_eq!(
+            response.cursor_pair,
+            Some(BracketPair {
+                open: range(0, 0, 1),
+                close: range(0, 2, 3),
+            })
+        );
+    }
+
+    #[test]
+    fn test_brackets_strings_and_comments() {
+        // Brackets in strings and comments are ignored
+        let response = brackets("paste('(', r\"[)]\") # )", Position::new(0, 0));
+        assert_eq!(response.pairs.len(), 1);
+        assert!(response.unmatched.is_empty());
+
+        // Unterminated string while typing
+        let response = brackets("paste(\"a(", Position::new(0, 9));
+        assert!(response.in_string);
+        assert_eq!(response.unmatched, vec![range(0, 5, 6)]);
+
+        // Right after a closing quote
+        let response = brackets("'a'", Position::new(0, 3));
+        assert!(!response.in_string);
+
+        let response = brackets("1 # (", Position::new(0, 5));
+        assert!(response.in_comment);
+        assert!(!response.in_string);
+    }
+}
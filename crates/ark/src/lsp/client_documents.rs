@@ -0,0 +1,236 @@
+//
+// client_documents.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use tower_lsp::lsp_types::DidChangeTextDocumentParams;
+use tower_lsp::lsp_types::Url;
+
+use crate::lsp::documents::Document;
+use crate::lsp::main_loop::LspState;
+use crate::lsp::session::ClientId;
+use crate::lsp::state::WorldState;
+
+/// Contents of documents opened by several clients.
+///
+/// Clients don't share their buffers, so the edits of one client don't apply
+/// to the contents of another. The world state holds the contents of the
+/// owner of each document, the last client that opened it. The other clients
+/// keep their own copy, which takes over the world state when the owner
+/// closes the document.
+#[derive(Default)]
+pub(crate) struct ClientDocuments {
+    owners: HashMap<Url, ClientId>,
+    copies: HashMap<(ClientId, Url), ClientDocument>,
+}
+
+/// A document as seen by a client that doesn't own it
+struct ClientDocument {
+    document: Document,
+    parser: tree_sitter::Parser,
+}
+
+impl ClientDocuments {
+    /// Make `client_id` the owner of `uri`. The previous owner keeps the
+    /// current contents of the world state as its copy. Must be called before
+    /// the world state is updated with the contents of the new owner.
+    pub(crate) fn open(
+        &mut self,
+        client_id: ClientId,
+        uri: &Url,
+        lsp_state: &mut LspState,
+        world: &WorldState,
+    ) {
+        self.copies.remove(&(client_id, uri.clone()));
+
+        let Some(owner) = self.owners.insert(uri.clone(), client_id) else {
+            return;
+        };
+        if owner == client_id {
+            return;
+        }
+
+        if let (Some(document), Some(parser)) =
+            (world.documents.get(uri), lsp_state.parsers.remove(uri))
+        {
+            let copy = ClientDocument {
+                document: document.clone(),
+                parser,
+            };
+            self.copies.insert((owner, uri.clone()), copy);
+        }
+    }
+
+    /// Whether `client_id` owns `uri`, in which case its changes apply to the
+    /// world state
+    pub(crate) fn is_owner(&self, client_id: ClientId, uri: &Url) -> bool {
+        self.owners.get(uri) == Some(&client_id)
+    }
+
+    /// Apply changes of a client that doesn't own the document to its copy
+    pub(crate) fn did_change(
+        &mut self,
+        client_id: ClientId,
+        params: &DidChangeTextDocumentParams,
+    ) -> anyhow::Result<()> {
+        let uri = &params.text_document.uri;
+        let copy = self
+            .copies
+            .get_mut(&(client_id, uri.clone()))
+            .ok_or_else(|| anyhow!("LSP client {client_id} doesn't have {uri} open"))?;
+
+        copy.document.on_did_change(&mut copy.parser, params)
+    }
+
+    /// Release the document of a client. When the owner closes a document
+    /// that other clients still have open, one of them becomes the owner and
+    /// its copy replaces the contents of the world state. Returns whether
+    /// that happened, in which case the document must be refreshed.
+    pub(crate) fn close(
+        &mut self,
+        client_id: ClientId,
+        uri: &Url,
+        lsp_state: &mut LspState,
+        world: &mut WorldState,
+    ) -> bool {
+        self.copies.remove(&(client_id, uri.clone()));
+
+        if !self.is_owner(client_id, uri) {
+            return false;
+        }
+        self.owners.remove(uri);
+
+        let Some(key) = self.copies.keys().find(|(_, other)| other == uri).cloned() else {
+            return false;
+        };
+        let (owner, _) = key;
+        let copy = self.copies.remove(&key).unwrap();
+
+        world.documents.insert(uri.clone(), copy.document);
+        lsp_state.parsers.insert(uri.clone(), copy.parser);
+        self.owners.insert(uri.clone(), owner);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::DidChangeTextDocumentParams;
+    use tower_lsp::lsp_types::Position;
+    use tower_lsp::lsp_types::Range;
+    use tower_lsp::lsp_types::TextDocumentContentChangeEvent;
+    use tower_lsp::lsp_types::Url;
+    use tower_lsp::lsp_types::VersionedTextDocumentIdentifier;
+
+    use crate::lsp::client_documents::ClientDocuments;
+    use crate::lsp::documents::Document;
+    use crate::lsp::main_loop::LspState;
+    use crate::lsp::state::WorldState;
+
+    fn parser() -> tree_sitter::Parser {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_r::LANGUAGE.into())
+            .unwrap();
+        parser
+    }
+
+    // Mimics `state_handlers::did_open()`
+    fn open(lsp_state: &mut LspState, world: &mut WorldState, uri: &Url, contents: &str) {
+        let mut parser = parser();
+        let document = Document::new_with_parser(contents, &mut parser, Some(0));
+        lsp_state.parsers.insert(uri.clone(), parser);
+        world.documents.insert(uri.clone(), document);
+    }
+
+    fn replace_params(
+        uri: &Url,
+        version: i32,
+        end: u32,
+        text: &str,
+    ) -> DidChangeTextDocumentParams {
+        DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range {
+                    start: Position::new(0, 0),
+                    end: Position::new(0, end),
+                }),
+                range_length: None,
+                text: String::from(text),
+            }],
+        }
+    }
+
+    fn contents(world: &WorldState, uri: &Url) -> String {
+        world.documents.get(uri).unwrap().contents.to_string()
+    }
+
+    #[test]
+    fn test_client_documents_owner_closes_first() {
+        let mut documents = ClientDocuments::default();
+        let mut lsp_state = LspState::default();
+        let mut world = WorldState::default();
+        let uri = Url::parse("file:///test.R").unwrap();
+
+        // Client 1 opens the document, then client 2 which becomes the owner
+        documents.open(1, &uri, &mut lsp_state, &world);
+        open(&mut lsp_state, &mut world, &uri, "x <- 1");
+        documents.open(2, &uri, &mut lsp_state, &world);
+        open(&mut lsp_state, &mut world, &uri, "x <- 1");
+        assert!(documents.is_owner(2, &uri));
+        assert!(!documents.is_owner(1, &uri));
+
+        // Client 1 edits its own copy, the world follows client 2
+        let params = replace_params(&uri, 1, 6, "y <- 1");
+        documents.did_change(1, &params).unwrap();
+        assert_eq!(contents(&world, &uri), "x <- 1");
+
+        // The owner closes the document first. Client 1 takes over with its
+        // own contents.
+        assert!(documents.close(2, &uri, &mut lsp_state, &mut world));
+        assert!(documents.is_owner(1, &uri));
+        assert_eq!(contents(&world, &uri), "y <- 1");
+
+        // The changes of client 1 now apply to the world
+        let params = replace_params(&uri, 2, 6, "z <- 1");
+        let document = world.documents.get_mut(&uri).unwrap();
+        let parser = lsp_state.parsers.get_mut(&uri).unwrap();
+        document.on_did_change(parser, &params).unwrap();
+        assert_eq!(contents(&world, &uri), "z <- 1");
+
+        // The last client closes the document, nobody takes over
+        assert!(!documents.close(1, &uri, &mut lsp_state, &mut world));
+        assert!(!documents.is_owner(1, &uri));
+    }
+
+    #[test]
+    fn test_client_documents_other_client_closes_first() {
+        let mut documents = ClientDocuments::default();
+        let mut lsp_state = LspState::default();
+        let mut world = WorldState::default();
+        let uri = Url::parse("file:///test.R").unwrap();
+
+        documents.open(1, &uri, &mut lsp_state, &world);
+        open(&mut lsp_state, &mut world, &uri, "x <- 1");
+        documents.open(2, &uri, &mut lsp_state, &world);
+        open(&mut lsp_state, &mut world, &uri, "x <- 2");
+
+        // Client 1 closes its copy, the owner is unaffected
+        assert!(!documents.close(1, &uri, &mut lsp_state, &mut world));
+        assert!(documents.is_owner(2, &uri));
+        assert_eq!(contents(&world, &uri), "x <- 2");
+        assert!(documents
+            .did_change(1, &replace_params(&uri, 1, 6, "y"))
+            .is_err());
+    }
+}
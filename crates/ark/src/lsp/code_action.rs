@@ -0,0 +1,43 @@
+//
+// code_action.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::CodeAction;
+use tower_lsp::lsp_types::CodeActionKind;
+use tower_lsp::lsp_types::CodeActionOrCommand;
+use tower_lsp::lsp_types::CodeActionParams;
+use tower_lsp::lsp_types::WorkspaceEdit;
+
+use crate::lsp::diagnostics_flow::QuickFix;
+
+/// Provide the quick fixes of the diagnostics in the requested range. Fixes
+/// are computed along with the diagnostics and stored in their `data` field,
+/// which the client sends back with the request, so that the fix always
+/// matches the diagnostic the user is looking at.
+pub(crate) fn code_actions(params: CodeActionParams) -> Vec<CodeActionOrCommand> {
+    let uri = params.text_document.uri;
+
+    params
+        .context
+        .diagnostics
+        .into_iter()
+        .filter_map(|diagnostic| {
+            let fix: QuickFix = serde_json::from_value(diagnostic.data.clone()?).ok()?;
+            let changes = HashMap::from([(uri.clone(), fix.edits)]);
+
+            Some(CodeActionOrCommand::CodeAction(CodeAction {
+                title: fix.title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic]),
+                edit: Some(WorkspaceEdit::new(changes)),
+                is_preferred: Some(true),
+                ..Default::default()
+            }))
+        })
+        .collect()
+}
@@ -0,0 +1,174 @@
+//
+// code_lens.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use ropey::Rope;
+use serde_json::Value;
+use tower_lsp::lsp_types::CodeLens;
+use tower_lsp::lsp_types::Command;
+use tree_sitter::Node;
+
+use crate::i18n::tr;
+use crate::lsp::documents::Document;
+use crate::lsp::encoding::convert_tree_sitter_range_to_lsp_range;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+
+/// Command that runs code in the global environment once the console is idle,
+/// with the code passed as single argument. See `lsp::commands`.
+pub(crate) const ARK_EXECUTE_CODE_COMMAND: &str = "ark.executeCode";
+
+/// Provide "Run" lenses for top-level function definitions and `test_that()`
+/// blocks, and "Debug" lenses for functions. Only the top-level nodes of the
+/// document are inspected so this stays cheap to compute on every edit. Titles
+/// are translated with `i18n::tr()`.
+///
+/// Code of lenses doesn't run at the prompt, so it can't enter the browser
+/// itself. Debugging a function defines it and flags it with `debugonce()`:
+/// the browser opens at its next call from the console.
+pub(crate) fn code_lenses(document: &Document) -> anyhow::Result<Vec<CodeLens>> {
+    let contents = &document.contents;
+    let root = document.ast.root_node();
+
+    let mut lenses = Vec::new();
+    let mut cursor = root.walk();
+
+    for node in root.children(&mut cursor) {
+        if let Some(name) = function_name(&node, contents)? {
+            let code = contents.node_slice(&node)?.to_string();
+            let debug_code = format!("{code}\ndebugonce({name})");
+
+            lenses.push(new_lens(
+                &node,
+                contents,
+                tr("code_lens_run_function"),
+                code,
+            ));
+            lenses.push(new_lens(
+                &node,
+                contents,
+                tr("code_lens_debug_function"),
+                debug_code,
+            ));
+            continue;
+        }
+
+        if is_test(&node, contents)? {
+            let code = contents.node_slice(&node)?.to_string();
+            lenses.push(new_lens(&node, contents, tr("code_lens_run_test"), code));
+        }
+    }
+
+    Ok(lenses)
+}
+
+fn new_lens(node: &Node, contents: &Rope, title: &str, code: String) -> CodeLens {
+    CodeLens {
+        range: convert_tree_sitter_range_to_lsp_range(contents, node.range()),
+        command: Some(Command {
+            title: String::from(title),
+            command: String::from(ARK_EXECUTE_CODE_COMMAND),
+            arguments: Some(vec![Value::String(code)]),
+        }),
+        data: None,
+    }
+}
+
+/// Name of the function defined by an assignment like `foo <- function() {}`
+fn function_name(node: &Node, contents: &Rope) -> anyhow::Result<Option<String>> {
+    if !matches!(
+        node.node_type(),
+        NodeType::BinaryOperator(BinaryOperatorType::LeftAssignment) |
+            NodeType::BinaryOperator(BinaryOperatorType::EqualsAssignment)
+    ) {
+        return Ok(None);
+    }
+
+    let (Some(lhs), Some(rhs)) = (
+        node.child_by_field_name("lhs"),
+        node.child_by_field_name("rhs"),
+    ) else {
+        return Ok(None);
+    };
+
+    if !lhs.is_identifier_or_string() || !rhs.is_function_definition() {
+        return Ok(None);
+    }
+
+    Ok(Some(contents.node_slice(&lhs)?.to_string()))
+}
+
+/// Whether the node is a `test_that()` call
+fn is_test(node: &Node, contents: &Rope) -> anyhow::Result<bool> {
+    if !node.is_call() {
+        return Ok(false);
+    }
+
+    let Some(function) = node.child_by_field_name("function") else {
+        return Ok(false);
+    };
+
+    let function = contents.node_slice(&function)?;
+    Ok(function == "test_that" || function == "testthat::test_that")
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::CodeLens;
+
+    use crate::lsp::code_lens::code_lenses;
+    use crate::lsp::documents::Document;
+
+    fn lens_code(lens: &CodeLens) -> &str {
+        let arguments = lens.command.as_ref().unwrap().arguments.as_ref().unwrap();
+        arguments[0].as_str().unwrap()
+    }
+
+    #[test]
+    fn test_code_lens_functions() {
+        let document = Document::new("foo <- function(x) x\nbar <- 1\n", None);
+        let lenses = code_lenses(&document).unwrap();
+
+        assert_eq!(lenses.len(), 2);
+        assert_eq!(lenses[0].range.start.line, 0);
+        assert_eq!(lenses[0].command.as_ref().unwrap().title, "Run function");
+        assert_eq!(lens_code(&lenses[0]), "foo <- function(x) x");
+        assert_eq!(
+            lenses[1].command.as_ref().unwrap().title,
+            "Debug on next call"
+        );
+        assert_eq!(
+            lens_code(&lenses[1]),
+            "foo <- function(x) x\ndebugonce(foo)"
+        );
+    }
+
+    #[test]
+    fn test_code_lens_tests() {
+        let document = Document::new(
+            "library(testthat)\n\ntestthat::test_that('works', {\n  expect_true(TRUE)\n})\n",
+            None,
+        );
+        let lenses = code_lenses(&document).unwrap();
+
+        assert_eq!(lenses.len(), 1);
+        assert_eq!(lenses[0].range.start.line, 2);
+        assert_eq!(lenses[0].command.as_ref().unwrap().title, "Run test");
+        assert_eq!(
+            lens_code(&lenses[0]),
+            "testthat::test_that('works', {\n  expect_true(TRUE)\n})"
+        );
+    }
+
+    #[test]
+    fn test_code_lens_ignores_nested_definitions() {
+        let document = Document::new("{\n  foo <- function() 1\n}\n", None);
+        let lenses = code_lenses(&document).unwrap();
+        assert!(lenses.is_empty());
+    }
+}
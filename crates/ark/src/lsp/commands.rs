@@ -0,0 +1,351 @@
+//
+// commands.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use anyhow::anyhow;
+use harp::environment::R_ENVS;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use serde_json::json;
+use serde_json::Value;
+use tower_lsp::lsp_types::ExecuteCommandParams;
+use tower_lsp::lsp_types::MessageActionItem;
+use tower_lsp::lsp_types::MessageType;
+use tower_lsp::Client;
+
+use crate::i18n::tr;
+use crate::interface::RMain;
+use crate::lsp;
+use crate::modules;
+use crate::modules::ARK_ENVS;
+use crate::r_task;
+use crate::session_access;
+
+/// A command that the frontend can execute with `workspace/executeCommand`,
+/// e.g. from a keyboard shortcut
+pub(crate) struct LspCommand {
+    pub id: &'static str,
+
+    /// Positional arguments. Required arguments come first.
+    pub arguments: &'static [CommandArgument],
+
+    /// Id of the question asked to the user before running the command, see
+    /// `i18n::tr()`. The command is cancelled unless the user confirms.
+    pub confirmation: Option<&'static str>,
+
+    /// Whether the command changes the session. Such commands are rejected in
+    /// read-only sessions.
+    pub mutating: bool,
+
+    /// Runs the command with validated arguments. Optional arguments that were
+    /// not supplied are `Value::Null`. The result is sent back to the frontend.
+    pub handler: fn(&[Value]) -> anyhow::Result<Value>,
+}
+
+pub(crate) struct CommandArgument {
+    pub name: &'static str,
+    pub kind: ArgumentKind,
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ArgumentKind {
+    Bool,
+    Number,
+    String,
+}
+
+/// Commands of the R session
+const SESSION_COMMANDS: &[LspCommand] = &[
+    LspCommand {
+        id: "ark.restartR",
+        arguments: &[],
+        confirmation: None,
+        mutating: true,
+        handler: restart_r,
+    },
+    LspCommand {
+        id: "ark.clearWorkspace",
+        arguments: &[CommandArgument {
+            name: "include_hidden",
+            kind: ArgumentKind::Bool,
+            required: false,
+        }],
+        confirmation: Some("clear_workspace_question"),
+        mutating: true,
+        handler: clear_workspace,
+    },
+    LspCommand {
+        id: "ark.toggleProfiling",
+        arguments: &[CommandArgument {
+            name: "interval",
+            kind: ArgumentKind::Number,
+            required: false,
+        }],
+        confirmation: None,
+        mutating: false,
+        handler: toggle_profiling,
+    },
+    LspCommand {
+        id: "ark.executeCode",
+        arguments: &[CommandArgument {
+            name: "code",
+            kind: ArgumentKind::String,
+            required: true,
+        }],
+        confirmation: None,
+        mutating: true,
+        handler: execute_code,
+    },
+];
+
+/// Commands of the Ark R modules
+const MODULES_COMMANDS: &[LspCommand] = &[LspCommand {
+    id: "ark.reloadModules",
+    arguments: &[],
+    confirmation: None,
+    mutating: false,
+    handler: reload_modules,
+}];
+
+const COMMANDS: &[&[LspCommand]] = &[SESSION_COMMANDS, MODULES_COMMANDS];
+
+/// Identifiers of all commands, advertised to the client in `initialize`
+pub(crate) fn command_ids() -> Vec<String> {
+    commands().map(|command| String::from(command.id)).collect()
+}
+
+fn commands() -> impl Iterator<Item = &'static LspCommand> {
+    COMMANDS.iter().flat_map(|commands| commands.iter())
+}
+
+fn find_command(id: &str) -> Option<&'static LspCommand> {
+    commands().find(|command| command.id == id)
+}
+
+pub(crate) async fn execute_command(
+    params: ExecuteCommandParams,
+    client: &Client,
+) -> anyhow::Result<Option<Value>> {
+    let command = find_command(&params.command)
+        .ok_or_else(|| anyhow!("Unknown command '{}'", params.command))?;
+
+    let arguments = validate_arguments(command, params.arguments)?;
+
+    if command.mutating {
+        if let Err(err) = session_access::check_change() {
+            let message = err.change_message();
+            client.show_message(MessageType::ERROR, message).await;
+            return Err(anyhow!("Command '{}' rejected: {message}", command.id));
+        }
+    }
+
+    if let Some(question) = command.confirmation {
+        if !confirm(client, tr(question)).await {
+            lsp::log_info!("Command '{}' cancelled by the user", command.id);
+            return Ok(Some(json!({ "cancelled": true })));
+        }
+    }
+
+    // Handlers block on R
+    let result = tokio::task::spawn_blocking(move || (command.handler)(&arguments)).await?;
+
+    if let Err(err) = &result {
+        let message = format!("Failed to execute '{}': {err}", command.id);
+        client.show_message(MessageType::ERROR, &message).await;
+    }
+
+    result.map(Some)
+}
+
+/// Check arguments against the command's declaration. Returns one value per
+/// declared argument, `Value::Null` for optional arguments not supplied.
+fn validate_arguments(command: &LspCommand, arguments: Vec<Value>) -> anyhow::Result<Vec<Value>> {
+    if arguments.len() > command.arguments.len() {
+        return Err(anyhow!(
+            "Command '{}' takes at most {} argument(s) but {} were supplied",
+            command.id,
+            command.arguments.len(),
+            arguments.len()
+        ));
+    }
+
+    let mut arguments = arguments.into_iter();
+    let mut out = Vec::with_capacity(command.arguments.len());
+
+    for argument in command.arguments {
+        let value = match arguments.next() {
+            Some(Value::Null) | None if argument.required => {
+                return Err(anyhow!(
+                    "Command '{}' requires argument '{}'",
+                    command.id,
+                    argument.name
+                ));
+            },
+            Some(value) => value,
+            None => Value::Null,
+        };
+
+        let valid = match argument.kind {
+            _ if value.is_null() => true,
+            ArgumentKind::Bool => value.is_boolean(),
+            ArgumentKind::Number => value.is_number(),
+            ArgumentKind::String => value.is_string(),
+        };
+
+        if !valid {
+            return Err(anyhow!(
+                "Argument '{}' of command '{}' must be a {:?}, not `{value}`",
+                argument.name,
+                command.id,
+                argument.kind
+            ));
+        }
+
+        out.push(value);
+    }
+
+    Ok(out)
+}
+
+async fn confirm(client: &Client, question: &str) -> bool {
+    let yes = tr("yes");
+
+    let actions = vec![
+        MessageActionItem {
+            title: String::from(yes),
+            properties: Default::default(),
+        },
+        MessageActionItem {
+            title: String::from(tr("no")),
+            properties: Default::default(),
+        },
+    ];
+
+    let answer = client
+        .show_message_request(MessageType::WARNING, question, Some(actions))
+        .await;
+
+    match answer {
+        Ok(Some(action)) => action.title == yes,
+        Ok(None) => false,
+        Err(err) => {
+            lsp::log_error!("Failed to ask for confirmation: {err:?}");
+            false
+        },
+    }
+}
+
+fn clear_workspace(arguments: &[Value]) -> anyhow::Result<Value> {
+    let include_hidden = arguments[0].as_bool().unwrap_or(false);
+
+    let removed = r_task(|| -> anyhow::Result<Vec<String>> {
+        let names: Vec<String> = RFunction::new("base", "ls")
+            .param("envir", R_ENVS.global)
+            .param("all.names", include_hidden)
+            .call()?
+            .try_into()?;
+
+        RFunction::new("base", "rm")
+            .param("list", names.clone())
+            .param("envir", R_ENVS.global)
+            .call()?;
+
+        Ok(names)
+    })?;
+
+    Ok(json!({ "removed": removed }))
+}
+
+/// Run code in the global environment, e.g. from a code lens. The code runs
+/// once the console is idle so it doesn't interleave with executions, and
+/// its output is sent to the console.
+fn restart_r(_arguments: &[Value]) -> anyhow::Result<Value> {
+    r_task(|| RMain::get().request_restart())?;
+    Ok(Value::Null)
+}
+
+fn execute_code(arguments: &[Value]) -> anyhow::Result<Value> {
+    let code = String::from(arguments[0].as_str().unwrap_or_default());
+
+    r_task::spawn_idle(|| async move {
+        let result = RFunction::new("", "execute_code")
+            .add(code)
+            .call_in(ARK_ENVS.positron_ns);
+        if let Err(err) = result {
+            log::error!("Can't execute code: {err:?}");
+        }
+    });
+
+    Ok(Value::Null)
+}
+
+fn toggle_profiling(arguments: &[Value]) -> anyhow::Result<Value> {
+    let interval = arguments[0].as_f64();
+
+    r_task(|| -> anyhow::Result<Value> {
+        let state = RFunction::from(".ps.profiling.toggle")
+            .param("interval", interval)
+            .call()?;
+        Ok(Value::try_from(state)?)
+    })
+}
+
+fn reload_modules(_arguments: &[Value]) -> anyhow::Result<Value> {
+    r_task(|| -> anyhow::Result<()> {
+        let namespace = RMain::get()
+            .positron_ns
+            .as_ref()
+            .ok_or_else(|| anyhow!("The Positron namespace is not initialized"))?;
+        modules::reload(namespace.sexp)
+    })?;
+
+    Ok(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use serde_json::json;
+
+    use crate::lsp::commands::command_ids;
+    use crate::lsp::commands::find_command;
+    use crate::lsp::commands::validate_arguments;
+
+    #[test]
+    fn test_command_ids_are_unique() {
+        let ids = command_ids();
+        let unique: HashSet<&String> = ids.iter().collect();
+        assert_eq!(ids.len(), unique.len());
+        assert!(ids.contains(&String::from("ark.restartR")));
+        assert!(ids.contains(&String::from("ark.clearWorkspace")));
+    }
+
+    #[test]
+    fn test_command_argument_validation() {
+        let command = find_command("ark.clearWorkspace").unwrap();
+
+        // Optional arguments are filled with `null`
+        let arguments = validate_arguments(command, vec![]).unwrap();
+        assert_eq!(arguments, vec![json!(null)]);
+
+        let arguments = validate_arguments(command, vec![json!(true)]).unwrap();
+        assert_eq!(arguments, vec![json!(true)]);
+
+        // Wrong type
+        assert!(validate_arguments(command, vec![json!("yes")]).is_err());
+
+        // Too many arguments
+        assert!(validate_arguments(command, vec![json!(true), json!(1)]).is_err());
+
+        // Missing required argument
+        let command = find_command("ark.executeCode").unwrap();
+        assert!(validate_arguments(command, vec![]).is_err());
+        assert!(validate_arguments(command, vec![json!(1)]).is_err());
+        assert!(validate_arguments(command, vec![json!("1 + 1")]).is_ok());
+    }
+}
@@ -12,4 +12,6 @@ mod sources;
 mod types;
 
 pub(crate) use provide::provide_completions;
+pub(crate) use provide::provide_r_completions;
+pub(crate) use provide::provide_static_completions;
 pub(crate) use resolve::resolve_completion;
@@ -47,6 +47,7 @@ use crate::lsp::encoding::convert_point_to_position;
 use crate::lsp::traits::rope::RopeExt;
 use crate::treesitter::NodeType;
 use crate::treesitter::NodeTypeExt;
+use crate::user_config::user_config;
 
 pub(super) fn completion_item(
     label: impl AsRef<str>,
@@ -132,8 +133,10 @@ pub(super) fn completion_item_from_assignment(
         }
 
         item.kind = Some(CompletionItemKind::FUNCTION);
-        item.insert_text_format = Some(InsertTextFormat::SNIPPET);
-        item.insert_text = Some(format!("{}($0)", label));
+        if user_config().completions.function_parentheses {
+            item.insert_text_format = Some(InsertTextFormat::SNIPPET);
+            item.insert_text = Some(format!("{}($0)", label));
+        }
     }
 
     return Ok(item);
@@ -179,6 +182,12 @@ pub(super) fn completion_item_from_function<T: AsRef<str>>(
     item.detail = Some(detail);
 
     let insert_text = sym_quote_invalid(name);
+
+    if !user_config().completions.function_parentheses {
+        item.insert_text = Some(insert_text);
+        return Ok(item);
+    }
+
     item.insert_text_format = Some(InsertTextFormat::SNIPPET);
     item.insert_text = Some(format!("{insert_text}($0)"));
 
@@ -199,6 +208,23 @@ pub(super) unsafe fn completion_item_from_dataset(name: &str) -> Result<Completi
     Ok(item)
 }
 
+pub(super) fn completion_item_from_argument_value(
+    value: &str,
+    function: &str,
+    enquote: bool,
+) -> Result<CompletionItem> {
+    let mut item = completion_item(value, CompletionData::Unknown)?;
+
+    item.kind = Some(CompletionItemKind::ENUM_MEMBER);
+    item.detail = Some(format!("{function}()"));
+
+    if enquote {
+        item.insert_text = Some(format!("\"{value}\""));
+    }
+
+    Ok(item)
+}
+
 pub(super) unsafe fn completion_item_from_data_variable(
     name: &str,
     owner: &str,
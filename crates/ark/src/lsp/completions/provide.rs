@@ -8,8 +8,11 @@
 use anyhow::Result;
 use tower_lsp::lsp_types::CompletionItem;
 
-use crate::lsp::completions::sources::completions_from_composite_sources;
-use crate::lsp::completions::sources::completions_from_unique_sources;
+use crate::lsp::completions::sources::completions_from_r_sources;
+use crate::lsp::completions::sources::completions_from_sources;
+use crate::lsp::completions::sources::spawn_static_completions;
+use crate::lsp::completions::sources::RCompletions;
+use crate::lsp::completions::sources::StaticCompletions;
 use crate::lsp::document_context::DocumentContext;
 use crate::lsp::state::WorldState;
 
@@ -21,12 +24,26 @@ pub(crate) fn provide_completions(
 ) -> Result<Vec<CompletionItem>> {
     log::info!("provide_completions()");
 
-    if let Some(completions) = completions_from_unique_sources(context)? {
-        return Ok(completions);
-    };
+    completions_from_sources(context, state)
+}
+
+// Entry points for completions of the LSP. Sources that don't need R start
+// right away on the analysis pool with `spawn_static_completions()`, while
+// the others run within an `r_task()` with `provide_r_completions()`. The
+// results are then merged with `StaticCompletions::merge()`.
+pub(crate) fn provide_static_completions(
+    context: &DocumentContext,
+    state: &WorldState,
+) -> StaticCompletions {
+    spawn_static_completions(context, state)
+}
+
+// Must be within an `r_task()`.
+pub(crate) fn provide_r_completions(
+    context: &DocumentContext,
+    state: &WorldState,
+) -> Result<RCompletions> {
+    log::info!("provide_r_completions()");
 
-    // At this point we aren't in a "unique" completion case, so just return a
-    // set of reasonable completions based on loaded packages, the open
-    // document, the current workspace, and any call related arguments
-    completions_from_composite_sources(context, state)
+    completions_from_r_sources(context, state)
 }
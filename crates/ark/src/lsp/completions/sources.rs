@@ -7,8 +7,12 @@
 
 mod common;
 mod composite;
+mod registry;
 mod unique;
 mod utils;
 
-pub use composite::completions_from_composite_sources;
-pub use unique::completions_from_unique_sources;
+pub use registry::completions_from_r_sources;
+pub use registry::completions_from_sources;
+pub use registry::spawn_static_completions;
+pub use registry::RCompletions;
+pub use registry::StaticCompletions;
@@ -5,4 +5,5 @@
 //
 //
 
+pub(crate) mod argument_value;
 pub(crate) mod subset;
@@ -0,0 +1,59 @@
+//
+// argument_value.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use anyhow::Result;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::utils::r_is_null;
+use tower_lsp::lsp_types::CompletionItem;
+
+use crate::lsp::completions::completion_item::completion_item_from_argument_value;
+use crate::lsp::completions::sources::utils::call_active_parameter;
+use crate::lsp::document_context::DocumentContext;
+
+/// Completions for the value of an argument that takes one of a closed set of
+/// values, like `cor(method = <here>)` or `TRUE`/`FALSE` flags
+///
+/// The values come from the default of the formal, e.g.
+/// `method = c("pearson", "kendall", "spearman")`, or else from the usage
+/// section of the function's documentation. Strings are quoted if `enquote`
+/// is set, and logical values aren't offered when it isn't since we are
+/// already inside a string.
+pub(crate) fn completions_from_argument_values(
+    context: &DocumentContext,
+    enquote: bool,
+) -> Result<Option<Vec<CompletionItem>>> {
+    let Some((function, argument)) = call_active_parameter(context)? else {
+        return Ok(None);
+    };
+
+    let values = RFunction::from(".ps.completions.argumentValues")
+        .param("name", function.as_str())
+        .param("argument", argument.as_str())
+        .call()?;
+
+    if r_is_null(values.sexp) {
+        return Ok(None);
+    }
+
+    let is_string: bool = values.vector_elt(1)?.try_into()?;
+    if !is_string && !enquote {
+        return Ok(None);
+    }
+
+    let values: Vec<String> = values.vector_elt(0)?.try_into()?;
+
+    let mut completions = vec![];
+    for value in values.iter() {
+        match completion_item_from_argument_value(value, &function, enquote && is_string) {
+            Ok(item) => completions.push(item),
+            Err(err) => log::error!("{err:?}"),
+        }
+    }
+
+    Ok(Some(completions))
+}
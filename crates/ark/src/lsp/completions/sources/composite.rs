@@ -5,174 +5,189 @@
 //
 //
 
+mod argument_value;
 mod call;
+mod data_table;
 mod document;
+mod formula;
 mod keyword;
+mod package;
 mod pipe;
 mod search_path;
 mod snippets;
 mod subset;
 mod workspace;
 
-use std::collections::HashSet;
+use std::time::Duration;
 
-use anyhow::Result;
+use argument_value::completions_from_argument_value;
 use call::completions_from_call;
+use data_table::completions_from_data_table;
 use document::completions_from_document;
+use formula::completions_from_formula;
 use keyword::completions_from_keywords;
+use package::completions_from_package;
 use pipe::completions_from_pipe;
-use pipe::find_pipe_root;
+pub(super) use pipe::find_pipe_root;
+pub(super) use pipe::PipeRoot;
 use search_path::completions_from_search_path;
 use snippets::completions_from_snippets;
-use stdext::*;
 use subset::completions_from_subset;
-use tower_lsp::lsp_types::CompletionItem;
-use tower_lsp::lsp_types::CompletionItemKind;
-use tree_sitter::Node;
 use workspace::completions_from_workspace;
 
-use crate::lsp::document_context::DocumentContext;
-use crate::lsp::state::WorldState;
-use crate::treesitter::NodeType;
-use crate::treesitter::NodeTypeExt;
-
-pub fn completions_from_composite_sources(
-    context: &DocumentContext,
-    state: &WorldState,
-) -> Result<Vec<CompletionItem>> {
-    log::info!("completions_from_composite_sources()");
-
-    let mut completions: Vec<CompletionItem> = vec![];
-
-    let root = find_pipe_root(context)?;
-
-    // Try argument completions
-    if let Some(mut additional_completions) = completions_from_call(context, root.clone())? {
-        completions.append(&mut additional_completions);
-    }
-
-    // Try pipe completions
-    if let Some(mut additional_completions) = completions_from_pipe(root.clone())? {
-        completions.append(&mut additional_completions);
-    }
-
-    // Try subset completions (`[` or `[[`)
-    if let Some(mut additional_completions) = completions_from_subset(context)? {
-        completions.append(&mut additional_completions);
-    }
-
-    // Call, pipe, and subset completions should show up no matter what when
-    // the user requests completions (this allows them to Tab their way through
-    // completions effectively without typing anything). For the rest of the
-    // general completions, we require an identifier to begin showing
-    // anything.
-    if is_identifier_like(context.node) {
-        completions.append(&mut completions_from_keywords());
-        completions.append(&mut completions_from_snippets());
-        completions.append(&mut completions_from_search_path(context)?);
-
-        if let Some(mut additional_completions) = completions_from_document(context)? {
-            completions.append(&mut additional_completions);
-        }
-
-        if let Some(mut additional_completions) = completions_from_workspace(context, state)? {
-            completions.append(&mut additional_completions);
-        }
-    }
-
-    // Remove duplicates
-    let mut uniques = HashSet::new();
-    completions.retain(|x| uniques.insert(x.label.clone()));
-
-    // Sort completions by providing custom 'sort' text to be used when
-    // ordering completion results. we use some placeholders at the front
-    // to 'bin' different completion types differently; e.g. we place parameter
-    // completions at the front, followed by variable completions (like pipe
-    // completions and subset completions), followed by anything else.
-    for item in &mut completions {
-        // Start with existing `sort_text` if one exists
-        let sort_text = item.sort_text.take();
-
-        let sort_text = match sort_text {
-            Some(sort_text) => sort_text,
-            None => item.label.clone(),
-        };
-
-        case! {
-            // Argument name
-            item.kind == Some(CompletionItemKind::FIELD) => {
-                item.sort_text = Some(join!["1-", sort_text]);
-            }
-
-            // Something like pipe completions, or data frame column names
-            item.kind == Some(CompletionItemKind::VARIABLE) => {
-                item.sort_text = Some(join!["2-", sort_text]);
-            }
-
-            // Package names generally have higher preference than function
-            // names. Particularly useful for `dev|` to get to `devtools::`,
-            // as that has a lot of base R functions with similar names.
-            item.kind == Some(CompletionItemKind::MODULE) => {
-                item.sort_text = Some(join!["3-", sort_text]);
-            }
-
-            => {
-                item.sort_text = Some(join!["4-", sort_text]);
-            }
-        }
-    }
-
-    Ok(completions)
-}
-
-fn is_identifier_like(x: Node) -> bool {
-    if x.is_identifier() {
-        // Obvious case
-        return true;
-    }
-
-    // If the user exactly types these keywords, then they end up matching
-    // anonymous nodes in the tree-sitter grammar, so they show up as
-    // non-`identifier` kinds. However, we do still want to provide completions
-    // here, especially in two cases:
-    // - `for<tab>` should provide completions for things like `forcats`
-    // - `for<tab>` should provide snippet completions for the `for` snippet
-    // The keywords here come from matching snippets in `r.code-snippets`.
-    if matches!(x.node_type(), NodeType::Anonymous(kind) if matches!(kind.as_str(), "if" | "for" | "while"))
-    {
-        return true;
-    }
-
-    return false;
-}
-
-#[cfg(test)]
-mod tests {
-    use tree_sitter::Point;
-
-    use crate::lsp::completions::sources::composite::is_identifier_like;
-    use crate::lsp::document_context::DocumentContext;
-    use crate::lsp::documents::Document;
-    use crate::r_task;
-    use crate::treesitter::NodeType;
-    use crate::treesitter::NodeTypeExt;
-
-    #[test]
-    fn test_completions_on_anonymous_node_keywords() {
-        r_task(|| {
-            // `if`, `for`, and `while` in particular are both tree-sitter
-            // anonymous nodes and snippet keywords, so they need to look like
-            // identifiers that we provide completions for
-            for keyword in ["if", "for", "while"] {
-                let point = Point { row: 0, column: 0 };
-                let document = Document::new(keyword, None);
-                let context = DocumentContext::new(&document, point, None);
-                assert!(is_identifier_like(context.node));
-                assert_eq!(
-                    context.node.node_type(),
-                    NodeType::Anonymous(keyword.to_string())
-                );
-            }
-        })
-    }
+use crate::lsp::completions::sources::registry::CompletionSource;
+use crate::lsp::completions::sources::registry::DedupKey;
+use crate::lsp::completions::sources::registry::Lane;
+use crate::lsp::completions::sources::registry::SourceKind;
+use crate::lsp::completions::sources::registry::Trigger;
+
+/// Composite sources, whose completions are merged. Argument value, call, pipe,
+/// formula, data.table, and subset completions show up no matter what, for the rest of the general
+/// completions we require an identifier to begin showing anything.
+///
+/// Sources that don't need R run on the static lane with a budget that
+/// reflects how much work they do: keywords and snippets are fixed lists, the
+/// document is walked once, and the workspace index may be large.
+pub(super) fn sources() -> Vec<CompletionSource> {
+    vec![
+        // Closed sets of argument values, ahead of `TRUE` and `FALSE` keywords
+        CompletionSource {
+            name: "argument_value",
+            priority: 95,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Always,
+                dedup: DedupKey::Label,
+                lane: Lane::R,
+            },
+            provide: |context| completions_from_argument_value(context.document),
+        },
+        CompletionSource {
+            name: "call",
+            priority: 90,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Always,
+                dedup: DedupKey::Label,
+                lane: Lane::R,
+            },
+            provide: |context| completions_from_call(context.document, context.pipe_root()?),
+        },
+        CompletionSource {
+            name: "pipe",
+            priority: 80,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Always,
+                dedup: DedupKey::Label,
+                lane: Lane::R,
+            },
+            provide: |context| completions_from_pipe(context.pipe_root()?),
+        },
+        // `lm(y ~ x, data = df)`
+        CompletionSource {
+            name: "formula",
+            priority: 70,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Always,
+                dedup: DedupKey::Label,
+                lane: Lane::R,
+            },
+            provide: |context| completions_from_formula(context.document),
+        },
+        // `dt[<here>]`, before `[` completions so columns aren't quoted
+        CompletionSource {
+            name: "data_table",
+            priority: 65,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Always,
+                dedup: DedupKey::Label,
+                lane: Lane::R,
+            },
+            provide: |context| completions_from_data_table(context.document),
+        },
+        // `[` or `[[`
+        CompletionSource {
+            name: "subset",
+            priority: 60,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Always,
+                dedup: DedupKey::Label,
+                lane: Lane::R,
+            },
+            provide: |context| completions_from_subset(context.document),
+        },
+        CompletionSource {
+            name: "keywords",
+            priority: 50,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Identifier,
+                dedup: DedupKey::Label,
+                lane: Lane::Static {
+                    budget: Duration::from_millis(50),
+                },
+            },
+            provide: |_| Ok(Some(completions_from_keywords())),
+        },
+        CompletionSource {
+            name: "snippets",
+            priority: 40,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Identifier,
+                dedup: DedupKey::LabelAndKind,
+                lane: Lane::Static {
+                    budget: Duration::from_millis(50),
+                },
+            },
+            provide: |context| {
+                Ok(context
+                    .state
+                    .snippet_support
+                    .then(completions_from_snippets))
+            },
+        },
+        // Package project in the workspace, ahead of the search path so its
+        // items are ranked first
+        CompletionSource {
+            name: "package",
+            priority: 35,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Identifier,
+                dedup: DedupKey::Label,
+                lane: Lane::R,
+            },
+            provide: |context| completions_from_package(context.document, context.state),
+        },
+        CompletionSource {
+            name: "search_path",
+            priority: 30,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Identifier,
+                dedup: DedupKey::Label,
+                lane: Lane::R,
+            },
+            provide: |context| Ok(Some(completions_from_search_path(context.document)?)),
+        },
+        CompletionSource {
+            name: "document",
+            priority: 20,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Identifier,
+                dedup: DedupKey::Label,
+                lane: Lane::Static {
+                    budget: Duration::from_millis(100),
+                },
+            },
+            provide: |context| completions_from_document(context.document),
+        },
+        CompletionSource {
+            name: "workspace",
+            priority: 10,
+            kind: SourceKind::Composite {
+                trigger: Trigger::Identifier,
+                dedup: DedupKey::Label,
+                lane: Lane::Static {
+                    budget: Duration::from_millis(200),
+                },
+            },
+            provide: |context| completions_from_workspace(context.document, context.state),
+        },
+    ]
 }
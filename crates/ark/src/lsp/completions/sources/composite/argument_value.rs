@@ -0,0 +1,106 @@
+//
+// argument_value.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use anyhow::Result;
+use tower_lsp::lsp_types::CompletionItem;
+
+use crate::lsp::completions::sources::common::argument_value::completions_from_argument_values;
+use crate::lsp::completions::sources::utils::call_node_position_type;
+use crate::lsp::completions::sources::utils::CallNodePositionType;
+use crate::lsp::document_context::DocumentContext;
+use crate::treesitter::node_in_string;
+
+/// Checks for `fn(arg = <here>)` completions of an argument that takes one of
+/// a closed set of values
+///
+/// Different from `unique::argument_value::completions_from_string_argument_value()`,
+/// which applies inside `""` and doesn't enquote its completion items.
+pub(super) fn completions_from_argument_value(
+    context: &DocumentContext,
+) -> Result<Option<Vec<CompletionItem>>> {
+    log::info!("completions_from_argument_value()");
+
+    const ENQUOTE: bool = true;
+
+    if node_in_string(&context.node) {
+        return Ok(None);
+    }
+
+    // Positional values like `fn(x, <here>)` are in a `Name` position, where
+    // argument names are a better bet
+    match call_node_position_type(&context.node, context.point) {
+        CallNodePositionType::Value | CallNodePositionType::Ambiguous => (),
+        CallNodePositionType::Name |
+        CallNodePositionType::Outside |
+        CallNodePositionType::Unknown => return Ok(None),
+    }
+
+    completions_from_argument_values(context, ENQUOTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use harp::eval::parse_eval_global;
+
+    use crate::fixtures::point_from_cursor;
+    use crate::lsp::completions::sources::composite::argument_value::completions_from_argument_value;
+    use crate::lsp::document_context::DocumentContext;
+    use crate::lsp::documents::Document;
+    use crate::r_task;
+
+    fn completions(code: &str) -> Option<Vec<(String, Option<String>)>> {
+        let (text, point) = point_from_cursor(code);
+        let document = Document::new(text.as_str(), None);
+        let context = DocumentContext::new(&document, point, None);
+
+        completions_from_argument_value(&context)
+            .unwrap()
+            .map(|items| {
+                items
+                    .into_iter()
+                    .map(|item| (item.label, item.insert_text))
+                    .collect()
+            })
+    }
+
+    #[test]
+    fn test_argument_value_completions() {
+        r_task(|| {
+            parse_eval_global("my_fun <- function(x, type = c('a', 'b'), flag = FALSE) x").unwrap();
+
+            let quoted = |value: &str| (String::from(value), Some(format!("\"{value}\"")));
+
+            assert_eq!(
+                completions("my_fun(1, type = @)"),
+                Some(vec![quoted("a"), quoted("b")])
+            );
+            assert_eq!(
+                completions("my_fun(1, flag = @)"),
+                Some(vec![
+                    (String::from("TRUE"), None),
+                    (String::from("FALSE"), None)
+                ])
+            );
+
+            // No closed set of values
+            assert_eq!(completions("my_fun(x = @)"), None);
+
+            // Argument names are completed here
+            assert_eq!(completions("my_fun(1, @)"), None);
+
+            // Closed set in a function of an attached package
+            let labels: Vec<String> = completions("cor(1, 2, method = @)")
+                .unwrap()
+                .into_iter()
+                .map(|(label, _)| label)
+                .collect();
+            assert_eq!(labels, vec!["pearson", "kendall", "spearman"]);
+
+            parse_eval_global("remove(my_fun)").unwrap();
+        })
+    }
+}
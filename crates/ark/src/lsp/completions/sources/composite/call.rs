@@ -261,13 +261,18 @@ fn completions_from_workspace_arguments(
     match entry.data {
         indexer::IndexEntryData::Function { name, arguments } => {
             for argument in arguments {
+                if argument == "..." {
+                    continue;
+                }
                 match completion_item_from_parameter(argument.as_str(), name.as_str(), context) {
                     Ok(item) => completions.push(item),
                     Err(err) => log::error!("{err:?}"),
                 }
             }
         },
-        indexer::IndexEntryData::Section { level: _, title: _ } => {
+        indexer::IndexEntryData::Section { level: _, title: _ } |
+        indexer::IndexEntryData::Class { .. } |
+        indexer::IndexEntryData::Generic { .. } => {
             // Not a function
             return Ok(None);
         },
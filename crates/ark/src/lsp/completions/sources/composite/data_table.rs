@@ -0,0 +1,133 @@
+//
+// data_table.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use anyhow::Result;
+use harp::utils::r_inherits;
+use tower_lsp::lsp_types::CompletionItem;
+
+use crate::lsp::completions::sources::common::subset::is_within_subset_delimiters;
+use crate::lsp::completions::sources::utils::completions_from_object_names;
+use crate::lsp::completions::sources::utils::eval_object;
+use crate::lsp::document_context::DocumentContext;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+
+/// Checks for `dt[<here>]` completions, where `dt` is a data.table
+///
+/// Columns of data.tables are referred to as variables in `i`, `j`, and `by`,
+/// so unlike `[` completions they are not quoted. In chains like
+/// `dt[i][<here>]`, the columns of the root table are completed.
+pub(super) fn completions_from_data_table(
+    context: &DocumentContext,
+) -> Result<Option<Vec<CompletionItem>>> {
+    log::info!("completions_from_data_table()");
+
+    const ENQUOTE: bool = false;
+
+    let mut node = context.node;
+
+    loop {
+        if node.node_type() == NodeType::Subset {
+            break;
+        }
+
+        // If we reach a brace list, bail
+        if node.is_braced_expression() {
+            return Ok(None);
+        }
+
+        node = match node.parent() {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+    }
+
+    if !is_within_subset_delimiters(&context.point, &node) {
+        return Ok(None);
+    }
+
+    // Evaluating the chain would call `[`, so the root table is evaluated
+    // instead. Subsetting never adds columns, though `:=` might.
+    let Some(mut root) = node.child(0) else {
+        return Ok(None);
+    };
+    while root.node_type() == NodeType::Subset {
+        root = match root.child(0) {
+            Some(child) => child,
+            None => return Ok(None),
+        };
+    }
+
+    let name = context.document.contents.node_slice(&root)?.to_string();
+
+    let Some(object) = eval_object(&name) else {
+        return Ok(None);
+    };
+    if !r_inherits(object.sexp, "data.table") {
+        return Ok(None);
+    }
+
+    Ok(Some(completions_from_object_names(
+        object,
+        name.as_str(),
+        ENQUOTE,
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use harp::eval::parse_eval_global;
+    use tree_sitter::Point;
+
+    use crate::lsp::completions::sources::composite::data_table::completions_from_data_table;
+    use crate::lsp::document_context::DocumentContext;
+    use crate::lsp::documents::Document;
+    use crate::r_task;
+
+    fn completions_at_end(code: &str) -> Option<Vec<String>> {
+        // Cursor right before the last `]`
+        let point = Point {
+            row: 0,
+            column: code.len() - 1,
+        };
+        let document = Document::new(code, None);
+        let context = DocumentContext::new(&document, point, None);
+
+        completions_from_data_table(&context)
+            .unwrap()
+            .map(|items| items.into_iter().map(|item| item.label).collect())
+    }
+
+    #[test]
+    fn test_data_table_completions() {
+        r_task(|| {
+            // Mimic a data.table without depending on the package
+            parse_eval_global(
+                "dt <- structure(list(a = 1, b = 2), class = c('data.table', 'data.frame'))",
+            )
+            .unwrap();
+            parse_eval_global("df <- data.frame(a = 1)").unwrap();
+
+            let labels = Some(vec![String::from("a"), String::from("b")]);
+            assert_eq!(completions_at_end("dt[]"), labels);
+            assert_eq!(completions_at_end("dt[, a := 1][]"), labels);
+
+            // Not quoted, unlike `[` completions
+            let point = Point { row: 0, column: 3 };
+            let document = Document::new("dt[]", None);
+            let context = DocumentContext::new(&document, point, None);
+            let items = completions_from_data_table(&context).unwrap().unwrap();
+            assert_eq!(items[0].insert_text, None);
+
+            // Data frames are left to `[` completions
+            assert_eq!(completions_at_end("df[]"), None);
+
+            parse_eval_global("remove(dt, df)").unwrap();
+        })
+    }
+}
@@ -0,0 +1,126 @@
+//
+// formula.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use anyhow::Result;
+use tower_lsp::lsp_types::CompletionItem;
+use tree_sitter::Node;
+
+use crate::lsp::completions::sources::utils::completions_from_evaluated_object_names;
+use crate::lsp::document_context::DocumentContext;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::NodeTypeExt;
+
+/// Complete the column names of the `data` argument of the call a formula is
+/// passed to, e.g. `x` and `z` in `lm(y ~ x + z, data = df)`
+pub(super) fn completions_from_formula(
+    context: &DocumentContext,
+) -> Result<Option<Vec<CompletionItem>>> {
+    log::info!("completions_from_formula()");
+
+    let Some(formula) = context.formula() else {
+        return Ok(None);
+    };
+
+    // The formula must be passed as an argument of a call
+    let Some(argument) = formula.parent() else {
+        return Ok(None);
+    };
+    if !argument.is_argument() {
+        return Ok(None);
+    }
+
+    let Some(call) = argument.parent().and_then(|arguments| arguments.parent()) else {
+        return Ok(None);
+    };
+    if !call.is_call() {
+        return Ok(None);
+    }
+
+    let Some(data) = find_data_argument(context, &call)? else {
+        return Ok(None);
+    };
+
+    const ENQUOTE: bool = false;
+
+    completions_from_evaluated_object_names(&data, ENQUOTE)
+}
+
+fn find_data_argument(context: &DocumentContext, call: &Node) -> Result<Option<String>> {
+    let Some(arguments) = call.child_by_field_name("arguments") else {
+        return Ok(None);
+    };
+
+    let mut cursor = arguments.walk();
+
+    for argument in arguments.children_by_field_name("argument", &mut cursor) {
+        let Some(name) = argument.child_by_field_name("name") else {
+            continue;
+        };
+        if context.document.contents.node_slice(&name)? != "data" {
+            continue;
+        }
+
+        let Some(value) = argument.child_by_field_name("value") else {
+            return Ok(None);
+        };
+
+        let value = context.document.contents.node_slice(&value)?.to_string();
+        return Ok(Some(value));
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use harp::eval::RParseEvalOptions;
+    use tree_sitter::Point;
+
+    use crate::lsp::completions::sources::composite::formula::completions_from_formula;
+    use crate::lsp::document_context::DocumentContext;
+    use crate::lsp::documents::Document;
+    use crate::r_task;
+
+    #[test]
+    fn test_completions_from_formula() {
+        r_task(|| {
+            let options = RParseEvalOptions {
+                forbid_function_calls: false,
+                ..Default::default()
+            };
+
+            harp::parse_eval("df <- data.frame(a = 1, b = 2)", options.clone()).unwrap();
+
+            let document = Document::new("lm(a ~ b, data = df)", None);
+
+            // On both sides of the `~`
+            for column in [4, 8] {
+                let point = Point { row: 0, column };
+                let context = DocumentContext::new(&document, point, None);
+
+                let completions = completions_from_formula(&context).unwrap().unwrap();
+                assert_eq!(completions.len(), 2);
+                assert_eq!(completions[0].label, "a");
+                assert_eq!(completions[1].label, "b");
+            }
+
+            // Outside of the formula
+            let point = Point { row: 0, column: 19 };
+            let context = DocumentContext::new(&document, point, None);
+            assert!(completions_from_formula(&context).unwrap().is_none());
+
+            // Without a `data` argument
+            let document = Document::new("lm(a ~ b)", None);
+            let point = Point { row: 0, column: 8 };
+            let context = DocumentContext::new(&document, point, None);
+            assert!(completions_from_formula(&context).unwrap().is_none());
+
+            // Clean up
+            harp::parse_eval("remove(df)", options.clone()).unwrap();
+        })
+    }
+}
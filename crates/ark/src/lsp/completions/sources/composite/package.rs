@@ -0,0 +1,172 @@
+//
+// package.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use anyhow::Result;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::RObject;
+use libr::R_lsInternal;
+use tower_lsp::lsp_types::CompletionItem;
+
+use crate::lsp::completions::completion_item::completion_item_from_namespace;
+use crate::lsp::completions::completion_item::completion_item_from_package;
+use crate::lsp::completions::sources::utils::filter_out_dot_prefixes;
+use crate::lsp::document_context::DocumentContext;
+use crate::lsp::package::Package;
+use crate::lsp::state::WorldState;
+
+/// Completions for the code of the package open in the workspace, based on
+/// its `DESCRIPTION` and `NAMESPACE` files:
+///
+/// - All functions of the package when its namespace is loaded, e.g. with
+///   `devtools::load_all()`. Internal functions are in scope in package code
+///   so they are offered without `pkg:::`.
+/// - Symbols imported in `NAMESPACE`, and exports of the packages listed in
+///   `Depends`.
+/// - Names of the packages listed in `Depends` and `Imports`.
+///
+/// These rank above completions of the same kind from the search path, such
+/// as unrelated installed packages.
+pub(super) fn completions_from_package(
+    context: &DocumentContext,
+    state: &WorldState,
+) -> Result<Option<Vec<CompletionItem>>> {
+    log::info!("completions_from_package()");
+
+    let Some(package) = Package::from_workspace(&state.workspace) else {
+        return Ok(None);
+    };
+
+    let mut completions = vec![];
+
+    let is_loaded: bool = RFunction::new("base", "isNamespaceLoaded")
+        .add(package.name.as_str())
+        .call()?
+        .try_into()?;
+    if is_loaded {
+        if let Some(namespace) = namespace(&package.name) {
+            let symbols = unsafe { RObject::new(R_lsInternal(*namespace, 1)) };
+            let symbols: Vec<String> = symbols.try_into()?;
+            push_namespace_items(&mut completions, &namespace, &package.name, &symbols);
+        }
+    }
+
+    // Packages whose exports are all in scope
+    let attached = package
+        .depends
+        .iter()
+        .chain(package.namespace.imports.iter());
+    for name in attached {
+        let Some(namespace) = namespace(name) else {
+            continue;
+        };
+        let exports = RFunction::new("base", "getNamespaceExports")
+            .add(namespace.clone())
+            .call()?;
+        let exports: Vec<String> = exports.try_into()?;
+        push_namespace_items(&mut completions, &namespace, name, &exports);
+    }
+
+    for (name, symbols) in package.namespace.imports_from.iter() {
+        let Some(namespace) = namespace(name) else {
+            continue;
+        };
+        push_namespace_items(&mut completions, &namespace, name, symbols);
+    }
+
+    for name in package.depends.iter().chain(package.imports.iter()) {
+        completions.push(unsafe { completion_item_from_package(name, true)? });
+    }
+
+    filter_out_dot_prefixes(context, &mut completions);
+
+    // Ahead of the `1-` and `2-` of search path completions
+    for item in completions.iter_mut() {
+        item.sort_text = Some(format!("0-{}", item.label));
+    }
+
+    Ok(Some(completions))
+}
+
+/// Namespace of an installed package, loaded if needed
+fn namespace(package: &str) -> Option<RObject> {
+    RFunction::new("base", "getNamespace")
+        .add(package)
+        .call()
+        .ok()
+}
+
+fn push_namespace_items(
+    completions: &mut Vec<CompletionItem>,
+    namespace: &RObject,
+    package: &str,
+    symbols: &[String],
+) {
+    for symbol in symbols {
+        match unsafe { completion_item_from_namespace(symbol, namespace.sexp, package) } {
+            Ok(item) => completions.push(item),
+            Err(err) => log::error!("{err:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Url;
+    use tree_sitter::Point;
+
+    use crate::lsp::completions::sources::composite::package::completions_from_package;
+    use crate::lsp::document_context::DocumentContext;
+    use crate::lsp::documents::Document;
+    use crate::lsp::state::WorldState;
+    use crate::r_task;
+
+    #[test]
+    fn test_package_completions() {
+        r_task(|| {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(
+                dir.path().join("DESCRIPTION"),
+                "Package: mypkg\nDepends: R (>= 4.1), tools\nImports: utils\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("NAMESPACE"), "importFrom(stats, median)\n").unwrap();
+
+            let document = Document::new("med", None);
+            let context = DocumentContext::new(&document, Point { row: 0, column: 3 }, None);
+
+            // Not in a package project
+            let state = WorldState::default();
+            assert!(completions_from_package(&context, &state)
+                .unwrap()
+                .is_none());
+
+            let mut state = WorldState::default();
+            let folder = Url::from_directory_path(dir.path()).unwrap();
+            state.workspace.folders.push(folder);
+
+            let completions = completions_from_package(&context, &state).unwrap().unwrap();
+            let sort_text = |label: &str| {
+                completions
+                    .iter()
+                    .find(|item| item.label == label)
+                    .and_then(|item| item.sort_text.clone())
+            };
+
+            // Imported from `stats`, exported by `tools`
+            assert_eq!(sort_text("median"), Some(String::from("0-median")));
+            assert_eq!(sort_text("file_ext"), Some(String::from("0-file_ext")));
+
+            // Other exports of `stats` aren't imported
+            assert_eq!(sort_text("sd"), None);
+
+            // Dependencies
+            assert_eq!(sort_text("tools"), Some(String::from("0-tools")));
+            assert_eq!(sort_text("utils"), Some(String::from("0-utils")));
+        })
+    }
+}
@@ -15,9 +15,10 @@ use crate::lsp::completions::sources::utils::completions_from_object_names;
 use crate::lsp::document_context::DocumentContext;
 use crate::lsp::traits::rope::RopeExt;
 use crate::treesitter::NodeTypeExt;
+use crate::user_config::user_config;
 
 #[derive(Clone)]
-pub(super) struct PipeRoot {
+pub(crate) struct PipeRoot {
     pub(super) name: String,
 
     /// If `None`, we found a pipe root and tried to evaluate it, but the
@@ -51,7 +52,7 @@ pub(super) fn completions_from_pipe(
 
 /// Loop should be kept in sync with `completions_from_call()` so they find
 /// the same call to detect the pipe root of
-pub(super) fn find_pipe_root(context: &DocumentContext) -> anyhow::Result<Option<PipeRoot>> {
+pub(crate) fn find_pipe_root(context: &DocumentContext) -> anyhow::Result<Option<PipeRoot>> {
     log::info!("find_pipe_root()");
 
     let mut node = context.node;
@@ -92,7 +93,7 @@ pub(super) fn find_pipe_root(context: &DocumentContext) -> anyhow::Result<Option
 
 fn eval_pipe_root(name: &str) -> Option<RObject> {
     let options = RParseEvalOptions {
-        forbid_function_calls: true,
+        forbid_function_calls: !user_config().evaluation.allow_function_calls,
         ..Default::default()
     };
 
@@ -171,6 +172,7 @@ mod tests {
     use harp::eval::RParseEvalOptions;
     use tree_sitter::Point;
 
+    use crate::lsp::completions::sources::composite::pipe::completions_from_pipe;
     use crate::lsp::completions::sources::composite::pipe::find_pipe_root;
     use crate::lsp::document_context::DocumentContext;
     use crate::lsp::documents::Document;
@@ -229,4 +231,25 @@ mod tests {
             harp::parse_eval("remove(x)", options.clone()).unwrap();
         });
     }
+
+    #[test]
+    fn test_pipe_completions_in_verb_arguments() {
+        r_task(|| {
+            harp::parse_eval_global("df <- data.frame(a = 1, b = 2)").unwrap();
+
+            // `df |> mutate(c = <here>)` and `df %>% filter(<here>)`
+            for (code, column) in [("df |> mutate(c = )", 17), ("df %>% filter()", 14)] {
+                let point = Point { row: 0, column };
+                let document = Document::new(code, None);
+                let context = DocumentContext::new(&document, point, None);
+
+                let root = find_pipe_root(&context).unwrap();
+                let completions = completions_from_pipe(root).unwrap().unwrap();
+                let labels: Vec<String> = completions.into_iter().map(|item| item.label).collect();
+                assert_eq!(labels, vec![String::from("a"), String::from("b")]);
+            }
+
+            harp::parse_eval_global("remove(df)").unwrap();
+        });
+    }
 }
@@ -97,6 +97,8 @@ pub(super) fn completions_from_workspace(
             },
 
             indexer::IndexEntryData::Section { level: _, title: _ } => {},
+            indexer::IndexEntryData::Class { .. } => {},
+            indexer::IndexEntryData::Generic { .. } => {},
         }
     });
 
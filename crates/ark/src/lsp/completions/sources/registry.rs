@@ -0,0 +1,843 @@
+//
+// registry.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::cell::OnceCell;
+use std::collections::HashSet;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use crossbeam::channel::unbounded;
+use crossbeam::channel::Receiver;
+use stdext::cancellation::CancellationToken;
+use stdext::*;
+use tower_lsp::lsp_types::CompletionItem;
+use tower_lsp::lsp_types::CompletionItemKind;
+use tree_sitter::Node;
+
+use crate::lsp::completions::sources::composite;
+use crate::lsp::completions::sources::composite::find_pipe_root;
+use crate::lsp::completions::sources::composite::PipeRoot;
+use crate::lsp::completions::sources::unique;
+use crate::lsp::document_context::DocumentContext;
+use crate::lsp::pool;
+use crate::lsp::state::WorldState;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+use crate::user_config::user_config;
+
+/// A source of completions, declared in `unique::sources()` or
+/// `composite::sources()`
+pub(super) struct CompletionSource {
+    /// Identifies the source in logs and in the `disabled_sources` setting
+    pub name: &'static str,
+
+    /// Sources are consulted by decreasing priority. Among composite sources,
+    /// the priority also decides which of two duplicate items is kept.
+    pub priority: u32,
+
+    pub kind: SourceKind,
+
+    /// Returns `None` when the source doesn't apply at the cursor
+    pub provide: fn(&SourceContext) -> Result<Option<Vec<CompletionItem>>>,
+}
+
+pub(super) enum SourceKind {
+    /// The first unique source that applies provides all completions, e.g.
+    /// `$` or `pkg::` completions. Unique sources are consulted before
+    /// composite ones.
+    Unique,
+
+    /// Completions of all composite sources that apply are merged
+    Composite {
+        trigger: Trigger,
+        dedup: DedupKey,
+        lane: Lane,
+    },
+}
+
+/// Where the cursor must be for a composite source to be consulted
+pub(super) enum Trigger {
+    /// Anywhere. Lets users Tab their way through e.g. argument names without
+    /// typing anything.
+    Always,
+
+    /// On an identifier, see `is_identifier_like()`
+    Identifier,
+}
+
+/// Items of composite sources that share a key with an item of a source of
+/// higher priority are dropped. Keys of different types never match.
+#[derive(Clone, Copy)]
+pub(super) enum DedupKey {
+    Label,
+
+    /// Lets items share a label with items of other sources, e.g. the
+    /// `lapply` snippet and the `lapply` function
+    LabelAndKind,
+}
+
+/// Where a composite source runs
+pub(super) enum Lane {
+    /// On the R thread. Sources that evaluate code or inspect R objects must
+    /// run there.
+    R,
+
+    /// On the analysis pool for completion requests of the LSP, in parallel
+    /// with the other static sources and with the R lane. These sources only
+    /// look at the document and the world state. Their items are dropped when
+    /// they take longer than `budget`, so that a slow source doesn't hold up
+    /// the whole list.
+    Static { budget: Duration },
+}
+
+/// Items of a composite source, before merging
+pub struct SourceCompletions {
+    name: &'static str,
+    priority: u32,
+    dedup: DedupKey,
+    items: Vec<CompletionItem>,
+}
+
+/// Completions of the R lane, see `completions_from_r_sources()`
+pub enum RCompletions {
+    /// A unique source applies, static sources are ignored
+    Unique(Vec<CompletionItem>),
+
+    /// Items of the composite sources of the R lane, to be merged with the
+    /// static ones
+    Composite(Vec<SourceCompletions>),
+}
+
+/// Static sources running on the analysis pool for a completion request, see
+/// `spawn_static_completions()`
+pub struct StaticCompletions {
+    rx: Receiver<(usize, Option<SourceCompletions>)>,
+
+    /// Name and deadline of the sources we're still waiting for, by index
+    pending: Vec<Option<(&'static str, Instant)>>,
+
+    /// Cancelled once the request is done with the static sources, so that
+    /// their jobs are skipped if they haven't started yet
+    done: CancellationToken,
+}
+
+/// What sources know about the completion request
+pub(super) struct SourceContext<'a> {
+    pub document: &'a DocumentContext<'a>,
+    pub state: &'a WorldState,
+    pipe_root: OnceCell<Option<PipeRoot>>,
+}
+
+impl<'a> SourceContext<'a> {
+    pub fn new(document: &'a DocumentContext<'a>, state: &'a WorldState) -> Self {
+        Self {
+            document,
+            state,
+            pipe_root: OnceCell::new(),
+        }
+    }
+
+    /// Root of the pipe chain the cursor is in, if any. Finding it may
+    /// evaluate code, so it's computed once and shared by sources.
+    pub fn pipe_root(&self) -> Result<Option<PipeRoot>> {
+        if let Some(root) = self.pipe_root.get() {
+            return Ok(root.clone());
+        }
+        let root = find_pipe_root(self.document)?;
+        Ok(self.pipe_root.get_or_init(|| root).clone())
+    }
+}
+
+/// All sources of completions, by decreasing priority
+pub(super) fn registry() -> Vec<CompletionSource> {
+    let mut sources = unique::sources();
+    sources.append(&mut composite::sources());
+    sources.sort_by(|x, y| y.priority.cmp(&x.priority));
+    sources
+}
+
+/// Sources of the registry that aren't disabled by the user
+fn enabled_sources() -> Vec<CompletionSource> {
+    let disabled = user_config().completions.disabled_sources;
+
+    registry()
+        .into_iter()
+        .filter(|source| !disabled.iter().any(|name| name == source.name))
+        .collect()
+}
+
+/// Consults all sources on the current thread, which must be within an
+/// `r_task()`
+pub fn completions_from_sources(
+    context: &DocumentContext,
+    state: &WorldState,
+) -> Result<Vec<CompletionItem>> {
+    completions_from_registry(&enabled_sources(), &SourceContext::new(context, state))
+}
+
+/// Consults the sources of the R lane, i.e. the unique sources and the
+/// composite sources that run on the R thread. The others are consulted by
+/// `spawn_static_completions()`. Must be within an `r_task()`.
+pub fn completions_from_r_sources(
+    context: &DocumentContext,
+    state: &WorldState,
+) -> Result<RCompletions> {
+    let sources = enabled_sources();
+    let context = SourceContext::new(context, state);
+
+    if let Some(completions) = completions_from_unique(&sources, &context)? {
+        return Ok(RCompletions::Unique(completions));
+    }
+
+    let results = completions_from_composite(&sources, &context, |lane| matches!(lane, Lane::R))?;
+    Ok(RCompletions::Composite(results))
+}
+
+/// Consults the static composite sources that apply at the cursor on the
+/// analysis pool. Their items are merged with those of the R lane by
+/// `StaticCompletions::merge()`.
+pub fn spawn_static_completions(
+    context: &DocumentContext,
+    state: &WorldState,
+) -> StaticCompletions {
+    let (tx, rx) = unbounded();
+    let mut pending = vec![];
+    let start = Instant::now();
+    let done = CancellationToken::new();
+
+    for source in enabled_sources() {
+        let SourceKind::Composite {
+            trigger,
+            dedup,
+            lane: Lane::Static { budget },
+        } = &source.kind
+        else {
+            continue;
+        };
+        if !trigger.matches(context) {
+            continue;
+        }
+
+        let index = pending.len();
+        pending.push(Some((source.name, start + *budget)));
+
+        let tx = tx.clone();
+        let document = context.document.clone();
+        let point = context.point;
+        let trigger = context.trigger.clone();
+        let state = state.clone();
+        let dedup = *dedup;
+        let name = source.name;
+        let priority = source.priority;
+        let provide = source.provide;
+        let done = done.clone();
+
+        // Not keyed, concurrent requests, e.g. from different clients, don't
+        // supersede each other
+        pool::spawn_analysis(None, move |_| {
+            if done.is_cancelled() {
+                return Ok(None);
+            }
+
+            let document_context = DocumentContext::new(&document, point, trigger);
+            let context = SourceContext::new(&document_context, &state);
+
+            let completions = match provide(&context) {
+                Ok(items) => items.map(|items| SourceCompletions {
+                    name,
+                    priority,
+                    dedup,
+                    items,
+                }),
+                Err(err) => {
+                    log::error!("Completion source '{name}' failed: {err:?}");
+                    None
+                },
+            };
+
+            // The request may have given up on us already
+            let _ = tx.send((index, completions));
+            Ok(None)
+        });
+    }
+
+    StaticCompletions { rx, pending, done }
+}
+
+impl StaticCompletions {
+    /// Merge the items of the static sources with those of the R lane. Waits
+    /// for the static sources that are still running, each up to the end of
+    /// its budget.
+    pub fn merge(self, completions: RCompletions) -> Vec<CompletionItem> {
+        let mut results = match completions {
+            RCompletions::Unique(completions) => return completions,
+            RCompletions::Composite(results) => results,
+        };
+
+        results.append(&mut self.collect());
+        merge_composite(results)
+    }
+
+    fn collect(mut self) -> Vec<SourceCompletions> {
+        let mut results = vec![];
+        let mut pending = std::mem::take(&mut self.pending);
+
+        while let Some(deadline) = pending.iter().flatten().map(|(_, deadline)| *deadline).max() {
+            // Times out, or all jobs are done or were dropped by the pool
+            let Ok((index, completions)) = self.rx.recv_deadline(deadline) else {
+                break;
+            };
+            let Some((name, deadline)) = pending[index].take() else {
+                continue;
+            };
+
+            if Instant::now() > deadline {
+                log::warn!("Dropping completions of source '{name}', it exceeded its budget");
+                continue;
+            }
+            if let Some(completions) = completions {
+                log::info!(
+                    "Completions from static source '{name}': {} items",
+                    completions.items.len()
+                );
+                results.push(completions);
+            }
+        }
+
+        for (name, _) in pending.into_iter().flatten() {
+            log::warn!("Dropping completions of source '{name}', it exceeded its budget");
+        }
+
+        results
+    }
+}
+
+impl Drop for StaticCompletions {
+    fn drop(&mut self) {
+        self.done.cancel();
+    }
+}
+
+/// Consults `sources`, which must be sorted by decreasing priority
+fn completions_from_registry(
+    sources: &[CompletionSource],
+    context: &SourceContext,
+) -> Result<Vec<CompletionItem>> {
+    if let Some(completions) = completions_from_unique(sources, context)? {
+        return Ok(completions);
+    }
+
+    // At this point we aren't in a "unique" completion case, so just return a
+    // set of reasonable completions based on loaded packages, the open
+    // document, the current workspace, and any call related arguments
+    let results = completions_from_composite(sources, context, |_| true)?;
+    Ok(merge_composite(results))
+}
+
+fn completions_from_unique(
+    sources: &[CompletionSource],
+    context: &SourceContext,
+) -> Result<Option<Vec<CompletionItem>>> {
+    for source in sources {
+        let SourceKind::Unique = source.kind else {
+            continue;
+        };
+        if let Some(completions) = (source.provide)(context)? {
+            log::info!("Completions from unique source '{}'", source.name);
+            return Ok(Some(completions));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Consults the composite sources of the lanes selected by `filter`, one
+/// after the other
+fn completions_from_composite(
+    sources: &[CompletionSource],
+    context: &SourceContext,
+    filter: impl Fn(&Lane) -> bool,
+) -> Result<Vec<SourceCompletions>> {
+    let mut results = vec![];
+
+    for source in sources {
+        let SourceKind::Composite {
+            trigger,
+            dedup,
+            lane,
+        } = &source.kind
+        else {
+            continue;
+        };
+        if !filter(lane) {
+            continue;
+        }
+
+        // Give up between sources once the request is cancelled, e.g. when
+        // the frontend interrupts a completion request on the Shell socket
+        harp::cancellation::check_cancelled()?;
+
+        if !trigger.matches(context.document) {
+            continue;
+        }
+
+        let Some(items) = (source.provide)(context)? else {
+            continue;
+        };
+        log::info!(
+            "Completions from composite source '{}': {} items",
+            source.name,
+            items.len()
+        );
+
+        results.push(SourceCompletions {
+            name: source.name,
+            priority: source.priority,
+            dedup: *dedup,
+            items,
+        });
+    }
+
+    Ok(results)
+}
+
+/// Merge the items of composite sources by decreasing priority, regardless of
+/// the order in which the sources completed
+fn merge_composite(mut results: Vec<SourceCompletions>) -> Vec<CompletionItem> {
+    results.sort_by(|x, y| y.priority.cmp(&x.priority).then(x.name.cmp(y.name)));
+
+    let mut completions: Vec<CompletionItem> = vec![];
+    let mut uniques = HashSet::new();
+
+    for result in results {
+        for item in result.items {
+            // Kinds are compared through their debug representation since
+            // `CompletionItemKind` isn't `Hash`
+            let key = match result.dedup {
+                DedupKey::Label => (item.label.clone(), None),
+                DedupKey::LabelAndKind => (item.label.clone(), Some(format!("{:?}", item.kind))),
+            };
+            if uniques.insert(key) {
+                completions.push(item);
+            }
+        }
+    }
+
+    set_sort_text_by_kind(&mut completions);
+
+    completions
+}
+
+impl Trigger {
+    fn matches(&self, context: &DocumentContext) -> bool {
+        match self {
+            Trigger::Always => true,
+            Trigger::Identifier => is_identifier_like(context.node),
+        }
+    }
+}
+
+/// Sort completions by providing custom 'sort' text to be used when
+/// ordering completion results. we use some placeholders at the front
+/// to 'bin' different completion types differently; e.g. we place argument
+/// values and parameter completions at the front, followed by variable
+/// completions (like pipe completions and subset completions), followed by
+/// anything else.
+fn set_sort_text_by_kind(completions: &mut [CompletionItem]) {
+    for item in completions {
+        // Start with existing `sort_text` if one exists
+        let sort_text = item.sort_text.take();
+
+        let sort_text = match sort_text {
+            Some(sort_text) => sort_text,
+            None => item.label.clone(),
+        };
+
+        case! {
+            // Closed set of values of an argument
+            item.kind == Some(CompletionItemKind::ENUM_MEMBER) => {
+                item.sort_text = Some(join!["0-", sort_text]);
+            }
+
+            // Argument name
+            item.kind == Some(CompletionItemKind::FIELD) => {
+                item.sort_text = Some(join!["1-", sort_text]);
+            }
+
+            // Something like pipe completions, or data frame column names
+            item.kind == Some(CompletionItemKind::VARIABLE) => {
+                item.sort_text = Some(join!["2-", sort_text]);
+            }
+
+            // Package names generally have higher preference than function
+            // names. Particularly useful for `dev|` to get to `devtools::`,
+            // as that has a lot of base R functions with similar names.
+            item.kind == Some(CompletionItemKind::MODULE) => {
+                item.sort_text = Some(join!["3-", sort_text]);
+            }
+
+            => {
+                item.sort_text = Some(join!["4-", sort_text]);
+            }
+        }
+    }
+}
+
+fn is_identifier_like(x: Node) -> bool {
+    if x.is_identifier() {
+        // Obvious case
+        return true;
+    }
+
+    // If the user exactly types these keywords, then they end up matching
+    // anonymous nodes in the tree-sitter grammar, so they show up as
+    // non-`identifier` kinds. However, we do still want to provide completions
+    // here, especially in two cases:
+    // - `for<tab>` should provide completions for things like `forcats`
+    // - `for<tab>` should provide snippet completions for the `for` snippet
+    // The keywords here come from matching snippets in `r.code-snippets`.
+    if matches!(x.node_type(), NodeType::Anonymous(kind) if matches!(kind.as_str(), "if" | "for" | "while" | "function"))
+    {
+        return true;
+    }
+
+    return false;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use crossbeam::channel::unbounded;
+
+    use tower_lsp::lsp_types::CompletionItem;
+    use tower_lsp::lsp_types::CompletionItemKind;
+    use tower_lsp::lsp_types::InsertTextFormat;
+    use tree_sitter::Point;
+
+    use crate::lsp::completions::sources::registry::completions_from_registry;
+    use crate::lsp::completions::sources::registry::is_identifier_like;
+    use crate::lsp::completions::sources::registry::registry;
+    use crate::lsp::completions::sources::registry::CompletionSource;
+    use crate::lsp::completions::sources::registry::merge_composite;
+    use crate::lsp::completions::sources::registry::spawn_static_completions;
+    use crate::lsp::completions::sources::registry::DedupKey;
+    use crate::lsp::completions::sources::registry::Lane;
+    use crate::lsp::completions::sources::registry::RCompletions;
+    use crate::lsp::completions::sources::registry::SourceCompletions;
+    use crate::lsp::completions::sources::registry::SourceContext;
+    use crate::lsp::completions::sources::registry::SourceKind;
+    use crate::lsp::completions::sources::registry::StaticCompletions;
+    use crate::lsp::completions::sources::registry::Trigger;
+    use crate::lsp::document_context::DocumentContext;
+    use crate::lsp::documents::Document;
+    use crate::lsp::state::WorldState;
+    use crate::r_task;
+    use crate::treesitter::NodeType;
+    use crate::treesitter::NodeTypeExt;
+
+    fn item(label: &str, kind: CompletionItemKind) -> CompletionItem {
+        CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            ..Default::default()
+        }
+    }
+
+    fn composite(
+        name: &'static str,
+        priority: u32,
+        trigger: Trigger,
+        dedup: DedupKey,
+        provide: fn(&SourceContext) -> anyhow::Result<Option<Vec<CompletionItem>>>,
+    ) -> CompletionSource {
+        CompletionSource {
+            name,
+            priority,
+            kind: SourceKind::Composite {
+                trigger,
+                dedup,
+                lane: Lane::R,
+            },
+            provide,
+        }
+    }
+
+    fn completions_at(code: &str, sources: &[CompletionSource]) -> Vec<CompletionItem> {
+        let state = WorldState {
+            snippet_support: true,
+            ..Default::default()
+        };
+        completions_with_state_at(code, sources, &state)
+    }
+
+    fn completions_with_state_at(
+        code: &str,
+        sources: &[CompletionSource],
+        state: &WorldState,
+    ) -> Vec<CompletionItem> {
+        let document = Document::new(code, None);
+        let point = Point {
+            row: 0,
+            column: code.len(),
+        };
+        let context = DocumentContext::new(&document, point, None);
+        completions_from_registry(sources, &SourceContext::new(&context, state)).unwrap()
+    }
+
+    fn labels(completions: &[CompletionItem]) -> Vec<(&str, Option<CompletionItemKind>)> {
+        completions
+            .iter()
+            .map(|item| (item.label.as_str(), item.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_completions_registry() {
+        let sources = registry();
+
+        let names: HashSet<&str> = sources.iter().map(|source| source.name).collect();
+        assert_eq!(names.len(), sources.len());
+
+        // Unique sources are consulted first so they're declared first
+        let first_composite = sources
+            .iter()
+            .position(|source| matches!(source.kind, SourceKind::Composite { .. }))
+            .unwrap();
+        assert!(sources[first_composite..]
+            .iter()
+            .all(|source| matches!(source.kind, SourceKind::Composite { .. })));
+    }
+
+    #[test]
+    fn test_completions_from_registry_dedup() {
+        let sources = vec![
+            composite("a", 3, Trigger::Always, DedupKey::Label, |_| {
+                Ok(Some(vec![item("foo", CompletionItemKind::FUNCTION)]))
+            }),
+            composite("b", 2, Trigger::Always, DedupKey::LabelAndKind, |_| {
+                Ok(Some(vec![
+                    item("foo", CompletionItemKind::SNIPPET),
+                    item("bar", CompletionItemKind::SNIPPET),
+                ]))
+            }),
+            composite("c", 1, Trigger::Always, DedupKey::Label, |_| {
+                Ok(Some(vec![
+                    item("foo", CompletionItemKind::VARIABLE),
+                    item("bar", CompletionItemKind::VARIABLE),
+                ]))
+            }),
+        ];
+
+        // Items of higher priority win, keys of different types never match
+        let completions = completions_at("x", &sources);
+        assert_eq!(labels(&completions), vec![
+            ("foo", Some(CompletionItemKind::FUNCTION)),
+            ("foo", Some(CompletionItemKind::SNIPPET)),
+            ("bar", Some(CompletionItemKind::SNIPPET)),
+            ("bar", Some(CompletionItemKind::VARIABLE)),
+        ]);
+
+        // Items are binned by kind
+        assert_eq!(completions[0].sort_text, Some(String::from("4-foo")));
+        assert_eq!(completions[3].sort_text, Some(String::from("2-bar")));
+    }
+
+    #[test]
+    fn test_completions_from_registry_triggers() {
+        let sources = vec![
+            CompletionSource {
+                name: "none",
+                priority: 4,
+                kind: SourceKind::Unique,
+                provide: |_| Ok(None),
+            },
+            composite("always", 2, Trigger::Always, DedupKey::Label, |_| {
+                Ok(Some(vec![item("always", CompletionItemKind::FIELD)]))
+            }),
+            composite(
+                "identifier",
+                1,
+                Trigger::Identifier,
+                DedupKey::Label,
+                |_| Ok(Some(vec![item("identifier", CompletionItemKind::FUNCTION)])),
+            ),
+        ];
+
+        let completions = completions_at("x", &sources);
+        assert_eq!(labels(&completions), vec![
+            ("always", Some(CompletionItemKind::FIELD)),
+            ("identifier", Some(CompletionItemKind::FUNCTION)),
+        ]);
+
+        let completions = completions_at("1", &sources);
+        assert_eq!(labels(&completions), vec![(
+            "always",
+            Some(CompletionItemKind::FIELD)
+        )]);
+
+        // Unique sources that apply take over
+        let mut sources = sources;
+        sources.insert(0, CompletionSource {
+            name: "unique",
+            priority: 5,
+            kind: SourceKind::Unique,
+            provide: |_| Ok(Some(vec![])),
+        });
+        assert!(completions_at("x", &sources).is_empty());
+    }
+
+    fn source_completions(
+        name: &'static str,
+        priority: u32,
+        items: Vec<CompletionItem>,
+    ) -> SourceCompletions {
+        SourceCompletions {
+            name,
+            priority,
+            dedup: DedupKey::Label,
+            items,
+        }
+    }
+
+    #[test]
+    fn test_merge_composite_is_deterministic() {
+        let results = || {
+            vec![
+                source_completions("a", 1, vec![item("foo", CompletionItemKind::VARIABLE)]),
+                source_completions("b", 2, vec![item("foo", CompletionItemKind::FUNCTION)]),
+                source_completions("c", 2, vec![item("bar", CompletionItemKind::FUNCTION)]),
+            ]
+        };
+
+        // Sources are merged by priority and name, whatever the order in
+        // which they completed
+        let expected = vec![
+            ("foo", Some(CompletionItemKind::FUNCTION)),
+            ("bar", Some(CompletionItemKind::FUNCTION)),
+        ];
+
+        let completions = merge_composite(results());
+        assert_eq!(labels(&completions), expected);
+
+        let mut reversed = results();
+        reversed.reverse();
+        let completions = merge_composite(reversed);
+        assert_eq!(labels(&completions), expected);
+    }
+
+    #[test]
+    fn test_static_completions_budget() {
+        let (tx, rx) = unbounded();
+        let now = Instant::now();
+
+        let statics = StaticCompletions {
+            rx,
+            pending: vec![
+                Some(("slow", now)),
+                Some(("fast", now + Duration::from_secs(10))),
+            ],
+        };
+
+        std::thread::sleep(Duration::from_millis(1));
+
+        let slow = source_completions("slow", 2, vec![item("slow", CompletionItemKind::FUNCTION)]);
+        let fast = source_completions("fast", 1, vec![item("fast", CompletionItemKind::FUNCTION)]);
+        tx.send((0, Some(slow))).unwrap();
+        tx.send((1, Some(fast))).unwrap();
+        drop(tx);
+
+        // Items arriving after the budget of their source are dropped
+        let completions = statics.merge(RCompletions::Composite(vec![]));
+        assert_eq!(labels(&completions), vec![(
+            "fast",
+            Some(CompletionItemKind::FUNCTION)
+        )]);
+    }
+
+    #[test]
+    fn test_static_completions_on_pool() {
+        let document = Document::new("TRU", None);
+        let point = Point { row: 0, column: 3 };
+        let context = DocumentContext::new(&document, point, None);
+        let state = WorldState::default();
+
+        // Keywords are a static source
+        let statics = spawn_static_completions(&context, &state);
+        let completions = statics.merge(RCompletions::Composite(vec![]));
+        assert!(completions.iter().any(|item| item.label == "TRUE"));
+
+        // Unique sources of the R lane take over
+        let statics = spawn_static_completions(&context, &state);
+        let completions = statics.merge(RCompletions::Unique(vec![]));
+        assert!(completions.is_empty());
+    }
+
+    #[test]
+    fn test_completions_from_registered_source() {
+        r_task(|| {
+            // Registered sources can be consulted on their own
+            let sources: Vec<CompletionSource> = registry()
+                .into_iter()
+                .filter(|source| matches!(source.name, "snippets" | "search_path"))
+                .collect();
+
+            // The `lapply` snippet doesn't hide the `lapply` function
+            let completions = completions_at("lapply", &sources);
+            let kinds: Vec<Option<CompletionItemKind>> = completions
+                .iter()
+                .filter(|item| item.label == "lapply")
+                .map(|item| item.kind)
+                .collect();
+            assert_eq!(kinds, vec![
+                Some(CompletionItemKind::SNIPPET),
+                Some(CompletionItemKind::FUNCTION)
+            ]);
+        })
+    }
+
+    #[test]
+    fn test_completions_snippets_require_client_support() {
+        let sources: Vec<CompletionSource> = registry()
+            .into_iter()
+            .filter(|source| source.name == "snippets")
+            .collect();
+
+        let completions = completions_at("tryCatch", &sources);
+        let item = completions
+            .iter()
+            .find(|item| item.label == "tryCatch")
+            .unwrap();
+        assert_eq!(item.insert_text_format, Some(InsertTextFormat::SNIPPET));
+
+        // Clients that can't expand snippets don't get any
+        let state = WorldState::default();
+        assert!(completions_with_state_at("tryCatch", &sources, &state).is_empty());
+    }
+
+    #[test]
+    fn test_completions_on_anonymous_node_keywords() {
+        r_task(|| {
+            // `if`, `for`, `while`, and `function` in particular are both
+            // tree-sitter anonymous nodes and snippet keywords, so they need to
+            // look like identifiers that we provide completions for
+            for keyword in ["if", "for", "while", "function"] {
+                let point = Point { row: 0, column: 0 };
+                let document = Document::new(keyword, None);
+                let context = DocumentContext::new(&document, point, None);
+                assert!(is_identifier_like(context.node));
+                assert_eq!(
+                    context.node.node_type(),
+                    NodeType::Anonymous(keyword.to_string())
+                );
+            }
+        })
+    }
+}
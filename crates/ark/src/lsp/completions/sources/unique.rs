@@ -5,6 +5,7 @@
 //
 //
 
+mod argument_value;
 mod colon;
 mod comment;
 mod custom;
@@ -14,7 +15,6 @@ mod namespace;
 mod string;
 mod subset;
 
-use anyhow::Result;
 use colon::completions_from_single_colon;
 use comment::completions_from_comment;
 use custom::completions_from_custom_source;
@@ -22,52 +22,61 @@ use extractor::completions_from_at;
 use extractor::completions_from_dollar;
 use namespace::completions_from_namespace;
 use string::completions_from_string;
-use tower_lsp::lsp_types::CompletionItem;
 
-use crate::lsp::document_context::DocumentContext;
+use crate::lsp::completions::sources::registry::CompletionSource;
+use crate::lsp::completions::sources::registry::SourceKind;
 
-pub fn completions_from_unique_sources(
-    context: &DocumentContext,
-) -> Result<Option<Vec<CompletionItem>>> {
-    log::info!("completions_from_unique_sources()");
-
-    // Try to detect a single colon first, which is a special case where we
-    // don't provide any completions
-    if let Some(completions) = completions_from_single_colon(context) {
-        return Ok(Some(completions));
-    }
-
-    // Try comment / roxygen2 completions
-    if let Some(completions) = completions_from_comment(context)? {
-        return Ok(Some(completions));
-    }
-
-    // Try string (like file path) completions
-    if let Some(completions) = completions_from_string(context)? {
-        return Ok(Some(completions));
-    }
-
-    // Try `package::prefix` (or `:::`) namespace completions
-    if let Some(completions) = completions_from_namespace(context)? {
-        return Ok(Some(completions));
-    }
-
-    // Try specialized custom completions
-    // (Should be before more general ast / call completions)
-    if let Some(completions) = completions_from_custom_source(context)? {
-        return Ok(Some(completions));
-    }
-
-    // Try `$` completions
-    if let Some(completions) = completions_from_dollar(context)? {
-        return Ok(Some(completions));
-    }
-
-    // Try `@` completions
-    if let Some(completions) = completions_from_at(context)? {
-        return Ok(Some(completions));
-    }
-
-    // No unique sources of completions, allow composite sources to run
-    Ok(None)
+/// Unique sources, the first one that applies provides all completions
+pub(super) fn sources() -> Vec<CompletionSource> {
+    vec![
+        // A single colon is a special case where we don't provide any
+        // completions
+        CompletionSource {
+            name: "colon",
+            priority: 170,
+            kind: SourceKind::Unique,
+            provide: |context| Ok(completions_from_single_colon(context.document)),
+        },
+        // Comment / roxygen2 completions
+        CompletionSource {
+            name: "comment",
+            priority: 160,
+            kind: SourceKind::Unique,
+            provide: |context| completions_from_comment(context.document),
+        },
+        // String (like file path) completions
+        CompletionSource {
+            name: "string",
+            priority: 150,
+            kind: SourceKind::Unique,
+            provide: |context| completions_from_string(context.document),
+        },
+        // `package::prefix` (or `:::`) namespace completions
+        CompletionSource {
+            name: "namespace",
+            priority: 140,
+            kind: SourceKind::Unique,
+            provide: |context| completions_from_namespace(context.document),
+        },
+        // Specialized custom completions, should be before more general ast /
+        // call completions
+        CompletionSource {
+            name: "custom",
+            priority: 130,
+            kind: SourceKind::Unique,
+            provide: |context| completions_from_custom_source(context.document),
+        },
+        CompletionSource {
+            name: "dollar",
+            priority: 120,
+            kind: SourceKind::Unique,
+            provide: |context| completions_from_dollar(context.document),
+        },
+        CompletionSource {
+            name: "at",
+            priority: 110,
+            kind: SourceKind::Unique,
+            provide: |context| completions_from_at(context.document),
+        },
+    ]
 }
@@ -0,0 +1,89 @@
+//
+// argument_value.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use anyhow::Result;
+use tower_lsp::lsp_types::CompletionItem;
+use tree_sitter::Node;
+
+use crate::lsp::completions::sources::common::argument_value::completions_from_argument_values;
+use crate::lsp::document_context::DocumentContext;
+use crate::treesitter::NodeTypeExt;
+
+/// Checks for `fn(arg = "<here>")` completions of an argument that takes one of
+/// a closed set of strings
+///
+/// Only applies when the string is the whole value of the argument, not e.g.
+/// in `fn(arg = paste0("<here>"))`. Different from
+/// `composite::argument_value::completions_from_argument_value()`, which
+/// applies outside of `""` and enquotes its completion items.
+pub(super) fn completions_from_string_argument_value(
+    node: &Node,
+    context: &DocumentContext,
+) -> Result<Option<Vec<CompletionItem>>> {
+    log::info!("completions_from_string_argument_value()");
+
+    // Already inside a string
+    const ENQUOTE: bool = false;
+
+    let Some(argument) = node.parent() else {
+        return Ok(None);
+    };
+    if !argument.is_argument() {
+        return Ok(None);
+    }
+    if argument.child_by_field_name("value") != Some(*node) {
+        return Ok(None);
+    }
+
+    completions_from_argument_values(context, ENQUOTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use harp::eval::parse_eval_global;
+
+    use crate::fixtures::point_from_cursor;
+    use crate::lsp::completions::sources::unique::string::completions_from_string;
+    use crate::lsp::document_context::DocumentContext;
+    use crate::lsp::documents::Document;
+    use crate::r_task;
+
+    #[test]
+    fn test_string_argument_value_completions() {
+        r_task(|| {
+            parse_eval_global("my_fun <- function(type = c('a', 'b'), flag = FALSE) type").unwrap();
+
+            let completions = |code: &str| {
+                let (text, point) = point_from_cursor(code);
+                let document = Document::new(text.as_str(), None);
+                let context = DocumentContext::new(&document, point, None);
+                completions_from_string(&context).unwrap().unwrap()
+            };
+
+            // Not quoted again
+            let items = completions("my_fun(type = '@')");
+            let items: Vec<(String, Option<String>)> = items
+                .into_iter()
+                .map(|item| (item.label, item.insert_text))
+                .collect();
+            assert_eq!(items, vec![
+                (String::from("a"), None),
+                (String::from("b"), None)
+            ]);
+
+            // Positional
+            let items = completions("my_fun('@')");
+            assert_eq!(items.len(), 2);
+
+            // Not the whole value of the argument, falls back to file paths
+            let items = completions("my_fun(type = c('@'))");
+            assert!(!items.iter().any(|item| item.label == "a"));
+
+            parse_eval_global("remove(my_fun)").unwrap();
+        })
+    }
+}
@@ -5,25 +5,128 @@
 //
 //
 
+use std::collections::HashSet;
 use std::path::Path;
+use std::sync::LazyLock;
 
 use anyhow::Result;
 use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
 use regex::Regex;
 use tower_lsp::lsp_types::CompletionItem;
+use tower_lsp::lsp_types::CompletionItemKind;
 use tower_lsp::lsp_types::Documentation;
 use tower_lsp::lsp_types::InsertTextFormat;
 use tower_lsp::lsp_types::MarkupContent;
 use tower_lsp::lsp_types::MarkupKind;
+use tree_sitter::Node;
 use yaml_rust::YamlLoader;
 
 use crate::lsp::completions::completion_item::completion_item;
+use crate::lsp::completions::completion_item::completion_item_from_scope_parameter;
 use crate::lsp::completions::types::CompletionData;
 use crate::lsp::document_context::DocumentContext;
+use crate::lsp::indexer;
 use crate::lsp::traits::rope::RopeExt;
+use crate::lsp::traits::string::StringExt;
+use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::NodeType;
 use crate::treesitter::NodeTypeExt;
 
+/// Tags offered when roxygen2, which ships the full list, isn't installed:
+/// `(name, template, description)`
+const ROXYGEN_TAGS: &[(&str, Option<&str>, &str)] = &[
+    (
+        "aliases",
+        Some(" ${1:alias}"),
+        "Add additional aliases to the topic.",
+    ),
+    (
+        "description",
+        Some("\n${1:A short description...}\n"),
+        "A short description of the purpose of the function.",
+    ),
+    (
+        "details",
+        Some("\n${1:Additional details...}\n"),
+        "Additional details about the function.",
+    ),
+    (
+        "examples",
+        Some("\n${1:# example code}\n"),
+        "Executable R code that demonstrates how the function works.",
+    ),
+    (
+        "examplesIf",
+        Some(" ${1:condition}\n${2:# example code}\n"),
+        "Run examples only when `condition` is `TRUE`.",
+    ),
+    (
+        "export",
+        None,
+        "Export this function, method, generic, or class so it's available outside of the package.",
+    ),
+    (
+        "family",
+        Some(" ${1:family name}"),
+        "Add a See Also section linking to the other functions of the family.",
+    ),
+    (
+        "importFrom",
+        Some(" ${1:package} ${2:function}"),
+        "Import specific functions from a package.",
+    ),
+    (
+        "inheritParams",
+        Some(" ${1:source}"),
+        "Inherit argument documentation from another function.",
+    ),
+    (
+        "keywords",
+        Some(" ${1:keyword}"),
+        "Add standardised keywords, e.g. `internal`.",
+    ),
+    ("name", Some(" ${1:name}"), "The name of the topic."),
+    ("noRd", None, "Suppress generation of the `.Rd` file."),
+    (
+        "param",
+        Some(" ${1:name} ${2:description}"),
+        "Describe a function input.",
+    ),
+    (
+        "rdname",
+        Some(" ${1:topic-name}"),
+        "Override the name of the generated `.Rd` file.",
+    ),
+    (
+        "return",
+        Some(" ${1:description}"),
+        "Describe the function's output.",
+    ),
+    (
+        "seealso",
+        Some(" ${1:links}"),
+        "Link to other related documentation.",
+    ),
+    (
+        "title",
+        Some(" ${1:title}"),
+        "A one-line description shown at the top of the documentation page.",
+    ),
+];
+
+/// `#' @param <here>`, possibly after other names, as in `@param x,<here>`
+static RE_ROXYGEN_PARAM_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"@param\s+[\w.,]*$").unwrap());
+
+/// Names documented by a `@param` tag
+static RE_ROXYGEN_PARAM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"@param\s+([\w.,]+)").unwrap());
+
+/// `#' \link{<here>}`
+static RE_ROXYGEN_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\link\{([\w.]*)$").unwrap());
+
 pub fn completions_from_comment(context: &DocumentContext) -> Result<Option<Vec<CompletionItem>>> {
     log::info!("completions_from_comment()");
 
@@ -33,10 +136,25 @@ pub fn completions_from_comment(context: &DocumentContext) -> Result<Option<Vec<
         return Ok(None);
     }
 
-    let pattern = Regex::new(r"^.*\s")?;
-
+    // The comment up to the cursor. Comments don't span several lines.
     let contents = context.document.contents.node_slice(&node)?.to_string();
-    let token = pattern.replace(contents.as_str(), "");
+    let offset = context
+        .point
+        .column
+        .saturating_sub(node.start_position().column);
+    let contents = contents.get(..offset).unwrap_or(contents.as_str());
+
+    if contents.starts_with("#'") {
+        if RE_ROXYGEN_PARAM_NAME.is_match(contents) {
+            return Ok(Some(completions_from_roxygen_param(context)?));
+        }
+        if let Some(captures) = RE_ROXYGEN_LINK.captures(contents) {
+            return Ok(Some(completions_from_roxygen_link(context, &captures[1])?));
+        }
+    }
+
+    let pattern = Regex::new(r"^.*\s")?;
+    let token = pattern.replace(contents, "");
 
     let mut completions: Vec<CompletionItem> = vec![];
 
@@ -56,12 +174,13 @@ pub fn completions_from_comment(context: &DocumentContext) -> Result<Option<Vec<
             .to::<String>()?
     };
 
-    if tags.is_empty() {
-        return Ok(Some(completions));
-    }
-
     let tags = Path::new(&tags);
-    if !tags.exists() {
+    if tags.as_os_str().is_empty() || !tags.exists() {
+        for (name, template, description) in ROXYGEN_TAGS {
+            let template = template.map(inject_roxygen_comment_after_newline);
+            let item = completion_item_from_roxygen(name, template.as_deref(), Some(*description))?;
+            completions.push(item);
+        }
         return Ok(Some(completions));
     }
 
@@ -89,6 +208,107 @@ pub fn completions_from_comment(context: &DocumentContext) -> Result<Option<Vec<
     Ok(Some(completions))
 }
 
+/// Parameters of the function documented by the roxygen block around the
+/// cursor, for `#' @param <here>`. Parameters that the block already
+/// documents are left out.
+fn completions_from_roxygen_param(context: &DocumentContext) -> Result<Vec<CompletionItem>> {
+    let contents = &context.document.contents;
+    let mut documented: HashSet<String> = HashSet::new();
+
+    let mut collect_documented = |node: &Node| -> Result<()> {
+        let comment = contents.node_slice(node)?.to_string();
+        if let Some(captures) = RE_ROXYGEN_PARAM.captures(&comment) {
+            documented.extend(captures[1].split(',').map(String::from));
+        }
+        Ok(())
+    };
+
+    let mut node = context.node;
+    while let Some(previous) = node.prev_sibling().filter(|x| x.is_comment()) {
+        collect_documented(&previous)?;
+        node = previous;
+    }
+
+    // The block ends with the documented expression
+    let mut node = context.node;
+    let documented_node = loop {
+        match node.next_sibling() {
+            Some(next) if next.is_comment() => {
+                collect_documented(&next)?;
+                node = next;
+            },
+            next => break next,
+        }
+    };
+
+    let Some(function) = documented_node.and_then(roxygen_documented_function) else {
+        return Ok(vec![]);
+    };
+    let Some(parameters) = function.child_by_field_name("parameters") else {
+        return Ok(vec![]);
+    };
+
+    let mut completions = vec![];
+
+    let mut cursor = parameters.walk();
+    for parameter in parameters.children_by_field_name("parameter", &mut cursor) {
+        let Some(name) = parameter.child_by_field_name("name") else {
+            continue;
+        };
+        let name = contents.node_slice(&name)?.to_string();
+        if documented.contains(&name) {
+            continue;
+        }
+        completions.push(completion_item_from_scope_parameter(&name, context)?);
+    }
+
+    Ok(completions)
+}
+
+/// The function definition of `fn <- function() {}`, or of a bare
+/// `function() {}`
+fn roxygen_documented_function(node: Node) -> Option<Node> {
+    let node = match node.node_type() {
+        NodeType::BinaryOperator(BinaryOperatorType::LeftAssignment) |
+        NodeType::BinaryOperator(BinaryOperatorType::EqualsAssignment) => {
+            node.child_by_field_name("rhs")?
+        },
+        _ => node,
+    };
+
+    node.is_function_definition().then_some(node)
+}
+
+/// Functions of the document and of the workspace, for `#' \link{<here>}`
+fn completions_from_roxygen_link(
+    context: &DocumentContext,
+    token: &str,
+) -> Result<Vec<CompletionItem>> {
+    // The document's own functions come first, it might not be indexed yet
+    let mut entries = indexer::index_entries(context.document);
+    indexer::map(|_path, _symbol, entry| entries.push(entry.clone()));
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut completions = vec![];
+
+    for entry in entries {
+        let indexer::IndexEntryData::Function { name, arguments } = entry.data else {
+            continue;
+        };
+        if !name.fuzzy_matches(token) || !seen.insert(name.clone()) {
+            continue;
+        }
+
+        let mut item =
+            completion_item(name.clone(), CompletionData::Object { name: name.clone() })?;
+        item.kind = Some(CompletionItemKind::FUNCTION);
+        item.detail = Some(format!("{name}({})", arguments.join(", ")));
+        completions.push(item);
+    }
+
+    Ok(completions)
+}
+
 fn completion_item_from_roxygen(
     name: &str,
     template: Option<&str>,
@@ -197,6 +417,60 @@ fn test_roxygen_comment() {
     });
 }
 
+#[test]
+fn test_roxygen_param_completions() {
+    use tree_sitter::Point;
+
+    use crate::lsp::documents::Document;
+
+    let labels = |code: &str, point: Point| -> Vec<String> {
+        let document = Document::new(code, None);
+        let context = DocumentContext::new(&document, point, None);
+        let completions = completions_from_comment(&context).unwrap().unwrap();
+        completions.into_iter().map(|item| item.label).collect()
+    };
+
+    // Parameters already documented in the block are left out
+    let code = "#' Title\n#' @param x Foo\n#' @param \nf <- function(x, y, ...) {}\n";
+    let point = Point { row: 2, column: 10 };
+    assert_eq!(labels(code, point), vec!["y", "..."]);
+
+    let code = "#' @param \n#' @param x,y Foo\nf = function(x, y, z) {}\n";
+    let point = Point { row: 0, column: 10 };
+    assert_eq!(labels(code, point), vec!["z"]);
+
+    // Not documenting a function
+    let code = "#' @param \nx <- 1\n";
+    let point = Point { row: 0, column: 10 };
+    assert!(labels(code, point).is_empty());
+
+    // Only in roxygen comments
+    let code = "# @param \nf <- function(x) {}\n";
+    let point = Point { row: 0, column: 9 };
+    assert!(labels(code, point).is_empty());
+}
+
+#[test]
+fn test_roxygen_link_completions() {
+    use tree_sitter::Point;
+
+    use crate::lsp::documents::Document;
+
+    let code = "#' See \\link{fo}\nfoo <- function(x) {}\nbar <- function() {}\n";
+    let point = Point { row: 0, column: 15 };
+    let document = Document::new(code, None);
+    let context = DocumentContext::new(&document, point, None);
+    let completions = completions_from_comment(&context).unwrap().unwrap();
+
+    let foo: Vec<&CompletionItem> = completions
+        .iter()
+        .filter(|item| item.label == "foo")
+        .collect();
+    assert_eq!(foo.len(), 1);
+    assert_eq!(foo[0].detail, Some(String::from("foo(x)")));
+    assert!(!completions.iter().any(|item| item.label == "bar"));
+}
+
 #[test]
 fn test_roxygen_completion_item() {
     let name = "aliases";
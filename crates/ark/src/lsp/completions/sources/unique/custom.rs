@@ -15,19 +15,17 @@ use libr::R_NilValue;
 use libr::VECSXP;
 use libr::VECTOR_ELT;
 use stdext::unwrap;
-use stdext::IntoResult;
 use tower_lsp::lsp_types::CompletionItem;
 
-use crate::lsp;
 use crate::lsp::completions::completion_item::completion_item;
 use crate::lsp::completions::completion_item::completion_item_from_dataset;
 use crate::lsp::completions::completion_item::completion_item_from_package;
+use crate::lsp::completions::sources::utils::call_active_parameter;
 use crate::lsp::completions::sources::utils::call_node_position_type;
 use crate::lsp::completions::sources::utils::set_sort_text_by_words_first;
 use crate::lsp::completions::sources::utils::CallNodePositionType;
 use crate::lsp::completions::types::CompletionData;
 use crate::lsp::document_context::DocumentContext;
-use crate::lsp::signature_help::r_signature_help;
 use crate::treesitter::node_in_string;
 use crate::treesitter::NodeTypeExt;
 
@@ -74,50 +72,10 @@ pub fn completions_from_custom_source_impl(
     let point = context.point;
     let node = context.node;
 
-    // Use the signature help tools to figure out the necessary pieces.
-    let signatures = r_signature_help(context)?;
-    let Some(signatures) = signatures else {
+    let Some((name, parameter)) = call_active_parameter(context)? else {
         return Ok(None);
     };
 
-    // Pull out the relevant signature information.
-    let signature = signatures.signatures.get(0).into_result()?;
-    let mut name = signature.label.clone();
-    let parameters = signature.parameters.as_ref().into_result()?;
-    let index = signature.active_parameter.into_result()? as usize;
-    // TODO: Currently, argument matching is not very accurate. This is just a
-    // workaround to supresses the error rather than showing a cryptic error
-    // message to users, but there should be some better option.
-    //
-    // cf. https://github.com/posit-dev/positron/issues/3467
-    if index >= parameters.len() {
-        lsp::log_error!("Index {index} is out of bounds of the parameters of `{name}`");
-        return Ok(None);
-    }
-    let parameter = parameters.get(index).into_result()?;
-
-    // Extract the parameter text.
-    let parameter = match parameter.label.clone() {
-        tower_lsp::lsp_types::ParameterLabel::LabelOffsets([start, end]) => {
-            let label = signature.label.as_str();
-            let substring = label.get((start as usize)..(end as usize));
-            substring.unwrap().to_string()
-        },
-        tower_lsp::lsp_types::ParameterLabel::Simple(string) => string,
-    };
-
-    // Parameter text typically contains the parameter name and its default value if there is one.
-    // Extract out just the parameter name for matching purposes.
-    let parameter = match parameter.find("=") {
-        Some(loc) => &parameter[..loc].trim(),
-        None => parameter.as_str(),
-    };
-
-    // Trim off the function parameters from the signature.
-    if let Some(index) = name.find('(') {
-        name = name[0..index].to_string();
-    }
-
     // Check and see if we're in the 'name' position,
     // versus the 'value' position, for a function invocation.
     //
@@ -481,4 +439,24 @@ mod tests {
             harp::parse_eval_base(format!("options({name} = NULL)").as_str()).unwrap();
         })
     }
+
+    #[test]
+    fn test_completion_custom_options_documented() {
+        r_task(|| {
+            // Documented in `?options` but unset by default
+            let name = "warning.expression";
+            let is_set =
+                harp::parse_eval_base(format!("'{name}' %in% names(options())").as_str()).unwrap();
+            assert!(!bool::try_from(is_set).unwrap());
+
+            let (text, point) = point_from_cursor("options(warning.exp@)");
+            let document = Document::new(text.as_str(), None);
+            let context = DocumentContext::new(&document, point, None);
+
+            let completions = completions_from_custom_source(&context).unwrap().unwrap();
+            assert!(completions
+                .iter()
+                .any(|completion| completion.label == name));
+        })
+    }
 }
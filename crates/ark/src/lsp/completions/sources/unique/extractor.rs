@@ -21,9 +21,11 @@ use crate::lsp::completions::completion_item::completion_item_from_data_variable
 use crate::lsp::completions::sources::utils::set_sort_text_by_first_appearance;
 use crate::lsp::document_context::DocumentContext;
 use crate::lsp::traits::rope::RopeExt;
+use crate::lsp::type_annotations::find_annotation;
 use crate::treesitter::ExtractOperatorType;
 use crate::treesitter::NodeType;
 use crate::treesitter::NodeTypeExt;
+use crate::user_config::user_config;
 
 pub fn completions_from_dollar(context: &DocumentContext) -> Result<Option<Vec<CompletionItem>>> {
     completions_from_extractor(
@@ -70,7 +72,12 @@ fn completions_from_extractor(
     // Extract out its name from the document
     let text = context.document.contents.node_slice(&node)?.to_string();
 
-    completions.append(&mut completions_from_extractor_object(text.as_str(), fun)?);
+    match completions_from_extractor_object(text.as_str(), fun)? {
+        Some(mut object_completions) => completions.append(&mut object_completions),
+        // The object can't be evaluated, fall back to the fields declared by
+        // its type annotation, if any
+        None => completions.append(&mut completions_from_annotation(context, text.as_str())),
+    }
 
     Ok(Some(completions))
 }
@@ -102,7 +109,12 @@ fn locate_extractor_node(node: Node, node_type: NodeType) -> Option<Node> {
     }
 }
 
-fn completions_from_extractor_object(text: &str, fun: &str) -> Result<Vec<CompletionItem>> {
+/// Completions for the names of the object `text`, or `None` if it can't be
+/// evaluated
+fn completions_from_extractor_object(
+    text: &str,
+    fun: &str,
+) -> Result<Option<Vec<CompletionItem>>> {
     log::info!("completions_from_extractor_object({text:?}, {fun:?})");
 
     const ENQUOTE: bool = false;
@@ -115,11 +127,11 @@ fn completions_from_extractor_object(text: &str, fun: &str) -> Result<Vec<Comple
 
         if !r_env_has(*env_utils, sym) {
             // We'd like to generate these completions, but not a new enough version of R
-            return Ok(completions);
+            return Ok(Some(completions));
         }
 
         let options = RParseEvalOptions {
-            forbid_function_calls: true,
+            forbid_function_calls: !user_config().evaluation.allow_function_calls,
             ..Default::default()
         };
 
@@ -129,10 +141,10 @@ fn completions_from_extractor_object(text: &str, fun: &str) -> Result<Vec<Comple
                 // LHS of the call was too complex to evaluate. This is fine, we know
                 // we are on the RHS of a `$` or `@`, so we return an empty "unique"
                 // completion list to stop the completions search.
-                Error::UnsafeEvaluationError(_) => return Ok(completions),
+                Error::UnsafeEvaluationError(_) => return Ok(None),
                 // LHS of the call evaluated to an error. Totally possible if the
                 // user is writing pseudocode. Don't want to propagate an error here.
-                _ => return Ok(completions),
+                _ => return Ok(None),
             },
         };
 
@@ -140,7 +152,7 @@ fn completions_from_extractor_object(text: &str, fun: &str) -> Result<Vec<Comple
 
         if r_typeof(*names) != STRSXP {
             // Could come from a malformed user supplied S3 method
-            return Ok(completions);
+            return Ok(Some(completions));
         }
 
         let names = names.to::<Vec<String>>()?;
@@ -157,7 +169,29 @@ fn completions_from_extractor_object(text: &str, fun: &str) -> Result<Vec<Comple
     // the same order as in the underlying object.
     set_sort_text_by_first_appearance(&mut completions);
 
-    Ok(completions)
+    Ok(Some(completions))
+}
+
+fn completions_from_annotation(context: &DocumentContext, text: &str) -> Vec<CompletionItem> {
+    const ENQUOTE: bool = false;
+
+    let Some(annotation) = find_annotation(context.document, text, context.point) else {
+        return vec![];
+    };
+
+    let mut completions = vec![];
+
+    for field in annotation.fields.iter() {
+        match completion_item_from_data_variable(field, text, ENQUOTE) {
+            Ok(item) => completions.push(item),
+            Err(err) => log::error!("{err:?}"),
+        }
+    }
+
+    // In the order of the annotation
+    set_sort_text_by_first_appearance(&mut completions);
+
+    completions
 }
 
 #[cfg(test)]
@@ -257,6 +291,33 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_dollar_completions_from_type_annotation() {
+        r_task(|| {
+            let (text, point) = point_from_cursor(
+                "# @type ark_test_annotated: data.frame{id, name}\nark_test_annotated$@",
+            );
+            let document = Document::new(text.as_str(), None);
+            let context = DocumentContext::new(&document, point, None);
+
+            // The object doesn't exist, the fields come from the annotation
+            let completions = completions_from_dollar(&context).unwrap().unwrap();
+            assert_eq!(completions.len(), 2);
+            assert_eq!(completions.get(0).unwrap().label, String::from("id"));
+            assert_eq!(completions.get(1).unwrap().label, String::from("name"));
+
+            // Annotations only apply to the code that follows them
+            let (text, point) = point_from_cursor(
+                "ark_test_annotated$@\n# @type ark_test_annotated: data.frame{id, name}",
+            );
+            let document = Document::new(text.as_str(), None);
+            let context = DocumentContext::new(&document, point, None);
+
+            let completions = completions_from_dollar(&context).unwrap().unwrap();
+            assert_eq!(completions.len(), 0);
+        })
+    }
+
     #[test]
     fn test_dollar_completions_before_the_dollar() {
         r_task(|| {
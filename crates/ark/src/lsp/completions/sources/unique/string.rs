@@ -8,6 +8,7 @@
 use anyhow::Result;
 use tower_lsp::lsp_types::CompletionItem;
 
+use super::argument_value::completions_from_string_argument_value;
 use super::file_path::completions_from_string_file_path;
 use crate::lsp::completions::sources::unique::subset::completions_from_string_subset;
 use crate::lsp::document_context::DocumentContext;
@@ -47,6 +48,13 @@ pub fn completions_from_string(context: &DocumentContext) -> Result<Option<Vec<C
         return Ok(Some(completions));
     }
 
+    // Check if the string is the value of an argument that takes one of a closed
+    // set of strings, like `cor(method = "<tab>")`
+    if let Some(mut candidates) = completions_from_string_argument_value(&node, context)? {
+        completions.append(&mut candidates);
+        return Ok(Some(completions));
+    }
+
     // If no special string cases are hit, we show file path completions
     completions.append(&mut completions_from_string_file_path(&node, context)?);
 
@@ -58,10 +66,11 @@ mod tests {
     use stdext::assert_match;
 
     use crate::fixtures::point_from_cursor;
-    use crate::lsp::completions::sources::completions_from_unique_sources;
+    use crate::lsp::completions::sources::completions_from_sources;
     use crate::lsp::completions::sources::unique::string::completions_from_string;
     use crate::lsp::document_context::DocumentContext;
     use crate::lsp::documents::Document;
+    use crate::lsp::state::WorldState;
     use crate::r_task;
     use crate::treesitter::node_find_string;
     use crate::treesitter::NodeTypeExt;
@@ -115,8 +124,8 @@ mod tests {
             assert_match!(res, Some(items) => { assert!(items.len() == 0) });
 
             // Check one level up too
-            let res = completions_from_unique_sources(&context).unwrap();
-            assert_match!(res, Some(items) => { assert!(items.len() == 0) });
+            let res = completions_from_sources(&context, &WorldState::default()).unwrap();
+            assert!(res.is_empty());
         })
     }
 }
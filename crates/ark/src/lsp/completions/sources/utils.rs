@@ -12,17 +12,22 @@ use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
 use harp::object::RObject;
 use regex::Regex;
+use stdext::IntoResult;
 use tower_lsp::lsp_types::CompletionItem;
+use tower_lsp::lsp_types::ParameterLabel;
 use tree_sitter::Node;
 use tree_sitter::Point;
 
+use crate::lsp;
 use crate::lsp::completions::completion_item::completion_item_from_data_variable;
 use crate::lsp::document_context::DocumentContext;
+use crate::lsp::signature_help::r_signature_help;
 use crate::lsp::traits::node::NodeExt;
 use crate::lsp::traits::point::PointExt;
 use crate::lsp::traits::rope::RopeExt;
 use crate::treesitter::NodeType;
 use crate::treesitter::NodeTypeExt;
+use crate::user_config::user_config;
 
 pub(super) fn set_sort_text_by_first_appearance(completions: &mut Vec<CompletionItem>) {
     let size = completions.len();
@@ -167,14 +172,80 @@ fn call_prev_leaf_position_type(node: &Node, allow_ambiguous: bool) -> CallNodeP
     }
 }
 
+/// The function of the call the cursor is in, and the name of the parameter
+/// the cursor is matched to, as determined by signature help
+pub(super) fn call_active_parameter(context: &DocumentContext) -> Result<Option<(String, String)>> {
+    let Some(signatures) = r_signature_help(context)? else {
+        return Ok(None);
+    };
+
+    // Pull out the relevant signature information.
+    let signature = signatures.signatures.get(0).into_result()?;
+    let parameters = signature.parameters.as_ref().into_result()?;
+    let index = signature.active_parameter.into_result()? as usize;
+
+    // Trim off the function parameters from the signature.
+    let name = match signature.label.find('(') {
+        Some(index) => &signature.label[0..index],
+        None => signature.label.as_str(),
+    };
+
+    // TODO: Currently, argument matching is not very accurate. This is just a
+    // workaround to supresses the error rather than showing a cryptic error
+    // message to users, but there should be some better option.
+    //
+    // cf. https://github.com/posit-dev/positron/issues/3467
+    if index >= parameters.len() {
+        lsp::log_error!("Index {index} is out of bounds of the parameters of `{name}`");
+        return Ok(None);
+    }
+    let parameter = parameters.get(index).into_result()?;
+
+    // Extract the parameter text.
+    let parameter = match parameter.label.clone() {
+        ParameterLabel::LabelOffsets([start, end]) => {
+            let label = signature.label.as_str();
+            let substring = label.get((start as usize)..(end as usize));
+            substring.unwrap().to_string()
+        },
+        ParameterLabel::Simple(string) => string,
+    };
+
+    // Parameter text typically contains the parameter name and its default value if there is one.
+    // Extract out just the parameter name for matching purposes.
+    let parameter = match parameter.find("=") {
+        Some(loc) => parameter[..loc].trim(),
+        None => parameter.as_str(),
+    };
+
+    Ok(Some((String::from(name), String::from(parameter))))
+}
+
 pub(super) fn completions_from_evaluated_object_names(
     name: &str,
     enquote: bool,
 ) -> Result<Option<Vec<CompletionItem>>> {
     log::info!("completions_from_evaluated_object_names({name:?})");
 
+    let Some(object) = eval_object(name) else {
+        return Ok(None);
+    };
+
+    let completions = if harp::utils::r_is_matrix(object.sexp) {
+        // Special case just for 2D arrays
+        completions_from_object_colnames(object, name, enquote)?
+    } else {
+        completions_from_object_names(object, name, enquote)?
+    };
+
+    Ok(Some(completions))
+}
+
+/// Evaluates the object whose names are completed, unless that requires
+/// calling functions and the user didn't allow it
+pub(super) fn eval_object(name: &str) -> Option<RObject> {
     let options = RParseEvalOptions {
-        forbid_function_calls: true,
+        forbid_function_calls: !user_config().evaluation.allow_function_calls,
         ..Default::default()
     };
 
@@ -186,29 +257,20 @@ pub(super) fn completions_from_evaluated_object_names(
     // expected to happen with complex inputs.
     // If we get a `TryCatchError`, that is typically an 'object not found' error resulting
     // from the user typing pseudocode. Log those at info level without a full backtrace.
-    let object = match object {
-        Ok(object) => object,
+    match object {
+        Ok(object) => Some(object),
         Err(err) => match err {
-            Error::UnsafeEvaluationError(_) => return Ok(None),
+            Error::UnsafeEvaluationError(_) => None,
             Error::TryCatchError { message, .. } => {
                 log::info!("Can't evaluate object: {message}");
-                return Ok(None);
+                None
             },
             _ => {
                 log::error!("Can't evaluate object: {err}");
-                return Ok(None);
+                None
             },
         },
-    };
-
-    let completions = if harp::utils::r_is_matrix(object.sexp) {
-        // Special case just for 2D arrays
-        completions_from_object_colnames(object, name, enquote)?
-    } else {
-        completions_from_object_names(object, name, enquote)?
-    };
-
-    Ok(Some(completions))
+    }
 }
 
 pub(super) fn completions_from_object_names(
@@ -1,14 +1,27 @@
+use std::collections::HashMap;
+
 use serde::Deserialize;
 use serde::Serialize;
 use struct_field_names_as_array::FieldNamesAsArray;
 
 use crate::lsp;
+use crate::lsp::diagnostics::DiagnosticRule;
 use crate::lsp::diagnostics::DiagnosticsConfig;
+use crate::lsp::diagnostics::RuleLevel;
+use crate::lsp::project_config::ProjectConfig;
 
 /// Configuration of the LSP
 #[derive(Clone, Debug)]
 pub(crate) struct LspConfig {
+    /// Diagnostics settings of the client merged with those of the project
     pub(crate) diagnostics: DiagnosticsConfig,
+
+    /// Diagnostics settings of the client
+    pub(crate) client_diagnostics: DiagnosticsConfig,
+
+    /// Settings of the `.ark.toml` file of the workspace, see
+    /// `project_config.rs`
+    pub(crate) project: ProjectConfig,
 }
 
 /// Configuration of a document.
@@ -32,9 +45,11 @@ pub struct IndentationConfig {
     pub tab_width: usize,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum IndentStyle {
+    #[serde(alias = "tab")]
     Tab,
+    #[serde(alias = "space")]
     Space,
 }
 
@@ -51,6 +66,9 @@ pub(crate) struct VscDocumentConfig {
 pub(crate) struct VscDiagnosticsConfig {
     // DEV NOTE: Update `section_from_key()` method after adding a field
     pub enable: bool,
+    pub generated_files: Option<Vec<String>>,
+    pub rules: Option<HashMap<String, String>>,
+    pub lintr: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -64,10 +82,37 @@ impl Default for LspConfig {
     fn default() -> Self {
         Self {
             diagnostics: Default::default(),
+            client_diagnostics: Default::default(),
+            project: Default::default(),
         }
     }
 }
 
+impl LspConfig {
+    /// Set the diagnostics settings of the client. Returns whether the
+    /// merged settings changed.
+    pub(crate) fn set_client_diagnostics(&mut self, config: DiagnosticsConfig) -> bool {
+        self.client_diagnostics = config;
+        self.merge_diagnostics()
+    }
+
+    /// Set the settings of the project. Returns whether the merged
+    /// diagnostics settings changed.
+    pub(crate) fn set_project(&mut self, project: ProjectConfig) -> bool {
+        self.project = project;
+        self.merge_diagnostics()
+    }
+
+    fn merge_diagnostics(&mut self) -> bool {
+        let mut config = self.client_diagnostics.clone();
+        self.project.apply_diagnostics(&mut config);
+
+        let changed = self.diagnostics != config;
+        self.diagnostics = config;
+        changed
+    }
+}
+
 impl Default for IndentationConfig {
     fn default() -> Self {
         Self {
@@ -121,6 +166,9 @@ impl VscDiagnosticsConfig {
     pub(crate) fn section_from_key(key: &str) -> &str {
         match key {
             "enable" => "positron.r.diagnostics.enable",
+            "generated_files" => "positron.r.diagnostics.generatedFiles",
+            "rules" => "positron.r.diagnostics.rules",
+            "lintr" => "positron.r.diagnostics.lintr",
             _ => "unknown", // To be caught via downstream errors
         }
     }
@@ -130,10 +178,38 @@ impl From<VscDiagnosticsConfig> for DiagnosticsConfig {
     fn from(value: VscDiagnosticsConfig) -> Self {
         Self {
             enable: value.enable,
+            generated_files: value.generated_files.unwrap_or_default(),
+            rules: parse_rules(value.rules.unwrap_or_default()),
+            lintr: value.lintr.unwrap_or(false),
         }
     }
 }
 
+/// Parse the levels of diagnostic rules by name, e.g. `unused-variable` to
+/// `off`. Unknown rules and levels are logged and skipped.
+pub(crate) fn parse_rules(
+    rules: impl IntoIterator<Item = (String, String)>,
+) -> HashMap<DiagnosticRule, RuleLevel> {
+    let mut out = HashMap::new();
+
+    for (rule, level) in rules {
+        let Some(rule) = DiagnosticRule::from_str(&rule) else {
+            lsp::log_warn!("Unknown diagnostic rule '{rule}'");
+            continue;
+        };
+        let Some(level) = RuleLevel::from_str(&level) else {
+            lsp::log_warn!(
+                "Unknown level '{level}' for diagnostic rule '{}'",
+                rule.as_str()
+            );
+            continue;
+        };
+        out.insert(rule, level);
+    }
+
+    out
+}
+
 pub(crate) fn indent_style_from_lsp(insert_spaces: bool) -> IndentStyle {
     if insert_spaces {
         IndentStyle::Space
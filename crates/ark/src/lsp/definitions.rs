@@ -6,16 +6,24 @@
 //
 
 use anyhow::Result;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::object::RObject;
+use harp::utils::r_is_null;
 use tower_lsp::lsp_types::GotoDefinitionParams;
 use tower_lsp::lsp_types::GotoDefinitionResponse;
 use tower_lsp::lsp_types::LocationLink;
+use tower_lsp::lsp_types::Position;
 use tower_lsp::lsp_types::Range;
 use tower_lsp::lsp_types::Url;
+use tree_sitter::Node;
 
 use crate::lsp::documents::Document;
 use crate::lsp::encoding::convert_point_to_position;
 use crate::lsp::encoding::convert_position_to_point;
 use crate::lsp::indexer;
+use crate::lsp::scheduler::r_request;
+use crate::lsp::scheduler::Priority;
 use crate::lsp::traits::node::NodeExt;
 use crate::lsp::traits::rope::RopeExt;
 use crate::treesitter::NodeTypeExt;
@@ -42,27 +50,22 @@ pub unsafe fn goto_definition<'a>(
     let end = convert_point_to_position(contents, node.end_position());
     let range = Range { start, end };
 
-    // search for a reference in the document index
     if node.is_identifier() {
         let symbol = document.contents.node_slice(&node)?.to_string();
-        if let Some((path, entry)) = indexer::find(symbol.as_str()) {
-            let link = LocationLink {
-                origin_selection_range: None,
-                target_uri: Url::from_file_path(path).unwrap(),
-                target_range: entry.range,
-                target_selection_range: entry.range,
-            };
-            let response = GotoDefinitionResponse::Link(vec![link]);
-            return Ok(Some(response));
+
+        // `pkg::fun` and `pkg:::fun` are looked up in the namespace of `pkg`
+        if let Some(package) = namespace_of(&node, document)? {
+            let link = r_request("goto_definition", Priority::Normal, || {
+                session_definition(&symbol, Some(&package))
+            })?;
+            return Ok(link.map(|link| GotoDefinitionResponse::Link(vec![link])));
+        }
+
+        if let Some(link) = definition(&symbol, document, &params) {
+            return Ok(Some(GotoDefinitionResponse::Link(vec![link])));
         }
     }
 
-    // TODO: We should see if we can find the referenced item in:
-    //
-    // 1. The document's current AST,
-    // 2. The public functions from other documents in the project,
-    // 3. A definition in the R session (which we could open in a virtual document)
-    //
     // If we can't find a definition, then we can return the referenced item itself,
     // which will tell Positron to instead try to look for references for that symbol.
     let link = LocationLink {
@@ -75,3 +78,182 @@ pub unsafe fn goto_definition<'a>(
     let response = GotoDefinitionResponse::Link(vec![link]);
     Ok(Some(response))
 }
+
+/// Find the definition of `symbol`, looking in order at:
+///
+/// 1. The top-level functions of the current document, which may not be
+///    indexed (e.g. untitled buffers).
+/// 2. The functions of the other documents of the workspace.
+/// 3. The functions of the R session that have source references, either
+///    because they were sourced from a file or because their namespace was
+///    populated with a virtual document by `ns_populate_srcref()`.
+fn definition(
+    symbol: &str,
+    document: &Document,
+    params: &GotoDefinitionParams,
+) -> Option<LocationLink> {
+    if let Some(entry) = indexer::find_in_document(document, symbol) {
+        return Some(LocationLink {
+            origin_selection_range: None,
+            target_uri: params
+                .text_document_position_params
+                .text_document
+                .uri
+                .clone(),
+            target_range: entry.range,
+            target_selection_range: entry.range,
+        });
+    }
+
+    if let Some((path, entry)) = indexer::find_function(symbol) {
+        if let Ok(uri) = Url::from_file_path(path) {
+            return Some(LocationLink {
+                origin_selection_range: None,
+                target_uri: uri,
+                target_range: entry.range,
+                target_selection_range: entry.range,
+            });
+        }
+    }
+
+    r_request("goto_definition", Priority::Normal, || {
+        session_definition(symbol, None)
+    })
+    .unwrap_or_default()
+}
+
+/// The package of `pkg::fun` when `node` is `fun`
+fn namespace_of(node: &Node, document: &Document) -> Result<Option<String>> {
+    let Some(parent) = node.parent() else {
+        return Ok(None);
+    };
+    if !parent.is_namespace_operator() || parent.child_by_field_name("rhs") != Some(*node) {
+        return Ok(None);
+    }
+    let Some(lhs) = parent.child_by_field_name("lhs") else {
+        return Ok(None);
+    };
+    Ok(Some(document.contents.node_slice(&lhs)?.to_string()))
+}
+
+fn session_definition(symbol: &str, package: Option<&str>) -> Option<LocationLink> {
+    let mut call = RFunction::from(".ps.srcref.locate");
+    call.add(symbol);
+    if let Some(package) = package {
+        call.param("package", package);
+    }
+
+    let location = match call.call() {
+        Ok(location) => location,
+        Err(err) => {
+            log::warn!("Can't locate the definition of `{symbol}` in the session: {err:?}");
+            return None;
+        },
+    };
+    if r_is_null(location.sexp) {
+        return None;
+    }
+
+    let file: String = RObject::view(harp::list_get(location.sexp, 0))
+        .try_into()
+        .ok()?;
+    let start_line: i32 = RObject::view(harp::list_get(location.sexp, 1))
+        .try_into()
+        .ok()?;
+    let end_line: i32 = RObject::view(harp::list_get(location.sexp, 2))
+        .try_into()
+        .ok()?;
+
+    let uri = if file.starts_with("ark:") {
+        Url::parse(&file).ok()?
+    } else {
+        Url::from_file_path(&file).ok()?
+    };
+
+    // Source references are 1-based. Columns are byte offsets which we can't
+    // convert without the contents of the file, so select whole lines.
+    let start = Position::new((start_line - 1).max(0) as u32, 0);
+    let end = Position::new(end_line.max(0) as u32, 0);
+
+    Some(LocationLink {
+        origin_selection_range: None,
+        target_uri: uri,
+        target_range: Range { start, end },
+        target_selection_range: Range { start, end: start },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::GotoDefinitionParams;
+    use tower_lsp::lsp_types::GotoDefinitionResponse;
+    use tower_lsp::lsp_types::LocationLink;
+    use tower_lsp::lsp_types::Position;
+    use tower_lsp::lsp_types::TextDocumentIdentifier;
+    use tower_lsp::lsp_types::TextDocumentPositionParams;
+    use tower_lsp::lsp_types::Url;
+
+    use crate::lsp::definitions::goto_definition;
+    use crate::lsp::documents::Document;
+    use crate::r_task;
+
+    fn definition_at(code: &str, position: Position) -> Option<LocationLink> {
+        let document = Document::new(code, None);
+        let params = GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Url::parse("untitled:Untitled-1").unwrap(),
+                },
+                position,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+
+        match unsafe { goto_definition(&document, params) }.unwrap()? {
+            GotoDefinitionResponse::Link(mut links) => links.pop(),
+            _ => panic!("Expected a link"),
+        }
+    }
+
+    #[test]
+    fn test_goto_definition_in_document() {
+        r_task(|| {
+            let code = "foo <- function() 1\nbar <- 2\nfoo <- function() 3\nfoo()";
+            let link = definition_at(code, Position::new(3, 1)).unwrap();
+
+            // The last definition wins
+            assert_eq!(link.target_range.start, Position::new(2, 0));
+            assert_eq!(link.target_range.end, Position::new(2, 3));
+            assert_eq!(link.origin_selection_range, None);
+        })
+    }
+
+    #[test]
+    fn test_goto_definition_in_session() {
+        r_task(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("fn.R");
+            std::fs::write(&path, "\n\nark_test_definition <- function() {\n  1\n}\n").unwrap();
+
+            let code = format!(
+                "source('{}', keep.source = TRUE)",
+                path.to_str().unwrap().replace('\\', "/")
+            );
+            harp::parse_eval_global(&code).unwrap();
+
+            let link = definition_at("ark_test_definition()", Position::new(0, 1)).unwrap();
+            assert_eq!(
+                link.target_uri.to_file_path().unwrap(),
+                std::fs::canonicalize(&path).unwrap()
+            );
+            assert_eq!(link.target_range.start, Position::new(2, 0));
+            assert_eq!(link.target_range.end, Position::new(5, 0));
+
+            harp::parse_eval_global("rm(ark_test_definition)").unwrap();
+
+            // Functions without source references have no definition
+            assert_eq!(definition_at("base::paste", Position::new(0, 7)), None);
+        })
+    }
+}
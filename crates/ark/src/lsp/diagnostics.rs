@@ -16,25 +16,70 @@ use ropey::Rope;
 use stdext::*;
 use tower_lsp::lsp_types::Diagnostic;
 use tower_lsp::lsp_types::DiagnosticSeverity;
+use tower_lsp::lsp_types::NumberOrString;
 use tree_sitter::Node;
 use tree_sitter::Range;
 
 use crate::lsp::declarations::top_level_declare;
+use crate::lsp::diagnostics_flow::flow_diagnostics;
 use crate::lsp::diagnostics_syntax::syntax_diagnostics;
 use crate::lsp::documents::Document;
 use crate::lsp::encoding::convert_tree_sitter_range_to_lsp_range;
 use crate::lsp::indexer;
 use crate::lsp::state::WorldState;
 use crate::lsp::traits::rope::RopeExt;
+use crate::lsp::type_annotations;
+use crate::lsp::type_annotations::TypeAnnotation;
+use crate::treesitter::node_depth_exceeds;
 use crate::treesitter::node_has_error_or_missing;
 use crate::treesitter::BinaryOperatorType;
 use crate::treesitter::NodeType;
 use crate::treesitter::NodeTypeExt;
 use crate::treesitter::UnaryOperatorType;
+use crate::treesitter::MAX_TREE_DEPTH;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct DiagnosticsConfig {
     pub enable: bool,
+
+    /// Globs of generated files, for which diagnostics and formatting edits are
+    /// suppressed. See `is_generated()`.
+    pub generated_files: Vec<String>,
+
+    /// Rules turned off or reported with a different severity than the
+    /// default
+    pub rules: HashMap<DiagnosticRule, RuleLevel>,
+
+    /// Whether lintr is run on saved documents, see `lintr.rs`
+    pub lintr: bool,
+}
+
+/// The checks performed by the diagnostics engine. The rule of a diagnostic is
+/// reported as its code, e.g. `unknown-symbol`, and is the key under which
+/// users configure it with the `positron.r.diagnostics.rules` setting.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DiagnosticRule {
+    Syntax,
+    UnknownSymbol,
+    UnknownPackage,
+    UnknownOption,
+    CallArity,
+    ArgumentType,
+    AssignmentInCondition,
+    NaComparison,
+    UnusedArgument,
+    UnusedVariable,
+    UnreachableCode,
+}
+
+/// Severity of the diagnostics of a rule, or `Off` to suppress them
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RuleLevel {
+    Off,
+    Hint,
+    Information,
+    Warning,
+    Error,
 }
 
 #[derive(Clone)]
@@ -52,9 +97,23 @@ pub struct DiagnosticContext<'a> {
     /// The symbols defined in the workspace.
     pub workspace_symbols: HashSet<String>,
 
+    /// The parameters of the functions defined in the workspace and at the
+    /// top level of the document, used to check the arguments of calls.
+    pub function_parameters: HashMap<String, Vec<String>>,
+
+    /// The type annotations of the document, see `type_annotations.rs`.
+    pub annotations: Vec<TypeAnnotation>,
+
+    /// The annotated parameters of the functions defined at the top level of
+    /// the document, used to check the types of the arguments of calls.
+    pub parameter_types: HashMap<String, HashMap<String, TypeAnnotation>>,
+
     // The set of packages that are currently installed.
     pub installed_packages: HashSet<String>,
 
+    // The set of options known to the session.
+    pub known_options: HashSet<String>,
+
     // Whether or not we're inside of a formula.
     pub in_formula: bool,
 
@@ -64,7 +123,83 @@ pub struct DiagnosticContext<'a> {
 
 impl Default for DiagnosticsConfig {
     fn default() -> Self {
-        Self { enable: true }
+        Self {
+            enable: true,
+            generated_files: Vec::new(),
+            rules: HashMap::new(),
+            lintr: false,
+        }
+    }
+}
+
+impl DiagnosticRule {
+    const ALL: [DiagnosticRule; 11] = [
+        Self::Syntax,
+        Self::UnknownSymbol,
+        Self::UnknownPackage,
+        Self::UnknownOption,
+        Self::CallArity,
+        Self::ArgumentType,
+        Self::AssignmentInCondition,
+        Self::NaComparison,
+        Self::UnusedArgument,
+        Self::UnusedVariable,
+        Self::UnreachableCode,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Syntax => "syntax",
+            Self::UnknownSymbol => "unknown-symbol",
+            Self::UnknownPackage => "unknown-package",
+            Self::UnknownOption => "unknown-option",
+            Self::CallArity => "call-arity",
+            Self::ArgumentType => "argument-type",
+            Self::AssignmentInCondition => "assignment-in-condition",
+            Self::NaComparison => "na-comparison",
+            Self::UnusedArgument => "unused-argument",
+            Self::UnusedVariable => "unused-variable",
+            Self::UnreachableCode => "unreachable-code",
+        }
+    }
+
+    pub fn from_str(rule: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.as_str() == rule)
+    }
+
+    /// Record the rule as the code of `diagnostic`
+    pub(crate) fn tag(self, diagnostic: &mut Diagnostic) {
+        diagnostic.code = Some(NumberOrString::String(String::from(self.as_str())));
+    }
+
+    fn of(diagnostic: &Diagnostic) -> Option<Self> {
+        match &diagnostic.code {
+            Some(NumberOrString::String(code)) => Self::from_str(code),
+            _ => None,
+        }
+    }
+}
+
+impl RuleLevel {
+    pub fn from_str(level: &str) -> Option<Self> {
+        match level {
+            "off" => Some(Self::Off),
+            "hint" => Some(Self::Hint),
+            "information" => Some(Self::Information),
+            "warning" => Some(Self::Warning),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Self::Off => None,
+            Self::Hint => Some(DiagnosticSeverity::HINT),
+            Self::Information => Some(DiagnosticSeverity::INFORMATION),
+            Self::Warning => Some(DiagnosticSeverity::WARNING),
+            Self::Error => Some(DiagnosticSeverity::ERROR),
+        }
     }
 }
 
@@ -75,7 +210,11 @@ impl<'a> DiagnosticContext<'a> {
             document_symbols: Vec::new(),
             session_symbols: HashSet::new(),
             workspace_symbols: HashSet::new(),
+            function_parameters: HashMap::new(),
+            annotations: Vec::new(),
+            parameter_types: HashMap::new(),
             installed_packages: HashSet::new(),
+            known_options: HashSet::new(),
             in_formula: false,
             in_call: false,
         }
@@ -125,12 +264,35 @@ pub(crate) fn generate_diagnostics(doc: Document, state: WorldState) -> Vec<Diag
 
     // Add the current workspace symbols.
     indexer::map(|_path, _symbol, entry| match &entry.data {
-        indexer::IndexEntryData::Function { name, arguments: _ } => {
+        indexer::IndexEntryData::Function { name, arguments } => {
+            context.workspace_symbols.insert(name.to_string());
+            context
+                .function_parameters
+                .insert(name.to_string(), arguments.clone());
+        },
+        indexer::IndexEntryData::Generic { name } => {
             context.workspace_symbols.insert(name.to_string());
         },
+        indexer::IndexEntryData::Class {
+            generator: Some(generator),
+            ..
+        } => {
+            context.workspace_symbols.insert(generator.to_string());
+        },
         _ => {},
     });
 
+    // The definitions of the document take precedence, the index may be stale
+    for entry in indexer::index_entries(&doc) {
+        if let indexer::IndexEntryData::Function { name, arguments } = entry.data {
+            context.function_parameters.insert(name, arguments);
+        }
+    }
+
+    let root = doc.ast.root_node();
+    context.annotations = type_annotations::annotations(root, &doc.contents);
+    context.parameter_types = type_annotations::parameter_annotations(root, &doc.contents);
+
     for scope in state.console_scopes.iter() {
         for name in scope.iter() {
             if is_symbol_valid(name.as_str()) {
@@ -146,12 +308,19 @@ pub(crate) fn generate_diagnostics(doc: Document, state: WorldState) -> Vec<Diag
         context.installed_packages.insert(pkg.clone());
     }
 
-    // Start iterating through the nodes.
-    let root = doc.ast.root_node();
+    for option in state.known_options.iter() {
+        context.known_options.insert(option.clone());
+    }
 
+    // Start iterating through the nodes.
     // Collect syntax related diagnostics for `ERROR` and `MISSING` nodes
     match syntax_diagnostics(root, &context) {
-        Ok(mut syntax_diagnostics) => diagnostics.append(&mut syntax_diagnostics),
+        Ok(mut syntax_diagnostics) => {
+            for diagnostic in syntax_diagnostics.iter_mut() {
+                DiagnosticRule::Syntax.tag(diagnostic);
+            }
+            diagnostics.append(&mut syntax_diagnostics)
+        },
         Err(err) => log::error!("Error while generating syntax diagnostics: {err:?}"),
     }
 
@@ -161,9 +330,39 @@ pub(crate) fn generate_diagnostics(doc: Document, state: WorldState) -> Vec<Diag
         Err(err) => log::error!("Error while generating semantic diagnostics: {err:?}"),
     }
 
+    // Collect data flow diagnostics, e.g. unused variables
+    match flow_diagnostics(root, &context) {
+        Ok(mut flow_diagnostics) => diagnostics.append(&mut flow_diagnostics),
+        Err(err) => log::error!("Error while generating data flow diagnostics: {err:?}"),
+    }
+
+    apply_rules(&mut diagnostics, &state.config.diagnostics.rules);
+
     diagnostics
 }
 
+/// Drop the diagnostics of the rules turned off by the user and adjust the
+/// severity of the others
+fn apply_rules(diagnostics: &mut Vec<Diagnostic>, rules: &HashMap<DiagnosticRule, RuleLevel>) {
+    if rules.is_empty() {
+        return;
+    }
+
+    diagnostics.retain_mut(|diagnostic| {
+        let Some(level) = DiagnosticRule::of(diagnostic).and_then(|rule| rules.get(&rule)) else {
+            return true;
+        };
+
+        match level.severity() {
+            Some(severity) => {
+                diagnostic.severity = Some(severity);
+                true
+            },
+            None => false,
+        }
+    });
+}
+
 fn semantic_diagnostics(
     root: Node,
     context: &mut DiagnosticContext,
@@ -179,10 +378,13 @@ fn semantic_diagnostics(
     //
     // This scheme allows us to emit some semantic diagnostics even when
     // we see syntax errors in other parts of the file.
+    //
+    // Expressions that are too deeply nested to be recursed into safely are
+    // skipped too.
     let mut cursor = root.walk();
 
     for child in root.children(&mut cursor) {
-        if node_has_error_or_missing(&child) {
+        if node_has_error_or_missing(&child) || node_depth_exceeds(&child, MAX_TREE_DEPTH) {
             continue;
         }
 
@@ -619,7 +821,8 @@ fn recurse_namespace(
         let range = lhs.range();
         let range = convert_tree_sitter_range_to_lsp_range(context.contents, range);
         let message = format!("Package '{}' is not installed.", package);
-        let diagnostic = Diagnostic::new_simple(range, message);
+        let mut diagnostic = Diagnostic::new_simple(range, message);
+        DiagnosticRule::UnknownPackage.tag(&mut diagnostic);
         diagnostics.push(diagnostic);
     }
 
@@ -697,7 +900,8 @@ fn recurse_parenthesized_expression(
         let range = node.range();
         let range = convert_tree_sitter_range_to_lsp_range(context.contents, range);
         let message = format!("Expected at most 1 statement within parentheses, found {n}.");
-        let diagnostic = Diagnostic::new_simple(range, message);
+        let mut diagnostic = Diagnostic::new_simple(range, message);
+        DiagnosticRule::Syntax.tag(&mut diagnostic);
         diagnostics.push(diagnostic);
     }
 
@@ -770,7 +974,8 @@ fn check_call_like_next_sibling(
 
     let range = convert_tree_sitter_range_to_lsp_range(context.contents, range);
     let message = "Expected ',' between expressions.";
-    let diagnostic = Diagnostic::new_simple(range, message.into());
+    let mut diagnostic = Diagnostic::new_simple(range, message.into());
+    DiagnosticRule::Syntax.tag(&mut diagnostic);
     diagnostics.push(diagnostic);
 
     ().ok()
@@ -819,6 +1024,9 @@ fn recurse_call(
     let callee = node.child(0).into_result()?;
     recurse(callee, context, diagnostics)?;
 
+    check_call_arity(node, context, diagnostics)?;
+    check_argument_types(node, context, diagnostics)?;
+
     // dispatch based on the function
     //
     // TODO: Handle certain 'scope-generating' function calls, e.g.
@@ -827,6 +1035,15 @@ fn recurse_call(
     let fun = fun.as_str();
 
     match fun {
+        "options" | "base::options" => {
+            check_option_names(node, false, context, diagnostics)?;
+            recurse_call_arguments_default(node, context, diagnostics)?
+        },
+        "getOption" | "base::getOption" => {
+            check_option_names(node, true, context, diagnostics)?;
+            recurse_call_arguments_default(node, context, diagnostics)?
+        },
+
         // default case: recurse into each argument
         _ => recurse_call_arguments_default(node, context, diagnostics)?,
     };
@@ -834,6 +1051,327 @@ fn recurse_call(
     ().ok()
 }
 
+/// Flag arguments that the called function can't accept, e.g. `f(1, 2)` or
+/// `f(y = 1)` with `f <- function(x) x`. Only functions of the workspace or
+/// of the document are checked, and functions taking `...` accept anything.
+fn check_call_arity(
+    node: Node,
+    context: &mut DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let callee = node.child_by_field_name("function").into_result()?;
+    if !callee.is_identifier() {
+        return ().ok();
+    }
+    let fun = context.contents.node_slice(&callee)?.to_string();
+
+    if is_shadowed(&fun, context) {
+        return ().ok();
+    }
+
+    let Some(parameters) = context.function_parameters.get(&fun) else {
+        return ().ok();
+    };
+    if parameters.iter().any(|parameter| parameter == "...") {
+        return ().ok();
+    }
+
+    let Some(arguments) = node.child_by_field_name("arguments") else {
+        return ().ok();
+    };
+
+    let mut named = vec![];
+    let mut positional = vec![];
+
+    let mut cursor = arguments.walk();
+    for argument in arguments.children_by_field_name("argument", &mut cursor) {
+        match argument.child_by_field_name("name") {
+            Some(name) => {
+                let text = context.contents.node_slice(&name)?.to_string();
+                let text = text.trim_matches(|c| matches!(c, '"' | '\'' | '`'));
+                named.push((name, text.to_string()));
+            },
+            None if argument.child_by_field_name("value").is_some() => positional.push(argument),
+            None => {},
+        }
+    }
+
+    // Match names exactly first, then by unique prefix, as R does
+    let mut unmatched: Vec<&str> = parameters.iter().map(String::as_str).collect();
+    let mut unknown = vec![];
+
+    named.retain(
+        |(_, name)| match unmatched.iter().position(|x| *x == name.as_str()) {
+            Some(i) => {
+                unmatched.remove(i);
+                false
+            },
+            None => true,
+        },
+    );
+
+    for (name_node, name) in named {
+        let candidates: Vec<usize> = unmatched
+            .iter()
+            .enumerate()
+            .filter(|(_, parameter)| parameter.starts_with(name.as_str()))
+            .map(|(i, _)| i)
+            .collect();
+
+        match candidates.as_slice() {
+            [i] => {
+                unmatched.remove(*i);
+            },
+            [] => unknown.push((name_node, name)),
+            // Ambiguous partial match, R complains about that differently
+            _ => {},
+        }
+    }
+
+    for (name_node, name) in unknown {
+        let range = convert_tree_sitter_range_to_lsp_range(context.contents, name_node.range());
+        let message = format!("Unused argument `{name}` in call to `{fun}()`.");
+        let mut diagnostic = Diagnostic::new_simple(range, message);
+        diagnostic.severity = Some(DiagnosticSeverity::WARNING);
+        DiagnosticRule::CallArity.tag(&mut diagnostic);
+        diagnostics.push(diagnostic);
+    }
+
+    if positional.len() > unmatched.len() {
+        let first = positional[unmatched.len()];
+        let last = positional[positional.len() - 1];
+
+        let range = Range {
+            start_byte: first.start_byte(),
+            start_point: first.start_position(),
+            end_byte: last.end_byte(),
+            end_point: last.end_position(),
+        };
+        let range = convert_tree_sitter_range_to_lsp_range(context.contents, range);
+
+        let n = parameters.len();
+        let message = format!(
+            "Too many arguments in call to `{fun}()`, it takes {n} {}.",
+            if n == 1 { "argument" } else { "arguments" }
+        );
+        let mut diagnostic = Diagnostic::new_simple(range, message);
+        diagnostic.severity = Some(DiagnosticSeverity::WARNING);
+        DiagnosticRule::CallArity.tag(&mut diagnostic);
+        diagnostics.push(diagnostic);
+    }
+
+    ().ok()
+}
+
+/// Flag arguments whose type contradicts the type annotation of the parameter
+/// they are passed to, e.g. `f("a")` with `# @type n: numeric` above
+/// `f <- function(n) n`. The type of an argument is known when it is a
+/// literal or an annotated object. Only atomic types are compared to the
+/// annotation of the parameter, other classes may be related by inheritance.
+fn check_argument_types(
+    node: Node,
+    context: &mut DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let callee = node.child_by_field_name("function").into_result()?;
+    if !callee.is_identifier() {
+        return ().ok();
+    }
+    let fun = context.contents.node_slice(&callee)?.to_string();
+
+    if is_shadowed(&fun, context) {
+        return ().ok();
+    }
+
+    let Some(types) = context.parameter_types.get(&fun) else {
+        return ().ok();
+    };
+    let Some(parameters) = context.function_parameters.get(&fun) else {
+        return ().ok();
+    };
+    let Some(arguments) = node.child_by_field_name("arguments") else {
+        return ().ok();
+    };
+
+    let mut matched = vec![];
+    let mut positional = vec![];
+    let mut unmatched: Vec<&str> = parameters.iter().map(String::as_str).collect();
+
+    // Match names exactly, then positions up to `...`
+    let mut cursor = arguments.walk();
+    for argument in arguments.children_by_field_name("argument", &mut cursor) {
+        let Some(value) = argument.child_by_field_name("value") else {
+            continue;
+        };
+        match argument.child_by_field_name("name") {
+            Some(name) => {
+                let name = context.contents.node_slice(&name)?.to_string();
+                let name = name.trim_matches(|c| matches!(c, '"' | '\'' | '`'));
+                if let Some(i) = unmatched.iter().position(|x| *x == name) {
+                    matched.push((value, unmatched.remove(i)));
+                }
+            },
+            None => positional.push(value),
+        }
+    }
+
+    let positions = unmatched.iter().take_while(|parameter| **parameter != "...");
+    matched.extend(positional.into_iter().zip(positions.copied()));
+
+    for (value, parameter) in matched {
+        let Some(expected) = types.get(parameter) else {
+            continue;
+        };
+        let Some(actual) = argument_type(value, context)? else {
+            continue;
+        };
+        if type_annotations::is_compatible(&expected.class, &actual) {
+            continue;
+        }
+
+        let range = convert_tree_sitter_range_to_lsp_range(context.contents, value.range());
+        let message = format!(
+            "Argument `{parameter}` of `{fun}()` is annotated as `{}`, not `{actual}`.",
+            expected.class
+        );
+        let mut diagnostic = Diagnostic::new_simple(range, message);
+        diagnostic.severity = Some(DiagnosticSeverity::WARNING);
+        DiagnosticRule::ArgumentType.tag(&mut diagnostic);
+        diagnostics.push(diagnostic);
+    }
+
+    ().ok()
+}
+
+/// The atomic type of an argument value, if known statically
+fn argument_type(value: Node, context: &DiagnosticContext) -> Result<Option<String>> {
+    let class = match value.node_type() {
+        NodeType::String => "character",
+        NodeType::Integer => "integer",
+        NodeType::Float => "numeric",
+        NodeType::Complex => "complex",
+        NodeType::True | NodeType::False => "logical",
+        NodeType::Null => "NULL",
+        NodeType::Identifier => {
+            let name = context.contents.node_slice(&value)?.to_string();
+            let annotation = type_annotations::annotation_at(
+                &context.annotations,
+                &name,
+                value.start_position(),
+            );
+            return Ok(annotation
+                .filter(|annotation| annotation.is_atomic())
+                .map(|annotation| annotation.class.clone()));
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(String::from(class)))
+}
+
+/// Whether local variables and parameters shadow the function `fun` of the
+/// workspace
+fn is_shadowed(fun: &str, context: &DiagnosticContext) -> bool {
+    context
+        .document_symbols
+        .iter()
+        .skip(1)
+        .any(|symbols| symbols.contains_key(fun))
+}
+
+/// Flag option names that are probably typos of a known option, e.g.
+/// `options(digts = 3)` or `getOption("digts")`. Unknown names that are not
+/// close to a known option are assumed to be new options and are not flagged.
+///
+/// `getter` is `true` for `getOption()`, where only the first argument is an
+/// option name.
+fn check_option_names(
+    node: Node,
+    getter: bool,
+    context: &mut DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    // Without a session we don't know which options exist
+    if context.known_options.is_empty() {
+        return ().ok();
+    }
+
+    let Some(arguments) = node.child_by_field_name("arguments") else {
+        return ().ok();
+    };
+
+    let mut cursor = arguments.walk();
+    let arguments = arguments.children_by_field_name("argument", &mut cursor);
+    let arguments = arguments.take(if getter { 1 } else { usize::MAX });
+
+    for argument in arguments {
+        // Option names are passed as argument names to `options()` and as
+        // strings to `getOption()` or to `options()` when querying values
+        let name = match argument.child_by_field_name("name") {
+            Some(name) if !getter => name,
+            Some(name) if context.contents.node_slice(&name)? != "x" => continue,
+            _ => match argument.child_by_field_name("value") {
+                Some(value) if value.is_string() => value,
+                _ => continue,
+            },
+        };
+
+        let option = context.contents.node_slice(&name)?.to_string();
+        let option = option.trim_matches(|c| matches!(c, '"' | '\'' | '`'));
+
+        if option.is_empty() || context.known_options.contains(option) {
+            continue;
+        }
+
+        let Some(suggestion) = closest_option(option, &context.known_options) else {
+            continue;
+        };
+
+        let range = convert_tree_sitter_range_to_lsp_range(context.contents, name.range());
+        let message = format!("Unknown option '{option}'. Did you mean '{suggestion}'?");
+        let mut diagnostic = Diagnostic::new_simple(range, message);
+        diagnostic.severity = Some(DiagnosticSeverity::WARNING);
+        DiagnosticRule::UnknownOption.tag(&mut diagnostic);
+        diagnostics.push(diagnostic);
+    }
+
+    ().ok()
+}
+
+/// Known option closest to `option`, if it is within a couple of edits.
+/// Shorter names tolerate fewer edits to avoid spurious suggestions.
+fn closest_option<'a>(option: &str, known_options: &'a HashSet<String>) -> Option<&'a str> {
+    let max_distance = (option.chars().count() / 4).clamp(1, 2);
+
+    known_options
+        .iter()
+        .map(|known| (edit_distance(option, known), known))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, known)| known.as_str())
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(x: &str, y: &str) -> usize {
+    let y: Vec<char> = y.chars().collect();
+    let mut previous: Vec<usize> = (0..=y.len()).collect();
+
+    for (i, x_char) in x.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, y_char) in y.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x_char != *y_char);
+            let insertion = current[j] + 1;
+            let deletion = previous[j + 1] + 1;
+            current.push(substitution.min(insertion).min(deletion));
+        }
+
+        previous = current;
+    }
+
+    previous[y.len()]
+}
+
 fn recurse_subset(
     node: Node,
     context: &mut DiagnosticContext,
@@ -935,6 +1473,7 @@ fn check_invalid_na_comparison(
             let range = convert_tree_sitter_range_to_lsp_range(context.contents, range);
             let mut diagnostic = Diagnostic::new_simple(range, message.into());
             diagnostic.severity = Some(DiagnosticSeverity::INFORMATION);
+            DiagnosticRule::NaComparison.tag(&mut diagnostic);
             diagnostics.push(diagnostic);
         }
     }
@@ -968,7 +1507,8 @@ fn check_unexpected_assignment_in_if_conditional(
     let range = condition.range();
     let range = convert_tree_sitter_range_to_lsp_range(context.contents, range);
     let message = "Unexpected '='; use '==' to compare values for equality.";
-    let diagnostic = Diagnostic::new_simple(range, message.into());
+    let mut diagnostic = Diagnostic::new_simple(range, message.into());
+    DiagnosticRule::AssignmentInCondition.tag(&mut diagnostic);
     diagnostics.push(diagnostic);
 
     true.ok()
@@ -1019,6 +1559,7 @@ fn check_symbol_in_scope(
     let message = format!("No symbol named '{}' in scope.", identifier);
     let mut diagnostic = Diagnostic::new_simple(range, message);
     diagnostic.severity = Some(DiagnosticSeverity::WARNING);
+    DiagnosticRule::UnknownSymbol.tag(&mut diagnostic);
     diagnostics.push(diagnostic);
 
     true.ok()
@@ -1028,10 +1569,15 @@ fn check_symbol_in_scope(
 mod tests {
     use harp::eval::RParseEvalOptions;
     use once_cell::sync::Lazy;
+    use tower_lsp::lsp_types::DiagnosticSeverity;
+    use tower_lsp::lsp_types::NumberOrString;
     use tower_lsp::lsp_types::Position;
 
     use crate::interface::console_inputs;
+    use crate::lsp::diagnostics::edit_distance;
     use crate::lsp::diagnostics::generate_diagnostics;
+    use crate::lsp::diagnostics::DiagnosticRule;
+    use crate::lsp::diagnostics::RuleLevel;
     use crate::lsp::documents::Document;
     use crate::lsp::state::WorldState;
     use crate::r_task;
@@ -1045,6 +1591,7 @@ mod tests {
         WorldState {
             console_scopes: inputs.console_scopes,
             installed_packages: inputs.installed_packages,
+            known_options: inputs.known_options,
             ..Default::default()
         }
     }
@@ -1074,6 +1621,25 @@ foo
         })
     }
 
+    #[test]
+    fn test_deeply_nested_code() {
+        r_task(|| {
+            // Too deep to be analysed, but other expressions still are
+            let n = 5000;
+            let text = format!("{}x{}\nfoo", "f(".repeat(n), ")".repeat(n));
+            let document = Document::new(&text, None);
+            let diagnostics = generate_diagnostics(document, DEFAULT_STATE.clone());
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].range.start, Position::new(1, 0));
+
+            // Unbalanced
+            let text = format!("{}x", "f(".repeat(n));
+            let document = Document::new(&text, None);
+            let diagnostics = generate_diagnostics(document, DEFAULT_STATE.clone());
+            assert!(!diagnostics.is_empty());
+        })
+    }
+
     #[test]
     fn test_comment_after_call_argument() {
         r_task(|| {
@@ -1358,4 +1924,154 @@ foo
             insta::assert_snapshot!(diagnostic.message);
         })
     }
+
+    #[test]
+    fn test_option_name_typos() {
+        r_task(|| {
+            let code = "
+                options(digts = 3, my.new.option = TRUE)
+                getOption('max.prnt')
+                options(digits = 3)
+            ";
+            let document = Document::new(code, None);
+
+            let diagnostics = generate_diagnostics(document.clone(), DEFAULT_STATE.clone());
+            assert_eq!(diagnostics.len(), 2);
+
+            let diagnostic = diagnostics.get(0).unwrap();
+            assert_eq!(diagnostic.range.start.line, 1);
+            insta::assert_snapshot!(diagnostic.message);
+
+            let diagnostic = diagnostics.get(1).unwrap();
+            assert_eq!(diagnostic.range.start.line, 2);
+            insta::assert_snapshot!(diagnostic.message);
+        })
+    }
+
+    #[test]
+    fn test_call_arity() {
+        r_task(|| {
+            let code = "
+                f <- function(x, value = 1) x
+                g <- function(x, ...) x
+                f(1, 2, 3)
+                f(1, val = 2)
+                f(y = 1)
+                g(1, 2, y = 3)
+            ";
+            let document = Document::new(code, None);
+
+            let diagnostics = generate_diagnostics(document, DEFAULT_STATE.clone());
+            assert_eq!(diagnostics.len(), 2);
+
+            let diagnostic = diagnostics.get(0).unwrap();
+            assert_eq!(diagnostic.range.start.line, 3);
+            assert_eq!(
+                diagnostic.message,
+                "Too many arguments in call to `f()`, it takes 2 arguments."
+            );
+
+            let diagnostic = diagnostics.get(1).unwrap();
+            assert_eq!(diagnostic.range.start.line, 5);
+            assert_eq!(diagnostic.message, "Unused argument `y` in call to `f()`.");
+        })
+    }
+
+    #[test]
+    fn test_argument_types() {
+        r_task(|| {
+            let code = "
+                # @type n: numeric
+                # @type label: character
+                f <- function(x, n, label = NULL) paste(x, n, label)
+                # @type name: character
+                name <- character()
+                # @type df: data.frame
+                df <- data.frame()
+                f(1, 2L, 'a')
+                f(1, 'a', label = TRUE)
+                f(1, name, df)
+            ";
+            let document = Document::new(code, None);
+
+            let diagnostics = generate_diagnostics(document, DEFAULT_STATE.clone());
+            assert_eq!(diagnostics.len(), 3);
+
+            let diagnostic = diagnostics.get(0).unwrap();
+            assert_eq!(diagnostic.range.start.line, 9);
+            assert_eq!(
+                diagnostic.message,
+                "Argument `n` of `f()` is annotated as `numeric`, not `character`."
+            );
+            assert_eq!(
+                diagnostic.code,
+                Some(NumberOrString::String(String::from("argument-type")))
+            );
+
+            let diagnostic = diagnostics.get(1).unwrap();
+            assert_eq!(diagnostic.range.start.line, 9);
+            assert_eq!(
+                diagnostic.message,
+                "Argument `label` of `f()` is annotated as `character`, not `logical`."
+            );
+
+            // Only atomic annotations are compared, so `df` isn't flagged
+            let diagnostic = diagnostics.get(2).unwrap();
+            assert_eq!(diagnostic.range.start.line, 10);
+            assert!(diagnostic.message.starts_with("Argument `n` of `f()`"));
+        })
+    }
+
+    #[test]
+    fn test_diagnostic_rules() {
+        r_task(|| {
+            let code = "
+                f <- function(x) x
+                f(1, 2)
+                foo
+            ";
+            let document = Document::new(code, None);
+
+            let mut state = DEFAULT_STATE.clone();
+            state
+                .config
+                .diagnostics
+                .rules
+                .insert(DiagnosticRule::UnknownSymbol, RuleLevel::Off);
+            state
+                .config
+                .diagnostics
+                .rules
+                .insert(DiagnosticRule::CallArity, RuleLevel::Error);
+
+            let diagnostics = generate_diagnostics(document, state);
+            assert_eq!(diagnostics.len(), 1);
+
+            let diagnostic = diagnostics.get(0).unwrap();
+            assert_eq!(diagnostic.range.start.line, 2);
+            assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+            assert_eq!(
+                diagnostic.code,
+                Some(NumberOrString::String(String::from("call-arity")))
+            );
+        })
+    }
+
+    #[test]
+    fn test_diagnostic_rule_names() {
+        assert_eq!(
+            DiagnosticRule::from_str("unknown-symbol"),
+            Some(DiagnosticRule::UnknownSymbol)
+        );
+        assert_eq!(DiagnosticRule::from_str("unknown"), None);
+        assert_eq!(RuleLevel::from_str("off"), Some(RuleLevel::Off));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("digits", "digits"), 0);
+        assert_eq!(edit_distance("digts", "digits"), 1);
+        assert_eq!(edit_distance("widht", "width"), 2);
+        assert_eq!(edit_distance("", "warn"), 4);
+    }
 }
@@ -0,0 +1,750 @@
+//
+// diagnostics_flow.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use ropey::Rope;
+use serde::Deserialize;
+use serde::Serialize;
+use tower_lsp::lsp_types::Diagnostic;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+use tower_lsp::lsp_types::DiagnosticTag;
+use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::Range;
+use tower_lsp::lsp_types::TextEdit;
+use tree_sitter::Node;
+use tree_sitter::Point;
+
+use crate::lsp::diagnostics::DiagnosticContext;
+use crate::lsp::diagnostics::DiagnosticRule;
+use crate::lsp::encoding::convert_point_to_position;
+use crate::lsp::encoding::convert_tree_sitter_range_to_lsp_range;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::node_depth_exceeds;
+use crate::treesitter::node_has_error_or_missing;
+use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+use crate::treesitter::UnaryOperatorType;
+use crate::treesitter::MAX_TREE_DEPTH;
+
+/// Calls that look up or evaluate symbols of the calling environment
+/// dynamically. Variables and arguments may be used through them without
+/// appearing in the code, so functions making these calls are not checked for
+/// unused symbols.
+const DYNAMIC_CALLS: &[&str] = &[
+    "UseMethod",
+    "NextMethod",
+    "standardGeneric",
+    "callNextMethod",
+    "environment",
+    "current_env",
+    "sys.function",
+    "sys.call",
+    "match.call",
+    "get",
+    "get0",
+    "mget",
+    "exists",
+    "eval",
+    "evalq",
+    "ls",
+    "browser",
+];
+
+/// Calls whose arguments are quoted or evaluated in their own environment,
+/// so that assignments in their arguments don't define local variables
+const QUOTING_CALLS: &[&str] = &[
+    "quote",
+    "bquote",
+    "expression",
+    "substitute",
+    "alist",
+    "local",
+];
+
+/// Calls that never return to the caller
+const EXIT_CALLS: &[&str] = &[
+    "return",
+    "stop",
+    "abort",
+    "cli_abort",
+    "q",
+    "quit",
+    "invokeRestart",
+];
+
+/// Strings may refer to variables by name, e.g. `get("x")` or
+/// `glue("{x}")`, so any name found in a string counts as a use
+static RE_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[A-Za-z.][A-Za-z0-9._]*").unwrap());
+
+/// Quick fix for a diagnostic. Stored in the `data` field of the diagnostic,
+/// which clients send back when requesting code actions.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct QuickFix {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Symbols used in a function
+#[derive(Default)]
+struct Usage {
+    names: HashSet<String>,
+
+    /// Whether the function looks up symbols dynamically, see `DYNAMIC_CALLS`
+    dynamic: bool,
+}
+
+/// Data flow diagnostics, computed separately for each function:
+///
+/// - Arguments that are never referenced.
+/// - Local variables that are assigned but never used.
+/// - Code following a `return()`, `stop()`, `break` or `next`, which is never
+///   evaluated.
+///
+/// Symbols are matched by name anywhere within the function, including in
+/// nested functions and strings, so that uses through closures or non-standard
+/// evaluation don't cause false positives. The diagnostics are tagged as
+/// unnecessary code and come with a quick fix.
+pub(crate) fn flow_diagnostics(
+    root: Node,
+    context: &DiagnosticContext,
+) -> anyhow::Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let mut cursor = root.walk();
+
+    // As for semantic diagnostics, skip top level expressions that don't parse
+    // or are too deeply nested
+    for child in root.children(&mut cursor) {
+        if node_has_error_or_missing(&child) || node_depth_exceeds(&child, MAX_TREE_DEPTH) {
+            continue;
+        }
+        visit_functions(child, context, &mut diagnostics)?;
+    }
+
+    Ok(diagnostics)
+}
+
+fn visit_functions(
+    node: Node,
+    context: &DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    if node.is_function_definition() {
+        diagnose_function(node, context, diagnostics)?;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit_functions(child, context, diagnostics)?;
+    }
+
+    Ok(())
+}
+
+fn diagnose_function(
+    node: Node,
+    context: &DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    let Some(body) = node.child_by_field_name("body") else {
+        return Ok(());
+    };
+    let parameters = node.child_by_field_name("parameters");
+
+    // Default values of parameters may refer to other parameters
+    let mut usage = Usage::default();
+    if let Some(parameters) = parameters {
+        collect_usage(parameters, context.contents, &mut usage)?;
+    }
+    collect_usage(body, context.contents, &mut usage)?;
+
+    if !usage.dynamic {
+        // The signature of callbacks is imposed by the caller
+        if let Some(parameters) = parameters {
+            if !is_callback(&node) {
+                check_unused_parameters(parameters, &usage, context, diagnostics)?;
+            }
+        }
+        check_unused_variables(body, body, &usage, context, diagnostics)?;
+    }
+
+    check_unreachable_code(body, context, diagnostics)
+}
+
+fn collect_usage(node: Node, contents: &Rope, usage: &mut Usage) -> anyhow::Result<()> {
+    match node.node_type() {
+        NodeType::Identifier => {
+            if !is_binding(&node) {
+                usage.names.insert(symbol_name(&node, contents)?);
+            }
+        },
+        NodeType::String => {
+            let text = contents.node_slice(&node)?.to_string();
+            for name in RE_NAME.find_iter(&text) {
+                usage.names.insert(String::from(name.as_str()));
+            }
+            return Ok(());
+        },
+        NodeType::Call => {
+            if let Some(function) = node.child_by_field_name("function") {
+                if DYNAMIC_CALLS.contains(&call_name(&function, contents)?.as_str()) {
+                    usage.dynamic = true;
+                }
+            }
+        },
+        _ => {},
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_usage(child, contents, usage)?;
+    }
+
+    Ok(())
+}
+
+/// Whether an identifier names something rather than refers to a symbol,
+/// e.g. the target of an assignment or the name of an argument
+fn is_binding(node: &Node) -> bool {
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+
+    match parent.node_type() {
+        NodeType::Parameter | NodeType::Argument => {
+            parent.child_by_field_name("name") == Some(*node)
+        },
+        NodeType::ExtractOperator(_) => parent.child_by_field_name("rhs") == Some(*node),
+        NodeType::NamespaceOperator(_) => true,
+        NodeType::BinaryOperator(op) => assignment_target(&parent, &op, true) == Some(*node),
+        _ => false,
+    }
+}
+
+/// Target of an assignment. Super-assignments don't define local variables and
+/// are only included if `super_assignment` is set.
+fn assignment_target<'tree>(
+    node: &Node<'tree>,
+    op: &BinaryOperatorType,
+    super_assignment: bool,
+) -> Option<Node<'tree>> {
+    match op {
+        BinaryOperatorType::LeftAssignment | BinaryOperatorType::EqualsAssignment => {
+            node.child_by_field_name("lhs")
+        },
+        BinaryOperatorType::RightAssignment => node.child_by_field_name("rhs"),
+        BinaryOperatorType::LeftSuperAssignment if super_assignment => {
+            node.child_by_field_name("lhs")
+        },
+        BinaryOperatorType::RightSuperAssignment if super_assignment => {
+            node.child_by_field_name("rhs")
+        },
+        _ => None,
+    }
+}
+
+fn assignment_value<'tree>(node: &Node<'tree>, op: &BinaryOperatorType) -> Option<Node<'tree>> {
+    match op {
+        BinaryOperatorType::RightAssignment => node.child_by_field_name("lhs"),
+        _ => node.child_by_field_name("rhs"),
+    }
+}
+
+fn check_unused_parameters(
+    parameters: Node,
+    usage: &Usage,
+    context: &DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    let contents = context.contents;
+    let mut cursor = parameters.walk();
+
+    for parameter in parameters.children_by_field_name("parameter", &mut cursor) {
+        // Skips `...` too
+        let Some(name) = parameter.child_by_field_name("name") else {
+            continue;
+        };
+        if !name.is_identifier() {
+            continue;
+        }
+
+        let symbol = symbol_name(&name, contents)?;
+        if symbol.starts_with('.') || usage.names.contains(&symbol) {
+            continue;
+        }
+
+        let text = contents.node_slice(&name)?.to_string();
+        let dotted = match text.strip_prefix('`') {
+            Some(text) => format!("`.{text}"),
+            None => format!(".{text}"),
+        };
+
+        let range = convert_tree_sitter_range_to_lsp_range(contents, name.range());
+        let fix = QuickFix {
+            title: format!("Prefix `{symbol}` with a dot"),
+            edits: vec![TextEdit::new(range, dotted)],
+        };
+
+        diagnostics.push(new_diagnostic(
+            range,
+            format!("Argument `{symbol}` is never used."),
+            DiagnosticSeverity::HINT,
+            DiagnosticRule::UnusedArgument,
+            fix,
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_unused_variables(
+    node: Node,
+    body: Node,
+    usage: &Usage,
+    context: &DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    match node.node_type() {
+        // Nested functions have their own variables
+        NodeType::FunctionDefinition => return Ok(()),
+
+        NodeType::UnaryOperator(UnaryOperatorType::Tilde) |
+        NodeType::BinaryOperator(BinaryOperatorType::Tilde) => return Ok(()),
+
+        NodeType::Call => {
+            if let Some(function) = node.child_by_field_name("function") {
+                if QUOTING_CALLS.contains(&call_name(&function, context.contents)?.as_str()) {
+                    return Ok(());
+                }
+            }
+        },
+
+        NodeType::BinaryOperator(op) => {
+            let target = assignment_target(&node, &op, false);
+            let value = assignment_value(&node, &op);
+            if let (Some(target), Some(value)) = (target, value) {
+                if target.is_identifier() {
+                    check_unused_variable(node, target, value, body, usage, context, diagnostics)?;
+                }
+            }
+        },
+
+        _ => {},
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        check_unused_variables(child, body, usage, context, diagnostics)?;
+    }
+
+    Ok(())
+}
+
+fn check_unused_variable(
+    assignment: Node,
+    target: Node,
+    value: Node,
+    body: Node,
+    usage: &Usage,
+    context: &DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    let contents = context.contents;
+
+    let name = symbol_name(&target, contents)?;
+    if name.starts_with('.') || usage.names.contains(&name) {
+        return Ok(());
+    }
+
+    // `function() x <- 1` returns the value of `x`
+    if is_function_result(assignment, body) {
+        return Ok(());
+    }
+
+    // Keep the value if evaluating it might have side effects, or if the
+    // assignment is part of a larger expression
+    let fix = if is_statement(&assignment) && !has_side_effects(&value) {
+        QuickFix {
+            title: format!("Remove unused variable `{name}`"),
+            edits: vec![TextEdit::new(
+                removal_range(
+                    contents,
+                    assignment.start_position(),
+                    assignment.end_position(),
+                ),
+                String::new(),
+            )],
+        }
+    } else {
+        QuickFix {
+            title: format!("Remove assignment to `{name}`"),
+            edits: vec![TextEdit::new(
+                convert_tree_sitter_range_to_lsp_range(contents, assignment.range()),
+                contents.node_slice(&value)?.to_string(),
+            )],
+        }
+    };
+
+    let range = convert_tree_sitter_range_to_lsp_range(contents, target.range());
+    diagnostics.push(new_diagnostic(
+        range,
+        format!("Variable `{name}` is assigned but never used."),
+        DiagnosticSeverity::INFORMATION,
+        DiagnosticRule::UnusedVariable,
+        fix,
+    ));
+
+    Ok(())
+}
+
+/// Whether the value of `node` is returned by the function of `body`
+fn is_function_result(node: Node, body: Node) -> bool {
+    let mut node = node;
+
+    while node != body {
+        let Some(parent) = node.parent() else {
+            return false;
+        };
+
+        match parent.node_type() {
+            NodeType::BracedExpression => {
+                let mut cursor = parent.walk();
+                let last = parent.children_by_field_name("body", &mut cursor).last();
+                if last != Some(node) {
+                    return false;
+                }
+            },
+            NodeType::IfStatement => {
+                if parent.child_by_field_name("condition") == Some(node) {
+                    return false;
+                }
+            },
+            NodeType::ParenthesizedExpression => {},
+            _ => return false,
+        }
+
+        node = parent;
+    }
+
+    true
+}
+
+fn is_statement(node: &Node) -> bool {
+    node.parent()
+        .is_some_and(|parent| parent.is_braced_expression() || parent.is_program())
+}
+
+fn has_side_effects(node: &Node) -> bool {
+    !matches!(
+        node.node_type(),
+        NodeType::Identifier |
+            NodeType::Integer |
+            NodeType::Float |
+            NodeType::Complex |
+            NodeType::String |
+            NodeType::True |
+            NodeType::False |
+            NodeType::Null |
+            NodeType::Inf |
+            NodeType::Nan |
+            NodeType::Na(_) |
+            NodeType::FunctionDefinition
+    )
+}
+
+fn check_unreachable_code(
+    node: Node,
+    context: &DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    match node.node_type() {
+        // Nested functions are diagnosed on their own
+        NodeType::FunctionDefinition => return Ok(()),
+        NodeType::BracedExpression => check_unreachable_statements(node, context, diagnostics)?,
+        _ => {},
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        check_unreachable_code(child, context, diagnostics)?;
+    }
+
+    Ok(())
+}
+
+fn check_unreachable_statements(
+    node: Node,
+    context: &DiagnosticContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    let contents = context.contents;
+
+    let mut cursor = node.walk();
+    let statements: Vec<Node> = node.children_by_field_name("body", &mut cursor).collect();
+
+    let mut exit = None;
+    for (i, statement) in statements.iter().enumerate() {
+        if always_exits(statement, contents)? {
+            exit = Some(i);
+            break;
+        }
+    }
+
+    let Some(exit) = exit else {
+        return Ok(());
+    };
+    let unreachable = &statements[exit + 1..];
+    let (Some(first), Some(last)) = (unreachable.first(), unreachable.last()) else {
+        return Ok(());
+    };
+
+    let start = first.start_position();
+    let end = last.end_position();
+
+    let fix = QuickFix {
+        title: String::from("Remove unreachable code"),
+        edits: vec![TextEdit::new(
+            removal_range(contents, start, end),
+            String::new(),
+        )],
+    };
+
+    let range = Range::new(
+        convert_point_to_position(contents, start),
+        convert_point_to_position(contents, end),
+    );
+    diagnostics.push(new_diagnostic(
+        range,
+        String::from("Code is unreachable."),
+        DiagnosticSeverity::INFORMATION,
+        DiagnosticRule::UnreachableCode,
+        fix,
+    ));
+
+    Ok(())
+}
+
+/// Whether evaluating `node` always leaves the enclosing block, by returning
+/// from the function, signalling an error, or jumping to the next iteration
+fn always_exits(node: &Node, contents: &Rope) -> anyhow::Result<bool> {
+    match node.node_type() {
+        NodeType::Break | NodeType::Next => Ok(true),
+
+        NodeType::Call => {
+            let Some(function) = node.child_by_field_name("function") else {
+                return Ok(false);
+            };
+            Ok(EXIT_CALLS.contains(&call_name(&function, contents)?.as_str()))
+        },
+
+        NodeType::BracedExpression | NodeType::ParenthesizedExpression => {
+            let mut cursor = node.walk();
+            for child in node.children_by_field_name("body", &mut cursor) {
+                if always_exits(&child, contents)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        },
+
+        NodeType::IfStatement => {
+            let consequence = node.child_by_field_name("consequence");
+            let alternative = node.child_by_field_name("alternative");
+            let (Some(consequence), Some(alternative)) = (consequence, alternative) else {
+                return Ok(false);
+            };
+            Ok(always_exits(&consequence, contents)? && always_exits(&alternative, contents)?)
+        },
+
+        _ => Ok(false),
+    }
+}
+
+/// Whether a function is passed as argument to a call, e.g. a `tryCatch()`
+/// handler or an `lapply()` callback
+fn is_callback(node: &Node) -> bool {
+    node.parent().is_some_and(|parent| parent.is_argument())
+}
+
+/// Name of a called function, without namespace, e.g. `stop` for
+/// `base::stop()`
+fn call_name(function: &Node, contents: &Rope) -> anyhow::Result<String> {
+    let text = contents.node_slice(function)?.to_string();
+    let name = text.rsplit(':').next().unwrap_or_default();
+    Ok(String::from(name.trim_matches('`')))
+}
+
+fn symbol_name(node: &Node, contents: &Rope) -> anyhow::Result<String> {
+    let text = contents.node_slice(node)?.to_string();
+    Ok(String::from(text.trim_matches('`')))
+}
+
+/// Range of code to remove. Whole lines are removed when the code is the only
+/// thing on them, so that no blank lines are left behind.
+fn removal_range(contents: &Rope, start: Point, end: Point) -> Range {
+    let line_start = contents.line_to_byte(start.row);
+    let before = contents
+        .get_byte_slice(line_start..line_start + start.column)
+        .map(|slice| slice.to_string())
+        .unwrap_or_default();
+
+    let line = contents.line(end.row).to_string();
+    let after = line.get(end.column..).unwrap_or_default();
+
+    let whole_lines = before.trim().is_empty() && after.trim().is_empty();
+    if whole_lines && end.row + 1 < contents.len_lines() {
+        return Range::new(
+            Position::new(start.row as u32, 0),
+            Position::new(end.row as u32 + 1, 0),
+        );
+    }
+
+    Range::new(
+        convert_point_to_position(contents, start),
+        convert_point_to_position(contents, end),
+    )
+}
+
+fn new_diagnostic(
+    range: Range,
+    message: String,
+    severity: DiagnosticSeverity,
+    rule: DiagnosticRule,
+    fix: QuickFix,
+) -> Diagnostic {
+    let mut diagnostic = Diagnostic::new_simple(range, message);
+    diagnostic.severity = Some(severity);
+    rule.tag(&mut diagnostic);
+    diagnostic.tags = Some(vec![DiagnosticTag::UNNECESSARY]);
+    diagnostic.data = serde_json::to_value(fix).ok();
+    diagnostic
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+    use tower_lsp::lsp_types::Diagnostic;
+    use tower_lsp::lsp_types::Position;
+
+    use crate::lsp::diagnostics::DiagnosticContext;
+    use crate::lsp::diagnostics_flow::flow_diagnostics;
+    use crate::lsp::diagnostics_flow::QuickFix;
+    use crate::lsp::documents::Document;
+
+    fn diagnostics(text: &str) -> Vec<Diagnostic> {
+        let document = Document::new(text, None);
+        let contents = Rope::from_str(text);
+        let context = DiagnosticContext::new(&contents);
+        flow_diagnostics(document.ast.root_node(), &context).unwrap()
+    }
+
+    fn fix(diagnostic: &Diagnostic) -> QuickFix {
+        serde_json::from_value(diagnostic.data.clone().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_unused_parameters() {
+        let diagnostics = diagnostics("f <- function(x, y, .z, ...) x");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Argument `y` is never used.");
+        assert_eq!(diagnostics[0].range.start, Position::new(0, 17));
+
+        let fix = fix(&diagnostics[0]);
+        assert_eq!(fix.edits[0].new_text, ".y");
+
+        // Used in another default, in a nested function, in a string
+        let text = "f <- function(x, n = length(x), y, z) function() y + glue('{z}') + n";
+        assert!(diagnostics(text).is_empty());
+
+        // Callbacks and methods are not checked
+        assert!(diagnostics("tryCatch(f(), error = function(e) NULL)").is_empty());
+        assert!(diagnostics("print.foo <- function(x, ...) NextMethod()").is_empty());
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        let text = "f <- function() {
+  x <- 1
+  y <- g()
+  z <- 2
+  z
+}";
+        let diagnostics = diagnostics(text);
+        assert_eq!(diagnostics.len(), 2);
+
+        assert_eq!(
+            diagnostics[0].message,
+            "Variable `x` is assigned but never used."
+        );
+        let x = fix(&diagnostics[0]);
+        assert_eq!(x.title, "Remove unused variable `x`");
+        assert_eq!(x.edits[0].range.start, Position::new(1, 0));
+        assert_eq!(x.edits[0].range.end, Position::new(2, 0));
+        assert_eq!(x.edits[0].new_text, "");
+
+        // The call is kept
+        let y = fix(&diagnostics[1]);
+        assert_eq!(y.title, "Remove assignment to `y`");
+        assert_eq!(y.edits[0].new_text, "g()");
+    }
+
+    #[test]
+    fn test_unused_variables_false_positives() {
+        // Returned value
+        assert!(diagnostics("f <- function() x <- 1").is_empty());
+        assert!(diagnostics("f <- function() { if (a) x <- 1 else x <- 2 }").is_empty());
+
+        // Super-assignment, use in closure or string, quoted code
+        let text = "f <- function() {
+  a <<- 1
+  b <- 1
+  c <- 1
+  d <- quote(e <- 1)
+  g <- function() b
+  glue::glue('{c}')
+  list(g, d)
+}";
+        assert!(diagnostics(text).is_empty());
+
+        // Dynamic lookups
+        assert!(diagnostics("f <- function() { x <- 1; environment() }").is_empty());
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let text = "f <- function(x) {
+  if (x) {
+    return(1)
+  } else {
+    stop('no')
+  }
+  x
+  2
+}";
+        let diagnostics = diagnostics(text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Code is unreachable.");
+        assert_eq!(diagnostics[0].range.start, Position::new(6, 2));
+        assert_eq!(diagnostics[0].range.end, Position::new(7, 3));
+
+        let fix = fix(&diagnostics[0]);
+        assert_eq!(fix.edits[0].range.start, Position::new(6, 0));
+        assert_eq!(fix.edits[0].range.end, Position::new(8, 0));
+
+        // Only inside of functions
+        assert!(diagnostics("{ return(1); 2 }").is_empty());
+
+        let text = "f <- function(xs) for (x in xs) { if (x) next; g(x) }";
+        assert!(diagnostics(text).is_empty());
+
+        let text = "f <- function(xs) for (x in xs) { next; g(x) }";
+        assert_eq!(diagnostics(text).len(), 1);
+    }
+}
@@ -15,6 +15,7 @@ use crate::lsp::traits::rope::RopeExt;
 use crate::treesitter::node_has_error_or_missing;
 use crate::treesitter::NodeType;
 use crate::treesitter::NodeTypeExt;
+use crate::treesitter::MAX_TREE_DEPTH;
 
 pub(crate) fn syntax_diagnostics(
     root: Node,
@@ -22,7 +23,7 @@ pub(crate) fn syntax_diagnostics(
 ) -> anyhow::Result<Vec<Diagnostic>> {
     let mut diagnostics = Vec::new();
 
-    recurse(root, context, &mut diagnostics)?;
+    recurse(root, 0, context, &mut diagnostics)?;
 
     Ok(diagnostics)
 }
@@ -31,20 +32,24 @@ pub(crate) fn syntax_diagnostics(
 // which can also be `ERROR`s. The goal is to target the deepest (most precise) `ERROR`
 // nodes and only report syntax errors for those. We accomplish this by recursing
 // into children first and bailing if we find any children that we reported an error for.
+// Errors nested deeper than `MAX_TREE_DEPTH` are reported on their closest `ERROR`
+// ancestor within reach.
 fn recurse(
     node: Node,
+    depth: usize,
     context: &DiagnosticContext,
     diagnostics: &mut Vec<Diagnostic>,
 ) -> anyhow::Result<bool> {
-    if !node_has_error_or_missing(&node) {
-        // Stop recursion if this branch of the tree doesn't have issues
+    if !node_has_error_or_missing(&node) || depth > MAX_TREE_DEPTH {
+        // Stop recursion if this branch of the tree doesn't have issues or is
+        // too deep to recurse into safely
         return Ok(false);
     }
 
     // Always look for contextual `MISSING` issues based on the current node type
     diagnose_missing(node, context, diagnostics)?;
 
-    let mut any_errors = recurse_children(node, context, diagnostics)?;
+    let mut any_errors = recurse_children(node, depth, context, diagnostics)?;
 
     // Report an error when:
     // - No children were `ERROR`s
@@ -59,6 +64,7 @@ fn recurse(
 
 fn recurse_children(
     node: Node,
+    depth: usize,
     context: &DiagnosticContext,
     diagnostics: &mut Vec<Diagnostic>,
 ) -> anyhow::Result<bool> {
@@ -66,7 +72,7 @@ fn recurse_children(
     let mut cursor = node.walk();
 
     for child in node.children(&mut cursor) {
-        any_errors |= recurse(child, context, diagnostics)?;
+        any_errors |= recurse(child, depth + 1, context, diagnostics)?;
     }
 
     Ok(any_errors)
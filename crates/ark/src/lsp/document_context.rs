@@ -10,6 +10,7 @@ use tree_sitter::Point;
 
 use crate::lsp::documents::Document;
 use crate::lsp::traits::node::NodeExt;
+use crate::treesitter::NodeTypeExt;
 
 #[derive(Debug)]
 pub struct DocumentContext<'a> {
@@ -40,6 +41,25 @@ impl<'a> DocumentContext<'a> {
             trigger,
         }
     }
+
+    /// Find the innermost formula containing the context node, e.g. `y ~ x`
+    /// when the cursor is on `x`. The search stops at braced expressions and
+    /// function definitions since these introduce regular code.
+    pub fn formula(&self) -> Option<Node<'a>> {
+        let mut node = self.node;
+
+        loop {
+            if node.is_formula() {
+                return Some(node);
+            }
+
+            if node.is_braced_expression() || node.is_function_definition() {
+                return None;
+            }
+
+            node = node.parent()?;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -77,4 +97,36 @@ mod tests {
             "1".to_string()
         );
     }
+
+    #[test]
+    fn test_document_context_formula() {
+        let document = Document::new("lm(y ~ x + log(z), data = df)", None);
+
+        // On both sides of the `~`
+        for column in [4, 8, 16] {
+            let point = Point { row: 0, column };
+            let context = DocumentContext::new(&document, point, None);
+            let formula = context.formula().unwrap();
+            assert_eq!(
+                context
+                    .document
+                    .contents
+                    .node_slice(&formula)
+                    .unwrap()
+                    .to_string(),
+                "y ~ x + log(z)".to_string()
+            );
+        }
+
+        // Outside of the formula
+        let point = Point { row: 0, column: 28 };
+        let context = DocumentContext::new(&document, point, None);
+        assert!(context.formula().is_none());
+
+        // Braced expressions are not part of the formula
+        let document = Document::new("~ { x }", None);
+        let point = Point { row: 0, column: 5 };
+        let context = DocumentContext::new(&document, point, None);
+        assert!(context.formula().is_none());
+    }
 }
@@ -5,9 +5,15 @@
 //
 //
 
+use std::hash::DefaultHasher;
+use std::hash::Hasher;
+use std::path::Path;
+use std::time::SystemTime;
+
 use anyhow::*;
 use ropey::Rope;
 use tower_lsp::lsp_types::DidChangeTextDocumentParams;
+use tower_lsp::lsp_types::Position;
 use tower_lsp::lsp_types::TextDocumentContentChangeEvent;
 use tree_sitter::InputEdit;
 use tree_sitter::Parser;
@@ -15,6 +21,8 @@ use tree_sitter::Point;
 use tree_sitter::Tree;
 
 use crate::lsp::config::DocumentConfig;
+use crate::lsp::encoding::check_point;
+use crate::lsp::encoding::check_position;
 use crate::lsp::encoding::convert_position_to_point;
 use crate::lsp::traits::rope::RopeExt;
 
@@ -35,6 +43,19 @@ fn compute_point(point: Point, text: &str) -> Point {
     }
 }
 
+/// Converts a `position` sent by the frontend to a point and byte of
+/// `contents`. Fails instead of panicking or pointing into another line when
+/// `position` isn't a character boundary of `contents`, e.g. when its column
+/// is beyond the end of its line.
+fn checked_position_to_byte(contents: &Rope, position: Position) -> Result<(Point, usize)> {
+    check_position(contents, position).context("Invalid position")?;
+
+    let point = convert_position_to_point(contents, position);
+    check_point(contents, point).context("Invalid point")?;
+
+    Ok((point, contents.point_to_byte(point)))
+}
+
 #[derive(Clone)]
 pub struct Document {
     // The document's textual contents.
@@ -49,6 +70,57 @@ pub struct Document {
 
     // Configuration of the document, such as indentation settings.
     pub config: DocumentConfig,
+
+    // State of the backing file when the editor was last in sync with it,
+    // i.e. when the document was opened or saved. None for documents that
+    // are not backed by a file.
+    pub disk: Option<DiskState>,
+}
+
+/// Fingerprint of a file on disk
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiskState {
+    pub modified: Option<SystemTime>,
+    pub hash: u64,
+}
+
+/// Outcome of comparing a document with its file on disk
+#[derive(Debug, PartialEq)]
+pub enum DiskChange {
+    /// The file didn't change since we last saw it
+    Unchanged,
+
+    /// The file changed and now has the same contents as the editor, e.g.
+    /// after the editor saved it
+    Synced,
+
+    /// The file changed and differs from the editor contents, e.g. it was
+    /// regenerated by a build step while the document was open
+    Diverged,
+
+    /// The file was deleted
+    Deleted,
+}
+
+impl DiskState {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let contents = std::fs::read(path)?;
+        Ok(Self::new(path, &contents))
+    }
+
+    fn new(path: &Path, contents: &[u8]) -> Self {
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        let mut hasher = DefaultHasher::new();
+        hasher.write(contents);
+
+        Self {
+            modified,
+            hash: hasher.finish(),
+        }
+    }
 }
 
 impl std::fmt::Debug for Document {
@@ -81,10 +153,55 @@ impl Document {
             version,
             ast,
             config: Default::default(),
+            disk: None,
+        }
+    }
+
+    /// Remember the current state of the file at `path`. Called when the
+    /// editor is known to be in sync with it.
+    pub fn sync_disk(&mut self, path: &Path) {
+        self.disk = DiskState::from_path(path).ok();
+    }
+
+    /// Compare the document with the file at `path` after it changed on disk,
+    /// and remember the new state of the file so that a change is only
+    /// reported once. The document contents are left untouched: the editor
+    /// stays the source of truth for open documents.
+    pub fn check_disk(&mut self, path: &Path) -> DiskChange {
+        let std::result::Result::Ok(contents) = std::fs::read(path) else {
+            if path.exists() {
+                // Can't tell, e.g. the file is being written
+                return DiskChange::Unchanged;
+            }
+            return match self.disk.take() {
+                Some(_) => DiskChange::Deleted,
+                None => DiskChange::Unchanged,
+            };
+        };
+
+        let state = DiskState::new(path, &contents);
+        if self
+            .disk
+            .as_ref()
+            .is_some_and(|disk| disk.hash == state.hash)
+        {
+            self.disk = Some(state);
+            return DiskChange::Unchanged;
+        }
+        self.disk = Some(state);
+
+        if self.contents == String::from_utf8_lossy(&contents) {
+            DiskChange::Synced
+        } else {
+            DiskChange::Diverged
         }
     }
 
-    pub fn on_did_change(&mut self, parser: &mut Parser, params: &DidChangeTextDocumentParams) {
+    pub fn on_did_change(
+        &mut self,
+        parser: &mut Parser,
+        params: &DidChangeTextDocumentParams,
+    ) -> Result<()> {
         let new_version = params.text_document.version;
 
         // Check for out-of-order change notifications
@@ -100,14 +217,21 @@ impl Document {
             }
         }
 
+        // Apply the changes to a copy so that a change set that fails partway
+        // leaves the document and its version untouched. Ropes and trees are
+        // cheap to clone as they share their nodes.
+        let mut document = self.clone();
+
         for event in &params.content_changes {
-            if let Err(err) = self.update(parser, event) {
-                panic!("Failed to update document: {err:?}");
-            }
+            document
+                .update(parser, event)
+                .context("Failed to update document")?;
         }
 
-        // Set new version
-        self.version = Some(new_version);
+        document.version = Some(new_version);
+        *self = document;
+
+        Ok(())
     }
 
     fn update(
@@ -127,11 +251,14 @@ impl Document {
         // offsets can be computed correctly.
         let ast = &mut self.ast;
 
-        let start_point = convert_position_to_point(&self.contents, range.start);
-        let start_byte = self.contents.point_to_byte(start_point);
+        let (start_point, start_byte) = checked_position_to_byte(&self.contents, range.start)?;
+        let (old_end_point, old_end_byte) = checked_position_to_byte(&self.contents, range.end)?;
 
-        let old_end_point = convert_position_to_point(&self.contents, range.end);
-        let old_end_byte = self.contents.point_to_byte(old_end_point);
+        // Check the range before editing anything so that an invalid edit
+        // sent by the frontend leaves the document untouched
+        if old_end_byte < start_byte {
+            bail!("Invalid edit range: end {old_end_point} is before start {start_point}");
+        }
 
         let new_end_point = compute_point(start_point, &change.text);
         let new_end_byte = start_byte + change.text.as_bytes().len();
@@ -193,16 +320,20 @@ impl Document {
         // How far into this chunk are we?
         let byte = byte - chunk_byte_idx;
 
-        // Now return the slice from that `byte` to the end of the chunk.
+        // Now return the slice from that `byte` to the end of the chunk. Slice
+        // the bytes rather than the `str` since `byte` is not guaranteed to
+        // fall on a character boundary after a bogus edit.
         // SAFETY: This should never panic, since `get_chunk_at_byte()` worked.
-        let slice = &chunk[byte..];
-
-        slice.as_bytes()
+        &chunk.as_bytes()[byte..]
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use tower_lsp::lsp_types::Range;
+    use tower_lsp::lsp_types::Url;
+    use tower_lsp::lsp_types::VersionedTextDocumentIdentifier;
+
     use super::*;
 
     #[test]
@@ -226,10 +357,103 @@ mod tests {
         assert_eq!(point, Point::new(1, 0));
     }
 
+    fn did_change_params(
+        version: i32,
+        start: Position,
+        end: Position,
+        text: &str,
+    ) -> DidChangeTextDocumentParams {
+        DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: Url::parse("file:///test.R").unwrap(),
+                version,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range { start, end }),
+                range_length: None,
+                text: String::from(text),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_document_invalid_edit_range() {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_r::LANGUAGE.into())
+            .unwrap();
+
+        let mut document = Document::new("x <- 1\ny <- 2", None);
+
+        // Line beyond the end of the document
+        let params = did_change_params(1, Position::new(10, 0), Position::new(10, 0), "z");
+        assert!(document.on_did_change(&mut parser, &params).is_err());
+
+        // Column beyond the end of the document
+        let params = did_change_params(2, Position::new(1, 100), Position::new(1, 100), "z");
+        assert!(document.on_did_change(&mut parser, &params).is_err());
+
+        // Column beyond the end of a line that isn't the last one, which
+        // would otherwise point into the next line
+        let params = did_change_params(2, Position::new(0, 8), Position::new(0, 8), "z");
+        assert!(document.on_did_change(&mut parser, &params).is_err());
+        let params = did_change_params(2, Position::new(0, 0), Position::new(0, 7), "z");
+        assert!(document.on_did_change(&mut parser, &params).is_err());
+
+        // End before start
+        let params = did_change_params(3, Position::new(1, 2), Position::new(0, 2), "z");
+        assert!(document.on_did_change(&mut parser, &params).is_err());
+
+        // The document is untouched and can still be updated
+        assert_eq!(document.contents.to_string(), "x <- 1\ny <- 2");
+
+        let params = did_change_params(4, Position::new(1, 5), Position::new(1, 6), "3");
+        document.on_did_change(&mut parser, &params).unwrap();
+        assert_eq!(document.contents.to_string(), "x <- 1\ny <- 3");
+        assert_eq!(document.version, Some(4));
+
+        // A change set with an invalid change isn't partially applied
+        let mut params = did_change_params(5, Position::new(0, 5), Position::new(0, 6), "2");
+        let mut invalid = did_change_params(5, Position::new(10, 0), Position::new(10, 0), "z");
+        params.content_changes.append(&mut invalid.content_changes);
+        assert!(document.on_did_change(&mut parser, &params).is_err());
+        assert_eq!(document.contents.to_string(), "x <- 1\ny <- 3");
+        assert_eq!(document.version, Some(4));
+    }
+
     #[test]
     fn test_document_starts_at_0_0_with_leading_whitespace() {
         let document = Document::new("\n\n# hi there", None);
         let root = document.ast.root_node();
         assert_eq!(root.start_position(), Point::new(0, 0));
     }
+
+    #[test]
+    fn test_document_disk_divergence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.R");
+        std::fs::write(&path, "x <- 1").unwrap();
+
+        let mut document = Document::new("x <- 1", None);
+        document.sync_disk(&path);
+        assert_eq!(document.check_disk(&path), DiskChange::Unchanged);
+
+        // Written by another process
+        std::fs::write(&path, "x <- 2").unwrap();
+        assert_eq!(document.check_disk(&path), DiskChange::Diverged);
+
+        // Only reported once
+        assert_eq!(document.check_disk(&path), DiskChange::Unchanged);
+
+        // Saved from the editor
+        std::fs::write(&path, "x <- 1").unwrap();
+        assert_eq!(document.check_disk(&path), DiskChange::Synced);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(document.check_disk(&path), DiskChange::Deleted);
+        assert_eq!(document.check_disk(&path), DiskChange::Unchanged);
+
+        // The editor contents are never touched
+        assert_eq!(document.contents.to_string(), "x <- 1");
+    }
 }
@@ -5,6 +5,7 @@
 //
 //
 
+use anyhow::anyhow;
 use ropey::Rope;
 use tower_lsp::lsp_types::Position;
 use tree_sitter::Point;
@@ -39,6 +40,13 @@ use tree_sitter::Point;
 ///
 /// So we need a way to convert the UTF-16 `Position`s to UTF-8 `tree_sitter::Point`s and
 /// back. This requires the document itself, and is what the helpers in this file implement.
+///
+/// Lines are only broken on `\n`, as in tree-sitter, so a `\r\n` line ends with a `\r`
+/// that clients never point past. The LSP specification also breaks lines on a lone
+/// `\r`, which we don't support: clients and ark disagree on the lines that follow it.
+/// Lone `\r`s only come from classic Mac OS files, and handling them here would mean
+/// scanning the whole document on every conversion to map client lines to tree-sitter
+/// rows.
 pub fn get_position_encoding_kind() -> tower_lsp::lsp_types::PositionEncodingKind {
     tower_lsp::lsp_types::PositionEncodingKind::UTF16
 }
@@ -53,6 +61,8 @@ pub fn convert_tree_sitter_range_to_lsp_range(
 }
 
 pub fn convert_position_to_point(x: &Rope, position: Position) -> Point {
+    debug_check_invariant(|| check_position(x, position));
+
     let line = position.line as usize;
     let character = position.character as usize;
 
@@ -62,6 +72,8 @@ pub fn convert_position_to_point(x: &Rope, position: Position) -> Point {
 }
 
 pub fn convert_point_to_position(x: &Rope, point: Point) -> Position {
+    debug_check_invariant(|| check_point(x, point));
+
     let line = point.row;
     let character = point.column;
 
@@ -102,6 +114,94 @@ where
     f(x, character)
 }
 
+/// Checks that a UTF-8 based `point` refers to a character boundary of `x`.
+///
+/// The row must be a line of `x` and the column must be at most the length of
+/// that line, excluding the `\n`. Like tree-sitter, we don't consider `\r` to
+/// be part of the line ending, so the column may be past the `\r` of a `\r\n`.
+pub(crate) fn check_point(x: &Rope, point: Point) -> anyhow::Result<()> {
+    let line = line_contents(x, point.row)?;
+
+    if point.column > line.len() {
+        return Err(anyhow!(
+            "Point {point} is beyond the end of line '{line}' ({n} bytes)",
+            n = line.len()
+        ));
+    }
+    if !line.is_char_boundary(point.column) {
+        return Err(anyhow!(
+            "Point {point} is inside a character of line '{line}'"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that a UTF-16 based `position` refers to a character boundary of `x`.
+///
+/// Same as `check_point()`, and the character offset must not fall between the
+/// two halves of a surrogate pair, e.g. in the middle of an emoji.
+pub(crate) fn check_position(x: &Rope, position: Position) -> anyhow::Result<()> {
+    let line = line_contents(x, position.line as usize)?;
+    let character = position.character as usize;
+
+    let mut n = 0;
+    for char in line.chars() {
+        if n >= character {
+            break;
+        }
+        n += char.len_utf16();
+    }
+
+    if n < character {
+        return Err(anyhow!(
+            "Position {line_number}:{character} is beyond the end of line '{line}' ({n} UTF-16 code units)",
+            line_number = position.line
+        ));
+    }
+    if n > character {
+        return Err(anyhow!(
+            "Position {line_number}:{character} is inside a surrogate pair of line '{line}'",
+            line_number = position.line
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns line `line` of `x` without its `\n`
+fn line_contents(x: &Rope, line: usize) -> anyhow::Result<String> {
+    let Some(contents) = x.get_line(line) else {
+        return Err(anyhow!(
+            "Line {line} is out of bounds, the document has {n} lines",
+            n = x.len_lines()
+        ));
+    };
+
+    let mut contents = contents.to_string();
+    if contents.ends_with('\n') {
+        contents.pop();
+    }
+
+    Ok(contents)
+}
+
+/// Runs an invariant check in debug builds only, logging violations along with
+/// a backtrace so we can learn where the offending position comes from
+fn debug_check_invariant<F>(check: F)
+where
+    F: FnOnce() -> anyhow::Result<()>,
+{
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    if let Err(err) = check() {
+        let trace = std::backtrace::Backtrace::force_capture();
+        log::error!("Position invariant violated: {err}\n\nBacktrace:\n{trace}");
+    }
+}
+
 /// Converts a character offset into a particular line from UTF-16 to UTF-8
 fn convert_character_from_utf16_to_utf8(x: &str, character: usize) -> usize {
     if x.is_ascii() {
@@ -156,3 +256,179 @@ fn convert_character_from_utf8_to_utf16(x: &str, character: usize) -> usize {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+    use ropey::Rope;
+    use tower_lsp::lsp_types::Position;
+    use tree_sitter::Point;
+
+    use crate::lsp::encoding::check_point;
+    use crate::lsp::encoding::check_position;
+    use crate::lsp::encoding::convert_point_to_position;
+    use crate::lsp::encoding::convert_position_to_point;
+    use crate::lsp::encoding::convert_tree_sitter_range_to_lsp_range;
+    use crate::lsp::traits::rope::RopeExt;
+
+    /// Documents mixing ASCII, 2 and 3 byte characters, emojis (surrogate pairs
+    /// in UTF-16), and line breaks, including `\r\n` and lone `\r`
+    fn document() -> impl Strategy<Value = String> {
+        let char = prop::sample::select(vec![
+            'a', 'b', ' ', '(', 'é', 'ß', '中', '€', '😀', '🎉', '\u{2028}', '\n', '\r',
+        ]);
+        prop::collection::vec(char, 0..200).prop_map(|chars| chars.into_iter().collect::<String>())
+    }
+
+    /// A valid UTF-8 point of `x`, picked with `row` and `column` indices
+    fn point(x: &Rope, row: Index, column: Index) -> Point {
+        let row = row.index(x.len_lines());
+        let line = x.line(row).to_string();
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+
+        // Any character boundary, including the end of the line
+        let boundaries: Vec<usize> = line
+            .char_indices()
+            .map(|(pos, _)| pos)
+            .chain(std::iter::once(line.len()))
+            .collect();
+
+        Point::new(row, boundaries[column.index(boundaries.len())])
+    }
+
+    proptest! {
+        #[test]
+        fn test_point_position_round_trip(text in document(), row: Index, column: Index) {
+            let x = Rope::from_str(&text);
+            let point = point(&x, row, column);
+            prop_assert!(check_point(&x, point).is_ok());
+
+            let position = convert_point_to_position(&x, point);
+            prop_assert!(check_position(&x, position).is_ok(), "{position:?}");
+            prop_assert_eq!(position.line as usize, point.row);
+
+            prop_assert_eq!(convert_position_to_point(&x, position), point);
+        }
+
+        #[test]
+        fn test_point_byte_round_trip(text in document(), row: Index, column: Index) {
+            let x = Rope::from_str(&text);
+            let point = point(&x, row, column);
+
+            let byte = x.point_to_byte(point);
+            prop_assert!(byte <= x.len_bytes());
+            prop_assert!(text.is_char_boundary(byte));
+
+            // Rows must agree with tree-sitter, which only breaks lines on `\n`
+            let row = text[..byte].matches('\n').count();
+            prop_assert_eq!(row, point.row);
+            prop_assert_eq!(x.byte_to_line(byte), point.row);
+            prop_assert_eq!(byte - x.line_to_byte(point.row), point.column);
+        }
+
+        #[test]
+        fn test_range_conversion_preserves_order(
+            text in document(),
+            rows: (Index, Index),
+            columns: (Index, Index),
+        ) {
+            let x = Rope::from_str(&text);
+            let mut start = point(&x, rows.0, columns.0);
+            let mut end = point(&x, rows.1, columns.1);
+            if end < start {
+                std::mem::swap(&mut start, &mut end);
+            }
+
+            let range = tree_sitter::Range {
+                start_byte: x.point_to_byte(start),
+                end_byte: x.point_to_byte(end),
+                start_point: start,
+                end_point: end,
+            };
+            let range = convert_tree_sitter_range_to_lsp_range(&x, range);
+
+            prop_assert!(range.start <= range.end);
+            prop_assert_eq!(convert_position_to_point(&x, range.start), start);
+            prop_assert_eq!(convert_position_to_point(&x, range.end), end);
+        }
+    }
+
+    #[test]
+    fn test_check_point() {
+        let x = Rope::from_str("aé\n😀\n");
+
+        assert!(check_point(&x, Point::new(0, 0)).is_ok());
+        assert!(check_point(&x, Point::new(0, 3)).is_ok());
+        assert!(check_point(&x, Point::new(2, 0)).is_ok());
+
+        // Inside `é`
+        assert!(check_point(&x, Point::new(0, 2)).is_err());
+        // On the line ending
+        assert!(check_point(&x, Point::new(0, 4)).is_err());
+        // Inside the emoji
+        assert!(check_point(&x, Point::new(1, 1)).is_err());
+        // Beyond the last line
+        assert!(check_point(&x, Point::new(3, 0)).is_err());
+    }
+
+    #[test]
+    fn test_check_position() {
+        let x = Rope::from_str("aé\n😀\n");
+
+        assert!(check_position(&x, Position::new(0, 2)).is_ok());
+        assert!(check_position(&x, Position::new(1, 0)).is_ok());
+        assert!(check_position(&x, Position::new(1, 2)).is_ok());
+
+        // Between the two halves of the emoji's surrogate pair
+        assert!(check_position(&x, Position::new(1, 1)).is_err());
+        // On the line ending
+        assert!(check_position(&x, Position::new(0, 3)).is_err());
+        // Beyond the last line
+        assert!(check_position(&x, Position::new(3, 0)).is_err());
+    }
+
+    #[test]
+    fn test_lone_carriage_returns_are_not_supported() {
+        // Clients see `b` on line 1 and `c` on line 2. We don't break lines on
+        // a lone `\r`, see `get_position_encoding_kind()`.
+        let x = Rope::from_str("a\rb\nc");
+
+        // `b` is reported on line 0
+        assert_eq!(
+            convert_point_to_position(&x, Point::new(0, 2)),
+            Position::new(0, 2)
+        );
+
+        // The client's `b` is our `c`
+        assert_eq!(
+            convert_position_to_point(&x, Position::new(1, 0)),
+            Point::new(1, 0)
+        );
+        assert_eq!(x.point_to_byte(Point::new(1, 0)), "a\rb\n".len());
+
+        // The client's `c` is beyond the end of the document
+        assert!(check_position(&x, Position::new(2, 0)).is_err());
+    }
+
+    #[test]
+    fn test_unicode_line_separators_are_not_line_breaks() {
+        // Tree-sitter only breaks lines on `\n`. The rope must agree or rows
+        // would be off by one after a `\u{2028}`.
+        let x = Rope::from_str("a\u{2028}b\nc");
+        assert_eq!(x.len_lines(), 2);
+        assert_eq!(x.point_to_byte(Point::new(1, 0)), "a\u{2028}b\n".len());
+
+        // Same for a lone `\r`
+        let x = Rope::from_str("a\rb\r\nc");
+        assert_eq!(x.len_lines(), 2);
+        assert_eq!(x.point_to_byte(Point::new(1, 0)), "a\rb\r\n".len());
+
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_r::LANGUAGE.into())
+            .unwrap();
+        let tree = parser.parse("a\rb\r\nc", None).unwrap();
+        assert_eq!(tree.root_node().end_position(), Point::new(1, 1));
+    }
+}
@@ -11,6 +11,10 @@ use stdext::event::Event;
 #[derive(Default)]
 pub struct Events {
     pub console_prompt: Event<()>,
+
+    /// Emitted before `console_prompt` when the previous request was
+    /// interrupted. Comms should resync their state with R.
+    pub console_interrupted: Event<()>,
 }
 
 pub static EVENTS: Lazy<Events> = Lazy::new(|| Events::default());
@@ -0,0 +1,186 @@
+//
+// folding_range.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use ropey::Rope;
+use tower_lsp::lsp_types::FoldingRange;
+use tower_lsp::lsp_types::FoldingRangeKind;
+use tree_sitter::Node;
+
+use crate::lsp::documents::Document;
+use crate::lsp::symbols::parse_comment_as_section;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::NodeTypeExt;
+
+/// Folding ranges of a document:
+///
+/// - Braced blocks, including function bodies. The closing brace is left
+///   visible.
+/// - Function definitions whose body isn't braced but spans several lines.
+/// - Sections delimited by comments like `# Title ----`. A section extends to
+///   the next section of the same or a higher level, or to the end of the
+///   block it belongs to.
+pub(crate) fn folding_ranges(document: &Document) -> anyhow::Result<Vec<FoldingRange>> {
+    let contents = &document.contents;
+    let root = document.ast.root_node();
+
+    let mut ranges = Vec::new();
+    collect(root, contents, &mut ranges)?;
+
+    // The program block extends to the last line of the document, not
+    // counting the empty line after a trailing newline
+    let mut last_line = contents.len_lines().saturating_sub(1);
+    if last_line > 0 && contents.line(last_line).len_chars() == 0 {
+        last_line -= 1;
+    }
+    collect_sections(root, last_line, contents, &mut ranges)?;
+
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    Ok(ranges)
+}
+
+fn collect(node: Node, contents: &Rope, ranges: &mut Vec<FoldingRange>) -> anyhow::Result<()> {
+    let start = node.start_position().row;
+    let end = node.end_position().row;
+
+    if node.is_braced_expression() {
+        push(ranges, start, end.saturating_sub(1), None);
+
+        // The block is folded up to its closing brace
+        collect_sections(node, end.saturating_sub(1), contents, ranges)?;
+    }
+
+    if node.is_function_definition() {
+        let is_braced = node
+            .child_by_field_name("body")
+            .is_some_and(|body| body.is_braced_expression());
+        if !is_braced {
+            push(ranges, start, end, None);
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect(child, contents, ranges)?;
+    }
+
+    Ok(())
+}
+
+/// Sections of the comments directly inside `block`, which ends on `last_line`
+fn collect_sections(
+    block: Node,
+    last_line: usize,
+    contents: &Rope,
+    ranges: &mut Vec<FoldingRange>,
+) -> anyhow::Result<()> {
+    // Sections still open, with their level and first line
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+
+    let mut cursor = block.walk();
+    for child in block.children(&mut cursor) {
+        if !child.is_comment() {
+            continue;
+        }
+
+        let comment = contents.node_slice(&child)?.to_string();
+        let Some((level, _title)) = parse_comment_as_section(&comment) else {
+            continue;
+        };
+
+        let line = child.start_position().row;
+        while let Some(&(open_level, open_line)) = stack.last() {
+            if open_level < level {
+                break;
+            }
+            push(
+                ranges,
+                open_line,
+                line.saturating_sub(1),
+                Some(FoldingRangeKind::Region),
+            );
+            stack.pop();
+        }
+
+        stack.push((level, line));
+    }
+
+    for (_level, open_line) in stack {
+        push(ranges, open_line, last_line, Some(FoldingRangeKind::Region));
+    }
+
+    Ok(())
+}
+
+/// Only ranges that hide at least one line are pushed
+fn push(
+    ranges: &mut Vec<FoldingRange>,
+    start_line: usize,
+    end_line: usize,
+    kind: Option<FoldingRangeKind>,
+) {
+    if end_line <= start_line {
+        return;
+    }
+
+    ranges.push(FoldingRange {
+        start_line: start_line as u32,
+        end_line: end_line as u32,
+        kind,
+        ..Default::default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::FoldingRangeKind;
+
+    use crate::lsp::documents::Document;
+    use crate::lsp::folding_range::folding_ranges;
+
+    /// Ranges as `(start_line, end_line, is_section)`
+    fn ranges(code: &str) -> Vec<(u32, u32, bool)> {
+        let document = Document::new(code, None);
+        folding_ranges(&document)
+            .unwrap()
+            .into_iter()
+            .map(|range| {
+                let is_section = range.kind == Some(FoldingRangeKind::Region);
+                (range.start_line, range.end_line, is_section)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_folding_braces() {
+        let code = "f <- function(x) {\n  if (x) {\n    1\n  }\n}\n";
+        assert_eq!(ranges(code), vec![(0, 3, false), (1, 2, false)]);
+
+        // Blocks on a single line or on two lines don't hide anything
+        assert_eq!(ranges("{ 1 }\n{\n}\n"), vec![]);
+    }
+
+    #[test]
+    fn test_folding_unbraced_function() {
+        let code = "f <- function(x)\n  x +\n    1\n";
+        assert_eq!(ranges(code), vec![(0, 2, false)]);
+    }
+
+    #[test]
+    fn test_folding_sections() {
+        let code = "# One ----\na\n## Two ----\nb\n# Three ----\nc\n";
+        assert_eq!(
+            ranges(code),
+            vec![(0, 3, true), (2, 3, true), (4, 5, true),]
+        );
+    }
+
+    #[test]
+    fn test_folding_sections_in_blocks() {
+        let code = "{\n  # Inner ----\n  a\n  b\n}\n# Outer ----\n";
+        assert_eq!(ranges(code), vec![(0, 3, false), (1, 3, true)]);
+    }
+}
@@ -0,0 +1,684 @@
+//
+// formatting.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Native formatter for R code, backing the `textDocument/formatting` and
+// `textDocument/rangeFormatting` requests so that formatting doesn't require
+// styler in the R session. The formatter reflows the tokens of the syntax tree:
+//
+// - Lines are reindented according to the nesting of braces, calls, and
+//   operator chains.
+// - Spacing around operators, commas, and keywords follows the tidyverse
+//   style guide.
+// - Line breaks of the user are kept, with at most one blank line in a row.
+//   Calls that don't fit on a line are broken with one argument per line.
+//
+// Code with syntax errors is left untouched.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use tree_sitter::Node;
+
+use crate::lsp::config::IndentationConfig;
+use crate::lsp::documents::Document;
+use crate::lsp::indent::new_line_indent;
+use crate::lsp::offset::ArkPoint;
+use crate::lsp::offset::ArkRange;
+use crate::lsp::offset::ArkTextEdit;
+use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+
+/// Lines longer than this are broken at the arguments of calls
+const LINE_WIDTH: usize = 80;
+
+/// Format the whole document. Returns `None` if there is nothing to change.
+pub fn format_document(doc: &Document) -> anyhow::Result<Option<Vec<ArkTextEdit>>> {
+    let source = doc.contents.to_string();
+    let root = doc.ast.root_node();
+
+    if root.has_error() {
+        log::trace!("Not formatting document with syntax errors");
+        return Ok(None);
+    }
+
+    let nodes = children(&root);
+    if nodes.is_empty() {
+        return Ok(None);
+    }
+
+    let Some(mut formatted) = format_nodes(&source, &doc.config.indent, &nodes) else {
+        return Ok(None);
+    };
+    formatted.push('\n');
+
+    Ok(edit(doc, 0, source.len(), &formatted))
+}
+
+/// Format the top-level expressions that overlap with `range`
+pub fn format_range(doc: &Document, range: ArkRange) -> anyhow::Result<Option<Vec<ArkTextEdit>>> {
+    let source = doc.contents.to_string();
+    let root = doc.ast.root_node();
+
+    let start = point_to_byte(doc, range.start);
+    let end = point_to_byte(doc, range.end);
+
+    let nodes: Vec<Node> = children(&root)
+        .into_iter()
+        .filter(|node| node.end_byte() > start && node.start_byte() < end)
+        .collect();
+
+    let (Some(first), Some(last)) = (nodes.first(), nodes.last()) else {
+        return Ok(None);
+    };
+
+    if nodes.iter().any(|node| node.has_error()) {
+        log::trace!("Not formatting range with syntax errors");
+        return Ok(None);
+    }
+
+    // Reindent the first line too if the expression starts it
+    let mut start = first.start_byte();
+    let line_start = doc.contents.line_to_byte(first.start_position().row);
+    if source[line_start..start]
+        .chars()
+        .all(|c| c == ' ' || c == '\t')
+    {
+        start = line_start;
+    }
+
+    let Some(formatted) = format_nodes(&source, &doc.config.indent, &nodes) else {
+        return Ok(None);
+    };
+
+    Ok(edit(doc, start, last.end_byte(), &formatted))
+}
+
+/// Format a sequence of top-level expressions. Returns `None` if the
+/// formatted code isn't equivalent to the source, which would be a bug of the
+/// formatter.
+fn format_nodes(source: &str, config: &IndentationConfig, nodes: &[Node]) -> Option<String> {
+    let mut candidates = vec![];
+    for node in nodes {
+        collect_breakable(*node, &mut candidates);
+    }
+
+    let mut exploded = HashSet::new();
+
+    let mut formatted = loop {
+        let mut formatter = Formatter::new(source, config, &exploded);
+        formatter.format_nodes(nodes);
+
+        let Some(node) = formatter.next_to_explode(&candidates) else {
+            break formatter.output;
+        };
+        exploded.insert(node);
+    };
+
+    let len = formatted.trim_end().len();
+    formatted.truncate(len);
+
+    let expected: Vec<(&str, String)> = nodes
+        .iter()
+        .flat_map(|node| tokens(*node, source))
+        .collect();
+
+    let doc = Document::new(&formatted, None);
+    if doc.ast.root_node().has_error() {
+        log::error!("Formatting would introduce syntax errors, leaving the code as is");
+        return None;
+    }
+
+    let actual: Vec<(&str, String)> = children(&doc.ast.root_node())
+        .into_iter()
+        .flat_map(|node| tokens(node, &formatted))
+        .collect();
+
+    if expected != actual {
+        log::error!("Formatting would change the meaning of the code, leaving it as is");
+        return None;
+    }
+
+    Some(formatted)
+}
+
+struct Formatter<'a, 'tree> {
+    source: &'a str,
+    config: &'a IndentationConfig,
+
+    /// Start bytes of the arguments and parameters that are broken with one
+    /// element per line
+    exploded: &'a HashSet<usize>,
+
+    output: String,
+
+    /// Current line of the output and indentation level of each line
+    line: usize,
+    line_levels: Vec<usize>,
+
+    /// Output line of each token, by start byte in the source
+    token_lines: HashMap<usize, usize>,
+
+    prev: Option<Node<'tree>>,
+    prev_end: usize,
+    at_line_start: bool,
+    force_break: bool,
+}
+
+impl<'a, 'tree> Formatter<'a, 'tree> {
+    fn new(source: &'a str, config: &'a IndentationConfig, exploded: &'a HashSet<usize>) -> Self {
+        Self {
+            source,
+            config,
+            exploded,
+            output: String::new(),
+            line: 0,
+            line_levels: vec![0],
+            token_lines: HashMap::new(),
+            prev: None,
+            prev_end: 0,
+            at_line_start: true,
+            force_break: false,
+        }
+    }
+
+    fn format_nodes(&mut self, nodes: &[Node<'tree>]) {
+        if let Some(first) = nodes.first() {
+            self.prev_end = first.start_byte();
+        }
+        for node in nodes {
+            self.format(*node, 0);
+        }
+    }
+
+    /// Format `node`. Tokens that start a line are indented by `level`,
+    /// unless the node has its own rules.
+    fn format(&mut self, node: Node<'tree>, level: usize) {
+        if is_token(&node) {
+            return self.token(node, level);
+        }
+
+        match node.node_type() {
+            NodeType::BracedExpression |
+            NodeType::ParenthesizedExpression |
+            NodeType::Arguments |
+            NodeType::Parameters => self.delimited(node, level),
+
+            NodeType::BinaryOperator(_)
+                if !node.parent().is_some_and(|x| x.is_binary_operator()) =>
+            {
+                self.chain(node, level)
+            },
+
+            NodeType::IfStatement |
+            NodeType::ForStatement |
+            NodeType::WhileStatement |
+            NodeType::RepeatStatement |
+            NodeType::FunctionDefinition => self.statement(node, level),
+
+            _ => {
+                for child in children(&node) {
+                    self.format(child, level);
+                }
+            },
+        }
+    }
+
+    /// Braces, parentheses, and brackets. The contents are indented one level
+    /// deeper than the line of the opening delimiter, and the closing
+    /// delimiter is aligned with that line.
+    fn delimited(&mut self, node: Node<'tree>, level: usize) {
+        let exploded = self.exploded.contains(&node.start_byte());
+        let children = children(&node);
+        let n = children.len();
+        let mut base = level;
+
+        for (i, child) in children.into_iter().enumerate() {
+            if i == 0 && is_opener(&child) {
+                self.format(child, level);
+                base = self.level();
+                continue;
+            }
+
+            if i == n - 1 && is_closer(&child) {
+                self.force_break |= exploded;
+                self.format(child, base);
+                continue;
+            }
+
+            if exploded && !matches!(child.node_type(), NodeType::Comma | NodeType::Comment) {
+                self.force_break = true;
+            }
+            self.format(child, base + 1);
+        }
+    }
+
+    /// Chains of binary operators, e.g. pipelines. Continuation lines are
+    /// indented one level deeper than the first line, without staircase.
+    fn chain(&mut self, node: Node<'tree>, level: usize) {
+        self.line_break(first_token(node), level);
+        let continuation = self.level() + 1;
+
+        for child in children(&node) {
+            self.format(child, continuation);
+        }
+    }
+
+    /// Control flow and function definitions. Bodies that aren't braced are
+    /// indented when they start a line.
+    fn statement(&mut self, node: Node<'tree>, level: usize) {
+        let mut cursor = node.walk();
+        let mut children = vec![];
+        if cursor.goto_first_child() {
+            loop {
+                children.push((cursor.node(), cursor.field_name()));
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+
+        let mut base = level;
+
+        for (i, (child, field)) in children.into_iter().enumerate() {
+            if i == 0 {
+                self.format(child, level);
+                base = self.level();
+                continue;
+            }
+
+            let level = match field {
+                Some("body" | "consequence" | "alternative") => {
+                    if child.is_braced_expression() {
+                        base
+                    } else {
+                        base + 1
+                    }
+                },
+                _ if !child.is_named() => base,
+                _ => base + 1,
+            };
+            self.format(child, level);
+        }
+    }
+
+    fn token(&mut self, node: Node<'tree>, level: usize) {
+        let broke = self.line_break(node, level);
+
+        if !broke && !self.at_line_start {
+            if let Some(prev) = self.prev {
+                if needs_space(&prev, &node) {
+                    self.output.push(' ');
+                }
+            }
+        }
+
+        self.output
+            .push_str(&self.source[node.start_byte()..node.end_byte()]);
+        self.token_lines.insert(node.start_byte(), self.line);
+
+        // Lines of multiline strings are taken verbatim
+        self.line += node.end_position().row - node.start_position().row;
+        let level = self.level();
+        self.line_levels.resize(self.line + 1, level);
+
+        self.prev = Some(node);
+        self.prev_end = node.end_byte();
+        self.at_line_start = false;
+    }
+
+    /// Start a new line before `node` if the source has one or if a break is
+    /// forced. Returns whether a line was started.
+    fn line_break(&mut self, node: Node<'tree>, level: usize) -> bool {
+        let gap = &self.source[self.prev_end..node.start_byte()];
+        let newlines = gap.matches('\n').count();
+
+        // Semicolons are not part of the tree
+        if gap.contains(';') {
+            self.output.push(';');
+        }
+        self.prev_end = node.start_byte();
+
+        let force = std::mem::take(&mut self.force_break);
+
+        if self.prev.is_none() || self.at_line_start || (newlines == 0 && !force) {
+            return false;
+        }
+
+        // Trailing whitespace
+        let len = self.output.trim_end_matches([' ', '\t']).len();
+        self.output.truncate(len);
+
+        // Keep at most one blank line
+        let newlines = newlines.clamp(1, 2);
+        for _ in 0..newlines {
+            self.output.push('\n');
+            self.line += 1;
+            self.line_levels.push(0);
+        }
+        self.line_levels[self.line] = level;
+
+        let indent = new_line_indent(self.config, level * self.config.indent_size);
+        self.output.push_str(&indent);
+
+        self.at_line_start = true;
+        true
+    }
+
+    /// Indentation level of the current line
+    fn level(&self) -> usize {
+        self.line_levels[self.line]
+    }
+
+    /// The outermost arguments or parameters on the first line that is too
+    /// long, if they can be broken
+    fn next_to_explode(&self, candidates: &[Node]) -> Option<usize> {
+        for (line, text) in self.output.lines().enumerate() {
+            if self.width(text) <= LINE_WIDTH {
+                continue;
+            }
+
+            let on_line = |node: Option<Node>| {
+                node.and_then(|node| self.token_lines.get(&node.start_byte()).copied()) ==
+                    Some(line)
+            };
+
+            let candidate = candidates
+                .iter()
+                .filter(|node| !self.exploded.contains(&node.start_byte()))
+                .filter(|node| {
+                    on_line(node.child(0)) && on_line(node.child(node.child_count() - 1))
+                })
+                .min_by_key(|node| node.start_byte());
+
+            if let Some(candidate) = candidate {
+                return Some(candidate.start_byte());
+            }
+        }
+
+        None
+    }
+
+    fn width(&self, line: &str) -> usize {
+        line.chars()
+            .map(|c| if c == '\t' { self.config.tab_width } else { 1 })
+            .sum()
+    }
+}
+
+fn children<'tree>(node: &Node<'tree>) -> Vec<Node<'tree>> {
+    let mut cursor = node.walk();
+    node.children(&mut cursor).collect()
+}
+
+/// Nodes formatted as a whole
+fn is_token(node: &Node) -> bool {
+    node.child_count() == 0 || matches!(node.node_type(), NodeType::String | NodeType::Comment)
+}
+
+fn first_token(node: Node) -> Node {
+    let mut node = node;
+    while !is_token(&node) {
+        match node.child(0) {
+            Some(child) => node = child,
+            None => break,
+        }
+    }
+    node
+}
+
+fn is_opener(node: &Node) -> bool {
+    matches!(node.kind(), "(" | "{" | "[" | "[[")
+}
+
+fn is_closer(node: &Node) -> bool {
+    matches!(node.kind(), ")" | "}" | "]" | "]]")
+}
+
+/// Arguments and parameters with at least one element
+fn collect_breakable<'tree>(node: Node<'tree>, out: &mut Vec<Node<'tree>>) {
+    if matches!(node.node_type(), NodeType::Arguments | NodeType::Parameters) &&
+        children(&node)
+            .iter()
+            .any(|child| matches!(child.node_type(), NodeType::Argument | NodeType::Parameter))
+    {
+        out.push(node);
+    }
+
+    for child in children(&node) {
+        collect_breakable(child, out);
+    }
+}
+
+/// The type of the operator node of which `node` is the operator token
+fn operator_of(node: &Node) -> Option<NodeType> {
+    let parent = node.parent()?;
+    if parent.child_by_field_name("operator") != Some(*node) {
+        return None;
+    }
+    Some(parent.node_type())
+}
+
+fn needs_space(prev: &Node, next: &Node) -> bool {
+    let prev_kind = prev.kind();
+    let next_kind = next.kind();
+
+    if next.node_type() == NodeType::Comment {
+        return true;
+    }
+    if matches!(prev_kind, "(" | "[" | "[[") {
+        return false;
+    }
+    if matches!(next_kind, ")" | "]" | "]]" | "comma" | ";") {
+        return false;
+    }
+    if matches!(prev_kind, "comma" | ";") {
+        return true;
+    }
+
+    match operator_of(prev) {
+        Some(NodeType::UnaryOperator(_)) => return false,
+        Some(NodeType::ExtractOperator(_) | NodeType::NamespaceOperator(_)) => return false,
+        Some(NodeType::BinaryOperator(op)) => return !is_tight(op),
+        _ => {},
+    }
+    match operator_of(next) {
+        Some(NodeType::ExtractOperator(_) | NodeType::NamespaceOperator(_)) => return false,
+        Some(NodeType::BinaryOperator(op)) => return !is_tight(op),
+        _ => {},
+    }
+
+    match next_kind {
+        // Calls and function definitions, but not `if (`
+        "(" => !next
+            .parent()
+            .is_some_and(|parent| matches!(parent.kind(), "arguments" | "parameters")),
+        "[" | "[[" => false,
+        "}" => prev_kind != "{",
+        _ => true,
+    }
+}
+
+/// Operators written without surrounding spaces
+fn is_tight(op: BinaryOperatorType) -> bool {
+    matches!(
+        op,
+        BinaryOperatorType::Exponentiate | BinaryOperatorType::Colon
+    )
+}
+
+/// Kind and text of the tokens of `node`, to check that formatting only
+/// changed whitespace
+fn tokens(node: Node, source: &str) -> Vec<(&'static str, String)> {
+    if is_token(&node) {
+        let text = source[node.start_byte()..node.end_byte()].trim_end();
+        return vec![(node.kind(), String::from(text))];
+    }
+
+    children(&node)
+        .into_iter()
+        .flat_map(|child| tokens(child, source))
+        .collect()
+}
+
+fn point_to_byte(doc: &Document, point: ArkPoint) -> usize {
+    if point.row >= doc.contents.len_lines() {
+        return doc.contents.len_bytes();
+    }
+    let byte = doc.contents.line_to_byte(point.row) + point.column;
+    byte.min(doc.contents.len_bytes())
+}
+
+fn byte_to_point(doc: &Document, byte: usize) -> ArkPoint {
+    let row = doc.contents.byte_to_line(byte);
+    let column = byte - doc.contents.line_to_byte(row);
+    ArkPoint { row, column }
+}
+
+/// Edit replacing the source between `start` and `end` by `formatted`,
+/// reduced to the part that actually changes
+fn edit(doc: &Document, start: usize, end: usize, formatted: &str) -> Option<Vec<ArkTextEdit>> {
+    let old = doc.contents.byte_slice(start..end).to_string();
+
+    if old == formatted {
+        return None;
+    }
+
+    let prefix: usize = old
+        .chars()
+        .zip(formatted.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(formatted[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+
+    let range = ArkRange {
+        start: byte_to_point(doc, start + prefix),
+        end: byte_to_point(doc, end - suffix),
+    };
+    let new_text = String::from(&formatted[prefix..formatted.len() - suffix]);
+
+    Some(vec![ArkTextEdit { range, new_text }])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::config::IndentStyle;
+    use crate::lsp::config::IndentationConfig;
+    use crate::lsp::documents::Document;
+    use crate::lsp::formatting::format_document;
+    use crate::lsp::formatting::format_range;
+    use crate::lsp::offset::apply_text_edits;
+    use crate::lsp::offset::ArkPoint;
+    use crate::lsp::offset::ArkRange;
+
+    fn format(text: &str) -> String {
+        let mut doc = Document::new(text, None);
+        doc.config.indent = IndentationConfig {
+            indent_style: IndentStyle::Space,
+            indent_size: 2,
+            tab_width: 2,
+        };
+
+        let mut text = String::from(text);
+        if let Some(edits) = format_document(&doc).unwrap() {
+            apply_text_edits(edits, &mut text).unwrap();
+        }
+        text
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        assert_eq!(format("x<-1+2"), "x <- 1 + 2\n");
+        assert_eq!(format("f( a,b =1 )"), "f(a, b = 1)\n");
+        assert_eq!(format("x [1] ;y$z"), "x[1]; y$z\n");
+        assert_eq!(format("-x^2 + 1:10"), "-x^2 + 1:10\n");
+        assert_eq!(format("base :: paste0(!a)"), "base::paste0(!a)\n");
+        assert_eq!(format("if(x) y else z"), "if (x) y else z\n");
+        assert_eq!(
+            format("f <- function (x) { x }"),
+            "f <- function(x) { x }\n"
+        );
+        assert_eq!(format("\\(x)x"), "\\(x) x\n");
+        assert_eq!(format("y~x"), "y ~ x\n");
+    }
+
+    #[test]
+    fn test_format_indentation() {
+        assert_eq!(
+            format("f <- function(x) {\nif (x) {\n      1\n} else {\n2\n}\n}"),
+            "f <- function(x) {\n  if (x) {\n    1\n  } else {\n    2\n  }\n}\n"
+        );
+
+        // Pipelines are indented once, without staircase
+        assert_eq!(
+            format("x %>%\nfilter(a) %>%\n        mutate(\n  b = 1\n      )"),
+            "x %>%\n  filter(a) %>%\n  mutate(\n    b = 1\n  )\n"
+        );
+
+        // Function arguments are indented relative to the line of the call
+        assert_eq!(
+            format("lapply(x, function(y) {\n    y\n})"),
+            "lapply(x, function(y) {\n  y\n})\n"
+        );
+
+        // Bodies that aren't braced
+        assert_eq!(
+            format("for (i in x)\nprint(i)"),
+            "for (i in x)\n  print(i)\n"
+        );
+    }
+
+    #[test]
+    fn test_format_blank_lines_and_comments() {
+        assert_eq!(
+            format("# comment\nx <- 1 # trailing   \n\n\n\ny<-2\n\n"),
+            "# comment\nx <- 1 # trailing\n\ny <- 2\n"
+        );
+
+        // Strings are kept verbatim
+        assert_eq!(format("x <- 'a  \n   b'"), "x <- 'a  \n   b'\n");
+    }
+
+    #[test]
+    fn test_format_long_calls() {
+        let text =
+            "result <- some_function(first_argument = 1, second_argument = 2, third_argument = 3)";
+        assert_eq!(
+            format(text),
+            "result <- some_function(\n  first_argument = 1,\n  second_argument = 2,\n  third_argument = 3\n)\n"
+        );
+    }
+
+    #[test]
+    fn test_format_syntax_errors() {
+        let doc = Document::new("x <-<- 1 +", None);
+        assert!(format_document(&doc).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_format_range() {
+        let mut text = String::from("x<-1\ny<-2\nz<-3\n");
+        let doc = Document::new(&text, None);
+
+        let range = ArkRange {
+            start: ArkPoint { row: 1, column: 0 },
+            end: ArkPoint { row: 1, column: 2 },
+        };
+        let edits = format_range(&doc, range).unwrap().unwrap();
+        apply_text_edits(edits, &mut text).unwrap();
+
+        assert_eq!(text, "x<-1\ny <- 2\nz<-3\n");
+    }
+}
@@ -0,0 +1,298 @@
+//
+// generated.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use globset::Glob;
+use ropey::Rope;
+use url::Url;
+
+use crate::lsp;
+use crate::lsp::documents::Document;
+use crate::lsp::state::Workspace;
+use crate::lsp::state::WorldState;
+
+/// Name of the file that marks generated files in a workspace folder
+pub(crate) const GITATTRIBUTES_FILE_NAME: &str = ".gitattributes";
+
+/// Number of lines at the top of a file that are searched for a "Generated by"
+/// header
+const HEADER_LINES: usize = 5;
+
+/// Headers written by the tools that generate R files in packages
+const GENERATED_HEADERS: &[&str] = &[
+    "# Generated by roxygen2: do not edit by hand",
+    "# Generated by using Rcpp::compileAttributes() -> do not edit by hand",
+    "# Generated by cpp11: do not edit by hand",
+];
+
+/// Whether a document was generated by a tool, e.g. `R/RcppExports.R`.
+///
+/// Users can't fix the diagnostics of these files, and their edits would be
+/// overwritten anyway, so we suppress diagnostics and formatting edits for
+/// them. Navigation features stay active. A file is considered generated when:
+///
+/// - It is named `RcppExports.R`.
+/// - It starts with the header of roxygen2, Rcpp, or cpp11, like
+///   `# Generated by roxygen2: do not edit by hand`.
+/// - It matches one of the `positron.r.diagnostics.generatedFiles` globs.
+/// - It is marked as `linguist-generated` in the `.gitattributes` file of a
+///   workspace folder.
+pub(crate) fn is_generated(uri: &Url, document: &Document, state: &WorldState) -> bool {
+    let Ok(path) = uri.to_file_path() else {
+        // Untitled documents are never generated
+        return false;
+    };
+
+    if path.file_name().is_some_and(|name| name == "RcppExports.R") {
+        return true;
+    }
+
+    if has_generated_header(&document.contents) {
+        return true;
+    }
+
+    let folders: Vec<_> = state
+        .workspace
+        .folders
+        .iter()
+        .filter_map(|folder| folder.to_file_path().ok())
+        .collect();
+
+    let globs = &state.config.diagnostics.generated_files;
+    if matches_globs(&path, globs.iter().map(String::as_str), &folders) {
+        return true;
+    }
+
+    state.git_attributes.is_generated(&path)
+}
+
+fn has_generated_header(contents: &Rope) -> bool {
+    contents.lines().take(HEADER_LINES).any(|line| {
+        let line = line.to_string();
+        let line = line.trim_start();
+        GENERATED_HEADERS
+            .iter()
+            .any(|header| line.starts_with(header))
+    })
+}
+
+/// Match globs against the path relative to each workspace folder, or against
+/// the full path for files outside of the workspace
+pub(crate) fn matches_globs<'a>(
+    path: &Path,
+    globs: impl IntoIterator<Item = &'a str>,
+    folders: &[PathBuf],
+) -> bool {
+    let relative_paths: Vec<&Path> = folders
+        .iter()
+        .filter_map(|folder| path.strip_prefix(folder).ok())
+        .collect();
+
+    for glob in globs {
+        let matcher = match Glob::new(glob) {
+            Ok(glob) => glob.compile_matcher(),
+            Err(err) => {
+                lsp::log_warn!("Invalid glob '{glob}': {err}");
+                continue;
+            },
+        };
+
+        if matcher.is_match(path) || relative_paths.iter().any(|path| matcher.is_match(path)) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// The `linguist-generated` attributes of the `.gitattributes` files at the
+/// root of the workspace folders, which is how GitHub hides generated files
+/// from diffs. Loaded when the workspace is initialized and when one of these
+/// files changes, rather than read for each document.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub(crate) struct GitAttributes {
+    folders: Vec<FolderAttributes>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FolderAttributes {
+    folder: PathBuf,
+
+    /// Globs relative to the folder and whether they mark files as generated.
+    /// Later rules take precedence over earlier ones, as in git.
+    rules: Vec<(String, bool)>,
+}
+
+impl GitAttributes {
+    pub(crate) fn from_workspace(workspace: &Workspace) -> Self {
+        let folders = workspace
+            .folders
+            .iter()
+            .filter_map(|folder| folder.to_file_path().ok())
+            .filter_map(|folder| {
+                let attributes =
+                    std::fs::read_to_string(folder.join(GITATTRIBUTES_FILE_NAME)).ok()?;
+                Some(FolderAttributes {
+                    rules: parse_rules(&attributes),
+                    folder,
+                })
+            })
+            .collect();
+
+        Self { folders }
+    }
+
+    /// Whether `path` is marked as generated with the `linguist-generated`
+    /// attribute
+    fn is_generated(&self, path: &Path) -> bool {
+        self.folders.iter().any(|attributes| {
+            let Ok(relative) = path.strip_prefix(&attributes.folder) else {
+                return false;
+            };
+
+            let mut generated = false;
+            for (glob, value) in attributes.rules.iter() {
+                if matches_globs(relative, [glob.as_str()], &[]) {
+                    generated = *value;
+                }
+            }
+            generated
+        })
+    }
+}
+
+fn parse_rules(attributes: &str) -> Vec<(String, bool)> {
+    let mut rules = vec![];
+
+    for line in attributes.lines() {
+        let mut fields = line.split_whitespace();
+
+        let Some(pattern) = fields.next() else {
+            continue;
+        };
+        if pattern.starts_with('#') {
+            continue;
+        }
+
+        let value = fields.find_map(|attribute| match attribute {
+            "linguist-generated" | "linguist-generated=true" => Some(true),
+            "-linguist-generated" | "linguist-generated=false" => Some(false),
+            _ => None,
+        });
+        let Some(value) = value else {
+            continue;
+        };
+
+        // Patterns without a slash match files at any level
+        let glob = match pattern.strip_prefix('/') {
+            Some(pattern) => pattern.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{pattern}"),
+        };
+
+        rules.push((glob, value));
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::lsp::documents::Document;
+    use crate::lsp::generated::is_generated;
+    use crate::lsp::generated::GitAttributes;
+    use crate::lsp::state::WorldState;
+
+    fn file_uri(path: &std::path::Path) -> Url {
+        Url::from_file_path(path).unwrap()
+    }
+
+    #[test]
+    fn test_generated_files_by_name_and_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = WorldState::default();
+        let document = Document::new("foo <- function() 1\n", None);
+
+        let uri = file_uri(&dir.path().join("R").join("RcppExports.R"));
+        assert!(is_generated(&uri, &document, &state));
+
+        let uri = file_uri(&dir.path().join("R").join("foo.R"));
+        assert!(!is_generated(&uri, &document, &state));
+
+        let document = Document::new(
+            "# Generated by using Rcpp::compileAttributes() -> do not edit by hand\nfoo <- 1\n",
+            None,
+        );
+        assert!(is_generated(&uri, &document, &state));
+
+        let document = Document::new("# Generated by roxygen2: do not edit by hand\n", None);
+        assert!(is_generated(&uri, &document, &state));
+
+        // Other mentions of generation are not headers of known tools
+        let document = Document::new(
+            "# Generated by hand, do not edit by hand without asking\nfoo <- 1\n",
+            None,
+        );
+        assert!(!is_generated(&uri, &document, &state));
+
+        // Untitled documents are never generated
+        let uri = Url::parse("untitled:Untitled-1").unwrap();
+        assert!(!is_generated(&uri, &document, &state));
+    }
+
+    #[test]
+    fn test_generated_files_by_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = Document::new("foo <- 1\n", None);
+
+        let mut state = WorldState::default();
+        state.workspace.folders.push(file_uri(dir.path()));
+        state.config.diagnostics.generated_files = vec![String::from("R/generated-*.R")];
+
+        let uri = file_uri(&dir.path().join("R").join("generated-foo.R"));
+        assert!(is_generated(&uri, &document, &state));
+
+        let uri = file_uri(&dir.path().join("R").join("foo.R"));
+        assert!(!is_generated(&uri, &document, &state));
+    }
+
+    #[test]
+    fn test_generated_files_by_gitattributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = Document::new("foo <- 1\n", None);
+
+        std::fs::write(
+            dir.path().join(".gitattributes"),
+            "# Generated code\n*.gen.R linguist-generated\n/R/data.R linguist-generated=true\nkeep.gen.R -linguist-generated\n",
+        )
+        .unwrap();
+
+        let mut state = WorldState::default();
+        state.workspace.folders.push(file_uri(dir.path()));
+
+        // Attributes are read once for the workspace
+        let uri = file_uri(&dir.path().join("R").join("foo.gen.R"));
+        assert!(!is_generated(&uri, &document, &state));
+        state.git_attributes = GitAttributes::from_workspace(&state.workspace);
+
+        let uri = file_uri(&dir.path().join("R").join("foo.gen.R"));
+        assert!(is_generated(&uri, &document, &state));
+
+        let uri = file_uri(&dir.path().join("R").join("data.R"));
+        assert!(is_generated(&uri, &document, &state));
+
+        let uri = file_uri(&dir.path().join("R").join("keep.gen.R"));
+        assert!(!is_generated(&uri, &document, &state));
+
+        let uri = file_uri(&dir.path().join("R").join("foo.R"));
+        assert!(!is_generated(&uri, &document, &state));
+    }
+}
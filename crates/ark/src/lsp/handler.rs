@@ -15,12 +15,16 @@ use stdext::spawn;
 use tokio::runtime::Runtime;
 
 use super::backend;
+use super::session::LspSession;
 use crate::interface::KernelInfo;
 
 pub struct Lsp {
     runtime: Arc<Runtime>,
     kernel_init_rx: BusReader<KernelInfo>,
     kernel_initialized: bool,
+
+    /// Shared by all connections, created on first start
+    session: Option<Arc<LspSession>>,
 }
 
 impl Lsp {
@@ -29,6 +33,7 @@ impl Lsp {
             runtime: Arc::new(tokio::runtime::Runtime::new().unwrap()),
             kernel_init_rx,
             kernel_initialized: false,
+            session: None,
         }
     }
 }
@@ -52,12 +57,17 @@ impl ServerHandler for Lsp {
             self.kernel_initialized = true;
         }
 
-        // Retain ownership of the tokio `runtime` inside the `Lsp` to
-        // account for potential reconnects
+        // Retain ownership of the tokio `runtime` and of the session inside
+        // the `Lsp` to account for potential reconnects. The documents and
+        // the index of the session survive them.
         let runtime = self.runtime.clone();
+        let session = self
+            .session
+            .get_or_insert_with(|| Arc::new(LspSession::start(&runtime)))
+            .clone();
 
         spawn!("ark-lsp", move || {
-            backend::start_lsp(runtime, tcp_address, conn_init_tx)
+            backend::start_lsp(runtime, session, tcp_address, conn_init_tx)
         });
         return Ok(());
     }
@@ -5,32 +5,51 @@
 //
 //
 
+use std::time::Duration;
+
 use anyhow::anyhow;
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use serde_json::Value;
+use stdext::cancellation::CancellationToken;
 use stdext::unwrap;
 use struct_field_names_as_array::FieldNamesAsArray;
+use tower_lsp::lsp_types::request::GotoTypeDefinitionParams;
+use tower_lsp::lsp_types::request::GotoTypeDefinitionResponse;
+use tower_lsp::lsp_types::CodeActionParams;
+use tower_lsp::lsp_types::CodeActionResponse;
+use tower_lsp::lsp_types::CodeLens;
+use tower_lsp::lsp_types::CodeLensParams;
 use tower_lsp::lsp_types::CompletionItem;
+use tower_lsp::lsp_types::CompletionList;
 use tower_lsp::lsp_types::CompletionParams;
 use tower_lsp::lsp_types::CompletionResponse;
+use tower_lsp::lsp_types::DocumentFormattingParams;
 use tower_lsp::lsp_types::DocumentOnTypeFormattingParams;
+use tower_lsp::lsp_types::DocumentRangeFormattingParams;
 use tower_lsp::lsp_types::DocumentSymbolParams;
 use tower_lsp::lsp_types::DocumentSymbolResponse;
+use tower_lsp::lsp_types::ExecuteCommandParams;
+use tower_lsp::lsp_types::FoldingRange;
+use tower_lsp::lsp_types::FoldingRangeParams;
 use tower_lsp::lsp_types::GotoDefinitionParams;
 use tower_lsp::lsp_types::GotoDefinitionResponse;
 use tower_lsp::lsp_types::Hover;
 use tower_lsp::lsp_types::HoverContents;
 use tower_lsp::lsp_types::HoverParams;
 use tower_lsp::lsp_types::Location;
-use tower_lsp::lsp_types::MessageType;
+use tower_lsp::lsp_types::PrepareRenameResponse;
 use tower_lsp::lsp_types::ReferenceParams;
 use tower_lsp::lsp_types::Registration;
+use tower_lsp::lsp_types::RenameParams;
 use tower_lsp::lsp_types::SelectionRange;
 use tower_lsp::lsp_types::SelectionRangeParams;
+use tower_lsp::lsp_types::SemanticTokensParams;
+use tower_lsp::lsp_types::SemanticTokensResult;
 use tower_lsp::lsp_types::SignatureHelp;
 use tower_lsp::lsp_types::SignatureHelpParams;
 use tower_lsp::lsp_types::SymbolInformation;
+use tower_lsp::lsp_types::TextDocumentPositionParams;
 use tower_lsp::lsp_types::TextEdit;
 use tower_lsp::lsp_types::WorkspaceEdit;
 use tower_lsp::lsp_types::WorkspaceSymbolParams;
@@ -40,13 +59,24 @@ use tree_sitter::Point;
 
 use crate::analysis::input_boundaries::input_boundaries;
 use crate::lsp;
-use crate::lsp::completions::provide_completions;
+use crate::lsp::brackets::brackets;
+use crate::lsp::brackets::BracketsParams;
+use crate::lsp::brackets::BracketsResponse;
+use crate::lsp::code_action::code_actions;
+use crate::lsp::code_lens::code_lenses;
+use crate::lsp::commands;
+use crate::lsp::completions::provide_r_completions;
+use crate::lsp::completions::provide_static_completions;
 use crate::lsp::completions::resolve_completion;
 use crate::lsp::config::VscDiagnosticsConfig;
 use crate::lsp::config::VscDocumentConfig;
 use crate::lsp::definitions::goto_definition;
 use crate::lsp::document_context::DocumentContext;
 use crate::lsp::encoding::convert_position_to_point;
+use crate::lsp::folding_range::folding_ranges;
+use crate::lsp::formatting::format_document;
+use crate::lsp::formatting::format_range;
+use crate::lsp::generated;
 use crate::lsp::help_topic::help_topic;
 use crate::lsp::help_topic::HelpTopicParams;
 use crate::lsp::help_topic::HelpTopicResponse;
@@ -54,18 +84,25 @@ use crate::lsp::hover::r_hover;
 use crate::lsp::indent::indent_edit;
 use crate::lsp::input_boundaries::InputBoundariesParams;
 use crate::lsp::input_boundaries::InputBoundariesResponse;
-use crate::lsp::main_loop::LspState;
+use crate::lsp::main_loop::ClientCaps;
+use crate::lsp::offset::ArkRange;
 use crate::lsp::offset::IntoLspOffset;
+use crate::lsp::project_config;
 use crate::lsp::references::find_references;
+use crate::lsp::rename::prepare_rename;
+use crate::lsp::rename::rename;
+use crate::lsp::scheduler::r_request;
+use crate::lsp::scheduler::Priority;
 use crate::lsp::selection_range::convert_selection_range_from_tree_sitter_to_lsp;
 use crate::lsp::selection_range::selection_range;
+use crate::lsp::semantic_tokens::semantic_tokens;
 use crate::lsp::signature_help::r_signature_help;
 use crate::lsp::state::WorldState;
 use crate::lsp::statement_range::statement_range;
 use crate::lsp::statement_range::StatementRangeParams;
 use crate::lsp::statement_range::StatementRangeResponse;
 use crate::lsp::symbols;
-use crate::r_task;
+use crate::lsp::type_definitions::goto_type_definition;
 
 pub static ARK_VDOC_REQUEST: &'static str = "ark/internal/virtualDocument";
 
@@ -77,19 +114,23 @@ pub(crate) struct VirtualDocumentParams {
 
 pub(crate) type VirtualDocumentResponse = String;
 
+/// How long completion requests wait before querying R. A request superseded
+/// by the next keystroke during this delay never reaches the R thread.
+const COMPLETION_DEBOUNCE: Duration = Duration::from_millis(30);
+
 // Handlers that do not mutate the world state. They take a sharing reference or
 // a clone of the state.
 
 pub(crate) async fn handle_initialized(
     client: &Client,
-    lsp_state: &LspState,
+    needs_registration: &ClientCaps,
 ) -> anyhow::Result<()> {
     let span = tracing::info_span!("handle_initialized").entered();
 
     // Register capabilities to the client
     let mut regs: Vec<Registration> = vec![];
 
-    if lsp_state.needs_registration.did_change_configuration {
+    if needs_registration.did_change_configuration {
         // The `didChangeConfiguration` request instructs the client to send
         // a notification when the tracked settings have changed.
         //
@@ -109,6 +150,23 @@ pub(crate) async fn handle_initialized(
         regs.append(&mut config_diagnostics_regs);
     }
 
+    if needs_registration.did_change_watched_files {
+        // Get notified of R files changed outside of the editor so we can
+        // re-index them and detect divergence with open documents, and of
+        // changes to the project settings and generated files
+        regs.push(Registration {
+            id: uuid::Uuid::new_v4().to_string(),
+            method: String::from("workspace/didChangeWatchedFiles"),
+            register_options: Some(serde_json::json!({
+                "watchers": [
+                    { "globPattern": "**/*.{R,r}" },
+                    { "globPattern": format!("**/{}", project_config::FILE_NAME) },
+                    { "globPattern": format!("**/{}", generated::GITATTRIBUTES_FILE_NAME) },
+                ]
+            })),
+        });
+    }
+
     client
         .register_capability(regs)
         .instrument(span.exit())
@@ -154,19 +212,19 @@ pub(crate) fn handle_document_symbol(
         })
 }
 
-pub(crate) async fn handle_execute_command(client: &Client) -> anyhow::Result<Option<Value>> {
-    match client.apply_edit(WorkspaceEdit::default()).await {
-        Ok(res) if res.applied => client.log_message(MessageType::INFO, "applied").await,
-        Ok(_) => client.log_message(MessageType::INFO, "rejected").await,
-        Err(err) => client.log_message(MessageType::ERROR, err).await,
-    }
-    Ok(None)
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) async fn handle_execute_command(
+    params: ExecuteCommandParams,
+    client: &Client,
+) -> anyhow::Result<Option<Value>> {
+    commands::execute_command(params, client).await
 }
 
 #[tracing::instrument(level = "info", skip_all)]
 pub(crate) fn handle_completion(
     params: CompletionParams,
     state: &WorldState,
+    cancellation: CancellationToken,
 ) -> anyhow::Result<Option<CompletionResponse>> {
     // Get reference to document.
     let uri = params.text_document_position.text_document.uri;
@@ -181,7 +239,26 @@ pub(crate) fn handle_completion(
     let context = DocumentContext::new(&document, point, trigger);
     lsp::log_info!("Completion context: {:#?}", context);
 
-    let completions = r_task(|| provide_completions(&context, state))?;
+    std::thread::sleep(COMPLETION_DEBOUNCE);
+    if cancellation.is_cancelled() {
+        return Ok(Some(incomplete_completions()));
+    }
+
+    // Sources that don't need R run in parallel while we wait for our turn
+    let statics = provide_static_completions(&context, state);
+
+    let completions = r_request("completion", Priority::Interactive, || {
+        harp::cancellation::with_cancellation(&cancellation, || {
+            provide_r_completions(&context, state)
+        })
+    });
+
+    // The completions of a cancelled request may be partial, or an error
+    // raised at a cancellation point
+    if cancellation.is_cancelled() {
+        return Ok(Some(incomplete_completions()));
+    }
+    let completions = statics.merge(completions??);
 
     if !completions.is_empty() {
         Ok(Some(CompletionResponse::Array(completions)))
@@ -190,11 +267,22 @@ pub(crate) fn handle_completion(
     }
 }
 
+/// Response to a cancelled completion request. Marked as incomplete so that
+/// clients that still use it ask again rather than cache an empty list.
+fn incomplete_completions() -> CompletionResponse {
+    CompletionResponse::List(CompletionList {
+        is_incomplete: true,
+        items: vec![],
+    })
+}
+
 #[tracing::instrument(level = "info", skip_all)]
 pub(crate) fn handle_completion_resolve(
     mut item: CompletionItem,
 ) -> anyhow::Result<CompletionItem> {
-    r_task(|| resolve_completion(&mut item))?;
+    r_request("completion_resolve", Priority::Interactive, || {
+        resolve_completion(&mut item)
+    })??;
     Ok(item)
 }
 
@@ -213,7 +301,7 @@ pub(crate) fn handle_hover(
     let context = DocumentContext::new(&document, point, None);
 
     // request hover information
-    let result = r_task(|| r_hover(&context));
+    let result = r_request("hover", Priority::Interactive, || r_hover(&context))?;
 
     // unwrap errors
     let result = unwrap!(result, Err(err) => {
@@ -247,7 +335,9 @@ pub(crate) fn handle_signature_help(
     let context = DocumentContext::new(&document, point, None);
 
     // request signature help
-    let result = r_task(|| r_signature_help(&context));
+    let result = r_request("signature_help", Priority::Interactive, || {
+        r_signature_help(&context)
+    })?;
 
     // unwrap errors
     let result = unwrap!(result, Err(err) => {
@@ -281,6 +371,22 @@ pub(crate) fn handle_goto_definition(
     Ok(result)
 }
 
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_goto_type_definition(
+    params: GotoTypeDefinitionParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<GotoTypeDefinitionResponse>> {
+    let uri = &params.text_document_position_params.text_document.uri;
+    let document = state.get_document(uri)?;
+
+    let result = unwrap!(goto_type_definition(&document, params), Err(err) => {
+        lsp::log_error!("{err:?}");
+        return Ok(None);
+    });
+
+    Ok(result)
+}
+
 #[tracing::instrument(level = "info", skip_all)]
 pub(crate) fn handle_selection_range(
     params: SelectionRangeParams,
@@ -312,6 +418,48 @@ pub(crate) fn handle_selection_range(
     Ok(Some(selections))
 }
 
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_folding_range(
+    params: FoldingRangeParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<Vec<FoldingRange>>> {
+    let uri = params.text_document.uri;
+    let document = state.get_document(&uri)?;
+
+    Ok(Some(folding_ranges(document)?))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_semantic_tokens_full(
+    params: SemanticTokensParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<SemanticTokensResult>> {
+    let uri = params.text_document.uri;
+    let document = state.get_document(&uri)?;
+
+    Ok(Some(SemanticTokensResult::Tokens(semantic_tokens(
+        document,
+    )?)))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_code_lens(
+    params: CodeLensParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<Vec<CodeLens>>> {
+    let uri = params.text_document.uri;
+    let document = state.get_document(&uri)?;
+
+    Ok(Some(code_lenses(document)?))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_code_action(
+    params: CodeActionParams,
+) -> anyhow::Result<Option<CodeActionResponse>> {
+    Ok(Some(code_actions(params)))
+}
+
 #[tracing::instrument(level = "info", skip_all)]
 pub(crate) fn handle_references(
     params: ReferenceParams,
@@ -331,6 +479,22 @@ pub(crate) fn handle_references(
     }
 }
 
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_prepare_rename(
+    params: TextDocumentPositionParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<PrepareRenameResponse>> {
+    prepare_rename(params, state)
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_rename(
+    params: RenameParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<WorkspaceEdit>> {
+    rename(params, state)
+}
+
 #[tracing::instrument(level = "info", skip_all)]
 pub(crate) fn handle_statement_range(
     params: StatementRangeParams,
@@ -374,6 +538,12 @@ pub(crate) fn handle_indent(
     let uri = ctxt.text_document.uri;
 
     let doc = state.get_document(&uri)?;
+
+    // Don't format generated files, edits would be overwritten anyway
+    if generated::is_generated(&uri, doc, state) {
+        return Ok(None);
+    }
+
     let pos = ctxt.position;
     let point = convert_position_to_point(&doc.contents, pos);
 
@@ -384,6 +554,43 @@ pub(crate) fn handle_indent(
     })
 }
 
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_formatting(
+    params: DocumentFormattingParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<Vec<TextEdit>>> {
+    let uri = params.text_document.uri;
+    let doc = state.get_document(&uri)?;
+
+    if generated::is_generated(&uri, doc, state) {
+        return Ok(None);
+    }
+
+    let edits = format_document(doc)?;
+    Ok(edits.map(|edits| edits.into_lsp_offset(&doc.contents)))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_range_formatting(
+    params: DocumentRangeFormattingParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<Vec<TextEdit>>> {
+    let uri = params.text_document.uri;
+    let doc = state.get_document(&uri)?;
+
+    if generated::is_generated(&uri, doc, state) {
+        return Ok(None);
+    }
+
+    let range = ArkRange {
+        start: convert_position_to_point(&doc.contents, params.range.start),
+        end: convert_position_to_point(&doc.contents, params.range.end),
+    };
+
+    let edits = format_range(doc, range)?;
+    Ok(edits.map(|edits| edits.into_lsp_offset(&doc.contents)))
+}
+
 // TODO: Should be in WorldState and updated via message passing
 pub static mut ARK_VDOCS: Lazy<DashMap<String, String>> = Lazy::new(|| DashMap::new());
 
@@ -400,6 +607,13 @@ pub(crate) fn handle_virtual_document(
 pub(crate) fn handle_input_boundaries(
     params: InputBoundariesParams,
 ) -> anyhow::Result<InputBoundariesResponse> {
-    let boundaries = r_task(|| input_boundaries(&params.text))?;
+    let boundaries = r_request("input_boundaries", Priority::Interactive, || {
+        input_boundaries(&params.text)
+    })??;
     Ok(InputBoundariesResponse { boundaries })
 }
+
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn handle_brackets(params: BracketsParams) -> anyhow::Result<BracketsResponse> {
+    Ok(brackets(&params.text, params.position))
+}
@@ -277,18 +277,23 @@ impl RHtmlHelp {
 
         // add topic
         if let Some(topic) = self.topic() {
-            push!(markdown, md_italic(&topic), md_newline());
+            push!(markdown, md_italic(&md_escape_html(&topic)), md_newline());
         }
 
         if let Some(title) = self.title() {
-            push!(markdown, md_h2(&title), md_newline(), "------\n");
+            push!(
+                markdown,
+                md_h2(&md_escape_html(&title)),
+                md_newline(),
+                "------\n"
+            );
         }
 
         // iterate through the different sections in the help file
         for_each_section(&self.html, |header, elements| {
             // add a title
             let header = elt_text(header);
-            markdown.push_str(md_h3(header.as_str()).as_str());
+            markdown.push_str(md_h3(&md_escape_html(&header)).as_str());
             markdown.push_str(md_newline().as_str());
 
             // add body
@@ -6,6 +6,8 @@
 //
 
 use anyhow::*;
+use harp::eval::RParseEvalOptions;
+use harp::utils::r_is_function;
 use stdext::unwrap;
 use stdext::unwrap::IntoResult;
 use tower_lsp::lsp_types::MarkupContent;
@@ -14,15 +16,39 @@ use tree_sitter::Node;
 
 use crate::lsp::document_context::DocumentContext;
 use crate::lsp::help::RHtmlHelp;
+use crate::lsp::hover_evaluation::is_glimpse_target;
+use crate::lsp::hover_evaluation::r_hover_evaluation;
+use crate::lsp::hover_evaluation::r_hover_glimpse;
+use crate::lsp::indexer;
+use crate::lsp::markdown::md_codeblock;
+use crate::lsp::signature_help::r_signature_label;
 use crate::lsp::traits::rope::RopeExt;
+use crate::lsp::type_annotations::find_annotation;
 use crate::treesitter::NodeTypeExt;
+use crate::user_config::user_config;
 
 enum HoverContext {
-    Topic { topic: String },
-    QualifiedTopic { package: String, topic: String },
+    Topic {
+        topic: String,
+    },
+    QualifiedTopic {
+        package: String,
+        topic: String,
+    },
+    Parameter {
+        package: Option<String>,
+        topic: String,
+        name: String,
+    },
 }
 
 fn hover_context(node: Node, context: &DocumentContext) -> Result<Option<HoverContext>> {
+    // if we're on the name of an argument in a call, e.g. `na.rm` in
+    // `mean(x, na.rm = TRUE)`, document that parameter of the called function
+    if let Some(ctx) = hover_parameter_context(node, context)? {
+        return Ok(Some(ctx));
+    }
+
     // if the parent node is a namespace call, use that node instead
     // TODO: What if the user hovers the cursor over 'dplyr' in e.g. 'dplyr::mutate'?
     let mut node = node;
@@ -66,7 +92,74 @@ fn hover_context(node: Node, context: &DocumentContext) -> Result<Option<HoverCo
     Ok(None)
 }
 
+fn hover_parameter_context(node: Node, context: &DocumentContext) -> Result<Option<HoverContext>> {
+    let Some(argument) = node.parent() else {
+        return Ok(None);
+    };
+    if !argument.is_argument() || argument.child_by_field_name("name") != Some(node) {
+        return Ok(None);
+    }
+
+    let Some(call) = argument.parent().and_then(|arguments| arguments.parent()) else {
+        return Ok(None);
+    };
+    if !call.is_call() {
+        return Ok(None);
+    }
+
+    let function = call.child_by_field_name("function").into_result()?;
+    let contents = &context.document.contents;
+
+    let (package, topic) = if function.is_namespace_operator() {
+        let lhs = function.child_by_field_name("lhs").into_result()?;
+        let rhs = function.child_by_field_name("rhs").into_result()?;
+
+        if !lhs.is_identifier_or_string() || !rhs.is_identifier_or_string() {
+            return Ok(None);
+        }
+
+        let package = contents.node_slice(&lhs)?.to_string();
+        let topic = contents.node_slice(&rhs)?.to_string();
+        (Some(package), topic)
+    } else if function.is_identifier() {
+        (None, contents.node_slice(&function)?.to_string())
+    } else {
+        // e.g. anonymous function calls, we don't have any docs for those
+        return Ok(None);
+    };
+
+    let name = contents.node_slice(&node)?.to_string();
+
+    Ok(Some(HoverContext::Parameter {
+        package,
+        topic,
+        name,
+    }))
+}
+
 pub(crate) fn r_hover(context: &DocumentContext) -> anyhow::Result<Option<MarkupContent>> {
+    if let Some(markup) = r_hover_help(context)? {
+        return Ok(Some(markup));
+    }
+
+    if let Some(markup) = r_hover_glimpse(context)? {
+        return Ok(Some(markup));
+    }
+
+    // Objects that don't exist in the session may have a type annotation
+    if let Some(markup) = r_hover_annotation(context)? {
+        return Ok(Some(markup));
+    }
+
+    // Fall back to a preview of the value, if the user opted in
+    if user_config().evaluation.hover {
+        return r_hover_evaluation(context);
+    }
+
+    Ok(None)
+}
+
+fn r_hover_help(context: &DocumentContext) -> anyhow::Result<Option<MarkupContent>> {
     // get the node
     let node = &context.node;
 
@@ -80,18 +173,25 @@ pub(crate) fn r_hover(context: &DocumentContext) -> anyhow::Result<Option<Markup
         return Ok(None);
     });
 
-    // Currently, `hover_context()` restricts to only showing hover docs for functions,
-    // so we also use `RHtmlHelp::from_function()` here
-    let help = match ctx {
-        HoverContext::QualifiedTopic { package, topic } => {
-            RHtmlHelp::from_function(topic.as_str(), Some(package.as_str()))?
-        },
+    let (topic, package) = match ctx {
+        HoverContext::QualifiedTopic { package, topic } => (topic, Some(package)),
 
-        HoverContext::Topic { topic } => RHtmlHelp::from_function(topic.as_str(), None)?,
+        HoverContext::Topic { topic } => (topic, None),
+
+        HoverContext::Parameter {
+            package,
+            topic,
+            name,
+        } => return r_hover_parameter(topic.as_str(), package.as_deref(), name.as_str()),
     };
 
+    // Currently, `hover_context()` restricts to only showing hover docs for functions,
+    // so we also use `RHtmlHelp::from_function()` here
+    let help = RHtmlHelp::from_function(topic.as_str(), package.as_deref())?;
+
     let help = unwrap!(help, None => {
-        return Ok(None);
+        // e.g. functions defined by the user, which don't have a help page
+        return r_hover_signature(topic.as_str(), package.as_deref());
     });
 
     let markdown = help.markdown()?;
@@ -100,3 +200,194 @@ pub(crate) fn r_hover(context: &DocumentContext) -> anyhow::Result<Option<Markup
         value: markdown,
     }))
 }
+
+fn r_hover_annotation(context: &DocumentContext) -> anyhow::Result<Option<MarkupContent>> {
+    let node = context.node;
+    if !node.is_identifier() || !is_glimpse_target(&node) {
+        return Ok(None);
+    }
+
+    let name = context.document.contents.node_slice(&node)?.to_string();
+
+    let annotation = unwrap!(find_annotation(context.document, &name, context.point), None => {
+        return Ok(None);
+    });
+
+    Ok(Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: annotation.markdown(),
+    }))
+}
+
+/// Signature of a function without a help page, from the session or else from
+/// the workspace index
+fn r_hover_signature(topic: &str, package: Option<&str>) -> anyhow::Result<Option<MarkupContent>> {
+    let code = match package {
+        Some(package) => format!("{package}::{topic}"),
+        None => String::from(topic),
+    };
+
+    let function = harp::parse_eval(code.as_str(), RParseEvalOptions {
+        forbid_function_calls: true,
+        ..Default::default()
+    });
+
+    let label = match function {
+        std::result::Result::Ok(function) if r_is_function(function.sexp) => {
+            Some(r_signature_label(topic, function.sexp)?)
+        },
+        // Not sourced yet, or not a function
+        _ => package
+            .is_none()
+            .then(|| workspace_signature_label(topic))
+            .flatten(),
+    };
+
+    let label = unwrap!(label, None => {
+        return Ok(None);
+    });
+
+    Ok(Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: md_codeblock("r", label.as_str()),
+    }))
+}
+
+fn workspace_signature_label(topic: &str) -> Option<String> {
+    let (_path, entry) = indexer::find(topic)?;
+
+    match entry.data {
+        indexer::IndexEntryData::Function { name, arguments } => {
+            Some(format!("{name}({})", arguments.join(", ")))
+        },
+        _ => None,
+    }
+}
+
+/// Documentation for a single parameter of a function, taken from the
+/// Arguments section of its help page
+fn r_hover_parameter(
+    topic: &str,
+    package: Option<&str>,
+    name: &str,
+) -> anyhow::Result<Option<MarkupContent>> {
+    let help = unwrap!(RHtmlHelp::from_function(topic, package)?, None => {
+        return Ok(None);
+    });
+
+    // Don't fall back to the whole function documentation if the parameter is
+    // not documented, e.g. when it's forwarded through `...`
+    let markup = unwrap!(help.parameter(name)?, None => {
+        return Ok(None);
+    });
+
+    let value = format!("`{name}`: argument of `{topic}()`\n\n{}", markup.value);
+
+    Ok(Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::MarkupContent;
+
+    use crate::fixtures::point_from_cursor;
+    use crate::lsp::document_context::DocumentContext;
+    use crate::lsp::documents::Document;
+    use crate::lsp::hover::r_hover;
+    use crate::r_task;
+
+    fn hover(text: &str) -> Option<MarkupContent> {
+        let (text, point) = point_from_cursor(text);
+        let document = Document::new(text.as_str(), None);
+        let context = DocumentContext::new(&document, point, None);
+        r_task(|| r_hover(&context).unwrap())
+    }
+
+    #[test]
+    fn test_hover_parameter() {
+        let markup = hover("mean(x, na.@rm = TRUE)").unwrap();
+        assert!(markup.value.starts_with("`na.rm`: argument of `mean()`"));
+        assert!(markup.value.contains("`NA` values"));
+        assert!(!markup.value.contains("### Usage"));
+
+        let markup = hover("base::mean(x, na.@rm = TRUE)").unwrap();
+        assert!(markup.value.starts_with("`na.rm`: argument of `mean()`"));
+
+        // Undocumented parameters don't fall back to the function docs
+        assert!(hover("mean(x, not_a_par@ameter = TRUE)").is_none());
+
+        // Argument values are not parameters
+        assert!(hover("mean(x, na.rm = tr@ue_value)").is_none());
+    }
+
+    #[test]
+    fn test_hover_function() {
+        let markup = hover("me@an(x)").unwrap();
+        assert!(markup.value.contains("### Usage"));
+    }
+
+    #[test]
+    fn test_hover_signature() {
+        r_task(|| {
+            harp::parse_eval_global("ark_test_hover_fn <- function(x, y = 1, ...) x").unwrap();
+        });
+
+        // Functions without a help page show their signature
+        let markup = hover("ark_test_hover@_fn(1)").unwrap();
+        assert_eq!(
+            markup.value,
+            "``` r\nark_test_hover_fn(x, y = 1, ...)\n```\n"
+        );
+
+        r_task(|| {
+            harp::parse_eval_global("rm(ark_test_hover_fn)").unwrap();
+        });
+    }
+
+    #[test]
+    fn test_hover_annotation() {
+        let markup = hover("# @type ark_test_df: data.frame{id, name}\nark_test@_df").unwrap();
+        assert_eq!(
+            markup.value,
+            "`ark_test_df`: `data.frame` (type annotation)\n\nFields: `id`, `name`"
+        );
+
+        assert!(hover("ark_test@_df\n# @type ark_test_df: data.frame").is_none());
+    }
+
+    #[test]
+    fn test_hover_glimpse() {
+        r_task(|| {
+            harp::parse_eval_global(
+                "ark_test_hover_df <- data.frame(a = 1:10, b = letters[1:10])
+                 ark_test_hover_fn <- function(x) x",
+            )
+            .unwrap();
+        });
+
+        let markup = hover("ark_test_hover@_df").unwrap();
+        assert!(markup
+            .value
+            .starts_with("`ark_test_hover_df`: object in the global environment"));
+        assert!(markup.value.contains("Class: `data.frame`"));
+        assert!(markup.value.contains("Dimensions: 10 × 2"));
+
+        // Only the first rows are printed
+        assert!(markup.value.contains("6 6 f"));
+        assert!(!markup.value.contains("7 7 g"));
+
+        let markup = hover("ark_test_hover@_fn").unwrap();
+        assert!(markup.value.contains("ark_test_hover_fn(x)"));
+
+        // Argument names and fields refer to something else
+        assert!(hover("list(ark_test_hover@_df = 1)").is_none());
+        assert!(hover("x$ark_test_hover@_df").is_none());
+
+        r_task(|| {
+            harp::parse_eval_global("rm(ark_test_hover_df, ark_test_hover_fn)").unwrap();
+        });
+    }
+}
@@ -0,0 +1,489 @@
+//
+// hover_evaluation.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Live previews of values in hovers, enabled by the `evaluation.hover`
+// setting. Only expressions that can be evaluated without running user code
+// are previewed: literals, symbols, and `$` chains like `x$y$z`. Symbols are
+// looked up from the global environment without triggering active bindings
+// or forcing promises (except for lazy-loaded data), and `$` is only applied
+// to lists and environments that don't have a `$` method.
+//
+// Glimpses of objects bound in the global environment are always shown, as
+// they only look up a binding.
+//
+// Formatting previews and glimpses may still dispatch to methods, e.g.
+// `format()` methods registered by packages. This is bounded by a time limit
+// and only happens while R is idle at the prompt.
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use harp::array_preview::is_array;
+use harp::array_preview::ArrayPreview;
+use harp::environment::Binding;
+use harp::environment::BindingValue;
+use harp::environment::Environment;
+use harp::environment::R_ENVS;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::object::RObject;
+use harp::utils::r_classes;
+use harp::utils::r_is_data_frame;
+use harp::utils::r_is_function;
+use harp::utils::r_is_null;
+use harp::utils::r_promise_force_with_rollback;
+use harp::utils::r_promise_is_lazy_load_binding;
+use harp::utils::r_typeof;
+use harp::vector::Vector;
+use libr::ENVSXP;
+use libr::SEXP;
+use libr::VECSXP;
+use ropey::Rope;
+use tower_lsp::lsp_types::MarkupContent;
+use tower_lsp::lsp_types::MarkupKind;
+use tree_sitter::Node;
+
+use crate::interface::RMain;
+use crate::lsp::document_context::DocumentContext;
+use crate::lsp::indexer;
+use crate::lsp::signature_help::r_signature_label;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::ExtractOperatorType;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+use crate::variables::variable::WorkspaceVariableDisplayType;
+use crate::variables::variable::WorkspaceVariableDisplayValue;
+
+/// Time budget for evaluating and formatting a value. Formatting may call
+/// display methods registered by packages, which could be slow.
+const HOVER_EVALUATION_TIME_LIMIT: Duration = Duration::from_millis(250);
+
+/// Number of elements, or rows, printed in glimpses
+const GLIMPSE_HEAD_SIZE: i32 = 6;
+
+/// Number of columns of matrices and arrays shown in previews
+const PREVIEW_COLUMNS: usize = 8;
+
+pub(crate) fn r_hover_evaluation(
+    context: &DocumentContext,
+) -> anyhow::Result<Option<MarkupContent>> {
+    let Some(node) = hover_expression(context.node) else {
+        return Ok(None);
+    };
+    if !is_idle() {
+        return Ok(None);
+    }
+
+    let contents = &context.document.contents;
+    let code = contents.node_slice(&node)?.to_string();
+
+    let preview = with_time_limit(HOVER_EVALUATION_TIME_LIMIT, || -> anyhow::Result<_> {
+        let Some(value) = evaluate(&node, contents)? else {
+            return Ok(None);
+        };
+
+        let display_type = WorkspaceVariableDisplayType::from(value.sexp, true).display_type;
+
+        // Arrays keep their shape
+        if is_array(value.sexp) {
+            return Ok(Some((display_type, array_grid(value.sexp)?)));
+        }
+
+        let display_value = WorkspaceVariableDisplayValue::from(value.sexp);
+        let mut value = display_value.display_value;
+        if display_value.is_truncated {
+            value.push_str(" …");
+        }
+
+        Ok(Some((display_type, value)))
+    })?;
+
+    let Some((display_type, value)) = preview else {
+        return Ok(None);
+    };
+
+    let value = format!(
+        "**Live value** of `{code}`, evaluated in the global environment\n\n`{display_type}`\n\n```\n{value}\n```"
+    );
+
+    Ok(Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    }))
+}
+
+/// Glimpse of the object bound to the hovered symbol in the global
+/// environment: its class, dimensions, and first elements, or its signature
+/// for functions
+pub(crate) fn r_hover_glimpse(context: &DocumentContext) -> anyhow::Result<Option<MarkupContent>> {
+    let node = context.node;
+    if !node.is_identifier() || !is_glimpse_target(&node) || !is_idle() {
+        return Ok(None);
+    }
+
+    let name = context.document.contents.node_slice(&node)?.to_string();
+
+    let global = Environment::view(R_ENVS.global);
+    if !global.exists(name.as_str()) {
+        return Ok(None);
+    }
+
+    let glimpse = with_time_limit(HOVER_EVALUATION_TIME_LIMIT, || -> anyhow::Result<_> {
+        let Some(object) = binding_value(&global, &name)? else {
+            return Ok(None);
+        };
+        Ok(Some(glimpse(&name, object)?))
+    })?;
+
+    Ok(glimpse.map(|value| MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    }))
+}
+
+fn glimpse(name: &str, object: RObject) -> anyhow::Result<String> {
+    if r_is_function(object.sexp) {
+        let label = r_signature_label(name, object.sexp)?;
+        return Ok(format!(
+            "`{name}`: function in the global environment\n\n```r\n{label}\n```"
+        ));
+    }
+
+    let glimpse = RFunction::from(".ps.format.glimpse")
+        .add(object.clone())
+        .param("n", GLIMPSE_HEAD_SIZE)
+        .call()?;
+
+    let class: Vec<String> = glimpse.vector_elt(0)?.try_into()?;
+    let size: String = glimpse.vector_elt(1)?.try_into()?;
+    let mut head: Vec<String> = glimpse.vector_elt(2)?.try_into()?;
+
+    // The corner of arrays rather than all the columns of their first rows
+    if is_array(object.sexp) {
+        head = vec![array_grid(object.sexp)?];
+    }
+
+    let class: Vec<String> = class.iter().map(|class| format!("`{class}`")).collect();
+    let mut value = format!(
+        "`{name}`: object in the global environment\n\nClass: {}  \n{size}",
+        class.join(", ")
+    );
+
+    if !head.is_empty() {
+        value.push_str(&format!("\n\n```\n{}\n```", head.join("\n")));
+    }
+
+    Ok(value)
+}
+
+/// Preview of the top-left corner of an array, laid out like R prints it
+fn array_grid(x: SEXP) -> anyhow::Result<String> {
+    let preview = ArrayPreview::new(x, GLIMPSE_HEAD_SIZE as usize, PREVIEW_COLUMNS)?;
+    Ok(preview.grid())
+}
+
+/// Whether a glimpse of the global variable named like `node` makes sense.
+/// Argument names, fields of `$` and `@`, and namespaced symbols refer to
+/// something else.
+pub(crate) fn is_glimpse_target(node: &Node) -> bool {
+    let Some(parent) = node.parent() else {
+        return true;
+    };
+
+    if parent.is_argument() {
+        return parent.child_by_field_name("name") != Some(*node);
+    }
+
+    if matches!(parent.node_type(), NodeType::ExtractOperator(_)) {
+        return parent.child_by_field_name("rhs") != Some(*node);
+    }
+
+    !parent.is_namespace_operator()
+}
+
+/// The expression to preview when hovering `node`. For `x$y$z`, hovering `y`
+/// previews `x$y`.
+pub(crate) fn hover_expression(node: Node) -> Option<Node> {
+    let mut node = node;
+
+    // Hovering the contents of a string previews the string
+    if node.node_type() == NodeType::StringContent {
+        node = node.parent()?;
+    }
+
+    while let Some(parent) = node.parent() {
+        if !is_dollar(&parent) || parent.child_by_field_name("rhs") != Some(node) {
+            break;
+        }
+        node = parent;
+    }
+
+    if !is_previewable(&node) {
+        return None;
+    }
+
+    Some(node)
+}
+
+fn is_previewable(node: &Node) -> bool {
+    match node.node_type() {
+        NodeType::Integer |
+        NodeType::Float |
+        NodeType::Complex |
+        NodeType::String |
+        NodeType::True |
+        NodeType::False |
+        NodeType::Null |
+        NodeType::Inf |
+        NodeType::Nan |
+        NodeType::Na(_) |
+        NodeType::Identifier => true,
+
+        NodeType::ExtractOperator(ExtractOperatorType::Dollar) => {
+            let lhs = node.child_by_field_name("lhs");
+            let rhs = node.child_by_field_name("rhs");
+
+            match (lhs, rhs) {
+                (Some(lhs), Some(rhs)) => {
+                    is_previewable(&lhs) && (rhs.is_identifier() || rhs.is_string())
+                },
+                _ => false,
+            }
+        },
+
+        _ => false,
+    }
+}
+
+/// Evaluate `node` without running user code. Returns `None` when the value
+/// can't be determined safely.
+pub(crate) fn evaluate(node: &Node, contents: &Rope) -> anyhow::Result<Option<RObject>> {
+    match node.node_type() {
+        NodeType::Identifier => {
+            let name = contents.node_slice(node)?.to_string();
+            let global = Environment::view(R_ENVS.global);
+
+            let Some(env) = global.ancestors().find(|env| env.exists(name.as_str())) else {
+                return Ok(None);
+            };
+            binding_value(&env, &name)
+        },
+
+        NodeType::ExtractOperator(ExtractOperatorType::Dollar) => {
+            let lhs = node
+                .child_by_field_name("lhs")
+                .ok_or(anyhow!("Missing `lhs`"))?;
+            let rhs = node
+                .child_by_field_name("rhs")
+                .ok_or(anyhow!("Missing `rhs`"))?;
+
+            let Some(object) = evaluate(&lhs, contents)? else {
+                return Ok(None);
+            };
+
+            let name = match indexer::string_value(&rhs, contents) {
+                Some(name) => name,
+                None => contents.node_slice(&rhs)?.to_string(),
+            };
+
+            extract(object, &name)
+        },
+
+        // Literals, evaluating them has no side effects
+        _ => {
+            let code = contents.node_slice(node)?.to_string();
+            Ok(Some(harp::parse_eval_base(&code)?))
+        },
+    }
+}
+
+fn binding_value(env: &Environment, name: &str) -> anyhow::Result<Option<RObject>> {
+    let binding = Binding::new(env, name.into())?;
+
+    match binding.value {
+        BindingValue::Standard { object, .. } | BindingValue::Altrep { object, .. } => {
+            Ok(Some(object))
+        },
+
+        // Lazy-loaded objects, e.g. datasets, are fetched from the package
+        // database. Other promises may run arbitrary code.
+        BindingValue::Promise { promise } => {
+            if unsafe { r_promise_is_lazy_load_binding(promise.sexp) } {
+                Ok(Some(r_promise_force_with_rollback(promise.sexp)?))
+            } else {
+                Ok(None)
+            }
+        },
+
+        // Triggering an active binding runs its function
+        BindingValue::Active { .. } => Ok(None),
+    }
+}
+
+/// `object$name` for lists and environments that don't dispatch `$` to a
+/// method
+fn extract(object: RObject, name: &str) -> anyhow::Result<Option<RObject>> {
+    if has_dollar_method(&object)? {
+        return Ok(None);
+    }
+
+    match r_typeof(object.sexp) {
+        ENVSXP => {
+            let env = Environment::new(object);
+            if !env.exists(name) {
+                return Ok(Some(RObject::null()));
+            }
+            binding_value(&env, name)
+        },
+
+        VECSXP => {
+            let value = RFunction::new("base", "[[")
+                .add(object)
+                .add(name)
+                .param("exact", true)
+                .call()?;
+            Ok(Some(value))
+        },
+
+        _ => Ok(None),
+    }
+}
+
+fn has_dollar_method(object: &RObject) -> anyhow::Result<bool> {
+    // `$.data.frame` only warns about partial matching
+    if r_is_data_frame(object.sexp) {
+        return Ok(false);
+    }
+
+    let Some(classes) = r_classes(object.sexp) else {
+        return Ok(false);
+    };
+
+    for class in classes.iter().flatten() {
+        let method = RFunction::new("utils", "getS3method")
+            .add("$")
+            .add(class)
+            .param("optional", true)
+            .call()?;
+
+        if !r_is_null(method.sexp) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn is_dollar(node: &Node) -> bool {
+    node.node_type() == NodeType::ExtractOperator(ExtractOperatorType::Dollar)
+}
+
+/// Whether R is waiting at the prompt rather than running an execute request.
+/// Tasks on the R thread can also run while user code is executing.
+fn is_idle() -> bool {
+    !RMain::is_initialized() || !RMain::get().is_executing()
+}
+
+/// Run `f` with an elapsed time limit. R code that runs past the limit throws
+/// an error.
+///
+/// R can't report the current time limits, so they can't be restored
+/// afterwards. Our limit is transient, which keeps the limits the user set
+/// with `setTimeLimit()` for the next top-level computations but replaces
+/// those of the current one. To leave running user code alone, this fails
+/// unless R is idle.
+pub(crate) fn with_time_limit<T>(limit: Duration, f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    if !is_idle() {
+        return Err(anyhow!("Can't set a time limit while R is executing code"));
+    }
+
+    let set_time_limit = |elapsed: f64| {
+        RFunction::new("base", "setTimeLimit")
+            .param("elapsed", elapsed)
+            .param("transient", true)
+            .call()
+    };
+
+    set_time_limit(limit.as_secs_f64())?;
+    let out = f();
+
+    if let Err(err) = set_time_limit(f64::INFINITY) {
+        log::error!("Can't reset the time limit: {err:?}");
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::MarkupContent;
+
+    use crate::fixtures::point_from_cursor;
+    use crate::lsp::document_context::DocumentContext;
+    use crate::lsp::documents::Document;
+    use crate::lsp::hover_evaluation::r_hover_evaluation;
+    use crate::r_task;
+
+    fn hover(text: &str) -> Option<MarkupContent> {
+        let (text, point) = point_from_cursor(text);
+        let document = Document::new(text.as_str(), None);
+        let context = DocumentContext::new(&document, point, None);
+        r_task(|| r_hover_evaluation(&context).unwrap())
+    }
+
+    #[test]
+    fn test_hover_evaluation() {
+        r_task(|| {
+            harp::parse_eval_global(
+                "ark_test_hover <- list(a = list(b = 42L), f = function() 1)
+                 ark_test_hover_env <- new.env()
+                 makeActiveBinding('active', function() stop('triggered'), ark_test_hover_env)
+                 delayedAssign('ark_test_hover_promise', stop('forced'))",
+            )
+            .unwrap();
+        });
+
+        let markup = hover("ark_test_hover$a$@b").unwrap();
+        assert!(markup
+            .value
+            .starts_with("**Live value** of `ark_test_hover$a$b`"));
+        assert!(markup.value.contains("\n42\n"));
+
+        // Hovering the middle of a chain previews the chain up to there
+        let markup = hover("ark_test_hover$@a$b").unwrap();
+        assert!(markup
+            .value
+            .starts_with("**Live value** of `ark_test_hover$a`"));
+
+        // Literals
+        let markup = hover("1@0L").unwrap();
+        assert!(markup.value.contains("\n10\n"));
+
+        // Matrices keep their shape
+        r_task(|| {
+            harp::parse_eval_global("ark_test_hover_matrix <- matrix(1:4, 2)").unwrap();
+        });
+        let markup = hover("ark_test_hover_matrix@").unwrap();
+        assert!(markup.value.contains("`int [2, 2]`"));
+        assert!(markup
+            .value
+            .contains("\n     [,1] [,2]\n[1,]    1    3\n[2,]    2    4\n"));
+
+        // Unknown objects, calls, active bindings, and promises aren't previewed
+        assert!(hover("ark_test_hover_not_there@").is_none());
+        assert!(hover("identity(ark_test_hover)$@a").is_none());
+        assert!(hover("ark_test_hover_env$act@ive").is_none());
+        assert!(hover("ark_test_hover_pro@mise").is_none());
+
+        r_task(|| {
+            harp::parse_eval_global(
+                "rm(ark_test_hover, ark_test_hover_env, ark_test_hover_promise, ark_test_hover_matrix)",
+            )
+            .unwrap();
+        });
+    }
+}
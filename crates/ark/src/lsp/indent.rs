@@ -12,12 +12,13 @@ use crate::treesitter::NodeTypeExt;
 
 /// Provide indentation corrections
 ///
-/// Hooked up to format-on-type for newline characters.
+/// Hooked up to format-on-type for newline characters and closing delimiters.
 ///
 /// This is not a full indenter yet. We only provide corrections for the
 /// Positron frontend when the VS Code regexp-based indenting rules are not able
 /// to indent as expected. For instance we reindent pipeline components to
-/// ensure alignment and avoid a staircase effect.
+/// ensure alignment and avoid a staircase effect, and align arguments with
+/// the first argument of their call.
 ///
 /// Once we implement a full formatter, indentation will be provided for any
 /// constructs based on the formatter and will be fully consistent with it.
@@ -89,6 +90,49 @@ pub fn indent_edit(doc: &Document, line: usize) -> anyhow::Result<Option<Vec<Ark
         (brace_parent_indent(parent), config.indent_size)
     };
 
+    let arguments_indent = |parent: tree_sitter::Node| -> Option<(usize, usize)> {
+        // Error recovery doesn't give malformed arguments a reliable structure
+        let is_malformed = |node: tree_sitter::Node| {
+            let mut cursor = node.walk();
+            let mut children = node.children(&mut cursor);
+            children.any(|child| child.is_error())
+        };
+        if is_malformed(parent) || parent.parent().is_some_and(is_malformed) {
+            return None;
+        }
+
+        let open = parent.child_by_field_name("open")?;
+
+        match text_at_indent().next() {
+            // If we're looking at a closing delimiter, indent at the beginning
+            // of line of the opening delimiter
+            Some(')' | ']') => return Some((node_line_indent(open), 0)),
+            // Leave comma-first styles alone
+            Some(',') => return None,
+            _ => {},
+        }
+
+        // If arguments follow the opening delimiter, align with the first one.
+        // Otherwise indent from the opening delimiter's beginning of line.
+        let mut first = open.next_sibling();
+        while let Some(node) = first {
+            if !matches!(node.node_type(), NodeType::Comma | NodeType::Comment) {
+                break;
+            }
+            first = node.next_sibling();
+        }
+
+        match first {
+            Some(first)
+                if first.start_position().row == open.start_position().row &&
+                    Some(first) != parent.child_by_field_name("close") =>
+            {
+                Some((point_indent(text, first.start_position(), config), 0))
+            },
+            _ => Some((node_line_indent(open), config.indent_size)),
+        }
+    };
+
     // Structured in two stages as in Emacs TS rules: first match, then
     // return anchor and indent size. We can add more rules here as needed.
     let (anchor, indent) = match bol_parent {
@@ -109,6 +153,12 @@ pub fn indent_edit(doc: &Document, line: usize) -> anyhow::Result<Option<Vec<Ark
 
             (node_line_indent(anchor), config.indent_size)
         },
+
+        // Indentation of arguments of calls and parameters of functions
+        parent if is_arguments(&parent) => match arguments_indent(parent) {
+            Some(indent) => indent,
+            None => return Ok(None),
+        },
         _ => return Ok(None),
     };
 
@@ -138,17 +188,28 @@ pub fn indent_edit(doc: &Document, line: usize) -> anyhow::Result<Option<Vec<Ark
 
     // Indent closing delimiter to mitigate VS Code's indent-outdent behaviour
     // https://github.com/posit-dev/positron/issues/3484
-    if bol_parent.is_braced_expression() {
+    let close = if bol_parent.is_braced_expression() {
         // FIXME: Use named delim node once available
         let n = bol_parent.child_count();
-        if n > 1 {
-            let close = bol_parent.child(n - 1).unwrap();
-            let close_line = close.start_position().row;
-
-            if close.node_type() == NodeType::Anonymous("}".into()) && close_line > line {
-                if let Some(ref mut close_edits) = indent_edit(doc, close_line)? {
-                    edits.append(close_edits);
-                }
+        (n > 1)
+            .then(|| bol_parent.child(n - 1).unwrap())
+            .filter(|close| close.node_type() == NodeType::Anonymous("}".into()))
+    } else if is_arguments(&bol_parent) {
+        // Only if the delimiter starts its line, as other lines are left alone
+        bol_parent.child_by_field_name("close").filter(|close| {
+            let point = close.start_position();
+            !close.is_missing() && line_indent(text, point.row, config).1 == point.column
+        })
+    } else {
+        None
+    };
+
+    if let Some(close) = close {
+        let close_line = close.start_position().row;
+
+        if close_line > line {
+            if let Some(ref mut close_edits) = indent_edit(doc, close_line)? {
+                edits.append(close_edits);
             }
         }
     }
@@ -171,6 +232,31 @@ fn brace_parent(node: tree_sitter::Node) -> tree_sitter::Node {
     }
 }
 
+/// Arguments of calls and subsets, or parameters of function definitions
+fn is_arguments(node: &tree_sitter::Node) -> bool {
+    node.is_arguments() || node.node_type() == NodeType::Parameters
+}
+
+/// Returns the indent needed to align with `point`, in spaces
+fn point_indent(
+    text: &ropey::Rope,
+    point: tree_sitter::Point,
+    config: &IndentationConfig,
+) -> usize {
+    let mut indent = 0;
+    let mut byte = 0;
+
+    for c in text.line(point.row).chars() {
+        if byte >= point.column {
+            break;
+        }
+        indent += if c == '\t' { config.tab_width } else { 1 };
+        byte += c.len_utf8();
+    }
+
+    indent
+}
+
 /// Returns indent as a pair of space size and byte size
 pub fn line_indent(text: &ropey::Rope, line: usize, config: &IndentationConfig) -> (usize, usize) {
     let mut byte_indent = 0;
@@ -390,6 +476,71 @@ mod tests {
         assert_eq!(text, String::from("function(\n        ) {\n  \n}"));
     }
 
+    #[test]
+    fn test_line_indent_arguments() {
+        // Aligned with the first argument
+        let mut text = String::from("fun_call(argument1,\nargument2)");
+        let doc = test_doc(&text);
+
+        let edit = indent_edit(&doc, 1).unwrap().unwrap();
+        apply_text_edits(edit, &mut text).unwrap();
+        assert_eq!(
+            text,
+            String::from("fun_call(argument1,\n         argument2)")
+        );
+
+        // Indented from the call's beginning of line
+        let mut text = String::from("  fun_call(\nargument1,\n    argument2\n  )");
+        let doc = test_doc(&text);
+
+        let edit = indent_edit(&doc, 1).unwrap().unwrap();
+        apply_text_edits(edit, &mut text).unwrap();
+        assert_eq!(
+            text,
+            String::from("  fun_call(\n    argument1,\n    argument2\n  )")
+        );
+
+        // Parameters of function definitions
+        let mut text = String::from("function(x,\n  y) {}");
+        let doc = test_doc(&text);
+
+        let edit = indent_edit(&doc, 1).unwrap().unwrap();
+        apply_text_edits(edit, &mut text).unwrap();
+        assert_eq!(text, String::from("function(x,\n         y) {}"));
+
+        // Comma-first styles are left alone
+        let doc = test_doc("fun_call(argument1\n, argument2)");
+        assert_match!(indent_edit(&doc, 1), Ok(None));
+    }
+
+    #[test]
+    fn test_line_indent_arguments_closing() {
+        let mut text = String::from("  fun_call(\n    argument\n    )");
+        let doc = test_doc(&text);
+
+        let edit = indent_edit(&doc, 2).unwrap().unwrap();
+        apply_text_edits(edit, &mut text).unwrap();
+        assert_eq!(text, String::from("  fun_call(\n    argument\n  )"));
+
+        let mut text = String::from("object[\n  argument\n  ]");
+        let doc = test_doc(&text);
+
+        let edit = indent_edit(&doc, 2).unwrap().unwrap();
+        apply_text_edits(edit, &mut text).unwrap();
+        assert_eq!(text, String::from("object[\n  argument\n]"));
+    }
+
+    #[test]
+    fn test_line_indent_arguments_closing_multiline() {
+        // The closing delimiter is outdented along with the new line
+        let mut text = String::from("fun_call(\n\n  )");
+        let doc = test_doc(&text);
+
+        let edit = indent_edit(&doc, 1).unwrap().unwrap();
+        apply_text_edits(edit, &mut text).unwrap();
+        assert_eq!(text, String::from("fun_call(\n  \n)"));
+    }
+
     #[test]
     fn test_new_line_indent() {
         let tab_cfg = IndentationConfig {
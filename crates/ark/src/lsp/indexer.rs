@@ -6,7 +6,9 @@
 //
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::Path;
+use std::path::PathBuf;
 use std::result::Result::Ok;
 use std::sync::Arc;
 use std::sync::LazyLock;
@@ -25,8 +27,12 @@ use walkdir::WalkDir;
 use crate::lsp;
 use crate::lsp::documents::Document;
 use crate::lsp::encoding::convert_point_to_position;
+use crate::lsp::pool::Cancellation;
+use crate::lsp::progress::Progress;
+use crate::lsp::traits::cursor::TreeCursorExt;
 use crate::lsp::traits::rope::RopeExt;
 use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::ExtractOperatorType;
 use crate::treesitter::NodeType;
 use crate::treesitter::NodeTypeExt;
 
@@ -40,6 +46,25 @@ pub enum IndexEntryData {
         level: usize,
         title: String,
     },
+    /// Class created with `setClass()`, `setRefClass()`, or `R6Class()`
+    Class {
+        name: String,
+        system: ClassSystem,
+        /// Variable the generator of the class is assigned to, e.g. `Foo` in
+        /// `Foo <- R6Class("Foo")`
+        generator: Option<String>,
+    },
+    /// S4 generic created with `setGeneric()`
+    Generic {
+        name: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClassSystem {
+    S4,
+    RefClass,
+    R6,
 }
 
 #[derive(Clone, Debug)]
@@ -49,33 +74,91 @@ pub struct IndexEntry {
     pub data: IndexEntryData,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum ReferenceKind {
+    SymbolName, // a regular R symbol
+    DollarName, // a dollar name, following '$'
+    AtName,     // a slot name, following '@'
+}
+
 type DocumentPath = String;
 type DocumentSymbol = String;
 type DocumentSymbolIndex = HashMap<DocumentSymbol, IndexEntry>;
 type WorkspaceIndex = Arc<Mutex<HashMap<DocumentPath, DocumentSymbolIndex>>>;
 
+/// Ranges of the identifiers of a document, by kind and name
+type DocumentReferenceIndex = HashMap<(ReferenceKind, DocumentSymbol), Vec<Range>>;
+type WorkspaceReferenceIndex = Mutex<HashMap<DocumentPath, DocumentReferenceIndex>>;
+
 static WORKSPACE_INDEX: LazyLock<WorkspaceIndex> = LazyLock::new(|| Default::default());
+
+/// Updated along with `WORKSPACE_INDEX`, so that finding references doesn't
+/// require parsing the files of the workspace
+static WORKSPACE_REFERENCES: LazyLock<WorkspaceReferenceIndex> =
+    LazyLock::new(|| Default::default());
+
+/// Files open in the editor. They are indexed from the editor contents with
+/// `update()` and never from disk, which may be out of date.
+static OPEN_PATHS: LazyLock<Mutex<HashSet<DocumentPath>>> = LazyLock::new(|| Default::default());
 pub static RE_COMMENT_SECTION: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^\s*(#+)\s*(.*?)\s*[#=-]{4,}\s*$").unwrap());
 
+/// Index the R files of the workspace folders, except for those `is_excluded`
+/// by the project settings. Files indexed by a previous run that are now
+/// excluded are forgotten.
 #[tracing::instrument(level = "info", skip_all)]
-pub fn start(folders: Vec<String>) {
+pub(crate) fn start(
+    folders: Vec<String>,
+    is_excluded: impl Fn(&Path) -> bool,
+    cancellation: &Cancellation,
+) {
     let now = std::time::Instant::now();
     lsp::log_info!("Initial indexing started");
 
+    let mut progress = Progress::begin("Indexing R workspace", true);
+
+    // Open files are indexed from the editor contents, excluded or not
+    let is_stale = |path: &str| is_excluded(Path::new(path)) && !is_open(Path::new(path));
+    WORKSPACE_INDEX
+        .lock()
+        .unwrap()
+        .retain(|path, _| !is_stale(path));
+    WORKSPACE_REFERENCES
+        .lock()
+        .unwrap()
+        .retain(|path, _| !is_stale(path));
+
+    // Collect files upfront so we can report progress as a percentage
+    let mut paths: Vec<PathBuf> = Vec::new();
     for folder in folders {
         let walker = WalkDir::new(folder);
-        for entry in walker.into_iter().filter_entry(|e| filter_entry(e)) {
+        let walker = walker
+            .into_iter()
+            .filter_entry(|e| filter_entry(e) && !is_excluded(e.path()));
+        for entry in walker {
             if let Ok(entry) = entry {
                 if entry.file_type().is_file() {
-                    if let Err(err) = index_file(entry.path()) {
-                        lsp::log_error!("Can't index file {:?}: {err:?}", entry.path());
-                    }
+                    paths.push(entry.into_path());
                 }
             }
         }
     }
 
+    for (i, path) in paths.iter().enumerate() {
+        if progress.is_cancelled() || cancellation.is_cancelled() {
+            lsp::log_info!(
+                "Initial indexing cancelled after {}ms",
+                now.elapsed().as_millis()
+            );
+            return;
+        }
+
+        if let Err(err) = index_file(path) {
+            lsp::log_error!("Can't index file {path:?}: {err:?}");
+        }
+        progress.report(i + 1, paths.len());
+    }
+
     lsp::log_info!(
         "Initial indexing finished after {}ms",
         now.elapsed().as_millis()
@@ -94,6 +177,113 @@ pub fn find(symbol: &str) -> Option<(String, IndexEntry)> {
     None
 }
 
+/// Like `find()` but skips sections, whose titles may look like symbols
+pub(crate) fn find_function(symbol: &str) -> Option<(String, IndexEntry)> {
+    let index = WORKSPACE_INDEX.lock().unwrap();
+
+    for (path, index) in index.iter() {
+        if let Some(entry) = index.get(symbol) {
+            if matches!(entry.data, IndexEntryData::Function { .. }) {
+                return Some((path.clone(), entry.clone()));
+            }
+        }
+    }
+
+    None
+}
+
+/// Find a top-level function definition in a document that isn't necessarily
+/// indexed, e.g. an untitled buffer
+pub(crate) fn find_in_document(document: &Document, symbol: &str) -> Option<IndexEntry> {
+    let root = document.ast.root_node();
+    let mut cursor = root.walk();
+
+    // The last definition wins, as when the document is sourced
+    let mut out = None;
+    for node in root.children(&mut cursor) {
+        if let Ok(Some(entry)) = index_function(Path::new(""), &document.contents, &node) {
+            if entry.key == symbol {
+                out = Some(entry);
+            }
+        }
+    }
+
+    out
+}
+
+/// Occurrences of `symbol` in indexed files, sorted by file
+pub(crate) fn find_references(kind: ReferenceKind, symbol: &str) -> Vec<(PathBuf, Range)> {
+    let index = WORKSPACE_REFERENCES.lock().unwrap();
+    let key = (kind, symbol.to_string());
+
+    let mut paths: Vec<&DocumentPath> = index.keys().collect();
+    paths.sort();
+
+    let mut out = Vec::new();
+    for path in paths {
+        if let Some(ranges) = index[path].get(&key) {
+            out.extend(ranges.iter().map(|range| (PathBuf::from(path), *range)));
+        }
+    }
+
+    out
+}
+
+/// Find a class by name or by the name of its generator
+pub(crate) fn find_class(name: &str) -> Option<(String, IndexEntry)> {
+    let index = WORKSPACE_INDEX.lock().unwrap();
+
+    for (path, index) in index.iter() {
+        if let Some(entry) = index.values().find(|entry| entry.is_class(name)) {
+            return Some((path.clone(), entry.clone()));
+        }
+    }
+
+    None
+}
+
+/// Find an S4 generic by name
+pub(crate) fn find_generic(name: &str) -> Option<(String, IndexEntry)> {
+    let index = WORKSPACE_INDEX.lock().unwrap();
+
+    for (path, index) in index.iter() {
+        if let Some(entry) = index.get(name) {
+            if matches!(entry.data, IndexEntryData::Generic { .. }) {
+                return Some((path.clone(), entry.clone()));
+            }
+        }
+    }
+
+    None
+}
+
+/// Index the top-level definitions of a document that isn't necessarily
+/// indexed, e.g. an untitled buffer
+pub(crate) fn index_entries(document: &Document) -> Vec<IndexEntry> {
+    let root = document.ast.root_node();
+    let mut cursor = root.walk();
+
+    root.children(&mut cursor)
+        .filter_map(|node| index_node(Path::new(""), &document.contents, &node).ok())
+        .flatten()
+        .collect()
+}
+
+impl IndexEntry {
+    /// Whether this is the class `name`, or the class whose generator is
+    /// `name`
+    pub(crate) fn is_class(&self, name: &str) -> bool {
+        match &self.data {
+            IndexEntryData::Class {
+                name: class,
+                generator,
+                ..
+            } => class == name || generator.as_deref() == Some(name),
+            _ => false,
+        }
+    }
+}
+
 pub fn map(mut callback: impl FnMut(&Path, &String, &IndexEntry)) {
     let index = WORKSPACE_INDEX.lock().unwrap();
 
@@ -112,6 +302,45 @@ pub fn update(document: &Document, path: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Mark a file as open in the editor or closed
+pub(crate) fn set_open(path: &Path, open: bool) -> anyhow::Result<()> {
+    let path = str_from_path(path)?;
+    let mut paths = OPEN_PATHS.lock().unwrap();
+
+    if open {
+        paths.insert(path.to_string());
+    } else {
+        paths.remove(path);
+    }
+
+    Ok(())
+}
+
+fn is_open(path: &Path) -> bool {
+    let Ok(path) = str_from_path(path) else {
+        return false;
+    };
+    OPEN_PATHS.lock().unwrap().contains(path)
+}
+
+/// Index a file again from disk after it changed outside of the editor
+#[tracing::instrument(level = "trace", skip_all, fields(path = ?path))]
+pub(crate) fn reindex(path: &Path) -> anyhow::Result<()> {
+    if is_open(path) {
+        return Ok(());
+    }
+    clear(path)?;
+    index_file(path)
+}
+
+/// Forget the symbols of a deleted file
+pub(crate) fn remove(path: &Path) -> anyhow::Result<()> {
+    let path = str_from_path(path)?;
+    WORKSPACE_INDEX.lock().unwrap().remove(path);
+    WORKSPACE_REFERENCES.lock().unwrap().remove(path);
+    Ok(())
+}
+
 fn insert(path: &Path, entry: IndexEntry) -> anyhow::Result<()> {
     let mut index = WORKSPACE_INDEX.lock().unwrap();
     let path = str_from_path(path)?;
@@ -172,8 +401,12 @@ fn index_file(path: &Path) -> anyhow::Result<()> {
         return Ok(());
     }
 
+    // The editor contents are the source of truth for open files
+    if is_open(path) {
+        return Ok(());
+    }
+
     // TODO: Handle document encodings here.
-    // TODO: Check if there's an up-to-date buffer to be used.
     let contents = std::fs::read(path)?;
     let contents = String::from_utf8(contents)?;
     let document = Document::new(contents.as_str(), None);
@@ -187,6 +420,17 @@ fn index_document(document: &Document, path: &Path) {
     let ast = &document.ast;
     let contents = &document.contents;
 
+    match str_from_path(path) {
+        Ok(path) => {
+            let references = index_references(document);
+            WORKSPACE_REFERENCES
+                .lock()
+                .unwrap()
+                .insert(path.to_string(), references);
+        },
+        Err(err) => lsp::log_error!("Can't index references: {err:?}"),
+    }
+
     let root = ast.root_node();
     let mut cursor = root.walk();
     for node in root.children(&mut cursor) {
@@ -200,6 +444,62 @@ fn index_document(document: &Document, path: &Path) {
     }
 }
 
+fn index_references(document: &Document) -> DocumentReferenceIndex {
+    let contents = &document.contents;
+    let mut index = DocumentReferenceIndex::new();
+
+    let mut cursor = document.ast.walk();
+    cursor.recurse(|node| {
+        if !node.is_identifier() {
+            return true;
+        }
+        let Ok(symbol) = contents.node_slice(&node) else {
+            return true;
+        };
+
+        let start = convert_point_to_position(contents, node.start_position());
+        let end = convert_point_to_position(contents, node.end_position());
+
+        index
+            .entry((node_reference_kind(&node), symbol.to_string()))
+            .or_default()
+            .push(Range::new(start, end));
+
+        true
+    });
+
+    index
+}
+
+// Assuming `x` is an `identifier`, is it the RHS of a `$` or `@`?
+pub(crate) fn node_reference_kind(x: &Node) -> ReferenceKind {
+    let Some(parent) = x.parent() else {
+        // No `parent`, must be a regular symbol
+        return ReferenceKind::SymbolName;
+    };
+
+    let parent_type = parent.node_type();
+
+    if !matches!(parent_type, NodeType::ExtractOperator(_)) {
+        // Parent not `$` or `@`
+        return ReferenceKind::SymbolName;
+    }
+
+    // Need to check that we actually came from the RHS
+    let Some(rhs) = parent.child_by_field_name("rhs") else {
+        return ReferenceKind::SymbolName;
+    };
+    if &rhs != x {
+        return ReferenceKind::SymbolName;
+    };
+
+    match parent_type {
+        NodeType::ExtractOperator(ExtractOperatorType::Dollar) => ReferenceKind::DollarName,
+        NodeType::ExtractOperator(ExtractOperatorType::At) => ReferenceKind::AtName,
+        _ => std::unreachable!(),
+    }
+}
+
 fn index_node(path: &Path, contents: &Rope, node: &Node) -> anyhow::Result<Option<IndexEntry>> {
     if let Ok(Some(entry)) = index_function(path, contents, node) {
         return Ok(Some(entry));
@@ -209,9 +509,94 @@ fn index_node(path: &Path, contents: &Rope, node: &Node) -> anyhow::Result<Optio
         return Ok(Some(entry));
     }
 
+    if let Ok(Some(entry)) = index_class_or_generic(path, contents, node) {
+        return Ok(Some(entry));
+    }
+
     Ok(None)
 }
 
+/// Index `setClass("Foo")`, `Foo <- R6Class("Foo")`, `setGeneric("foo")`, and
+/// similar calls. The entry points at the name of the class or generic.
+fn index_class_or_generic(
+    _path: &Path,
+    contents: &Rope,
+    node: &Node,
+) -> anyhow::Result<Option<IndexEntry>> {
+    let (generator, call) = match node.node_type() {
+        NodeType::BinaryOperator(BinaryOperatorType::LeftAssignment) |
+        NodeType::BinaryOperator(BinaryOperatorType::EqualsAssignment) => {
+            let lhs = node.child_by_field_name("lhs").into_result()?;
+            lhs.is_identifier_or_string().into_result()?;
+            let rhs = node.child_by_field_name("rhs").into_result()?;
+            (Some(contents.node_slice(&lhs)?.to_string()), rhs)
+        },
+        _ => (None, *node),
+    };
+
+    call.is_call().into_result()?;
+
+    // Allow namespaced calls like `R6::R6Class()`
+    let mut fun = call.child_by_field_name("function").into_result()?;
+    if fun.is_namespace_operator() {
+        fun = fun.child_by_field_name("rhs").into_result()?;
+    }
+    let fun = contents.node_slice(&fun)?.to_string();
+
+    let system = match fun.as_str() {
+        "setClass" => Some(ClassSystem::S4),
+        "setRefClass" => Some(ClassSystem::RefClass),
+        "R6Class" => Some(ClassSystem::R6),
+        "setGeneric" => None,
+        _ => return Ok(None),
+    };
+
+    // The name is the first argument
+    let arguments = call.child_by_field_name("arguments").into_result()?;
+    let mut cursor = arguments.walk();
+    let first = arguments
+        .children_by_field_name("argument", &mut cursor)
+        .next()
+        .into_result()?;
+    let name_node = first.child_by_field_name("value").into_result()?;
+    let name = string_value(&name_node, contents).into_result()?;
+
+    let start = convert_point_to_position(contents, name_node.start_position());
+    let end = convert_point_to_position(contents, name_node.end_position());
+
+    let data = match system {
+        Some(system) => IndexEntryData::Class {
+            name: name.clone(),
+            system,
+            generator,
+        },
+        None => IndexEntryData::Generic { name: name.clone() },
+    };
+
+    Ok(Some(IndexEntry {
+        key: name,
+        range: Range { start, end },
+        data,
+    }))
+}
+
+/// Contents of a string literal, without the quotes
+pub(crate) fn string_value(node: &Node, contents: &Rope) -> Option<String> {
+    if !node.is_string() {
+        return None;
+    }
+
+    let mut cursor = node.walk();
+    let value = node
+        .children(&mut cursor)
+        .find(|child| child.node_type() == NodeType::StringContent)
+        .and_then(|content| contents.node_slice(&content).ok())
+        .map(|content| content.to_string());
+
+    // Empty strings have no content node
+    Some(value.unwrap_or_default())
+}
+
 fn index_function(
     _path: &Path,
     contents: &Rope,
@@ -243,7 +628,7 @@ fn index_function(
     let mut cursor = parameters.walk();
     for child in parameters.children(&mut cursor) {
         let name = unwrap!(child.child_by_field_name("name"), None => continue);
-        if name.is_identifier() {
+        if name.is_identifier() || name.node_type() == NodeType::Dots {
             let name = contents.node_slice(&name)?.to_string();
             arguments.push(name);
         }
@@ -292,3 +677,50 @@ fn index_comment(_path: &Path, contents: &Rope, node: &Node) -> anyhow::Result<O
         data: IndexEntryData::Section { level, title },
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::path::PathBuf;
+
+    use crate::lsp::documents::Document;
+    use crate::lsp::indexer::find_references;
+    use crate::lsp::indexer::remove;
+    use crate::lsp::indexer::update;
+    use crate::lsp::indexer::ReferenceKind;
+
+    #[test]
+    fn test_find_references() {
+        let path = Path::new("/ark-test-indexer/references.R");
+        let lines = |refs: Vec<(PathBuf, tower_lsp::lsp_types::Range)>| -> Vec<u32> {
+            refs.into_iter()
+                .filter(|(p, _)| p == path)
+                .map(|(_, range)| range.start.line)
+                .collect()
+        };
+
+        let document = Document::new("foo <- 1\nfoo$foo\nbar(foo)", None);
+        update(&document, path).unwrap();
+
+        assert_eq!(
+            lines(find_references(ReferenceKind::SymbolName, "foo")),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            lines(find_references(ReferenceKind::DollarName, "foo")),
+            vec![1]
+        );
+
+        // Updates replace the references of the file
+        let document = Document::new("bar <- 1", None);
+        update(&document, path).unwrap();
+        assert!(lines(find_references(ReferenceKind::SymbolName, "foo")).is_empty());
+        assert_eq!(
+            lines(find_references(ReferenceKind::SymbolName, "bar")),
+            vec![0]
+        );
+
+        remove(path).unwrap();
+        assert!(lines(find_references(ReferenceKind::SymbolName, "bar")).is_empty());
+    }
+}
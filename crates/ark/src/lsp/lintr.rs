@@ -0,0 +1,189 @@
+//
+// lintr.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// lintr is an optional provider of diagnostics that complements the native
+// ones with style checks and the linters configured in the `.lintr` file of
+// the project. It is turned on with the `positron.r.diagnostics.lintr`
+// setting or `lintr = true` in the `[diagnostics]` section of `.ark.toml`.
+//
+// lintr reads files from disk, so documents are linted when they are saved.
+// The file is linted by an idle R task under a time limit, so that it never
+// holds up the console. The lints are sent to the main loop, which adds them
+// to the diagnostics of the document as long as it hasn't been edited since.
+// Lints are reported with source `lintr` and the name of their linter as
+// code, e.g. `object_name_linter`. Lints that start where a native diagnostic
+// does are dropped, e.g. the parse errors that both report.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::object::RObject;
+use harp::utils::r_is_null;
+use tower_lsp::lsp_types::Diagnostic;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+use tower_lsp::lsp_types::NumberOrString;
+use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::Range;
+use url::Url;
+
+use crate::interface::RMain;
+use crate::lsp::hover_evaluation::with_time_limit;
+use crate::lsp::main_loop::KernelNotification;
+use crate::modules::ARK_ENVS;
+use crate::r_task;
+
+/// Time budget of lintr for a document. Lints are skipped when it runs out.
+const LINTR_TIME_LIMIT: Duration = Duration::from_secs(5);
+
+/// Lints of the version of a document that was saved
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Lints {
+    pub version: Option<i32>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Lint the file of a saved document once R is idle. The lints are published
+/// to the main loop with `KernelNotification::DidPublishLints`.
+pub(crate) fn spawn_lintr(uri: Url, path: PathBuf, version: Option<i32>) {
+    r_task::spawn_idle(move || async move {
+        let diagnostics = match lint(&path) {
+            Ok(diagnostics) => diagnostics,
+            Err(err) => {
+                log::warn!("Can't lint '{}': {err:?}", path.display());
+                return;
+            },
+        };
+
+        let lints = Lints {
+            version,
+            diagnostics,
+        };
+        RMain::get().send_lsp_notification(KernelNotification::DidPublishLints(uri, lints));
+    });
+}
+
+/// Lint a file with lintr. Must be called on the R thread. lintr not being
+/// installed results in no lints.
+fn lint(path: &Path) -> anyhow::Result<Vec<Diagnostic>> {
+    let path = path.to_string_lossy().to_string();
+
+    let lints = with_time_limit(LINTR_TIME_LIMIT, || {
+        Ok(RFunction::new("", "lintr_lints")
+            .add(path)
+            .call_in(ARK_ENVS.positron_ns)?)
+    })?;
+
+    if r_is_null(lints.sexp) {
+        return Ok(vec![]);
+    }
+
+    let lints: Vec<RObject> = lints.try_into()?;
+    lints.into_iter().map(lint_diagnostic).collect()
+}
+
+/// Convert a lint record of `lintr_lints()` to a diagnostic. lintr positions
+/// are 1-based and the end column is inclusive.
+fn lint_diagnostic(lint: RObject) -> anyhow::Result<Diagnostic> {
+    let line: i32 = lint.vector_elt(0)?.try_into()?;
+    let column: i32 = lint.vector_elt(1)?.try_into()?;
+    let end_column: i32 = lint.vector_elt(2)?.try_into()?;
+    let kind: String = lint.vector_elt(3)?.try_into()?;
+    let message: String = lint.vector_elt(4)?.try_into()?;
+    let linter: String = lint.vector_elt(5)?.try_into()?;
+
+    let line = (line - 1).max(0) as u32;
+    let start = (column - 1).max(0) as u32;
+    let end = end_column.max(column).max(1) as u32;
+
+    let severity = match kind.as_str() {
+        "error" => DiagnosticSeverity::ERROR,
+        "warning" => DiagnosticSeverity::WARNING,
+        _ => DiagnosticSeverity::INFORMATION,
+    };
+
+    Ok(Diagnostic {
+        range: Range::new(Position::new(line, start), Position::new(line, end)),
+        severity: Some(severity),
+        code: Some(NumberOrString::String(linter)),
+        source: Some(String::from("lintr")),
+        message,
+        ..Default::default()
+    })
+}
+
+/// Add the lints to the native diagnostics of a document, except those that
+/// start where a native diagnostic does
+pub(crate) fn merge_lints(diagnostics: &mut Vec<Diagnostic>, lints: Vec<Diagnostic>) {
+    let lints: Vec<Diagnostic> = lints
+        .into_iter()
+        .filter(|lint| {
+            !diagnostics
+                .iter()
+                .any(|diagnostic| diagnostic.range.start == lint.range.start)
+        })
+        .collect();
+
+    diagnostics.extend(lints);
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Diagnostic;
+    use tower_lsp::lsp_types::DiagnosticSeverity;
+    use tower_lsp::lsp_types::NumberOrString;
+    use tower_lsp::lsp_types::Position;
+    use tower_lsp::lsp_types::Range;
+
+    use crate::lsp::lintr::lint_diagnostic;
+    use crate::lsp::lintr::merge_lints;
+    use crate::r_task;
+
+    #[test]
+    fn test_lint_diagnostic() {
+        r_task(|| {
+            let lint = harp::parse_eval_base(
+                "list(line = 2L, column = 3L, end_column = 7L, type = 'style', message = 'Use snake_case.', linter = 'object_name_linter')",
+            )
+            .unwrap();
+
+            let diagnostic = lint_diagnostic(lint).unwrap();
+            assert_eq!(
+                diagnostic.range,
+                Range::new(Position::new(1, 2), Position::new(1, 7))
+            );
+            assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::INFORMATION));
+            assert_eq!(
+                diagnostic.code,
+                Some(NumberOrString::String(String::from("object_name_linter")))
+            );
+            assert_eq!(diagnostic.source.as_deref(), Some("lintr"));
+            assert_eq!(diagnostic.message, "Use snake_case.");
+        })
+    }
+
+    #[test]
+    fn test_merge_lints() {
+        let diagnostic = |line: u32, character: u32, message: &str| Diagnostic {
+            range: Range::new(
+                Position::new(line, character),
+                Position::new(line, character + 1),
+            ),
+            message: String::from(message),
+            ..Default::default()
+        };
+
+        let mut diagnostics = vec![diagnostic(0, 4, "native")];
+        let lints = vec![diagnostic(0, 4, "duplicate"), diagnostic(1, 0, "lint")];
+        merge_lints(&mut diagnostics, lints);
+
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["native", "lint"]);
+    }
+}
@@ -6,16 +6,30 @@
 //
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::future;
+use std::future::Future;
 use std::pin::Pin;
 
 use anyhow::anyhow;
 use futures::StreamExt;
+use stdext::cancellation::CancellationToken;
 use tokio::sync::mpsc::unbounded_channel as tokio_unbounded_channel;
 use tokio::task::JoinHandle;
 use tower_lsp::lsp_types;
+use tower_lsp::lsp_types::notification::Progress as ProgressNotification;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::CompletionParams;
 use tower_lsp::lsp_types::Diagnostic;
+use tower_lsp::lsp_types::DidCloseTextDocumentParams;
 use tower_lsp::lsp_types::MessageType;
+use tower_lsp::lsp_types::NumberOrString;
+use tower_lsp::lsp_types::ProgressParams;
+use tower_lsp::lsp_types::ProgressParamsValue;
+use tower_lsp::lsp_types::ProgressToken;
+use tower_lsp::lsp_types::TextDocumentIdentifier;
+use tower_lsp::lsp_types::WorkDoneProgress;
+use tower_lsp::lsp_types::WorkDoneProgressCreateParams;
 use tower_lsp::Client;
 use url::Url;
 
@@ -24,9 +38,17 @@ use crate::lsp::backend::LspMessage;
 use crate::lsp::backend::LspNotification;
 use crate::lsp::backend::LspRequest;
 use crate::lsp::backend::LspResponse;
+use crate::lsp::client_documents::ClientDocuments;
 use crate::lsp::diagnostics;
 use crate::lsp::documents::Document;
+use crate::lsp::generated;
 use crate::lsp::handlers;
+use crate::lsp::lintr;
+use crate::lsp::lintr::Lints;
+use crate::lsp::progress;
+use crate::lsp::progress::Progress;
+use crate::lsp::progress::ProgressEvent;
+use crate::lsp::session::ClientId;
 use crate::lsp::state::WorldState;
 use crate::lsp::state_handlers;
 use crate::lsp::state_handlers::ConsoleInputs;
@@ -57,20 +79,31 @@ type TaskList<T> = futures::stream::FuturesUnordered<Pin<Box<dyn AnyhowJoinHandl
 
 #[derive(Debug)]
 pub(crate) enum Event {
-    Lsp(LspMessage),
+    Lsp(ClientId, LspMessage),
     Kernel(KernelNotification),
+    Client(ClientEvent),
+}
+
+#[derive(Debug)]
+pub(crate) enum ClientEvent {
+    Connected(ClientId, Client),
+    Disconnected(ClientId),
 }
 
 #[derive(Debug)]
 pub(crate) enum KernelNotification {
     DidChangeConsoleInputs(ConsoleInputs),
+    DidPublishPackageProblems(HashMap<Url, Vec<Diagnostic>>),
+    DidPublishLints(Url, Lints),
 }
 
 #[derive(Debug)]
 pub(crate) enum AuxiliaryEvent {
     Log(lsp_types::MessageType, String),
     PublishDiagnostics(Url, Vec<Diagnostic>, Option<i32>),
+    Progress(ProgressEvent),
     SpawnedTask(JoinHandle<anyhow::Result<Option<AuxiliaryEvent>>>),
+    Client(ClientEvent),
 }
 
 /// Global state for the main loop
@@ -80,6 +113,10 @@ pub(crate) enum AuxiliaryEvent {
 /// the heart of the LSP. The tower-lsp backend and the Jupyter kernel
 /// communicate with the main loop through the `Event` channel that is passed on
 /// construction.
+///
+/// The global state is owned by the `LspSession` and outlives connections.
+/// Each connected client is tracked in `clients` and the world state is
+/// shared between them.
 pub(crate) struct GlobalState {
     /// The global world state containing all inputs for LSP analysis lives
     /// here. The dispatcher provides refs, exclusive refs, or snapshots
@@ -91,15 +128,34 @@ pub(crate) struct GlobalState {
     /// handlers, and is not cloneable.
     lsp_state: LspState,
 
-    /// LSP client shared with tower-lsp and the log loop
-    client: Client,
+    /// Connected LSP clients, shared with tower-lsp and the log loop
+    clients: HashMap<ClientId, ClientState>,
+
+    /// Contents of documents opened by several clients. The world state
+    /// follows the last client that opened a document, the other clients keep
+    /// their own copy.
+    client_documents: ClientDocuments,
+
+    /// Workspace folders covered by the last round of indexing. A client
+    /// reconnecting to the same workspace doesn't trigger a new round.
+    indexed_folders: Option<Vec<Url>>,
 
     /// Event channels for the main loop. The tower-lsp methods forward
     /// notifications and requests here via `Event::Lsp`. We also receive
-    /// messages from the kernel via `Event::Kernel`, and from ourselves via
-    /// `Event::Task`.
+    /// messages from the kernel via `Event::Kernel`, and connections and
+    /// disconnections of clients via `Event::Client`.
     events_tx: TokioUnboundedSender<Event>,
     events_rx: TokioUnboundedReceiver<Event>,
+
+    /// The latest completion request. Completions run concurrently with the
+    /// main loop and are cancelled once superseded, see `spawn_completion()`.
+    completion: Option<PendingCompletion>,
+}
+
+struct PendingCompletion {
+    /// Token the client may cancel with `window/workDoneProgress/cancel`
+    work_done_token: Option<ProgressToken>,
+    cancellation: CancellationToken,
 }
 
 /// Unlike `WorldState`, `ParserState` cannot be cloned and is only accessed by
@@ -108,15 +164,25 @@ pub(crate) struct GlobalState {
 pub(crate) struct LspState {
     /// The set of tree-sitter document parsers managed by the `GlobalState`.
     pub(crate) parsers: HashMap<Url, tree_sitter::Parser>,
+}
+
+/// State of a connected client
+pub(crate) struct ClientState {
+    pub(crate) client: Client,
 
     /// List of capabilities for which we need to send a registration request
     /// when we get the `Initialized` notification.
     pub(crate) needs_registration: ClientCaps,
+
+    /// Documents opened by the client. A document is closed once no client
+    /// has it open.
+    pub(crate) open_documents: HashSet<Url>,
 }
 
 #[derive(Debug, Default)]
 pub(crate) struct ClientCaps {
     pub(crate) did_change_configuration: bool,
+    pub(crate) did_change_watched_files: bool,
 }
 
 /// State for the auxiliary loop
@@ -127,21 +193,23 @@ pub(crate) struct ClientCaps {
 ///
 /// The auxiliary loop currently handles:
 /// - Log messages.
+/// - Progress notifications.
 /// - Joining of spawned blocking tasks to relay any errors or panics to the LSP log.
+///
+/// Log messages, diagnostics, and progress notifications are sent to all
+/// connected clients.
 struct AuxiliaryState {
-    client: Client,
+    clients: HashMap<ClientId, Client>,
     auxiliary_event_rx: TokioUnboundedReceiver<AuxiliaryEvent>,
     tasks: TaskList<Option<AuxiliaryEvent>>,
+
+    /// Progress tokens that clients have acknowledged and not yet ended
+    progress_tokens: HashSet<(ClientId, String)>,
 }
 
 impl GlobalState {
-    /// Create a new global state
-    ///
-    /// # Arguments
-    ///
-    /// * `client`: The tower-lsp client shared with the tower-lsp backend
-    ///   and auxiliary loop.
-    pub(crate) fn new(client: Client) -> Self {
+    /// Create a new global state. Clients are added as they connect.
+    pub(crate) fn new() -> Self {
         // Transmission channel for the main loop events. Shared with the
         // tower-lsp backend and the Jupyter kernel.
         let (events_tx, events_rx) = tokio_unbounded_channel::<Event>();
@@ -149,9 +217,12 @@ impl GlobalState {
         Self {
             world: WorldState::default(),
             lsp_state: LspState::default(),
-            client,
+            clients: HashMap::new(),
+            client_documents: ClientDocuments::default(),
+            indexed_folders: None,
             events_tx,
             events_rx,
+            completion: None,
         }
     }
 
@@ -169,7 +240,7 @@ impl GlobalState {
 
         // Spawn latency-sensitive auxiliary loop. Must be first to initialise
         // global transmission channel.
-        let aux = AuxiliaryState::new(self.client.clone());
+        let aux = AuxiliaryState::new();
         set.spawn(async move { aux.start().await });
 
         // Spawn main loop
@@ -213,34 +284,59 @@ impl GlobalState {
         let loop_tick = std::time::Instant::now();
 
         match event {
-            Event::Lsp(msg) => match msg {
+            Event::Lsp(client_id, msg) => match msg {
                 LspMessage::Notification(notif) => {
                     lsp::log_info!("{notif:#?}");
 
                     match notif {
                         LspNotification::Initialized(_params) => {
-                            handlers::handle_initialized(&self.client, &self.lsp_state).await?;
+                            let client = self.client(client_id)?;
+                            handlers::handle_initialized(&client.client, &client.needs_registration).await?;
+                            self.index_workspace()?;
                         },
                         LspNotification::DidChangeWorkspaceFolders(_params) => {
                             // TODO: Restart indexer with new folders.
                         },
                         LspNotification::DidChangeConfiguration(params) => {
-                            state_handlers::did_change_configuration(params, &self.client, &mut self.world).await?;
+                            let client = self.client(client_id)?.client.clone();
+                            state_handlers::did_change_configuration(params, &client, &mut self.world).await?;
                         },
-                        LspNotification::DidChangeWatchedFiles(_params) => {
-                            // TODO: Re-index the changed files.
+                        LspNotification::DidChangeWatchedFiles(params) => {
+                            let client = self.client(client_id)?.client.clone();
+                            state_handlers::did_change_watched_files(params, &client, &mut self.world).await?;
                         },
                         LspNotification::DidOpenTextDocument(params) => {
+                            let uri = params.text_document.uri.clone();
+                            self.client_mut(client_id)?.open_documents.insert(uri.clone());
+                            self.client_documents.open(client_id, &uri, &mut self.lsp_state, &self.world);
                             state_handlers::did_open(params, &mut self.lsp_state, &mut self.world)?;
                         },
                         LspNotification::DidChangeTextDocument(params) => {
-                            state_handlers::did_change(params, &mut self.lsp_state, &mut self.world)?;
+                            if self.client_documents.is_owner(client_id, &params.text_document.uri) {
+                                state_handlers::did_change(params, &mut self.lsp_state, &mut self.world)?;
+                            } else {
+                                self.client_documents.did_change(client_id, &params)?;
+                            }
                         },
-                        LspNotification::DidSaveTextDocument(_params) => {
-                            // Currently ignored
+                        LspNotification::DidSaveTextDocument(params) => {
+                            state_handlers::did_save(params, &mut self.world)?;
                         },
                         LspNotification::DidCloseTextDocument(params) => {
-                            state_handlers::did_close(params, &mut self.lsp_state, &mut self.world)?;
+                            self.client_mut(client_id)?.open_documents.remove(&params.text_document.uri);
+                            self.close_document(client_id, params.text_document.uri);
+                        },
+                        LspNotification::WorkDoneProgressCancel(params) => {
+                            progress::cancel(&params.token);
+                            self.cancel_completion(Some(&params.token));
+                        },
+                        LspNotification::DidOpenVirtualDocument(params) => {
+                            state_handlers::did_open_virtual_document(params, &mut self.world);
+                        },
+                        LspNotification::DidChangeVirtualDocument(params) => {
+                            state_handlers::did_change_virtual_document(params, &mut self.world)?;
+                        },
+                        LspNotification::DidCloseVirtualDocument(params) => {
+                            state_handlers::did_close_virtual_document(params, &mut self.world)?;
                         },
                     }
                 },
@@ -250,10 +346,14 @@ impl GlobalState {
 
                     match request {
                         LspRequest::Initialize(params) => {
-                            respond(tx, state_handlers::initialize(params, &mut self.lsp_state, &mut self.world), LspResponse::Initialize)?;
+                            let client = self.clients.get_mut(&client_id).ok_or_else(|| unknown_client(client_id))?;
+                            respond(tx, state_handlers::initialize(params, &mut client.needs_registration, &mut self.world), LspResponse::Initialize)?;
                         },
                         LspRequest::Shutdown() => {
-                            // TODO
+                            // Other clients share the session, which lives as
+                            // long as the kernel. Only release the documents
+                            // of this client, which then disconnects.
+                            self.close_client_documents(client_id)?;
                             respond(tx, Ok(()), LspResponse::Shutdown)?;
                         },
                         LspRequest::WorkspaceSymbol(params) => {
@@ -262,23 +362,36 @@ impl GlobalState {
                         LspRequest::DocumentSymbol(params) => {
                             respond(tx, handlers::handle_document_symbol(params, &self.world), LspResponse::DocumentSymbol)?;
                         },
-                        LspRequest::ExecuteCommand(_params) => {
-                            respond(tx, handlers::handle_execute_command(&self.client).await, LspResponse::ExecuteCommand)?;
+                        LspRequest::ExecuteCommand(params) => {
+                            // Commands wait for the user to confirm and for R,
+                            // so they run in their own task
+                            let client = self.client(client_id)?.client.clone();
+                            lsp::spawn(async move {
+                                let result = handlers::handle_execute_command(params, &client).await;
+                                respond(tx, result, LspResponse::ExecuteCommand).and(Ok(None))
+                            });
                         },
                         LspRequest::Completion(params) => {
-                            respond(tx, handlers::handle_completion(params, &self.world), LspResponse::Completion)?;
+                            self.spawn_completion(params, tx);
                         },
                         LspRequest::CompletionResolve(params) => {
-                            respond(tx, handlers::handle_completion_resolve(params), LspResponse::CompletionResolve)?;
+                            // These handlers wait for R, see `scheduler.rs`
+                            Self::spawn_handler(tx, move || handlers::handle_completion_resolve(params), LspResponse::CompletionResolve);
                         },
                         LspRequest::Hover(params) => {
-                            respond(tx, handlers::handle_hover(params, &self.world), LspResponse::Hover)?;
+                            let state = self.world.clone();
+                            Self::spawn_handler(tx, move || handlers::handle_hover(params, &state), LspResponse::Hover);
                         },
                         LspRequest::SignatureHelp(params) => {
-                            respond(tx, handlers::handle_signature_help(params, &self.world), LspResponse::SignatureHelp)?;
+                            let state = self.world.clone();
+                            Self::spawn_handler(tx, move || handlers::handle_signature_help(params, &state), LspResponse::SignatureHelp);
                         },
                         LspRequest::GotoDefinition(params) => {
-                            respond(tx, handlers::handle_goto_definition(params, &self.world), LspResponse::GotoDefinition)?;
+                            let state = self.world.clone();
+                            Self::spawn_handler(tx, move || handlers::handle_goto_definition(params, &state), LspResponse::GotoDefinition);
+                        },
+                        LspRequest::GotoTypeDefinition(params) => {
+                            respond(tx, handlers::handle_goto_type_definition(params, &self.world), LspResponse::GotoTypeDefinition)?;
                         },
                         LspRequest::GotoImplementation(_params) => {
                             // TODO
@@ -287,9 +400,27 @@ impl GlobalState {
                         LspRequest::SelectionRange(params) => {
                             respond(tx, handlers::handle_selection_range(params, &self.world), LspResponse::SelectionRange)?;
                         },
+                        LspRequest::FoldingRange(params) => {
+                            respond(tx, handlers::handle_folding_range(params, &self.world), LspResponse::FoldingRange)?;
+                        },
+                        LspRequest::SemanticTokensFull(params) => {
+                            respond(tx, handlers::handle_semantic_tokens_full(params, &self.world), LspResponse::SemanticTokensFull)?;
+                        },
                         LspRequest::References(params) => {
                             respond(tx, handlers::handle_references(params, &self.world), LspResponse::References)?;
                         },
+                        LspRequest::PrepareRename(params) => {
+                            respond(tx, handlers::handle_prepare_rename(params, &self.world), LspResponse::PrepareRename)?;
+                        },
+                        LspRequest::Rename(params) => {
+                            respond(tx, handlers::handle_rename(params, &self.world), LspResponse::Rename)?;
+                        },
+                        LspRequest::CodeLens(params) => {
+                            respond(tx, handlers::handle_code_lens(params, &self.world), LspResponse::CodeLens)?;
+                        },
+                        LspRequest::CodeAction(params) => {
+                            respond(tx, handlers::handle_code_action(params), LspResponse::CodeAction)?;
+                        },
                         LspRequest::StatementRange(params) => {
                             respond(tx, handlers::handle_statement_range(params, &self.world), LspResponse::StatementRange)?;
                         },
@@ -300,11 +431,22 @@ impl GlobalState {
                             state_handlers::did_change_formatting_options(&params.text_document_position.text_document.uri, &params.options, &mut self.world);
                             respond(tx, handlers::handle_indent(params, &self.world), LspResponse::OnTypeFormatting)?;
                         },
+                        LspRequest::Formatting(params) => {
+                            state_handlers::did_change_formatting_options(&params.text_document.uri, &params.options, &mut self.world);
+                            respond(tx, handlers::handle_formatting(params, &self.world), LspResponse::Formatting)?;
+                        },
+                        LspRequest::RangeFormatting(params) => {
+                            state_handlers::did_change_formatting_options(&params.text_document.uri, &params.options, &mut self.world);
+                            respond(tx, handlers::handle_range_formatting(params, &self.world), LspResponse::RangeFormatting)?;
+                        },
                         LspRequest::VirtualDocument(params) => {
                             respond(tx, handlers::handle_virtual_document(params), LspResponse::VirtualDocument)?;
                         },
                         LspRequest::InputBoundaries(params) => {
-                            respond(tx, handlers::handle_input_boundaries(params), LspResponse::InputBoundaries)?;
+                            Self::spawn_handler(tx, move || handlers::handle_input_boundaries(params), LspResponse::InputBoundaries);
+                        },
+                        LspRequest::Brackets(params) => {
+                            respond(tx, handlers::handle_brackets(params), LspResponse::Brackets)?;
                         },
                     };
                 },
@@ -314,6 +456,21 @@ impl GlobalState {
                 KernelNotification::DidChangeConsoleInputs(inputs) => {
                     state_handlers::did_change_console_inputs(inputs, &mut self.world)?;
                 },
+                KernelNotification::DidPublishPackageProblems(problems) => {
+                    state_handlers::did_publish_package_problems(problems, &mut self.world)?;
+                },
+                KernelNotification::DidPublishLints(uri, lints) => {
+                    state_handlers::did_publish_lints(uri, lints, &mut self.world)?;
+                },
+            },
+
+            Event::Client(event) => match event {
+                ClientEvent::Connected(client_id, client) => {
+                    self.client_connected(client_id, client);
+                },
+                ClientEvent::Disconnected(client_id) => {
+                    self.client_disconnected(client_id)?;
+                },
             },
         }
 
@@ -325,7 +482,104 @@ impl GlobalState {
         Ok(())
     }
 
-    #[allow(dead_code)] // Currently unused
+    fn client(&self, client_id: ClientId) -> anyhow::Result<&ClientState> {
+        self.clients
+            .get(&client_id)
+            .ok_or_else(|| unknown_client(client_id))
+    }
+
+    fn client_mut(&mut self, client_id: ClientId) -> anyhow::Result<&mut ClientState> {
+        self.clients
+            .get_mut(&client_id)
+            .ok_or_else(|| unknown_client(client_id))
+    }
+
+    /// Whether any client has the document open
+    fn is_open(&self, uri: &Url) -> bool {
+        self.clients
+            .values()
+            .any(|client| client.open_documents.contains(uri))
+    }
+
+    fn client_connected(&mut self, client_id: ClientId, client: Client) {
+        lsp::log_info!("LSP client {client_id} connected");
+
+        send_auxiliary(AuxiliaryEvent::Client(ClientEvent::Connected(
+            client_id,
+            client.clone(),
+        )));
+
+        self.clients.insert(
+            client_id,
+            ClientState {
+                client,
+                needs_registration: ClientCaps::default(),
+                open_documents: HashSet::new(),
+            },
+        );
+    }
+
+    /// Close the documents of the disconnected client. The rest of the world
+    /// state is kept for the next client.
+    fn client_disconnected(&mut self, client_id: ClientId) -> anyhow::Result<()> {
+        self.close_client_documents(client_id)?;
+        self.clients.remove(&client_id);
+
+        send_auxiliary(AuxiliaryEvent::Client(ClientEvent::Disconnected(client_id)));
+        lsp::log_info!("LSP client {client_id} disconnected");
+
+        Ok(())
+    }
+
+    fn close_client_documents(&mut self, client_id: ClientId) -> anyhow::Result<()> {
+        let open_documents = std::mem::take(&mut self.client_mut(client_id)?.open_documents);
+
+        for uri in open_documents {
+            self.close_document(client_id, uri);
+        }
+
+        Ok(())
+    }
+
+    /// Release a document closed by a client. The document is closed once no
+    /// client has it open. When its owner closes it, another client takes
+    /// over with its own contents.
+    fn close_document(&mut self, client_id: ClientId, uri: Url) {
+        let resynced =
+            self.client_documents
+                .close(client_id, &uri, &mut self.lsp_state, &mut self.world);
+
+        if resynced {
+            if let Err(err) = state_handlers::did_resync(&uri, &self.world) {
+                lsp::log_error!("{err:?}");
+            }
+        }
+
+        if self.is_open(&uri) {
+            return;
+        }
+
+        let params = DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri },
+        };
+        if let Err(err) = state_handlers::did_close(params, &mut self.lsp_state, &mut self.world) {
+            lsp::log_error!("{err:?}");
+        }
+    }
+
+    /// Index the workspace, unless it was already indexed for a previous
+    /// client
+    fn index_workspace(&mut self) -> anyhow::Result<()> {
+        if self.indexed_folders.as_ref() == Some(&self.world.workspace.folders) {
+            return Ok(());
+        }
+
+        state_handlers::initialized(&self.world)?;
+        self.indexed_folders = Some(self.world.workspace.folders.clone());
+
+        Ok(())
+    }
+
     /// Spawn blocking thread for LSP request handler
     ///
     /// Use this for handlers that might take too long to handle on the main
@@ -347,6 +601,50 @@ impl GlobalState {
             respond(response_tx, handler(), into_lsp_response).and(Ok(None))
         })
     }
+
+    /// Spawn a completion request handler
+    ///
+    /// Completions are requested on every keystroke, so a request is usually
+    /// superseded by the next one before it's done. Each request cancels the
+    /// previous one, which then gives up without querying R or stops at its
+    /// next cancellation point. This way a typing burst doesn't queue up work
+    /// on the R thread. The client may also cancel a request through its
+    /// work done progress token.
+    fn spawn_completion(
+        &mut self,
+        params: CompletionParams,
+        response_tx: TokioUnboundedSender<anyhow::Result<LspResponse>>,
+    ) {
+        self.cancel_completion(None);
+
+        let cancellation = CancellationToken::new();
+        self.completion = Some(PendingCompletion {
+            work_done_token: params.work_done_progress_params.work_done_token.clone(),
+            cancellation: cancellation.clone(),
+        });
+
+        let state = self.world.clone();
+        Self::spawn_handler(
+            response_tx,
+            move || handlers::handle_completion(params, &state, cancellation),
+            LspResponse::Completion,
+        );
+    }
+
+    /// Cancel the pending completion request. If `token` is supplied, only
+    /// cancel the request if it was made with this work done progress token.
+    fn cancel_completion(&mut self, token: Option<&ProgressToken>) {
+        let Some(completion) = &self.completion else {
+            return;
+        };
+
+        if token.is_some() && completion.work_done_token.as_ref() != token {
+            return;
+        }
+
+        completion.cancellation.cancel();
+        self.completion = None;
+    }
 }
 
 /// Respond to a request from the LSP
@@ -386,11 +684,15 @@ fn respond<T>(
     out
 }
 
+fn unknown_client(client_id: ClientId) -> anyhow::Error {
+    anyhow!("Unknown LSP client {client_id}")
+}
+
 // Needed for spawning the loop
 unsafe impl Sync for AuxiliaryState {}
 
 impl AuxiliaryState {
-    fn new(client: Client) -> Self {
+    fn new() -> Self {
         // Channels for communication with the auxiliary loop
         let (auxiliary_event_tx, auxiliary_event_rx) = tokio_unbounded_channel::<AuxiliaryEvent>();
 
@@ -420,9 +722,10 @@ impl AuxiliaryState {
         tasks.push(pending);
 
         Self {
-            client,
+            clients: HashMap::new(),
             auxiliary_event_rx,
             tasks,
+            progress_tokens: HashSet::new(),
         }
     }
 
@@ -434,11 +737,21 @@ impl AuxiliaryState {
         loop {
             match self.next_event().await {
                 AuxiliaryEvent::Log(level, message) => self.log(level, message).await,
+                AuxiliaryEvent::Progress(event) => self.progress(event).await,
                 AuxiliaryEvent::SpawnedTask(handle) => self.tasks.push(Box::pin(handle)),
                 AuxiliaryEvent::PublishDiagnostics(uri, diagnostics, version) => {
-                    self.client
-                        .publish_diagnostics(uri, diagnostics, version)
-                        .await
+                    for client in self.clients.values() {
+                        client
+                            .publish_diagnostics(uri.clone(), diagnostics.clone(), version)
+                            .await
+                    }
+                },
+                AuxiliaryEvent::Client(ClientEvent::Connected(client_id, client)) => {
+                    self.clients.insert(client_id, client);
+                },
+                AuxiliaryEvent::Client(ClientEvent::Disconnected(client_id)) => {
+                    self.clients.remove(&client_id);
+                    self.progress_tokens.retain(|(id, _)| *id != client_id);
                 },
             }
         }
@@ -462,11 +775,71 @@ impl AuxiliaryState {
         }
     }
 
+    async fn progress(&mut self, event: ProgressEvent) {
+        let (token, value) = match event {
+            ProgressEvent::Begin(token, begin) => {
+                // Server-initiated tokens must be acknowledged by each client
+                // before we can report progress with them
+                for (client_id, client) in self.clients.iter() {
+                    let params = WorkDoneProgressCreateParams {
+                        token: NumberOrString::String(token.clone()),
+                    };
+                    let result = client.send_request::<WorkDoneProgressCreate>(params).await;
+
+                    match result {
+                        Ok(()) => {
+                            self.progress_tokens.insert((*client_id, token.clone()));
+                        },
+                        Err(err) => {
+                            client
+                                .log_message(
+                                    MessageType::ERROR,
+                                    format!("Can't create progress token:\n{err:?}"),
+                                )
+                                .await
+                        },
+                    }
+                }
+                (token, WorkDoneProgress::Begin(begin))
+            },
+            ProgressEvent::Report(token, report) => (token, WorkDoneProgress::Report(report)),
+            ProgressEvent::End(token, end) => (token, WorkDoneProgress::End(end)),
+        };
+        let done = matches!(value, WorkDoneProgress::End(_));
+
+        for (client_id, client) in self.clients.iter() {
+            let key = (*client_id, token.clone());
+            if !self.progress_tokens.contains(&key) {
+                continue;
+            }
+
+            let params = ProgressParams {
+                token: NumberOrString::String(token.clone()),
+                value: ProgressParamsValue::WorkDone(value.clone()),
+            };
+            client
+                .send_notification::<ProgressNotification>(params)
+                .await
+        }
+
+        if done {
+            self.progress_tokens.retain(|(_, other)| *other != token);
+        }
+    }
+
     async fn log(&self, level: MessageType, message: String) {
-        self.client.log_message(level, message).await
+        // Between connections, log to the kernel instead
+        if self.clients.is_empty() {
+            log::info!("{message}");
+            return;
+        }
+
+        for client in self.clients.values() {
+            client.log_message(level, message.clone()).await
+        }
     }
     async fn log_error(&self, message: String) {
-        self.client.log_message(MessageType::ERROR, message).await
+        self.log(MessageType::ERROR, message).await
     }
 }
 
@@ -476,7 +849,7 @@ fn auxiliary_tx() -> &'static TokioUnboundedSender<AuxiliaryEvent> {
     unsafe { AUXILIARY_EVENT_TX.get().unwrap() }
 }
 
-fn send_auxiliary(event: AuxiliaryEvent) {
+pub(crate) fn send_auxiliary(event: AuxiliaryEvent) {
     if let Err(err) = auxiliary_tx().send(event) {
         // The error includes the event
         log::warn!("LSP is shut down, can't send event:\n{err:?}");
@@ -491,9 +864,12 @@ pub(crate) fn log(level: lsp_types::MessageType, message: String) {
         return;
     }
 
-    // Check that channel is still alive in case the LSP was closed.
-    // If closed, fallthrough.
-    if let Ok(_) = auxiliary_tx().send(AuxiliaryEvent::Log(level, message.clone())) {
+    // Check that channel is still alive in case the LSP was closed, and that
+    // it exists at all since LSP features may be used by the kernel before the
+    // LSP is started, e.g. for completions in Jupyter notebooks. If not,
+    // fallthrough.
+    let tx = unsafe { AUXILIARY_EVENT_TX.get() };
+    if let Some(Ok(_)) = tx.map(|tx| tx.send(AuxiliaryEvent::Log(level, message.clone()))) {
         return;
     }
 
@@ -509,8 +885,9 @@ pub(crate) fn log(level: lsp_types::MessageType, message: String) {
 
 /// Spawn a blocking task
 ///
-/// This runs tasks that do semantic analysis on a separate thread pool to avoid
-/// blocking the main loop.
+/// This runs tasks on tokio's blocking thread pool to avoid blocking the main
+/// loop. CPU-bound analysis should use `spawn_analysis()` instead so it doesn't
+/// starve the threads used for I/O.
 ///
 /// Can optionally return an event for the auxiliary loop (i.e. a log message or
 /// diagnostics publication).
@@ -526,12 +903,34 @@ where
     send_auxiliary(AuxiliaryEvent::SpawnedTask(handle));
 }
 
+/// Spawn an async task
+///
+/// Like `spawn_blocking()`, for tasks that wait on the client or on other
+/// tasks, e.g. for a user confirmation, and shouldn't hold up the main loop.
+pub(crate) fn spawn<Task>(task: Task)
+where
+    Task: Future<Output = anyhow::Result<Option<AuxiliaryEvent>>>,
+    Task: Send + 'static,
+{
+    let handle = tokio::spawn(task);
+    send_auxiliary(AuxiliaryEvent::SpawnedTask(handle));
+}
+
 pub(crate) fn spawn_diagnostics_refresh(uri: Url, document: Document, state: WorldState) {
-    lsp::spawn_blocking(move || {
+    // Keyed by URI so that a refresh for a newer version of the document
+    // supersedes a pending one
+    let key = format!("diagnostics:{uri}");
+
+    lsp::spawn_analysis(Some(key), move |cancellation| {
         let _s = tracing::info_span!("diagnostics_refresh", uri = %uri).entered();
 
         let version = document.version;
-        let diagnostics = diagnostics::generate_diagnostics(document, state);
+        let diagnostics = document_diagnostics(&uri, document, state);
+
+        // Don't publish diagnostics that are already outdated
+        if cancellation.is_cancelled() {
+            return Ok(None);
+        }
 
         Ok(Some(AuxiliaryEvent::PublishDiagnostics(
             uri,
@@ -541,10 +940,66 @@ pub(crate) fn spawn_diagnostics_refresh(uri: Url, document: Document, state: Wor
     })
 }
 
+/// Diagnostics are suppressed for generated files since users can't fix them.
+/// Problems found by the last package check are added to the diagnostics of
+/// the document, and so are the lints of its saved version if lintr is on.
+fn document_diagnostics(uri: &Url, document: Document, state: WorldState) -> Vec<Diagnostic> {
+    let problems = state.package_problems.get(uri).cloned().unwrap_or_default();
+
+    if generated::is_generated(uri, &document, &state) {
+        return problems;
+    }
+
+    // Lints of an older version would point to the wrong lines
+    let lints = state
+        .lints
+        .get(uri)
+        .filter(|lints| state.config.diagnostics.lintr && lints.version == document.version)
+        .map(|lints| lints.diagnostics.clone());
+
+    let mut diagnostics = diagnostics::generate_diagnostics(document, state);
+    if let Some(lints) = lints {
+        lintr::merge_lints(&mut diagnostics, lints);
+    }
+    diagnostics.extend(problems);
+    diagnostics
+}
+
+/// Number of documents from which a refresh of all diagnostics is considered
+/// heavy enough to be reported to the user as progress
+const DIAGNOSTICS_PROGRESS_THRESHOLD: usize = 20;
+
 pub(crate) fn spawn_diagnostics_refresh_all(state: WorldState) {
-    for (url, document) in state.documents.iter() {
-        spawn_diagnostics_refresh(url.clone(), document.clone(), state.clone())
+    if state.documents.len() < DIAGNOSTICS_PROGRESS_THRESHOLD {
+        for (url, document) in state.documents.iter() {
+            spawn_diagnostics_refresh(url.clone(), document.clone(), state.clone())
+        }
+        return;
     }
+
+    lsp::spawn_analysis(Some(String::from("diagnostics_all")), move |cancellation| {
+        let _s = tracing::info_span!("diagnostics_refresh_all").entered();
+
+        let mut progress = Progress::begin("Analyzing R documents", false);
+        let total = state.documents.len();
+
+        for (i, (uri, document)) in state.documents.iter().enumerate() {
+            // A newer refresh of all documents has been requested
+            if cancellation.is_cancelled() {
+                break;
+            }
+
+            let diagnostics = document_diagnostics(uri, document.clone(), state.clone());
+            publish_diagnostics(uri.clone(), diagnostics, document.version);
+            progress.report(i + 1, total);
+        }
+
+        Ok(None)
+    })
+}
+
+pub(crate) fn send_progress(event: ProgressEvent) {
+    send_auxiliary(AuxiliaryEvent::Progress(event));
 }
 
 pub(crate) fn publish_diagnostics(uri: Url, diagnostics: Vec<Diagnostic>, version: Option<i32>) {
@@ -7,6 +7,8 @@
 
 #![allow(dead_code)]
 
+use std::cell::Cell;
+
 use ego_tree::NodeRef;
 use scraper::node::Text;
 use scraper::ElementRef;
@@ -53,6 +55,50 @@ pub fn md_newline() -> String {
     "\n\n".to_string()
 }
 
+/// Escape HTML markup in text taken from help pages, since Markdown renderers
+/// would otherwise render it
+pub fn md_escape_html(text: &str) -> String {
+    text.replace('<', "\\<").replace('>', "\\>")
+}
+
+/// Math fences for LaTeX taken from `\eqn{}` and `\deqn{}` in help pages, e.g.
+/// `$x^2$` inline or `$$` fences on their own lines for display math. Returns
+/// `None` for LaTeX that can't be fenced, which should be shown as plain text.
+pub fn md_math(tex: &str, display: bool) -> Option<String> {
+    // KaTeX delimiters are redundant with the fences
+    let tex = tex.trim();
+    let tex = tex
+        .strip_prefix("\\(")
+        .and_then(|tex| tex.strip_suffix("\\)"))
+        .or_else(|| {
+            tex.strip_prefix("\\[")
+                .and_then(|tex| tex.strip_suffix("\\]"))
+        })
+        .unwrap_or(tex)
+        .trim();
+
+    // A dollar would close the fence early
+    if tex.is_empty() || tex.contains('$') {
+        return None;
+    }
+
+    // Angle brackets would be taken for HTML markup
+    let tex = tex.replace('<', "\\lt ").replace('>', "\\gt ");
+
+    if display {
+        Some(join!("\n$$\n", tex, "\n$$\n"))
+    } else {
+        // Inline fences can't span lines
+        let tex = tex.split_whitespace().collect::<Vec<_>>().join(" ");
+        Some(join!("$", tex, "$"))
+    }
+}
+
+/// Elements whose contents are never displayed
+const HIDDEN_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "iframe", "object", "embed",
+];
+
 pub fn elt_text(node: ElementRef) -> String {
     node.text().collect::<String>()
 }
@@ -77,13 +123,21 @@ pub fn elt_next(node: ElementRef) -> Option<ElementRef> {
     None
 }
 
+/// Nesting of elements beyond which their content is converted as plain text,
+/// so that pathological documents can't overflow the stack
+const MAX_DEPTH: usize = 128;
+
 pub struct MarkdownConverter<'a> {
     node: NodeRef<'a, Node>,
+    depth: Cell<usize>,
 }
 
 impl<'a> MarkdownConverter<'a> {
     pub fn new(node: NodeRef<'a, Node>) -> Self {
-        MarkdownConverter { node }
+        MarkdownConverter {
+            node,
+            depth: Cell::new(0),
+        }
     }
 
     pub fn convert(&self) -> String {
@@ -95,16 +149,39 @@ impl<'a> MarkdownConverter<'a> {
     fn convert_node(&self, node: NodeRef<'a, Node>, buffer: &mut String) {
         if node.value().is_element() {
             let element = ElementRef::wrap(node).unwrap();
+
+            let depth = self.depth.get();
+            if depth >= MAX_DEPTH {
+                // `text()` walks the descendants iteratively
+                buffer.extend(element.text());
+                return;
+            }
+
+            self.depth.set(depth + 1);
             self.convert_element(element, buffer);
+            self.depth.set(depth);
         } else if node.value().is_text() {
             let text = node.value().as_text().unwrap();
-            self.convert_text(text, buffer);
+            let in_code = node.ancestors().any(|ancestor| {
+                ancestor
+                    .value()
+                    .as_element()
+                    .is_some_and(|element| matches!(element.name(), "code" | "pre"))
+            });
+            self.convert_text(text, in_code, buffer);
         }
     }
 
     fn convert_element(&self, element: ElementRef<'a>, buffer: &mut String) {
         let name = element.value().name();
         match name {
+            _ if HIDDEN_ELEMENTS.contains(&name) => {},
+
+            // Math from `\eqn{}` and `\deqn{}`
+            "code" if element.value().classes().any(|class| class == "reqn") => {
+                self.convert_math(element, buffer)
+            },
+
             "code" => {
                 buffer.push('`');
                 self.convert_children(element, buffer);
@@ -160,8 +237,39 @@ impl<'a> MarkdownConverter<'a> {
         }
     }
 
-    fn convert_text(&self, text: &Text, buffer: &mut String) {
-        buffer.push_str(text.to_string().as_str())
+    fn convert_math(&self, element: ElementRef<'a>, buffer: &mut String) {
+        let tex = elt_text(element);
+
+        // `\deqn{}` is rendered in its own centered paragraph
+        let display = element
+            .parent()
+            .and_then(ElementRef::wrap)
+            .is_some_and(|parent| {
+                parent.value().name() == "p" &&
+                    parent
+                        .value()
+                        .attr("style")
+                        .is_some_and(|style| style.contains("center"))
+            });
+
+        match md_math(&tex, display) {
+            Some(math) => buffer.push_str(&math),
+            None => {
+                // Fall back to plain text in a code span
+                buffer.push('`');
+                buffer.push_str(tex.trim());
+                buffer.push('`');
+            },
+        }
+    }
+
+    fn convert_text(&self, text: &Text, in_code: bool, buffer: &mut String) {
+        // Markup isn't interpreted in code spans
+        if in_code {
+            buffer.push_str(text)
+        } else {
+            buffer.push_str(&md_escape_html(text))
+        }
     }
 
     fn convert_tr(&self, element: ElementRef<'a>, buffer: &mut String) {
@@ -190,3 +298,52 @@ impl<'a> MarkdownConverter<'a> {
         buffer.pop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use crate::lsp::markdown::md_math;
+    use crate::lsp::markdown::MarkdownConverter;
+
+    fn convert(html: &str) -> String {
+        let html = Html::parse_fragment(html);
+        MarkdownConverter::new(*html.root_element()).convert()
+    }
+
+    #[test]
+    fn test_markdown_inline_math() {
+        assert_eq!(
+            convert(r#"<p>The mean <code class="reqn">\bar{x} = \frac{1}{n}</code>.</p>"#),
+            "\nThe mean $\\bar{x} = \\frac{1}{n}$.\n"
+        );
+
+        // Other code elements are unaffected
+        assert_eq!(convert("<p><code>x < 1</code></p>"), "\n`x < 1`\n");
+    }
+
+    #[test]
+    fn test_markdown_display_math() {
+        assert_eq!(
+            convert(r#"<p style="text-align: center;"><code class="reqn">a&lt;b</code></p>"#),
+            "\n\n$$\na\\lt b\n$$\n\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown_math_fallback() {
+        assert_eq!(md_math(r"\(x^2\)", false).unwrap(), "$x^2$");
+        assert_eq!(md_math("a\n+ b", false).unwrap(), "$a + b$");
+        assert_eq!(md_math("cost in $", false), None);
+        assert_eq!(
+            convert(r#"<code class="reqn">cost in $</code>"#),
+            "`cost in $`"
+        );
+    }
+
+    #[test]
+    fn test_markdown_deep_nesting() {
+        let html = format!("{}text{}", "<div>".repeat(5000), "</div>".repeat(5000));
+        assert_eq!(convert(&html), "text");
+    }
+}
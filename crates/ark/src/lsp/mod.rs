@@ -6,37 +6,60 @@
 //
 
 pub mod backend;
+pub mod brackets;
+mod client_documents;
+pub mod code_action;
+pub mod code_lens;
 pub mod comm;
+mod commands;
 pub mod completions;
 mod config;
 mod declarations;
 pub mod definitions;
 pub mod diagnostics;
+pub mod diagnostics_flow;
 pub mod diagnostics_syntax;
 pub mod document_context;
 pub mod documents;
 pub mod encoding;
 pub mod events;
+pub mod folding_range;
+pub mod formatting;
+mod generated;
 pub mod handler;
 pub mod handlers;
 pub mod help;
 pub mod help_topic;
 pub mod hover;
+pub mod hover_evaluation;
 pub mod indent;
 pub mod indexer;
 pub mod input_boundaries;
+pub mod lintr;
 pub mod main_loop;
 pub mod markdown;
 pub mod offset;
+pub mod package;
+pub mod package_problems;
+mod pool;
+mod progress;
+pub mod project_config;
 pub mod references;
+pub mod rename;
+mod scheduler;
 pub mod selection_range;
+pub mod semantic_tokens;
+mod session;
 pub mod signature_help;
 pub mod state;
 pub mod state_handlers;
 pub mod statement_range;
 pub mod symbols;
 pub mod traits;
+pub mod type_annotations;
+pub mod type_definitions;
 pub mod util;
+pub mod virtual_documents;
 
 // These send LSP messages in a non-async and non-blocking way.
 // The LOG level is not timestamped so we're not using it.
@@ -60,6 +83,8 @@ pub(crate) use log_error;
 pub(crate) use log_info;
 pub(crate) use log_warn;
 pub(crate) use main_loop::publish_diagnostics;
+pub(crate) use main_loop::spawn;
 pub(crate) use main_loop::spawn_blocking;
 pub(crate) use main_loop::spawn_diagnostics_refresh;
 pub(crate) use main_loop::spawn_diagnostics_refresh_all;
+pub(crate) use pool::spawn_analysis;
@@ -0,0 +1,220 @@
+//
+// package.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::path::Path;
+
+use ropey::Rope;
+use tree_sitter::Node;
+
+use crate::lsp::documents::Document;
+use crate::lsp::indexer::string_value;
+use crate::lsp::state::Workspace;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::NodeTypeExt;
+
+/// An R package whose sources are open in the workspace, as described by its
+/// `DESCRIPTION` and `NAMESPACE` files
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Package {
+    pub name: String,
+
+    /// Packages listed in the `Depends` field, attached along with the package
+    pub depends: Vec<String>,
+
+    /// Packages listed in the `Imports` field
+    pub imports: Vec<String>,
+
+    pub namespace: Namespace,
+}
+
+/// Directives of a `NAMESPACE` file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Namespace {
+    /// Packages imported whole with `import()`
+    pub imports: Vec<String>,
+
+    /// Symbols imported with `importFrom()`, by package
+    pub imports_from: Vec<(String, Vec<String>)>,
+}
+
+impl Package {
+    /// The package at the root of one of the workspace folders, if any
+    pub(crate) fn from_workspace(workspace: &Workspace) -> Option<Self> {
+        workspace
+            .folders
+            .iter()
+            .filter_map(|folder| folder.to_file_path().ok())
+            .find_map(|root| Self::load(&root))
+    }
+
+    /// Load the package whose sources are in `root`. The `NAMESPACE` file is
+    /// optional, e.g. before roxygen2 first generated it.
+    pub(crate) fn load(root: &Path) -> Option<Self> {
+        let description = std::fs::read_to_string(root.join("DESCRIPTION")).ok()?;
+        let mut package = Self::from_description(&description)?;
+
+        if let Ok(namespace) = std::fs::read_to_string(root.join("NAMESPACE")) {
+            package.namespace = Namespace::parse(&namespace);
+        }
+
+        Some(package)
+    }
+
+    /// Parse the contents of a `DESCRIPTION` file. Returns `None` if it
+    /// doesn't describe a package.
+    pub(crate) fn from_description(contents: &str) -> Option<Self> {
+        let fields = parse_dcf(contents);
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        let name = field("Package")?.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            name: String::from(name),
+            depends: parse_dependencies(field("Depends").unwrap_or_default()),
+            imports: parse_dependencies(field("Imports").unwrap_or_default()),
+            namespace: Namespace::default(),
+        })
+    }
+}
+
+impl Namespace {
+    /// Parse the contents of a `NAMESPACE` file. Only the import directives
+    /// are retained, and conditional directives are skipped.
+    pub(crate) fn parse(contents: &str) -> Self {
+        let document = Document::new(contents, None);
+        let contents = &document.contents;
+        let root = document.ast.root_node();
+
+        let mut namespace = Self::default();
+
+        let mut cursor = root.walk();
+        for node in root.children(&mut cursor) {
+            let Some((directive, arguments)) = parse_directive(&node, contents) else {
+                continue;
+            };
+
+            match directive.as_str() {
+                "import" => namespace.imports.extend(arguments),
+                "importFrom" => {
+                    let mut arguments = arguments.into_iter();
+                    if let Some(package) = arguments.next() {
+                        namespace.imports_from.push((package, arguments.collect()));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        namespace
+    }
+}
+
+/// Name of a directive like `importFrom(foo, "bar")` and its unnamed
+/// arguments
+fn parse_directive(node: &Node, contents: &Rope) -> Option<(String, Vec<String>)> {
+    if !node.is_call() {
+        return None;
+    }
+
+    let function = node.child_by_field_name("function")?;
+    let directive = contents.node_slice(&function).ok()?.to_string();
+
+    let arguments = node.child_by_field_name("arguments")?;
+    let mut cursor = arguments.walk();
+    let arguments = arguments
+        .children_by_field_name("argument", &mut cursor)
+        .filter(|argument| argument.child_by_field_name("name").is_none())
+        .filter_map(|argument| {
+            let value = argument.child_by_field_name("value")?;
+            if value.is_string() {
+                return string_value(&value, contents);
+            }
+            if value.is_identifier() {
+                return contents.node_slice(&value).ok().map(|x| x.to_string());
+            }
+            None
+        })
+        .collect();
+
+    Some((directive, arguments))
+}
+
+/// Fields of a Debian Control File, the format of `DESCRIPTION` files.
+/// Continuation lines start with whitespace and are joined to the value of
+/// their field.
+fn parse_dcf(contents: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+
+    for line in contents.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+
+        if let Some((field, value)) = line.split_once(':') {
+            fields.push((String::from(field.trim()), String::from(value.trim())));
+        }
+    }
+
+    fields
+}
+
+/// Package names of a field like `Imports: dplyr (>= 1.0.0), rlang`. R itself
+/// is left out.
+fn parse_dependencies(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter_map(|dependency| {
+            let name = dependency.split('(').next()?.trim();
+            (!name.is_empty() && name != "R").then(|| String::from(name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::package::Namespace;
+    use crate::lsp::package::Package;
+
+    #[test]
+    fn test_package_from_description() {
+        let description = "Package: mypkg\nTitle: My Package\nDepends:\n    R (>= 4.1),\n    methods\nImports: dplyr (>= 1.0.0), rlang,\n  vctrs\n";
+        let package = Package::from_description(description).unwrap();
+
+        assert_eq!(package.name, "mypkg");
+        assert_eq!(package.depends, vec!["methods"]);
+        assert_eq!(package.imports, vec!["dplyr", "rlang", "vctrs"]);
+
+        assert!(Package::from_description("Title: Not a package\n").is_none());
+    }
+
+    #[test]
+    fn test_namespace_parse() {
+        let namespace = "# Generated by roxygen2: do not edit by hand\n\nS3method(print,foo)\nexport(bar)\nexport(\"baz\")\nimport(rlang)\nimportFrom(dplyr,filter)\nimportFrom(\"purrr\", \"map\", map2)\nif (getRversion() >= \"4.0\") import(qux)\n";
+        let namespace = Namespace::parse(namespace);
+
+        assert_eq!(namespace.imports, vec!["rlang"]);
+        assert_eq!(namespace.imports_from, vec![
+            (String::from("dplyr"), vec![String::from("filter")]),
+            (String::from("purrr"), vec![
+                String::from("map"),
+                String::from("map2")
+            ]),
+        ]);
+    }
+}
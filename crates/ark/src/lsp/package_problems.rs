@@ -0,0 +1,362 @@
+//
+// package_problems.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Problems reported by `R CMD build`, `R CMD INSTALL`, and `R CMD check` (or
+// their devtools equivalents) are published as diagnostics on the files they
+// refer to, so that users can go from a check NOTE straight to the offending
+// line. The frontend sends the output of the commands it runs with the
+// `publish_package_problems` RPC, see `package.R`.
+//
+// The output is scanned line by line for:
+//
+// - Compiler messages, e.g. `init.c:12:5: warning: unused variable 'x'`.
+// - Rd problems, e.g. `checkRd: (-1) foo.Rd:12: Lost braces`.
+// - Source references following code problems, e.g. `(foo.R:12-14)` below
+//   `foo: no visible binding for global variable 'x'`.
+// - testthat failures, e.g. `── Failure ('test-foo.R:12:3'): works ──`.
+// - Other locations followed by a message, e.g. the parse errors of
+//   `R CMD INSTALL` or roxygen warnings: `foo.R:12:3: unexpected symbol`.
+//
+// The severity of a problem is the status of the check it belongs to, e.g.
+// `* checking R code for possible problems ... NOTE`, unless the problem
+// states its own. Problems are attached to files of the package: check and
+// install work on copies of the package, so paths are matched against the
+// package root by their longest suffix that exists there.
+
+use std::collections::HashMap;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use harp::object::RObject;
+use libr::SEXP;
+use regex::Regex;
+use tower_lsp::lsp_types::Diagnostic;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::Range;
+use url::Url;
+
+use crate::interface::RMain;
+use crate::lsp::main_loop::KernelNotification;
+
+static RE_SECTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\* .*?(?:\.\.\.\s*(NOTE|WARNING|ERROR|OK))?\s*$").unwrap());
+
+static RE_COMPILER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?P<path>[^\s:][^:]*):(?P<line>\d+):(?:(?P<column>\d+):)?\s*(?P<level>fatal error|error|warning|note):\s*(?P<message>.+)$",
+    )
+    .unwrap()
+});
+
+static RE_CHECK_RD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^checkRd: \(-?\d+\) (?P<path>[^:]+\.Rd):(?P<line>\d+)(?:-\d+)?: (?P<message>.+)$")
+        .unwrap()
+});
+
+static RE_SRCREF: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*\((?P<path>[^():]+\.[Rr]):(?P<line>\d+)(?:-(?P<end>\d+))?\)\s*$").unwrap()
+});
+
+static RE_TESTTHAT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?P<level>Failure|Error|Warning) \('?(?P<path>[^':()]+\.[Rr]):(?P<line>\d+)(?::(?P<column>\d+))?'?\): (?P<message>.+?)[\s─]*$",
+    )
+    .unwrap()
+});
+
+static RE_LOCATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^\s*(?P<path>[^\s:()][^:()]*\.(?:R|r|Rd|c|cc|cpp|h|hpp|f|f90)):(?P<line>\d+)(?::(?P<column>\d+))?:\s*(?P<message>.+)$",
+    )
+    .unwrap()
+});
+
+/// Subdirectories of a package where files referred to by their name only
+/// may be found
+const PACKAGE_DIRS: [&str; 6] = ["R", "src", "man", "tests/testthat", "tests", "vignettes"];
+
+/// Parse the output of `R CMD <command>` run on the package at `root` into
+/// diagnostics, by file
+pub(crate) fn parse_package_problems(
+    output: &str,
+    command: &str,
+    root: &Path,
+) -> HashMap<Url, Vec<Diagnostic>> {
+    let source = format!("R CMD {command}");
+
+    let mut problems: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+    let mut severity = None;
+    let mut message: Option<&str> = None;
+
+    for line in output.lines() {
+        let line = line.trim_end();
+
+        if let Some(captures) = RE_SECTION.captures(line) {
+            severity = captures.get(1).and_then(|status| status_severity(status.as_str()));
+            message = None;
+            continue;
+        }
+
+        let problem = if let Some(captures) = RE_COMPILER.captures(line) {
+            let severity = match &captures["level"] {
+                "note" => DiagnosticSeverity::INFORMATION,
+                "warning" => DiagnosticSeverity::WARNING,
+                _ => DiagnosticSeverity::ERROR,
+            };
+            Some(Problem::new(&captures, severity, captures["message"].to_string()))
+        } else if let Some(captures) = RE_CHECK_RD.captures(line) {
+            let severity = severity.unwrap_or(DiagnosticSeverity::WARNING);
+            Some(Problem::new(&captures, severity, captures["message"].to_string()))
+        } else if let Some(captures) = RE_SRCREF.captures(line) {
+            // The location of the problem on the previous line
+            message.take().map(|message| {
+                let severity = severity.unwrap_or(DiagnosticSeverity::WARNING);
+                Problem::new(&captures, severity, message.to_string())
+            })
+        } else if let Some(captures) = RE_TESTTHAT.captures(line) {
+            let severity = match &captures["level"] {
+                "Warning" => DiagnosticSeverity::WARNING,
+                _ => DiagnosticSeverity::ERROR,
+            };
+            let message = format!("Test {}: {}", captures["level"].to_lowercase(), &captures["message"]);
+            Some(Problem::new(&captures, severity, message))
+        } else if let Some(captures) = RE_LOCATION.captures(line) {
+            let severity = severity.unwrap_or(DiagnosticSeverity::ERROR);
+            Some(Problem::new(&captures, severity, captures["message"].to_string()))
+        } else {
+            if !line.trim().is_empty() {
+                message = Some(line.trim());
+            }
+            None
+        };
+
+        let Some(problem) = problem else {
+            continue;
+        };
+
+        let Some(path) = resolve_path(problem.path.as_str(), root) else {
+            log::trace!("Can't find `{}` in the package, skipping problem", problem.path);
+            continue;
+        };
+        let Ok(uri) = Url::from_file_path(&path) else {
+            continue;
+        };
+
+        let mut diagnostic = Diagnostic::new_simple(problem.range, problem.message);
+        diagnostic.severity = Some(problem.severity);
+        diagnostic.source = Some(source.clone());
+
+        problems.entry(uri).or_default().push(diagnostic);
+    }
+
+    problems
+}
+
+struct Problem {
+    path: String,
+    range: Range,
+    severity: DiagnosticSeverity,
+    message: String,
+}
+
+impl Problem {
+    fn new(captures: &regex::Captures, severity: DiagnosticSeverity, message: String) -> Self {
+        let number = |name: &str| -> Option<u32> {
+            captures.name(name).and_then(|x| x.as_str().parse::<u32>().ok())
+        };
+
+        // Lines and columns are 1-based in the output
+        let line = number("line").unwrap_or(1).saturating_sub(1);
+        let column = number("column").unwrap_or(1).saturating_sub(1);
+        let end = number("end").map_or(line, |end| end.saturating_sub(1).max(line));
+
+        // Clients clamp the end position to the end of the line
+        let range = Range {
+            start: Position::new(line, column),
+            end: Position::new(end, u32::MAX),
+        };
+
+        Self {
+            path: captures["path"].to_string(),
+            range,
+            severity,
+            message,
+        }
+    }
+}
+
+fn status_severity(status: &str) -> Option<DiagnosticSeverity> {
+    match status {
+        "NOTE" => Some(DiagnosticSeverity::INFORMATION),
+        "WARNING" => Some(DiagnosticSeverity::WARNING),
+        "ERROR" => Some(DiagnosticSeverity::ERROR),
+        _ => None,
+    }
+}
+
+/// Find the file of the package at `root` that `path` refers to. `path` may
+/// be relative to the package or to one of its subdirectories, or point into
+/// a copy of the package, e.g. `pkg.Rcheck/00_pkg_src/pkg/R/foo.R`.
+fn resolve_path(path: &str, root: &Path) -> Option<PathBuf> {
+    let path = Path::new(path);
+
+    if path.is_absolute() && path.starts_with(root) && path.is_file() {
+        return Some(path.to_path_buf());
+    }
+
+    let components: Vec<Component> = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+
+    for i in 0..components.len() {
+        let candidate = root.join(components[i..].iter().collect::<PathBuf>());
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+    }
+
+    let name = components.last()?;
+    PACKAGE_DIRS
+        .iter()
+        .map(|dir| root.join(dir).join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Publish the problems found in the output of `R CMD <command>` for the
+/// package at `root`. Replaces the problems of the previous run. Returns the
+/// number of problems.
+#[harp::register]
+pub unsafe extern "C" fn ps_publish_package_problems(
+    output: SEXP,
+    command: SEXP,
+    root: SEXP,
+) -> anyhow::Result<SEXP> {
+    let output: String = RObject::view(output).try_into()?;
+    let command: String = RObject::view(command).try_into()?;
+    let root: String = RObject::view(root).try_into()?;
+
+    let problems = parse_package_problems(&output, &command, Path::new(&root));
+    let n: i32 = problems.values().map(|x| x.len() as i32).sum();
+
+    RMain::get().send_lsp_notification(KernelNotification::DidPublishPackageProblems(problems));
+
+    Ok(RObject::from(n).sexp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tower_lsp::lsp_types::DiagnosticSeverity;
+    use url::Url;
+
+    use crate::lsp::package_problems::parse_package_problems;
+
+    fn package() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for dir in ["R", "src", "man", "tests/testthat"] {
+            std::fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+        for file in ["R/foo.R", "src/init.c", "man/foo.Rd", "tests/testthat/test-foo.R"] {
+            std::fs::write(root.path().join(file), "").unwrap();
+        }
+        root
+    }
+
+    fn uri(root: &Path, file: &str) -> Url {
+        Url::from_file_path(root.join(file)).unwrap()
+    }
+
+    #[test]
+    fn test_check_problems() {
+        let root = package();
+        let root = root.path();
+
+        let output = "
+* checking R code for possible problems ... NOTE
+foo: no visible binding for global variable 'x'
+  (/tmp/Rtmp123/pkg.Rcheck/00_pkg_src/pkg/R/foo.R:12-14)
+* checking Rd files ... WARNING
+checkRd: (-1) foo.Rd:3: Lost braces
+* checking for missing documentation entries ... OK
+";
+        let problems = parse_package_problems(output, "check", root);
+        assert_eq!(problems.len(), 2);
+
+        let diagnostics = problems.get(&uri(root, "R/foo.R")).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(
+            diagnostic.message,
+            "foo: no visible binding for global variable 'x'"
+        );
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::INFORMATION));
+        assert_eq!(diagnostic.range.start.line, 11);
+        assert_eq!(diagnostic.range.end.line, 13);
+        assert_eq!(diagnostic.source, Some(String::from("R CMD check")));
+
+        let diagnostics = problems.get(&uri(root, "man/foo.Rd")).unwrap();
+        assert_eq!(diagnostics[0].message, "Lost braces");
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostics[0].range.start.line, 2);
+    }
+
+    #[test]
+    fn test_install_problems() {
+        let root = package();
+        let root = root.path();
+
+        let output = "
+* installing *source* package 'pkg' ...
+gcc -I/usr/share/R/include -c init.c -o init.o
+init.c:12:5: warning: unused variable 'x' [-Wunused-variable]
+Error in parse(outFile) :
+  /tmp/RtmpXYZ/R.INSTALL123/pkg/R/foo.R:4:7: unexpected symbol
+* checking for file 'missing.R' ... OK
+missing.R:1:1: unexpected end of input
+";
+        let problems = parse_package_problems(output, "INSTALL", root);
+
+        let diagnostics = problems.get(&uri(root, "src/init.c")).unwrap();
+        assert_eq!(
+            diagnostics[0].message,
+            "unused variable 'x' [-Wunused-variable]"
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostics[0].range.start.line, 11);
+        assert_eq!(diagnostics[0].range.start.character, 4);
+
+        let diagnostics = problems.get(&uri(root, "R/foo.R")).unwrap();
+        assert_eq!(diagnostics[0].message, "unexpected symbol");
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostics[0].range.start.line, 3);
+
+        // Files that aren't part of the package are skipped
+        assert_eq!(problems.len(), 2);
+    }
+
+    #[test]
+    fn test_testthat_problems() {
+        let root = package();
+        let root = root.path();
+
+        let output = "
+── Failure ('test-foo.R:12:3'): foo works ──────────────────────────────────────
+foo() not equal to 1.
+";
+        let problems = parse_package_problems(output, "check", root);
+
+        let diagnostics = problems.get(&uri(root, "tests/testthat/test-foo.R")).unwrap();
+        assert_eq!(diagnostics[0].message, "Test failure: foo works");
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostics[0].range.start.line, 11);
+    }
+}
@@ -0,0 +1,335 @@
+//
+// pool.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use crossbeam::channel::bounded;
+use crossbeam::channel::Receiver;
+use crossbeam::channel::Sender;
+use crossbeam::channel::TrySendError;
+use stdext::spawn;
+
+use crate::lsp;
+use crate::lsp::main_loop::send_auxiliary;
+use crate::lsp::main_loop::AuxiliaryEvent;
+
+/// Maximum number of jobs waiting for a worker. Submitting to a full queue
+/// fails instead of blocking the caller, which is usually the main loop.
+const QUEUE_CAPACITY: usize = 64;
+
+/// Bounds on the number of worker threads. We want at least two so that
+/// diagnostics keep flowing during indexing, but we don't want to compete with
+/// R for all cores.
+const MIN_WORKERS: usize = 2;
+const MAX_WORKERS: usize = 4;
+
+/// Dedicated pool of threads for CPU-bound analysis (diagnostics, indexing)
+///
+/// Tokio's blocking pool is unbounded and shared with I/O, so a burst of heavy
+/// tasks delays unrelated responses. Jobs submitted here run on a fixed number
+/// of workers fed by a bounded queue.
+///
+/// Jobs may be submitted with a key, e.g. the URI of the document they
+/// analyse. Submitting a job with the same key supersedes the previous one: if
+/// it hasn't started yet, the new job takes its place in the queue, and
+/// otherwise it stops at its next `is_cancelled()` check. Superseded jobs
+/// don't hold on to queue slots, and a job that doesn't fit in the queue
+/// leaves its predecessor alone.
+struct WorkerPool {
+    tx: Sender<Task>,
+
+    /// The latest keyed jobs that are queued or running
+    keys: Arc<Mutex<HashMap<String, KeyedTask>>>,
+}
+
+type Job = Box<dyn FnOnce(&Cancellation) -> anyhow::Result<Option<AuxiliaryEvent>> + Send>;
+
+/// Slot of a queued job. Emptied by the worker that runs it.
+type JobSlot = Arc<Mutex<Option<Job>>>;
+
+struct Task {
+    key: Option<String>,
+    cancellation: Cancellation,
+    job: JobSlot,
+}
+
+struct KeyedTask {
+    cancellation: Cancellation,
+    job: JobSlot,
+}
+
+/// Cooperative cancellation flag passed to analysis jobs
+#[derive(Clone, Default)]
+pub(crate) struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SubmitError {
+    QueueFull,
+    Disconnected,
+}
+
+static POOL: LazyLock<WorkerPool> = LazyLock::new(|| {
+    let n_workers = std::thread::available_parallelism()
+        .map(|n| n.get() / 2)
+        .unwrap_or(MIN_WORKERS)
+        .clamp(MIN_WORKERS, MAX_WORKERS);
+    WorkerPool::new(n_workers, QUEUE_CAPACITY)
+});
+
+impl WorkerPool {
+    fn new(n_workers: usize, capacity: usize) -> Self {
+        let (tx, rx) = bounded::<Task>(capacity);
+        let keys: Arc<Mutex<HashMap<String, KeyedTask>>> = Default::default();
+
+        for i in 0..n_workers {
+            let rx = rx.clone();
+            let keys = keys.clone();
+            spawn!(format!("ark-lsp-worker-{i}"), move || worker(rx, keys));
+        }
+
+        Self { tx, keys }
+    }
+
+    fn submit<Handler>(&self, key: Option<String>, handler: Handler) -> Result<(), SubmitError>
+    where
+        Handler: FnOnce(&Cancellation) -> anyhow::Result<Option<AuxiliaryEvent>>,
+        Handler: Send + 'static,
+    {
+        let job: Job = Box::new(handler);
+
+        // Held until the job is queued so that workers can't release its key
+        // before it's registered
+        let mut keys = self.keys.lock().unwrap();
+
+        let previous = key.as_ref().and_then(|key| keys.get(key));
+        if let Some(previous) = previous {
+            // Take the place of the previous job if it hasn't started yet
+            let mut slot = previous.job.lock().unwrap();
+            if slot.is_some() {
+                *slot = Some(job);
+                return Ok(());
+            }
+        }
+
+        let cancellation = Cancellation::default();
+        let slot: JobSlot = Arc::new(Mutex::new(Some(job)));
+
+        let task = Task {
+            key: key.clone(),
+            cancellation: cancellation.clone(),
+            job: slot.clone(),
+        };
+
+        if let Err(err) = self.tx.try_send(task) {
+            return Err(match err {
+                TrySendError::Full(_) => SubmitError::QueueFull,
+                TrySendError::Disconnected(_) => SubmitError::Disconnected,
+            });
+        }
+
+        if let Some(key) = key {
+            let task = KeyedTask {
+                cancellation,
+                job: slot,
+            };
+            if let Some(previous) = keys.insert(key, task) {
+                previous.cancellation.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn worker(rx: Receiver<Task>, keys: Arc<Mutex<HashMap<String, KeyedTask>>>) {
+    for task in rx.iter() {
+        let job = task.job.lock().unwrap().take();
+
+        if let Some(job) = job {
+            let cancellation = task.cancellation.clone();
+
+            // Keep the worker alive if the job panics
+            match std::panic::catch_unwind(AssertUnwindSafe(|| job(&cancellation))) {
+                Ok(Ok(Some(event))) => send_auxiliary(event),
+                Ok(Ok(None)) => {},
+                Ok(Err(err)) => lsp::log_error!("Analysis job failed: {err:?}"),
+                Err(_) => lsp::log_error!("Analysis job panicked"),
+            }
+        }
+
+        release_key(&keys, &task.key, &task.cancellation);
+    }
+}
+
+/// Forget the cancellation flag of a finished job, unless it has already been
+/// superseded by a newer job with the same key
+fn release_key(
+    keys: &Mutex<HashMap<String, KeyedTask>>,
+    key: &Option<String>,
+    cancellation: &Cancellation,
+) {
+    let Some(key) = key else {
+        return;
+    };
+
+    let mut keys = keys.lock().unwrap();
+    if keys
+        .get(key)
+        .is_some_and(|task| Arc::ptr_eq(&task.cancellation.0, &cancellation.0))
+    {
+        keys.remove(key);
+    }
+}
+
+/// Run a CPU-bound analysis job on the worker pool
+///
+/// Like `spawn_blocking()`, the job can optionally return an event for the
+/// auxiliary loop. Jobs should check `Cancellation::is_cancelled()` between
+/// units of work and return early once cancelled. See `WorkerPool` for the
+/// meaning of `key`.
+pub(crate) fn spawn_analysis<Handler>(key: Option<String>, handler: Handler)
+where
+    Handler: FnOnce(&Cancellation) -> anyhow::Result<Option<AuxiliaryEvent>>,
+    Handler: Send + 'static,
+{
+    if let Err(err) = POOL.submit(key.clone(), handler) {
+        lsp::log_warn!("Dropping analysis job {key:?}: {err:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use crossbeam::channel::bounded;
+
+    use crate::lsp::pool::SubmitError;
+    use crate::lsp::pool::WorkerPool;
+
+    #[test]
+    fn test_pool_supersedes_keyed_jobs() {
+        let pool = WorkerPool::new(1, 8);
+        let (gate_tx, gate_rx) = bounded::<()>(0);
+        let (done_tx, done_rx) = bounded::<&str>(8);
+
+        // Occupy the only worker until we open the gate
+        pool.submit(None, move |_| {
+            gate_rx.recv().unwrap();
+            Ok(None)
+        })
+        .unwrap();
+
+        let first_ran = Arc::new(AtomicBool::new(false));
+        let first_ran_clone = first_ran.clone();
+        let tx = done_tx.clone();
+        pool.submit(Some(String::from("a")), move |_| {
+            first_ran_clone.store(true, Ordering::Relaxed);
+            tx.send("first").unwrap();
+            Ok(None)
+        })
+        .unwrap();
+
+        let tx = done_tx.clone();
+        pool.submit(Some(String::from("a")), move |_| {
+            tx.send("second").unwrap();
+            Ok(None)
+        })
+        .unwrap();
+
+        gate_tx.send(()).unwrap();
+
+        assert_eq!(done_rx.recv().unwrap(), "second");
+        assert!(!first_ran.load(Ordering::Relaxed));
+
+        // The key is released once the last job has finished
+        while !pool.keys.lock().unwrap().is_empty() {
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_pool_queue_is_bounded() {
+        let pool = WorkerPool::new(1, 1);
+        let (gate_tx, gate_rx) = bounded::<()>(0);
+        let (started_tx, started_rx) = bounded::<()>(0);
+
+        pool.submit(None, move |_| {
+            started_tx.send(()).unwrap();
+            gate_rx.recv().unwrap();
+            Ok(None)
+        })
+        .unwrap();
+        started_rx.recv().unwrap();
+
+        // Fills the queue
+        pool.submit(None, |_| Ok(None)).unwrap();
+
+        let err = pool.submit(Some(String::from("a")), |_| Ok(None));
+        assert_eq!(err, Err(SubmitError::QueueFull));
+
+        // Rejected jobs don't leave their key behind
+        assert!(pool.keys.lock().unwrap().is_empty());
+
+        gate_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_pool_superseded_jobs_dont_hold_slots() {
+        let pool = WorkerPool::new(1, 1);
+        let (gate_tx, gate_rx) = bounded::<()>(0);
+        let (started_tx, started_rx) = bounded::<()>(0);
+        let (done_tx, done_rx) = bounded::<&str>(8);
+
+        pool.submit(None, move |_| {
+            started_tx.send(()).unwrap();
+            gate_rx.recv().unwrap();
+            Ok(None)
+        })
+        .unwrap();
+        started_rx.recv().unwrap();
+
+        // Fills the queue
+        let tx = done_tx.clone();
+        pool.submit(Some(String::from("a")), move |_| {
+            tx.send("first").unwrap();
+            Ok(None)
+        })
+        .unwrap();
+
+        // Takes the place of the queued job
+        let tx = done_tx.clone();
+        pool.submit(Some(String::from("a")), move |_| {
+            tx.send("second").unwrap();
+            Ok(None)
+        })
+        .unwrap();
+
+        // Doesn't fit, and leaves the queued job alone
+        let err = pool.submit(Some(String::from("b")), |_| Ok(None));
+        assert_eq!(err, Err(SubmitError::QueueFull));
+
+        // The first job was dropped without running
+        drop(done_tx);
+        gate_tx.send(()).unwrap();
+        assert_eq!(done_rx.recv().unwrap(), "second");
+        assert!(done_rx.recv().is_err());
+    }
+}
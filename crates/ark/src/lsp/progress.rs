@@ -0,0 +1,151 @@
+//
+// progress.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use tower_lsp::lsp_types::NumberOrString;
+use tower_lsp::lsp_types::ProgressToken;
+use tower_lsp::lsp_types::WorkDoneProgressBegin;
+use tower_lsp::lsp_types::WorkDoneProgressEnd;
+use tower_lsp::lsp_types::WorkDoneProgressReport;
+
+use crate::lsp::main_loop::send_progress;
+
+/// Whether the client supports server-initiated progress, as advertised in its
+/// `window.workDoneProgress` capability. Set during initialization.
+static SUPPORTED: AtomicBool = AtomicBool::new(false);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Cancellation flags of the cancellable tasks currently in progress, indexed
+/// by token
+static CANCELLATIONS: LazyLock<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+    LazyLock::new(|| Default::default());
+
+#[derive(Debug)]
+pub(crate) enum ProgressEvent {
+    Begin(String, WorkDoneProgressBegin),
+    Report(String, WorkDoneProgressReport),
+    End(String, WorkDoneProgressEnd),
+}
+
+/// Progress of a long-running task, reported to the client with `$/progress`
+/// notifications
+///
+/// The progress ends when this is dropped. When the client doesn't support
+/// progress reporting, this is a no-op apart from tracking cancellation.
+pub(crate) struct Progress {
+    token: Option<String>,
+    cancelled: Arc<AtomicBool>,
+    percentage: u32,
+}
+
+pub(crate) fn set_supported(supported: bool) {
+    SUPPORTED.store(supported, Ordering::Relaxed);
+}
+
+/// Request cancellation of a task in progress. Called when the user cancels
+/// progress from the client. The task stops at its next `is_cancelled()`
+/// check.
+pub(crate) fn cancel(token: &ProgressToken) {
+    let NumberOrString::String(token) = token else {
+        return;
+    };
+
+    if let Some(cancelled) = CANCELLATIONS.lock().unwrap().get(token) {
+        cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Progress {
+    pub(crate) fn begin(title: &str, cancellable: bool) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        if !SUPPORTED.load(Ordering::Relaxed) {
+            return Self {
+                token: None,
+                cancelled,
+                percentage: 0,
+            };
+        }
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let token = format!("ark/progress/{id}");
+
+        if cancellable {
+            CANCELLATIONS
+                .lock()
+                .unwrap()
+                .insert(token.clone(), cancelled.clone());
+        }
+
+        send_progress(ProgressEvent::Begin(token.clone(), WorkDoneProgressBegin {
+            title: title.to_string(),
+            cancellable: Some(cancellable),
+            message: None,
+            percentage: Some(0),
+        }));
+
+        Self {
+            token: Some(token),
+            cancelled,
+            percentage: 0,
+        }
+    }
+
+    /// Report that `done` out of `total` steps are complete. Only notifies the
+    /// client when the percentage changes so that we don't flood it with
+    /// messages.
+    pub(crate) fn report(&mut self, done: usize, total: usize) {
+        let Some(token) = &self.token else {
+            return;
+        };
+
+        let percentage = if total == 0 {
+            100
+        } else {
+            (done.min(total) * 100 / total) as u32
+        };
+
+        if percentage == self.percentage {
+            return;
+        }
+        self.percentage = percentage;
+
+        send_progress(ProgressEvent::Report(
+            token.clone(),
+            WorkDoneProgressReport {
+                cancellable: None,
+                message: None,
+                percentage: Some(percentage),
+            },
+        ));
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let Some(token) = self.token.take() else {
+            return;
+        };
+
+        CANCELLATIONS.lock().unwrap().remove(&token);
+
+        let message = self.is_cancelled().then(|| String::from("Cancelled"));
+        send_progress(ProgressEvent::End(token, WorkDoneProgressEnd { message }));
+    }
+}
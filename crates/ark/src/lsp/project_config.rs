@@ -0,0 +1,311 @@
+//
+// project_config.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::lsp;
+use crate::lsp::config::parse_rules;
+use crate::lsp::config::DocumentConfig;
+use crate::lsp::config::IndentStyle;
+use crate::lsp::diagnostics::DiagnosticsConfig;
+use crate::lsp::generated::matches_globs;
+use crate::lsp::state::Workspace;
+use crate::user_config::EvaluationConfig;
+
+/// Name of the project settings file, at the root of a workspace folder
+pub(crate) const FILE_NAME: &str = ".ark.toml";
+
+/// Settings of a project, read from the `.ark.toml` file at the root of a
+/// workspace folder:
+///
+/// ```toml
+/// [diagnostics]
+/// enable = true
+/// generated_files = ["R/generated-*.R"]
+/// lintr = true
+///
+/// [diagnostics.rules]
+/// unused-variable = "off"
+/// na-comparison = "error"
+///
+/// [formatting]
+/// indent_style = "space"
+/// indent_size = 4
+/// tab_width = 8
+///
+/// [evaluation]
+/// allow_function_calls = true
+///
+/// [indexing]
+/// exclude = ["data-raw", "inst/extdata/**"]
+/// ```
+///
+/// All settings are optional. The file is checked in with the project and
+/// shared by its contributors, so its settings take precedence over those of
+/// the user: the editor settings for diagnostics and formatting, and the
+/// user configuration file for evaluation (see `user_config.rs`). Diagnostic
+/// rules and generated files are added to those of the user.
+///
+/// The file is reloaded when it changes, if the client can watch files.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProjectConfig {
+    pub diagnostics: ProjectDiagnosticsConfig,
+    pub formatting: ProjectFormattingConfig,
+    pub evaluation: ProjectEvaluationConfig,
+    pub indexing: ProjectIndexingConfig,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProjectDiagnosticsConfig {
+    pub enable: Option<bool>,
+
+    /// Globs of generated files, relative to the workspace folder. See
+    /// `is_generated()`.
+    pub generated_files: Vec<String>,
+
+    /// Levels of diagnostic rules by name, e.g. `unused-variable = "off"`
+    pub rules: HashMap<String, String>,
+
+    /// Whether lintr is run on saved documents, see `lintr.rs`
+    pub lintr: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProjectFormattingConfig {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+}
+
+/// Policies for code that Ark evaluates on its own, see `EvaluationConfig`
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProjectEvaluationConfig {
+    pub allow_function_calls: Option<bool>,
+    pub hover: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProjectIndexingConfig {
+    /// Globs of files and folders that aren't indexed, relative to the
+    /// workspace folder. Excluded files are still analysed when open.
+    pub exclude: Vec<String>,
+}
+
+impl ProjectConfig {
+    /// Settings of the first workspace folder that has a `.ark.toml` file.
+    /// Errors are logged and leave the defaults in place.
+    pub(crate) fn from_workspace(workspace: &Workspace) -> Self {
+        let paths = workspace
+            .folders
+            .iter()
+            .filter_map(|folder| folder.to_file_path().ok())
+            .map(|folder| folder.join(FILE_NAME));
+
+        for path in paths {
+            match Self::read(&path) {
+                Ok(Some(config)) => return config,
+                Ok(None) => continue,
+                Err(err) => {
+                    lsp::log_error!("Can't read project settings '{}': {err:?}", path.display());
+                    return Self::default();
+                },
+            }
+        }
+
+        Self::default()
+    }
+
+    /// Parse a project settings file. Returns `None` if it doesn't exist.
+    pub(crate) fn read(path: &Path) -> anyhow::Result<Option<Self>> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(toml::from_str(&contents)?))
+    }
+
+    pub(crate) fn apply_diagnostics(&self, config: &mut DiagnosticsConfig) {
+        let project = &self.diagnostics;
+
+        if let Some(enable) = project.enable {
+            config.enable = enable;
+        }
+        config
+            .generated_files
+            .extend(project.generated_files.iter().cloned());
+        config.rules.extend(parse_rules(project.rules.clone()));
+        if let Some(lintr) = project.lintr {
+            config.lintr = lintr;
+        }
+    }
+
+    pub(crate) fn apply_formatting(&self, config: &mut DocumentConfig) {
+        let project = &self.formatting;
+
+        if let Some(indent_style) = project.indent_style {
+            config.indent.indent_style = indent_style;
+        }
+        if let Some(indent_size) = project.indent_size {
+            config.indent.indent_size = indent_size;
+        }
+        if let Some(tab_width) = project.tab_width {
+            config.indent.tab_width = tab_width;
+        }
+    }
+
+    /// Whether `path` is excluded from indexing. `folders` are the paths of
+    /// the workspace folders.
+    pub(crate) fn is_excluded(&self, path: &Path, folders: &[PathBuf]) -> bool {
+        let globs = &self.indexing.exclude;
+        !globs.is_empty() && matches_globs(path, globs.iter().map(String::as_str), folders)
+    }
+}
+
+impl ProjectEvaluationConfig {
+    pub(crate) fn apply(&self, config: &mut EvaluationConfig) {
+        if let Some(allow_function_calls) = self.allow_function_calls {
+            config.allow_function_calls = allow_function_calls;
+        }
+        if let Some(hover) = self.hover {
+            config.hover = hover;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use url::Url;
+
+    use crate::lsp::config::DocumentConfig;
+    use crate::lsp::config::IndentStyle;
+    use crate::lsp::config::LspConfig;
+    use crate::lsp::diagnostics::DiagnosticRule;
+    use crate::lsp::diagnostics::DiagnosticsConfig;
+    use crate::lsp::diagnostics::RuleLevel;
+    use crate::lsp::project_config::ProjectConfig;
+    use crate::lsp::state::Workspace;
+    use crate::user_config::EvaluationConfig;
+
+    #[test]
+    fn test_project_config_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".ark.toml");
+
+        let mut workspace = Workspace::default();
+        workspace
+            .folders
+            .push(Url::from_directory_path(dir.path()).unwrap());
+
+        // Missing files are empty configurations
+        assert_eq!(ProjectConfig::read(&path).unwrap(), None);
+        assert_eq!(
+            ProjectConfig::from_workspace(&workspace),
+            ProjectConfig::default()
+        );
+
+        std::fs::write(
+            &path,
+            "[diagnostics.rules]\nunused-variable = \"off\"\n\n[formatting]\nindent_style = \"tab\"\ntab_width = 8\n\n[evaluation]\nallow_function_calls = true\n\n[indexing]\nexclude = [\"data-raw\"]\n",
+        )
+        .unwrap();
+        let config = ProjectConfig::from_workspace(&workspace);
+        assert_eq!(config.formatting.indent_style, Some(IndentStyle::Tab));
+        assert_eq!(config.formatting.indent_size, None);
+        assert_eq!(config.evaluation.allow_function_calls, Some(true));
+        assert_eq!(config.indexing.exclude, vec!["data-raw"]);
+
+        // Typos are reported
+        std::fs::write(&path, "[formatting]\nindent_syle = \"tab\"\n").unwrap();
+        assert!(ProjectConfig::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_project_config_merge() {
+        let project: ProjectConfig = toml::from_str(
+            "[diagnostics]\ngenerated_files = [\"R/gen-*.R\"]\nlintr = true\n\n[diagnostics.rules]\nunused-variable = \"off\"\nna-comparison = \"error\"\n\n[formatting]\nindent_size = 4\n\n[evaluation]\nhover = false\n",
+        )
+        .unwrap();
+
+        // Project rules take precedence over those of the user
+        let client = DiagnosticsConfig {
+            generated_files: vec![String::from("R/other.R")],
+            rules: HashMap::from([
+                (DiagnosticRule::UnusedVariable, RuleLevel::Warning),
+                (DiagnosticRule::CallArity, RuleLevel::Hint),
+            ]),
+            ..Default::default()
+        };
+
+        let mut config = LspConfig::default();
+        assert!(config.set_client_diagnostics(client));
+        assert!(config.set_project(project.clone()));
+
+        let diagnostics = &config.diagnostics;
+        assert!(diagnostics.enable);
+        assert!(diagnostics.lintr);
+        assert_eq!(diagnostics.generated_files, vec!["R/other.R", "R/gen-*.R"]);
+        assert_eq!(
+            diagnostics.rules.get(&DiagnosticRule::UnusedVariable),
+            Some(&RuleLevel::Off)
+        );
+        assert_eq!(
+            diagnostics.rules.get(&DiagnosticRule::NaComparison),
+            Some(&RuleLevel::Error)
+        );
+        assert_eq!(
+            diagnostics.rules.get(&DiagnosticRule::CallArity),
+            Some(&RuleLevel::Hint)
+        );
+
+        // Removing the project settings restores those of the user
+        assert!(config.set_project(ProjectConfig::default()));
+        assert_eq!(config.diagnostics, config.client_diagnostics);
+
+        // Unset settings keep the values of the user
+        let mut document = DocumentConfig::default();
+        document.indent.indent_style = IndentStyle::Tab;
+        project.apply_formatting(&mut document);
+        assert_eq!(document.indent.indent_style, IndentStyle::Tab);
+        assert_eq!(document.indent.indent_size, 4);
+        assert_eq!(document.indent.tab_width, 2);
+
+        let mut evaluation = EvaluationConfig {
+            allow_function_calls: true,
+            hover: true,
+        };
+        project.evaluation.apply(&mut evaluation);
+        assert!(evaluation.allow_function_calls);
+        assert!(!evaluation.hover);
+    }
+
+    #[test]
+    fn test_project_config_is_excluded() {
+        let project: ProjectConfig =
+            toml::from_str("[indexing]\nexclude = [\"data-raw\", \"inst/**/*.R\"]\n").unwrap();
+        let folders = vec![PathBuf::from("/project")];
+
+        assert!(project.is_excluded(&PathBuf::from("/project/data-raw"), &folders));
+        assert!(project.is_excluded(&PathBuf::from("/project/inst/scripts/foo.R"), &folders));
+        assert!(!project.is_excluded(&PathBuf::from("/project/R/foo.R"), &folders));
+        assert!(
+            !ProjectConfig::default().is_excluded(&PathBuf::from("/project/data-raw"), &folders)
+        );
+    }
+}
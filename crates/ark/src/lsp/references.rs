@@ -5,8 +5,6 @@
 //
 //
 
-use std::path::Path;
-
 use anyhow::anyhow;
 use ropey::Rope;
 use stdext::unwrap::IntoResult;
@@ -18,71 +16,34 @@ use tower_lsp::lsp_types::ReferenceParams;
 use tower_lsp::lsp_types::Url;
 use tree_sitter::Node;
 use tree_sitter::Point;
-use walkdir::WalkDir;
 
 use crate::lsp;
 use crate::lsp::documents::Document;
 use crate::lsp::encoding::convert_point_to_position;
 use crate::lsp::encoding::convert_position_to_point;
-use crate::lsp::indexer::filter_entry;
+use crate::lsp::indexer;
+use crate::lsp::indexer::node_reference_kind;
+use crate::lsp::indexer::ReferenceKind;
+use crate::lsp::state::detached_documents;
 use crate::lsp::state::with_document;
 use crate::lsp::state::WorldState;
 use crate::lsp::traits::cursor::TreeCursorExt;
 use crate::lsp::traits::rope::RopeExt;
-use crate::lsp::traits::url::UrlExt;
-use crate::treesitter::ExtractOperatorType;
-use crate::treesitter::NodeType;
 use crate::treesitter::NodeTypeExt;
 
-#[derive(Debug, PartialEq)]
-enum ReferenceKind {
-    SymbolName, // a regular R symbol
-    DollarName, // a dollar name, following '$'
-    AtName,     // a slot name, following '@'
+/// The identifier whose references are looked up
+pub(crate) struct Context {
+    pub(crate) kind: ReferenceKind,
+    pub(crate) symbol: String,
+    /// Range of the identifier at the cursor
+    pub(crate) range: Range,
 }
 
-// Assuming `x` is an `identifier`, is it the RHS of a `$` or `@`?
-fn node_reference_kind(x: &Node) -> ReferenceKind {
-    let Some(parent) = x.parent() else {
-        // No `parent`, must be a regular symbol
-        return ReferenceKind::SymbolName;
-    };
-
-    let parent_type = parent.node_type();
-
-    if !matches!(parent_type, NodeType::ExtractOperator(_)) {
-        // Parent not `$` or `@`
-        return ReferenceKind::SymbolName;
-    }
-
-    // Need to check that we actually came from the RHS
-    let Some(rhs) = parent.child_by_field_name("rhs") else {
-        return ReferenceKind::SymbolName;
-    };
-    if &rhs != x {
-        return ReferenceKind::SymbolName;
-    };
-
-    match parent_type {
-        NodeType::ExtractOperator(ExtractOperatorType::Dollar) => ReferenceKind::DollarName,
-        NodeType::ExtractOperator(ExtractOperatorType::At) => ReferenceKind::AtName,
-        _ => std::unreachable!(),
-    }
-}
-
-struct Context {
-    kind: ReferenceKind,
-    symbol: String,
-}
-
-fn add_reference(node: &Node, contents: &Rope, path: &Path, locations: &mut Vec<Location>) {
+fn add_reference(node: &Node, contents: &Rope, uri: &Url, locations: &mut Vec<Location>) {
     let start = convert_point_to_position(contents, node.start_position());
     let end = convert_point_to_position(contents, node.end_position());
 
-    let location = Location::new(
-        Url::from_file_path(path).expect("valid path"),
-        Range::new(start, end),
-    );
+    let location = Location::new(uri.clone(), Range::new(start, end));
     locations.push(location);
 }
 
@@ -99,12 +60,13 @@ fn found_match(node: &Node, contents: &Rope, context: &Context) -> bool {
     context.kind == node_reference_kind(node)
 }
 
-fn build_context(uri: &Url, position: Position, state: &WorldState) -> anyhow::Result<Context> {
-    // Unwrap the URL.
-    let path = uri.file_path()?;
-
+pub(crate) fn build_context(
+    uri: &Url,
+    position: Position,
+    state: &WorldState,
+) -> anyhow::Result<Context> {
     // Figure out the identifier we're looking for.
-    let context = with_document(path.as_path(), state, |document| {
+    let context = with_document(uri, state, |document| {
         let ast = &document.ast;
         let contents = &document.contents;
         let point = convert_position_to_point(contents, position);
@@ -142,46 +104,23 @@ fn build_context(uri: &Url, position: Position, state: &WorldState) -> anyhow::R
         // return identifier text contents
         let symbol = document.contents.node_slice(&node)?.to_string();
 
-        Ok(Context { kind, symbol })
+        let start = convert_point_to_position(contents, node.start_position());
+        let end = convert_point_to_position(contents, node.end_position());
+        let range = Range::new(start, end);
+
+        Ok(Context {
+            kind,
+            symbol,
+            range,
+        })
     });
 
     return context;
 }
 
-fn find_references_in_folder(
-    context: &Context,
-    path: &Path,
-    locations: &mut Vec<Location>,
-    state: &WorldState,
-) {
-    let walker = WalkDir::new(path);
-    for entry in walker.into_iter().filter_entry(|entry| filter_entry(entry)) {
-        let entry = unwrap!(entry, Err(_) => { continue; });
-        let path = entry.path();
-        let ext = unwrap!(path.extension(), None => { continue; });
-        if ext != "r" && ext != "R" {
-            continue;
-        }
-
-        lsp::log_info!("found R file {}", path.display());
-        let result = with_document(path, state, |document| {
-            find_references_in_document(context, path, document, locations);
-            return Ok(());
-        });
-
-        match result {
-            Ok(result) => result,
-            Err(_error) => {
-                lsp::log_warn!("error retrieving document for path {}", path.display());
-                continue;
-            },
-        }
-    }
-}
-
 fn find_references_in_document(
     context: &Context,
-    path: &Path,
+    uri: &Url,
     document: &Document,
     locations: &mut Vec<Location>,
 ) {
@@ -191,7 +130,7 @@ fn find_references_in_document(
     let mut cursor = ast.walk();
     cursor.recurse(|node| {
         if found_match(&node, contents, &context) {
-            add_reference(&node, contents, path, locations);
+            add_reference(&node, contents, uri, locations);
         }
 
         return true;
@@ -202,9 +141,6 @@ pub(crate) fn find_references(
     params: ReferenceParams,
     state: &WorldState,
 ) -> anyhow::Result<Vec<Location>> {
-    // Create our locations vector.
-    let mut locations: Vec<Location> = Vec::new();
-
     // Extract relevant parameters.
     let uri = params.text_document_position.text_document.uri;
     let position = params.text_document_position.position;
@@ -214,13 +150,33 @@ pub(crate) fn find_references(
         return Err(anyhow!("Failed to find build context at position {position:?}: {err:?}"));
     });
 
-    // Now, start searching through workspace folders for references to that identifier.
-    for folder in state.workspace.folders.iter() {
-        if let Ok(path) = folder.to_file_path() {
-            lsp::log_info!("searching references in folder {}", path.display());
-            find_references_in_folder(&context, &path, &mut locations, state);
+    Ok(find_context_references(&context, state))
+}
+
+/// References to the identifier of `context` in the workspace and in the
+/// documents open outside of it
+pub(crate) fn find_context_references(context: &Context, state: &WorldState) -> Vec<Location> {
+    let mut locations: Vec<Location> = Vec::new();
+
+    // Look up references to that identifier in the files of the
+    // workspace. Open files are indexed from their editor contents.
+    let references = indexer::find_references(context.kind.clone(), &context.symbol);
+    for (path, range) in references {
+        let Ok(uri) = Url::from_file_path(&path) else {
+            lsp::log_warn!("Can't convert path {} to URI", path.display());
+            continue;
+        };
+        locations.push(Location::new(uri, range));
+    }
+
+    // And search through untitled buffers and virtual documents, which aren't
+    // indexed. Files outside of the workspace are indexed when opened.
+    for (uri, document) in detached_documents(state) {
+        if uri.to_file_path().is_ok() {
+            continue;
         }
+        find_references_in_document(context, uri, document, &mut locations);
     }
 
-    return Ok(locations);
+    locations
 }
@@ -0,0 +1,277 @@
+//
+// rename.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use harp::utils::is_symbol_valid;
+use harp::utils::sym_quote;
+use tower_lsp::lsp_types::PrepareRenameResponse;
+use tower_lsp::lsp_types::RenameParams;
+use tower_lsp::lsp_types::TextDocumentPositionParams;
+use tower_lsp::lsp_types::TextEdit;
+use tower_lsp::lsp_types::Url;
+use tower_lsp::lsp_types::WorkspaceEdit;
+use tree_sitter::Node;
+
+use crate::lsp::documents::Document;
+use crate::lsp::indexer;
+use crate::lsp::indexer::ReferenceKind;
+use crate::lsp::references::build_context;
+use crate::lsp::references::find_context_references;
+use crate::lsp::references::Context;
+use crate::lsp::state::with_document;
+use crate::lsp::state::WorldState;
+use crate::lsp::traits::cursor::TreeCursorExt;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+
+const RESERVED_WORDS: &[&str] = &[
+    "if",
+    "else",
+    "repeat",
+    "while",
+    "function",
+    "for",
+    "next",
+    "break",
+    "TRUE",
+    "FALSE",
+    "NULL",
+    "Inf",
+    "NaN",
+    "NA",
+    "NA_integer_",
+    "NA_real_",
+    "NA_character_",
+    "NA_complex_",
+    "in",
+];
+
+/// Check that the identifier at the cursor can be renamed. Only symbols
+/// defined in the workspace or in the current document can be renamed, so that
+/// e.g. renaming a call to `paste()` doesn't rewrite all the calls to
+/// `paste()` of the workspace.
+pub(crate) fn prepare_rename(
+    params: TextDocumentPositionParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<PrepareRenameResponse>> {
+    let uri = &params.text_document.uri;
+
+    let Ok(context) = build_context(uri, params.position, state) else {
+        return Ok(None);
+    };
+
+    if !is_renamable(&context, uri, state) {
+        return Ok(None);
+    }
+
+    Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+        range: context.range,
+        placeholder: context.symbol,
+    }))
+}
+
+/// Rename the identifier at the cursor, along with all its references in the
+/// workspace and in open documents
+pub(crate) fn rename(
+    params: RenameParams,
+    state: &WorldState,
+) -> anyhow::Result<Option<WorkspaceEdit>> {
+    let uri = &params.text_document_position.text_document.uri;
+    let position = params.text_document_position.position;
+
+    let Ok(context) = build_context(uri, position, state) else {
+        return Ok(None);
+    };
+
+    if !is_renamable(&context, uri, state) {
+        return Err(anyhow!(
+            "Can't rename `{}`, it isn't defined in the workspace",
+            context.symbol
+        ));
+    }
+
+    let new_name = new_symbol(&params.new_name)?;
+
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for location in find_context_references(&context, state) {
+        // Virtual documents are read-only
+        if location.uri.scheme() == "ark" {
+            continue;
+        }
+        changes
+            .entry(location.uri)
+            .or_default()
+            .push(TextEdit::new(location.range, new_name.clone()));
+    }
+
+    Ok(Some(WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    }))
+}
+
+fn is_renamable(context: &Context, uri: &Url, state: &WorldState) -> bool {
+    // Fields of lists and slots can't be tied to a definition
+    if context.kind != ReferenceKind::SymbolName {
+        return false;
+    }
+
+    if indexer::find_function(&context.symbol).is_some() {
+        return true;
+    }
+
+    with_document(uri, state, |document| {
+        Ok(has_definition(document, &context.symbol))
+    })
+    .unwrap_or(false)
+}
+
+/// Whether `symbol` is assigned, or is a parameter or a loop variable, in
+/// `document`
+fn has_definition(document: &Document, symbol: &str) -> bool {
+    let contents = &document.contents;
+    let mut found = false;
+
+    document.ast.walk().recurse(|node| {
+        if found {
+            return false;
+        }
+
+        if node.is_identifier() &&
+            contents.node_slice(&node).is_ok_and(|text| text == symbol) &&
+            is_definition(&node)
+        {
+            found = true;
+        }
+
+        true
+    });
+
+    found
+}
+
+fn is_definition(node: &Node) -> bool {
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+
+    let field = match parent.node_type() {
+        NodeType::BinaryOperator(BinaryOperatorType::LeftAssignment) |
+        NodeType::BinaryOperator(BinaryOperatorType::LeftSuperAssignment) |
+        NodeType::BinaryOperator(BinaryOperatorType::EqualsAssignment) => "lhs",
+        NodeType::BinaryOperator(BinaryOperatorType::RightAssignment) |
+        NodeType::BinaryOperator(BinaryOperatorType::RightSuperAssignment) => "rhs",
+        NodeType::Parameter => "name",
+        NodeType::ForStatement => "variable",
+        _ => return false,
+    };
+
+    parent.child_by_field_name(field) == Some(*node)
+}
+
+/// The text that replaces the references, with backticks if `name` isn't
+/// syntactic
+fn new_symbol(name: &str) -> anyhow::Result<String> {
+    let name = name.trim();
+
+    if name.is_empty() || name == "``" {
+        return Err(anyhow!("Can't rename to an empty name"));
+    }
+
+    // Already quoted by the user
+    if name.len() > 2 && name.starts_with('`') && name.ends_with('`') {
+        return Ok(name.to_string());
+    }
+
+    if is_symbol_valid(name) && !RESERVED_WORDS.contains(&name) {
+        Ok(name.to_string())
+    } else {
+        Ok(sym_quote(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+    use tower_lsp::lsp_types::RenameParams;
+    use tower_lsp::lsp_types::TextDocumentIdentifier;
+    use tower_lsp::lsp_types::TextDocumentPositionParams;
+    use tower_lsp::lsp_types::Url;
+
+    use crate::lsp::documents::Document;
+    use crate::lsp::indexer;
+    use crate::lsp::rename::new_symbol;
+    use crate::lsp::rename::prepare_rename;
+    use crate::lsp::rename::rename;
+    use crate::lsp::state::WorldState;
+
+    fn position_params(uri: &Url, line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position::new(line, character),
+        }
+    }
+
+    #[test]
+    fn test_rename() {
+        let path = std::env::temp_dir().join("ark-test-rename").join("utils.R");
+        let path = path.as_path();
+        let file = Url::from_file_path(path).unwrap();
+        let untitled = Url::parse("untitled:Untitled-1").unwrap();
+
+        let mut state = WorldState::default();
+
+        let document = Document::new("ark_helper <- function(x) x\nark_helper(1)", None);
+        indexer::update(&document, path).unwrap();
+        state.documents.insert(file.clone(), document);
+
+        let document = Document::new("ark_helper(2)\npaste(ark_helper)", None);
+        state.documents.insert(untitled.clone(), document);
+
+        // Functions defined in the workspace can be renamed from anywhere
+        let response = prepare_rename(position_params(&untitled, 0, 2), &state).unwrap();
+        assert!(response.is_some());
+
+        let params = RenameParams {
+            text_document_position: position_params(&untitled, 0, 2),
+            new_name: String::from("ark_util"),
+            work_done_progress_params: Default::default(),
+        };
+        let edit = rename(params, &state).unwrap().unwrap();
+        let changes = edit.changes.unwrap();
+
+        let lines = |uri: &Url| -> Vec<u32> {
+            let mut lines: Vec<u32> = changes[uri].iter().map(|e| e.range.start.line).collect();
+            lines.sort();
+            lines
+        };
+        assert_eq!(lines(&file), vec![0, 1]);
+        assert_eq!(lines(&untitled), vec![0, 1]);
+        assert!(changes[&file]
+            .iter()
+            .all(|edit| edit.new_text == "ark_util"));
+
+        // Symbols defined elsewhere can't be renamed
+        let response = prepare_rename(position_params(&untitled, 1, 1), &state).unwrap();
+        assert!(response.is_none());
+
+        indexer::remove(path).unwrap();
+    }
+
+    #[test]
+    fn test_rename_new_symbol() {
+        assert_eq!(new_symbol("foo").unwrap(), "foo");
+        assert_eq!(new_symbol("foo bar").unwrap(), "`foo bar`");
+        assert_eq!(new_symbol("`foo bar`").unwrap(), "`foo bar`");
+        assert_eq!(new_symbol("if").unwrap(), "`if`");
+        assert!(new_symbol(" ").is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//
+// scheduler.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::BinaryHeap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Condvar;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+
+use crate::interface::RMain;
+use crate::lsp;
+use crate::r_task;
+use crate::r_task::r_task_timeout;
+
+/// Priority of LSP work that needs the R thread
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Priority {
+    /// Work that no one is waiting on
+    Background,
+
+    /// Requests triggered explicitly by the user, e.g. go to definition
+    Normal,
+
+    /// Requests sent while the user is typing, e.g. completions and hovers
+    Interactive,
+}
+
+impl Priority {
+    /// How long a request may wait for the R thread before giving up. The
+    /// client has usually moved on by then.
+    fn timeout(self) -> Duration {
+        match self {
+            Priority::Interactive => Duration::from_secs(2),
+            Priority::Normal => Duration::from_secs(5),
+            Priority::Background => Duration::from_secs(30),
+        }
+    }
+}
+
+/// Scheduler of the LSP requests that evaluate R code
+///
+/// The R thread runs one task at a time, and none at all while it's busy with
+/// a computation that doesn't check for interrupts. Requests sent straight to
+/// the R thread queue up behind each other in the order they arrive.
+///
+/// Instead, LSP requests take turns to send a task to the R thread, by
+/// decreasing priority and then in order of arrival. Each request gives up
+/// once it has waited longer than the timeout of its priority, whether for
+/// its turn or for the R thread to pick up its task, so that a busy R session
+/// makes LSP features fail fast rather than pile up.
+struct Scheduler {
+    state: Mutex<SchedulerState>,
+    turn: Condvar,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    /// Whether a request currently has its turn
+    busy: bool,
+    waiting: BinaryHeap<Ticket>,
+    next_seq: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Ticket {
+    priority: Priority,
+    /// Reversed so that earlier requests come first among equals
+    seq: std::cmp::Reverse<u64>,
+}
+
+/// Counters of scheduled requests, recorded in the tracing profile and logged
+/// when requests time out
+#[derive(Default)]
+struct Stats {
+    completed: AtomicU64,
+    timed_out: AtomicU64,
+    wait_ms: AtomicU64,
+}
+
+static SCHEDULER: LazyLock<Scheduler> = LazyLock::new(Scheduler::new);
+static STATS: LazyLock<Stats> = LazyLock::new(Stats::default);
+
+/// A request's turn, passed to the next request when dropped
+struct Turn<'a> {
+    scheduler: &'a Scheduler,
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        self.scheduler.state.lock().unwrap().busy = false;
+        self.scheduler.turn.notify_all();
+    }
+}
+
+impl Scheduler {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(SchedulerState::default()),
+            turn: Condvar::new(),
+        }
+    }
+
+    /// Wait for our turn. Returns `None` if `deadline` passes first.
+    fn acquire(&self, priority: Priority, deadline: Instant) -> Option<Turn<'_>> {
+        let mut state = self.state.lock().unwrap();
+
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.waiting.push(Ticket {
+            priority,
+            seq: std::cmp::Reverse(seq),
+        });
+
+        loop {
+            let is_next = state
+                .waiting
+                .peek()
+                .is_some_and(|ticket| ticket.seq.0 == seq);
+
+            if !state.busy && is_next {
+                state.waiting.pop();
+                state.busy = true;
+                return Some(Turn { scheduler: self });
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                state.waiting.retain(|ticket| ticket.seq.0 != seq);
+
+                // We may have been next in line
+                drop(state);
+                self.turn.notify_all();
+
+                return None;
+            }
+
+            state = self.turn.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
+
+/// Run `f` on the R thread on behalf of the LSP request `name`, once it's the
+/// turn of the request, see `Scheduler`. Fails if the request times out
+/// before `f` has started. Once started, `f` runs to completion, so it should
+/// check for cancellation if it might take long.
+#[track_caller]
+pub(crate) fn r_request<'env, F, T>(
+    name: &'static str,
+    priority: Priority,
+    f: F,
+) -> anyhow::Result<T>
+where
+    F: FnOnce() -> T,
+    F: 'env + Send,
+    T: 'env + Send,
+{
+    // Nested requests run right away, like nested `r_task()`s. Waiting for
+    // our turn would deadlock since the outer request holds it.
+    if stdext::IS_TESTING || RMain::on_main_thread() {
+        return Ok(r_task(f));
+    }
+
+    let _span = tracing::info_span!("lsp_r_request", name, ?priority).entered();
+
+    let start = Instant::now();
+    let deadline = start + priority.timeout();
+
+    let turn = tracing::trace_span!("lsp_r_request_wait")
+        .in_scope(|| SCHEDULER.acquire(priority, deadline));
+
+    let result = match turn {
+        Some(_turn) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            r_task_timeout(remaining, f)
+        },
+        None => None,
+    };
+
+    let waited = start.elapsed();
+
+    let Some(value) = result else {
+        let timed_out = STATS.timed_out.fetch_add(1, Ordering::Relaxed) + 1;
+        let completed = STATS.completed.load(Ordering::Relaxed);
+        lsp::log_warn!(
+            "The R session didn't answer the `{name}` request after {} ms \
+             ({timed_out} requests timed out, {completed} completed).",
+            waited.as_millis()
+        );
+        return Err(anyhow!("Timed out while waiting for R to answer `{name}`."));
+    };
+
+    let waited_ms = waited.as_millis() as u64;
+    let completed = STATS.completed.fetch_add(1, Ordering::Relaxed) + 1;
+    let wait_ms = STATS.wait_ms.fetch_add(waited_ms, Ordering::Relaxed) + waited_ms;
+    tracing::trace!(
+        completed,
+        timed_out = STATS.timed_out.load(Ordering::Relaxed),
+        mean_wait_ms = wait_ms / completed,
+        "lsp_r_request_stats"
+    );
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use crossbeam::channel::bounded;
+
+    use crate::lsp::scheduler::Priority;
+    use crate::lsp::scheduler::Scheduler;
+
+    #[test]
+    fn test_scheduler_priority() {
+        let scheduler = Arc::new(Scheduler::new());
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        let turn = scheduler.acquire(Priority::Normal, deadline).unwrap();
+
+        let (done_tx, done_rx) = bounded::<Priority>(2);
+        let mut handles = vec![];
+
+        for priority in [Priority::Background, Priority::Interactive] {
+            let handle = std::thread::spawn({
+                let scheduler = scheduler.clone();
+                let done_tx = done_tx.clone();
+                move || {
+                    let _turn = scheduler.acquire(priority, deadline).unwrap();
+                    done_tx.send(priority).unwrap();
+                }
+            });
+            handles.push(handle);
+
+            // Wait for the request to be queued
+            while scheduler.state.lock().unwrap().waiting.len() < handles.len() {
+                std::thread::yield_now();
+            }
+        }
+
+        drop(turn);
+
+        assert_eq!(done_rx.recv().unwrap(), Priority::Interactive);
+        assert_eq!(done_rx.recv().unwrap(), Priority::Background);
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_scheduler_timeout() {
+        let scheduler = Scheduler::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let turn = scheduler.acquire(Priority::Normal, deadline).unwrap();
+
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert!(scheduler.acquire(Priority::Interactive, deadline).is_none());
+
+        // Timed out requests leave the queue
+        assert!(scheduler.state.lock().unwrap().waiting.is_empty());
+
+        drop(turn);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        assert!(scheduler.acquire(Priority::Background, deadline).is_some());
+    }
+}
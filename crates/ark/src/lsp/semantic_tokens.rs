@@ -0,0 +1,425 @@
+//
+// semantic_tokens.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use ropey::Rope;
+use tower_lsp::lsp_types::SemanticToken;
+use tower_lsp::lsp_types::SemanticTokenModifier;
+use tower_lsp::lsp_types::SemanticTokenType;
+use tower_lsp::lsp_types::SemanticTokens;
+use tower_lsp::lsp_types::SemanticTokensLegend;
+use tree_sitter::Node;
+
+use crate::lsp::documents::Document;
+use crate::lsp::encoding::convert_point_to_position;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+
+/// Token types, in the order of the legend
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TokenType {
+    Namespace = 0,
+    Function = 1,
+    Parameter = 2,
+    Method = 3,
+}
+
+/// Token modifiers, as bits of the legend
+const DECLARATION: u32 = 1 << 0;
+
+/// Legend of the semantic tokens, declared in the server capabilities
+pub(crate) fn semantic_tokens_legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::NAMESPACE,
+            SemanticTokenType::FUNCTION,
+            SemanticTokenType::PARAMETER,
+            SemanticTokenType::METHOD,
+        ],
+        token_modifiers: vec![SemanticTokenModifier::DECLARATION],
+    }
+}
+
+/// A token before encoding, positioned with its node
+struct Token<'tree> {
+    node: Node<'tree>,
+    token_type: TokenType,
+    modifiers: u32,
+}
+
+/// Classify the parts of R code that TextMate grammars can't tell apart:
+///
+/// - Names of functions defined with `name <- function() {}`.
+/// - Parameters of functions, where they are declared and used.
+/// - Methods: generics of `setGeneric()` and `setMethod()`, and functions in
+///   the `public`, `private`, and `active` lists of `R6Class()`.
+/// - Namespaced calls, i.e. `pkg` and `fun` in `pkg::fun()`.
+pub(crate) fn semantic_tokens(document: &Document) -> anyhow::Result<SemanticTokens> {
+    let contents = &document.contents;
+
+    let mut tokens = Vec::new();
+    let mut scopes = Vec::new();
+    collect(document.ast.root_node(), contents, &mut scopes, &mut tokens)?;
+
+    // Nodes are collected in tree order, which isn't the document order when
+    // a parent classifies its children ahead of time
+    tokens.sort_by_key(|token| token.node.start_byte());
+    tokens.dedup_by_key(|token| token.node.start_byte());
+
+    Ok(SemanticTokens {
+        result_id: None,
+        data: encode(&tokens, contents),
+    })
+}
+
+fn collect<'tree>(
+    node: Node<'tree>,
+    contents: &Rope,
+    scopes: &mut Vec<Vec<String>>,
+    tokens: &mut Vec<Token<'tree>>,
+) -> anyhow::Result<()> {
+    match node.node_type() {
+        NodeType::FunctionDefinition => {
+            return collect_function(node, contents, scopes, tokens);
+        },
+
+        NodeType::Identifier => {
+            let name = contents.node_slice(&node)?.to_string();
+            if scopes.iter().any(|scope| scope.contains(&name)) {
+                push(tokens, node, TokenType::Parameter, 0);
+            }
+            return Ok(());
+        },
+
+        NodeType::NamespaceOperator(_) => {
+            if let Some(lhs) = node.child_by_field_name("lhs") {
+                push(tokens, lhs, TokenType::Namespace, 0);
+            }
+
+            // `pkg::data` may well not be a function
+            let is_callee = node.parent().is_some_and(|parent| {
+                parent.is_call() && parent.child_by_field_name("function") == Some(node)
+            });
+            if let Some(rhs) = node.child_by_field_name("rhs") {
+                if is_callee {
+                    push(tokens, rhs, TokenType::Function, 0);
+                }
+            }
+            return Ok(());
+        },
+
+        NodeType::ExtractOperator(_) => {
+            // The names of `x$name` and `x@name` are never parameters
+            if let Some(lhs) = node.child_by_field_name("lhs") {
+                collect(lhs, contents, scopes, tokens)?;
+            }
+            return Ok(());
+        },
+
+        NodeType::Argument => {
+            // Neither are argument names
+            if let Some(value) = node.child_by_field_name("value") {
+                collect(value, contents, scopes, tokens)?;
+            }
+            return Ok(());
+        },
+
+        NodeType::BinaryOperator(BinaryOperatorType::LeftAssignment) |
+        NodeType::BinaryOperator(BinaryOperatorType::LeftSuperAssignment) |
+        NodeType::BinaryOperator(BinaryOperatorType::EqualsAssignment) => {
+            if let (Some(lhs), Some(rhs)) = (
+                node.child_by_field_name("lhs"),
+                node.child_by_field_name("rhs"),
+            ) {
+                if lhs.is_identifier_or_string() && rhs.is_function_definition() {
+                    push(tokens, lhs, TokenType::Function, DECLARATION);
+                }
+            }
+        },
+
+        NodeType::Call => {
+            collect_methods(node, contents, tokens)?;
+        },
+
+        _ => {},
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect(child, contents, scopes, tokens)?;
+    }
+
+    Ok(())
+}
+
+/// Parameters are in scope of their defaults and of the body
+fn collect_function<'tree>(
+    node: Node<'tree>,
+    contents: &Rope,
+    scopes: &mut Vec<Vec<String>>,
+    tokens: &mut Vec<Token<'tree>>,
+) -> anyhow::Result<()> {
+    let mut names = Vec::new();
+    let mut defaults = Vec::new();
+
+    if let Some(parameters) = node.child_by_field_name("parameters") {
+        let mut cursor = parameters.walk();
+        for parameter in parameters.children_by_field_name("parameter", &mut cursor) {
+            if let Some(name) = parameter.child_by_field_name("name") {
+                push(tokens, name, TokenType::Parameter, DECLARATION);
+                names.push(contents.node_slice(&name)?.to_string());
+            }
+            if let Some(default) = parameter.child_by_field_name("default") {
+                defaults.push(default);
+            }
+        }
+    }
+
+    scopes.push(names);
+
+    let body = node.child_by_field_name("body");
+    let result = defaults
+        .into_iter()
+        .chain(body)
+        .try_for_each(|child| collect(child, contents, scopes, tokens));
+
+    scopes.pop();
+    result
+}
+
+/// Classify the generics of `setGeneric("name")` and `setMethod("name")`,
+/// and the methods of `R6Class()`
+fn collect_methods<'tree>(
+    node: Node<'tree>,
+    contents: &Rope,
+    tokens: &mut Vec<Token<'tree>>,
+) -> anyhow::Result<()> {
+    let Some(mut function) = node.child_by_field_name("function") else {
+        return Ok(());
+    };
+    if function.is_namespace_operator() {
+        let Some(rhs) = function.child_by_field_name("rhs") else {
+            return Ok(());
+        };
+        function = rhs;
+    }
+
+    let Some(arguments) = node.child_by_field_name("arguments") else {
+        return Ok(());
+    };
+    let mut cursor = arguments.walk();
+    let arguments: Vec<Node> = arguments
+        .children_by_field_name("argument", &mut cursor)
+        .collect();
+
+    match contents.node_slice(&function)?.to_string().as_str() {
+        "setGeneric" | "setMethod" | "setReplaceMethod" => {
+            let name = arguments
+                .first()
+                .and_then(|argument| argument.child_by_field_name("value"));
+            if let Some(name) = name.filter(|name| name.is_string()) {
+                push(tokens, name, TokenType::Method, DECLARATION);
+            }
+        },
+
+        "R6Class" => {
+            for argument in arguments {
+                let (Some(name), Some(value)) = (
+                    argument.child_by_field_name("name"),
+                    argument.child_by_field_name("value"),
+                ) else {
+                    continue;
+                };
+
+                if !matches!(
+                    contents.node_slice(&name)?.to_string().as_str(),
+                    "public" | "private" | "active"
+                ) {
+                    continue;
+                }
+
+                collect_r6_methods(value, contents, tokens)?;
+            }
+        },
+
+        _ => {},
+    }
+
+    Ok(())
+}
+
+/// Functions of `list(name = function() {})`
+fn collect_r6_methods<'tree>(
+    node: Node<'tree>,
+    contents: &Rope,
+    tokens: &mut Vec<Token<'tree>>,
+) -> anyhow::Result<()> {
+    if !node.is_call() {
+        return Ok(());
+    }
+    let Some(arguments) = node.child_by_field_name("arguments") else {
+        return Ok(());
+    };
+
+    let mut cursor = arguments.walk();
+    for argument in arguments.children_by_field_name("argument", &mut cursor) {
+        let (Some(name), Some(value)) = (
+            argument.child_by_field_name("name"),
+            argument.child_by_field_name("value"),
+        ) else {
+            continue;
+        };
+
+        if value.is_function_definition() {
+            push(tokens, name, TokenType::Method, DECLARATION);
+        }
+    }
+
+    Ok(())
+}
+
+fn push<'tree>(
+    tokens: &mut Vec<Token<'tree>>,
+    node: Node<'tree>,
+    token_type: TokenType,
+    modifiers: u32,
+) {
+    tokens.push(Token {
+        node,
+        token_type,
+        modifiers,
+    });
+}
+
+/// Encode tokens relative to each other, as required by the protocol.
+/// Positions and lengths are in UTF-16 code units. Tokens can't span lines,
+/// multiline strings are left to the grammar.
+fn encode(tokens: &[Token], contents: &Rope) -> Vec<SemanticToken> {
+    let mut data = Vec::with_capacity(tokens.len());
+    let mut line = 0;
+    let mut start = 0;
+
+    for token in tokens {
+        let range = token.node.range();
+        if range.start_point.row != range.end_point.row {
+            continue;
+        }
+
+        let token_start = convert_point_to_position(contents, range.start_point);
+        let token_end = convert_point_to_position(contents, range.end_point);
+
+        let delta_line = token_start.line - line;
+        let delta_start = if delta_line == 0 {
+            token_start.character - start
+        } else {
+            token_start.character
+        };
+
+        data.push(SemanticToken {
+            delta_line,
+            delta_start,
+            length: token_end.character - token_start.character,
+            token_type: token.token_type as u32,
+            token_modifiers_bitset: token.modifiers,
+        });
+
+        line = token_start.line;
+        start = token_start.character;
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::SemanticToken;
+
+    use crate::lsp::documents::Document;
+    use crate::lsp::semantic_tokens::semantic_tokens;
+    use crate::lsp::semantic_tokens::TokenType;
+    use crate::lsp::semantic_tokens::DECLARATION;
+
+    /// Decode tokens to `(line, character, length, type, modifiers)`
+    fn tokens(code: &str) -> Vec<(u32, u32, u32, u32, u32)> {
+        let document = Document::new(code, None);
+        let data = semantic_tokens(&document).unwrap().data;
+
+        let mut line = 0;
+        let mut start = 0;
+        data.iter()
+            .map(|token: &SemanticToken| {
+                if token.delta_line > 0 {
+                    start = 0;
+                }
+                line += token.delta_line;
+                start += token.delta_start;
+                (
+                    line,
+                    start,
+                    token.length,
+                    token.token_type,
+                    token.token_modifiers_bitset,
+                )
+            })
+            .collect()
+    }
+
+    const NAMESPACE: u32 = TokenType::Namespace as u32;
+    const FUNCTION: u32 = TokenType::Function as u32;
+    const PARAMETER: u32 = TokenType::Parameter as u32;
+    const METHOD: u32 = TokenType::Method as u32;
+
+    #[test]
+    fn test_semantic_tokens_functions() {
+        assert_eq!(tokens("foo <- function(x, y = x) {\n  x + z\n}"), vec![
+            (0, 0, 3, FUNCTION, DECLARATION),
+            (0, 16, 1, PARAMETER, DECLARATION),
+            (0, 19, 1, PARAMETER, DECLARATION),
+            (0, 23, 1, PARAMETER, 0),
+            (1, 2, 1, PARAMETER, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_semantic_tokens_parameter_scope() {
+        // `x` is only a parameter inside the function, and argument names
+        // and extracted names are never parameters
+        assert_eq!(tokens("f <- function(x) g(x = x$x)\nx"), vec![
+            (0, 0, 1, FUNCTION, DECLARATION),
+            (0, 14, 1, PARAMETER, DECLARATION),
+            (0, 23, 1, PARAMETER, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_semantic_tokens_namespaced_calls() {
+        assert_eq!(tokens("dplyr::filter(df)\nx <- datasets::mtcars"), vec![
+            (0, 0, 5, NAMESPACE, 0),
+            (0, 7, 6, FUNCTION, 0),
+            (1, 5, 8, NAMESPACE, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_semantic_tokens_methods() {
+        assert_eq!(
+            tokens("setGeneric('area', function(shape) standardGeneric('area'))"),
+            vec![
+                (0, 11, 6, METHOD, DECLARATION),
+                (0, 28, 5, PARAMETER, DECLARATION),
+            ]
+        );
+
+        let code = "Foo <- R6::R6Class('Foo', public = list(bar = function() 1, n = 1))";
+        assert_eq!(tokens(code), vec![
+            (0, 7, 2, NAMESPACE, 0),
+            (0, 11, 7, FUNCTION, 0),
+            (0, 40, 3, METHOD, DECLARATION),
+        ]);
+    }
+}
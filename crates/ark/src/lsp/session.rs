@@ -0,0 +1,62 @@
+//
+// session.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use tokio::runtime::Runtime;
+
+use crate::lsp::main_loop::Event;
+use crate::lsp::main_loop::GlobalState;
+use crate::lsp::main_loop::TokioUnboundedSender;
+
+/// Identifies a connection to an LSP client
+pub(crate) type ClientId = u64;
+
+/// The LSP session outlives connections to clients
+///
+/// The session owns the main and auxiliary loops, and through them the
+/// document store and the state of the indexer. It is created on the first
+/// start of the LSP and shared by all connections, so that a client can
+/// reconnect, e.g. after a reload of the frontend, without losing the work
+/// done so far, and so that several editors can be connected at once.
+pub(crate) struct LspSession {
+    /// Channel for communication with the main loop, shared by connections
+    events_tx: TokioUnboundedSender<Event>,
+
+    next_client_id: AtomicU64,
+
+    /// Handle to the main and auxiliary loops. Dropping it cancels the loops
+    /// and drops all owned state.
+    _loops: Mutex<tokio::task::JoinSet<()>>,
+}
+
+impl LspSession {
+    /// Start the main and auxiliary loops on `runtime`
+    pub(crate) fn start(runtime: &Runtime) -> Self {
+        let _guard = runtime.enter();
+
+        let state = GlobalState::new();
+        let events_tx = state.events_tx();
+        let loops = state.start();
+
+        Self {
+            events_tx,
+            next_client_id: AtomicU64::new(1),
+            _loops: Mutex::new(loops),
+        }
+    }
+
+    pub(crate) fn events_tx(&self) -> TokioUnboundedSender<Event> {
+        self.events_tx.clone()
+    }
+
+    pub(crate) fn next_client_id(&self) -> ClientId {
+        self.next_client_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
@@ -316,6 +316,19 @@ pub(crate) fn r_signature_help(context: &DocumentContext) -> anyhow::Result<Opti
     Ok(Some(help))
 }
 
+/// Signature of a function as it would be called, like `fn(x, y = 1)`, with
+/// defaults abbreviated as in signature help
+///
+/// SAFETY: Requires access to the R runtime.
+pub(crate) fn r_signature_label(name: &str, function: SEXP) -> anyhow::Result<String> {
+    let arguments: Vec<String> = r_formals(function)?
+        .into_iter()
+        .map(|argument| argument_label(argument.name, argument.value.sexp))
+        .collect();
+
+    Ok(format!("{name}({})", arguments.join(", ")))
+}
+
 fn is_within_call_parentheses(x: &Point, node: &Node) -> bool {
     if node.node_type() != NodeType::Call {
         // This would be very weird
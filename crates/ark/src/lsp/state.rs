@@ -1,11 +1,13 @@
 use std::collections::HashMap;
-use std::path::Path;
 
 use anyhow::anyhow;
+use tower_lsp::lsp_types::Diagnostic;
 use url::Url;
 
 use crate::lsp::config::LspConfig;
 use crate::lsp::documents::Document;
+use crate::lsp::generated::GitAttributes;
+use crate::lsp::lintr::Lints;
 
 #[derive(Clone, Default, Debug)]
 /// The world state, i.e. all the inputs necessary for analysing or refactoring
@@ -15,6 +17,10 @@ pub(crate) struct WorldState {
     /// Watched documents
     pub(crate) documents: HashMap<Url, Document>,
 
+    /// In-memory documents supplied by the client, see `virtual_documents.rs`.
+    /// Documents open in an editor take precedence.
+    pub(crate) virtual_documents: HashMap<Url, Document>,
+
     /// Watched folders
     pub(crate) workspace: Workspace,
 
@@ -44,7 +50,24 @@ pub(crate) struct WorldState {
     /// Currently installed packages
     pub(crate) installed_packages: Vec<String>,
 
+    /// Options known to the session, used to detect typos in option names
+    pub(crate) known_options: Vec<String>,
+
+    /// Problems found by the last package build, install, or check, by file,
+    /// see `package_problems.rs`
+    pub(crate) package_problems: HashMap<Url, Vec<Diagnostic>>,
+
+    /// Lints of saved documents, see `lintr.rs`
+    pub(crate) lints: HashMap<Url, Lints>,
+
+    /// Generated files marked in the workspace, see `generated.rs`
+    pub(crate) git_attributes: GitAttributes,
+
     pub(crate) config: LspConfig,
+
+    /// Whether the client can expand snippets in completion items, as
+    /// advertised in `initialize`
+    pub(crate) snippet_support: bool,
 }
 
 #[derive(Clone, Default, Debug)]
@@ -56,6 +79,8 @@ impl WorldState {
     pub(crate) fn get_document(&self, uri: &Url) -> anyhow::Result<&Document> {
         if let Some(doc) = self.documents.get(uri) {
             Ok(doc)
+        } else if let Some(doc) = self.virtual_documents.get(uri) {
+            Ok(doc)
         } else {
             Err(anyhow!("Can't find document for URI {uri}"))
         }
@@ -70,40 +95,99 @@ impl WorldState {
     }
 }
 
-pub(crate) fn with_document<T, F>(
-    path: &Path,
-    state: &WorldState,
-    mut callback: F,
-) -> anyhow::Result<T>
+/// Call `callback` with the document at `uri`. Documents open in an editor
+/// and virtual documents are used as is, other documents are read from disk.
+pub(crate) fn with_document<T, F>(uri: &Url, state: &WorldState, callback: F) -> anyhow::Result<T>
 where
-    F: FnMut(&Document) -> anyhow::Result<T>,
+    F: FnOnce(&Document) -> anyhow::Result<T>,
 {
-    let mut fallback = || {
-        let contents = std::fs::read_to_string(path)?;
-        let document = Document::new(contents.as_str(), None);
-        return callback(&document);
-    };
+    if let Ok(document) = state.get_document(uri) {
+        return callback(document);
+    }
 
-    // If we have a cached copy of the document (because we're monitoring it)
-    // then use that; otherwise, try to read the document from the provided
-    // path and use that instead.
-    let Ok(uri) = Url::from_file_path(path) else {
-        log::info!(
-            "couldn't construct uri from {}; reading from disk instead",
-            path.display()
-        );
-        return fallback();
+    let Ok(path) = uri.to_file_path() else {
+        return Err(anyhow!("Can't find document for URI {uri}"));
     };
 
-    let Ok(document) = state.get_document(&uri) else {
-        log::info!("no document for uri {uri}; reading from disk instead");
-        return fallback();
+    log::info!("no document for uri {uri}; reading from disk instead");
+    let contents = std::fs::read_to_string(path)?;
+    let document = Document::new(contents.as_str(), None);
+    callback(&document)
+}
+
+/// In-memory documents that don't live under a workspace folder, e.g.
+/// untitled buffers and virtual documents. These aren't found by walking the
+/// workspace folders.
+pub(crate) fn detached_documents(state: &WorldState) -> Vec<(&Url, &Document)> {
+    let in_workspace = |uri: &Url| {
+        let Ok(path) = uri.to_file_path() else {
+            return false;
+        };
+        state
+            .workspace
+            .folders
+            .iter()
+            .filter_map(|folder| folder.to_file_path().ok())
+            .any(|folder| path.starts_with(folder))
     };
 
-    return callback(document);
+    let virtual_documents = state
+        .virtual_documents
+        .iter()
+        .filter(|(uri, _)| !state.documents.contains_key(uri));
+
+    state
+        .documents
+        .iter()
+        .chain(virtual_documents)
+        .filter(|(uri, _)| !in_workspace(uri))
+        .collect()
 }
 
 pub(crate) fn workspace_uris(state: &WorldState) -> Vec<Url> {
     let uris: Vec<Url> = state.documents.iter().map(|elt| elt.0.clone()).collect();
     uris
 }
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::lsp::documents::Document;
+    use crate::lsp::state::detached_documents;
+    use crate::lsp::state::with_document;
+    use crate::lsp::state::WorldState;
+
+    #[test]
+    fn test_with_virtual_document() {
+        let mut state = WorldState::default();
+
+        let untitled = Url::parse("untitled:Untitled-1").unwrap();
+        let cell = Url::parse("vscode-notebook-cell:/nb.ipynb#cell").unwrap();
+        assert!(with_document(&untitled, &state, |_| Ok(())).is_err());
+
+        state
+            .virtual_documents
+            .insert(untitled.clone(), Document::new("x <- 1", None));
+        state
+            .virtual_documents
+            .insert(cell.clone(), Document::new("y <- 2", None));
+
+        let contents = with_document(&untitled, &state, |doc| Ok(doc.contents.to_string()));
+        assert_eq!(contents.unwrap(), "x <- 1");
+
+        // Documents open in an editor take precedence
+        state
+            .documents
+            .insert(untitled.clone(), Document::new("x <- 3", None));
+        let contents = with_document(&untitled, &state, |doc| Ok(doc.contents.to_string()));
+        assert_eq!(contents.unwrap(), "x <- 3");
+
+        let mut detached: Vec<String> = detached_documents(&state)
+            .into_iter()
+            .map(|(_, doc)| doc.contents.to_string())
+            .collect();
+        detached.sort();
+        assert_eq!(detached, vec!["x <- 3", "y <- 2"]);
+    }
+}
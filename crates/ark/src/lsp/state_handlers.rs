@@ -5,31 +5,48 @@
 //
 //
 
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::Path;
+use std::path::PathBuf;
 
+use amalthea::features;
 use anyhow::anyhow;
 use serde_json::Value;
 use struct_field_names_as_array::FieldNamesAsArray;
+use tower_lsp::lsp_types::CodeActionProviderCapability;
+use tower_lsp::lsp_types::CodeLensOptions;
 use tower_lsp::lsp_types::CompletionOptions;
+use tower_lsp::lsp_types::Diagnostic;
 use tower_lsp::lsp_types::ConfigurationItem;
 use tower_lsp::lsp_types::DidChangeConfigurationParams;
 use tower_lsp::lsp_types::DidChangeTextDocumentParams;
+use tower_lsp::lsp_types::DidChangeWatchedFilesParams;
 use tower_lsp::lsp_types::DidCloseTextDocumentParams;
 use tower_lsp::lsp_types::DidOpenTextDocumentParams;
+use tower_lsp::lsp_types::DidSaveTextDocumentParams;
 use tower_lsp::lsp_types::DocumentOnTypeFormattingOptions;
 use tower_lsp::lsp_types::ExecuteCommandOptions;
+use tower_lsp::lsp_types::FileChangeType;
+use tower_lsp::lsp_types::FoldingRangeProviderCapability;
 use tower_lsp::lsp_types::FormattingOptions;
 use tower_lsp::lsp_types::HoverProviderCapability;
 use tower_lsp::lsp_types::ImplementationProviderCapability;
 use tower_lsp::lsp_types::InitializeParams;
 use tower_lsp::lsp_types::InitializeResult;
+use tower_lsp::lsp_types::MessageType;
 use tower_lsp::lsp_types::OneOf;
+use tower_lsp::lsp_types::RenameOptions;
 use tower_lsp::lsp_types::SelectionRangeProviderCapability;
+use tower_lsp::lsp_types::SemanticTokensFullOptions;
+use tower_lsp::lsp_types::SemanticTokensOptions;
+use tower_lsp::lsp_types::SemanticTokensServerCapabilities;
 use tower_lsp::lsp_types::ServerCapabilities;
 use tower_lsp::lsp_types::ServerInfo;
 use tower_lsp::lsp_types::SignatureHelpOptions;
 use tower_lsp::lsp_types::TextDocumentSyncCapability;
 use tower_lsp::lsp_types::TextDocumentSyncKind;
+use tower_lsp::lsp_types::TypeDefinitionProviderCapability;
 use tower_lsp::lsp_types::WorkDoneProgressOptions;
 use tower_lsp::lsp_types::WorkspaceFoldersServerCapabilities;
 use tower_lsp::lsp_types::WorkspaceServerCapabilities;
@@ -37,18 +54,34 @@ use tracing::Instrument;
 use tree_sitter::Parser;
 use url::Url;
 
+use crate::features::SEMANTIC_TOKENS;
 use crate::lsp;
+use crate::lsp::commands;
 use crate::lsp::config::indent_style_from_lsp;
 use crate::lsp::config::DocumentConfig;
 use crate::lsp::config::VscDiagnosticsConfig;
 use crate::lsp::config::VscDocumentConfig;
 use crate::lsp::diagnostics::DiagnosticsConfig;
+use crate::lsp::documents::DiskChange;
 use crate::lsp::documents::Document;
 use crate::lsp::encoding::get_position_encoding_kind;
+use crate::lsp::generated;
+use crate::lsp::generated::GitAttributes;
 use crate::lsp::indexer;
+use crate::lsp::lintr;
+use crate::lsp::lintr::Lints;
+use crate::lsp::main_loop::ClientCaps;
 use crate::lsp::main_loop::LspState;
+use crate::lsp::progress;
+use crate::lsp::project_config;
+use crate::lsp::project_config::ProjectConfig;
+use crate::lsp::semantic_tokens::semantic_tokens_legend;
 use crate::lsp::state::workspace_uris;
 use crate::lsp::state::WorldState;
+use crate::lsp::virtual_documents::VirtualDocumentDidChangeParams;
+use crate::lsp::virtual_documents::VirtualDocumentDidCloseParams;
+use crate::lsp::virtual_documents::VirtualDocumentDidOpenParams;
+use crate::user_config;
 
 // Handlers that mutate the world state
 
@@ -65,6 +98,10 @@ pub struct ConsoleInputs {
     /// Packages currently installed in the library path. TODO: Should send
     /// library paths instead and inspect and cache package information in the LSP.
     pub installed_packages: Vec<String>,
+
+    /// Names of the options known to the session: documented options of base R
+    /// and loaded packages, and options set by the user
+    pub known_options: Vec<String>,
 }
 
 // Handlers taking exclusive references to global state
@@ -72,7 +109,7 @@ pub struct ConsoleInputs {
 #[tracing::instrument(level = "info", skip_all)]
 pub(crate) fn initialize(
     params: InitializeParams,
-    lsp_state: &mut LspState,
+    needs_registration: &mut ClientCaps,
     state: &mut WorldState,
 ) -> anyhow::Result<InitializeResult> {
     // Take note of supported capabilities so we can register them in the
@@ -80,28 +117,45 @@ pub(crate) fn initialize(
     if let Some(ws_caps) = params.capabilities.workspace {
         if matches!(ws_caps.did_change_configuration, Some(caps) if matches!(caps.dynamic_registration, Some(true)))
         {
-            lsp_state.needs_registration.did_change_configuration = true;
+            needs_registration.did_change_configuration = true;
+        }
+        if matches!(ws_caps.did_change_watched_files, Some(caps) if matches!(caps.dynamic_registration, Some(true)))
+        {
+            needs_registration.did_change_watched_files = true;
         }
     }
 
-    // Initialize the workspace folders
-    let mut folders: Vec<String> = Vec::new();
+    // Long-running tasks such as indexing report their progress if the
+    // client supports it
+    let work_done_progress = params
+        .capabilities
+        .window
+        .and_then(|caps| caps.work_done_progress)
+        .unwrap_or(false);
+    progress::set_supported(work_done_progress);
+
+    // Snippet completions are only provided to clients that can expand them
+    state.snippet_support = params
+        .capabilities
+        .text_document
+        .as_ref()
+        .and_then(|caps| caps.completion.as_ref())
+        .and_then(|caps| caps.completion_item.as_ref())
+        .and_then(|caps| caps.snippet_support)
+        .unwrap_or(false);
+
+    // Initialize the workspace folders. These may already be known when a
+    // client reconnects or when several clients share the session.
     if let Some(workspace_folders) = params.workspace_folders {
         for folder in workspace_folders.iter() {
-            state.workspace.folders.push(folder.uri.clone());
-            if let Ok(path) = folder.uri.to_file_path() {
-                if let Some(path) = path.to_str() {
-                    folders.push(path.to_string());
-                }
+            if !state.workspace.folders.contains(&folder.uri) {
+                state.workspace.folders.push(folder.uri.clone());
             }
         }
     }
 
-    // Start first round of indexing
-    lsp::spawn_blocking(|| {
-        indexer::start(folders);
-        Ok(None)
-    });
+    set_project_config(ProjectConfig::from_workspace(&state.workspace), state);
+    state.git_attributes = GitAttributes::from_workspace(&state.workspace);
 
     Ok(InitializeResult {
         server_info: Some(ServerInfo {
@@ -114,6 +168,19 @@ pub(crate) fn initialize(
                 TextDocumentSyncKind::INCREMENTAL,
             )),
             selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+            folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+            semantic_tokens_provider: features::is_enabled(&SEMANTIC_TOKENS).then(|| {
+                SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+                    legend: semantic_tokens_legend(),
+                    range: Some(false),
+                    full: Some(SemanticTokensFullOptions::Bool(true)),
+                    work_done_progress_options: Default::default(),
+                })
+            }),
+            code_lens_provider: Some(CodeLensOptions {
+                resolve_provider: Some(false),
+            }),
+            code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
             hover_provider: Some(HoverProviderCapability::from(true)),
             completion_provider: Some(CompletionOptions {
                 resolve_provider: Some(true),
@@ -130,13 +197,19 @@ pub(crate) fn initialize(
                 },
             }),
             definition_provider: Some(OneOf::Left(true)),
-            type_definition_provider: None,
+            type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
             implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
             references_provider: Some(OneOf::Left(true)),
+            rename_provider: Some(OneOf::Right(RenameOptions {
+                prepare_provider: Some(true),
+                work_done_progress_options: WorkDoneProgressOptions {
+                    work_done_progress: None,
+                },
+            })),
             document_symbol_provider: Some(OneOf::Left(true)),
             workspace_symbol_provider: Some(OneOf::Left(true)),
             execute_command_provider: Some(ExecuteCommandOptions {
-                commands: vec![],
+                commands: commands::command_ids(),
                 work_done_progress_options: Default::default(),
             }),
             workspace: Some(WorkspaceServerCapabilities {
@@ -148,13 +221,50 @@ pub(crate) fn initialize(
             }),
             document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
                 first_trigger_character: String::from("\n"),
-                more_trigger_character: None,
+                more_trigger_character: Some(vec![String::from("}"), String::from(")")]),
             }),
+            document_formatting_provider: Some(OneOf::Left(true)),
+            document_range_formatting_provider: Some(OneOf::Left(true)),
             ..ServerCapabilities::default()
         },
     })
 }
 
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn initialized(state: &WorldState) -> anyhow::Result<()> {
+    // Start first round of indexing. We wait until the client is initialized
+    // because it can't receive progress notifications before that.
+    spawn_indexer(state);
+    Ok(())
+}
+
+fn spawn_indexer(state: &WorldState) {
+    let roots = workspace_paths(state);
+    let folders: Vec<String> = roots
+        .iter()
+        .filter_map(|path| path.to_str().map(String::from))
+        .collect();
+    let project = state.config.project.clone();
+
+    lsp::spawn_analysis(Some(String::from("indexer")), move |cancellation| {
+        indexer::start(
+            folders,
+            |path| project.is_excluded(path, &roots),
+            cancellation,
+        );
+        Ok(None)
+    });
+}
+
+fn workspace_paths(state: &WorldState) -> Vec<PathBuf> {
+    state
+        .workspace
+        .folders
+        .iter()
+        .filter_map(|uri| uri.to_file_path().ok())
+        .collect()
+}
+
 #[tracing::instrument(level = "info", skip_all)]
 pub(crate) fn did_open(
     params: DidOpenTextDocumentParams,
@@ -170,7 +280,15 @@ pub(crate) fn did_open(
         .set_language(&tree_sitter_r::LANGUAGE.into())
         .unwrap();
 
-    let document = Document::new_with_parser(contents, &mut parser, Some(version));
+    let mut document = Document::new_with_parser(contents, &mut parser, Some(version));
+    state.config.project.apply_formatting(&mut document.config);
+
+    if let Ok(path) = uri.to_file_path() {
+        document.sync_disk(&path);
+        if let Err(err) = indexer::set_open(&path, true) {
+            lsp::log_error!("{err:?}");
+        }
+    }
 
     lsp_state.parsers.insert(uri.clone(), parser);
     state.documents.insert(uri.clone(), document.clone());
@@ -198,7 +316,18 @@ pub(crate) fn did_change(
         .get_mut(uri)
         .ok_or(anyhow!("No parser for {uri}"))?;
 
-    doc.on_did_change(&mut parser, &params);
+    doc.on_did_change(&mut parser, &params)?;
+
+    update_index(uri, doc);
+    lsp::spawn_diagnostics_refresh(uri.clone(), doc.clone(), state.clone());
+
+    Ok(())
+}
+
+/// Refresh a document whose contents were replaced by those of another
+/// client, see `ClientDocuments::close()`
+pub(crate) fn did_resync(uri: &Url, state: &WorldState) -> anyhow::Result<()> {
+    let doc = state.get_document(uri)?;
 
     update_index(uri, doc);
     lsp::spawn_diagnostics_refresh(uri.clone(), doc.clone(), state.clone());
@@ -214,8 +343,11 @@ pub(crate) fn did_close(
 ) -> anyhow::Result<()> {
     let uri = params.text_document.uri;
 
-    // Publish empty set of diagnostics to clear them
-    lsp::publish_diagnostics(uri.clone(), Vec::new(), None);
+    // Clear the diagnostics of the document. Problems found by the last
+    // package check concern the file and remain.
+    let problems = state.package_problems.get(&uri).cloned().unwrap_or_default();
+    lsp::publish_diagnostics(uri.clone(), problems, None);
+    state.lints.remove(&uri);
 
     state
         .documents
@@ -227,11 +359,205 @@ pub(crate) fn did_close(
         .remove(&uri)
         .ok_or(anyhow!("Failed to remove parser for URI: {uri}"))?;
 
+    // Unsaved edits are discarded on close, so the file on disk is the source
+    // of truth again
+    if let Ok(path) = uri.to_file_path() {
+        if let Err(err) = indexer::set_open(&path, false) {
+            lsp::log_error!("{err:?}");
+        }
+        spawn_reindex(path);
+    }
+
     lsp::log_info!("did_close(): closed document with URI: '{uri}'.");
 
     Ok(())
 }
 
+#[tracing::instrument(level = "info", skip_all)]
+pub(crate) fn did_open_virtual_document(
+    params: VirtualDocumentDidOpenParams,
+    state: &mut WorldState,
+) {
+    let document = Document::new(params.text.as_str(), None);
+    state.virtual_documents.insert(params.uri, document);
+}
+
+pub(crate) fn did_change_virtual_document(
+    params: VirtualDocumentDidChangeParams,
+    state: &mut WorldState,
+) -> anyhow::Result<()> {
+    let uri = params.uri;
+    let document = state
+        .virtual_documents
+        .get_mut(&uri)
+        .ok_or(anyhow!("No virtual document for URI: {uri}"))?;
+
+    // Virtual documents are sent in full, there is no incremental update
+    *document = Document::new(params.text.as_str(), None);
+
+    Ok(())
+}
+
+pub(crate) fn did_close_virtual_document(
+    params: VirtualDocumentDidCloseParams,
+    state: &mut WorldState,
+) -> anyhow::Result<()> {
+    let uri = params.uri;
+
+    state
+        .virtual_documents
+        .remove(&uri)
+        .ok_or(anyhow!("Failed to remove virtual document for URI: {uri}"))?;
+
+    Ok(())
+}
+
+pub(crate) fn did_save(
+    params: DidSaveTextDocumentParams,
+    state: &mut WorldState,
+) -> anyhow::Result<()> {
+    let uri = &params.text_document.uri;
+    let doc = state.get_document_mut(uri)?;
+
+    // The editor and the disk are in sync again
+    let Ok(path) = uri.to_file_path() else {
+        return Ok(());
+    };
+    doc.sync_disk(&path);
+
+    // lintr reads the file, which now matches the document
+    let version = doc.version;
+    let diagnostics = &state.config.diagnostics;
+    if diagnostics.enable && diagnostics.lintr {
+        lintr::spawn_lintr(uri.clone(), path, version);
+    }
+
+    Ok(())
+}
+
+/// Handle files changed outside of the editor, e.g. by a build step or a
+/// `git checkout`. Closed files are re-indexed from disk. Open documents keep
+/// their editor contents, which all providers use, but we warn the user when
+/// the file now differs from them.
+pub(crate) async fn did_change_watched_files(
+    params: DidChangeWatchedFilesParams,
+    client: &tower_lsp::Client,
+    state: &mut WorldState,
+) -> anyhow::Result<()> {
+    let mut project_changed = false;
+    let mut attributes_changed = false;
+
+    for event in params.changes {
+        let Ok(path) = event.uri.to_file_path() else {
+            continue;
+        };
+
+        if path
+            .file_name()
+            .is_some_and(|name| name == project_config::FILE_NAME)
+        {
+            project_changed = true;
+            continue;
+        }
+
+        if path
+            .file_name()
+            .is_some_and(|name| name == generated::GITATTRIBUTES_FILE_NAME)
+        {
+            attributes_changed = true;
+            continue;
+        }
+
+        let Some(doc) = state.documents.get_mut(&event.uri) else {
+            if event.typ == FileChangeType::DELETED {
+                if let Err(err) = indexer::remove(&path) {
+                    lsp::log_error!("{err:?}");
+                }
+            } else if !state
+                .config
+                .project
+                .is_excluded(&path, &workspace_paths(state))
+            {
+                spawn_reindex(path);
+            }
+            continue;
+        };
+
+        let message = match doc.check_disk(&path) {
+            DiskChange::Unchanged | DiskChange::Synced => continue,
+            DiskChange::Diverged => format!(
+                "'{}' was changed on disk and differs from the editor contents. The editor contents are used for diagnostics and navigation until the file is saved or reverted.",
+                path.display()
+            ),
+            DiskChange::Deleted => format!(
+                "'{}' was deleted on disk. The editor contents are used for diagnostics and navigation until the file is saved or closed.",
+                path.display()
+            ),
+        };
+
+        lsp::log_warn!("{message}");
+        client.show_message(MessageType::WARNING, message).await;
+    }
+
+    if project_changed {
+        did_change_project_config(client, state).await?;
+    }
+
+    if attributes_changed {
+        let attributes = GitAttributes::from_workspace(&state.workspace);
+        if attributes != state.git_attributes {
+            state.git_attributes = attributes;
+            lsp::spawn_diagnostics_refresh_all(state.clone());
+        }
+    }
+
+    Ok(())
+}
+
+/// Reload the `.ark.toml` file of the workspace after it changed, see
+/// `project_config.rs`
+async fn did_change_project_config(
+    client: &tower_lsp::Client,
+    state: &mut WorldState,
+) -> anyhow::Result<()> {
+    let project = ProjectConfig::from_workspace(&state.workspace);
+    if project == state.config.project {
+        return Ok(());
+    }
+    lsp::log_info!("Loaded project settings: {project:?}");
+
+    let reindex = project.indexing != state.config.project.indexing;
+    set_project_config(project, state);
+
+    // Pull the editor settings of the documents again to merge them with the
+    // new formatting settings
+    update_config(workspace_uris(state), client, state)
+        .instrument(tracing::info_span!("did_change_project_config"))
+        .await?;
+
+    if reindex {
+        spawn_indexer(state);
+    }
+
+    Ok(())
+}
+
+fn set_project_config(project: ProjectConfig, state: &mut WorldState) {
+    user_config::set_project_evaluation(project.evaluation.clone());
+
+    if state.config.set_project(project) {
+        lsp::spawn_diagnostics_refresh_all(state.clone());
+    }
+}
+
+fn spawn_reindex(path: std::path::PathBuf) {
+    let key = format!("index:{}", path.display());
+    lsp::spawn_analysis(Some(key), move |_| {
+        indexer::reindex(&path)?;
+        Ok(None)
+    });
+}
+
 pub(crate) async fn did_change_configuration(
     _params: DidChangeConfigurationParams,
     client: &tower_lsp::Client,
@@ -252,7 +578,7 @@ pub(crate) fn did_change_formatting_options(
     opts: &FormattingOptions,
     state: &mut WorldState,
 ) {
-    let Ok(doc) = state.get_document_mut(uri) else {
+    let Some(doc) = state.documents.get_mut(uri) else {
         return;
     };
 
@@ -270,6 +596,9 @@ pub(crate) fn did_change_formatting_options(
     doc.config.indent.tab_width = opts.tab_size as usize;
     doc.config.indent.indent_style = indent_style_from_lsp(opts.insert_spaces);
 
+    // Project settings take precedence over the editor's
+    state.config.project.apply_formatting(&mut doc.config);
+
     // TODO:
     // `trim_trailing_whitespace`
     // `trim_final_newlines`
@@ -341,10 +670,7 @@ async fn update_config(
     let config: VscDiagnosticsConfig = serde_json::from_value(serde_json::Value::Object(map))?;
     let config: DiagnosticsConfig = config.into();
 
-    let changed = state.config.diagnostics != config;
-    state.config.diagnostics = config;
-
-    if changed {
+    if state.config.set_client_diagnostics(config) {
         lsp::spawn_diagnostics_refresh_all(state.clone());
     }
 
@@ -362,8 +688,10 @@ async fn update_config(
         // Deserialise the VS Code configuration
         let config: VscDocumentConfig = serde_json::from_value(serde_json::Value::Object(map))?;
 
-        // Now convert the VS Code specific type into our own type
-        let config: DocumentConfig = config.into();
+        // Now convert the VS Code specific type into our own type, with the
+        // settings of the project on top
+        let mut config: DocumentConfig = config.into();
+        state.config.project.apply_formatting(&mut config);
 
         // Finally, update the document's config
         state.get_document_mut(&uri)?.config = config;
@@ -379,6 +707,7 @@ pub(crate) fn did_change_console_inputs(
 ) -> anyhow::Result<()> {
     state.console_scopes = inputs.console_scopes;
     state.installed_packages = inputs.installed_packages;
+    state.known_options = inputs.known_options;
 
     // We currently rely on global console scopes for diagnostics, in particular
     // during package development in conjunction with `devtools::load_all()`.
@@ -389,6 +718,51 @@ pub(crate) fn did_change_console_inputs(
     Ok(())
 }
 
+/// Replace the problems of the last package check. The diagnostics of files
+/// with problems in either check are refreshed, from the open document if any.
+pub(crate) fn did_publish_lints(
+    uri: Url,
+    lints: Lints,
+    state: &mut WorldState,
+) -> anyhow::Result<()> {
+    // The document might have been closed while it was linted
+    let Some(document) = state.documents.get(&uri) else {
+        return Ok(());
+    };
+    let document = document.clone();
+
+    state.lints.insert(uri.clone(), lints);
+    lsp::spawn_diagnostics_refresh(uri, document, state.clone());
+
+    Ok(())
+}
+
+pub(crate) fn did_publish_package_problems(
+    problems: HashMap<Url, Vec<Diagnostic>>,
+    state: &mut WorldState,
+) -> anyhow::Result<()> {
+    let previous = std::mem::replace(&mut state.package_problems, problems);
+
+    let uris: HashSet<Url> = previous
+        .into_keys()
+        .chain(state.package_problems.keys().cloned())
+        .collect();
+
+    for uri in uris {
+        match state.documents.get(&uri) {
+            Some(document) => {
+                lsp::spawn_diagnostics_refresh(uri.clone(), document.clone(), state.clone())
+            },
+            None => {
+                let problems = state.package_problems.get(&uri).cloned().unwrap_or_default();
+                lsp::publish_diagnostics(uri, problems, None);
+            },
+        }
+    }
+
+    Ok(())
+}
+
 // FIXME: The initial indexer is currently racing against our state notification
 // handlers. The indexer is synchronised through a mutex but we might end up in
 // a weird state. Eventually the index should be moved to WorldState and created
@@ -82,6 +82,34 @@ pub fn symbols(params: &WorkspaceSymbolParams) -> anyhow::Result<Vec<SymbolInfor
                 });
             },
 
+            IndexEntryData::Class { name, .. } => {
+                info.push(SymbolInformation {
+                    name: name.to_string(),
+                    kind: SymbolKind::CLASS,
+                    location: Location {
+                        uri: Url::from_file_path(path).unwrap(),
+                        range: entry.range,
+                    },
+                    tags: None,
+                    deprecated: None,
+                    container_name: None,
+                });
+            },
+
+            IndexEntryData::Generic { name } => {
+                info.push(SymbolInformation {
+                    name: name.to_string(),
+                    kind: SymbolKind::FUNCTION,
+                    location: Location {
+                        uri: Url::from_file_path(path).unwrap(),
+                        range: entry.range,
+                    },
+                    tags: None,
+                    deprecated: None,
+                    container_name: None,
+                });
+            },
+
             IndexEntryData::Section { level: _, title } => {
                 info.push(SymbolInformation {
                     name: title.to_string(),
@@ -106,7 +134,7 @@ pub(crate) fn document_symbols(
     params: &DocumentSymbolParams,
 ) -> anyhow::Result<Vec<DocumentSymbol>> {
     let uri = &params.text_document.uri;
-    let document = state.documents.get(uri).into_result()?;
+    let document = state.get_document(uri)?;
     let ast = &document.ast;
     let contents = &document.contents;
 
@@ -137,9 +165,15 @@ fn index_node(
         NodeType::BinaryOperator(BinaryOperatorType::EqualsAssignment) => {
             index_assignment(&node, store, contents)?
         },
-        // Nothing to index. FIXME: We should handle argument lists, e.g. to
-        // index inside functions passed as arguments, or inside `test_that()`
-        // blocks.
+        // Index inside arguments, e.g. methods of R6 classes, functions passed
+        // to `lapply()`, or `test_that()` blocks
+        NodeType::Call => index_call(&node, store, contents)?,
+        // Index inside anonymous functions
+        NodeType::FunctionDefinition => match node.child_by_field_name("body") {
+            Some(body) => index_node(&body, store, contents)?,
+            None => store,
+        },
+        // Nothing to index
         _ => store,
     })
 }
@@ -278,7 +312,7 @@ fn index_assignment(
     let function = lhs.is_identifier_or_string() && rhs.is_function_definition();
 
     if function {
-        return index_assignment_with_function(node, store, contents);
+        return index_function(&lhs, &rhs, store, contents);
     }
 
     // otherwise, just index as generic object
@@ -286,22 +320,63 @@ fn index_assignment(
 
     let start = convert_point_to_position(contents, lhs.start_position());
     let end = convert_point_to_position(contents, lhs.end_position());
+    let selection_range = Range { start, end };
+
+    // The object may contain functions, e.g. an R6 class or a list of
+    // functions. These are nested under the object, whose range then needs to
+    // include them.
+    let children = index_node(&rhs, vec![], contents)?;
+
+    let range = if children.is_empty() {
+        selection_range
+    } else {
+        Range {
+            start,
+            end: convert_point_to_position(contents, rhs.end_position()),
+        }
+    };
 
-    let symbol = new_symbol(name, SymbolKind::VARIABLE, Range { start, end });
+    let mut symbol = new_symbol_node(name, SymbolKind::VARIABLE, range, children);
+    symbol.selection_range = selection_range;
     store.push(symbol);
 
     Ok(store)
 }
 
-fn index_assignment_with_function(
+fn index_call(
     node: &Node,
     mut store: Vec<DocumentSymbol>,
     contents: &Rope,
 ) -> anyhow::Result<Vec<DocumentSymbol>> {
-    // check for lhs, rhs
-    let lhs = node.child_by_field_name("lhs").into_result()?;
-    let rhs = node.child_by_field_name("rhs").into_result()?;
+    let Some(arguments) = node.child_by_field_name("arguments") else {
+        return Ok(store);
+    };
 
+    let mut cursor = arguments.walk();
+    for argument in arguments.children_by_field_name("argument", &mut cursor) {
+        let Some(value) = argument.child_by_field_name("value") else {
+            continue;
+        };
+
+        store = match argument.child_by_field_name("name") {
+            // Named functions, e.g. `list(method = function() {})`
+            Some(name) if value.is_function_definition() => {
+                index_function(&name, &value, store, contents)?
+            },
+            _ => index_node(&value, store, contents)?,
+        };
+    }
+
+    Ok(store)
+}
+
+// Index a function bound to `lhs`, by assignment or as a named argument
+fn index_function(
+    lhs: &Node,
+    rhs: &Node,
+    mut store: Vec<DocumentSymbol>,
+    contents: &Rope,
+) -> anyhow::Result<Vec<DocumentSymbol>> {
     // start extracting the argument names
     let mut arguments: Vec<String> = Vec::new();
     let parameters = rhs.child_by_field_name("parameters").into_result()?;
@@ -313,7 +388,7 @@ fn index_assignment_with_function(
         arguments.push(name);
     }
 
-    let name = contents.node_slice(&lhs)?.to_string();
+    let name = contents.node_slice(lhs)?.to_string();
     let detail = format!("function({})", arguments.join(", "));
 
     let range = Range {
@@ -335,7 +410,7 @@ fn index_assignment_with_function(
 }
 
 // Function to parse a comment and return the section level and title
-fn parse_comment_as_section(comment: &str) -> Option<(usize, String)> {
+pub(crate) fn parse_comment_as_section(comment: &str) -> Option<(usize, String)> {
     // Match lines starting with one or more '#' followed by some non-empty content and must end with 4 or more '-', '#', or `=`
     // Ensure that there's actual content between the start and the trailing symbols.
     if let Some(caps) = indexer::RE_COMMENT_SECTION.captures(comment) {
@@ -498,4 +573,72 @@ foo <- function() {
 
         assert_eq!(test_symbol("{ foo <- 1 }"), vec![foo]);
     }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range {
+            start: Position {
+                line: start.0,
+                character: start.1,
+            },
+            end: Position {
+                line: end.0,
+                character: end.1,
+            },
+        }
+    }
+
+    #[test]
+    fn test_symbol_call_arguments_function() {
+        let code =
+            "Foo <- R6Class('Foo', public = list(\n  bar = function(x) {\n    baz <- 1\n  }\n))";
+
+        let baz = new_symbol(
+            String::from("baz"),
+            SymbolKind::VARIABLE,
+            range((2, 4), (2, 7)),
+        );
+
+        let mut bar = new_symbol_node(
+            String::from("bar"),
+            SymbolKind::FUNCTION,
+            range((1, 2), (3, 3)),
+            vec![baz],
+        );
+        bar.detail = Some(String::from("function(x)"));
+
+        // The object spans the functions it contains
+        let mut foo = new_symbol_node(
+            String::from("Foo"),
+            SymbolKind::VARIABLE,
+            range((0, 0), (4, 2)),
+            vec![bar],
+        );
+        foo.selection_range = range((0, 0), (0, 3));
+
+        assert_eq!(test_symbol(code), vec![foo]);
+    }
+
+    #[test]
+    fn test_symbol_call_arguments_blocks() {
+        // Inside blocks passed as arguments
+        let code = "test_that('foo', {\n  foo <- 1\n})";
+        let foo = new_symbol(
+            String::from("foo"),
+            SymbolKind::VARIABLE,
+            range((1, 2), (1, 5)),
+        );
+        assert_eq!(test_symbol(code), vec![foo]);
+
+        // Inside anonymous functions
+        let code = "lapply(x, function(y) {\n  foo <- 1\n})";
+        let foo = new_symbol(
+            String::from("foo"),
+            SymbolKind::VARIABLE,
+            range((1, 2), (1, 5)),
+        );
+        assert_eq!(test_symbol(code), vec![foo]);
+
+        // Unnamed functions aren't symbols, other arguments are skipped
+        assert_eq!(test_symbol("list(function() 1, a = 2)"), vec![]);
+    }
 }
@@ -9,17 +9,23 @@ use tree_sitter::Node;
 use tree_sitter::Point;
 use tree_sitter::TreeCursor;
 
+// The walks are iterative so that deeply nested trees, e.g. machine generated
+// code with thousands of nested calls, can't overflow the stack. `depth`
+// tracks how far the cursor is below the node the walk started from, which
+// it never leaves.
+
 fn _recurse_impl<Callback: FnMut(Node) -> bool>(this: &mut TreeCursor, callback: &mut Callback) {
-    if !callback(this.node()) {
-        return;
-    }
+    let mut depth = 0;
 
-    if this.goto_first_child() {
-        _recurse_impl(this, callback);
-        while this.goto_next_sibling() {
-            _recurse_impl(this, callback);
+    loop {
+        if callback(this.node()) && this.goto_first_child() {
+            depth += 1;
+            continue;
+        }
+
+        if !_goto_next_impl(this, &mut depth) {
+            return;
         }
-        this.goto_parent();
     }
 }
 
@@ -27,25 +33,38 @@ fn _find_impl<Callback: FnMut(Node) -> bool>(
     this: &mut TreeCursor,
     callback: &mut Callback,
 ) -> bool {
-    if !callback(this.node()) {
-        return false;
-    }
+    let mut depth = 0;
 
-    if this.goto_first_child() {
-        if !_find_impl(this, callback) {
+    loop {
+        // Leave the cursor on the node
+        if !callback(this.node()) {
             return false;
         }
 
-        while this.goto_next_sibling() {
-            if !_find_impl(this, callback) {
-                return false;
-            }
+        if this.goto_first_child() {
+            depth += 1;
+            continue;
         }
 
+        if !_goto_next_impl(this, &mut depth) {
+            return true;
+        }
+    }
+}
+
+/// Move to the next sibling, or to the next sibling of the closest ancestor
+/// that has one. Returns `false`, with the cursor back on the starting node,
+/// once the walk is complete.
+fn _goto_next_impl(this: &mut TreeCursor, depth: &mut usize) -> bool {
+    while *depth > 0 {
+        if this.goto_next_sibling() {
+            return true;
+        }
         this.goto_parent();
+        *depth -= 1;
     }
 
-    return true;
+    false
 }
 
 // Extension trait for the TreeSitter cursor object.
@@ -12,32 +12,44 @@ use tree_sitter::TreeCursor;
 
 use crate::lsp::traits::point::PointExt;
 
-fn _dump_impl(cursor: &mut TreeCursor, source: &str, indent: &str, output: &mut String) {
-    let node = cursor.node();
-
-    if node.start_position().row == node.end_position().row {
-        // write line
-        output.push_str(
-            format!(
-                "{} - {} - {} ({} -- {})\n",
-                indent,
-                node.utf8_text(source.as_bytes()).unwrap(),
-                node.kind(),
-                node.start_position(),
-                node.end_position(),
-            )
-            .as_str(),
-        );
-    }
+fn _dump_impl(cursor: &mut TreeCursor, source: &str, output: &mut String) {
+    let mut depth = 0;
+
+    loop {
+        let node = cursor.node();
+
+        if node.start_position().row == node.end_position().row {
+            // write line
+            output.push_str(
+                format!(
+                    "{} - {} - {} ({} -- {})\n",
+                    "  ".repeat(depth),
+                    node.utf8_text(source.as_bytes()).unwrap(),
+                    node.kind(),
+                    node.start_position(),
+                    node.end_position(),
+                )
+                .as_str(),
+            );
+        }
 
-    if cursor.goto_first_child() {
-        let indent = format!("  {}", indent);
-        _dump_impl(cursor, source, indent.as_str(), output);
-        while cursor.goto_next_sibling() {
-            _dump_impl(cursor, source, indent.as_str(), output);
+        if cursor.goto_first_child() {
+            depth += 1;
+            continue;
         }
 
-        cursor.goto_parent();
+        // Iterative rather than recursive so that deep trees can't overflow
+        // the stack
+        loop {
+            if depth == 0 {
+                return;
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+            cursor.goto_parent();
+            depth -= 1;
+        }
     }
 }
 
@@ -95,7 +107,7 @@ pub trait NodeExt: Sized {
 impl<'tree> NodeExt for Node<'tree> {
     fn dump(&self, source: &str) -> String {
         let mut output = "\n".to_string();
-        _dump_impl(&mut self.walk(), source, "", &mut output);
+        _dump_impl(&mut self.walk(), source, &mut output);
         return output;
     }
 
@@ -208,21 +220,27 @@ impl<'tree> NodeExt for Node<'tree> {
     }
 }
 
-/// First, recurse through children to find the smallest
+/// First, descend through children to find the smallest
 /// node that contains the requested point.
 fn _find_smallest_container<'a>(node: &Node<'a>, point: Point) -> Option<Node<'a>> {
-    let mut cursor = node.walk();
-    let children = node.children(&mut cursor);
-
-    for child in children {
-        if _range_contains_point(child.range(), point) {
-            return _find_smallest_container(&child, point);
+    let mut node = *node;
+
+    // A loop rather than recursion so that deep trees can't overflow the stack
+    loop {
+        let mut cursor = node.walk();
+        let child = node
+            .children(&mut cursor)
+            .find(|child| _range_contains_point(child.range(), point));
+
+        match child {
+            Some(child) => node = child,
+            None => break,
         }
     }
 
     // No child contained the `point`, revert back to parent
     if _range_contains_point(node.range(), point) {
-        Some(*node)
+        Some(node)
     } else {
         None
     }
@@ -237,30 +255,37 @@ fn _range_contains_point(range: Range, point: Point) -> bool {
     )
 }
 
-/// Next, recurse through the children of this node
+/// Next, descend through the children of this node
 /// (if any) to find the closest child.
 fn _find_closest_child<'a>(node: &Node<'a>, point: Point) -> Option<Node<'a>> {
-    let mut cursor = node.walk();
-    let children = node.children(&mut cursor);
-
-    // Node iterators don't implement `rev()`, presumably for performance, but
-    // this is the cleanest way to implement this so we collect into a vector
-    // first.
-    let children: Vec<Node> = children.collect();
-
-    // Loop backwards through children. First time the `start` is before the
-    // `point` corresponds to the last child this is `true` for, which we then
-    // recurse into.
-    for child in children.into_iter().rev() {
-        if child.range().start_point.is_before_or_equal(point) {
-            return _find_closest_child(&child, point);
+    let mut node = *node;
+
+    loop {
+        let mut cursor = node.walk();
+
+        // Node iterators don't implement `rev()`, presumably for performance, but
+        // this is the cleanest way to implement this so we collect into a vector
+        // first.
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+
+        // Loop backwards through children. First time the `start` is before the
+        // `point` corresponds to the last child this is `true` for, which we then
+        // descend into.
+        let child = children
+            .into_iter()
+            .rev()
+            .find(|child| child.range().start_point.is_before_or_equal(point));
+
+        match child {
+            Some(child) => node = child,
+            None => break,
         }
     }
 
     // No children start before the `point`, revert back to parent
     // (probably rare)
     if node.range().start_point.is_before_or_equal(point) {
-        Some(*node)
+        Some(node)
     } else {
         None
     }
@@ -306,4 +331,27 @@ fn <- function(x, arg) {
         assert_eq!(node.start_position(), Point::new(2, 20));
         assert_eq!(node.end_position(), Point::new(2, 21))
     }
+
+    #[test]
+    fn test_deeply_nested_tree() {
+        let n = 5000;
+        let text = format!("{}x{}", "f(".repeat(n), ")".repeat(n));
+
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_r::LANGUAGE.into())
+            .expect("failed to create parser");
+        let tree = parser.parse(&text, None).unwrap();
+        let root = tree.root_node();
+
+        let point = Point::new(0, 2 * n);
+        let node = root.find_smallest_spanning_node(point).unwrap();
+        assert_eq!(node.kind(), "identifier");
+        assert_eq!(node.start_position(), point);
+
+        let node = root.find_closest_node_to_point(point).unwrap();
+        assert_eq!(node.start_position(), point);
+
+        assert!(root.dump(&text).contains(" - x - identifier"));
+    }
 }
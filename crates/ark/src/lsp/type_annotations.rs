@@ -0,0 +1,350 @@
+//
+// type_annotations.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Type annotations are comments telling the static analysis what an object
+// is when it can't be evaluated, e.g. in a script that hasn't been run yet:
+//
+// ```r
+// # @type scores: data.frame{id, name, score}
+// scores <- read.csv(path)
+// scores$ # Completes `id`, `name`, and `score`
+// ```
+//
+// An annotation takes a whole comment and follows this grammar, with
+// optional whitespace between tokens:
+//
+// ```
+// annotation := "#" ["'"] "@type" name ":" type
+// type       := class ["{" [fields] "}"]
+// fields     := name ("," name)*
+// name       := identifier | "`" text "`"
+// class      := identifier
+// ```
+//
+// The class is an R class such as `data.frame`, or `any`. An annotation
+// applies to the code that follows it, until the next annotation of the same
+// name. Its fields are completed after `$` and `@` when the object doesn't
+// exist in the session, and hovering the object shows its type.
+//
+// Annotations in the comment block right above a function definition that
+// name one of its parameters, e.g. `# @type n: integer` above
+// `f <- function(x, n)`, also type the arguments of the calls to that
+// function. Diagnostics compare them with the arguments whose type is known
+// statically, i.e. literals and annotated objects.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use ropey::Rope;
+use tree_sitter::Node;
+use tree_sitter::Point;
+
+use crate::lsp::documents::Document;
+use crate::lsp::traits::cursor::TreeCursorExt;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+
+static RE_TYPE_ANNOTATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^#'?\s*@type\s+(`[^`]+`|[\w.]+)\s*:\s*([\w.]+)\s*(?:\{([^}]*)\})?\s*$").unwrap()
+});
+
+/// Classes of atomic vectors. They don't inherit from other classes, so they
+/// can be compared to any class.
+const ATOMIC_CLASSES: [&str; 7] = [
+    "character",
+    "numeric",
+    "double",
+    "integer",
+    "logical",
+    "complex",
+    "NULL",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TypeAnnotation {
+    /// The annotated object, without backticks
+    pub name: String,
+
+    /// Its class, e.g. `data.frame`
+    pub class: String,
+
+    /// Its fields, which `$` and `@` complete
+    pub fields: Vec<String>,
+
+    /// The end of the annotation, from which it applies
+    pub point: Point,
+}
+
+impl TypeAnnotation {
+    /// Parse the text of a comment ending at `point`
+    pub(crate) fn parse(comment: &str, point: Point) -> Option<Self> {
+        let captures = RE_TYPE_ANNOTATION.captures(comment.trim())?;
+
+        let name = unquote(captures.get(1)?.as_str()).to_string();
+        let class = captures.get(2)?.as_str().to_string();
+
+        let fields = match captures.get(3) {
+            Some(fields) => fields
+                .as_str()
+                .split(',')
+                .map(|field| unquote(field.trim()).to_string())
+                .filter(|field| !field.is_empty())
+                .collect(),
+            None => vec![],
+        };
+
+        Some(Self {
+            name,
+            class,
+            fields,
+            point,
+        })
+    }
+
+    pub(crate) fn is_atomic(&self) -> bool {
+        ATOMIC_CLASSES.contains(&self.class.as_str())
+    }
+
+    /// Markdown description of the type, for hovers
+    pub(crate) fn markdown(&self) -> String {
+        let mut value = format!("`{}`: `{}` (type annotation)", self.name, self.class);
+
+        if !self.fields.is_empty() {
+            let fields: Vec<String> = self.fields.iter().map(|field| format!("`{field}`")).collect();
+            value.push_str(format!("\n\nFields: {}", fields.join(", ")).as_str());
+        }
+
+        value
+    }
+}
+
+/// The annotations of a document, in order of appearance
+pub(crate) fn annotations(root: Node, contents: &Rope) -> Vec<TypeAnnotation> {
+    let mut annotations = vec![];
+
+    root.walk().recurse(|node| {
+        if !node.is_comment() {
+            return true;
+        }
+
+        if let Ok(comment) = contents.node_slice(&node) {
+            let comment = comment.to_string();
+            if let Some(annotation) = TypeAnnotation::parse(comment.as_str(), node.end_position())
+            {
+                annotations.push(annotation);
+            }
+        }
+
+        false
+    });
+
+    annotations
+}
+
+/// The annotation of `name` that applies at `point`, among `annotations`
+pub(crate) fn annotation_at<'a>(
+    annotations: &'a [TypeAnnotation],
+    name: &str,
+    point: Point,
+) -> Option<&'a TypeAnnotation> {
+    let name = unquote(name);
+
+    annotations
+        .iter()
+        .filter(|annotation| annotation.point <= point && annotation.name == name)
+        .last()
+}
+
+/// The annotation of `name` that applies at `point` in `document`
+pub(crate) fn find_annotation(
+    document: &Document,
+    name: &str,
+    point: Point,
+) -> Option<TypeAnnotation> {
+    let annotations = annotations(document.ast.root_node(), &document.contents);
+    annotation_at(&annotations, name, point).cloned()
+}
+
+/// The annotated parameters of the functions defined at the top level of a
+/// document, by function and by parameter
+pub(crate) fn parameter_annotations(
+    root: Node,
+    contents: &Rope,
+) -> HashMap<String, HashMap<String, TypeAnnotation>> {
+    let mut functions = HashMap::new();
+
+    let mut cursor = root.walk();
+    for node in root.children(&mut cursor) {
+        let Some((name, parameters)) = function_definition(&node, contents) else {
+            continue;
+        };
+
+        let mut annotations = HashMap::new();
+
+        // Walk up the comment block that ends on the line above the definition
+        let mut row = node.start_position().row;
+        let mut previous = node.prev_sibling();
+
+        while let Some(comment) = previous.filter(|node| node.is_comment()) {
+            if comment.end_position().row + 1 != row {
+                break;
+            }
+            row = comment.start_position().row;
+            previous = comment.prev_sibling();
+
+            let Ok(text) = contents.node_slice(&comment) else {
+                continue;
+            };
+            let Some(annotation) =
+                TypeAnnotation::parse(text.to_string().as_str(), comment.end_position())
+            else {
+                continue;
+            };
+
+            // The closest annotation of a parameter wins
+            if parameters.contains(&annotation.name) && !annotations.contains_key(&annotation.name)
+            {
+                annotations.insert(annotation.name.clone(), annotation);
+            }
+        }
+
+        if !annotations.is_empty() {
+            functions.insert(name, annotations);
+        }
+    }
+
+    functions
+}
+
+/// Name and parameters of `name <- function(...)`
+fn function_definition(node: &Node, contents: &Rope) -> Option<(String, Vec<String>)> {
+    if !matches!(
+        node.node_type(),
+        NodeType::BinaryOperator(BinaryOperatorType::LeftAssignment) |
+            NodeType::BinaryOperator(BinaryOperatorType::EqualsAssignment)
+    ) {
+        return None;
+    }
+
+    let lhs = node.child_by_field_name("lhs")?;
+    let rhs = node.child_by_field_name("rhs")?;
+    if !lhs.is_identifier_or_string() || !rhs.is_function_definition() {
+        return None;
+    }
+
+    let name = contents.node_slice(&lhs).ok()?.to_string();
+    let parameters = rhs.child_by_field_name("parameters")?;
+
+    let mut cursor = parameters.walk();
+    let parameters = parameters
+        .children(&mut cursor)
+        .filter_map(|parameter| parameter.child_by_field_name("name"))
+        .filter_map(|name| contents.node_slice(&name).ok())
+        .map(|name| unquote(name.to_string().as_str()).to_string())
+        .collect();
+
+    Some((name, parameters))
+}
+
+/// Whether a value of class `actual` can be passed where `expected` is
+/// annotated
+pub(crate) fn is_compatible(expected: &str, actual: &str) -> bool {
+    if expected == "any" || expected == actual {
+        return true;
+    }
+
+    // Integers are numbers too
+    matches!(expected, "numeric" | "double") && matches!(actual, "numeric" | "double" | "integer")
+}
+
+fn unquote(name: &str) -> &str {
+    name.trim_matches('`')
+}
+
+#[cfg(test)]
+mod tests {
+    use tree_sitter::Point;
+
+    use crate::lsp::documents::Document;
+    use crate::lsp::type_annotations::find_annotation;
+    use crate::lsp::type_annotations::is_compatible;
+    use crate::lsp::type_annotations::parameter_annotations;
+    use crate::lsp::type_annotations::TypeAnnotation;
+
+    #[test]
+    fn test_parse_type_annotation() {
+        let point = Point::new(0, 0);
+
+        let annotation = TypeAnnotation::parse("# @type x: data.frame", point).unwrap();
+        assert_eq!(annotation.name, "x");
+        assert_eq!(annotation.class, "data.frame");
+        assert!(annotation.fields.is_empty());
+
+        let annotation =
+            TypeAnnotation::parse("#' @type `my df` : data.frame{ id, `a b`, }", point).unwrap();
+        assert_eq!(annotation.name, "my df");
+        assert_eq!(annotation.fields, vec!["id", "a b"]);
+
+        assert!(TypeAnnotation::parse("# @type x", point).is_none());
+        assert!(TypeAnnotation::parse("# @type x: data.frame and more", point).is_none());
+        assert!(TypeAnnotation::parse("# The @type x: foo", point).is_none());
+    }
+
+    #[test]
+    fn test_find_annotation() {
+        let code = "
+# @type x: list{a}
+x <- f()
+# @type x: list{b}
+x <- g()
+";
+        let document = Document::new(code, None);
+
+        assert!(find_annotation(&document, "x", Point::new(1, 0)).is_none());
+
+        let annotation = find_annotation(&document, "x", Point::new(2, 0)).unwrap();
+        assert_eq!(annotation.fields, vec!["a"]);
+
+        let annotation = find_annotation(&document, "x", Point::new(5, 0)).unwrap();
+        assert_eq!(annotation.fields, vec!["b"]);
+
+        assert!(find_annotation(&document, "y", Point::new(5, 0)).is_none());
+    }
+
+    #[test]
+    fn test_parameter_annotations() {
+        let code = "
+# @type n: integer
+# @type x: character
+
+# @type y: numeric
+f <- function(x, y, n) x
+g <- function(x) x
+";
+        let document = Document::new(code, None);
+        let functions = parameter_annotations(document.ast.root_node(), &document.contents);
+
+        // Only the block right above the definition counts
+        let f = functions.get("f").unwrap();
+        assert_eq!(f.len(), 1);
+        assert_eq!(f.get("y").unwrap().class, "numeric");
+
+        assert!(functions.get("g").is_none());
+    }
+
+    #[test]
+    fn test_is_compatible() {
+        assert!(is_compatible("any", "character"));
+        assert!(is_compatible("numeric", "integer"));
+        assert!(is_compatible("double", "numeric"));
+        assert!(!is_compatible("integer", "numeric"));
+        assert!(!is_compatible("data.frame", "character"));
+    }
+}
@@ -0,0 +1,335 @@
+//
+// type_definitions.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use anyhow::Result;
+use tower_lsp::lsp_types::request::GotoTypeDefinitionParams;
+use tower_lsp::lsp_types::request::GotoTypeDefinitionResponse;
+use tower_lsp::lsp_types::LocationLink;
+use tower_lsp::lsp_types::Range;
+use tower_lsp::lsp_types::Url;
+use tree_sitter::Node;
+use tree_sitter::Point;
+
+use crate::lsp::documents::Document;
+use crate::lsp::encoding::convert_point_to_position;
+use crate::lsp::encoding::convert_position_to_point;
+use crate::lsp::indexer;
+use crate::lsp::indexer::IndexEntry;
+use crate::lsp::indexer::IndexEntryData;
+use crate::lsp::traits::cursor::TreeCursorExt;
+use crate::lsp::traits::node::NodeExt;
+use crate::lsp::traits::rope::RopeExt;
+use crate::treesitter::node_find_string;
+use crate::treesitter::BinaryOperatorType;
+use crate::treesitter::ExtractOperatorType;
+use crate::treesitter::NodeType;
+use crate::treesitter::NodeTypeExt;
+
+enum Query {
+    /// A class, by name or by name of its generator
+    Class(String),
+    /// An S4 generic, by name
+    Generic(String),
+}
+
+/// Find the definition of the class of the object at the cursor. Handles:
+///
+/// - Objects created with `new("Foo")`, `Foo$new()`, or `Foo()`, where `Foo`
+///   is a class created with `setClass()`, `setRefClass()`, or `R6Class()`.
+/// - Constructors and generators, e.g. `Foo` in `Foo$new()` or the string in
+///   `new("Foo")`.
+/// - Calls to S4 generics created with `setGeneric()`.
+pub fn goto_type_definition(
+    document: &Document,
+    params: GotoTypeDefinitionParams,
+) -> Result<Option<GotoTypeDefinitionResponse>> {
+    let contents = &document.contents;
+
+    let position = params.text_document_position_params.position;
+    let point = convert_position_to_point(contents, position);
+
+    let Some(node) = document.ast.root_node().find_closest_node_to_point(point) else {
+        log::warn!("Failed to find the closest node to point {point}.");
+        return Ok(None);
+    };
+
+    let (node, queries) = if let Some(string) = node_find_string(&node) {
+        let Some(name) = indexer::string_value(&string, contents) else {
+            return Ok(None);
+        };
+        (string, vec![Query::Class(name)])
+    } else if node.is_identifier() {
+        (node, identifier_queries(&node, document, point)?)
+    } else {
+        return Ok(None);
+    };
+
+    let start = convert_point_to_position(contents, node.start_position());
+    let end = convert_point_to_position(contents, node.end_position());
+    let origin = Range { start, end };
+
+    let uri = &params.text_document_position_params.text_document.uri;
+
+    for query in queries.iter() {
+        if let Some(mut link) = type_definition(query, document, uri) {
+            link.origin_selection_range = Some(origin);
+            return Ok(Some(GotoTypeDefinitionResponse::Link(vec![link])));
+        }
+    }
+
+    Ok(None)
+}
+
+fn identifier_queries(node: &Node, document: &Document, point: Point) -> Result<Vec<Query>> {
+    let contents = &document.contents;
+    let symbol = contents.node_slice(node)?.to_string();
+    let parent = node.parent();
+
+    // `Foo$new`
+    if let Some(parent) = parent.filter(|parent| is_dollar(parent)) {
+        let lhs = parent.child_by_field_name("lhs");
+        let rhs = parent.child_by_field_name("rhs");
+
+        if rhs == Some(*node) && symbol == "new" {
+            let Some(lhs) = lhs.filter(|lhs| lhs.is_identifier()) else {
+                return Ok(vec![]);
+            };
+            return Ok(vec![Query::Class(contents.node_slice(&lhs)?.to_string())]);
+        }
+        if lhs == Some(*node) {
+            let mut queries = vec![Query::Class(symbol.clone())];
+            if let Some(class) = object_class(&symbol, document, point) {
+                queries.push(Query::Class(class));
+            }
+            return Ok(queries);
+        }
+    }
+
+    // `new("Foo")`, `Foo()`, `generic()`
+    if let Some(call) = parent
+        .filter(|parent| parent.is_call() && parent.child_by_field_name("function") == Some(*node))
+    {
+        if symbol == "new" {
+            return Ok(call_class(&call, document)
+                .map(Query::Class)
+                .into_iter()
+                .collect());
+        }
+        return Ok(vec![Query::Generic(symbol.clone()), Query::Class(symbol)]);
+    }
+
+    // Objects and generators
+    let mut queries = vec![];
+    if let Some(class) = object_class(&symbol, document, point) {
+        queries.push(Query::Class(class));
+    }
+    queries.push(Query::Class(symbol.clone()));
+    queries.push(Query::Generic(symbol));
+
+    Ok(queries)
+}
+
+/// The class of the object `symbol`, according to the last assignment to
+/// `symbol` before `point`
+fn object_class(symbol: &str, document: &Document, point: Point) -> Option<String> {
+    let contents = &document.contents;
+    let mut value = None;
+
+    document.ast.root_node().walk().recurse(|node| {
+        if node.start_position() > point {
+            return false;
+        }
+
+        if matches!(
+            node.node_type(),
+            NodeType::BinaryOperator(BinaryOperatorType::LeftAssignment) |
+                NodeType::BinaryOperator(BinaryOperatorType::EqualsAssignment)
+        ) {
+            let lhs = node.child_by_field_name("lhs");
+            let rhs = node.child_by_field_name("rhs");
+
+            if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+                let name = contents.node_slice(&lhs).ok().map(|x| x.to_string());
+                if lhs.is_identifier() && name.as_deref() == Some(symbol) {
+                    value = Some(rhs);
+                }
+            }
+        }
+
+        true
+    });
+
+    let value = value?;
+    if !value.is_call() {
+        return None;
+    }
+
+    call_class(&value, document)
+}
+
+/// The class of the object created by `new("Foo")`, `Foo$new()`, or `Foo()`
+fn call_class(call: &Node, document: &Document) -> Option<String> {
+    let contents = &document.contents;
+    let mut fun = call.child_by_field_name("function")?;
+
+    // `methods::new()`
+    if fun.is_namespace_operator() {
+        fun = fun.child_by_field_name("rhs")?;
+    }
+
+    if is_dollar(&fun) {
+        let lhs = fun.child_by_field_name("lhs")?;
+        let rhs = fun.child_by_field_name("rhs")?;
+        if !lhs.is_identifier() || contents.node_slice(&rhs).ok()? != "new" {
+            return None;
+        }
+        return Some(contents.node_slice(&lhs).ok()?.to_string());
+    }
+
+    if !fun.is_identifier() {
+        return None;
+    }
+    let fun = contents.node_slice(&fun).ok()?.to_string();
+
+    if fun != "new" {
+        return Some(fun);
+    }
+
+    let arguments = call.child_by_field_name("arguments")?;
+    let mut cursor = arguments.walk();
+    let first = arguments
+        .children_by_field_name("argument", &mut cursor)
+        .next()?;
+    indexer::string_value(&first.child_by_field_name("value")?, contents)
+}
+
+fn is_dollar(node: &Node) -> bool {
+    node.node_type() == NodeType::ExtractOperator(ExtractOperatorType::Dollar)
+}
+
+/// Look for the definition in the current document first, as it may not be
+/// indexed, then in the workspace
+fn type_definition(query: &Query, document: &Document, uri: &Url) -> Option<LocationLink> {
+    let matches = |entry: &IndexEntry| match query {
+        Query::Class(name) => entry.is_class(name),
+        Query::Generic(name) => matches!(
+            &entry.data,
+            IndexEntryData::Generic { name: generic } if generic == name
+        ),
+    };
+
+    // The last definition wins, as when the document is sourced
+    let entries = indexer::index_entries(document);
+    if let Some(entry) = entries.iter().rev().find(|entry| matches(entry)) {
+        return Some(link(uri.clone(), entry));
+    }
+
+    let (path, entry) = match query {
+        Query::Class(name) => indexer::find_class(name),
+        Query::Generic(name) => indexer::find_generic(name),
+    }?;
+    let uri = Url::from_file_path(path).ok()?;
+
+    Some(link(uri, &entry))
+}
+
+fn link(uri: Url, entry: &IndexEntry) -> LocationLink {
+    LocationLink {
+        origin_selection_range: None,
+        target_uri: uri,
+        target_range: entry.range,
+        target_selection_range: entry.range,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::request::GotoTypeDefinitionParams;
+    use tower_lsp::lsp_types::request::GotoTypeDefinitionResponse;
+    use tower_lsp::lsp_types::LocationLink;
+    use tower_lsp::lsp_types::Position;
+    use tower_lsp::lsp_types::Range;
+    use tower_lsp::lsp_types::TextDocumentIdentifier;
+    use tower_lsp::lsp_types::TextDocumentPositionParams;
+    use tower_lsp::lsp_types::Url;
+
+    use crate::lsp::documents::Document;
+    use crate::lsp::type_definitions::goto_type_definition;
+
+    fn type_definition_at(code: &str, position: Position) -> Option<LocationLink> {
+        let document = Document::new(code, None);
+        let params = GotoTypeDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Url::parse("untitled:Untitled-1").unwrap(),
+                },
+                position,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+
+        match goto_type_definition(&document, params).unwrap()? {
+            GotoTypeDefinitionResponse::Link(mut links) => links.pop(),
+            _ => panic!("Expected a link"),
+        }
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range {
+            start: Position::new(start.0, start.1),
+            end: Position::new(end.0, end.1),
+        }
+    }
+
+    #[test]
+    fn test_type_definition_r6() {
+        let code = "
+Person <- R6::R6Class('Person', public = list(name = NULL))
+p <- Person$new()
+p$name
+";
+        let target = range((1, 22), (1, 30));
+
+        // Object
+        let link = type_definition_at(code, Position::new(3, 0)).unwrap();
+        assert_eq!(link.target_range, target);
+        assert_eq!(link.origin_selection_range, Some(range((3, 0), (3, 1))));
+
+        // Generator and constructor
+        let link = type_definition_at(code, Position::new(2, 6)).unwrap();
+        assert_eq!(link.target_range, target);
+        let link = type_definition_at(code, Position::new(2, 13)).unwrap();
+        assert_eq!(link.target_range, target);
+
+        // Unknown objects have no type definition
+        assert_eq!(type_definition_at("x <- 1\nx", Position::new(1, 0)), None);
+    }
+
+    #[test]
+    fn test_type_definition_s4() {
+        let code = "
+setClass(\"Point\", representation(x = \"numeric\"))
+setGeneric(\"norm2\", function(p) standardGeneric(\"norm2\"))
+p <- new(\"Point\", x = 1)
+norm2(p)
+";
+
+        // Object
+        let link = type_definition_at(code, Position::new(4, 6)).unwrap();
+        assert_eq!(link.target_range, range((1, 9), (1, 16)));
+
+        // Class name in `new()`
+        let link = type_definition_at(code, Position::new(3, 11)).unwrap();
+        assert_eq!(link.target_range, range((1, 9), (1, 16)));
+        assert_eq!(link.origin_selection_range, Some(range((3, 9), (3, 16))));
+
+        // Generic
+        let link = type_definition_at(code, Position::new(4, 1)).unwrap();
+        assert_eq!(link.target_range, range((2, 11), (2, 18)));
+    }
+}
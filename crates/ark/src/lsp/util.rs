@@ -28,3 +28,11 @@ pub unsafe extern "C" fn ps_object_id(object: SEXP) -> anyhow::Result<SEXP> {
     let value = format!("{:p}", object);
     return Ok(Rf_mkString(value.as_ptr() as *const c_char));
 }
+
+/// Sends the packages and scopes of the session to the LSP, e.g. after the
+/// library paths changed outside of an execution
+#[harp::register]
+pub unsafe extern "C" fn ps_refresh_lsp() -> anyhow::Result<SEXP> {
+    crate::interface::RMain::get().refresh_lsp();
+    Ok(R_NilValue)
+}
@@ -0,0 +1,48 @@
+//
+// virtual_documents.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Virtual documents are R documents that only exist in the client and that
+// aren't synchronised with the usual `textDocument/` notifications, e.g. the
+// R chunks of a notebook or of an R Markdown document, concatenated by the
+// client. Completions, hover, references, and other features work against
+// their contents as if they were open in an editor.
+//
+// Not to be confused with `ARK_VDOC_REQUEST`, which goes the other way: the
+// client requests documents generated by Ark, e.g. the source of functions
+// without srcrefs.
+
+use serde::Deserialize;
+use serde::Serialize;
+use url::Url;
+
+pub static ARK_VIRTUAL_DOCUMENT_DID_OPEN: &'static str = "ark/virtualDocument/didOpen";
+pub static ARK_VIRTUAL_DOCUMENT_DID_CHANGE: &'static str = "ark/virtualDocument/didChange";
+pub static ARK_VIRTUAL_DOCUMENT_DID_CLOSE: &'static str = "ark/virtualDocument/didClose";
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualDocumentDidOpenParams {
+    /// Identifies the document in subsequent notifications and in requests,
+    /// e.g. `untitled:Untitled-1` or `vscode-notebook-cell:/path/to/nb.ipynb`.
+    pub uri: Url,
+    /// The full contents of the document.
+    pub text: String,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualDocumentDidChangeParams {
+    pub uri: Url,
+    /// The full contents of the document, replacing the previous ones.
+    pub text: String,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualDocumentDidCloseParams {
+    pub uri: Url,
+}
@@ -8,8 +8,11 @@
 #![allow(unused_unsafe)]
 
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::env;
 
+use amalthea::features;
+use amalthea::features::Source;
 use amalthea::kernel;
 use amalthea::kernel_spec::KernelSpec;
 use ark::interface::SessionMode;
@@ -17,6 +20,7 @@ use ark::logger;
 use ark::signals::initialize_signal_block;
 use ark::start::start_kernel;
 use ark::traps::register_trap_handlers;
+use ark::user_config;
 use ark::version::detect_r;
 use crossbeam::channel::unbounded;
 use notify::Watcher;
@@ -41,9 +45,24 @@ Available options:
 --startup-file FILE      An R file to run on session startup
 --session-mode MODE      The mode in which the session is running (console, notebook, background)
 --no-capture-streams     Do not capture stdout/stderr from R
+--read-only              Disable code execution. LSP features, help, and data
+                         viewers keep working with objects loaded at startup
+--shared-session ID      Allow other clients to observe the session. The client
+                         with the Jupyter session ID drives the session, the
+                         code and changes of other clients are rejected
 --version                Print the version of Ark
 --log FILE               Log to the given file (if not specified, stdout/stderr
                          will be used)
+--log-level LEVEL        Set the verbosity of the log (error, warn, info, debug,
+                         trace). Takes precedence over `RUST_LOG`
+--config FILE            Read user settings from the given TOML file (defaults
+                         to `~/.config/ark/config.toml`)
+--feature NAME[=BOOL]    Turn a feature flag on or off, e.g.
+                         `comm_chunking=true`. Can be repeated. Takes
+                         precedence over user settings
+--record DIR             Record the Jupyter messages exchanged with the frontend
+                         to a file in DIR, to reproduce protocol issues. HMAC
+                         signatures are not recorded
 --install                Install the kernel spec for Ark
 --help                   Print this help message
 "#
@@ -66,12 +85,18 @@ fn main() -> anyhow::Result<()> {
     let mut startup_file: Option<String> = None;
     let mut session_mode = SessionMode::Console;
     let mut log_file: Option<String> = None;
+    let mut log_level: Option<String> = None;
+    let mut config_file: Option<String> = None;
     let mut profile_file: Option<String> = None;
+    let mut record_dir: Option<String> = None;
+    let mut feature_overrides: HashMap<String, bool> = HashMap::new();
     let mut startup_notifier_file: Option<String> = None;
     let mut startup_delay: Option<std::time::Duration> = None;
     let mut r_args: Vec<String> = Vec::new();
     let mut has_action = false;
     let mut capture_streams = true;
+    let mut read_only = false;
+    let mut driver_session: Option<String> = None;
 
     // Process remaining arguments. TODO: Need an argument that can passthrough args to R
     while let Some(arg) = argv.next() {
@@ -127,6 +152,16 @@ fn main() -> anyhow::Result<()> {
                 has_action = true;
             },
             "--no-capture-streams" => capture_streams = false,
+            "--read-only" => read_only = true,
+            "--shared-session" => {
+                if let Some(session) = argv.next() {
+                    driver_session = Some(session);
+                } else {
+                    return Err(anyhow::anyhow!(
+                        "The session ID of the driving client must be specified when using the `--shared-session` argument."
+                    ));
+                }
+            },
             "--log" => {
                 if let Some(file) = argv.next() {
                     log_file = Some(file);
@@ -136,6 +171,24 @@ fn main() -> anyhow::Result<()> {
                     ));
                 }
             },
+            "--log-level" => {
+                if let Some(level) = argv.next() {
+                    log_level = Some(level);
+                } else {
+                    return Err(anyhow::anyhow!(
+                        "A log level must be specified when using the `--log-level` argument."
+                    ));
+                }
+            },
+            "--config" => {
+                if let Some(file) = argv.next() {
+                    config_file = Some(file);
+                } else {
+                    return Err(anyhow::anyhow!(
+                        "A configuration file must be specified when using the `--config` argument."
+                    ));
+                }
+            },
             "--profile" => {
                 if let Some(file) = argv.next() {
                     profile_file = Some(file);
@@ -145,6 +198,25 @@ fn main() -> anyhow::Result<()> {
                     ));
                 }
             },
+            "--record" => {
+                if let Some(dir) = argv.next() {
+                    record_dir = Some(dir);
+                } else {
+                    return Err(anyhow::anyhow!(
+                        "A directory must be specified when using the `--record` argument."
+                    ));
+                }
+            },
+            "--feature" => {
+                if let Some(feature) = argv.next() {
+                    let (name, enabled) = features::parse_override(&feature)?;
+                    feature_overrides.insert(name, enabled);
+                } else {
+                    return Err(anyhow::anyhow!(
+                        "A feature must be specified when using the `--feature` argument."
+                    ));
+                }
+            },
             "--startup-notifier-file" => {
                 if let Some(file) = argv.next() {
                     startup_notifier_file = Some(file);
@@ -180,8 +252,40 @@ fn main() -> anyhow::Result<()> {
         }
     }
 
+    let config_path = user_config::config_path(config_file.as_deref())?;
+
+    // Command line flags take precedence over the environment, which takes
+    // precedence over user settings
+    let log_level = match log_level {
+        Some(level) => Some(level),
+        None if env::var("RUST_LOG").is_ok() => None,
+        None => config_path
+            .as_deref()
+            .and_then(|path| user_config::read(path).ok())
+            .and_then(|config| config.log.level),
+    };
+
     // Initialize the logger.
-    logger::init(log_file.as_deref(), profile_file.as_deref());
+    logger::init(
+        log_file.as_deref(),
+        profile_file.as_deref(),
+        log_level.as_deref(),
+    );
+
+    // Features are registered before overrides are set, so that unknown
+    // names can be reported
+    ark::features::register();
+    if !feature_overrides.is_empty() {
+        features::set_overrides(Source::CommandLine, feature_overrides);
+    }
+
+    // Load user settings once the logger is up so that errors in the file are
+    // reported. They don't prevent startup.
+    user_config::init(config_path);
+
+    if let Some(dir) = record_dir {
+        amalthea::recorder::start(std::path::Path::new(&dir))?;
+    }
 
     if let Some(file) = startup_notifier_file {
         let path = std::path::Path::new(&file);
@@ -310,6 +414,8 @@ fn main() -> anyhow::Result<()> {
         startup_file,
         session_mode,
         capture_streams,
+        read_only,
+        driver_session,
     );
 
     // Just to please Rust
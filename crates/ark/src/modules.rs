@@ -101,38 +101,16 @@ pub fn initialize() -> anyhow::Result<RObject> {
     Environment::view(namespace.sexp).lock(false);
 
     // Load the positron and rstudio namespaces and their exported functions
-    for file in PositronModuleAsset::iter() {
-        source_asset::<PositronModuleAsset>(&file, "import_positron", namespace.sexp)?;
-    }
-    for file in RStudioModuleAsset::iter() {
-        source_asset::<RStudioModuleAsset>(&file, "import_rstudio", namespace.sexp)?;
-    }
+    import_modules(namespace.sexp)?;
 
     // Create a directory watcher that reloads module files as they are changed.
     #[cfg(debug_assertions)]
     {
-        use std::path::Path;
-
         use debug::*;
 
-        let source = std::env!("CARGO_MANIFEST_DIR");
-        let root = Path::new(&source).join("src").join("modules").to_path_buf();
+        let root = modules_source_root();
 
         if root.exists() {
-            // First reload all modules from source to reflect new changes that have
-            // not been built into the binary yet.
-            log::trace!("Loading R modules from sources via cargo manifest");
-            import_directory(
-                &root.join("positron"),
-                RModuleSource::Positron,
-                namespace.sexp,
-            )?;
-            import_directory(
-                &root.join("rstudio"),
-                debug::RModuleSource::RStudio,
-                namespace.sexp,
-            )?;
-
             // Spawn the watcher thread when R is idle so we don't try to access
             // the R API while R is starting up
             r_task::spawn_idle(move || async {
@@ -147,6 +125,38 @@ pub fn initialize() -> anyhow::Result<RObject> {
     return Ok(namespace);
 }
 
+/// Source the R modules again in the Positron namespace, e.g. to restore
+/// functions that were overwritten while debugging
+pub fn reload(namespace: SEXP) -> anyhow::Result<()> {
+    import_modules(namespace)
+}
+
+fn import_modules(namespace: SEXP) -> anyhow::Result<()> {
+    for file in PositronModuleAsset::iter() {
+        source_asset::<PositronModuleAsset>(&file, "import_positron", namespace)?;
+    }
+    for file in RStudioModuleAsset::iter() {
+        source_asset::<RStudioModuleAsset>(&file, "import_rstudio", namespace)?;
+    }
+
+    #[cfg(debug_assertions)]
+    {
+        use debug::*;
+
+        let root = modules_source_root();
+
+        if root.exists() {
+            // Reload all modules from source to reflect new changes that have
+            // not been built into the binary yet.
+            log::trace!("Loading R modules from sources via cargo manifest");
+            import_directory(&root.join("positron"), RModuleSource::Positron, namespace)?;
+            import_directory(&root.join("rstudio"), RModuleSource::RStudio, namespace)?;
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(debug_assertions)]
 mod debug {
     use std::collections::HashMap;
@@ -163,6 +173,11 @@ mod debug {
     use crate::interface::RMain;
     use crate::r_task;
 
+    pub fn modules_source_root() -> PathBuf {
+        let source = std::env!("CARGO_MANIFEST_DIR");
+        Path::new(&source).join("src").join("modules").to_path_buf()
+    }
+
     pub fn spawn_watcher_thread(root: PathBuf) {
         spawn!("ark-modules-watcher", {
             move || {
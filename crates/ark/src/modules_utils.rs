@@ -1,3 +1,11 @@
+use harp::object::is_identical;
+use harp::object::RObject;
+use harp::r_symbol;
+use harp::utils::r_env_binding_is_active;
+use harp::utils::r_is_promise;
+use harp::utils::r_promise_expr;
+use harp::utils::r_promise_is_forced;
+use harp::utils::r_promise_value;
 use libr::SEXP;
 
 #[harp::register]
@@ -14,3 +22,60 @@ pub unsafe extern "C" fn ps_deep_sleep(secs: SEXP) -> anyhow::Result<SEXP> {
 
     return Ok(harp::r_null());
 }
+
+/// Cancellation point for long running R code, see `harp::cancellation`
+#[harp::register]
+pub unsafe extern "C" fn ps_check_cancelled() -> anyhow::Result<SEXP> {
+    harp::cancellation::check_cancelled()?;
+    return Ok(harp::r_null());
+}
+
+/// Whether the bindings `name` of `env` and `other` hold the same object,
+/// without running code. Promises that haven't been forced are compared by
+/// their expression and environment, e.g. an export of a lazy-loaded package
+/// and its re-export by another package. Active bindings are never identical.
+#[harp::register]
+pub unsafe extern "C" fn ps_bindings_identical(
+    name: SEXP,
+    env: SEXP,
+    other: SEXP,
+) -> anyhow::Result<SEXP> {
+    let name: String = RObject::view(name).try_into()?;
+    let symbol = r_symbol!(name);
+
+    if r_env_binding_is_active(env, symbol)? || r_env_binding_is_active(other, symbol)? {
+        return Ok(RObject::from(false).sexp);
+    }
+
+    let x = libr::Rf_findVarInFrame(env, symbol);
+    let y = libr::Rf_findVarInFrame(other, symbol);
+
+    Ok(RObject::from(is_identical_binding(x, y)).sexp)
+}
+
+unsafe fn is_identical_binding(x: SEXP, y: SEXP) -> bool {
+    // Copied bindings share the same promise
+    if x == y {
+        return true;
+    }
+
+    let x = forced_value(x);
+    let y = forced_value(y);
+
+    match (r_is_promise(x), r_is_promise(y)) {
+        (false, false) => is_identical(x, y),
+        (true, true) => {
+            libr::PRENV(x) == libr::PRENV(y) && is_identical(r_promise_expr(x), r_promise_expr(y))
+        },
+        _ => false,
+    }
+}
+
+/// The value of a promise if it has been forced, the object itself otherwise
+fn forced_value(x: SEXP) -> SEXP {
+    if r_is_promise(x) && r_promise_is_forced(x) {
+        r_promise_value(x)
+    } else {
+        x
+    }
+}
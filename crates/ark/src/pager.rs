@@ -0,0 +1,34 @@
+//
+// pager.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// R shows files in a pager with `file.show()`, used by `page()`, by
+// `RShowDoc()`, and by the text help. Ark's pager, set with `options(pager =)`,
+// sends the contents of the files as a `page` payload of the reply to the
+// current execution. Jupyter frontends show it in a pager pane, or in the
+// output of the cell, and the payloads are dropped once the execution
+// completes.
+
+use harp::object::RObject;
+use libr::R_NilValue;
+use libr::SEXP;
+use serde_json::json;
+
+use crate::interface::RMain;
+
+/// Attach `text` to the reply to the current execution as a `page` payload
+#[harp::register]
+pub unsafe extern "C" fn ps_page(text: SEXP) -> anyhow::Result<SEXP> {
+    let text: String = RObject::view(text).try_into()?;
+
+    RMain::get_mut().push_payload(json!({
+        "source": "page",
+        "data": { "text/plain": text },
+        "start": 0,
+    }));
+
+    Ok(R_NilValue)
+}
@@ -26,14 +26,20 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Read;
+use std::time::Duration;
+use std::time::Instant;
 
 use amalthea::comm::comm_channel::CommMsg;
 use amalthea::comm::event::CommManagerEvent;
+use amalthea::comm::event_log_comm::KernelEventKind;
+use amalthea::comm::plot_comm::ClickParams;
 use amalthea::comm::plot_comm::PlotBackendReply;
 use amalthea::comm::plot_comm::PlotBackendRequest;
 use amalthea::comm::plot_comm::PlotFrontendEvent;
 use amalthea::comm::plot_comm::PlotResult;
+use amalthea::comm::plot_comm::PlotSize;
 use amalthea::comm::plot_comm::RenderFormat;
+use amalthea::event_log;
 use amalthea::socket::comm::CommInitiator;
 use amalthea::socket::comm::CommSocket;
 use amalthea::socket::iopub::IOPubMessage;
@@ -43,6 +49,7 @@ use amalthea::wire::update_display_data::UpdateDisplayData;
 use anyhow::bail;
 use base64::engine::general_purpose;
 use base64::Engine;
+use crossbeam::channel::RecvTimeoutError;
 use crossbeam::channel::Select;
 use crossbeam::channel::Sender;
 use harp::exec::RFunction;
@@ -51,6 +58,9 @@ use harp::object::RObject;
 use libr::pDevDesc;
 use libr::pGEcontext;
 use libr::R_NilValue;
+use libr::Rboolean;
+use libr::Rboolean_FALSE;
+use libr::Rboolean_TRUE;
 use libr::Rf_ScalarLogical;
 use libr::SEXP;
 use once_cell::sync::Lazy;
@@ -59,7 +69,12 @@ use stdext::result::ResultOrLog;
 use stdext::unwrap;
 use uuid::Uuid;
 
+use crate::interface::r_polled_events;
+use crate::interface::RMain;
+use crate::interface::SessionMode;
 use crate::r_task;
+use crate::signals::interrupts_pending;
+use crate::user_config::user_config;
 
 const POSITRON_PLOT_CHANNEL_ID: &str = "positron.plot";
 
@@ -104,10 +119,18 @@ struct DeviceContext {
     // for accessing indexed plots, e.g. for the Plots pane history.
     pub _id: Option<String>,
 
+    // IDs of the plot pages created since the last execute request
+    // completed. Reported to the session transcript.
+    pub _new_page_ids: Vec<String>,
+
     // A map, mapping plot IDs to the communication channels used
     // for communicating their rendered results to the frontend.
     pub _channels: HashMap<String, CommSocket>,
 
+    // The size and pixel ratio of the last render of each plot. Clicks
+    // on a rendered plot are located relative to this render.
+    pub _renders: HashMap<String, (PlotSize, f64)>,
+
     // The device callbacks, which are patched into the device.
     pub _callbacks: DeviceCallbacks,
 }
@@ -127,20 +150,24 @@ impl DeviceContext {
         let id = Uuid::new_v4().to_string();
         self._id = Some(id.clone());
         self._new_page = true;
+        self._new_page_ids.push(id);
     }
 
+    /// Returns the IDs of the plot pages created during the execution
     pub fn on_did_execute_request(
         &mut self,
         comm_manager_tx: Sender<CommManagerEvent>,
         iopub_tx: Sender<IOPubMessage>,
         dynamic_plots: bool,
-    ) {
+    ) -> Vec<String> {
         // After R code has completed execution, we use this to check if any graphics
         // need to be created
         if self._changes {
             self._changes = false;
             self.process_changes(comm_manager_tx, iopub_tx, dynamic_plots);
         }
+
+        std::mem::take(&mut self._new_page_ids)
     }
 
     pub fn on_process_events(&mut self) {
@@ -197,6 +224,9 @@ impl DeviceContext {
                 let size = unwrap!(plot_meta.size, None => {
                     bail!("Intrinsically sized plots are not yet supported.");
                 });
+                self._renders
+                    .insert(plot_id.clone(), (size.clone(), plot_meta.pixel_ratio));
+
                 let data = self.render_plot(
                     &plot_id,
                     size.width,
@@ -206,14 +236,144 @@ impl DeviceContext {
                 )?;
 
                 let mime_type = Self::get_mime_type(&plot_meta.format);
+                let description = match user_config().plots.describe {
+                    true => self.describe_plot(plot_id),
+                    false => None,
+                };
+
                 Ok(PlotBackendReply::RenderReply(PlotResult {
                     data: data.to_string(),
                     mime_type: mime_type.to_string(),
+                    description,
                 }))
             },
+            PlotBackendRequest::Click(_) | PlotBackendRequest::CancelLocate => {
+                bail!("Not waiting for graphical input.");
+            },
+        }
+    }
+
+    /// Wait for the user to click on the current plot, for `locator()` and
+    /// `identify()`. Returns the location of the click in device
+    /// coordinates, or `None` if the user cancelled or interrupted R.
+    pub fn locator(&mut self) -> anyhow::Result<Option<(f64, f64)>> {
+        // The plot is normally shown once the execute request completes,
+        // but the user needs to see it now to click on it
+        let main = RMain::get();
+        if self._changes {
+            self._changes = false;
+            let dynamic_plots =
+                main.is_ui_comm_connected() && main.session_mode == SessionMode::Console;
+            self.process_changes(
+                main.get_comm_manager_tx().clone(),
+                main.get_iopub_tx().clone(),
+                dynamic_plots,
+            );
+        }
+
+        let id = unwrap!(self._id.clone(), None => {
+            bail!("No plot to locate points on.");
+        });
+
+        // Only Positron can send clicks back, other frontends get static plots
+        let socket = unwrap!(self._channels.get(&id).cloned(), None => {
+            log::warn!("Can't locate points on plot {id}: graphical input requires Positron.");
+            return Ok(None);
+        });
+
+        let event = serde_json::to_value(PlotFrontendEvent::Locate)?;
+        socket.outgoing_tx.send(CommMsg::Data(event))?;
+
+        loop {
+            // Let R process the interrupt once `locator()` returns
+            if interrupts_pending() {
+                break;
+            }
+
+            // Keep running R tasks, e.g. for completions, while we wait
+            unsafe { r_polled_events() };
+
+            let message = match socket.incoming_rx.recv_timeout(LOCATOR_POLL_INTERVAL) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            };
+
+            // `None` until the user clicks or cancels. Other requests, e.g.
+            // renders when the plot pane is resized, are handled as usual.
+            let mut located: Option<Option<(f64, f64)>> = None;
+
+            socket.handle_request(message, |req| match req {
+                PlotBackendRequest::Click(params) => {
+                    located = Some(Some(self.locate_click(&id, &params)?));
+                    Ok(PlotBackendReply::ClickReply())
+                },
+                PlotBackendRequest::CancelLocate => {
+                    located = Some(None);
+                    Ok(PlotBackendReply::CancelLocateReply())
+                },
+                req => self.handle_rpc(req, &id),
+            });
+
+            match located {
+                Some(Some(point)) => return Ok(Some(point)),
+                Some(None) => break,
+                None => continue,
+            }
+        }
+
+        let event = serde_json::to_value(PlotFrontendEvent::LocateEnd)?;
+        socket.outgoing_tx.send(CommMsg::Data(event))?;
+
+        Ok(None)
+    }
+
+    /// Convert a click on the rendered image of a plot to device
+    /// coordinates. The image may have been rendered at a different size and
+    /// resolution than the device, see `.ps.graphics.locatePoint()`.
+    fn locate_click(&self, plot_id: &str, click: &ClickParams) -> anyhow::Result<(f64, f64)> {
+        let (size, pixel_ratio) = unwrap!(self._renders.get(plot_id), None => {
+            bail!("Can't locate a click on plot {plot_id} before it is rendered.");
+        });
+
+        // HiDPI renders have `pixel_ratio` image pixels per plot pixel
+        let width = size.width as f64 * pixel_ratio;
+        let height = size.height as f64 * pixel_ratio;
+
+        // Normalised device coordinates start from the bottom left corner
+        let x = click.x / width;
+        let y = 1.0 - click.y / height;
+
+        let point: Vec<f64> = RFunction::from(".ps.graphics.locatePoint")
+            .param("x", x)
+            .param("y", y)
+            .param("width", RObject::try_from(size.width)?)
+            .param("height", RObject::try_from(size.height)?)
+            .call()?
+            .try_into()?;
+
+        match point.as_slice() {
+            [x, y] => Ok((*x, *y)),
+            _ => bail!("Unexpected located point: {point:?}."),
         }
     }
 
+    /// Plain text description of a plot for screen readers, e.g. the plot
+    /// type, axes and number of series. `None` if the plot can't be described.
+    fn describe_plot(&self, plot_id: &str) -> Option<String> {
+        let description = r_task(|| -> anyhow::Result<Option<String>> {
+            let description = RFunction::from(".ps.graphics.describePlot")
+                .add(plot_id)
+                .call()?;
+            Ok(description.try_into()?)
+        });
+
+        unwrap!(description, Err(error) => {
+            log::error!("Failed to describe plot with id {plot_id} due to: {error}.");
+            return None;
+        })
+    }
+
     fn get_mime_type(format: &RenderFormat) -> String {
         match format {
             RenderFormat::Png => "image/png".to_string(),
@@ -360,18 +520,35 @@ impl DeviceContext {
     }
 
     fn create_display_data_plot(&mut self, id: &str) -> Result<serde_json::Value, anyhow::Error> {
-        // TODO: Take these from R global options? Like `ark.plot.width`?
-        let width = 800;
-        let height = 600;
+        let config = user_config().plots;
         let pixel_ratio = 1.0;
-        let format = RenderFormat::Png;
+        let format = RenderFormat::from(config.format);
 
-        let data = unwrap!(self.render_plot(id, width, height, pixel_ratio, &format), Err(error) => {
+        let data = unwrap!(self.render_plot(id, config.width, config.height, pixel_ratio, &format), Err(error) => {
             bail!("Failed to render plot with id {id} due to: {error}.");
         });
 
+        // Jupyter expects SVG as text rather than base64
+        let data = match format {
+            RenderFormat::Svg => {
+                let bytes = general_purpose::STANDARD_NO_PAD.decode(data)?;
+                String::from_utf8(bytes)?
+            },
+            _ => data,
+        };
+
         let mut map = serde_json::Map::new();
-        map.insert("image/png".to_string(), serde_json::to_value(data).unwrap());
+        map.insert(
+            Self::get_mime_type(&format),
+            serde_json::to_value(data).unwrap(),
+        );
+
+        // Frontends show the plain text representation to screen readers
+        if config.describe {
+            if let Some(description) = self.describe_plot(id) {
+                map.insert(String::from("text/plain"), json!(description));
+            }
+        }
 
         Ok(serde_json::Value::Object(map))
     }
@@ -388,6 +565,7 @@ impl DeviceContext {
         // TODO: Is it possible to do this without writing to file; e.g. could
         // we instead write to a connection or something else?
         self._rendering = true;
+        let start = Instant::now();
         let image_path = r_task(|| unsafe {
             RFunction::from(".ps.graphics.renderPlot")
                 .param("id", plot_id)
@@ -404,6 +582,12 @@ impl DeviceContext {
             bail!("Failed to render plot with id {plot_id} due to: {error}.");
         });
 
+        event_log::record(
+            KernelEventKind::PlotRendered,
+            Some(start.elapsed()),
+            Some(format!("{plot_id} ({width}x{height} {format})")),
+        );
+
         // Read contents into bytes.
         let conn = File::open(image_path)?;
         let mut reader = BufReader::new(conn);
@@ -418,6 +602,10 @@ impl DeviceContext {
     }
 }
 
+/// How often the locator checks for interrupts and R tasks while it waits
+/// for a click
+const LOCATOR_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 static mut DEVICE_CONTEXT: Lazy<DeviceContext> = Lazy::new(|| DeviceContext::default());
 
 // TODO: This macro needs to be updated every time we introduce support
@@ -458,8 +646,8 @@ pub unsafe fn on_did_execute_request(
     comm_manager_tx: Sender<CommManagerEvent>,
     iopub_tx: Sender<IOPubMessage>,
     dynamic_plots: bool,
-) {
-    DEVICE_CONTEXT.on_did_execute_request(comm_manager_tx, iopub_tx, dynamic_plots);
+) -> Vec<String> {
+    DEVICE_CONTEXT.on_did_execute_request(comm_manager_tx, iopub_tx, dynamic_plots)
 }
 
 // NOTE: May be called when rendering a plot to file, since this is done by
@@ -518,6 +706,23 @@ unsafe extern "C" fn gd_new_page(dd: pGEcontext, dev: pDevDesc) {
     DEVICE_CONTEXT.new_page(dd, dev);
 }
 
+unsafe extern "C" fn gd_locator(x: *mut f64, y: *mut f64, _dev: pDevDesc) -> Rboolean {
+    trace!("gd_locator");
+
+    match DEVICE_CONTEXT.locator() {
+        Ok(Some((px, py))) => {
+            *x = px;
+            *y = py;
+            Rboolean_TRUE
+        },
+        Ok(None) => Rboolean_FALSE,
+        Err(err) => {
+            log::error!("Can't locate point: {err:?}");
+            Rboolean_FALSE
+        },
+    }
+}
+
 unsafe fn ps_graphics_device_impl() -> anyhow::Result<SEXP> {
     // TODO: Don't allow creation of more than one graphics device.
     // TODO: Allow customization of the graphics device here?
@@ -566,6 +771,11 @@ unsafe fn ps_graphics_device_impl() -> anyhow::Result<SEXP> {
 
         callbacks.newPage = (*device).newPage;
         (*device).newPage = Some(gd_new_page);
+
+        // The underlying file device has no graphical input. Clicks are
+        // forwarded by the frontend instead.
+        (*device).locator = Some(gd_locator);
+        (*device).haveLocator = 2;
     });
 
     Ok(R_NilValue)
@@ -596,3 +806,75 @@ unsafe extern "C" fn ps_graphics_event(_name: SEXP) -> anyhow::Result<SEXP> {
 
     Ok(Rf_ScalarLogical(1))
 }
+
+#[cfg(test)]
+mod tests {
+    use harp::exec::RFunction;
+    use harp::exec::RFunctionExt;
+
+    use crate::r_task;
+
+    #[test]
+    fn test_describe_recorded_plot() {
+        r_task(|| {
+            let plot = harp::parse_eval_base(
+                "local({
+                    pdf(NULL)
+                    dev.control('enable')
+                    plot(1:10, main = 'Growth', xlab = 'Time', ylab = 'Size')
+                    plot <- recordPlot()
+                    dev.off()
+                    plot
+                })",
+            )
+            .unwrap();
+
+            let description: String = RFunction::from(".ps.graphics.describeRecordedPlot")
+                .add(plot)
+                .call()
+                .unwrap()
+                .try_into()
+                .unwrap();
+
+            assert!(description.starts_with("Scatter plot titled \"Growth\"."));
+            assert!(description.contains("X axis \"Time\""));
+            assert!(description.contains("Y axis \"Size\""));
+        })
+    }
+
+    #[test]
+    fn test_locate_point() {
+        r_task(|| {
+            // Click on a render twice as wide as the device, e.g. in a wide
+            // plots pane. The margins of the render are narrower relative to
+            // its width.
+            let located: Vec<f64> = harp::parse_eval_global(
+                "local({
+                    res <- .ps.graphics.defaultResolution
+
+                    pdf(NULL, width = 14, height = 7)
+                    plot(1:10)
+                    x <- grconvertX(3, 'user', 'ndc')
+                    y <- grconvertY(8, 'user', 'ndc')
+                    dev.off()
+
+                    pdf(NULL, width = 7, height = 7)
+                    on.exit(dev.off())
+                    plot(1:10)
+                    point <- .ps.graphics.locatePoint(x, y, 14 * res, 7 * res)
+
+                    c(
+                        grconvertX(point[[1]], 'device', 'user'),
+                        grconvertY(point[[2]], 'device', 'user')
+                    )
+                })",
+            )
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+            assert!((located[0] - 3.0).abs() < 1e-6);
+            assert!((located[1] - 8.0).abs() < 1e-6);
+        })
+    }
+}
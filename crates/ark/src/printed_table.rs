@@ -0,0 +1,147 @@
+//
+// printed_table.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::table::df_info;
+use harp::utils::r_inherits;
+use libr::SEXP;
+use serde::Serialize;
+
+/// Maximum number of columns described in the metadata of a printed table.
+/// Wider tables are only partially described, consoles don't show more
+/// columns than that anyway.
+const MAX_COLUMNS: i32 = 100;
+
+/// Description of a tibble or data.table printed at top level. It is sent
+/// along with the text output in the metadata of the `execute_result`
+/// message, so that the console can render type badges in the column headers
+/// and offer to open the table in the data viewer.
+#[derive(Debug, Serialize)]
+pub struct PrintedTable {
+    /// Either `tbl_df` or `data.table`
+    pub class: String,
+
+    pub num_rows: i32,
+
+    pub num_columns: i32,
+
+    /// The first `MAX_COLUMNS` columns of the table
+    pub columns: Vec<PrintedColumn>,
+
+    /// Passed to the `view_printed_table` method of the UI comm to open the
+    /// table in the data viewer
+    pub view_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrintedColumn {
+    pub name: String,
+
+    /// Abbreviated type, as shown in the header of tibbles, e.g. `int`
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+fn table_class(x: SEXP) -> Option<&'static str> {
+    if r_inherits(x, "tbl_df") {
+        Some("tbl_df")
+    } else if r_inherits(x, "data.table") {
+        Some("data.table")
+    } else {
+        None
+    }
+}
+
+/// Describe the value printed by an execution, if it is a tibble or a
+/// data.table. The value is kept on the R side so that it can be viewed later
+/// on, even once `.Last.value` has moved on.
+///
+/// - `code`: The code of the execution, used to title the data viewer and to
+///   watch the printed variable, if any.
+pub fn printed_table(x: SEXP, exec_count: u32, code: &str) -> anyhow::Result<Option<PrintedTable>> {
+    let Some(class) = table_class(x) else {
+        return Ok(None);
+    };
+
+    let info = df_info(x)?;
+    let num_described = info.dims.num_cols.min(MAX_COLUMNS);
+
+    let types: Vec<String> = RFunction::from(".ps.format.columnTypes")
+        .add(x)
+        .add(num_described)
+        .call()?
+        .try_into()?;
+
+    let columns = types
+        .into_iter()
+        .enumerate()
+        .map(|(i, type_name)| PrintedColumn {
+            name: info.col_names.get_unchecked(i as isize).unwrap_or_default(),
+            type_name,
+        })
+        .collect();
+
+    let view_id = format!("execution-{exec_count}");
+    RFunction::from(".ps.format.stashTable")
+        .add(x)
+        .add(view_id.as_str())
+        .add(code)
+        .call()?;
+
+    Ok(Some(PrintedTable {
+        class: String::from(class),
+        num_rows: info.dims.num_rows,
+        num_columns: info.dims.num_cols,
+        columns,
+        view_id,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use harp::exec::RFunction;
+    use harp::exec::RFunctionExt;
+
+    use crate::printed_table::printed_table;
+    use crate::r_task;
+
+    #[test]
+    fn test_printed_table() {
+        r_task(|| {
+            let df = harp::parse_eval_base(
+                "structure(
+                    data.frame(x = 1:2, y = c('a', 'b'), z = Sys.Date() + 0:1),
+                    class = c('tbl_df', 'tbl', 'data.frame')
+                )",
+            )
+            .unwrap();
+
+            let table = printed_table(df.sexp, 3, "df").unwrap().unwrap();
+            assert_eq!(table.class, "tbl_df");
+            assert_eq!(table.num_rows, 2);
+            assert_eq!(table.num_columns, 3);
+            assert_eq!(table.view_id, "execution-3");
+
+            let names: Vec<&str> = table.columns.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, vec!["x", "y", "z"]);
+            let types: Vec<&str> = table.columns.iter().map(|c| c.type_name.as_str()).collect();
+            assert_eq!(types, vec!["int", "chr", "date"]);
+
+            // The table can be retrieved once printed
+            let stashed = RFunction::from(".ps.format.stashedTable")
+                .add("execution-3")
+                .call()
+                .unwrap();
+            assert!(harp::utils::r_is_data_frame(stashed.sexp));
+
+            // Plain data frames aren't described
+            let df = harp::parse_eval_base("data.frame(x = 1)").unwrap();
+            assert!(printed_table(df.sexp, 4, "df").unwrap().is_none());
+        })
+    }
+}
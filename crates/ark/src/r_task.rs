@@ -13,11 +13,17 @@ use std::sync::OnceLock;
 use std::time::Duration;
 
 use crossbeam::channel::bounded;
+use crossbeam::channel::Receiver;
 use crossbeam::channel::Sender;
+use stdext::cancellation::CancellationToken;
 use uuid::Uuid;
 
 use crate::fixtures::r_test_init;
 use crate::interface::RMain;
+use crate::task_queue;
+use crate::task_queue::TaskKind;
+use crate::teardown;
+use crate::wait;
 
 // Compared to `futures::BoxFuture`, this doesn't require the future to be Send.
 // We don't need this bound since the executor runs on only on the R thread
@@ -69,6 +75,9 @@ pub struct RTaskStartInfo {
 
     /// Tracing span for the task
     pub span: tracing::Span,
+
+    /// Entry of the task in `task_queue`
+    pub queue_id: u64,
 }
 
 impl RTask {
@@ -95,7 +104,12 @@ impl std::task::Wake for RTaskWaker {
 }
 
 impl RTaskStartInfo {
-    pub(crate) fn new(idle: bool) -> Self {
+    /// Records the task in the task queue, with the caller as origin
+    #[track_caller]
+    pub(crate) fn new(kind: TaskKind) -> Self {
+        let idle = kind == TaskKind::AsyncIdle;
+        let queue_id = task_queue::enqueue(kind, std::panic::Location::caller());
+
         let thread = std::thread::current();
         let thread_id = thread.id();
         let thread_name = thread
@@ -113,6 +127,7 @@ impl RTaskStartInfo {
             start_time,
             elapsed_time: None,
             span,
+            queue_id,
         }
     }
 
@@ -139,6 +154,7 @@ impl RTaskStartInfo {
 // thread. See also `Crossbeam::thread::ScopedThreadBuilder` (from which
 // `r_task()` is adapted) for a similar approach.
 
+#[track_caller]
 pub fn r_task<'env, F, T>(f: F) -> T
 where
     F: FnOnce() -> T,
@@ -147,7 +163,7 @@ where
 {
     // Escape hatch for unit tests
     if stdext::IS_TESTING {
-        let _lock = unsafe { harp::fixtures::R_TEST_LOCK.lock() };
+        let _lock = harp::fixtures::R_TEST_LOCK.lock();
         r_test_init();
         return f();
     }
@@ -168,8 +184,11 @@ where
 
     {
         let result = Arc::clone(&result);
+        // Tasks stop at their cancellation points once the frontend goes
+        // away, see `teardown`
         let closure = move || {
-            *result.lock().unwrap() = Some(f());
+            let value = harp::cancellation::with_cancellation(&teardown::SHUTDOWN, f);
+            *result.lock().unwrap() = Some(value);
         };
 
         // Move `f` to heap and erase its lifetime so we can send it to
@@ -187,12 +206,12 @@ where
         let task = RTask::Sync(RTaskSync {
             fun: closure,
             status_tx: Some(status_tx),
-            start_info: RTaskStartInfo::new(false),
+            start_info: RTaskStartInfo::new(TaskKind::Sync),
         });
         get_tasks_interrupt_tx().send(task).unwrap();
 
         // Block until we get the signal that the task has started
-        let status = status_rx.recv().unwrap();
+        let status = wait::R_TASK.recv(&status_rx).unwrap();
 
         let RTaskStatus::Started = status else {
             let trace = std::backtrace::Backtrace::force_capture();
@@ -204,7 +223,7 @@ where
         };
 
         // Block until task was completed or timed out
-        let status = status_rx.recv().unwrap();
+        let status = wait::R_TASK.recv(&status_rx).unwrap();
 
         let RTaskStatus::Finished(status) = status else {
             let trace = std::backtrace::Backtrace::force_capture();
@@ -232,6 +251,156 @@ where
     return result.lock().unwrap().take().unwrap();
 }
 
+/// Like `r_task()`, with `token` as a cancellation point for the work done
+/// by `f`. Long running loops, e.g. formatting the values of a large vector,
+/// fail with `harp::Error::Cancelled` once `token` is cancelled, see
+/// `harp::cancellation`. Comms pass their `closed` token so that their work
+/// stops as soon as they are closed.
+///
+/// If `token` is cancelled while R is still busy with something else, we
+/// stop waiting for the task, `f` is never called, and `T::cancelled()` is
+/// returned.
+#[track_caller]
+pub fn r_task_cancellable<'env, F, T>(token: CancellationToken, f: F) -> T
+where
+    F: FnOnce() -> T,
+    F: 'env + Send,
+    T: 'env + Send + Cancelled,
+{
+    let f = {
+        let token = token.clone();
+        move || harp::cancellation::with_cancellation(&token, f)
+    };
+
+    if stdext::IS_TESTING || RMain::on_main_thread() {
+        return r_task(f);
+    }
+
+    let task = r_task_or_give_up(f, |status_rx| {
+        matches!(
+            wait::R_TASK.recv_cancellable(status_rx, &token),
+            Err(wait::WaitError::Cancelled { .. })
+        )
+    });
+
+    task.unwrap_or_else(T::cancelled)
+}
+
+/// Value of tasks given up on before they started, see `r_task_cancellable()`
+pub trait Cancelled {
+    fn cancelled() -> Self;
+}
+
+impl Cancelled for () {
+    fn cancelled() -> Self {}
+}
+
+impl<T> Cancelled for anyhow::Result<T> {
+    fn cancelled() -> Self {
+        Err(harp::Error::Cancelled.into())
+    }
+}
+
+impl<T> Cancelled for harp::Result<T> {
+    fn cancelled() -> Self {
+        Err(harp::Error::Cancelled)
+    }
+}
+
+/// Like `r_task()`, but gives up if the R thread hasn't picked up the task
+/// after `timeout`, e.g. because it's busy evaluating code that doesn't check
+/// for interrupts. In that case `f` is never called and `None` is returned.
+/// Once `f` has started, this waits for it to finish like `r_task()`.
+#[track_caller]
+pub(crate) fn r_task_timeout<'env, F, T>(timeout: Duration, f: F) -> Option<T>
+where
+    F: FnOnce() -> T,
+    F: 'env + Send,
+    T: 'env + Send,
+{
+    if stdext::IS_TESTING || RMain::on_main_thread() {
+        return Some(r_task(f));
+    }
+
+    r_task_or_give_up(f, |status_rx| status_rx.recv_timeout(timeout).is_err())
+}
+
+/// Run `f` on the R thread unless `give_up()` returns `true` while waiting
+/// for the task to start, in which case `f` is never called and `None` is
+/// returned. Once `f` has started, this waits for it to finish.
+#[track_caller]
+fn r_task_or_give_up<'env, F, T>(
+    f: F,
+    give_up: impl FnOnce(&Receiver<RTaskStatus>) -> bool,
+) -> Option<T>
+where
+    F: FnOnce() -> T,
+    F: 'env + Send,
+    T: 'env + Send,
+{
+    let result = SharedOption::default();
+
+    let closure = {
+        let result = Arc::clone(&result);
+        move || {
+            let value = harp::cancellation::with_cancellation(&teardown::SHUTDOWN, f);
+            *result.lock().unwrap() = Some(value);
+        }
+    };
+
+    // Erase the lifetime of `f` as in `r_task()`. The closure is shared with
+    // the task, which takes it when it starts. If we give up first, we take it
+    // back and drop it here, so it never outlives this scope.
+    let closure: Box<dyn FnOnce() + Send + 'env> = Box::new(closure);
+    let closure: Box<dyn FnOnce() + Send + 'static> = unsafe { std::mem::transmute(closure) };
+    let slot = Arc::new(Mutex::new(Some(closure)));
+
+    // Buffered so that the R thread doesn't block on a caller that gave up
+    let (status_tx, status_rx) = bounded::<RTaskStatus>(2);
+
+    let task = RTask::Sync(RTaskSync {
+        fun: Box::new({
+            let slot = Arc::clone(&slot);
+            move || {
+                // Release the lock before running the closure
+                let closure = slot.lock().unwrap().take();
+                if let Some(closure) = closure {
+                    closure();
+                }
+            }
+        }),
+        status_tx: Some(status_tx),
+        start_info: RTaskStartInfo::new(TaskKind::Sync),
+    });
+    get_tasks_interrupt_tx().send(task).unwrap();
+
+    if give_up(&status_rx) {
+        // The task may have started in the meantime, in which case the slot
+        // is empty and we wait for it to finish
+        if slot.lock().unwrap().take().is_some() {
+            return None;
+        }
+    }
+
+    loop {
+        match wait::R_TASK.recv(&status_rx).unwrap() {
+            RTaskStatus::Started => continue,
+            RTaskStatus::Finished(Ok(())) => break,
+            RTaskStatus::Finished(Err(err)) => {
+                let trace = std::backtrace::Backtrace::force_capture();
+                panic!(
+                    "While running task: {err:?}\n\
+                     Backtrace of calling thread:\n\n\
+                     {trace}"
+                );
+            },
+        }
+    }
+
+    result.lock().unwrap().take()
+}
+
+#[track_caller]
 pub(crate) fn spawn_idle<F, Fut>(fun: F)
 where
     F: FnOnce() -> Fut + 'static + Send,
@@ -240,6 +409,7 @@ where
     spawn_ext(fun, true)
 }
 
+#[track_caller]
 pub(crate) fn spawn_interrupt<F, Fut>(fun: F)
 where
     F: FnOnce() -> Fut + 'static + Send,
@@ -248,6 +418,7 @@ where
     spawn_ext(fun, false)
 }
 
+#[track_caller]
 fn spawn_ext<F, Fut>(fun: F, only_idle: bool)
 where
     F: FnOnce() -> Fut + 'static + Send,
@@ -255,7 +426,7 @@ where
 {
     // Escape hatch for unit tests
     if stdext::IS_TESTING {
-        let _lock = unsafe { harp::fixtures::R_TEST_LOCK.lock() };
+        let _lock = harp::fixtures::R_TEST_LOCK.lock();
         futures::executor::block_on(fun());
         return;
     }
@@ -272,7 +443,11 @@ where
     let task = RTask::Async(RTaskAsync {
         fut: Box::pin(fun()) as BoxFuture<'static, ()>,
         tasks_tx: tasks_tx.clone(),
-        start_info: RTaskStartInfo::new(only_idle),
+        start_info: RTaskStartInfo::new(if only_idle {
+            TaskKind::AsyncIdle
+        } else {
+            TaskKind::AsyncInterrupt
+        }),
     });
 
     tasks_tx.send(task).unwrap();
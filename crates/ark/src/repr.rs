@@ -0,0 +1,215 @@
+//
+// repr.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use amalthea::socket::iopub::IOPubMessage;
+use amalthea::wire::display_data::DisplayData;
+use amalthea::wire::update_display_data::TransientValue;
+use amalthea::wire::update_display_data::UpdateDisplayData;
+use base64::engine::general_purpose;
+use base64::Engine;
+use harp::exec::RFunction;
+use harp::exec::RFunctionExt;
+use harp::object::RObject;
+use harp::utils::r_is_null;
+use harp::utils::r_is_object;
+use libr::R_NilValue;
+use libr::RAWSXP;
+use libr::SEXP;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::interface::RMain;
+use crate::modules::ARK_ENVS;
+
+/// Representations larger than this many bytes, once serialised, are dropped
+/// so that a careless method can't flood the frontend
+pub const MAX_REPR_SIZE: usize = 1024 * 1024;
+
+/// Rich representations of `x` by MIME type, from the methods of
+/// `.ps.repr_mimebundle()`. Only objects are represented, there are no methods
+/// for base types.
+pub fn repr_mimebundle(x: SEXP) -> anyhow::Result<Map<String, Value>> {
+    if !r_is_object(x) {
+        return Ok(Map::new());
+    }
+
+    let bundle = RFunction::new("", "repr_bundle")
+        .add(x)
+        .call_in(ARK_ENVS.positron_ns)?;
+
+    Ok(cap_bundle(bundle_to_json(bundle)?))
+}
+
+/// Convert a bundle of representations to JSON. Binary representations, e.g.
+/// `image/png`, are raw vectors and are encoded in base64 as Jupyter expects.
+fn bundle_to_json(bundle: RObject) -> anyhow::Result<Value> {
+    let Some(names) = bundle.names() else {
+        return Ok(Value::Null);
+    };
+
+    let mut out = Map::new();
+
+    for (i, mime_type) in names.into_iter().enumerate() {
+        let Some(mime_type) = mime_type else {
+            continue;
+        };
+
+        let value = bundle.vector_elt(i as isize)?;
+        let value = if value.kind() == RAWSXP {
+            let bytes: Vec<u8> = (&value).try_into()?;
+            Value::String(general_purpose::STANDARD.encode(bytes))
+        } else {
+            value.try_to_json()?
+        };
+
+        out.insert(mime_type, value);
+    }
+
+    Ok(Value::Object(out))
+}
+
+/// Drop the representations that are too large. Anything but a JSON object
+/// is an empty bundle.
+fn cap_bundle(bundle: Value) -> Map<String, Value> {
+    let Value::Object(bundle) = bundle else {
+        return Map::new();
+    };
+
+    bundle
+        .into_iter()
+        .filter(|(mime_type, value)| {
+            let size = serde_json::to_string(value).map_or(usize::MAX, |value| value.len());
+            if size > MAX_REPR_SIZE {
+                log::warn!("Dropping '{mime_type}' representation of {size} bytes");
+                return false;
+            }
+            true
+        })
+        .collect()
+}
+
+/// Send a `display_data` message, for `ark.display()`. With `update`, send an
+/// `update_display_data` message instead, which replaces the output
+/// previously displayed with the same `display_id`.
+#[harp::register]
+pub unsafe extern "C" fn ps_display_data(
+    bundle: SEXP,
+    metadata: SEXP,
+    display_id: SEXP,
+    update: SEXP,
+) -> anyhow::Result<SEXP> {
+    let data = Value::Object(cap_bundle(bundle_to_json(RObject::view(bundle))?));
+
+    let metadata = match RObject::view(metadata).try_to_json()? {
+        Value::Object(metadata) => Value::Object(metadata),
+        _ => Value::Object(Map::new()),
+    };
+
+    let display_id: Option<String> = if r_is_null(display_id) {
+        None
+    } else {
+        Some(RObject::view(display_id).try_into()?)
+    };
+    let update: bool = RObject::view(update).try_into()?;
+
+    let message = match (display_id, update) {
+        (Some(display_id), true) => IOPubMessage::UpdateDisplayData(UpdateDisplayData {
+            data,
+            metadata,
+            transient: TransientValue {
+                display_id,
+                data: None,
+            },
+        }),
+        (None, true) => {
+            return Err(anyhow::anyhow!("Can't update a display without `display_id`"));
+        },
+        (display_id, false) => {
+            let mut transient = Map::new();
+            if let Some(display_id) = display_id {
+                transient.insert(String::from("display_id"), Value::String(display_id));
+            }
+            IOPubMessage::DisplayData(DisplayData {
+                data,
+                metadata,
+                transient: Value::Object(transient),
+            })
+        },
+    };
+    RMain::get().get_iopub_tx().send(message)?;
+
+    Ok(R_NilValue)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::r_task;
+    use crate::repr::bundle_to_json;
+    use crate::repr::cap_bundle;
+    use crate::repr::repr_mimebundle;
+    use crate::repr::MAX_REPR_SIZE;
+
+    #[test]
+    fn test_repr_mimebundle() {
+        r_task(|| {
+            harp::parse_eval_global(
+                ".ps.repr_mimebundle.ark_test_repr <- function(x, ...) list(
+                     'text/html' = c('<b>', 'x', '</b>'),
+                     'application/vnd.test+json' = list(a = 1L),
+                     'not a type' = 'dropped',
+                     'text/markdown' = 1
+                 )",
+            )
+            .unwrap();
+
+            let x = harp::parse_eval_global("structure(1, class = 'ark_test_repr')").unwrap();
+            let bundle = repr_mimebundle(x.sexp).unwrap();
+
+            assert_eq!(bundle.len(), 2);
+            assert_eq!(bundle["text/html"], json!("<b>\nx\n</b>"));
+            assert_eq!(bundle["application/vnd.test+json"], json!({ "a": 1 }));
+
+            // Values without methods have no representations
+            let x = harp::parse_eval_global("structure(1, class = 'ark_test_no_repr')").unwrap();
+            assert!(repr_mimebundle(x.sexp).unwrap().is_empty());
+
+            let x = harp::parse_eval_global("1").unwrap();
+            assert!(repr_mimebundle(x.sexp).unwrap().is_empty());
+
+            harp::parse_eval_global("rm(.ps.repr_mimebundle.ark_test_repr)").unwrap();
+        });
+    }
+
+    #[test]
+    fn test_repr_bundle_to_json() {
+        r_task(|| {
+            let bundle = harp::parse_eval_base(
+                "list('image/png' = as.raw(c(0x89, 0x50, 0x4e, 0x47)), 'text/plain' = 'x')",
+            )
+            .unwrap();
+            let bundle = bundle_to_json(bundle).unwrap();
+
+            assert_eq!(bundle["image/png"], json!("iVBORw=="));
+            assert_eq!(bundle["text/plain"], json!("x"));
+
+            let bundle = harp::parse_eval_base("NULL").unwrap();
+            assert_eq!(bundle_to_json(bundle).unwrap(), json!(null));
+        });
+    }
+
+    #[test]
+    fn test_repr_cap_bundle() {
+        let large = "x".repeat(MAX_REPR_SIZE + 1);
+        let bundle = cap_bundle(json!({ "text/html": large, "text/markdown": "**x**" }));
+        assert_eq!(bundle.len(), 1);
+        assert_eq!(bundle["text/markdown"], json!("**x**"));
+
+        assert!(cap_bundle(json!(null)).is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+//
+// session_access.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Whether clients may change the session. Read-only sessions (`--read-only`)
+// don't evaluate the code of execute requests, and reject the comm RPCs and
+// LSP commands that change the session, e.g. deleting variables. Comms that
+// only look at the session, like the LSP, help, and data viewers, keep
+// working.
+//
+// In shared sessions (`--shared-session ID`), the client with the given
+// Jupyter session ID drives the session. The other clients observe it: they
+// receive IOPub broadcasts, but their execute requests and the comm RPCs that
+// change the session are rejected. Clients are identified by the `session`
+// field of the headers of their messages, so a driver that reconnects with
+// the same session ID keeps driving the session.
+
+use std::sync::OnceLock;
+
+use amalthea::comm::comm_access;
+use amalthea::comm::comm_access::CommRpc;
+use serde_json::Value;
+
+use crate::i18n::tr;
+
+/// RPCs that change the session, by comm name and method. Besides the RPCs
+/// that change R objects, this includes those that write files on the host
+/// and those that answer R while it waits for input, like plot clicks for
+/// `locator()`.
+const MUTATING_RPCS: &[(&str, &str)] = &[
+    ("positron.dataExplorer", "export_object"),
+    ("positron.plot", "cancel_locate"),
+    ("positron.plot", "click"),
+    ("positron.ui", "call_method"),
+    ("positron.variables", "clear"),
+    ("positron.variables", "delete"),
+    ("positron.variables", "export_object"),
+];
+
+static SESSION_ACCESS: OnceLock<SessionAccess> = OnceLock::new();
+
+#[derive(Debug, Default)]
+pub(crate) struct SessionAccess {
+    pub read_only: bool,
+
+    /// The Jupyter session ID of the client driving a shared session. `None`
+    /// if the session isn't shared.
+    pub driver_session: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AccessDenied {
+    ReadOnly,
+    Observer,
+}
+
+impl AccessDenied {
+    /// Message of the error sent to a client whose execute request is
+    /// rejected
+    pub(crate) fn execution_message(&self) -> &'static str {
+        match self {
+            AccessDenied::ReadOnly => tr("read_only_session"),
+            AccessDenied::Observer => tr("observer_session"),
+        }
+    }
+
+    /// Message of the error sent to a client whose RPC or command is rejected
+    pub(crate) fn change_message(&self) -> &'static str {
+        match self {
+            AccessDenied::ReadOnly => tr("read_only_change"),
+            AccessDenied::Observer => tr("observer_change"),
+        }
+    }
+}
+
+/// Set up the access of clients to the session and install the policy of the
+/// comm manager. Must be called once, before the comms connect.
+pub(crate) fn initialize(access: SessionAccess) {
+    if SESSION_ACCESS.set(access).is_err() {
+        log::error!("Session access can only be initialized once");
+        return;
+    }
+    comm_access::set_policy(Box::new(check_comm_rpc));
+}
+
+fn session_access() -> &'static SessionAccess {
+    SESSION_ACCESS.get_or_init(SessionAccess::default)
+}
+
+pub(crate) fn is_read_only() -> bool {
+    session_access().read_only
+}
+
+/// Whether the session may be changed, e.g. by an LSP command. LSP clients
+/// aren't Jupyter clients, so only read-only sessions are checked.
+pub(crate) fn check_change() -> Result<(), AccessDenied> {
+    session_access().check_change()
+}
+
+/// Whether the Jupyter client identified by `session` may change the session,
+/// e.g. by executing code
+pub(crate) fn check_client(session: &str) -> Result<(), AccessDenied> {
+    session_access().check_client(session)
+}
+
+impl SessionAccess {
+    fn check_change(&self) -> Result<(), AccessDenied> {
+        if self.read_only {
+            return Err(AccessDenied::ReadOnly);
+        }
+        Ok(())
+    }
+
+    fn check_client(&self, session: &str) -> Result<(), AccessDenied> {
+        self.check_change()?;
+
+        match &self.driver_session {
+            Some(driver) if driver != session => Err(AccessDenied::Observer),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_comm_rpc(&self, rpc: &CommRpc) -> Result<(), String> {
+        if !is_mutating_rpc(rpc.comm_name, rpc.data) {
+            return Ok(());
+        }
+        self.check_client(&rpc.header.session)
+            .map_err(|err| String::from(err.change_message()))
+    }
+}
+
+fn check_comm_rpc(rpc: &CommRpc) -> Result<(), String> {
+    session_access().check_comm_rpc(rpc)
+}
+
+fn is_mutating_rpc(comm_name: &str, data: &Value) -> bool {
+    let Some(method) = data.get("method").and_then(Value::as_str) else {
+        return false;
+    };
+
+    MUTATING_RPCS
+        .iter()
+        .any(|(name, mutating)| *name == comm_name && *mutating == method)
+}
+
+#[cfg(test)]
+mod tests {
+    use amalthea::comm::comm_access::CommRpc;
+    use amalthea::wire::header::JupyterHeader;
+    use serde_json::json;
+
+    use crate::session_access::is_mutating_rpc;
+    use crate::session_access::AccessDenied;
+    use crate::session_access::SessionAccess;
+    use crate::session_access::MUTATING_RPCS;
+
+    #[test]
+    fn test_is_mutating_rpc() {
+        let clear = json!({ "method": "clear", "params": { "include_hidden_objects": false } });
+        assert!(is_mutating_rpc("positron.variables", &clear));
+        assert!(!is_mutating_rpc("positron.help", &clear));
+
+        let list = json!({ "method": "list", "params": {} });
+        assert!(!is_mutating_rpc("positron.variables", &list));
+
+        assert!(!is_mutating_rpc("positron.ui", &json!({})));
+
+        // Viewing a data frame doesn't change the session, exporting it does
+        let filters = json!({ "method": "set_row_filters", "params": { "filters": [] } });
+        assert!(!is_mutating_rpc("positron.dataExplorer", &filters));
+        let export = json!({ "method": "export_object", "params": { "file": "df.csv" } });
+        assert!(is_mutating_rpc("positron.dataExplorer", &export));
+    }
+
+    #[test]
+    fn test_mutating_rpcs_are_refused() {
+        let read_only = SessionAccess {
+            read_only: true,
+            driver_session: None,
+        };
+        let shared = SessionAccess {
+            read_only: false,
+            driver_session: Some(String::from("driver")),
+        };
+
+        let header = |session: &str| {
+            JupyterHeader::create(
+                String::from("comm_msg"),
+                String::from(session),
+                String::from("user"),
+            )
+        };
+        let driver = header("driver");
+        let observer = header("observer");
+
+        for &(comm_name, method) in MUTATING_RPCS {
+            let data = json!({ "method": method, "params": {} });
+            let from_driver = CommRpc {
+                comm_name,
+                header: &driver,
+                data: &data,
+            };
+            let from_observer = CommRpc {
+                comm_name,
+                header: &observer,
+                data: &data,
+            };
+
+            assert!(read_only.check_comm_rpc(&from_driver).is_err());
+            assert!(shared.check_comm_rpc(&from_observer).is_err());
+            assert!(shared.check_comm_rpc(&from_driver).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_check_client() {
+        let access = SessionAccess::default();
+        assert_eq!(access.check_client("any"), Ok(()));
+
+        let access = SessionAccess {
+            read_only: false,
+            driver_session: Some(String::from("driver")),
+        };
+        assert_eq!(access.check_client("driver"), Ok(()));
+        assert_eq!(access.check_client("observer"), Err(AccessDenied::Observer));
+        assert_eq!(access.check_change(), Ok(()));
+
+        // Nobody drives a read-only session
+        let access = SessionAccess {
+            read_only: true,
+            driver_session: Some(String::from("driver")),
+        };
+        assert_eq!(access.check_client("driver"), Err(AccessDenied::ReadOnly));
+    }
+}
@@ -5,8 +5,13 @@
 //
 //
 
+use std::collections::HashSet;
+
 use amalthea::comm::comm_channel::Comm;
 use amalthea::comm::event::CommManagerEvent;
+use amalthea::comm::protocol;
+use amalthea::cursor;
+use amalthea::language::shell_handler::ShellContext;
 use amalthea::language::shell_handler::ShellHandler;
 use amalthea::socket::comm::CommSocket;
 use amalthea::socket::stdin::StdInRequest;
@@ -24,7 +29,6 @@ use amalthea::wire::kernel_info_reply::KernelInfoReply;
 use amalthea::wire::kernel_info_request::KernelInfoRequest;
 use amalthea::wire::language_info::LanguageInfo;
 use amalthea::wire::language_info::LanguageInfoPositron;
-use amalthea::wire::originator::Originator;
 use async_trait::async_trait;
 use bus::BusReader;
 use crossbeam::channel::unbounded;
@@ -37,16 +41,30 @@ use harp::ParseResult;
 use log::*;
 use serde_json::json;
 use stdext::unwrap;
+use tower_lsp::lsp_types::CompletionItem;
+use tower_lsp::lsp_types::CompletionItemKind;
+use tower_lsp::lsp_types::CompletionTextEdit;
+use tower_lsp::lsp_types::InsertTextFormat;
+use tree_sitter::Point;
 
+use crate::data_import;
 use crate::help::r_help::RHelp;
 use crate::help_proxy;
+use crate::inspect::r_inspect;
 use crate::interface::KernelInfo;
 use crate::interface::RMain;
+use crate::lsp::completions::provide_completions;
+use crate::lsp::document_context::DocumentContext;
+use crate::lsp::documents::Document;
+use crate::lsp::state::WorldState;
 use crate::r_task;
+use crate::r_task::r_task_cancellable;
 use crate::request::KernelRequest;
 use crate::request::RRequest;
+use crate::task_queue;
 use crate::ui::UiComm;
 use crate::variables::r_variables::RVariables;
+use crate::variables::r_variables::VARIABLES_PROTOCOL_VERSIONS;
 
 pub struct Shell {
     comm_manager_tx: Sender<CommManagerEvent>,
@@ -92,12 +110,19 @@ impl Shell {
             }),
             Ok(ParseResult::Incomplete) => Ok(IsCompleteReply {
                 status: IsComplete::Incomplete,
-                indent: String::from("+"),
+                indent: continuation_indent(&req.code),
             }),
-            Err(_) | Ok(ParseResult::SyntaxError { .. }) => Ok(IsCompleteReply {
+            Ok(ParseResult::SyntaxError { .. }) => Ok(IsCompleteReply {
                 status: IsComplete::Invalid,
                 indent: String::from(""),
             }),
+            Err(err) => {
+                log::warn!("Can't parse code to test its completeness: {err:?}");
+                Ok(IsCompleteReply {
+                    status: IsComplete::Unknown,
+                    indent: String::from(""),
+                })
+            },
         }
     }
 }
@@ -106,6 +131,7 @@ impl Shell {
 impl ShellHandler for Shell {
     async fn handle_info_request(
         &mut self,
+        _ctx: &ShellContext,
         _req: &KernelInfoRequest,
     ) -> amalthea::Result<KernelInfoReply> {
         // Wait here for kernel initialization if it hasn't completed. This is
@@ -136,6 +162,7 @@ impl ShellHandler for Shell {
             positron: Some(LanguageInfoPositron {
                 input_prompt: kernel_info.input_prompt.clone(),
                 continuation_prompt: kernel_info.continuation_prompt.clone(),
+                environment_fingerprint: kernel_info.environment_fingerprint.clone(),
             }),
         };
         Ok(KernelInfoReply {
@@ -149,21 +176,33 @@ impl ShellHandler for Shell {
 
     async fn handle_complete_request(
         &self,
-        _req: &CompleteRequest,
+        ctx: &ShellContext,
+        req: &CompleteRequest,
     ) -> amalthea::Result<CompleteReply> {
-        // No matches in this toy implementation.
-        Ok(CompleteReply {
-            matches: Vec::new(),
-            status: Status::Ok,
-            cursor_start: 0,
-            cursor_end: 0,
-            metadata: json!({}),
-        })
+        // Completions of large objects can take a while, give up if the
+        // frontend interrupts us. Other Shell requests are handled meanwhile.
+        let reply = r_task_cancellable(ctx.cancellation.clone(), || r_complete(req));
+        let reply = reply.unwrap_or_else(|err| {
+            log::error!("Can't complete code: {err:?}");
+
+            // Replace nothing
+            let cursor_pos = clamp_cursor_pos(&req.code, req.cursor_pos);
+            CompleteReply {
+                matches: Vec::new(),
+                status: Status::Ok,
+                cursor_start: cursor_pos,
+                cursor_end: cursor_pos,
+                metadata: json!({}),
+            }
+        });
+
+        Ok(reply)
     }
 
     /// Handle a request to test code for completion.
     async fn handle_is_complete_request(
         &self,
+        _ctx: &ShellContext,
         req: &IsCompleteRequest,
     ) -> amalthea::Result<IsCompleteReply> {
         r_task(|| self.r_handle_is_complete_request(req))
@@ -173,15 +212,17 @@ impl ShellHandler for Shell {
     /// for processing.
     async fn handle_execute_request(
         &mut self,
-        originator: Originator,
+        ctx: &ShellContext,
         req: &ExecuteRequest,
     ) -> amalthea::Result<ExecuteReply> {
         let (response_tx, response_rx) = unbounded::<amalthea::Result<ExecuteReply>>();
         let mut req_clone = req.clone();
         req_clone.code = convert_line_endings(&req_clone.code, LineEnding::Posix);
+        let queue_id =
+            task_queue::enqueue_execution(&req_clone.code, &ctx.originator.header.msg_id);
         if let Err(err) = self.r_request_tx.send(RRequest::ExecuteCode(
             req_clone.clone(),
-            originator,
+            ctx.originator.clone(),
             response_tx,
         )) {
             warn!(
@@ -192,31 +233,46 @@ impl ShellHandler for Shell {
 
         trace!("Code sent to R: {}", req_clone.code);
         let result = response_rx.recv().unwrap();
+        task_queue::finish(queue_id);
 
         result
     }
 
     /// Handles an introspection request
-    async fn handle_inspect_request(&self, req: &InspectRequest) -> amalthea::Result<InspectReply> {
-        let data = match req.code.as_str() {
-            "err" => {
-                json!({"text/plain": "This generates an error!"})
-            },
-            "teapot" => {
-                json!({"text/plain": "This is clearly a teapot."})
-            },
-            _ => serde_json::Value::Null,
+    async fn handle_inspect_request(
+        &self,
+        ctx: &ShellContext,
+        req: &InspectRequest,
+    ) -> amalthea::Result<InspectReply> {
+        let description = r_task_cancellable(ctx.cancellation.clone(), || r_inspect(req));
+        let description = description.unwrap_or_else(|err| {
+            log::error!("Can't inspect code: {err:?}");
+            None
+        });
+
+        let data = match &description {
+            Some(description) => json!({
+                "text/plain": description,
+                "text/markdown": description,
+            }),
+            None => json!({}),
         };
+
         Ok(InspectReply {
             status: Status::Ok,
-            found: data != serde_json::Value::Null,
+            found: description.is_some(),
             data,
             metadata: json!({}),
         })
     }
 
     /// Handles a request to open a new comm channel
-    async fn handle_comm_open(&self, target: Comm, comm: CommSocket) -> amalthea::Result<bool> {
+    async fn handle_comm_open(
+        &self,
+        _ctx: &ShellContext,
+        target: Comm,
+        comm: CommSocket,
+    ) -> amalthea::Result<bool> {
         match target {
             Comm::Variables => handle_comm_open_variables(comm, self.comm_manager_tx.clone()),
             Comm::Ui => handle_comm_open_ui(
@@ -225,15 +281,251 @@ impl ShellHandler for Shell {
                 self.kernel_request_tx.clone(),
             ),
             Comm::Help => handle_comm_open_help(comm),
+            Comm::DataImport => handle_comm_open_data_import(comm),
             _ => Ok(false),
         }
     }
 }
 
+/// Completions for frontends that don't use the LSP, e.g. Jupyter notebooks.
+/// Uses the same engine as the LSP, on a one-off document.
+fn r_complete(req: &CompleteRequest) -> anyhow::Result<CompleteReply> {
+    let code = req.code.as_str();
+
+    let document = Document::new(code, None);
+    let (row, column) = cursor::cursor_pos_to_point(code, req.cursor_pos);
+    let context = DocumentContext::new(&document, Point::new(row, column), None);
+
+    let completions = provide_completions(&context, &WorldState::default())?;
+
+    // Items with a text edit tell us which range they replace. Other items
+    // replace the identifier before the cursor.
+    let cursor_end = clamp_cursor_pos(code, req.cursor_pos);
+    let identifier_start = identifier_start(code, cursor_end);
+
+    let items: Vec<(u32, CompletionItem)> = completions
+        .into_iter()
+        .map(|item| {
+            let start = match &item.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => cursor::utf16_position_to_cursor_pos(
+                    code,
+                    edit.range.start.line as usize,
+                    edit.range.start.character as usize,
+                ),
+                _ => identifier_start,
+            };
+            (start.min(cursor_end), item)
+        })
+        .collect();
+
+    // Jupyter replies have a single range for all matches. Matches that
+    // replace less than the others are prefixed with the code they keep.
+    let cursor_start = items
+        .iter()
+        .map(|(start, _)| *start)
+        .min()
+        .unwrap_or(identifier_start);
+
+    let mut matches: Vec<String> = Vec::new();
+    let mut types = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (start, item) in items {
+        let kind = item.kind;
+        let snippet = item.insert_text_format == Some(InsertTextFormat::SNIPPET);
+        let text = match item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => edit.new_text,
+            _ => item.insert_text.unwrap_or(item.label),
+        };
+
+        // Snippets can't be expanded by Jupyter frontends
+        let text = if snippet {
+            snippet_to_text(&text)
+        } else {
+            text
+        };
+
+        let kept_start = cursor::cursor_pos_to_offset(code, cursor_start);
+        let kept_end = cursor::cursor_pos_to_offset(code, start);
+        let text = format!("{}{text}", &code[kept_start..kept_end]);
+
+        // Different sources may complete the same text
+        if !seen.insert(text.clone()) {
+            continue;
+        }
+
+        types.push(json!({
+            "start": cursor_start,
+            "end": cursor_end,
+            "text": text,
+            "type": completion_type(kind),
+        }));
+        matches.push(text);
+    }
+
+    Ok(CompleteReply {
+        matches,
+        status: Status::Ok,
+        cursor_start,
+        cursor_end,
+        // Types shown next to the matches by JupyterLab, in the format of
+        // ipykernel
+        metadata: json!({ "_jupyter_types_experimental": types }),
+    })
+}
+
+/// Plain text inserted by a snippet when its placeholders are left as is:
+/// tabstops like `$1` are removed, and placeholders like `${1:x}` or
+/// `${1|a,b|}` are replaced by their default.
+fn snippet_to_text(snippet: &str) -> String {
+    let mut out = String::new();
+    let mut chars = snippet.chars().peekable();
+
+    // Number of placeholders whose default we're in
+    let mut depth = 0;
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            },
+            '}' if depth > 0 => depth -= 1,
+            '$' => match chars.peek() {
+                Some(next) if next.is_ascii_digit() => {
+                    while chars.next_if(|c| c.is_ascii_digit()).is_some() {}
+                },
+                Some('{') => {
+                    chars.next();
+                    // Tabstop number, or variable name
+                    let is_name = |c: &char| c.is_alphanumeric() || *c == '_';
+                    while chars.next_if(is_name).is_some() {}
+
+                    match chars.next() {
+                        Some(':') => depth += 1,
+                        Some('|') => {
+                            // Keep the first choice
+                            while let Some(char) = chars.next_if(|c| *c != ',' && *c != '|') {
+                                out.push(char);
+                            }
+                            while chars.next_if(|c| *c != '}').is_some() {}
+                            chars.next();
+                        },
+                        _ => {},
+                    }
+                },
+                _ => out.push(char),
+            },
+            _ => out.push(char),
+        }
+    }
+
+    out
+}
+
+/// Jupyter type of a completion item, as shown by JupyterLab
+fn completion_type(kind: Option<CompletionItemKind>) -> &'static str {
+    match kind {
+        Some(CompletionItemKind::FUNCTION) => "function",
+        Some(CompletionItemKind::KEYWORD) => "keyword",
+        Some(CompletionItemKind::MODULE) => "module",
+        Some(CompletionItemKind::FILE | CompletionItemKind::FOLDER) => "path",
+        Some(CompletionItemKind::FIELD) => "property",
+        Some(CompletionItemKind::SNIPPET) => "snippet",
+        Some(CompletionItemKind::STRUCT) => "class",
+        Some(CompletionItemKind::VARIABLE | CompletionItemKind::ENUM_MEMBER) => "instance",
+        _ => "<unknown>",
+    }
+}
+
+/// Clamp a cursor position to the end of the code
+fn clamp_cursor_pos(code: &str, cursor_pos: u32) -> u32 {
+    cursor::offset_to_cursor_pos(code, cursor::cursor_pos_to_offset(code, cursor_pos))
+}
+
+/// Cursor position of the start of the R identifier ending at `cursor_pos`
+fn identifier_start(code: &str, cursor_pos: u32) -> u32 {
+    let offset = cursor::cursor_pos_to_offset(code, cursor_pos);
+    let n = code[..offset]
+        .chars()
+        .rev()
+        .take_while(|char| char.is_alphanumeric() || *char == '.' || *char == '_')
+        .count();
+    cursor_pos - n as u32
+}
+
+/// Indentation of the line that continues incomplete `code`: one level deeper
+/// than the line that opened the innermost unclosed bracket, or than the last
+/// line for other continuations such as a trailing operator. Lines that
+/// continue a string aren't indented since that would change the string.
+fn continuation_indent(code: &str) -> String {
+    let lines: Vec<&str> = code.lines().collect();
+
+    // Lines on which the unclosed brackets were opened
+    let mut brackets: Vec<usize> = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut in_comment = false;
+    let mut line = 0;
+
+    let mut chars = code.chars();
+    while let Some(char) = chars.next() {
+        if char == '\n' {
+            line += 1;
+            in_comment = false;
+            continue;
+        }
+        if in_comment {
+            continue;
+        }
+
+        if let Some(open) = quote {
+            match char {
+                '\\' => {
+                    chars.next();
+                },
+                _ if char == open => quote = None,
+                _ => {},
+            }
+            continue;
+        }
+
+        match char {
+            '#' => in_comment = true,
+            '"' | '\'' | '`' => quote = Some(char),
+            '(' | '[' | '{' => brackets.push(line),
+            ')' | ']' | '}' => {
+                brackets.pop();
+            },
+            _ => {},
+        }
+    }
+
+    if quote.is_some() {
+        return String::new();
+    }
+
+    let base = match brackets.last() {
+        Some(line) => lines.get(*line).copied(),
+        None => lines
+            .iter()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .copied(),
+    };
+    let base = base.unwrap_or_default();
+    let base = &base[..base.len() - base.trim_start().len()];
+
+    format!("{base}  ")
+}
+
 fn handle_comm_open_variables(
     comm: CommSocket,
     comm_manager_tx: Sender<CommManagerEvent>,
 ) -> amalthea::Result<bool> {
+    let version = protocol::negotiate(&comm.comm_name, VARIABLES_PROTOCOL_VERSIONS)?;
+    protocol::announce(&comm, version);
+
     r_task(|| {
         let global_env = RObject::view(R_ENVS.global);
         RVariables::start(global_env, comm, comm_manager_tx);
@@ -259,6 +551,11 @@ fn handle_comm_open_ui(
     Ok(true)
 }
 
+fn handle_comm_open_data_import(comm: CommSocket) -> amalthea::Result<bool> {
+    data_import::comm::start(comm);
+    Ok(true)
+}
+
 fn handle_comm_open_help(comm: CommSocket) -> amalthea::Result<bool> {
     r_task(|| {
         // Ensure the R help server is started, and get its port
@@ -286,3 +583,25 @@ fn handle_comm_open_help(comm: CommSocket) -> amalthea::Result<bool> {
         Ok(true)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::snippet_to_text;
+
+    #[test]
+    fn test_snippet_to_text() {
+        assert_eq!(snippet_to_text("lapply($0)"), "lapply()");
+        assert_eq!(snippet_to_text("lapply(${1:x}, ${2:f})$0"), "lapply(x, f)");
+        assert_eq!(
+            snippet_to_text("for (${1:i} in ${2:seq}) {\n\t$0\n}"),
+            "for (i in seq) {\n\t\n}"
+        );
+
+        // Nested placeholders and choices
+        assert_eq!(snippet_to_text("f(${1:x = ${2:1}})"), "f(x = 1)");
+        assert_eq!(snippet_to_text("${1|TRUE,FALSE|}"), "TRUE");
+
+        // Escapes
+        assert_eq!(snippet_to_text("df\\$${1:col}"), "df$col");
+    }
+}
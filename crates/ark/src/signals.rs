@@ -5,7 +5,24 @@
  *
  */
 
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
 pub use crate::sys::signals::initialize_signal_block;
 pub use crate::sys::signals::initialize_signal_handlers;
 pub use crate::sys::signals::interrupts_pending;
 pub use crate::sys::signals::set_interrupts_pending;
+
+/// Whether the frontend requested an interrupt since the last top-level
+/// prompt. Unlike `interrupts_pending()`, this is not reset by R when the
+/// interrupt is processed.
+static INTERRUPT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_interrupt_requested() {
+    INTERRUPT_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Returns whether an interrupt was requested and resets the flag
+pub fn take_interrupt_requested() -> bool {
+    INTERRUPT_REQUESTED.swap(false, Ordering::SeqCst)
+}
@@ -34,6 +34,8 @@ pub fn start_kernel(
     startup_file: Option<String>,
     session_mode: SessionMode,
     capture_streams: bool,
+    read_only: bool,
+    driver_session: Option<String>,
 ) {
     // Create the channels used for communication. These are created here
     // as they need to be shared across different components / threads.
@@ -78,7 +80,11 @@ pub fn start_kernel(
 
     // Create the control handler; this is used to handle shutdown/interrupt and
     // related requests
-    let control = Arc::new(Mutex::new(Control::new(r_request_tx.clone())));
+    let control = Arc::new(Mutex::new(Control::new(
+        r_request_tx.clone(),
+        comm_manager_tx.clone(),
+        dap.clone(),
+    )));
 
     // Create the stream behavior; this determines whether the kernel should
     // capture stdout/stderr and send them to the frontend as IOPub messages
@@ -122,5 +128,7 @@ pub fn start_kernel(
         kernel_request_rx,
         dap,
         session_mode,
+        read_only,
+        driver_session,
     )
 }
@@ -14,9 +14,11 @@ use amalthea::wire::stream::StreamOutput;
 use harp::environment::R_ENVS;
 use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
+use harp::utils::r_is_null;
 use libr::Rf_eval;
 
 use crate::interface::RMain;
+use crate::r_task;
 use crate::sys;
 
 pub(crate) fn should_ignore_site_r_profile(args: &Vec<String>) -> bool {
@@ -37,6 +39,44 @@ pub(crate) fn push_ignore_user_r_profile(args: &mut Vec<String>) {
     args.push(String::from("--no-init-file"))
 }
 
+/// Spawn the check of the R library, see `check_library()`. It runs once the
+/// kernel is idle since scanning the user libraries can take a while.
+pub(crate) fn spawn_check_library() {
+    r_task::spawn_idle(|| async move {
+        if let Some(report) = check_library() {
+            RMain::get_mut().show_library_problems(report);
+        }
+    });
+}
+
+/// Check that the packages ark relies on load and that the user library was
+/// built for this version of R, see `.ps.check_library()`. Returns a report
+/// of the problems along with remediation steps, if any.
+pub(crate) fn check_library() -> Option<String> {
+    let report = match RFunction::from(".ps.check_library").call() {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!("Can't check the R library: {err:?}");
+            return None;
+        },
+    };
+
+    if r_is_null(report.sexp) {
+        return None;
+    }
+
+    let report: String = match report.try_into() {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!("Can't read the R library report: {err:?}");
+            return None;
+        },
+    };
+
+    log::warn!("{report}");
+    Some(report)
+}
+
 // Mimics `R_OpenSiteFile()`
 // https://github.com/wch/r-source/blob/ee6b15303be885d118d49b441e32a9cff5cda778/src/main/startup.c#L96
 pub(crate) fn source_site_r_profile(r_home: &PathBuf) {
@@ -161,3 +201,36 @@ fn find_user_r_profile() -> Option<PathBuf> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use harp::exec::RFunction;
+    use harp::exec::RFunctionExt;
+
+    use crate::modules::ARK_ENVS;
+    use crate::r_task;
+
+    #[test]
+    fn test_check_library() {
+        r_task(|| {
+            // The check itself never fails
+            RFunction::from(".ps.check_library").call().unwrap();
+
+            let problems = harp::parse_eval_base(
+                "list(list(title = 'Outdated.', packages = paste0('pkg', 1:12), remedy = 'update.packages()'))",
+            )
+            .unwrap();
+
+            let report: String = RFunction::new("", "format_library_problems")
+                .add(problems)
+                .call_in(ARK_ENVS.positron_ns)
+                .unwrap()
+                .try_into()
+                .unwrap();
+
+            assert!(report.starts_with("Problems were found with your R library:\n\nOutdated.\n"));
+            assert!(report.contains("- pkg10\n- and 2 more\n"));
+            assert!(report.ends_with("To fix this, run:\n  update.packages()\n"));
+        })
+    }
+}
@@ -16,6 +16,7 @@ use libr::ptr_R_WriteConsole;
 use libr::ptr_R_WriteConsoleEx;
 use libr::run_Rmainloop;
 use libr::setup_Rmainloop;
+use libr::InputHandler;
 use libr::R_Consolefile;
 use libr::R_HomeDir;
 use libr::R_InputHandlers;
@@ -24,7 +25,6 @@ use libr::R_Outputfile;
 use libr::R_PolledEvents;
 use libr::R_SignalHandlers;
 use libr::R_checkActivity;
-use libr::R_runHandlers;
 use libr::R_running_as_main_program;
 use libr::R_wait_usec;
 use libr::Rf_initialize_R;
@@ -35,6 +35,7 @@ use crate::interface::r_read_console;
 use crate::interface::r_show_message;
 use crate::interface::r_suicide;
 use crate::interface::r_write_console;
+use crate::interface::RMain;
 use crate::signals::initialize_signal_handlers;
 
 pub fn setup_r(mut args: Vec<*mut c_char>) {
@@ -110,9 +111,55 @@ pub fn run_activity_handlers() {
         // be as responsive as possible when rendering help pages.
         let mut fdset = R_checkActivity(0, 1);
 
+        //
+        // Handlers that keep rescheduling themselves while printing are cut
+        // short once they trip the hook guard, otherwise we'd never return.
         while fdset != std::ptr::null_mut() {
-            R_runHandlers(libr::get(R_InputHandlers), fdset);
+            if RMain::get().hook_guard.is_tripped() {
+                break;
+            }
+            run_handlers(fdset as *const libc::fd_set);
             fdset = R_checkActivity(0, 1);
         }
     }
 }
+
+/// Same as `R_runHandlers()`, but records the handler being run in the hook
+/// guard so that it can be removed if it's caught in an output loop
+unsafe fn run_handlers(fdset: *const libc::fd_set) {
+    let mut handler = libr::get(R_InputHandlers) as *mut InputHandler;
+
+    while !handler.is_null() {
+        // The handler might remove itself
+        let next = (*handler).next;
+
+        if let Some(callback) = (*handler).handler {
+            if libc::FD_ISSET((*handler).fileDescriptor, fdset) {
+                let previous = RMain::get_mut()
+                    .hook_guard
+                    .set_handler(Some(handler as usize));
+                callback((*handler).userData);
+                RMain::get_mut().hook_guard.set_handler(previous);
+            }
+        }
+
+        handler = next;
+    }
+}
+
+/// Remove the input handler caught in an output loop, if any, once the hooks
+/// have unwound. The other hooks are re-enabled.
+pub fn remove_looping_handler() {
+    let Some(handler) = RMain::get_mut().hook_guard.take_culprit() else {
+        return;
+    };
+    log::warn!("Removing input handler caught in an output loop");
+
+    // Does nothing if the handler was already removed
+    unsafe {
+        libr::removeInputHandler(
+            R_InputHandlers as *mut *mut InputHandler,
+            handler as *mut InputHandler,
+        );
+    }
+}
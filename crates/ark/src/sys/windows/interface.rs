@@ -126,6 +126,10 @@ pub fn run_activity_handlers() {
     // Nothing to do on Windows
 }
 
+pub fn remove_looping_handler() {
+    // Input handlers don't exist on Windows
+}
+
 // TODO: Windows
 // It is possible we will want to use something other than `get_R_HOME()` and `getRUser()` for these.
 // RStudio does use `get_R_HOME()`, but they have a custom helper instead of `getRUser()`.
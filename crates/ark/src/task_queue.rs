@@ -0,0 +1,385 @@
+//
+// task_queue.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Bookkeeping of the work queued for the R thread: executions sent by the
+// shell, and tasks sent by the LSP, comms, and other threads with `r_task()`
+// or `spawn_idle()`. The channels these go through can't be inspected, so
+// senders and the R thread record what happens to each entry here.
+//
+// When the kernel looks stuck, the queue tells what is running and what is
+// waiting behind it. It is available through the `task_queue` RPC of the UI
+// comm, which is answered without involving the R thread, and is logged on
+// interrupts.
+
+use std::collections::BTreeMap;
+use std::panic::Location;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Serialize;
+
+static QUEUE: LazyLock<Mutex<BTreeMap<u64, QueueEntry>>> = LazyLock::new(Default::default);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskKind {
+    /// Code sent by an `execute_request`
+    Execution,
+
+    /// Blocking task sent with `r_task()`
+    Sync,
+
+    /// Async task that only runs when R is idle, see `spawn_idle()`
+    AsyncIdle,
+
+    /// Async task that may run during interrupt checks, see
+    /// `spawn_interrupt()`
+    AsyncInterrupt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Queued,
+    Running,
+
+    /// Async task waiting to be woken up
+    Parked,
+}
+
+/// Snapshot of an entry of the queue
+#[derive(Clone, Debug, Serialize)]
+pub struct QueuedTask {
+    pub id: u64,
+    pub kind: TaskKind,
+    pub state: TaskState,
+
+    /// Part of the kernel the task comes from, e.g. `lsp` or `comm`
+    pub origin: String,
+
+    /// Where the task was created, e.g. `crates/ark/src/lsp/hover.rs:42`, or
+    /// the code of executions
+    pub detail: String,
+
+    /// Name of the thread that queued the task
+    pub thread: String,
+
+    /// Wall clock time at which the task was queued, RFC 3339
+    pub enqueued_at: String,
+
+    /// Time spent in the queue so far, in milliseconds. Includes the time
+    /// spent running.
+    pub elapsed_ms: u128,
+
+    /// Time spent running, in milliseconds. For async tasks, this is the
+    /// time since they were last polled.
+    pub running_ms: Option<u128>,
+}
+
+struct QueueEntry {
+    kind: TaskKind,
+    state: TaskState,
+    origin: String,
+    detail: String,
+    thread: String,
+    enqueued_at: chrono::DateTime<chrono::Local>,
+    enqueued: Instant,
+    started: Option<Instant>,
+
+    /// ID of the `execute_request` of executions
+    msg_id: Option<String>,
+
+    /// When the execution last emitted output
+    last_output: Option<Instant>,
+}
+
+/// The execution that R is running, see `running_execution()`
+#[derive(Clone, Debug)]
+pub(crate) struct RunningExecution {
+    pub id: u64,
+
+    /// ID of the `execute_request`
+    pub msg_id: String,
+
+    /// First line of the code
+    pub detail: String,
+
+    /// Time spent running
+    pub running: Duration,
+
+    /// Time since the last output, if any
+    pub since_output: Option<Duration>,
+}
+
+/// Record a task sent to the R thread from `location`. Returns the ID of the
+/// entry, to be passed to `start()` and `finish()`.
+pub(crate) fn enqueue(kind: TaskKind, location: &Location) -> u64 {
+    let file = location.file().replace('\\', "/");
+    let origin = origin_of_file(&file);
+    let detail = format!("{file}:{}", location.line());
+    enqueue_with(kind, origin, detail, None)
+}
+
+/// Record an execution sent by the `execute_request` `msg_id`. The first line
+/// of `code` serves as description.
+pub(crate) fn enqueue_execution(code: &str, msg_id: &str) -> u64 {
+    let mut lines = code.lines();
+    let first = lines.next().unwrap_or_default();
+
+    let mut detail: String = first.chars().take(80).collect();
+    if lines.next().is_some() || detail.len() < first.len() {
+        detail.push_str(" ...");
+    }
+    enqueue_with(TaskKind::Execution, "shell", detail, Some(String::from(msg_id)))
+}
+
+fn enqueue_with(kind: TaskKind, origin: &str, detail: String, msg_id: Option<String>) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    let thread = std::thread::current();
+    let thread = thread
+        .name()
+        .map(String::from)
+        .unwrap_or_else(|| format!("{:?}", thread.id()));
+
+    let entry = QueueEntry {
+        kind,
+        state: TaskState::Queued,
+        origin: String::from(origin),
+        detail,
+        thread,
+        enqueued_at: chrono::Local::now(),
+        enqueued: Instant::now(),
+        started: None,
+        msg_id,
+        last_output: None,
+    };
+    QUEUE.lock().unwrap().insert(id, entry);
+
+    id
+}
+
+/// The R thread started running the task, or resumed polling it
+pub(crate) fn start(id: u64) {
+    set_state(id, TaskState::Running);
+}
+
+/// The async task is waiting to be woken up
+pub(crate) fn park(id: u64) {
+    set_state(id, TaskState::Parked);
+}
+
+/// The R thread picked up the oldest queued execution. Executions are
+/// handled in order.
+pub(crate) fn start_execution() {
+    let mut queue = QUEUE.lock().unwrap();
+    let next = queue
+        .values_mut()
+        .find(|entry| entry.kind == TaskKind::Execution && entry.state == TaskState::Queued);
+
+    if let Some(entry) = next {
+        entry.state = TaskState::Running;
+        entry.started = Some(Instant::now());
+    }
+}
+
+pub(crate) fn finish(id: u64) {
+    QUEUE.lock().unwrap().remove(&id);
+}
+
+fn set_state(id: u64, state: TaskState) {
+    let mut queue = QUEUE.lock().unwrap();
+    let Some(entry) = queue.get_mut(&id) else {
+        return;
+    };
+
+    entry.state = state;
+    entry.started = match state {
+        TaskState::Running => Some(Instant::now()),
+        TaskState::Queued | TaskState::Parked => None,
+    };
+}
+
+/// Whether R is running an execution
+pub(crate) fn is_executing() -> bool {
+    let queue = QUEUE.lock().unwrap();
+    queue.values().any(is_running_execution)
+}
+
+/// The running execution emitted output
+pub(crate) fn record_output() {
+    let mut queue = QUEUE.lock().unwrap();
+    if let Some(entry) = queue.values_mut().find(|entry| is_running_execution(entry)) {
+        entry.last_output = Some(Instant::now());
+    }
+}
+
+/// The execution R is running, if any
+pub(crate) fn running_execution() -> Option<RunningExecution> {
+    let queue = QUEUE.lock().unwrap();
+    let (id, entry) = queue.iter().find(|(_, entry)| is_running_execution(entry))?;
+
+    Some(RunningExecution {
+        id: *id,
+        msg_id: entry.msg_id.clone().unwrap_or_default(),
+        detail: entry.detail.clone(),
+        running: entry.started?.elapsed(),
+        since_output: entry.last_output.map(|last_output| last_output.elapsed()),
+    })
+}
+
+fn is_running_execution(entry: &QueueEntry) -> bool {
+    entry.kind == TaskKind::Execution && entry.state == TaskState::Running
+}
+
+/// Current contents of the queue, oldest first
+pub fn snapshot() -> Vec<QueuedTask> {
+    let queue = QUEUE.lock().unwrap();
+
+    queue
+        .iter()
+        .map(|(id, entry)| QueuedTask {
+            id: *id,
+            kind: entry.kind,
+            state: entry.state,
+            origin: entry.origin.clone(),
+            detail: entry.detail.clone(),
+            thread: entry.thread.clone(),
+            enqueued_at: entry.enqueued_at.to_rfc3339(),
+            elapsed_ms: entry.enqueued.elapsed().as_millis(),
+            running_ms: entry.started.map(|started| started.elapsed().as_millis()),
+        })
+        .collect()
+}
+
+/// Log the contents of the queue, e.g. when the user interrupts a kernel that
+/// looks stuck
+pub fn log_snapshot() {
+    let tasks = snapshot();
+
+    if tasks.is_empty() {
+        log::info!("R task queue is empty");
+        return;
+    }
+
+    let lines: Vec<String> = tasks
+        .iter()
+        .map(|task| {
+            format!(
+                "- #{} {:?} {:?} from {} ({}) on thread '{}', queued at {} ({} ms ago)",
+                task.id,
+                task.kind,
+                task.state,
+                task.origin,
+                task.detail,
+                task.thread,
+                task.enqueued_at,
+                task.elapsed_ms
+            )
+        })
+        .collect();
+
+    log::info!("R task queue:\n{}", lines.join("\n"));
+}
+
+fn origin_of_file(file: &str) -> &'static str {
+    const ORIGINS: &[(&str, &str)] = &[
+        ("/lsp/", "lsp"),
+        ("/dap/", "dap"),
+        ("/data_explorer/", "comm"),
+        ("/variables/", "comm"),
+        ("/connections/", "comm"),
+        ("/plots/", "comm"),
+        ("/ui/", "comm"),
+        ("/help/", "comm"),
+        ("/shell.rs", "shell"),
+    ];
+
+    ORIGINS
+        .iter()
+        .find(|(pattern, _)| file.contains(pattern))
+        .map(|(_, origin)| *origin)
+        .unwrap_or("kernel")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::Location;
+
+    use crate::task_queue::enqueue;
+    use crate::task_queue::enqueue_execution;
+    use crate::task_queue::finish;
+    use crate::task_queue::origin_of_file;
+    use crate::task_queue::park;
+    use crate::task_queue::record_output;
+    use crate::task_queue::running_execution;
+    use crate::task_queue::snapshot;
+    use crate::task_queue::start;
+    use crate::task_queue::start_execution;
+    use crate::task_queue::TaskKind;
+    use crate::task_queue::TaskState;
+
+    #[test]
+    fn test_task_queue() {
+        // Other tests may queue tasks concurrently, only look at ours
+        let task = enqueue(TaskKind::AsyncIdle, Location::caller());
+        let execution = enqueue_execution("1 + 1\n2 + 2", "msg-1");
+
+        let state = |id| {
+            snapshot()
+                .into_iter()
+                .find(|task| task.id == id)
+                .map(|task| task.state)
+        };
+
+        let entry = snapshot().into_iter().find(|x| x.id == task).unwrap();
+        assert_eq!(entry.kind, TaskKind::AsyncIdle);
+        assert_eq!(entry.origin, "kernel");
+        assert!(entry.detail.contains("task_queue.rs:"));
+        assert!(entry.running_ms.is_none());
+
+        let entry = snapshot().into_iter().find(|x| x.id == execution).unwrap();
+        assert_eq!(entry.origin, "shell");
+        assert_eq!(entry.detail, "1 + 1 ...");
+
+        start(task);
+        assert_eq!(state(task), Some(TaskState::Running));
+        park(task);
+        assert_eq!(state(task), Some(TaskState::Parked));
+        finish(task);
+        assert_eq!(state(task), None);
+
+        start_execution();
+        assert_eq!(state(execution), Some(TaskState::Running));
+
+        let running = running_execution().unwrap();
+        assert_eq!(running.id, execution);
+        assert_eq!(running.msg_id, "msg-1");
+        assert!(running.since_output.is_none());
+        record_output();
+        assert!(running_execution().unwrap().since_output.is_some());
+
+        finish(execution);
+        assert_eq!(state(execution), None);
+        assert!(running_execution().is_none());
+    }
+
+    #[test]
+    fn test_task_queue_origin() {
+        assert_eq!(origin_of_file("crates/ark/src/lsp/hover.rs"), "lsp");
+        assert_eq!(
+            origin_of_file("crates/ark/src/data_explorer/r_data_explorer.rs"),
+            "comm"
+        );
+        assert_eq!(origin_of_file("crates/ark/src/interface.rs"), "kernel");
+    }
+}
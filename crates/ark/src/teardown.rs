@@ -0,0 +1,154 @@
+//
+// teardown.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Teardown of everything owned by the frontend, run when it disposes of the
+// session, e.g. because its window was closed. This is the single path taken
+// on shutdown requests before the R thread is asked to exit. Steps run in
+// order, each on a helper thread and bounded by a timeout so that a stuck
+// step doesn't keep the kernel alive:
+//
+// - In-flight R tasks are cancelled. Work done on behalf of the frontend
+//   stops at its next cancellation point, see `harp::cancellation`.
+// - Comms are closed, on both sides.
+// - The help proxy is stopped.
+// - The DAP and LSP servers disconnect their clients and stop listening.
+// - R is interrupted if it's running an execution, so that the shutdown
+//   request doesn't wait behind it.
+
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use amalthea::comm::event::CommManagerEvent;
+use amalthea::comm::event::CommManagerRequest;
+use crossbeam::channel::bounded;
+use crossbeam::channel::Sender;
+use stdext::cancellation::CancellationToken;
+use stdext::spawn;
+
+use crate::dap::Dap;
+use crate::task_queue;
+use crate::wait::WaitPolicy;
+
+/// Cancelled when the teardown starts. `r_task()` runs tasks with this token
+/// as cancellation point.
+pub(crate) static SHUTDOWN: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
+
+/// How often we check whether R is done with an interrupted execution
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+struct Step {
+    name: &'static str,
+    timeout: Duration,
+    run: Box<dyn FnOnce() -> anyhow::Result<()> + Send>,
+}
+
+impl Step {
+    fn new(
+        name: &'static str,
+        timeout: Duration,
+        run: impl FnOnce() -> anyhow::Result<()> + Send + 'static,
+    ) -> Self {
+        Self {
+            name,
+            timeout,
+            run: Box::new(run),
+        }
+    }
+}
+
+/// Tear down everything owned by the frontend. Only the first call has an
+/// effect.
+pub(crate) fn teardown(comm_manager_tx: Sender<CommManagerEvent>, dap: Arc<Mutex<Dap>>) {
+    if SHUTDOWN.is_cancelled() {
+        log::info!("Teardown: already done");
+        return;
+    }
+
+    log::info!("Teardown: starting");
+    let start = Instant::now();
+
+    let steps = vec![
+        Step::new("cancel R tasks", Duration::from_millis(100), || {
+            task_queue::log_snapshot();
+            SHUTDOWN.cancel();
+            Ok(())
+        }),
+        Step::new("close comms", Duration::from_secs(1), move || {
+            close_comms(comm_manager_tx)
+        }),
+        Step::new(
+            "stop help proxy",
+            Duration::from_secs(1),
+            crate::help_proxy::stop,
+        ),
+        Step::new("stop DAP server", Duration::from_secs(1), move || {
+            dap.lock().unwrap().stop();
+            Ok(())
+        }),
+        Step::new(
+            "stop LSP server",
+            Duration::from_secs(1),
+            crate::lsp::backend::stop_lsp,
+        ),
+        Step::new("interrupt R", Duration::from_secs(2), interrupt_r),
+    ];
+
+    for step in steps {
+        run_step(step);
+    }
+
+    log::info!("Teardown: done in {:?}", start.elapsed());
+}
+
+fn run_step(step: Step) {
+    let Step { name, timeout, run } = step;
+    log::info!("Teardown: {name}");
+
+    let start = Instant::now();
+    let (done_tx, done_rx) = bounded(1);
+
+    spawn!("ark-teardown", move || {
+        let _ = done_tx.send(run());
+    });
+
+    // A step that times out keeps running on its thread, but the teardown
+    // moves on
+    match WaitPolicy::new(name).with_timeout(timeout).recv(&done_rx) {
+        Ok(Ok(())) => log::info!("Teardown: {name}: done in {:?}", start.elapsed()),
+        Ok(Err(err)) => log::error!("Teardown: {name}: failed: {err:?}"),
+        Err(err) => log::error!("Teardown: {name}: {err}"),
+    }
+}
+
+fn close_comms(comm_manager_tx: Sender<CommManagerEvent>) -> anyhow::Result<()> {
+    let (tx, rx) = bounded(1);
+    comm_manager_tx.send(CommManagerEvent::Request(CommManagerRequest::CloseAll(tx)))?;
+
+    let count = rx.recv()?;
+    log::info!("Teardown: closed {count} comms");
+
+    Ok(())
+}
+
+/// Interrupt R and wait until it's done with the current execution
+fn interrupt_r() -> anyhow::Result<()> {
+    if !task_queue::is_executing() {
+        return Ok(());
+    }
+
+    crate::signals::set_interrupt_requested();
+    crate::sys::control::handle_interrupt_request();
+
+    while task_queue::is_executing() {
+        std::thread::sleep(INTERRUPT_POLL_INTERVAL);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//
+// transcript.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::VecDeque;
+
+use harp::object::RObject;
+use libr::SEXP;
+use serde::Serialize;
+
+use crate::interface::RMain;
+
+/// Number of executions kept in the transcript. Older ones are dropped.
+const MAX_ENTRIES: usize = 1000;
+
+/// Bytes of output kept for each execution. The rest is dropped.
+const MAX_ENTRY_OUTPUT: usize = 64 * 1024;
+
+/// Record of the last console executions of the session, in execution order.
+///
+/// Used to export the session as an R script or an R Markdown document, see
+/// `.ps.rpc.export_transcript()`. The transcript lives as long as the session
+/// so it is bounded to the last `MAX_ENTRIES` executions, and to
+/// `MAX_ENTRY_OUTPUT` bytes of output for each of them.
+#[derive(Default)]
+pub struct Transcript {
+    entries: VecDeque<TranscriptEntry>,
+
+    /// The entry of the execution in progress, if it is being recorded
+    current: Option<TranscriptEntry>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct TranscriptEntry {
+    /// The code submitted for execution
+    pub input: String,
+
+    /// Output emitted on stdout and stderr while executing `input`
+    pub output: String,
+
+    /// Whether `output` was cut at `MAX_ENTRY_OUTPUT` bytes
+    pub output_truncated: bool,
+
+    /// The error message if the execution failed
+    pub error: Option<String>,
+
+    /// IDs of the plots created while executing `input`
+    pub plots: Vec<String>,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &VecDeque<TranscriptEntry> {
+        &self.entries
+    }
+
+    /// Start recording the execution of `input`
+    pub fn start_entry(&mut self, input: &str) {
+        self.end_entry();
+        self.current = Some(TranscriptEntry {
+            input: input.to_string(),
+            ..Default::default()
+        });
+    }
+
+    /// Finish recording the current execution, if any
+    pub fn end_entry(&mut self) {
+        if let Some(entry) = self.current.take() {
+            if self.entries.len() == MAX_ENTRIES {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(entry);
+        }
+    }
+
+    pub fn push_output(&mut self, content: &str) {
+        let Some(entry) = &mut self.current else {
+            return;
+        };
+        if entry.output_truncated {
+            return;
+        }
+
+        let available = MAX_ENTRY_OUTPUT - entry.output.len();
+        if content.len() <= available {
+            entry.output.push_str(content);
+            return;
+        }
+
+        let mut end = available;
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        entry.output.push_str(&content[..end]);
+        entry.output_truncated = true;
+    }
+
+    pub fn push_error(&mut self, message: &str) {
+        if let Some(entry) = &mut self.current {
+            entry.error = Some(message.to_string());
+        }
+    }
+
+    pub fn push_plot(&mut self, id: &str) {
+        if let Some(entry) = &mut self.current {
+            entry.plots.push(id.to_string());
+        }
+    }
+}
+
+/// Returns the recorded entries as a list of lists with fields `input`,
+/// `output`, `output_truncated`, `error`, and `plots`
+#[harp::register]
+pub unsafe extern "C" fn ps_transcript_entries() -> anyhow::Result<SEXP> {
+    let entries: Vec<_> = RMain::with(|main| main.transcript().entries().iter().cloned().collect());
+    let entries = serde_json::to_value(entries)?;
+    Ok(RObject::from_json(entries)?.sexp)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transcript::Transcript;
+    use crate::transcript::MAX_ENTRIES;
+    use crate::transcript::MAX_ENTRY_OUTPUT;
+
+    #[test]
+    fn test_transcript_entries() {
+        let mut transcript = Transcript::new();
+
+        // Nothing is recorded outside of an entry
+        transcript.push_output("ignored");
+        assert!(transcript.entries().is_empty());
+
+        transcript.start_entry("1 + 1");
+        transcript.push_output("[1] 2\n");
+        transcript.start_entry("plot(1); stop('foo')");
+        transcript.push_plot("abc");
+        transcript.push_error("foo");
+        transcript.end_entry();
+
+        let entries = transcript.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].input, "1 + 1");
+        assert_eq!(entries[0].output, "[1] 2\n");
+        assert_eq!(entries[0].error, None);
+        assert_eq!(entries[1].plots, vec![String::from("abc")]);
+        assert_eq!(entries[1].error, Some(String::from("foo")));
+    }
+
+    #[test]
+    fn test_transcript_is_bounded() {
+        let mut transcript = Transcript::new();
+
+        for i in 0..(MAX_ENTRIES + 10) {
+            transcript.start_entry(&i.to_string());
+        }
+        transcript.end_entry();
+
+        let entries = transcript.entries();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].input, "10");
+
+        // Output is cut on a character boundary
+        transcript.start_entry("big");
+        transcript.push_output(&"a".repeat(MAX_ENTRY_OUTPUT - 1));
+        transcript.push_output("éa");
+        transcript.end_entry();
+
+        let entry = transcript.entries().back().unwrap();
+        assert_eq!(entry.output.len(), MAX_ENTRY_OUTPUT - 1);
+        assert!(entry.output_truncated);
+    }
+}
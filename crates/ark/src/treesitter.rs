@@ -296,6 +296,7 @@ pub trait NodeTypeExt: Sized {
     fn is_namespace_internal_operator(&self) -> bool;
     fn is_unary_operator(&self) -> bool;
     fn is_binary_operator(&self) -> bool;
+    fn is_formula(&self) -> bool;
     fn is_native_pipe_operator(&self) -> bool;
     fn is_magrittr_pipe_operator(&self, contents: &ropey::Rope) -> anyhow::Result<bool>;
     fn is_pipe_operator(&self, contents: &ropey::Rope) -> anyhow::Result<bool>;
@@ -390,6 +391,14 @@ impl NodeTypeExt for Node<'_> {
         matches!(self.node_type(), NodeType::BinaryOperator(_))
     }
 
+    fn is_formula(&self) -> bool {
+        matches!(
+            self.node_type(),
+            NodeType::UnaryOperator(UnaryOperatorType::Tilde) |
+                NodeType::BinaryOperator(BinaryOperatorType::Tilde)
+        )
+    }
+
     fn is_native_pipe_operator(&self) -> bool {
         self.node_type() == NodeType::BinaryOperator(BinaryOperatorType::Pipe)
     }
@@ -433,6 +442,39 @@ pub(crate) fn node_has_error_or_missing(node: &Node) -> bool {
     node.is_error() || node.has_error()
 }
 
+/// Depth of syntax trees beyond which the recursive analyses of the LSP give
+/// up, so that pathological code, e.g. machine generated with thousands of
+/// nested calls, can't overflow the stack. Hand written code stays well below.
+pub(crate) const MAX_TREE_DEPTH: usize = 256;
+
+/// Whether `node` has descendants more than `max` levels below it. Iterative,
+/// so it's safe on trees of any depth.
+pub(crate) fn node_depth_exceeds(node: &Node, max: usize) -> bool {
+    let mut cursor = node.walk();
+    let mut depth = 0;
+
+    loop {
+        if cursor.goto_first_child() {
+            depth += 1;
+            if depth > max {
+                return true;
+            }
+            continue;
+        }
+
+        loop {
+            if depth == 0 {
+                return false;
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+            cursor.goto_parent();
+            depth -= 1;
+        }
+    }
+}
+
 pub(crate) fn node_find_string<'a>(node: &'a Node) -> Option<Node<'a>> {
     // If we are on one of the following, we return the string parent:
     // - Anonymous node inside a string, like `"'"`
@@ -5,9 +5,11 @@
 //
 //
 
+use amalthea::comm::ui_comm::BindingsMaskedParams;
 use amalthea::comm::ui_comm::OpenEditorParams;
 use amalthea::comm::ui_comm::OpenWorkspaceParams;
 use amalthea::comm::ui_comm::Position;
+use amalthea::comm::ui_comm::ProgressParams;
 use amalthea::comm::ui_comm::Range;
 use amalthea::comm::ui_comm::SetEditorSelectionsParams;
 use amalthea::comm::ui_comm::ShowMessageParams;
@@ -15,6 +17,7 @@ use amalthea::comm::ui_comm::ShowUrlParams;
 use amalthea::comm::ui_comm::UiFrontendEvent;
 use harp::object::RObject;
 use libr::R_NilValue;
+use libr::Rf_ScalarLogical;
 use libr::SEXP;
 
 use crate::interface::RMain;
@@ -36,6 +39,60 @@ pub unsafe extern "C" fn ps_ui_show_message(message: SEXP) -> anyhow::Result<SEX
     Ok(R_NilValue)
 }
 
+#[harp::register]
+pub unsafe extern "C" fn ps_ui_progress(
+    id: SEXP,
+    title: SEXP,
+    message: SEXP,
+    done: SEXP,
+) -> anyhow::Result<SEXP> {
+    let params = ProgressParams {
+        id: RObject::view(id).try_into()?,
+        title: RObject::view(title).try_into()?,
+        message: RObject::view(message).try_into()?,
+        done: RObject::view(done).try_into()?,
+    };
+
+    let event = UiFrontendEvent::Progress(params);
+
+    let main = RMain::get();
+    let ui_comm_tx = main
+        .get_ui_comm_tx()
+        .ok_or_else(|| ui_comm_not_connected("ui_progress"))?;
+    ui_comm_tx.send_event(event);
+
+    Ok(R_NilValue)
+}
+
+#[harp::register]
+pub unsafe extern "C" fn ps_ui_bindings_masked(
+    environment: SEXP,
+    bindings: SEXP,
+) -> anyhow::Result<SEXP> {
+    let bindings = RObject::view(bindings).try_to_json()?;
+
+    let params = BindingsMaskedParams {
+        environment: RObject::view(environment).try_into()?,
+        bindings: serde_json::from_value(bindings)?,
+    };
+
+    let event = UiFrontendEvent::BindingsMasked(params);
+
+    let main = RMain::get();
+    let ui_comm_tx = main
+        .get_ui_comm_tx()
+        .ok_or_else(|| ui_comm_not_connected("ui_bindings_masked"))?;
+    ui_comm_tx.send_event(event);
+
+    Ok(R_NilValue)
+}
+
+#[harp::register]
+pub unsafe extern "C" fn ps_ui_is_connected() -> anyhow::Result<SEXP> {
+    let connected = RMain::is_initialized() && RMain::with(|main| main.is_ui_comm_connected());
+    Ok(Rf_ScalarLogical(connected as i32))
+}
+
 #[harp::register]
 pub unsafe extern "C" fn ps_ui_open_workspace(
     path: SEXP,
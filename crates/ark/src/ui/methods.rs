@@ -13,6 +13,7 @@ use amalthea::comm::ui_comm::ModifyEditorSelectionsParams;
 use amalthea::comm::ui_comm::NewDocumentParams;
 use amalthea::comm::ui_comm::ShowDialogParams;
 use amalthea::comm::ui_comm::ShowQuestionParams;
+use amalthea::comm::ui_comm::ShowQuickPickParams;
 use amalthea::comm::ui_comm::UiFrontendRequest;
 use harp::object::RObject;
 use harp::utils::r_is_null;
@@ -93,6 +94,18 @@ pub unsafe extern "C" fn ps_ui_show_question(
     Ok(out.sexp)
 }
 
+#[harp::register]
+pub unsafe extern "C" fn ps_ui_show_quick_pick(title: SEXP, items: SEXP) -> anyhow::Result<SEXP> {
+    let params = ShowQuickPickParams {
+        title: RObject::view(title).try_into()?,
+        items: RObject::view(items).try_into()?,
+    };
+
+    let main = RMain::get();
+    let out = main.call_frontend_method(UiFrontendRequest::ShowQuickPick(params))?;
+    Ok(out.sexp)
+}
+
 #[harp::register]
 pub unsafe extern "C" fn ps_ui_new_document(
     contents: SEXP,
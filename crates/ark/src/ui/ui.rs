@@ -5,7 +5,11 @@
 //
 //
 
+use std::time::Duration;
+use std::time::Instant;
+
 use amalthea::comm::comm_channel::CommMsg;
+use amalthea::comm::ui_comm::ExecutionRunningParams;
 use amalthea::comm::ui_comm::UiBackendReply;
 use amalthea::comm::ui_comm::UiBackendRequest;
 use amalthea::comm::ui_comm::UiFrontendEvent;
@@ -18,11 +22,26 @@ use crossbeam::select;
 use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
 use harp::object::RObject;
-use serde_json::Value;
 use stdext::spawn;
 use stdext::unwrap;
 
 use crate::r_task;
+use crate::task_queue;
+
+/// Executions running longer than this are reported to the frontend with
+/// `execution_running` events
+const LONG_RUNNING_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// Interval between two `execution_running` events
+const LONG_RUNNING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Long-running execution reported to the frontend. Once it's done, the
+/// frontend is told one last time.
+struct LongRunning {
+    msg_id: String,
+    code: String,
+    started: Instant,
+}
 
 #[derive(Debug)]
 pub enum UiCommMessage {
@@ -37,6 +56,7 @@ pub struct UiComm {
     comm: CommSocket,
     ui_comm_rx: Receiver<UiCommMessage>,
     stdin_request_tx: Sender<StdInRequest>,
+    long_running: Option<LongRunning>,
 }
 
 impl UiComm {
@@ -48,10 +68,11 @@ impl UiComm {
         let (ui_comm_tx, ui_comm_rx) = crossbeam::channel::unbounded::<UiCommMessage>();
 
         spawn!("ark-comm-ui", move || {
-            let frontend = Self {
+            let mut frontend = Self {
                 comm: comm.clone(),
                 ui_comm_rx: ui_comm_rx.clone(),
                 stdin_request_tx: stdin_request_tx.clone(),
+                long_running: None,
             };
             frontend.execution_thread();
         });
@@ -59,7 +80,9 @@ impl UiComm {
         ui_comm_tx
     }
 
-    fn execution_thread(&self) {
+    fn execution_thread(&mut self) {
+        let long_running_ticker = crossbeam::channel::tick(LONG_RUNNING_INTERVAL);
+
         loop {
             // Wait for an event on either the event channel (which forwards
             // Positron events to the frontend) or the comm channel (which
@@ -93,6 +116,8 @@ impl UiComm {
                         },
                     }
                 },
+
+                recv(long_running_ticker) -> _ => self.check_long_running(),
             }
         }
     }
@@ -106,6 +131,68 @@ impl UiComm {
         };
     }
 
+    /// Reports the running execution if it has exceeded
+    /// `LONG_RUNNING_THRESHOLD`, and the completion of the one reported
+    /// before, if any
+    fn check_long_running(&mut self) {
+        let running = task_queue::running_execution();
+
+        if let Some(reported) = &self.long_running {
+            let same = running
+                .as_ref()
+                .is_some_and(|running| running.msg_id == reported.msg_id);
+
+            if !same {
+                let elapsed = reported.started.elapsed();
+                self.dispatch_event(&UiFrontendEvent::ExecutionRunning(ExecutionRunningParams {
+                    msg_id: reported.msg_id.clone(),
+                    code: reported.code.clone(),
+                    elapsed_ms: elapsed.as_millis() as i64,
+                    since_output_ms: None,
+                    message: format!("Finished after {}", format_duration(elapsed)),
+                    done: true,
+                }));
+                self.long_running = None;
+            }
+        }
+
+        let Some(running) = running else {
+            return;
+        };
+        if running.running < LONG_RUNNING_THRESHOLD {
+            return;
+        }
+
+        let message = match running.since_output {
+            Some(since_output) => format!(
+                "Still running (elapsed {}, last output {} ago)",
+                format_duration(running.running),
+                format_duration(since_output)
+            ),
+            None => format!(
+                "Still running (elapsed {}, no output yet)",
+                format_duration(running.running)
+            ),
+        };
+
+        self.dispatch_event(&UiFrontendEvent::ExecutionRunning(ExecutionRunningParams {
+            msg_id: running.msg_id.clone(),
+            code: running.detail.clone(),
+            elapsed_ms: running.running.as_millis() as i64,
+            since_output_ms: running.since_output.map(|since| since.as_millis() as i64),
+            message,
+            done: false,
+        }));
+
+        if self.long_running.is_none() {
+            self.long_running = Some(LongRunning {
+                msg_id: running.msg_id,
+                code: running.detail,
+                started: Instant::now() - running.running,
+            });
+        }
+    }
+
     /**
      * Handles a comm message from the frontend.
      *
@@ -144,12 +231,17 @@ impl UiComm {
 
         log::trace!("Handling '{}' frontend RPC method", request.method);
 
-        // Today, all RPCs are fulfilled by R directly. Check to see if an R
-        // method of the appropriate name is defined.
-        //
-        // Consider: In the future, we may want to allow requests to be
-        // fulfilled here on the Rust side, with only some requests forwarded to
-        // R; Rust methods may wish to establish their own RPC handlers.
+        // Diagnostics of a kernel that looks stuck can't wait for R, so these
+        // are fulfilled here on the Rust side
+        if request.method == "task_queue" {
+            let tasks = crate::task_queue::snapshot();
+            return Ok(UiBackendReply::CallMethodReply(serde_json::to_value(
+                tasks,
+            )?));
+        }
+
+        // Other RPCs are fulfilled by R directly. Check to see if an R method
+        // of the appropriate name is defined.
 
         // The method name is prefixed with ".ps.rpc.", by convention
         let method = format!(".ps.rpc.{}", request.method);
@@ -170,11 +262,11 @@ impl UiComm {
         let result = r_task(|| {
             let mut call = RFunction::from(method);
             for param in request.params.iter() {
-                let p = RObject::try_from(param.clone())?;
+                let p = RObject::from_json(param.clone())?;
                 call.add(p);
             }
             let result = call.call()?;
-            Value::try_from(result)
+            result.try_to_json()
         })?;
 
         Ok(UiBackendReply::CallMethodReply(result))
@@ -190,3 +282,31 @@ impl UiComm {
         Ok(())
     }
 }
+
+/// Formats a duration as e.g. `1h 2m 13s`, `2m 13s`, or `45s`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+
+    if hours > 0 {
+        format!("{hours}h {mins}m {secs}s")
+    } else if mins > 0 {
+        format!("{mins}m {secs}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::ui::ui::format_duration;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(45_900)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(133)), "2m 13s");
+        assert_eq!(format_duration(Duration::from_secs(3733)), "1h 2m 13s");
+    }
+}
@@ -0,0 +1,411 @@
+//
+// user_config.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use amalthea::comm::plot_comm::RenderFormat;
+use amalthea::features;
+use amalthea::features::Source;
+use amalthea::socket::iopub::set_stream_flush_interval;
+use amalthea::socket::iopub::DEFAULT_STREAM_FLUSH_INTERVAL;
+use notify::RecommendedWatcher;
+use notify::Watcher;
+use serde::Deserialize;
+
+use crate::lsp::project_config::ProjectEvaluationConfig;
+
+/// User-level defaults for Ark, read from a TOML file:
+///
+/// ```toml
+/// [log]
+/// level = "debug"
+///
+/// [startup]
+/// check_library = false
+/// warmup_packages = true
+/// environment_fingerprint = true
+///
+/// [completions]
+/// function_parentheses = false
+/// disabled_sources = ["snippets", "workspace"]
+///
+/// [evaluation]
+/// allow_function_calls = true
+/// hover = true
+///
+/// [execution]
+/// isolate_state = true
+/// stream_flush_interval = 20
+///
+/// [data_viewer]
+/// page_size = 1000
+///
+/// [plots]
+/// format = "svg"
+/// width = 1000
+/// height = 800
+/// describe = true
+///
+/// [features]
+/// comm_chunking = true
+/// ```
+///
+/// The file is `$XDG_CONFIG_HOME/ark/config.toml` (`~/.config/ark/config.toml`
+/// by default) on Unix and `%APPDATA%\ark\config.toml` on Windows. A different
+/// file can be passed with `--config`. All settings are optional. Command line
+/// flags take precedence.
+///
+/// The file is watched and changes apply to the running session, except for
+/// the log level which is only read at startup. The evaluation policies may be
+/// overridden by the `.ark.toml` file of the project open in the workspace,
+/// see `ProjectConfig`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    pub log: LogConfig,
+    pub startup: StartupConfig,
+    pub completions: CompletionsConfig,
+    pub evaluation: EvaluationConfig,
+    pub execution: ExecutionConfig,
+    pub data_viewer: DataViewerConfig,
+    pub plots: PlotsConfig,
+
+    /// Overrides of feature flags by name, see `.ps.rpc.features()` for the
+    /// list. Flags passed with `--feature` take precedence.
+    pub features: HashMap<String, bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Verbosity of Ark's log, e.g. `info` or `trace`. Overridden by
+    /// `--log-level` and by the `RUST_LOG` environment variable.
+    pub level: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartupConfig {
+    /// Whether to check at startup that the packages Ark relies on load, and
+    /// that the packages of the user libraries were built for this version of
+    /// R. Problems are shown to the user along with remediation steps once
+    /// the session is idle.
+    pub check_library: bool,
+
+    /// Whether to load the functions of attached packages in the background
+    /// once the session is idle, so that the first completions don't have to.
+    /// Off by default since it runs the lazy-loading code of every export.
+    /// See `warmup.rs`.
+    pub warmup_packages: bool,
+
+    /// Whether the kernel info includes a fingerprint of the package
+    /// environment, the hash of `renv.lock` or of the loaded package
+    /// versions. See `fingerprint.rs`.
+    pub environment_fingerprint: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompletionsConfig {
+    /// Whether completing a function name inserts parentheses
+    pub function_parentheses: bool,
+
+    /// Names of completion sources that aren't consulted, e.g. `snippets`,
+    /// `search_path`, `document`, or `workspace`. See `unique::sources()` and
+    /// `composite::sources()` for the full list.
+    pub disabled_sources: Vec<String>,
+}
+
+/// Policies for code that Ark evaluates on its own, outside of executions
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvaluationConfig {
+    /// Whether completions may evaluate function calls to find out the type
+    /// of an object, e.g. `get_data()$` or `get_data() |> `. Calls may be
+    /// slow or have side effects, so this is disabled by default.
+    pub allow_function_calls: bool,
+
+    /// Whether hovering a symbol, a literal, or a `$` chain like `x$y` shows
+    /// a preview of its value in the global environment. Active bindings,
+    /// promises, and `$` methods are never triggered.
+    pub hover: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutionConfig {
+    /// Whether executions restore the options, graphical parameters, working
+    /// directory, and RNG kind once they complete, so that notebook cells
+    /// don't leak state to each other. Executions may override this, see
+    /// `cell_state.rs`.
+    pub isolate_state: bool,
+
+    /// Interval in milliseconds at which the output of executions is sent to
+    /// the frontend, 80 by default. Output is batched in between. With 0,
+    /// output is sent as soon as it is printed, at the cost of more messages.
+    pub stream_flush_interval: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataViewerConfig {
+    /// Maximum number of rows sent to the data viewer in a single request.
+    /// Frontends request rows page by page as the user scrolls, larger
+    /// requests are truncated.
+    pub page_size: Option<usize>,
+}
+
+/// Settings for plots displayed in notebooks. In Positron, plots are
+/// rendered at the size and in the format requested by the plots pane.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlotsConfig {
+    pub format: PlotFormat,
+    pub width: i64,
+    pub height: i64,
+
+    /// Whether rendered plots come with a plain text description of their
+    /// type, axes and series, for screen readers. Describing plots requires
+    /// inspecting them in R after each render, so this is opt-in.
+    pub describe: bool,
+}
+
+/// Formats that notebooks can display inline
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlotFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Svg,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            check_library: true,
+            warmup_packages: false,
+            environment_fingerprint: false,
+        }
+    }
+}
+
+impl Default for CompletionsConfig {
+    fn default() -> Self {
+        Self {
+            function_parentheses: true,
+            disabled_sources: vec![],
+        }
+    }
+}
+
+impl Default for PlotsConfig {
+    fn default() -> Self {
+        Self {
+            format: PlotFormat::Png,
+            width: 800,
+            height: 600,
+            describe: false,
+        }
+    }
+}
+
+impl From<PlotFormat> for RenderFormat {
+    fn from(value: PlotFormat) -> Self {
+        match value {
+            PlotFormat::Png => RenderFormat::Png,
+            PlotFormat::Jpeg => RenderFormat::Jpeg,
+            PlotFormat::Svg => RenderFormat::Svg,
+        }
+    }
+}
+
+static USER_CONFIG: LazyLock<RwLock<UserConfig>> = LazyLock::new(Default::default);
+
+/// Evaluation policies of the project open in the workspace
+static PROJECT_EVALUATION: LazyLock<RwLock<ProjectEvaluationConfig>> =
+    LazyLock::new(Default::default);
+
+/// Keeps the file watcher alive for the duration of the session
+static WATCHER: OnceLock<Mutex<RecommendedWatcher>> = OnceLock::new();
+
+/// Current user configuration. Read it at the time the settings are used
+/// rather than caching it, so that changes to the file take effect.
+pub fn user_config() -> UserConfig {
+    let mut config = USER_CONFIG.read().unwrap().clone();
+    PROJECT_EVALUATION
+        .read()
+        .unwrap()
+        .apply(&mut config.evaluation);
+    config
+}
+
+/// Set the evaluation policies of the project open in the workspace, which
+/// take precedence over those of the user
+pub(crate) fn set_project_evaluation(evaluation: ProjectEvaluationConfig) {
+    *PROJECT_EVALUATION.write().unwrap() = evaluation;
+}
+
+/// Load the user configuration and watch it for changes. Errors are logged
+/// and leave the defaults in place, a broken file shouldn't prevent the kernel
+/// from starting.
+pub fn init(path: Option<PathBuf>) {
+    let Some(path) = path else {
+        return;
+    };
+
+    reload(&path);
+
+    if let Err(err) = watch(path.clone()) {
+        log::warn!(
+            "Can't watch configuration file '{}': {err:?}",
+            path.display()
+        );
+    }
+}
+
+/// Path of the configuration file
+///
+/// - `path`: The file passed with `--config`, if any. Unlike the default
+///   file, it must exist.
+pub fn config_path(path: Option<&str>) -> anyhow::Result<Option<PathBuf>> {
+    if let Some(path) = path {
+        // Absolute so that it matches the paths reported by the file watcher
+        let path = std::fs::canonicalize(path)
+            .map_err(|err| anyhow::anyhow!("Can't find configuration file '{path}': {err}"))?;
+        return Ok(Some(path));
+    }
+
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home::home_dir().map(|home| home.join(".config")))
+    };
+
+    Ok(dir.map(|dir| dir.join("ark").join("config.toml")))
+}
+
+/// Parse a configuration file. A missing file is an empty configuration.
+pub fn read(path: &Path) -> anyhow::Result<UserConfig> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(UserConfig::default()),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(toml::from_str(&contents)?)
+}
+
+fn reload(path: &Path) {
+    match read(path) {
+        Ok(config) => {
+            let mut current = USER_CONFIG.write().unwrap();
+            if *current != config {
+                log::info!("Loaded configuration from '{}': {config:?}", path.display());
+                if current.features != config.features {
+                    features::set_overrides(Source::Config, config.features.clone());
+                }
+                if current.execution.stream_flush_interval !=
+                    config.execution.stream_flush_interval
+                {
+                    let interval = config
+                        .execution
+                        .stream_flush_interval
+                        .map_or(DEFAULT_STREAM_FLUSH_INTERVAL, Duration::from_millis);
+                    set_stream_flush_interval(interval);
+                }
+                *current = config;
+            }
+        },
+        Err(err) => {
+            log::error!(
+                "Can't read configuration file '{}': {err:?}",
+                path.display()
+            );
+        },
+    }
+}
+
+fn watch(path: PathBuf) -> anyhow::Result<()> {
+    // Watch the directory rather than the file since editors often replace
+    // files instead of writing to them, and the file might not exist yet
+    let Some(dir) = path.parent() else {
+        return Ok(());
+    };
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    let watched = path.clone();
+    let handler = move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if event.paths.iter().any(|path| path == &watched) {
+            reload(&watched);
+        }
+    };
+
+    let mut watcher = notify::RecommendedWatcher::new(handler, notify::Config::default())?;
+    watcher.watch(dir, notify::RecursiveMode::NonRecursive)?;
+    WATCHER.get_or_init(|| Mutex::new(watcher));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use amalthea::comm::plot_comm::RenderFormat;
+
+    use crate::user_config::read;
+    use crate::user_config::PlotFormat;
+    use crate::user_config::UserConfig;
+
+    #[test]
+    fn test_user_config_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        // Missing files are empty configurations
+        assert_eq!(read(&path).unwrap(), UserConfig::default());
+
+        std::fs::write(
+            &path,
+            "[completions]\nfunction_parentheses = false\ndisabled_sources = [\"snippets\"]\n\n[execution]\nstream_flush_interval = 0\n\n[plots]\nformat = \"svg\"\n\n[features]\ncomm_chunking = false\n",
+        )
+        .unwrap();
+        let config = read(&path).unwrap();
+        assert!(!config.completions.function_parentheses);
+        assert_eq!(config.completions.disabled_sources, vec!["snippets"]);
+        assert_eq!(config.plots.format, PlotFormat::Svg);
+        assert_eq!(RenderFormat::from(config.plots.format), RenderFormat::Svg);
+        assert_eq!(config.features.get("comm_chunking"), Some(&false));
+        assert_eq!(config.execution.stream_flush_interval, Some(0));
+
+        // Unset settings keep their defaults
+        assert_eq!(config.plots.width, 800);
+        assert!(!config.evaluation.allow_function_calls);
+        assert!(config.startup.check_library);
+        assert!(!config.startup.warmup_packages);
+        assert!(!config.startup.environment_fingerprint);
+        assert!(!config.execution.isolate_state);
+
+        // Typos are reported
+        std::fs::write(&path, "[plots]\nfromat = \"svg\"\n").unwrap();
+        assert!(read(&path).is_err());
+        std::fs::write(&path, "[plots]\nformat = \"pdf\"\n").unwrap();
+        assert!(read(&path).is_err());
+    }
+}
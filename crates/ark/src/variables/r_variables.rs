@@ -8,40 +8,60 @@
 use amalthea::comm::comm_channel::CommMsg;
 use amalthea::comm::event::CommManagerEvent;
 use amalthea::comm::variables_comm::ClipboardFormatFormat;
+use amalthea::comm::variables_comm::ExportObjectFormat;
+use amalthea::comm::variables_comm::ExportedObject;
 use amalthea::comm::variables_comm::FormattedVariable;
 use amalthea::comm::variables_comm::InspectedVariable;
 use amalthea::comm::variables_comm::RefreshParams;
+use amalthea::comm::variables_comm::SetViewGroupBy;
+use amalthea::comm::variables_comm::SetViewParams;
+use amalthea::comm::variables_comm::SetViewSortBy;
 use amalthea::comm::variables_comm::UpdateParams;
 use amalthea::comm::variables_comm::Variable;
+use amalthea::comm::variables_comm::VariableGroup;
+use amalthea::comm::variables_comm::VariableGroupKind;
+use amalthea::comm::variables_comm::VariableKind;
 use amalthea::comm::variables_comm::VariableList;
 use amalthea::comm::variables_comm::VariablesBackendReply;
 use amalthea::comm::variables_comm::VariablesBackendRequest;
 use amalthea::comm::variables_comm::VariablesFrontendEvent;
 use amalthea::socket::comm::CommSocket;
+use anyhow::anyhow;
 use crossbeam::channel::select;
 use crossbeam::channel::unbounded;
 use crossbeam::channel::Sender;
+use harp::cancellation::is_cancelled;
 use harp::environment::Binding;
+use harp::environment::BindingValue;
 use harp::environment::Environment;
 use harp::environment::EnvironmentFilter;
 use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
 use harp::object::RObject;
 use harp::utils::r_assert_type;
+use harp::utils::r_inherits;
+use harp::utils::r_typeof;
 use harp::vector::CharacterVector;
 use harp::vector::Vector;
 use libr::R_GlobalEnv;
 use libr::Rf_ScalarLogical;
 use libr::ENVSXP;
+use regex::Regex;
+use rustc_hash::FxHashMap;
 use stdext::spawn;
 
 use crate::data_explorer::r_data_explorer::DataObjectEnvInfo;
 use crate::data_explorer::r_data_explorer::RDataExplorer;
+use crate::export_object::export_object;
 use crate::lsp::events::EVENTS;
 use crate::r_task;
+use crate::r_task::r_task_cancellable;
 use crate::thread::RThreadSafe;
 use crate::variables::variable::PositronVariable;
 
+/// Versions of the variables protocol supported by ark
+pub const VARIABLES_PROTOCOL_VERSIONS: &[u32] = &[1];
+
 /**
  * The R Variables handler provides the server side of Positron's Variables panel, and is
  * responsible for creating and updating the list of variables.
@@ -66,6 +86,21 @@ pub struct RVariables {
     /// thread. Tracked in https://github.com/posit-dev/positron/issues/1812
     current_bindings: RThreadSafe<Vec<Binding>>,
     version: u64,
+
+    /// How the frontend wants variables to be grouped, filtered, and sorted
+    view: SetViewParams,
+
+    /// The compiled `filter` of the view
+    filter: Option<Regex>,
+
+    /// When each variable was last assigned, in milliseconds since the epoch.
+    /// Used to sort by most recent modification.
+    updated_times: FxHashMap<String, i64>,
+
+    /// The variables as last sent to the frontend, by name. Bindings to
+    /// objects that can be modified in place are compared against their
+    /// snapshot on each update, since their value doesn't change.
+    snapshot: FxHashMap<String, Variable>,
 }
 
 impl RVariables {
@@ -99,6 +134,10 @@ impl RVariables {
                 env,
                 current_bindings,
                 version: 0,
+                view: default_view(),
+                filter: None,
+                updated_times: FxHashMap::default(),
+                snapshot: FxHashMap::default(),
             };
             environment.execution_thread();
         });
@@ -115,15 +154,18 @@ impl RVariables {
             }
         });
 
-        // Perform the initial environment scan and deliver to the frontend
-        let variables = self.list_variables();
-        let length = variables.len() as i64;
-        let event = VariablesFrontendEvent::Refresh(RefreshParams {
-            variables,
-            length,
-            version: self.version as i64,
+        // Register a handler for console interrupt events. These are emitted
+        // right before the prompt event that follows an interrupt.
+        let (interrupt_signal_tx, interrupt_signal_rx) = unbounded::<()>();
+        let interrupt_listen_id = EVENTS.console_interrupted.listen({
+            move |_| {
+                log::info!("Got console interrupt signal.");
+                interrupt_signal_tx.send(()).unwrap();
+            }
         });
-        self.send_event(event, None);
+
+        // Perform the initial environment scan and deliver to the frontend
+        self.refresh();
 
         // Flag initially set to false, but set to true if the user closes the
         // channel (i.e. the frontend is closed)
@@ -139,6 +181,15 @@ impl RVariables {
                     }
                 },
 
+                // An interrupt might have prevented us from seeing some
+                // changes, so resync the whole list rather than sending an
+                // incremental update based on possibly stale bindings
+                recv(&interrupt_signal_rx) -> msg => {
+                    if let Ok(()) = msg {
+                        self.refresh();
+                    }
+                },
+
                 recv(&self.comm.incoming_rx) -> msg => {
                     let msg = match msg {
                         Ok(msg) => msg,
@@ -174,6 +225,7 @@ impl RVariables {
         }
 
         EVENTS.console_prompt.remove(listen_id);
+        EVENTS.console_interrupted.remove(interrupt_listen_id);
 
         if !user_initiated_close {
             // Send a close message to the frontend if the frontend didn't
@@ -190,18 +242,114 @@ impl RVariables {
         self.version
     }
 
+    /// Send the full list of variables to the frontend
+    fn refresh(&mut self) {
+        let list = self.list_variables();
+        let event = VariablesFrontendEvent::Refresh(RefreshParams {
+            variables: list.variables,
+            length: list.length,
+            version: self.version as i64,
+            groups: list.groups,
+        });
+        self.send_event(event, None);
+    }
+
     #[tracing::instrument(level = "trace", skip_all)]
-    fn list_variables(&mut self) -> Vec<Variable> {
+    fn list_variables(&mut self) -> VariableList {
         let mut variables: Vec<Variable> = vec![];
-        r_task(|| {
+        r_task_cancellable(self.comm.closed.clone(), || {
             self.update_bindings(self.bindings());
 
             for binding in self.current_bindings.get() {
-                variables.push(PositronVariable::new(binding).var());
+                // Describing variables formats their values, which can take
+                // a while in large environments
+                if is_cancelled() {
+                    break;
+                }
+
+                let mut variable = PositronVariable::new(binding).var();
+
+                // Report when the variable was last assigned rather than
+                // when it was listed
+                variable.updated_time = *self
+                    .updated_times
+                    .entry(binding.name.to_string())
+                    .or_insert(variable.updated_time);
+
+                variables.push(variable);
             }
         });
 
-        variables
+        self.snapshot = variables
+            .iter()
+            .map(|variable| (variable.display_name.clone(), variable.clone()))
+            .collect();
+
+        let length = variables.len() as i64;
+        let (variables, groups) = self.arrange_variables(variables);
+
+        VariableList {
+            variables,
+            length,
+            version: Some(self.version as i64),
+            groups,
+        }
+    }
+
+    /// Sort `variables` and group them by kind according to the view.
+    /// `variables` must be sorted by name.
+    fn arrange_variables(
+        &self,
+        mut variables: Vec<Variable>,
+    ) -> (Vec<Variable>, Option<Vec<VariableGroup>>) {
+        // Sorts are stable so ties stay sorted by name
+        match self.view.sort_by {
+            SetViewSortBy::Name => {},
+            SetViewSortBy::Size => variables.sort_by(|a, b| b.size.cmp(&a.size)),
+            SetViewSortBy::Recent => variables.sort_by(|a, b| b.updated_time.cmp(&a.updated_time)),
+        }
+
+        if let SetViewGroupBy::None = self.view.group_by {
+            return (variables, None);
+        }
+
+        let mut arranged = Vec::with_capacity(variables.len());
+        let mut groups = vec![];
+
+        for kind in [
+            VariableGroupKind::Data,
+            VariableGroupKind::Functions,
+            VariableGroupKind::Values,
+        ] {
+            let (group, rest): (Vec<Variable>, Vec<Variable>) = variables
+                .into_iter()
+                .partition(|variable| variable_group_kind(variable) == kind);
+            variables = rest;
+
+            if group.is_empty() {
+                continue;
+            }
+
+            groups.push(VariableGroup {
+                kind,
+                length: group.len() as i64,
+            });
+            arranged.extend(group);
+        }
+
+        (arranged, Some(groups))
+    }
+
+    /// Set how variables are grouped, filtered, and sorted
+    fn set_view(&mut self, view: SetViewParams) -> anyhow::Result<()> {
+        self.filter = match &view.filter {
+            Some(filter) => Some(
+                Regex::new(filter).map_err(|err| anyhow!("Invalid filter '{filter}': {err}"))?,
+            ),
+            None => None,
+        };
+        self.view = view;
+        Ok(())
     }
 
     fn handle_rpc(
@@ -210,13 +358,7 @@ impl RVariables {
     ) -> anyhow::Result<VariablesBackendReply> {
         match req {
             VariablesBackendRequest::List => {
-                let list = self.list_variables();
-                let count = list.len() as i64;
-                Ok(VariablesBackendReply::ListReply(VariableList {
-                    variables: list,
-                    length: count,
-                    version: Some(self.version as i64),
-                }))
+                Ok(VariablesBackendReply::ListReply(self.list_variables()))
             },
             VariablesBackendRequest::Clear(params) => {
                 self.clear(params.include_hidden_objects)?;
@@ -245,6 +387,17 @@ impl RVariables {
                 let viewer_id = self.view(&params.path)?;
                 Ok(VariablesBackendReply::ViewReply(viewer_id))
             },
+            VariablesBackendRequest::SetView(params) => {
+                self.set_view(params)?;
+                Ok(VariablesBackendReply::SetViewReply(self.list_variables()))
+            },
+            VariablesBackendRequest::ExportObject(params) => {
+                let format = self.export_object(&params.path, &params.file, params.format)?;
+                Ok(VariablesBackendReply::ExportObjectReply(ExportedObject {
+                    file: params.file,
+                    format,
+                }))
+            },
         }
     }
 
@@ -337,6 +490,25 @@ impl RVariables {
         })
     }
 
+    /// Save a variable to a file.
+    ///
+    /// - `path`: The path to the variable to export, as an array of access keys
+    /// - `file`: The path of the file to write
+    /// - `format`: The format to write, if requested by the frontend
+    fn export_object(
+        &mut self,
+        path: &Vec<String>,
+        file: &str,
+        format: Option<ExportObjectFormat>,
+    ) -> anyhow::Result<ExportObjectFormat> {
+        r_task_cancellable(self.comm.closed.clone(), || {
+            let env = self.env.get().clone();
+            let object = PositronVariable::resolve_data_object(env, &path)?;
+            let name = unsafe { path.get_unchecked(path.len() - 1) };
+            export_object(object.sexp, file, format, name)
+        })
+    }
+
     fn send_event(&mut self, message: VariablesFrontendEvent, request_id: Option<String>) {
         let data = serde_json::to_value(message);
 
@@ -360,9 +532,10 @@ impl RVariables {
     #[tracing::instrument(level = "trace", skip_all)]
     fn update(&mut self, request_id: Option<String>) {
         let mut assigned: Vec<Variable> = vec![];
+        let mut updated: Vec<Variable> = vec![];
         let mut removed: Vec<String> = vec![];
 
-        r_task(|| {
+        r_task_cancellable(self.comm.closed.clone(), || {
             let new_bindings = self.bindings();
 
             let mut old_iter = self.current_bindings.get().iter();
@@ -372,6 +545,11 @@ impl RVariables {
             let mut new_next = new_iter.next();
 
             loop {
+                // Leave the bindings as they were, the comm is going away
+                if is_cancelled() {
+                    return;
+                }
+
                 match (old_next, new_next) {
                     // nothing more to do
                     (None, None) => break,
@@ -379,6 +557,9 @@ impl RVariables {
                     // No more old, collect last new into added
                     (None, Some(mut new)) => {
                         loop {
+                            if is_cancelled() {
+                                return;
+                            }
                             assigned.push(PositronVariable::new(&new).var());
 
                             match new_iter.next() {
@@ -411,6 +592,15 @@ impl RVariables {
                         if old.name == new.name {
                             if old.value != new.value {
                                 assigned.push(PositronVariable::new(&new).var());
+                            } else if is_modifiable_in_place(&new.value) {
+                                let variable = PositronVariable::new(&new).var();
+                                let changed = match self.snapshot.get(&variable.display_name) {
+                                    Some(snapshot) => !is_same_summary(snapshot, &variable),
+                                    None => true,
+                                };
+                                if changed {
+                                    updated.push(variable);
+                                }
                             }
                             old_next = old_iter.next();
                             new_next = new_iter.next();
@@ -426,15 +616,31 @@ impl RVariables {
             }
 
             // Only update the bindings (and the version) if anything changed
-            if assigned.len() > 0 || removed.len() > 0 {
+            if assigned.len() > 0 || updated.len() > 0 || removed.len() > 0 {
                 self.update_bindings(new_bindings);
             }
         });
 
-        if assigned.len() > 0 || removed.len() > 0 || request_id.is_some() {
+        if self.comm.closed.is_cancelled() {
+            return;
+        }
+
+        for variable in assigned.iter().chain(updated.iter()) {
+            self.updated_times
+                .insert(variable.display_name.clone(), variable.updated_time);
+            self.snapshot
+                .insert(variable.display_name.clone(), variable.clone());
+        }
+        for name in &removed {
+            self.updated_times.remove(name);
+            self.snapshot.remove(name);
+        }
+
+        if assigned.len() > 0 || updated.len() > 0 || removed.len() > 0 || request_id.is_some() {
             // Send the message if anything changed or if this came from a request
             let event = VariablesFrontendEvent::Update(UpdateParams {
                 assigned,
+                updated,
                 removed,
                 unevaluated: vec![],
                 version: self.version as i64,
@@ -447,12 +653,62 @@ impl RVariables {
 
     fn bindings(&self) -> RThreadSafe<Vec<Binding>> {
         let env = self.env.get().clone();
-        let env = Environment::new_filtered(env, EnvironmentFilter::ExcludeHidden);
 
-        let mut bindings: Vec<Binding> = env.iter().filter_map(|b| b.ok()).collect();
+        let filter = if self.view.show_hidden {
+            EnvironmentFilter::None
+        } else {
+            EnvironmentFilter::ExcludeHidden
+        };
+        let env = Environment::new_filtered(env, filter);
+
+        let mut bindings: Vec<Binding> = env
+            .iter()
+            .filter_map(|b| b.ok())
+            .filter(|b| match &self.filter {
+                Some(filter) => filter.is_match(&b.name.to_string()),
+                None => true,
+            })
+            .collect();
 
         bindings.sort_by(|a, b| a.name.cmp(&b.name));
 
         RThreadSafe::new(bindings)
     }
 }
+
+/// The view of the variables pane when the frontend hasn't configured one
+fn default_view() -> SetViewParams {
+    SetViewParams {
+        group_by: SetViewGroupBy::None,
+        filter: None,
+        sort_by: SetViewSortBy::Name,
+        show_hidden: false,
+    }
+}
+
+/// Whether the object bound to a binding can change without the binding
+/// changing, e.g. environments such as R6 objects, or data.tables updated by
+/// reference
+fn is_modifiable_in_place(value: &BindingValue) -> bool {
+    let BindingValue::Standard { object, .. } = value else {
+        return false;
+    };
+    r_typeof(object.sexp) == ENVSXP || r_inherits(object.sexp, "data.table")
+}
+
+/// Whether two summaries of a variable only differ by their update time
+fn is_same_summary(old: &Variable, new: &Variable) -> bool {
+    let new = Variable {
+        updated_time: old.updated_time,
+        ..new.clone()
+    };
+    *old == new
+}
+
+fn variable_group_kind(variable: &Variable) -> VariableGroupKind {
+    match variable.kind {
+        VariableKind::Table => VariableGroupKind::Data,
+        VariableKind::Function => VariableGroupKind::Functions,
+        _ => VariableGroupKind::Values,
+    }
+}
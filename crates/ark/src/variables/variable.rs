@@ -12,6 +12,8 @@ use amalthea::comm::variables_comm::ClipboardFormatFormat;
 use amalthea::comm::variables_comm::Variable;
 use amalthea::comm::variables_comm::VariableKind;
 use anyhow::anyhow;
+use harp::array_preview::is_array;
+use harp::array_preview::ArrayPreview;
 use harp::call::RArgument;
 use harp::environment::Binding;
 use harp::environment::BindingValue;
@@ -25,6 +27,8 @@ use harp::object::RObject;
 use harp::r_null;
 use harp::r_symbol;
 use harp::symbol::RSymbol;
+use harp::text_width::text_width;
+use harp::text_width::truncate_to_width;
 use harp::utils::pairlist_size;
 use harp::utils::r_altrep_class;
 use harp::utils::r_assert_type;
@@ -59,6 +63,8 @@ use crate::methods::ArkGenerics;
 // Constants.
 const MAX_DISPLAY_VALUE_ENTRIES: usize = 1_000;
 const MAX_DISPLAY_VALUE_LENGTH: usize = 100;
+const MAX_DISPLAY_VALUE_ROWS: usize = 10;
+const MAX_DISPLAY_VALUE_COLUMNS: usize = 10;
 
 pub struct WorkspaceVariableDisplayValue {
     pub display_value: String,
@@ -90,7 +96,7 @@ impl WorkspaceVariableDisplayValue {
             },
             CLOSXP => Self::from_closure(value),
             ENVSXP => Self::from_env(value),
-            _ if r_is_matrix(value) => Self::from_matrix(value),
+            _ if is_array(value) => Self::from_array(value),
             _ => Self::from_default(value),
         }
     }
@@ -153,7 +159,7 @@ impl WorkspaceVariableDisplayValue {
             }
             display_value.push_str(&display_i.display_value);
 
-            if display_value.len() > MAX_DISPLAY_VALUE_LENGTH || display_i.is_truncated {
+            if text_width(&display_value) > MAX_DISPLAY_VALUE_LENGTH || display_i.is_truncated {
                 is_truncated = true;
             }
         }
@@ -229,7 +235,7 @@ impl WorkspaceVariableDisplayValue {
 
             // When the display value becomes too long, mark it as truncated and stop
             // building it.
-            if i == 10 || display_value.len() > MAX_DISPLAY_VALUE_LENGTH {
+            if i == 10 || text_width(&display_value) > MAX_DISPLAY_VALUE_LENGTH {
                 // If there are remaining entries, set the is_truncated flag and append a
                 // counter of how many more entries there are.
                 let remaining_entries = environment_length - 1 - i;
@@ -249,48 +255,18 @@ impl WorkspaceVariableDisplayValue {
         Self::new(display_value, is_truncated)
     }
 
-    // TODO: handle higher dimensional arrays, i.e. expand
-    //       recursively from the higher dimension
-    fn from_matrix(value: SEXP) -> Self {
-        let formatted = unwrap!(FormattedVector::new(value), Err(err) => {
+    /// The top-left corner of matrices and arrays, by column
+    fn from_array(value: SEXP) -> Self {
+        let preview = ArrayPreview::new(value, MAX_DISPLAY_VALUE_ROWS, MAX_DISPLAY_VALUE_COLUMNS);
+        let preview = unwrap!(preview, Err(err) => {
             return Self::from_error(err);
         });
 
-        let mut first = true;
-        let mut display_value = String::from("");
-        let mut is_truncated = false;
-
-        unsafe {
-            let dim = IntegerVector::new_unchecked(Rf_getAttrib(value, R_DimSymbol));
-            let n_col = dim.get_unchecked(1).unwrap() as isize;
-            display_value.push('[');
-            for i in 0..n_col {
-                if first {
-                    first = false;
-                } else {
-                    display_value.push_str(", ");
-                }
-
-                display_value.push('[');
-                let display_column = formatted.column_iter(i).join(" ");
-                if display_column.len() > MAX_DISPLAY_VALUE_LENGTH {
-                    is_truncated = true;
-                    // TODO: maybe this should only push_str() a slice
-                    //       of the first n (MAX_WIDTH?) characters in that case ?
-                }
-                display_value.push_str(display_column.as_str());
-                display_value.push(']');
-
-                if display_value.len() > MAX_DISPLAY_VALUE_LENGTH {
-                    is_truncated = true;
-                }
-                if is_truncated {
-                    break;
-                }
-            }
-            display_value.push(']');
-        }
-        Self::new(display_value, is_truncated)
+        let display_value = Self::from_untruncated_string(preview.inline());
+        Self::new(
+            display_value.display_value,
+            display_value.is_truncated || preview.is_truncated,
+        )
     }
 
     fn from_default(value: SEXP) -> Self {
@@ -309,7 +285,7 @@ impl WorkspaceVariableDisplayValue {
                 display_value.push(' ');
             }
             display_value.push_str(&x);
-            if display_value.len() > MAX_DISPLAY_VALUE_LENGTH {
+            if text_width(&display_value) > MAX_DISPLAY_VALUE_LENGTH {
                 is_truncated = true;
                 break;
             }
@@ -323,14 +299,11 @@ impl WorkspaceVariableDisplayValue {
         Self::new(String::from("??"), true)
     }
 
-    fn from_untruncated_string(mut value: String) -> Self {
-        let Some((index, _)) = value.char_indices().nth(MAX_DISPLAY_VALUE_LENGTH) else {
-            return Self::new(value, false);
-        };
-
-        // If an index is found, truncate the string to that index
-        value.truncate(index);
-        Self::new(value, true)
+    fn from_untruncated_string(value: String) -> Self {
+        match truncate_to_width(&value, MAX_DISPLAY_VALUE_LENGTH) {
+            Some(truncated) => Self::new(truncated.to_string(), true),
+            None => Self::new(value, false),
+        }
     }
 
     fn try_from_method(value: SEXP) -> Option<Self> {
@@ -1730,6 +1703,44 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_display_value_array() {
+        r_task(|| {
+            let x = harp::parse_eval_base("matrix(1:4, 2, dimnames = list(NULL, c('x', 'y')))")
+                .unwrap();
+            let display_value = WorkspaceVariableDisplayValue::from(x.sexp);
+            assert_eq!(display_value.display_value, "[x: [1 2], y: [3 4]]");
+            assert!(!display_value.is_truncated);
+
+            // The corner of the first slice
+            let x = harp::parse_eval_base("array(1:60, c(12, 2, 2))").unwrap();
+            let display_value = WorkspaceVariableDisplayValue::from(x.sexp);
+            assert_eq!(
+                display_value.display_value,
+                "[[1 2 3 4 5 6 7 8 9 10 …], [13 14 15 16 17 18 19 20 21 22 …]]"
+            );
+            assert!(display_value.is_truncated);
+        })
+    }
+
+    #[test]
+    fn test_display_value_wide_characters() {
+        // Display values are limited in columns rather than characters
+        let display_value = WorkspaceVariableDisplayValue::from_untruncated_string("日".repeat(60));
+        assert_eq!(display_value.display_value, "日".repeat(50));
+        assert!(display_value.is_truncated);
+
+        r_task(|| {
+            let x = harp::parse_eval_base("strrep('\\u65e5', 1:2)").unwrap();
+            let display_value = WorkspaceVariableDisplayValue::from(x.sexp);
+            assert!(!display_value.is_truncated);
+
+            let x = harp::parse_eval_base("strrep('\\u65e5', 60)").unwrap();
+            let display_value = WorkspaceVariableDisplayValue::from(x.sexp);
+            assert!(display_value.is_truncated);
+        })
+    }
+
     #[test]
     fn test_inspect_list() {
         r_task(|| {
@@ -0,0 +1,258 @@
+//
+// wait.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::fmt;
+use std::sync::LazyLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use crossbeam::channel::Receiver;
+use crossbeam::channel::RecvTimeoutError;
+use stdext::cancellation::CancellationToken;
+
+/// Waiting for an R task to start and complete. Tasks queue up behind
+/// whatever R is currently evaluating so we never give up, but long waits are
+/// logged.
+pub const R_TASK: WaitPolicy = WaitPolicy::new("R task");
+
+/// Waiting for the frontend to reply to a request of the UI comm. Some
+/// requests wait on the user, e.g. modal dialogs, so we never give up.
+pub const UI_FRONTEND_REPLY: WaitPolicy = WaitPolicy::new("reply from the frontend");
+
+/// Waiting for a comm to send a message to the frontend in tests. Comms reply
+/// quickly so a slow reply is a bug.
+pub const COMM_MESSAGE: WaitPolicy =
+    WaitPolicy::new("message from comm").with_timeout(Duration::from_secs(1));
+
+/// Waiting for the comm manager to notify of a new comm in tests
+pub const COMM_MANAGER_EVENT: WaitPolicy =
+    WaitPolicy::new("comm manager event").with_timeout(Duration::from_secs(1));
+
+/// How often indefinite waits are logged
+const LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often cancellable waits check their cancellation token
+const CANCELLATION_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Multiplier applied to all timeouts, set from the `ARK_TIMEOUT_SCALE`
+/// environment variable. Useful on slow machines, e.g. CI runners, and to
+/// tell slow replies apart from hangs.
+static TIMEOUT_SCALE: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("ARK_TIMEOUT_SCALE")
+        .ok()
+        .and_then(|scale| scale.parse::<f64>().ok())
+        .filter(|scale| scale.is_finite() && *scale > 0.0)
+        .unwrap_or(1.0)
+});
+
+/// Policy for a thread waiting on a reply from another thread.
+///
+/// Waits are split into attempts. Each attempt that runs out of time is
+/// logged as a warning, so that hangs show up in the logs along with what was
+/// being waited for. Once all attempts have timed out, the wait fails with
+/// `WaitError::Timeout`.
+///
+/// Without a timeout, attempts last `LOG_INTERVAL` and the wait never times
+/// out.
+#[derive(Debug, Clone, Copy)]
+pub struct WaitPolicy {
+    /// Describes what is being waited for, for logs and errors
+    pub what: &'static str,
+
+    /// Duration of each attempt, scaled by `ARK_TIMEOUT_SCALE`. `None` to
+    /// wait indefinitely.
+    pub timeout: Option<Duration>,
+
+    /// Number of attempts after the first one has timed out
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WaitError {
+    Timeout {
+        what: &'static str,
+        elapsed: Duration,
+    },
+    /// The other thread dropped its end of the channel without replying
+    Disconnected {
+        what: &'static str,
+    },
+    Cancelled {
+        what: &'static str,
+    },
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitError::Timeout { what, elapsed } => {
+                write!(f, "Timed out after {elapsed:?} while waiting for {what}")
+            },
+            WaitError::Disconnected { what } => {
+                write!(f, "Channel disconnected while waiting for {what}")
+            },
+            WaitError::Cancelled { what } => write!(f, "Cancelled while waiting for {what}"),
+        }
+    }
+}
+
+impl std::error::Error for WaitError {}
+
+impl WaitPolicy {
+    pub const fn new(what: &'static str) -> Self {
+        Self {
+            what,
+            timeout: None,
+            retries: 0,
+        }
+    }
+
+    pub const fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    pub const fn with_retries(self, retries: u32) -> Self {
+        Self { retries, ..self }
+    }
+
+    /// Block until a message is received on `rx`
+    pub fn recv<T>(&self, rx: &Receiver<T>) -> Result<T, WaitError> {
+        self.recv_impl(rx, None)
+    }
+
+    /// Block until a message is received on `rx`, or until `token` is
+    /// cancelled from another thread, e.g. when the comm waiting for the
+    /// reply is closed
+    pub fn recv_cancellable<T>(
+        &self,
+        rx: &Receiver<T>,
+        token: &CancellationToken,
+    ) -> Result<T, WaitError> {
+        self.recv_impl(rx, Some(token))
+    }
+
+    fn recv_impl<T>(
+        &self,
+        rx: &Receiver<T>,
+        token: Option<&CancellationToken>,
+    ) -> Result<T, WaitError> {
+        let what = self.what;
+        let start = Instant::now();
+
+        let attempt_timeout = match self.timeout {
+            Some(timeout) => timeout.mul_f64(*TIMEOUT_SCALE),
+            None => LOG_INTERVAL,
+        };
+
+        // Tokens don't wake us up so we poll them
+        let poll_timeout = match token {
+            Some(_) => attempt_timeout.min(CANCELLATION_POLL_INTERVAL),
+            None => attempt_timeout,
+        };
+
+        let mut attempt = 0;
+        let mut attempt_start = Instant::now();
+
+        loop {
+            if token.is_some_and(|token| token.is_cancelled()) {
+                return Err(WaitError::Cancelled { what });
+            }
+
+            match rx.recv_timeout(poll_timeout) {
+                Ok(msg) => return Ok(msg),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(WaitError::Disconnected { what })
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+            }
+
+            if attempt_start.elapsed() < attempt_timeout {
+                continue;
+            }
+
+            let elapsed = start.elapsed();
+
+            if self.timeout.is_some() && attempt >= self.retries {
+                log::error!("Timed out after {elapsed:?} while waiting for {what}.");
+                return Err(WaitError::Timeout { what, elapsed });
+            }
+
+            attempt += 1;
+            attempt_start = Instant::now();
+            log::warn!("Still waiting for {what} after {elapsed:?}.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use crossbeam::channel::bounded;
+    use stdext::cancellation::CancellationToken;
+
+    use crate::wait::WaitError;
+    use crate::wait::WaitPolicy;
+
+    const POLICY: WaitPolicy = WaitPolicy::new("test").with_timeout(Duration::from_millis(10));
+
+    #[test]
+    fn test_wait_recv() {
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+        assert_eq!(POLICY.recv(&rx), Ok(1));
+
+        drop(tx);
+        assert_eq!(
+            POLICY.recv(&rx),
+            Err(WaitError::Disconnected { what: "test" })
+        );
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        let (_tx, rx) = bounded::<()>(1);
+        let Err(WaitError::Timeout { elapsed, .. }) = POLICY.with_retries(2).recv(&rx) else {
+            panic!("Expected a timeout");
+        };
+        assert!(elapsed >= Duration::from_millis(30));
+
+        // Replies arriving during a retry are received
+        let (tx, rx) = bounded(1);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send(1).unwrap();
+        });
+        assert_eq!(POLICY.with_retries(100).recv(&rx), Ok(1));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_cancellation() {
+        let (_tx, rx) = bounded::<()>(1);
+        let token = CancellationToken::new();
+
+        let handle = thread::spawn({
+            let token = token.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                token.cancel();
+            }
+        });
+
+        let policy = WaitPolicy::new("test");
+        assert_eq!(
+            policy.recv_cancellable(&rx, &token),
+            Err(WaitError::Cancelled { what: "test" })
+        );
+        handle.join().unwrap();
+    }
+}
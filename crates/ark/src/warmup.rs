@@ -0,0 +1,181 @@
+//
+// warmup.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Background warmup of the metadata of attached packages. The exports of
+// attached packages are lazy-loaded: their bindings are promises that fetch
+// the object from the package database when forced. Completions force them
+// to tell functions from other objects, which makes the first completions of
+// a session with many attached packages slow.
+//
+// The warmup is opt-in, see `StartupConfig::warmup_packages`. Only the
+// functions exported by namespaces are kept. A promise can't tell what it
+// holds without being forced, so the other objects are loaded too but
+// dropped right away, leaving their promise untouched. Datasets of packages
+// (their lazydata, like the contents of `package:datasets`) are skipped
+// altogether: they may be large and loading them would only waste memory.
+//
+// The warmup runs as an idle task once the kernel first reports idle, so it
+// never delays readiness. It loads the promises package by package and yields
+// back to the R event loop between slices of work, so that execute requests
+// and other tasks don't wait behind it. It stops for good as soon as the
+// session gets busy with an execution, or shuts down.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use harp::environment::r_ns_env;
+use harp::environment::BindingValue;
+use harp::environment::Environment;
+use harp::utils::r_env_is_pkg_env;
+use harp::utils::r_is_function;
+use harp::utils::r_pkg_env_name;
+use harp::utils::r_promise_is_lazy_load_binding;
+use harp::RObject;
+use libr::R_EmptyEnv;
+use libr::R_GlobalEnv;
+use libr::ENCLOS;
+use libr::PRCODE;
+use libr::PRENV;
+use libr::SET_PRVALUE;
+use libr::SEXP;
+
+use crate::interface::RMain;
+use crate::r_task;
+use crate::teardown;
+
+/// How long the warmup may keep the R thread before yielding
+const WARMUP_SLICE: Duration = Duration::from_millis(10);
+
+/// Spawn the warmup of attached packages. It starts once the kernel is idle.
+pub(crate) fn spawn() {
+    // Don't slow down integration tests, where idle tasks run right away
+    if stdext::IS_TESTING {
+        return;
+    }
+
+    r_task::spawn_idle(|| async move {
+        if let Err(err) = warmup_packages().await {
+            log::error!("Can't warm up attached packages: {err:?}");
+        }
+    });
+}
+
+async fn warmup_packages() -> anyhow::Result<()> {
+    let start = Instant::now();
+    let mut tick = Instant::now();
+
+    // Executions bump the count, which tells us the session got busy even
+    // though we don't observe the execution itself
+    let execution_count = RMain::get().execution_count();
+    let is_cancelled =
+        || teardown::SHUTDOWN.is_cancelled() || RMain::get().execution_count() != execution_count;
+
+    let packages = attached_packages()?;
+    let n_packages = packages.len();
+    log::info!("Warming up {n_packages} attached packages");
+
+    let mut n_total = 0;
+
+    for (i, (name, env)) in packages.into_iter().enumerate() {
+        let mut n_loaded = 0;
+        let lazydata = lazydata_env(&name);
+
+        for binding in Environment::new(env).iter().filter_map(Result::ok) {
+            // Forced promises are reported as standard bindings
+            let BindingValue::Promise { promise } = binding.value else {
+                continue;
+            };
+
+            if let Some(lazydata) = &lazydata {
+                if lazydata.exists(binding.name) {
+                    continue;
+                }
+            }
+
+            // Other promises may run arbitrary code
+            if !unsafe { r_promise_is_lazy_load_binding(promise.sexp) } {
+                continue;
+            }
+
+            match force_if_function(promise.sexp) {
+                Ok(true) => n_loaded += 1,
+                Ok(false) => {},
+                Err(err) => log::trace!("Can't load `{name}::{}`: {err:?}", binding.name),
+            }
+
+            if tick.elapsed() > WARMUP_SLICE {
+                tokio::task::yield_now().await;
+                tick = Instant::now();
+
+                if is_cancelled() {
+                    log::info!(
+                        "Stopped warming up attached packages after {i}/{n_packages} \
+                         packages: the session is busy"
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        log::info!(
+            "Warmed up package `{name}` ({}/{n_packages}): {n_loaded} functions loaded",
+            i + 1
+        );
+        n_total += n_loaded;
+    }
+
+    log::info!(
+        "Warmed up {n_packages} attached packages in {} ms: {n_total} functions loaded",
+        start.elapsed().as_millis()
+    );
+
+    Ok(())
+}
+
+/// Load the object of a lazy-load `promise` and keep it in the promise if it's
+/// a function. Other objects are dropped and the promise is left untouched,
+/// like on errors (see `r_promise_force_with_rollback()`). Returns whether the
+/// promise was forced.
+fn force_if_function(promise: SEXP) -> harp::Result<bool> {
+    unsafe {
+        let value = harp::try_eval_silent(PRCODE(promise), PRENV(promise))?;
+
+        if !r_is_function(value.sexp) {
+            return Ok(false);
+        }
+
+        SET_PRVALUE(promise, value.sexp);
+        Ok(true)
+    }
+}
+
+/// Environment of the datasets of a package, if it has a namespace
+fn lazydata_env(package: &str) -> Option<Environment> {
+    let ns = r_ns_env(package).ok()?;
+    let info = ns.find(".__NAMESPACE__.").ok()?;
+    let lazydata = Environment::view(info).find("lazydata").ok()?;
+    Some(Environment::new(lazydata.into()))
+}
+
+/// Names and environments of the packages on the search path
+fn attached_packages() -> anyhow::Result<Vec<(String, RObject)>> {
+    let mut packages = vec![];
+
+    unsafe {
+        let mut env = ENCLOS(R_GlobalEnv);
+
+        while env != R_EmptyEnv {
+            if r_env_is_pkg_env(env) {
+                let name: String = RObject::view(r_pkg_env_name(env)).try_into()?;
+                packages.push((name, RObject::new(env)));
+            }
+            env = ENCLOS(env);
+        }
+    }
+
+    Ok(packages)
+}
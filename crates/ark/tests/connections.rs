@@ -15,6 +15,7 @@ use ark::connections::r_connection::RConnection;
 use ark::fixtures::socket_rpc_request;
 use ark::modules::ARK_ENVS;
 use ark::r_task::r_task;
+use ark::wait;
 use crossbeam::channel::bounded;
 use harp::exec::RFunction;
 use harp::object::RObject;
@@ -52,9 +53,7 @@ fn open_dummy_connection() -> socket::comm::CommSocket {
     });
 
     // Wait for the new comm to show up.
-    let msg = comm_manager_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    let msg = wait::COMM_MANAGER_EVENT.recv(&comm_manager_rx).unwrap();
 
     match msg {
         CommManagerEvent::Opened(socket, _value) => {
@@ -205,10 +204,7 @@ fn test_send_frontend_event() {
         .send(CommMsg::Data(serde_json::to_value(event).unwrap()))
         .unwrap();
 
-    let msg = socket
-        .outgoing_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    let msg = wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap();
 
     if let CommMsg::Data(value) = msg {
         let v: ConnectionsFrontendEvent = serde_json::from_value(value).unwrap();
@@ -61,6 +61,7 @@ use ark::fixtures::socket_rpc_request;
 use ark::lsp::events::EVENTS;
 use ark::r_task::r_task;
 use ark::thread::RThreadSafe;
+use ark::wait;
 use crossbeam::channel::bounded;
 use harp::environment::R_ENVS;
 use harp::object::RObject;
@@ -89,9 +90,7 @@ fn open_data_explorer(dataset: String) -> socket::comm::CommSocket {
     });
 
     // Wait for the new comm to show up.
-    let msg = comm_manager_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    let msg = wait::COMM_MANAGER_EVENT.recv(&comm_manager_rx).unwrap();
     match msg {
         CommManagerEvent::Opened(socket, _value) => {
             assert_eq!(socket.comm_name, "positron.dataExplorer");
@@ -122,9 +121,7 @@ fn open_data_explorer_from_expression(
     })?;
 
     // Release the R lock and wait for the new comm to show up.
-    let msg = comm_manager_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    let msg = wait::COMM_MANAGER_EVENT.recv(&comm_manager_rx).unwrap();
 
     match msg {
         CommManagerEvent::Opened(socket, _value) => {
@@ -196,10 +193,7 @@ fn expect_column_profile_results(
     let msg = CommMsg::Rpc(id, json);
     socket.incoming_tx.send(msg).unwrap();
 
-    let msg = socket
-        .outgoing_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    let msg = wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap();
 
     // Because during tests, no threads are created with r_task::spawn_idle, the messages are in
     // an incorrect order. We first receive the DataExplorerFrontndEvent with the column profiles
@@ -217,10 +211,7 @@ fn expect_column_profile_results(
         }
     );
 
-    let msg = socket
-        .outgoing_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    let msg = wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap();
 
     let reply: DataExplorerBackendReply = match msg {
         CommMsg::Rpc(_id, value) => {
@@ -1079,7 +1070,7 @@ fn test_live_updates() {
     EVENTS.console_prompt.emit(());
 
     // Wait for an update event to arrive
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Data(value) => {
             // Make sure it's a data update event.
             assert_match!(serde_json::from_value::<DataExplorerFrontendEvent>(value).unwrap(),
@@ -1121,7 +1112,7 @@ DataExplorerBackendReply::SetSortColumnsReply() => {});
     EVENTS.console_prompt.emit(());
 
     // Wait for an update event to arrive
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Data(value) => {
             // Make sure it's a data update event.
             assert_match!(serde_json::from_value::<DataExplorerFrontendEvent>(value).unwrap(),
@@ -1151,7 +1142,7 @@ DataExplorerBackendReply::SetSortColumnsReply() => {});
     EVENTS.console_prompt.emit(());
 
     // This should trigger a schema update event.
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Data(value) => {
             // Make sure it's schema update event.
             assert_match!(serde_json::from_value::<DataExplorerFrontendEvent>(value).unwrap(),
@@ -1180,7 +1171,7 @@ DataExplorerBackendReply::SetSortColumnsReply() => {});
     EVENTS.console_prompt.emit(());
 
     // Wait for an close event to arrive
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Close => {}
     );
 }
@@ -1367,7 +1358,7 @@ fn test_invalid_filters_preserved() {
     EVENTS.console_prompt.emit(());
 
     // Wait for an update event to arrive
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Data(value) => {
             // Make sure it's a data update event.
             assert_match!(serde_json::from_value::<DataExplorerFrontendEvent>(value).unwrap(),
@@ -1395,7 +1386,7 @@ fn test_invalid_filters_preserved() {
     EVENTS.console_prompt.emit(());
 
     // Wait for an update event to arrive
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Data(value) => {
             // Make sure it's a data update event.
             assert_match!(serde_json::from_value::<DataExplorerFrontendEvent>(value).unwrap(),
@@ -1423,7 +1414,7 @@ fn test_invalid_filters_preserved() {
     EVENTS.console_prompt.emit(());
 
     // Wait for an update event to arrive
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Data(value) => {
             // Make sure it's a data update event.
             assert_match!(serde_json::from_value::<DataExplorerFrontendEvent>(value).unwrap(),
@@ -1671,7 +1662,7 @@ fn test_update_data_filters_reapplied() {
 
     // Wait for an update event to arrive
     // Since only data changed, we expect a Data Update Event
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Data(value) => {
             // Make sure it's a data update event.
             assert_match!(serde_json::from_value::<DataExplorerFrontendEvent>(value).unwrap(),
@@ -1767,7 +1758,7 @@ fn test_data_update_num_rows() {
     EVENTS.console_prompt.emit(());
 
     // Wait for an update event to arrive
-    assert_match!(socket.outgoing_rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap(),
+    assert_match!(wait::COMM_MESSAGE.recv(&socket.outgoing_rx).unwrap(),
         CommMsg::Data(value) => {
             // Make sure it's a data update event.
             assert_match!(serde_json::from_value::<DataExplorerFrontendEvent>(value).unwrap(),
@@ -0,0 +1,139 @@
+//
+// environment-view.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use amalthea::comm::base_comm::JsonRpcError;
+use amalthea::comm::comm_channel::CommMsg;
+use amalthea::comm::event::CommManagerEvent;
+use amalthea::comm::variables_comm::SetViewGroupBy;
+use amalthea::comm::variables_comm::SetViewParams;
+use amalthea::comm::variables_comm::SetViewSortBy;
+use amalthea::comm::variables_comm::VariableGroup;
+use amalthea::comm::variables_comm::VariableGroupKind;
+use amalthea::comm::variables_comm::VariableList;
+use amalthea::comm::variables_comm::VariablesBackendReply;
+use amalthea::comm::variables_comm::VariablesBackendRequest;
+use amalthea::comm::variables_comm::VariablesFrontendEvent;
+use amalthea::socket::comm::CommSocket;
+use ark::fixtures::frontend_comm_socket;
+use ark::fixtures::socket_recv_event;
+use ark::fixtures::socket_rpc_request;
+use ark::r_task::r_task;
+use ark::thread::RThreadSafe;
+use ark::variables::r_variables::RVariables;
+use crossbeam::channel::bounded;
+
+fn set_view(comm: &CommSocket, view: SetViewParams) -> VariableList {
+    let request = VariablesBackendRequest::SetView(view);
+    match socket_rpc_request(comm, request) {
+        VariablesBackendReply::SetViewReply(list) => list,
+        reply => panic!("Expected set view reply, got {reply:?}"),
+    }
+}
+
+fn names(list: &VariableList) -> Vec<&str> {
+    list.variables
+        .iter()
+        .map(|variable| variable.display_name.as_str())
+        .collect()
+}
+
+/// The variables comm groups, filters, and sorts variables according to the
+/// view configured by the frontend. Lives in its own file because the
+/// environment tests emit console events that would update this comm too.
+#[test]
+fn test_environment_view() {
+    let env = r_task(|| {
+        let env = harp::parse_eval_base(
+            "local({
+                env <- new.env(parent = emptyenv())
+                env$df <- data.frame(x = 1:3)
+                env$f <- identity
+                env$big <- runif(1e4)
+                env$small <- 1
+                env$.hidden <- 2
+                env
+            })",
+        )
+        .unwrap();
+        RThreadSafe::new(env)
+    });
+
+    let comm = frontend_comm_socket("positron.variables");
+    let (comm_manager_tx, _) = bounded::<CommManagerEvent>(0);
+    r_task(|| {
+        let env = env.get().clone();
+        RVariables::start(env, comm.clone(), comm_manager_tx);
+    });
+
+    // The initial listing uses the default view
+    match socket_recv_event(&comm) {
+        VariablesFrontendEvent::Refresh(params) => {
+            assert_eq!(params.length, 4);
+            assert_eq!(params.groups, None);
+        },
+        event => panic!("Expected refresh event, got {event:?}"),
+    }
+
+    // Group by kind
+    let list = set_view(&comm, SetViewParams {
+        group_by: SetViewGroupBy::Kind,
+        filter: None,
+        sort_by: SetViewSortBy::Name,
+        show_hidden: false,
+    });
+    assert_eq!(names(&list), vec!["df", "f", "big", "small"]);
+    assert_eq!(
+        list.groups,
+        Some(vec![
+            VariableGroup {
+                kind: VariableGroupKind::Data,
+                length: 1,
+            },
+            VariableGroup {
+                kind: VariableGroupKind::Functions,
+                length: 1,
+            },
+            VariableGroup {
+                kind: VariableGroupKind::Values,
+                length: 2,
+            },
+        ])
+    );
+
+    // Sort by decreasing size
+    let list = set_view(&comm, SetViewParams {
+        group_by: SetViewGroupBy::None,
+        filter: None,
+        sort_by: SetViewSortBy::Size,
+        show_hidden: false,
+    });
+    assert_eq!(list.variables[0].display_name, "big");
+    assert_eq!(list.groups, None);
+
+    // Filter by name, including hidden objects
+    let list = set_view(&comm, SetViewParams {
+        group_by: SetViewGroupBy::None,
+        filter: Some(String::from("^(s|\\.)")),
+        sort_by: SetViewSortBy::Name,
+        show_hidden: true,
+    });
+    assert_eq!(names(&list), vec![".hidden", "small"]);
+    assert_eq!(list.length, 2);
+
+    // Invalid filters are rejected
+    let request = VariablesBackendRequest::SetView(SetViewParams {
+        group_by: SetViewGroupBy::None,
+        filter: Some(String::from("(")),
+        sort_by: SetViewSortBy::Name,
+        show_hidden: false,
+    });
+    let error: JsonRpcError = socket_rpc_request(&comm, request);
+    assert!(error.error.message.contains("Invalid filter"));
+
+    // Close the comm. Otherwise the thread panics
+    comm.incoming_tx.send(CommMsg::Close).unwrap();
+}
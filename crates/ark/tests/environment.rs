@@ -18,6 +18,7 @@ use ark::lsp::events::EVENTS;
 use ark::r_task::r_task;
 use ark::thread::RThreadSafe;
 use ark::variables::r_variables::RVariables;
+use ark::wait;
 use crossbeam::channel::bounded;
 use harp::exec::RFunction;
 use harp::exec::RFunctionExt;
@@ -173,10 +174,8 @@ fn test_environment_list() {
         .send(CommMsg::Rpc(request_id.clone(), data))
         .unwrap();
 
-    // Wait up to 1s for the comm to send us an update message
-    let msg = outgoing_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    // Wait for the comm to send us an update message
+    let msg = wait::COMM_MESSAGE.recv(&outgoing_rx).unwrap();
     let data = match msg {
         CommMsg::Data(data) => data,
         _ => panic!("Expected data message, got {:?}", msg),
@@ -276,6 +275,93 @@ fn test_environment_list() {
         _ => panic!("Expected delete reply"),
     };
 
+    // Simulate an interrupt signal. The whole list of variables is resent.
+    EVENTS.console_interrupted.emit(());
+
+    let msg = outgoing_rx.recv().unwrap();
+    let data = match msg {
+        CommMsg::Data(data) => data,
+        _ => panic!("Expected data message, got {:?}", msg),
+    };
+
+    let evt: VariablesFrontendEvent = serde_json::from_value(data).unwrap();
+    match evt {
+        VariablesFrontendEvent::Refresh(params) => {
+            assert_eq!(params.variables.len(), 1);
+            assert_eq!(params.variables[0].display_name, "b");
+        },
+        _ => panic!("Expected refresh event"),
+    }
+
     // Close the comm. Otherwise the thread panics
     incoming_tx.send(CommMsg::Close).unwrap();
 }
+
+/// Objects modified in place, such as environments, are reported as updated
+/// rather than assigned since their binding doesn't change
+#[test]
+fn test_environment_update_in_place() {
+    let (test_env, inner_env) = r_task(|| {
+        let env = RFunction::new("base", "new.env")
+            .param("parent", unsafe { R_EmptyEnv })
+            .call()
+            .unwrap();
+        let inner = RFunction::new("base", "new.env").call().unwrap();
+        unsafe { r_envir_set("e", *inner, *env) };
+        (RThreadSafe::new(env), RThreadSafe::new(inner))
+    });
+
+    let comm = CommSocket::new(
+        CommInitiator::FrontEnd,
+        String::from("test-environment-update-in-place-comm-id"),
+        String::from("positron.environment"),
+    );
+    let (comm_manager_tx, _) = bounded::<CommManagerEvent>(0);
+
+    let incoming_tx = comm.incoming_tx.clone();
+    let outgoing_rx = comm.outgoing_rx.clone();
+    r_task(|| {
+        let test_env = test_env.get().clone();
+        RVariables::start(test_env, comm.clone(), comm_manager_tx.clone());
+    });
+
+    let data = match outgoing_rx.recv().unwrap() {
+        CommMsg::Data(data) => data,
+        msg => panic!("Expected data message, got {:?}", msg),
+    };
+    let evt: VariablesFrontendEvent = serde_json::from_value(data).unwrap();
+    let version = match evt {
+        VariablesFrontendEvent::Refresh(params) => {
+            assert_eq!(params.variables.len(), 1);
+            params.version
+        },
+        _ => panic!("Expected refresh event"),
+    };
+
+    // Modify the environment without rebinding it
+    r_task(|| unsafe {
+        let inner_env = inner_env.get().clone();
+        r_envir_set("x", Rf_ScalarInteger(1), *inner_env);
+    });
+
+    EVENTS.console_prompt.emit(());
+
+    let data = match outgoing_rx.recv().unwrap() {
+        CommMsg::Data(data) => data,
+        msg => panic!("Expected data message, got {:?}", msg),
+    };
+    let evt: VariablesFrontendEvent = serde_json::from_value(data).unwrap();
+    match evt {
+        VariablesFrontendEvent::Update(params) => {
+            assert_eq!(params.assigned.len(), 0);
+            assert_eq!(params.removed.len(), 0);
+            assert_eq!(params.updated.len(), 1);
+            assert_eq!(params.updated[0].display_name, "e");
+            assert!(params.updated[0].has_children);
+            assert_eq!(params.version, version + 1);
+        },
+        _ => panic!("Expected update event"),
+    }
+
+    incoming_tx.send(CommMsg::Close).unwrap();
+}
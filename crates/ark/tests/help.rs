@@ -11,11 +11,11 @@ use amalthea::comm::comm_channel::CommMsg;
 use amalthea::comm::help_comm::HelpBackendReply;
 use amalthea::comm::help_comm::HelpBackendRequest;
 use amalthea::comm::help_comm::ShowHelpTopicParams;
-use amalthea::socket::comm::CommInitiator;
-use amalthea::socket::comm::CommSocket;
+use ark::fixtures::frontend_comm_socket;
 use ark::help::r_help::RHelp;
 use ark::help_proxy;
 use ark::r_task::r_task;
+use ark::wait;
 use harp::exec::RFunction;
 
 /**
@@ -25,11 +25,7 @@ use harp::exec::RFunction;
 #[test]
 fn test_help_comm() {
     // Create the comm socket for the Help comm
-    let comm = CommSocket::new(
-        CommInitiator::FrontEnd,
-        String::from("test-help-comm-id"),
-        String::from("positron.help"),
-    );
+    let comm = frontend_comm_socket("positron.help");
 
     let incoming_tx = comm.incoming_tx.clone();
     let outgoing_rx = comm.outgoing_rx.clone();
@@ -53,9 +49,8 @@ fn test_help_comm() {
             .send(CommMsg::Rpc(request_id.clone(), data))
             .unwrap();
 
-        // Wait for the response (up to 1 second; this should be fast!)
-        let duration = std::time::Duration::from_secs(1);
-        let response = outgoing_rx.recv_timeout(duration).unwrap();
+        // Wait for the response, this should be fast!
+        let response = wait::COMM_MESSAGE.recv(&outgoing_rx).unwrap();
         match response {
             CommMsg::Rpc(id, val) => {
                 let response = serde_json::from_value::<HelpBackendReply>(val).unwrap();
@@ -98,3 +93,21 @@ fn test_help_comm() {
     );
     assert!(RHelp::is_help_url(url.as_str(), r_help_port));
 }
+
+/**
+ * Without a connected help comm, help topics are rendered as plain text.
+ */
+#[test]
+fn test_help_as_text() {
+    let text = r_task(|| {
+        let code = "
+            topic <- utils::help('mean', package = 'base', help_type = 'html')
+            out <- capture.output(.ps.internal(print_help_files_with_topic(topic)))
+            paste(out, collapse = '\n')
+        ";
+        String::try_from(harp::parse_eval_global(code).unwrap()).unwrap()
+    });
+
+    assert!(text.contains("Arithmetic Mean"));
+    assert!(text.contains("Usage:"));
+}
@@ -0,0 +1,30 @@
+use amalthea::fixtures::dummy_frontend::ExecuteRequestOptions;
+use ark::fixtures::DummyArkFrontendReadOnly;
+use ark::r_task::r_task;
+
+#[test]
+fn test_read_only_execute_request() {
+    let frontend = DummyArkFrontendReadOnly::lock();
+
+    frontend.send_execute_request("x <- 1", ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, "x <- 1");
+
+    assert!(frontend.recv_iopub_execute_error().contains("read-only"));
+
+    frontend.recv_iopub_idle();
+
+    assert_eq!(
+        frontend.recv_shell_execute_reply_exception(),
+        input.execution_count
+    );
+
+    // The code was not evaluated but R is still available to comms
+    let exists = r_task(|| {
+        let exists = harp::parse_eval_global("exists('x', envir = globalenv())").unwrap();
+        bool::try_from(exists).unwrap()
+    });
+    assert!(!exists);
+}
@@ -0,0 +1,69 @@
+use amalthea::fixtures::dummy_frontend::ExecuteRequestOptions;
+use amalthea::wire::execute_request::ExecuteRequest;
+use amalthea::wire::jupyter_message::Message;
+use amalthea::wire::kernel_info_request::KernelInfoRequest;
+use ark::fixtures::DummyArkFrontendShared;
+use ark::r_task::r_task;
+use stdext::assert_match;
+
+#[test]
+fn test_shared_session_observer() {
+    let frontend = DummyArkFrontendShared::lock();
+
+    // The frontend was designated as the driver at startup
+    frontend.send_execute_request("x <- 1", ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, "x <- 1");
+
+    frontend.recv_iopub_idle();
+    assert_eq!(frontend.recv_shell_execute_reply(), input.execution_count);
+
+    // Observers can send non-mutating requests
+    frontend.send_shell_from_session(KernelInfoRequest {}, "observer");
+    assert_match!(frontend.recv_shell(), Message::KernelInfoReply(reply) => {
+        assert_eq!(reply.content.language_info.name, "R");
+    });
+    frontend.recv_iopub_busy();
+    frontend.recv_iopub_idle();
+
+    // But their code is rejected without being broadcast to other clients
+    frontend.send_shell_from_session(
+        ExecuteRequest {
+            code: String::from("y <- 2"),
+            silent: false,
+            store_history: true,
+            user_expressions: serde_json::Value::Null,
+            allow_stdin: false,
+            stop_on_error: false,
+            positron: None,
+        },
+        "observer",
+    );
+    frontend.recv_iopub_busy();
+    frontend.recv_iopub_idle();
+
+    // The execution count is unchanged
+    assert_eq!(
+        frontend.recv_shell_execute_reply_exception(),
+        input.execution_count
+    );
+
+    let exists = r_task(|| {
+        let exists = harp::parse_eval_global("exists('y', envir = globalenv())").unwrap();
+        bool::try_from(exists).unwrap()
+    });
+    assert!(!exists);
+
+    // The driver can keep executing code
+    frontend.send_execute_request("x", ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, "x");
+    assert_eq!(frontend.recv_iopub_execute_result(), "[1] 1");
+
+    frontend.recv_iopub_idle();
+    assert_eq!(frontend.recv_shell_execute_reply(), input.execution_count);
+}
@@ -1,4 +1,8 @@
 use amalthea::fixtures::dummy_frontend::ExecuteRequestOptions;
+use amalthea::wire::complete_request::CompleteRequest;
+use amalthea::wire::inspect_request::InspectRequest;
+use amalthea::wire::is_complete_reply::IsComplete;
+use amalthea::wire::is_complete_request::IsCompleteRequest;
 use amalthea::wire::jupyter_message::Message;
 use amalthea::wire::kernel_info_request::KernelInfoRequest;
 use ark::fixtures::DummyArkFrontend;
@@ -21,6 +25,109 @@ fn test_kernel_info() {
     frontend.recv_iopub_idle();
 }
 
+#[test]
+fn test_complete_request_non_ascii() {
+    let frontend = DummyArkFrontend::lock();
+
+    // The emoji counts as one position in `cursor_pos` but is four bytes long
+    let code = String::from("'🙂'; Sys.setenv");
+    frontend.send_shell(CompleteRequest {
+        cursor_pos: code.chars().count() as u32,
+        code,
+    });
+
+    assert_match!(frontend.recv_shell(), Message::CompleteReply(reply) => {
+        assert_eq!(reply.content.cursor_start, 5);
+        assert_eq!(reply.content.cursor_end, 15);
+        assert!(reply.content.matches.iter().any(|x| x == "Sys.setenv()"));
+
+        // Each match is typed
+        let types = reply.content.metadata["_jupyter_types_experimental"]
+            .as_array()
+            .unwrap();
+        assert_eq!(types.len(), reply.content.matches.len());
+
+        let setenv = types.iter().find(|x| x["text"] == "Sys.setenv()").unwrap();
+        assert_eq!(setenv["type"], "function");
+        assert_eq!(setenv["start"], 5);
+    });
+
+    frontend.recv_iopub_busy();
+    frontend.recv_iopub_idle();
+}
+
+#[test]
+fn test_is_complete_request() {
+    let frontend = DummyArkFrontend::lock();
+
+    let is_complete = |code: &str| -> (IsComplete, String) {
+        frontend.send_shell(IsCompleteRequest {
+            code: String::from(code),
+        });
+
+        let reply = assert_match!(frontend.recv_shell(), Message::IsCompleteReply(reply) => {
+            (reply.content.status, reply.content.indent)
+        });
+
+        frontend.recv_iopub_busy();
+        frontend.recv_iopub_idle();
+
+        reply
+    };
+
+    assert_match!(is_complete("1 + 1"), (IsComplete::Complete, indent) => {
+        assert_eq!(indent, "");
+    });
+    assert_match!(is_complete("1 +"), (IsComplete::Incomplete, indent) => {
+        assert_eq!(indent, "  ");
+    });
+    assert_match!(is_complete("f <- function() {\n  list(\n    a = 1,"), (IsComplete::Incomplete, indent) => {
+        assert_eq!(indent, "    ");
+    });
+    assert_match!(is_complete("x <- \"({\n"), (IsComplete::Incomplete, indent) => {
+        assert_eq!(indent, "");
+    });
+    assert_match!(is_complete("1 +)"), (IsComplete::Invalid, _));
+}
+
+#[test]
+fn test_inspect_request() {
+    let frontend = DummyArkFrontend::lock();
+
+    let inspect = |code: &str, detail_level: u32| -> (bool, serde_json::Value) {
+        frontend.send_shell(InspectRequest {
+            code: String::from(code),
+            cursor_pos: code.chars().count() as u32,
+            detail_level,
+        });
+
+        let reply = assert_match!(frontend.recv_shell(), Message::InspectReply(reply) => {
+            (reply.content.found, reply.content.data)
+        });
+
+        frontend.recv_iopub_busy();
+        frontend.recv_iopub_idle();
+
+        reply
+    };
+
+    let (found, data) = inspect("head(mtcars", 0);
+    assert!(found);
+    let text = data["text/plain"].as_str().unwrap();
+    assert!(text.starts_with("`mtcars`: object of class `data.frame`"));
+    assert!(text.contains("32 obs. of  11 variables"));
+    assert_eq!(data["text/markdown"], data["text/plain"]);
+
+    let (found, data) = inspect("Negate", 1);
+    assert!(found);
+    let text = data["text/plain"].as_str().unwrap();
+    assert!(text.contains("function (f)"));
+
+    let (found, data) = inspect("ark_test_inspect_undefined", 0);
+    assert!(!found);
+    assert_eq!(data, serde_json::json!({}));
+}
+
 #[test]
 fn test_execute_request() {
     let frontend = DummyArkFrontend::lock();
@@ -317,6 +424,38 @@ fn test_execute_request_error() {
     );
 }
 
+#[test]
+fn test_execute_request_input_not_allowed() {
+    let frontend = DummyArkFrontend::lock();
+
+    // Input requests are denied when `allow_stdin` is false
+    let code = "menu(c('a', 'b'))";
+    frontend.send_execute_request(code, ExecuteRequestOptions { allow_stdin: false });
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, code);
+
+    // `menu()` prints its choices before requesting input
+    frontend.recv_iopub_stream_stdout("\n1: a\n2: b\n\n");
+
+    // The warning names the call the user wrote, not the `readline()` it calls
+    frontend.recv_iopub_stream_stderr(
+        "Warning: `menu(c(\"a\", \"b\"))` requested input from the user with prompt \"Selection: \", but this frontend doesn't accept input requests during this execution.\n\
+         Supply the answer in the code instead, for instance with an argument or an option, or run the code in a console.\n",
+    );
+    assert!(frontend
+        .recv_iopub_execute_error()
+        .contains("Can't request input from the user in this execution."));
+
+    frontend.recv_iopub_idle();
+
+    assert_eq!(
+        frontend.recv_shell_execute_reply_exception(),
+        input.execution_count
+    );
+}
+
 #[test]
 fn test_execute_request_error_multiple_expressions() {
     let frontend = DummyArkFrontend::lock();
@@ -390,6 +529,110 @@ fn test_execute_request_single_line_buffer_overflow() {
     );
 }
 
+#[test]
+fn test_display_data() {
+    let frontend = DummyArkFrontend::lock();
+
+    let code = "ark.display('text/html' = '<b>1</b>', display_id = 'id')
+ark.display('text/html' = '<b>2</b>', display_id = 'id', update = TRUE)";
+    frontend.send_execute_request(code, ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, code);
+
+    assert_match!(frontend.recv_iopub(), Message::DisplayData(data) => {
+        assert_eq!(data.content.data["text/html"], "<b>1</b>");
+        assert_eq!(data.content.transient["display_id"], "id");
+    });
+
+    assert_match!(frontend.recv_iopub(), Message::UpdateDisplayData(data) => {
+        assert_eq!(data.content.data["text/html"], "<b>2</b>");
+        assert_eq!(data.content.transient.display_id, "id");
+    });
+
+    frontend.recv_iopub_idle();
+
+    assert_eq!(frontend.recv_shell_execute_reply(), input.execution_count);
+}
+
+#[test]
+fn test_execute_request_page_payload() {
+    let frontend = DummyArkFrontend::lock();
+
+    let code =
+        "f <- tempfile(); writeLines(c('a', 'b'), f); file.show(f, header = 'h', title = '')";
+    frontend.send_execute_request(code, ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, code);
+    frontend.recv_iopub_idle();
+
+    // Paged files are sent with the reply
+    assert_match!(frontend.recv_shell(), Message::ExecuteReply(reply) => {
+        assert_eq!(reply.content.execution_count, input.execution_count);
+        assert_eq!(reply.content.payload.len(), 1);
+        assert_eq!(reply.content.payload[0]["source"], "page");
+        assert_eq!(reply.content.payload[0]["data"]["text/plain"], "h\n\na\nb");
+    });
+
+    // And only with the reply of the execution that paged them
+    let code = "1";
+    frontend.send_execute_request(code, ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(frontend.recv_iopub_execute_result(), "[1] 1");
+    frontend.recv_iopub_idle();
+
+    assert_match!(frontend.recv_shell(), Message::ExecuteReply(reply) => {
+        assert_eq!(reply.content.execution_count, input.execution_count);
+        assert!(reply.content.payload.is_empty());
+    });
+}
+
+#[test]
+fn test_interrupt_request() {
+    let frontend = DummyArkFrontend::lock();
+
+    let code = "cat('started\\n'); for (ark_test_i in 1:50) Sys.sleep(0.1)";
+    let id = frontend.send_execute_request(code, ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, code);
+
+    // Only interrupt once R is evaluating the code
+    frontend.recv_iopub_stream_stdout("started\n");
+    let start = std::time::Instant::now();
+
+    frontend.send_control_interrupt_request();
+    frontend.recv_control_interrupt_reply();
+
+    // The loop is cut short and the execution completes. Interrupts aren't
+    // errors so the reply is a success.
+    frontend.recv_iopub_until_idle(&id);
+
+    assert_match!(frontend.recv_shell(), Message::ExecuteReply(reply) => {
+        assert_eq!(reply.content.execution_count, input.execution_count);
+    });
+
+    // The uninterrupted loop would have taken 5 seconds
+    assert!(start.elapsed() < std::time::Duration::from_secs(3));
+
+    let code = "ark_test_i < 50";
+    frontend.send_execute_request(code, ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, code);
+    assert_eq!(frontend.recv_iopub_execute_result(), "[1] TRUE");
+
+    frontend.recv_iopub_idle();
+
+    assert_eq!(frontend.recv_shell_execute_reply(), input.execution_count);
+}
+
 #[test]
 fn test_stdin_basic_prompt() {
     let frontend = DummyArkFrontend::lock();
@@ -537,3 +780,53 @@ fn test_stdin_from_menu() {
 
     assert_eq!(frontend.recv_shell_execute_reply(), input.execution_count);
 }
+
+#[test]
+fn test_stdin_from_ask_yes_no() {
+    let frontend = DummyArkFrontend::lock();
+
+    let options = ExecuteRequestOptions { allow_stdin: true };
+
+    let code = "askYesNo('Continue?')";
+    frontend.send_execute_request(code, options);
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(input.code, code);
+
+    let prompt = frontend.recv_stdin_input_request();
+    assert_eq!(prompt, String::from("Continue? (Yes/no/cancel) "));
+
+    frontend.send_stdin_input_reply(String::from("n"));
+
+    assert_eq!(frontend.recv_iopub_execute_result(), "[1] FALSE");
+
+    frontend.recv_iopub_idle();
+
+    assert_eq!(frontend.recv_shell_execute_reply(), input.execution_count);
+}
+
+#[test]
+fn test_export_transcript() {
+    let frontend = DummyArkFrontend::lock();
+
+    let code = "1 + 1";
+    frontend.send_execute_request(code, ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    assert_eq!(frontend.recv_iopub_execute_result(), "[1] 2");
+    frontend.recv_iopub_idle();
+    assert_eq!(frontend.recv_shell_execute_reply(), input.execution_count);
+
+    // The export request itself is not part of the exported transcript
+    let code = "x <- readLines(.ps.rpc.export_transcript(tempfile(fileext = '.R')))
+writeLines(tail(x, 3))";
+    frontend.send_execute_request(code, ExecuteRequestOptions::default());
+    frontend.recv_iopub_busy();
+
+    let input = frontend.recv_iopub_execute_input();
+    frontend.recv_iopub_stream_stdout("1 + 1\n#> [1] 2\n\n");
+    frontend.recv_iopub_idle();
+    assert_eq!(frontend.recv_shell_execute_reply(), input.execution_count);
+}
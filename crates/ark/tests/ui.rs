@@ -12,9 +12,10 @@ use amalthea::comm::ui_comm::CallMethodParams;
 use amalthea::comm::ui_comm::UiBackendReply;
 use amalthea::comm::ui_comm::UiBackendRequest;
 use amalthea::comm::ui_comm::UiFrontendEvent;
-use amalthea::socket::comm::CommInitiator;
-use amalthea::socket::comm::CommSocket;
 use amalthea::socket::stdin::StdInRequest;
+use ark::fixtures::frontend_comm_socket;
+use ark::fixtures::socket_recv_msg;
+use ark::fixtures::socket_rpc_request;
 use ark::r_task::r_task;
 use ark::ui::UiComm;
 use ark::ui::UiCommMessage;
@@ -30,11 +31,7 @@ use serde_json::Value;
 #[test]
 fn test_ui_comm() {
     // Create a sender/receiver pair for the comm channel.
-    let comm_socket = CommSocket::new(
-        CommInitiator::FrontEnd,
-        String::from("test-ui-comm-id"),
-        String::from("positron.UI"),
-    );
+    let comm_socket = frontend_comm_socket("positron.UI");
 
     // Communication channel between the main thread and the Amalthea
     // StdIn socket thread
@@ -66,10 +63,7 @@ fn test_ui_comm() {
     // Wait for the reply; this should be a FrontendRpcResult. We don't wait
     // more than a second since this should be quite fast and we don't want to
     // hang the test suite if it doesn't return.
-    let response = comm_socket
-        .outgoing_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    let response = socket_recv_msg(&comm_socket);
     match response {
         CommMsg::Rpc(id, result) => {
             println!("Got RPC result: {:?}", result);
@@ -108,10 +102,7 @@ fn test_ui_comm() {
         .unwrap();
 
     // Wait for the reply
-    let response = comm_socket
-        .outgoing_rx
-        .recv_timeout(std::time::Duration::from_secs(1))
-        .unwrap();
+    let response = socket_recv_msg(&comm_socket);
     match response {
         CommMsg::Rpc(id, result) => {
             println!("Got RPC result: {:?}", result);
@@ -139,3 +130,234 @@ fn test_ui_comm() {
         })))
         .unwrap();
 }
+
+fn getenv(name: &str) -> Option<String> {
+    let code = format!("Sys.getenv('{name}', unset = NA)");
+    r_task(move || {
+        let value = harp::parse_eval_global(&code).unwrap();
+        Option::<String>::try_from(value).unwrap()
+    })
+}
+
+#[test]
+fn test_ui_comm_process_env() {
+    let comm_socket = frontend_comm_socket("positron.UI");
+
+    let (stdin_request_tx, _stdin_request_rx) = bounded::<StdInRequest>(1);
+    let ui_comm_tx = UiComm::start(comm_socket.clone(), stdin_request_tx);
+
+    let old_path = getenv("PATH").unwrap();
+
+    let request = UiBackendRequest::CallMethod(CallMethodParams {
+        method: String::from("set_process_env"),
+        params: vec![
+            serde_json::json!({ "ARK_TEST_ASKPASS": "/ark/askpass" }),
+            serde_json::json!(["/ark/bin"]),
+        ],
+    });
+    let _reply: UiBackendReply = socket_rpc_request(&comm_socket, request);
+
+    assert_eq!(
+        getenv("ARK_TEST_ASKPASS"),
+        Some(String::from("/ark/askpass"))
+    );
+    assert!(getenv("PATH").unwrap().starts_with("/ark/bin"));
+
+    let request = UiBackendRequest::CallMethod(CallMethodParams {
+        method: String::from("reset_process_env"),
+        params: vec![],
+    });
+    let _reply: UiBackendReply = socket_rpc_request(&comm_socket, request);
+
+    // Variables that were unset are unset again
+    assert_eq!(getenv("ARK_TEST_ASKPASS"), None);
+    assert_eq!(getenv("PATH").unwrap(), old_path);
+
+    ui_comm_tx
+        .send(UiCommMessage::Event(UiFrontendEvent::Busy(BusyParams {
+            busy: false,
+        })))
+        .unwrap();
+}
+
+#[test]
+fn test_ui_comm_env_var_editor() {
+    let comm_socket = frontend_comm_socket("positron.UI");
+
+    let (stdin_request_tx, _stdin_request_rx) = bounded::<StdInRequest>(1);
+    let ui_comm_tx = UiComm::start(comm_socket.clone(), stdin_request_tx);
+
+    // Record changes reported to R-side listeners
+    r_task(|| {
+        harp::parse_eval_global(
+            "local({
+                changes <- list()
+                setHook('positron.envVarChanged', function(name, value) {
+                    changes[[length(changes) + 1]] <<- list(name, value)
+                })
+                .ark_test_env_var_changes <<- function() changes
+            })",
+        )
+        .unwrap();
+    });
+
+    let call = |method: &str, params: Vec<Value>| -> Value {
+        let request = UiBackendRequest::CallMethod(CallMethodParams {
+            method: String::from(method),
+            params,
+        });
+        let reply: UiBackendReply = socket_rpc_request(&comm_socket, request);
+        match reply {
+            UiBackendReply::CallMethodReply(value) => value,
+        }
+    };
+
+    // Secret-like values are masked in records
+    let record = call("set_env_var", vec![
+        Value::from("ARK_TEST_TOKEN"),
+        Value::from("hunter2"),
+    ]);
+    assert_eq!(getenv("ARK_TEST_TOKEN"), Some(String::from("hunter2")));
+    assert_eq!(record["masked"], Value::from(true));
+    assert_ne!(record["value"], Value::from("hunter2"));
+
+    call("set_env_var", vec![
+        Value::from("ARK_TEST_DIR"),
+        Value::from("/ark/dir"),
+    ]);
+
+    let vars = call("list_env_vars", vec![]);
+    let find = |name: &str| {
+        vars.as_array()
+            .unwrap()
+            .iter()
+            .find(|record| record["name"] == Value::from(name))
+            .cloned()
+            .unwrap()
+    };
+    assert_eq!(find("ARK_TEST_DIR")["value"], Value::from("/ark/dir"));
+    assert_eq!(find("ARK_TEST_DIR")["masked"], Value::from(false));
+    assert_eq!(find("ARK_TEST_TOKEN")["masked"], Value::from(true));
+
+    call("unset_env_var", vec![Value::from("ARK_TEST_TOKEN")]);
+    call("unset_env_var", vec![Value::from("ARK_TEST_DIR")]);
+    assert_eq!(getenv("ARK_TEST_TOKEN"), None);
+
+    let n_changes = r_task(|| {
+        let n = harp::parse_eval_global(
+            "local({
+                changes <- .ark_test_env_var_changes()
+                setHook('positron.envVarChanged', NULL, 'replace')
+                rm(.ark_test_env_var_changes, envir = globalenv())
+                length(changes)
+            })",
+        )
+        .unwrap();
+        i32::try_from(n).unwrap()
+    });
+    assert_eq!(n_changes, 4);
+
+    ui_comm_tx
+        .send(UiCommMessage::Event(UiFrontendEvent::Busy(BusyParams {
+            busy: false,
+        })))
+        .unwrap();
+}
+
+#[test]
+fn test_package_install_hooks() {
+    r_task(|| {
+        // The hook keeps the signature of `install.packages()` so that
+        // completions and argument matching are unaffected
+        let same_formals = harp::parse_eval_global(
+            "local({
+                hooks <- .ps.internal(package_hooks)
+                !identical(utils::install.packages, hooks$install.packages) &&
+                    identical(formals(utils::install.packages), formals(hooks$install.packages))
+            })",
+        )
+        .unwrap();
+        assert!(bool::try_from(same_formals).unwrap());
+
+        // Explicit mirror selections don't go through the frontend
+        let repos = harp::parse_eval_global(
+            "local({
+                old <- options(repos = c(CRAN = '@CRAN@'))
+                on.exit(options(old))
+                utils::chooseCRANmirror(ind = 1, local.only = TRUE)
+                getOption('repos')[['CRAN']]
+            })",
+        )
+        .unwrap();
+        assert_ne!(String::try_from(repos).unwrap(), "@CRAN@");
+    })
+}
+
+#[test]
+fn test_stale_namespaces() {
+    r_task(|| {
+        // Nothing was installed since the last check
+        let stale = harp::parse_eval_global(
+            "local({
+                .ps.internal(stale_namespaces_check())
+                .ps.internal(stale_namespaces_check())
+            })",
+        )
+        .unwrap();
+        assert_eq!(
+            Vec::<String>::try_from(stale).unwrap(),
+            Vec::<String>::new()
+        );
+
+        // Namespaces are reloaded after the namespaces they import
+        let ordered = harp::parse_eval_global(
+            "local({
+                order <- .ps.internal(namespace_reload_order('utils'))
+                imports <- lapply(order, function(ns) names(getNamespaceImports(ns)))
+
+                ordered <- vapply(seq_along(order), function(i) {
+                    all(intersect(imports[[i]], order) %in% order[seq_len(i - 1)])
+                }, logical(1))
+
+                order[[1]] == 'utils' && all(ordered)
+            })",
+        )
+        .unwrap();
+        assert!(bool::try_from(ordered).unwrap());
+    })
+}
+
+#[test]
+fn test_masked_bindings() {
+    r_task(|| {
+        let ok = harp::parse_eval_global(
+            "local({
+                attach(NULL, name = 'package:arkexporter')
+                attach(list(filter = function(x) x, pi = pi), name = 'package:arkmasking')
+                on.exit({
+                    detach('package:arkmasking')
+                    detach('package:arkexporter')
+                })
+
+                # Re-exports of lazy-loaded objects are promises with the same
+                # expression and environment. They are compared without being
+                # forced.
+                lazy_env <- new.env()
+                for (name in c('package:arkmasking', 'package:arkexporter')) {
+                    delayedAssign(
+                        'reexport',
+                        stop('forced'),
+                        eval.env = lazy_env,
+                        assign.env = as.environment(name)
+                    )
+                }
+
+                # `pi` is identical to `base::pi` so it doesn't count
+                bindings <- .ps.internal(masked_bindings('package:arkmasking'))
+                identical(bindings, list(list(name = 'filter', masked = list('package:stats'))))
+            })",
+        )
+        .unwrap();
+        assert!(bool::try_from(ok).unwrap());
+    })
+}
@@ -6,6 +6,7 @@
  */
 
 use amalthea::comm::comm_channel::Comm;
+use amalthea::language::shell_handler::ShellContext;
 use amalthea::language::shell_handler::ShellHandler;
 use amalthea::socket::comm::CommSocket;
 use amalthea::socket::iopub::IOPubMessage;
@@ -28,7 +29,6 @@ use amalthea::wire::jupyter_message::Status;
 use amalthea::wire::kernel_info_reply::KernelInfoReply;
 use amalthea::wire::kernel_info_request::KernelInfoRequest;
 use amalthea::wire::language_info::LanguageInfo;
-use amalthea::wire::originator::Originator;
 use async_trait::async_trait;
 use crossbeam::channel::Receiver;
 use crossbeam::channel::Sender;
@@ -61,6 +61,7 @@ impl Shell {
 impl ShellHandler for Shell {
     async fn handle_info_request(
         &mut self,
+        _ctx: &ShellContext,
         _req: &KernelInfoRequest,
     ) -> amalthea::Result<KernelInfoReply> {
         let info = LanguageInfo {
@@ -84,6 +85,7 @@ impl ShellHandler for Shell {
 
     async fn handle_complete_request(
         &self,
+        _ctx: &ShellContext,
         _req: &CompleteRequest,
     ) -> amalthea::Result<CompleteReply> {
         // No matches in this toy implementation.
@@ -99,6 +101,7 @@ impl ShellHandler for Shell {
     /// Handle a request to test code for completion.
     async fn handle_is_complete_request(
         &self,
+        _ctx: &ShellContext,
         _req: &IsCompleteRequest,
     ) -> amalthea::Result<IsCompleteReply> {
         // In this echo example, the code is always complete!
@@ -111,7 +114,7 @@ impl ShellHandler for Shell {
     /// Handles an ExecuteRequest; "executes" the code by echoing it.
     async fn handle_execute_request(
         &mut self,
-        _originator: Originator,
+        _ctx: &ShellContext,
         req: &ExecuteRequest,
     ) -> amalthea::Result<ExecuteReply> {
         // Increment counter if we are storing this execution in history
@@ -179,11 +182,16 @@ impl ShellHandler for Shell {
             status: Status::Ok,
             execution_count: self.execution_count,
             user_expressions: serde_json::Value::Null,
+            payload: vec![],
         })
     }
 
     /// Handles an introspection request
-    async fn handle_inspect_request(&self, req: &InspectRequest) -> amalthea::Result<InspectReply> {
+    async fn handle_inspect_request(
+        &self,
+        _ctx: &ShellContext,
+        req: &InspectRequest,
+    ) -> amalthea::Result<InspectReply> {
         let data = match req.code.as_str() {
             "err" => {
                 json!({"text/plain": "This generates an error!"})
@@ -201,7 +209,12 @@ impl ShellHandler for Shell {
         })
     }
 
-    async fn handle_comm_open(&self, _target: Comm, _comm: CommSocket) -> amalthea::Result<bool> {
+    async fn handle_comm_open(
+        &self,
+        _ctx: &ShellContext,
+        _target: Comm,
+        _comm: CommSocket,
+    ) -> amalthea::Result<bool> {
         // No comms in this toy implementation.
         Ok(false)
     }
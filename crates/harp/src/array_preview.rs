@@ -0,0 +1,288 @@
+//
+// array_preview.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use libr::R_DimNamesSymbol;
+use libr::R_DimSymbol;
+use libr::Rf_getAttrib;
+use libr::Rf_xlength;
+use libr::CPLXSXP;
+use libr::INTSXP;
+use libr::LGLSXP;
+use libr::RAWSXP;
+use libr::REALSXP;
+use libr::SEXP;
+use libr::STRSXP;
+
+use crate::object::RObject;
+use crate::text_width::pad_left;
+use crate::text_width::pad_right;
+use crate::text_width::text_width;
+use crate::utils::r_is_null;
+use crate::utils::r_typeof;
+use crate::vector::formatted_vector::FormattedVector;
+use crate::vector::CharacterVector;
+use crate::vector::IntegerVector;
+use crate::vector::Vector;
+
+/// Preview of a matrix or array that keeps its shape: the top-left corner of
+/// its first slice, along with the dimension names. Flattening an array to
+/// its first elements would only show the start of the first column.
+pub struct ArrayPreview {
+    /// Dimensions of the whole array
+    pub dim: Vec<usize>,
+
+    /// Names of the previewed rows and columns, if the array has dimnames
+    pub row_names: Option<Vec<String>>,
+    pub col_names: Option<Vec<String>>,
+
+    /// Label of the previewed slice of arrays of more than two dimensions,
+    /// e.g. `, , 1`
+    pub slice: Option<String>,
+
+    /// Formatted elements of the corner, by row
+    pub rows: Vec<Vec<String>>,
+
+    /// Whether rows, columns, or slices were left out
+    pub is_truncated: bool,
+}
+
+/// Whether `x` is an atomic array of at least two dimensions
+pub fn is_array(x: SEXP) -> bool {
+    if !matches!(
+        r_typeof(x),
+        LGLSXP | INTSXP | REALSXP | CPLXSXP | STRSXP | RAWSXP
+    ) {
+        return false;
+    }
+
+    let dim = unsafe { Rf_getAttrib(x, R_DimSymbol) };
+    !r_is_null(dim) && unsafe { Rf_xlength(dim) } >= 2
+}
+
+impl ArrayPreview {
+    /// Preview the first `max_rows` rows and `max_cols` columns of `x`, which
+    /// must satisfy `is_array()`
+    pub fn new(x: SEXP, max_rows: usize, max_cols: usize) -> crate::Result<Self> {
+        let dim = IntegerVector::new(unsafe { Rf_getAttrib(x, R_DimSymbol) })?;
+        let dim: Vec<usize> = dim.iter().map(|d| d.unwrap_or(0).max(0) as usize).collect();
+
+        let n_row = dim[0];
+        let n_col = dim[1];
+        let n_slice: usize = dim[2..].iter().product();
+
+        let shown_rows = n_row.min(max_rows);
+        let shown_cols = n_col.min(max_cols);
+
+        let formatted = FormattedVector::new(x)?;
+        let rows = (0..shown_rows)
+            .map(|i| {
+                (0..shown_cols)
+                    .map(|j| formatted.get_unchecked((i + j * n_row) as isize))
+                    .collect()
+            })
+            .collect();
+
+        // The first slice is previewed, labelled like R prints it
+        let slice = if dim.len() > 2 {
+            let indices: Vec<String> = (2..dim.len())
+                .map(|axis| {
+                    dim_names(x, axis, 1)
+                        .ok()
+                        .flatten()
+                        .and_then(|names| names.into_iter().next())
+                        .unwrap_or(String::from("1"))
+                })
+                .collect();
+            Some(format!(", , {}", indices.join(", ")))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            row_names: dim_names(x, 0, shown_rows)?,
+            col_names: dim_names(x, 1, shown_cols)?,
+            slice,
+            rows,
+            is_truncated: n_row > max_rows || n_col > max_cols || n_slice > 1,
+            dim,
+        })
+    }
+
+    /// Single line preview, by column as in the variables pane where the
+    /// children of a matrix are its columns, e.g. `[x: [1 2 …], y: [3 4 …]]`
+    pub fn inline(&self) -> String {
+        let n_col = self.rows.first().map_or(0, |row| row.len());
+        let rows_truncated = self.rows.len() < self.dim[0];
+
+        let columns: Vec<String> = (0..n_col)
+            .map(|j| {
+                let mut column: Vec<&str> = self.rows.iter().map(|row| row[j].as_str()).collect();
+                if rows_truncated {
+                    column.push("…");
+                }
+
+                let column = format!("[{}]", column.join(" "));
+                match &self.col_names {
+                    Some(names) => format!("{}: {column}", names[j]),
+                    None => column,
+                }
+            })
+            .collect();
+
+        format!("[{}]", columns.join(", "))
+    }
+
+    /// Multi-line preview laid out like R prints matrices, with the row and
+    /// column names and an ellipsis for what was left out
+    pub fn grid(&self) -> String {
+        let rows_truncated = self.rows.len() < self.dim[0];
+        let cols_truncated = self.rows.first().map_or(0, |row| row.len()) < self.dim[1];
+
+        let n_col = self.rows.first().map_or(0, |row| row.len());
+
+        let header: Vec<String> = match &self.col_names {
+            Some(names) => names.clone(),
+            None => (1..=n_col).map(|j| format!("[,{j}]")).collect(),
+        };
+        let labels: Vec<String> = match &self.row_names {
+            Some(names) => names.clone(),
+            None => (1..=self.rows.len()).map(|i| format!("[{i},]")).collect(),
+        };
+
+        let label_width = labels
+            .iter()
+            .map(|label| text_width(label))
+            .max()
+            .unwrap_or(0);
+        let widths: Vec<usize> = (0..n_col)
+            .map(|j| {
+                self.rows
+                    .iter()
+                    .map(|row| text_width(&row[j]))
+                    .chain(std::iter::once(text_width(&header[j])))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let line = |label: &str, cells: &[String]| -> String {
+            let mut line = pad_right(label, label_width);
+            for (cell, width) in cells.iter().zip(widths.iter()) {
+                line.push(' ');
+                line.push_str(&pad_left(cell, *width));
+            }
+            if cols_truncated {
+                line.push_str(" …");
+            }
+            line.trim_end().to_string()
+        };
+
+        let mut lines = vec![];
+
+        if let Some(slice) = &self.slice {
+            lines.push(slice.clone());
+            lines.push(String::new());
+        }
+
+        lines.push(line("", &header));
+        for (label, row) in labels.iter().zip(self.rows.iter()) {
+            lines.push(line(label, row));
+        }
+        if rows_truncated {
+            lines.push(String::from("…"));
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// The first `n` names of dimension `axis`, if named
+fn dim_names(x: SEXP, axis: usize, n: usize) -> crate::Result<Option<Vec<String>>> {
+    let dimnames = RObject::view(unsafe { Rf_getAttrib(x, R_DimNamesSymbol) });
+    if r_is_null(dimnames.sexp) {
+        return Ok(None);
+    }
+
+    let names = dimnames.vector_elt(axis as isize)?;
+    if r_typeof(names.sexp) != STRSXP {
+        return Ok(None);
+    }
+
+    let names = unsafe { CharacterVector::new_unchecked(names.sexp) };
+    let names = names
+        .iter()
+        .take(n)
+        .map(|name| name.unwrap_or(String::from("<NA>")))
+        .collect();
+
+    Ok(Some(names))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::array_preview::is_array;
+    use crate::array_preview::ArrayPreview;
+
+    #[test]
+    fn test_array_preview_matrix() {
+        crate::r_task(|| {
+            let x = harp::parse_eval_base(
+                "matrix(1:12, 3, dimnames = list(c('a', 'b', 'c'), c('w', 'x', 'y', 'z')))",
+            )
+            .unwrap();
+            assert!(is_array(x.sexp));
+
+            let preview = ArrayPreview::new(x.sexp, 2, 3).unwrap();
+            assert_eq!(preview.dim, vec![3, 4]);
+            assert_eq!(preview.rows, vec![vec!["1", "4", "7"], vec!["2", "5", "8"]]);
+            assert!(preview.is_truncated);
+
+            assert_eq!(preview.inline(), "[w: [1 2 …], x: [4 5 …], y: [7 8 …]]");
+            assert_eq!(preview.grid(), "  w x y …\na 1 4 7 …\nb 2 5 8 …\n…");
+
+            let x = harp::parse_eval_base("matrix(c(1L, 10L, 2L, 3L), 2)").unwrap();
+            let preview = ArrayPreview::new(x.sexp, 5, 5).unwrap();
+            assert!(!preview.is_truncated);
+            assert_eq!(preview.inline(), "[[1 10], [2 3]]");
+            assert_eq!(
+                preview.grid(),
+                "     [,1] [,2]\n[1,]    1    2\n[2,]   10    3"
+            );
+
+            // Wide characters take two columns
+            let x = harp::parse_eval_base(
+                "matrix(1:2, 1, dimnames = list('\\u884c', c('\\u5217\\u4e00', 'b')))",
+            )
+            .unwrap();
+            let preview = ArrayPreview::new(x.sexp, 5, 5).unwrap();
+            assert_eq!(preview.grid(), "   \u{5217}\u{4e00} b\n\u{884c}    1 2");
+        })
+    }
+
+    #[test]
+    fn test_array_preview_array() {
+        crate::r_task(|| {
+            let x = harp::parse_eval_base("array(1:8, c(2, 2, 2))").unwrap();
+            assert!(is_array(x.sexp));
+
+            let preview = ArrayPreview::new(x.sexp, 5, 5).unwrap();
+            assert_eq!(preview.slice.as_deref(), Some(", , 1"));
+            assert!(preview.is_truncated);
+            assert!(preview
+                .grid()
+                .starts_with(", , 1\n\n     [,1] [,2]\n[1,]    1    3"));
+
+            // Not arrays
+            let x = harp::parse_eval_base("1:3").unwrap();
+            assert!(!is_array(x.sexp));
+            let x = harp::parse_eval_base("array(1:3)").unwrap();
+            assert!(!is_array(x.sexp));
+            let x = harp::parse_eval_base("matrix(list(1, 2), 1)").unwrap();
+            assert!(!is_array(x.sexp));
+        })
+    }
+}
@@ -0,0 +1,83 @@
+//
+// cancellation.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+use std::cell::RefCell;
+
+use stdext::cancellation::CancellationToken;
+
+thread_local! {
+    /// Tokens of the work currently running on this thread, innermost last
+    static TOKENS: RefCell<Vec<CancellationToken>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f` with `token` as a cancellation point. Long running loops, e.g.
+/// formatting the elements of a large vector, call `check_cancelled()` to
+/// stop early once `token` is cancelled from another thread.
+///
+/// Meant to be called on the R thread by the owner of the work, typically a
+/// comm that cancels its token when it is closed, since the result isn't
+/// needed anymore.
+pub fn with_cancellation<T>(token: &CancellationToken, f: impl FnOnce() -> T) -> T {
+    TOKENS.with_borrow_mut(|tokens| tokens.push(token.clone()));
+
+    // Pop the token even if `f` unwinds
+    let _guard = TokenGuard;
+
+    f()
+}
+
+struct TokenGuard;
+
+impl Drop for TokenGuard {
+    fn drop(&mut self) {
+        TOKENS.with_borrow_mut(|tokens| tokens.pop());
+    }
+}
+
+/// Whether the work running on this thread was cancelled
+pub fn is_cancelled() -> bool {
+    TOKENS.with_borrow(|tokens| tokens.iter().any(|token| token.is_cancelled()))
+}
+
+/// Cancellation point for long running work, see `with_cancellation()`
+pub fn check_cancelled() -> crate::Result<()> {
+    if is_cancelled() {
+        return Err(crate::Error::Cancelled);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use stdext::cancellation::CancellationToken;
+
+    use crate::cancellation::check_cancelled;
+    use crate::cancellation::is_cancelled;
+    use crate::cancellation::with_cancellation;
+
+    #[test]
+    fn test_with_cancellation() {
+        let outer = CancellationToken::new();
+        let inner = CancellationToken::new();
+
+        assert!(check_cancelled().is_ok());
+
+        with_cancellation(&outer, || {
+            with_cancellation(&inner, || {
+                assert!(!is_cancelled());
+
+                // Cancelling any of the enclosing tokens cancels the work
+                outer.cancel();
+                assert!(matches!(check_cancelled(), Err(crate::Error::Cancelled)));
+            });
+            assert!(is_cancelled());
+        });
+
+        // Tokens are only checked while their work is running
+        assert!(!is_cancelled());
+    }
+}
@@ -66,6 +66,8 @@ pub enum Error {
         backtrace: Backtrace,
         span_trace: tracing_error::SpanTrace,
     },
+    /// The work was cancelled, see `harp::cancellation`
+    Cancelled,
     Anyhow(anyhow::Error),
 }
 
@@ -216,6 +218,10 @@ impl fmt::Display for Error {
                 write!(f, "C stack usage too close to the limit")
             },
 
+            Error::Cancelled => {
+                write!(f, "Cancelled")
+            },
+
             Error::Anyhow(err) => {
                 write!(f, "{err:?}")
             },
@@ -20,11 +20,14 @@ use crate::modules::HARP_ENV;
 use crate::object::r_null_or_try_into;
 use crate::object::RObject;
 use crate::r_symbol;
+use crate::utils::r_is_function;
 use crate::utils::r_stringify;
+use crate::utils::r_typeof;
 
 pub struct RFunction {
     pub call: RCall,
     is_namespaced: bool,
+    is_inlined: bool,
 }
 
 struct CallbackData<'a, F, T>
@@ -44,10 +47,18 @@ impl RFunction {
         Self::new_ext(package, function, true)
     }
 
+    /// Call a function object rather than a function looked up by name, e.g.
+    /// a closure supplied by the user and stored for later use as a callback.
+    ///
+    /// The function is inlined in the call. Symbols and calls passed as
+    /// arguments are quoted so that the function receives them as values
+    /// instead of evaluating them in the calling environment. Use
+    /// `RFunction::try_from()` to check that the object is a function.
     pub fn new_inlined(function: impl Into<RObject>) -> Self {
         RFunction {
             call: RCall::new(function),
             is_namespaced: false,
+            is_inlined: true,
         }
     }
 
@@ -66,6 +77,7 @@ impl RFunction {
             RFunction {
                 call: RCall::new(fun),
                 is_namespaced,
+                is_inlined: false,
             }
         }
     }
@@ -87,6 +99,17 @@ impl RFunction {
         let user_call = self.call.build();
         try_eval(user_call.sexp, env)
     }
+
+    fn marshal(&self, value: RObject) -> RObject {
+        if !self.is_inlined {
+            return value;
+        }
+
+        match r_typeof(value.sexp) {
+            SYMSXP | LANGSXP => RFunction::new("base", "quote").add(value).call.build(),
+            _ => value,
+        }
+    }
 }
 
 /// Evaluate R code in a context protected from errors and longjumps
@@ -119,6 +142,19 @@ impl From<String> for RFunction {
     }
 }
 
+impl TryFrom<RObject> for RFunction {
+    type Error = crate::error::Error;
+
+    fn try_from(function: RObject) -> Result<Self> {
+        if !r_is_function(function.sexp) {
+            return Err(Error::UnexpectedType(r_typeof(function.sexp), vec![
+                CLOSXP, BUILTINSXP, SPECIALSXP,
+            ]));
+        }
+        Ok(RFunction::new_inlined(function))
+    }
+}
+
 // NOTE: Having to import this trait cause a bit of friction during
 // development. Can we do without?
 pub trait RFunctionExt<T> {
@@ -129,14 +165,16 @@ pub trait RFunctionExt<T> {
 impl<T: Into<RObject>> RFunctionExt<Option<T>> for RFunction {
     fn param(&mut self, name: &str, value: Option<T>) -> &mut Self {
         if let Some(value) = value {
-            self.call.param(name, value.into());
+            let value = self.marshal(value.into());
+            self.call.param(name, value);
         }
         self
     }
 
     fn add(&mut self, value: Option<T>) -> &mut Self {
         if let Some(value) = value {
-            self.call.add(value.into());
+            let value = self.marshal(value.into());
+            self.call.add(value);
         }
         self
     }
@@ -144,11 +182,13 @@ impl<T: Into<RObject>> RFunctionExt<Option<T>> for RFunction {
 
 impl<T: Into<RObject>> RFunctionExt<T> for RFunction {
     fn param(&mut self, name: &str, value: T) -> &mut Self {
+        let value = self.marshal(value.into());
         self.call.param(name, value);
         self
     }
 
     fn add(&mut self, value: T) -> &mut Self {
+        let value = self.marshal(value.into());
         self.call.add(value);
         self
     }
@@ -495,7 +535,6 @@ mod tests {
 
     use super::*;
     use crate::utils::r_envir_remove;
-    use crate::utils::r_typeof;
 
     #[test]
     fn test_basic_function() {
@@ -557,6 +596,48 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_closure_function() {
+        crate::r_task(|| {
+            let closure = harp::parse_eval_base("function(x, y = 1) list(x, y)").unwrap();
+            let mut function = RFunction::try_from(closure).unwrap();
+
+            // Symbols are passed as values rather than evaluated
+            let symbol = unsafe { r_symbol!("not_a_variable") };
+            let result = function
+                .add(RObject::from(symbol))
+                .param("y", 2)
+                .call()
+                .unwrap();
+
+            let x = harp::list_get(result.sexp, 0);
+            assert_eq!(r_typeof(x), SYMSXP);
+
+            let y = RObject::view(harp::list_get(result.sexp, 1));
+            assert_eq!(i32::try_from(y).unwrap(), 2);
+        })
+    }
+
+    #[test]
+    fn test_closure_function_error() {
+        crate::r_task(|| {
+            let closure = harp::parse_eval_base("function(x) stop('ouch')").unwrap();
+            let result = RFunction::try_from(closure).unwrap().add(1).call();
+
+            assert_match!(result, Err(Error::TryCatchError { message, .. }) => {
+                assert_eq!(message, "ouch");
+            });
+
+            let not_a_function = RObject::from(1.0);
+            assert_match!(
+                RFunction::try_from(not_a_function),
+                Err(Error::UnexpectedType(actual, _)) => {
+                    assert_eq!(actual, REALSXP);
+                }
+            );
+        })
+    }
+
     #[test]
     fn test_try_catch_error() {
         crate::r_task(|| unsafe {
@@ -14,6 +14,10 @@ use libr::INTSXP;
 use libr::LGLSXP;
 use libr::NILSXP;
 use libr::REALSXP;
+use libr::SET_INTEGER_ELT;
+use libr::SET_LOGICAL_ELT;
+use libr::SET_REAL_ELT;
+use libr::SET_STRING_ELT;
 use libr::SET_VECTOR_ELT;
 use libr::STRSXP;
 use libr::SYMSXP;
@@ -25,7 +29,56 @@ use serde_json::Number;
 use serde_json::Value;
 
 use crate::exec::r_check_stack;
+use crate::object::r_dbl_na;
+use crate::object::r_int_na;
+use crate::object::r_lgl_na;
+use crate::object::r_str_na;
 use crate::object::RObject;
+use crate::r_char;
+
+/// Options for conversions between R objects and JSON values
+#[derive(Clone, Copy, Debug)]
+pub struct JsonOptions {
+    /// Whether length-one atomic vectors become JSON scalars (the default) or
+    /// arrays of length one. When `false`, zero-length vectors and lists also
+    /// become empty arrays rather than `null`, so that the shape of the data
+    /// doesn't depend on its length.
+    pub auto_unbox: bool,
+
+    /// Whether JSON arrays of scalars of the same type (allowing `null`s) are
+    /// simplified to atomic vectors, with `null` becoming `NA`. By default
+    /// JSON arrays always become lists.
+    pub simplify_vectors: bool,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self {
+            auto_unbox: true,
+            simplify_vectors: false,
+        }
+    }
+}
+
+impl RObject {
+    /// Convert to a JSON value with default options, see `TryFrom<RObject> for Value`
+    pub fn try_to_json(&self) -> crate::Result<Value> {
+        self.try_to_json_with(JsonOptions::default())
+    }
+
+    pub fn try_to_json_with(&self, options: JsonOptions) -> crate::Result<Value> {
+        options.r_to_json(self.clone())
+    }
+
+    /// Convert from a JSON value with default options, see `TryFrom<Value> for RObject`
+    pub fn from_json(value: Value) -> crate::Result<RObject> {
+        Self::from_json_with(value, JsonOptions::default())
+    }
+
+    pub fn from_json_with(value: Value, options: JsonOptions) -> crate::Result<RObject> {
+        options.json_to_r(value)
+    }
+}
 
 /// Conversion to JSON values from an R object.
 ///
@@ -56,6 +109,12 @@ use crate::object::RObject;
 impl TryFrom<RObject> for Value {
     type Error = crate::error::Error;
     fn try_from(obj: RObject) -> Result<Self, Self::Error> {
+        JsonOptions::default().r_to_json(obj)
+    }
+}
+
+impl JsonOptions {
+    fn r_to_json(&self, obj: RObject) -> crate::Result<Value> {
         // Since this function is recursive, check the stack before we proceed
         // to make sure we aren't about to overflow it.
         r_check_stack(None)?;
@@ -67,10 +126,10 @@ impl TryFrom<RObject> for Value {
             // Integers (INTSXP) ---
             INTSXP => match obj.length() {
                 // A length of 0 becomes JSON null
-                0 => Ok(Value::Null),
+                0 if self.auto_unbox => Ok(Value::Null),
 
                 // A single integer becomes a JSON number
-                1 => {
+                1 if self.auto_unbox => {
                     let value = unsafe { obj.to::<i32>()? };
                     Ok(Value::Number(value.into()))
                 },
@@ -92,10 +151,10 @@ impl TryFrom<RObject> for Value {
             // Real / floating point numbers (REALSXP) ---
             REALSXP => match obj.length() {
                 // A length of 0 becomes JSON null
-                0 => Ok(Value::Null),
+                0 if self.auto_unbox => Ok(Value::Null),
 
                 // A single value becomes a JSON number
-                1 => {
+                1 if self.auto_unbox => {
                     let value = unsafe { obj.to::<f64>()? };
                     // There's no try/into implicit conversion from f64 to a
                     // JSON number, but json! handles it.
@@ -119,10 +178,10 @@ impl TryFrom<RObject> for Value {
             // Logical / Boolean values (LGLSXP) ---
             LGLSXP => match obj.length() {
                 // A length of 0 becomes JSON null
-                0 => Ok(Value::Null),
+                0 if self.auto_unbox => Ok(Value::Null),
 
                 // A single value becomes a JSON true/false value
-                1 => {
+                1 if self.auto_unbox => {
                     let value = unsafe { obj.to::<bool>()? };
                     Ok(Value::Bool(value))
                 },
@@ -155,10 +214,10 @@ impl TryFrom<RObject> for Value {
             // Strings (STRSXP) ---
             STRSXP => match obj.length() {
                 // A length of 0 becomes JSON null
-                0 => Ok(Value::Null),
+                0 if self.auto_unbox => Ok(Value::Null),
 
                 // With exactly one value, convert to a string
-                1 => {
+                1 if self.auto_unbox => {
                     let str = unsafe { obj.to::<String>()? };
                     Ok(Value::String(str))
                 },
@@ -180,7 +239,7 @@ impl TryFrom<RObject> for Value {
             // Vectors/lists (VECSXP) ---
             VECSXP => match obj.length() {
                 // A length of 0 becomes JSON null
-                0 => Ok(Value::Null),
+                0 if self.auto_unbox => Ok(Value::Null),
 
                 _ => {
                     // See whether the object's values have names. We will try
@@ -215,7 +274,7 @@ impl TryFrom<RObject> for Value {
                             // minimum of the two.
                             let n = min(obj.length(), names.len().try_into().unwrap());
 
-                            // Create the map. Note that `Value::try_from` below
+                            // Create the map. Note that `r_to_json()` below
                             // will recurse into this function; this is how we
                             // handle arbitrarily deep lists.
                             //
@@ -229,7 +288,7 @@ impl TryFrom<RObject> for Value {
                                     Some(name) => name.clone(),
                                     None => String::new(),
                                 };
-                                let val = Value::try_from(obj.vector_elt(i)?)?;
+                                let val = self.r_to_json(obj.vector_elt(i)?)?;
 
                                 // Do we already have a value for this key? If
                                 // so, we need to convert the existing value to
@@ -263,13 +322,13 @@ impl TryFrom<RObject> for Value {
                             let n = obj.length();
                             let mut arr = Vec::<Value>::with_capacity(n.try_into().unwrap());
 
-                            // Create the array. Note that `Value::try_from`
+                            // Create the array. Note that `r_to_json()`
                             // below will recurse into this function to convert
                             // each element of the list to a value. Just like R
                             // list, JSON arrays can have elements of different
                             // types.
                             for i in 0..n {
-                                arr.push(Value::try_from(obj.vector_elt(i)?)?)
+                                arr.push(self.r_to_json(obj.vector_elt(i)?)?)
                             }
                             Ok(serde_json::Value::Array(arr))
                         },
@@ -313,17 +372,7 @@ impl TryFrom<Vec<Value>> for RObject {
     type Error = crate::error::Error;
 
     fn try_from(vals: Vec<Value>) -> Result<Self, Self::Error> {
-        // Consider: currently, this creates an unnamed list. It would be
-        // better, presuming that the values are all the same type, to create an
-        // atomic vector of that type.
-        unsafe {
-            let list = RObject::from(Rf_allocVector(VECSXP, vals.len() as isize));
-            for (i, val) in vals.iter().enumerate() {
-                let val = RObject::try_from(val.clone())?;
-                SET_VECTOR_ELT(list.sexp, i as isize, val.sexp);
-            }
-            return Ok(list);
-        }
+        JsonOptions::default().json_array_to_r(vals)
     }
 }
 
@@ -331,20 +380,7 @@ impl TryFrom<Map<String, Value>> for RObject {
     type Error = crate::error::Error;
 
     fn try_from(map: Map<String, Value>) -> Result<Self, Self::Error> {
-        // Convert the map's values into a vector of JSON values, then convert
-        // that to an R object.
-        let vals: Vec<Value> = map.values().cloned().collect();
-        let list = RObject::try_from(vals)?;
-
-        // Convert the map's keys into a vector of strings, then set the names
-        // of the R object to that vector.
-        let keys: Vec<String> = map.keys().cloned().collect();
-        let names = RObject::from(keys);
-        unsafe {
-            Rf_setAttrib(list.sexp, R_NamesSymbol, names.sexp);
-        }
-
-        Ok(list)
+        JsonOptions::default().json_object_to_r(map)
     }
 }
 
@@ -356,15 +392,121 @@ impl TryFrom<Value> for RObject {
     type Error = crate::error::Error;
 
     fn try_from(value: Value) -> Result<Self, Self::Error> {
+        JsonOptions::default().json_to_r(value)
+    }
+}
+
+impl JsonOptions {
+    fn json_to_r(&self, value: Value) -> crate::Result<RObject> {
         match value {
             Value::Null => Ok(RObject::from(())),
             Value::Bool(bool) => Ok(RObject::from(bool)),
             Value::Number(num) => RObject::try_from(num),
             Value::String(string) => Ok(RObject::from(string)),
-            Value::Array(values) => RObject::try_from(values),
-            Value::Object(map) => RObject::try_from(map),
+            Value::Array(values) => self.json_array_to_r(values),
+            Value::Object(map) => self.json_object_to_r(map),
+        }
+    }
+
+    fn json_array_to_r(&self, vals: Vec<Value>) -> crate::Result<RObject> {
+        if self.simplify_vectors {
+            if let Some(vector) = json_array_to_r_vector(&vals) {
+                return Ok(vector);
+            }
+        }
+
+        unsafe {
+            let list = RObject::from(Rf_allocVector(VECSXP, vals.len() as isize));
+            for (i, val) in vals.into_iter().enumerate() {
+                let val = self.json_to_r(val)?;
+                SET_VECTOR_ELT(list.sexp, i as isize, val.sexp);
+            }
+            return Ok(list);
         }
     }
+
+    fn json_object_to_r(&self, map: Map<String, Value>) -> crate::Result<RObject> {
+        let keys: Vec<String> = map.keys().cloned().collect();
+
+        // Objects always become lists, even when their values could be simplified
+        // to an atomic vector
+        let list = unsafe { RObject::from(Rf_allocVector(VECSXP, map.len() as isize)) };
+        for (i, (_, val)) in map.into_iter().enumerate() {
+            let val = self.json_to_r(val)?;
+            unsafe { SET_VECTOR_ELT(list.sexp, i as isize, val.sexp) };
+        }
+
+        // Set the names of the R object to the map's keys
+        let names = RObject::from(keys);
+        unsafe {
+            Rf_setAttrib(list.sexp, R_NamesSymbol, names.sexp);
+        }
+
+        Ok(list)
+    }
+}
+
+/**
+ * Simplify a JSON array of scalars of the same type to an atomic vector.
+ * `null` values become `NA`. Returns `None` if the array contains values of
+ * different types, nested arrays or objects, or only `null`s.
+ */
+fn json_array_to_r_vector(vals: &[Value]) -> Option<RObject> {
+    let n = vals.len() as isize;
+    let kind = match vals.iter().find(|val| !val.is_null())? {
+        Value::Bool(_) => LGLSXP,
+        Value::String(_) => STRSXP,
+        Value::Number(_) => {
+            let fits_integer = |val: &Value| match val {
+                Value::Number(num) => num
+                    .as_i64()
+                    .is_some_and(|x| i32::try_from(x).is_ok() && x != r_int_na() as i64),
+                _ => true,
+            };
+            if vals.iter().all(fits_integer) {
+                INTSXP
+            } else {
+                REALSXP
+            }
+        },
+        _ => return None,
+    };
+
+    let same_kind = vals.iter().all(|val| match val {
+        Value::Null => true,
+        Value::Bool(_) => kind == LGLSXP,
+        Value::String(_) => kind == STRSXP,
+        Value::Number(_) => kind == INTSXP || kind == REALSXP,
+        _ => false,
+    });
+    if !same_kind {
+        return None;
+    }
+
+    unsafe {
+        let vector = RObject::from(Rf_allocVector(kind, n));
+
+        for (i, val) in vals.iter().enumerate() {
+            let i = i as isize;
+            match (kind, val) {
+                (LGLSXP, Value::Bool(x)) => SET_LOGICAL_ELT(vector.sexp, i, *x as i32),
+                (LGLSXP, _) => SET_LOGICAL_ELT(vector.sexp, i, r_lgl_na()),
+                (INTSXP, Value::Number(x)) => {
+                    SET_INTEGER_ELT(vector.sexp, i, x.as_i64().unwrap() as i32)
+                },
+                (INTSXP, _) => SET_INTEGER_ELT(vector.sexp, i, r_int_na()),
+                (REALSXP, Value::Number(x)) => {
+                    SET_REAL_ELT(vector.sexp, i, x.as_f64().unwrap_or(r_dbl_na()))
+                },
+                (REALSXP, _) => SET_REAL_ELT(vector.sexp, i, r_dbl_na()),
+                (STRSXP, Value::String(x)) => SET_STRING_ELT(vector.sexp, i, r_char!(x)),
+                (STRSXP, _) => SET_STRING_ELT(vector.sexp, i, r_str_na()),
+                _ => unreachable!(),
+            }
+        }
+
+        Some(vector)
+    }
 }
 
 #[cfg(test)]
@@ -562,4 +704,49 @@ mod tests {
             );
         })
     }
+
+    #[test]
+    fn test_json_options_auto_unbox() {
+        crate::r_task(|| {
+            let options = JsonOptions {
+                auto_unbox: false,
+                ..Default::default()
+            };
+            let to_json = |expr: &str| {
+                let obj = harp::parse_eval_global(expr).unwrap();
+                obj.try_to_json_with(options).unwrap()
+            };
+
+            assert_eq!(to_json("1L"), json!([1]));
+            assert_eq!(to_json("integer()"), json!([]));
+            assert_eq!(to_json("list()"), json!([]));
+            assert_eq!(
+                to_json("list(a = 'x', b = 1:2)"),
+                json!({"a": ["x"], "b": [1, 2]})
+            );
+        })
+    }
+
+    #[test]
+    fn test_json_options_simplify_vectors() {
+        crate::r_task(|| {
+            let options = JsonOptions {
+                simplify_vectors: true,
+                ..Default::default()
+            };
+            let from_json = |expr: &str| {
+                let value: Value = serde_json::from_str(expr).unwrap();
+                deparse(RObject::from_json_with(value, options).unwrap())
+            };
+
+            assert_eq!(from_json("[1, null, 3]"), "c(1L, NA, 3L)");
+            assert_eq!(from_json("[1, 2.5]"), "c(1, 2.5)");
+            assert_eq!(from_json("[true, null]"), "c(TRUE, NA)");
+            assert_eq!(from_json("[\"a\", null]"), "c(\"a\", NA)");
+
+            // Mixed types and nested values are kept as lists
+            assert_eq!(from_json("[1, \"a\"]"), "list(1L, \"a\")");
+            assert_eq!(from_json("{\"a\": [1, 2]}"), "list(a = 1:2)");
+        })
+    }
 }
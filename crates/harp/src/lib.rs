@@ -4,8 +4,10 @@
 // Copyright (C) 2023 Posit Software, PBC. All rights reserved.
 //
 //
+pub mod array_preview;
 pub mod attrib;
 pub mod call;
+pub mod cancellation;
 pub mod command;
 pub mod data_frame;
 pub mod environment;
@@ -36,6 +38,7 @@ pub mod string;
 pub mod symbol;
 pub mod sys;
 pub mod table;
+pub mod text_width;
 pub mod traits;
 pub mod utils;
 pub mod vec_format;
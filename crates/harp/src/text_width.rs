@@ -0,0 +1,80 @@
+//
+// text_width.rs
+//
+// Copyright (C) 2024 Posit Software, PBC. All rights reserved.
+//
+//
+
+// Width of text in a monospace font, in columns. Characters of East Asian
+// scripts are usually wide and take two columns, combining marks take none,
+// so counting characters misaligns columnar text as soon as it contains CJK
+// characters.
+
+use unicode_width::UnicodeWidthChar;
+use unicode_width::UnicodeWidthStr;
+
+/// Number of columns that `text` takes in a monospace font
+pub fn text_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// The longest prefix of `text` that fits in `width` columns, or `None` if the
+/// whole text fits. A wide character that would straddle the limit is left
+/// out.
+pub fn truncate_to_width(text: &str, width: usize) -> Option<&str> {
+    let mut used = 0;
+
+    for (index, c) in text.char_indices() {
+        used += c.width().unwrap_or(0);
+        if used > width {
+            return Some(&text[..index]);
+        }
+    }
+
+    None
+}
+
+/// Pad `text` with leading spaces up to `width` columns
+pub fn pad_left(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text_width(text));
+    format!("{}{text}", " ".repeat(padding))
+}
+
+/// Pad `text` with trailing spaces up to `width` columns
+pub fn pad_right(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text_width(text));
+    format!("{text}{}", " ".repeat(padding))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text_width::pad_left;
+    use crate::text_width::pad_right;
+    use crate::text_width::text_width;
+    use crate::text_width::truncate_to_width;
+
+    #[test]
+    fn test_text_width() {
+        assert_eq!(text_width("abc"), 3);
+        assert_eq!(text_width("日本語"), 6);
+        assert_eq!(text_width("ｶﾀｶﾅ"), 4);
+        assert_eq!(text_width("한글 text"), 9);
+        assert_eq!(text_width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn test_truncate_to_width() {
+        assert_eq!(truncate_to_width("abc", 3), None);
+        assert_eq!(truncate_to_width("abcd", 3), Some("abc"));
+        assert_eq!(truncate_to_width("日本語", 4), Some("日本"));
+        assert_eq!(truncate_to_width("日本語", 5), Some("日本"));
+        assert_eq!(truncate_to_width("a日本", 2), Some("a"));
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(pad_left("日本", 6), "  日本");
+        assert_eq!(pad_right("日本", 6), "日本  ");
+        assert_eq!(pad_left("日本語", 4), "日本語");
+    }
+}
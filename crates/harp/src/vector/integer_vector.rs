@@ -10,10 +10,12 @@ use libr::R_xlen_t;
 use libr::Rf_allocVector;
 use libr::DATAPTR;
 use libr::INTEGER_ELT;
+use libr::INTEGER_GET_REGION;
 use libr::INTSXP;
 use libr::SEXP;
 
 use crate::object::RObject;
+use crate::vector::read_region;
 use crate::vector::FormatOptions;
 use crate::vector::Vector;
 
@@ -67,6 +69,15 @@ impl Vector for IntegerVector {
         unsafe { INTEGER_ELT(self.data(), index as R_xlen_t) }
     }
 
+    unsafe fn read_range_unchecked(
+        &self,
+        start: usize,
+        len: usize,
+        buf: &mut Vec<Self::UnderlyingType>,
+    ) {
+        read_region(self.data(), start, len, buf, INTEGER_GET_REGION)
+    }
+
     fn convert_value(x: &Self::UnderlyingType) -> Self::Type {
         *x
     }
@@ -5,10 +5,12 @@
 //
 //
 
+use libr::R_xlen_t;
 use libr::Rf_allocVector;
 use libr::Rf_xlength;
 use libr::SEXP;
 
+use crate::cancellation::check_cancelled;
 use crate::error::Result;
 use crate::utils::r_assert_capacity;
 use crate::utils::r_assert_type;
@@ -40,6 +42,9 @@ pub use raw_vector::RawVector;
 pub mod formatted_vector;
 pub mod names;
 
+/// Number of elements copied at a time by `Vector::to_vec_lossy()`
+const READ_CHUNK_SIZE: usize = 8192;
+
 // Formatting options for character vectors
 pub struct FormatOptions {
     // Wether to quote the strings or not (defaults to `true`)
@@ -118,6 +123,60 @@ pub trait Vector: Sized {
         }
     }
 
+    /// Copy the elements `start..start + len` to `buf`, replacing its
+    /// contents. Large vectors can be read in chunks with a single buffer.
+    fn read_range(
+        &self,
+        start: usize,
+        len: usize,
+        buf: &mut Vec<Self::UnderlyingType>,
+    ) -> Result<()> {
+        r_assert_capacity(self.data(), start + len)?;
+        unsafe { self.read_range_unchecked(start, len, buf) };
+        Ok(())
+    }
+
+    /// Like `read_range()` without bounds checks.
+    ///
+    /// Elements are copied one at a time by default. Vectors of plain data
+    /// use R's region getters instead, which copy whole regions at once and
+    /// only materialise the requested region of ALTREP vectors.
+    unsafe fn read_range_unchecked(
+        &self,
+        start: usize,
+        len: usize,
+        buf: &mut Vec<Self::UnderlyingType>,
+    ) {
+        buf.clear();
+        buf.extend((start..start + len).map(|i| self.get_unchecked_elt(i as isize)));
+    }
+
+    /// Copy the vector to a `Vec`, with missing values as `None`. Unlike the
+    /// `TryFrom` conversions, missing values aren't an error.
+    ///
+    /// Fails with `Error::Cancelled` if the work is cancelled while copying,
+    /// see `harp::cancellation`.
+    fn to_vec_lossy(&self) -> Result<Vec<Option<Self::Type>>> {
+        let len = unsafe { self.len() };
+
+        let mut out = Vec::with_capacity(len);
+        let mut buf = Vec::with_capacity(len.min(READ_CHUNK_SIZE));
+
+        for start in (0..len).step_by(READ_CHUNK_SIZE) {
+            check_cancelled()?;
+
+            let chunk_len = READ_CHUNK_SIZE.min(len - start);
+            unsafe { self.read_range_unchecked(start, chunk_len, &mut buf) };
+
+            out.extend(buf.iter().map(|x| match Self::is_na(x) {
+                true => None,
+                false => Some(Self::convert_value(x)),
+            }));
+        }
+
+        Ok(out)
+    }
+
     fn iter(&self) -> harp::vector::VectorIterator<'_, Self> {
         let size = unsafe { self.len() as isize };
         harp::vector::VectorIterator {
@@ -163,6 +222,22 @@ where
     }
 }
 
+/// Implementation of `Vector::read_range_unchecked()` for vectors with a
+/// region getter, e.g. `INTEGER_GET_REGION()`
+pub(crate) unsafe fn read_region<T>(
+    x: SEXP,
+    start: usize,
+    len: usize,
+    buf: &mut Vec<T>,
+    get_region: unsafe fn(SEXP, R_xlen_t, R_xlen_t, *mut T) -> R_xlen_t,
+) {
+    buf.clear();
+    buf.reserve(len);
+
+    let n = get_region(x, start as R_xlen_t, len as R_xlen_t, buf.as_mut_ptr());
+    buf.set_len(n as usize);
+}
+
 // Can we integrate this in a generic `TryFrom` impl for `Vector` objects?
 pub(crate) fn try_vec_from_r_vector<VectorType>(
     value: &VectorType,
@@ -194,3 +269,59 @@ where
 
     VectorType::new(value)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vector::*;
+
+    #[test]
+    fn test_read_range() {
+        crate::r_task(|| {
+            // Compact ALTREP sequence, only the requested region is materialised
+            let x = IntegerVector::new(harp::parse_eval_base("1:1e6").unwrap()).unwrap();
+            let mut buf = vec![];
+
+            x.read_range(10, 3, &mut buf).unwrap();
+            assert_eq!(buf, vec![11, 12, 13]);
+
+            // The buffer is reused across reads
+            x.read_range(999_998, 2, &mut buf).unwrap();
+            assert_eq!(buf, vec![999_999, 1_000_000]);
+
+            assert!(x.read_range(999_999, 2, &mut buf).is_err());
+
+            let x = CharacterVector::create(["a", "b", "c"]);
+            let mut buf = vec![];
+            x.read_range(1, 2, &mut buf).unwrap();
+            assert_eq!(buf.len(), 2);
+        })
+    }
+
+    #[test]
+    fn test_to_vec_lossy() {
+        crate::r_task(|| {
+            let x = NumericVector::new(harp::parse_eval_base("c(1.5, NA, 3)").unwrap()).unwrap();
+            assert_eq!(x.to_vec_lossy().unwrap(), vec![Some(1.5), None, Some(3.0)]);
+
+            let x = LogicalVector::new(harp::parse_eval_base("c(TRUE, NA)").unwrap()).unwrap();
+            assert_eq!(x.to_vec_lossy().unwrap(), vec![Some(true), None]);
+
+            let x = CharacterVector::new(harp::parse_eval_base("c('a', NA)").unwrap()).unwrap();
+            assert_eq!(x.to_vec_lossy().unwrap(), vec![
+                Some(String::from("a")),
+                None
+            ]);
+
+            // Spans several chunks
+            let x = IntegerVector::new(harp::parse_eval_base("1:20000").unwrap()).unwrap();
+            let expected: Vec<Option<i32>> = (1..=20000).map(Some).collect();
+            assert_eq!(x.to_vec_lossy().unwrap(), expected);
+
+            // Cancelled work stops at the next chunk
+            let token = stdext::cancellation::CancellationToken::new();
+            token.cancel();
+            let result = crate::cancellation::with_cancellation(&token, || x.to_vec_lossy());
+            assert!(matches!(result, Err(crate::Error::Cancelled)));
+        })
+    }
+}
@@ -10,9 +10,11 @@ use libr::Rf_allocVector;
 use libr::DATAPTR;
 use libr::RAWSXP;
 use libr::RAW_ELT;
+use libr::RAW_GET_REGION;
 use libr::SEXP;
 
 use crate::object::RObject;
+use crate::vector::read_region;
 use crate::vector::FormatOptions;
 use crate::vector::Vector;
 
@@ -66,6 +68,15 @@ impl Vector for RawVector {
         unsafe { RAW_ELT(self.data(), index as R_xlen_t) }
     }
 
+    unsafe fn read_range_unchecked(
+        &self,
+        start: usize,
+        len: usize,
+        buf: &mut Vec<Self::UnderlyingType>,
+    ) {
+        read_region(self.data(), start, len, buf, RAW_GET_REGION)
+    }
+
     fn convert_value(x: &Self::UnderlyingType) -> Self::Type {
         *x
     }
@@ -234,10 +234,24 @@ functions::generate! {
 
     pub fn INTEGER_ELT(x: SEXP, i: R_xlen_t) -> std::ffi::c_int;
 
+    pub fn INTEGER_GET_REGION(
+        sx: SEXP,
+        i: R_xlen_t,
+        n: R_xlen_t,
+        buf: *mut std::ffi::c_int
+    ) -> R_xlen_t;
+
     pub fn LOGICAL(x: SEXP) -> *mut std::ffi::c_int;
 
     pub fn LOGICAL_ELT(x: SEXP, i: R_xlen_t) -> std::ffi::c_int;
 
+    pub fn LOGICAL_GET_REGION(
+        sx: SEXP,
+        i: R_xlen_t,
+        n: R_xlen_t,
+        buf: *mut std::ffi::c_int
+    ) -> R_xlen_t;
+
     pub fn PRCODE(x: SEXP) -> SEXP;
 
     pub fn PRENV(x: SEXP) -> SEXP;
@@ -252,12 +266,16 @@ functions::generate! {
 
     pub fn RAW_ELT(x: SEXP, i: R_xlen_t) -> Rbyte;
 
+    pub fn RAW_GET_REGION(sx: SEXP, i: R_xlen_t, n: R_xlen_t, buf: *mut Rbyte) -> R_xlen_t;
+
     pub fn RDEBUG(x: SEXP) -> std::ffi::c_int;
 
     pub fn REAL(x: SEXP) -> *mut f64;
 
     pub fn REAL_ELT(x: SEXP, i: R_xlen_t) -> f64;
 
+    pub fn REAL_GET_REGION(sx: SEXP, i: R_xlen_t, n: R_xlen_t, buf: *mut f64) -> R_xlen_t;
+
     pub fn R_CHAR(x: SEXP) -> *const std::ffi::c_char;
 
     pub fn SETCAR(x: SEXP, y: SEXP) -> SEXP;
@@ -348,6 +366,13 @@ functions::generate! {
     #[cfg(target_family = "unix")]
     pub fn R_runHandlers(handlers: *const std::ffi::c_void, fdset: *const std::ffi::c_void);
 
+    /// NOTE: `handlers` is the address of `R_InputHandlers`
+    #[cfg(target_family = "unix")]
+    pub fn removeInputHandler(
+        handlers: *mut *mut InputHandler,
+        it: *mut InputHandler,
+    ) -> std::ffi::c_int;
+
     // -----------------------------------------------------------------------------------
     // Windows
 
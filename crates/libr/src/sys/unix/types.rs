@@ -5,6 +5,19 @@
 //
 //
 
-// Currently not used, but this keeps us aligned with `sys/windows/types.rs`.
-// If we start using this, remove this line from `types.rs`:
-// `#[cfg_attr(target_family = "unix", allow(unused_imports))]`
+// Unix specific types required by R functions we need
+
+#![allow(non_snake_case)]
+
+/// Handler of a file descriptor watched by the R event loop, from
+/// `R_ext/eventloop.h`
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct InputHandler {
+    pub activity: std::ffi::c_int,
+    pub fileDescriptor: std::ffi::c_int,
+    pub handler: Option<unsafe extern "C" fn(userData: *mut std::ffi::c_void)>,
+    pub next: *mut InputHandler,
+    pub active: std::ffi::c_int,
+    pub userData: *mut std::ffi::c_void,
+}
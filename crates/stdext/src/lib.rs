@@ -7,6 +7,7 @@
 
 pub mod all;
 pub mod any;
+pub mod cancellation;
 pub mod case;
 pub mod event;
 pub mod join;